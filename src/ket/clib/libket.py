@@ -58,7 +58,7 @@ API_argtypes = {
     "ket_process_new": ([c_void_p], [c_void_p]),
     "ket_process_delete": ([c_void_p], []),
     "ket_process_allocate_qubit": ([c_void_p], [c_size_t]),
-    "ket_process_free_qubit": ([c_void_p, c_void_p], []),
+    "ket_process_free_qubit": ([c_void_p, c_size_t], []),
     "ket_process_apply_gate": (
         [c_void_p, c_int32, c_int32, c_uint32, c_double, c_size_t],
         [],
@@ -78,6 +78,10 @@ API_argtypes = {
         [c_size_t],
     ),
     "ket_process_dump": ([c_void_p, POINTER(c_size_t), c_size_t], [c_size_t]),
+    "ket_process_dump_top_k": (
+        [c_void_p, POINTER(c_size_t), c_size_t, c_size_t],
+        [c_size_t],
+    ),
     "ket_process_ctrl_push": ([c_void_p, POINTER(c_size_t), c_size_t], []),
     "ket_process_ctrl_pop": ([c_void_p], []),
     "ket_process_adj_begin": ([c_void_p], []),
@@ -109,6 +113,15 @@ API_argtypes = {
         [c_void_p, c_char_p],
         [],
     ),
+    "ket_process_set_source_location": (
+        [c_void_p, POINTER(c_uint8), c_size_t, c_uint32, POINTER(c_uint8), c_size_t],
+        [],
+    ),
+    "ket_process_clear_source_location": ([c_void_p], []),
+    "ket_process_provenance_json": ([c_void_p, POINTER(c_uint8), c_size_t], [c_size_t]),
+    "ket_process_checkpoint": ([c_void_p], [c_void_p]),
+    "ket_process_rollback": ([c_void_p, c_void_p], []),
+    "ket_checkpoint_delete": ([c_void_p], []),
 }
 
 
@@ -0,0 +1,47 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use kbw::{
+    bench::Circuit,
+    dense::Dense,
+    quantum_execution::{QuantumExecution, QubitManager},
+    sparse::Sparse,
+};
+use ket::{BatchExecution, Instruction};
+
+fn execute<S: QuantumExecution + 'static>(num_qubits: usize, instructions: &[Instruction]) {
+    let mut simulator = QubitManager::<S>::new(num_qubits).unwrap();
+    simulator.submit_execution(instructions);
+}
+
+fn circuits(c: &mut Criterion) {
+    for circuit in Circuit::ALL {
+        let mut group = c.benchmark_group(circuit.name());
+        for num_qubits in [8, 12, 16] {
+            let instructions = circuit.instructions(num_qubits, 0);
+            let gates = instructions
+                .iter()
+                .filter(|instruction| matches!(instruction, Instruction::Gate { .. }))
+                .count();
+            group.throughput(Throughput::Elements(gates as u64));
+
+            group.bench_with_input(
+                BenchmarkId::new("dense", num_qubits),
+                &instructions,
+                |b, instructions| b.iter(|| execute::<Dense>(num_qubits, instructions)),
+            );
+            group.bench_with_input(
+                BenchmarkId::new("sparse", num_qubits),
+                &instructions,
+                |b, instructions| b.iter(|| execute::<Sparse>(num_qubits, instructions)),
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, circuits);
+criterion_main!(benches);
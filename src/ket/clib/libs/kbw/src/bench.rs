@@ -0,0 +1,288 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Standard circuits for benchmarking the simulators.
+//!
+//! The circuits are built directly as lists of [`ket::Instruction`] and executed with
+//! [`QubitManager`] in batch mode, so the measured time is spent in the simulator
+//! kernels and not in the construction of the process.
+
+use std::time::Instant;
+
+use ket::{Angle, BatchExecution, Instruction, QuantumGate};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::{error::Result, quantum_execution::QuantumExecution, quantum_execution::QubitManager};
+
+/// Benchmark circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Circuit {
+    /// Quantum Fourier transform on a uniform superposition.
+    Qft,
+    /// Random Clifford+T circuit with `4 * num_qubits` layers.
+    CliffordT,
+    /// GHZ state preparation.
+    Ghz,
+    /// Ladder of Toffoli gates that computes the AND of the control qubits into the
+    /// last ancilla and uncomputes the intermediate ancillas, as in the `test_tof_10`
+    /// circuit.
+    ToffoliLadder,
+}
+
+impl Circuit {
+    /// All benchmark circuits.
+    pub const ALL: [Circuit; 4] = [
+        Circuit::Qft,
+        Circuit::CliffordT,
+        Circuit::Ghz,
+        Circuit::ToffoliLadder,
+    ];
+
+    /// Name of the circuit.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Circuit::Qft => "qft",
+            Circuit::CliffordT => "clifford_t",
+            Circuit::Ghz => "ghz",
+            Circuit::ToffoliLadder => "toffoli_ladder",
+        }
+    }
+
+    /// Parses the circuit name.
+    pub fn from_name(name: &str) -> Option<Circuit> {
+        Circuit::ALL
+            .into_iter()
+            .find(|circuit| circuit.name() == name)
+    }
+
+    /// Builds the circuit instructions on `num_qubits` qubits.
+    ///
+    /// The `seed` is only used by [`Circuit::CliffordT`].
+    pub fn instructions(&self, num_qubits: usize, seed: u64) -> Vec<Instruction> {
+        let mut instructions: Vec<Instruction> = (0..num_qubits)
+            .map(|target| Instruction::Alloc { target })
+            .collect();
+
+        match self {
+            Circuit::Qft => qft(&mut instructions, num_qubits),
+            Circuit::CliffordT => clifford_t(&mut instructions, num_qubits, seed),
+            Circuit::Ghz => ghz(&mut instructions, num_qubits),
+            Circuit::ToffoliLadder => toffoli_ladder(&mut instructions, num_qubits),
+        }
+
+        instructions
+    }
+}
+
+fn gate(gate: QuantumGate, target: usize, control: &[usize]) -> Instruction {
+    Instruction::Gate {
+        gate,
+        target,
+        control: control.to_vec(),
+    }
+}
+
+fn qft(instructions: &mut Vec<Instruction>, num_qubits: usize) {
+    for target in 0..num_qubits {
+        instructions.push(gate(QuantumGate::Hadamard, target, &[]));
+    }
+    for target in 0..num_qubits {
+        instructions.push(gate(QuantumGate::Hadamard, target, &[]));
+        for (k, control) in (target + 1..num_qubits).enumerate() {
+            instructions.push(gate(
+                QuantumGate::Phase(Angle::PiFraction {
+                    top: 1,
+                    bottom: 1 << (k + 1).min(31),
+                }),
+                target,
+                &[control],
+            ));
+        }
+    }
+    for target in 0..num_qubits / 2 {
+        let other = num_qubits - target - 1;
+        instructions.push(gate(QuantumGate::PauliX, other, &[target]));
+        instructions.push(gate(QuantumGate::PauliX, target, &[other]));
+        instructions.push(gate(QuantumGate::PauliX, other, &[target]));
+    }
+}
+
+fn clifford_t(instructions: &mut Vec<Instruction>, num_qubits: usize, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..4 * num_qubits {
+        for target in 0..num_qubits {
+            let instruction = match rng.gen_range(0..4) {
+                0 => gate(QuantumGate::Hadamard, target, &[]),
+                1 => gate(
+                    QuantumGate::Phase(Angle::PiFraction { top: 1, bottom: 2 }),
+                    target,
+                    &[],
+                ),
+                2 => gate(
+                    QuantumGate::Phase(Angle::PiFraction { top: 1, bottom: 4 }),
+                    target,
+                    &[],
+                ),
+                _ if num_qubits > 1 => {
+                    let control = (target + rng.gen_range(1..num_qubits)) % num_qubits;
+                    gate(QuantumGate::PauliX, target, &[control])
+                }
+                _ => gate(QuantumGate::Hadamard, target, &[]),
+            };
+            instructions.push(instruction);
+        }
+    }
+}
+
+fn ghz(instructions: &mut Vec<Instruction>, num_qubits: usize) {
+    instructions.push(gate(QuantumGate::Hadamard, 0, &[]));
+    for target in 1..num_qubits {
+        instructions.push(gate(QuantumGate::PauliX, target, &[target - 1]));
+    }
+}
+
+fn toffoli_ladder(instructions: &mut Vec<Instruction>, num_qubits: usize) {
+    // `controls` control qubits followed by `controls - 1` ancillas.
+    let controls = num_qubits.div_ceil(2);
+    for target in 0..controls {
+        instructions.push(gate(QuantumGate::Hadamard, target, &[]));
+    }
+    if controls < 2 {
+        return;
+    }
+
+    let ancilla = |index: usize| controls + index;
+    let mut ladder = vec![gate(QuantumGate::PauliX, ancilla(0), &[0, 1])];
+    for control in 2..controls {
+        ladder.push(gate(
+            QuantumGate::PauliX,
+            ancilla(control - 1),
+            &[control, ancilla(control - 2)],
+        ));
+    }
+
+    instructions.extend(ladder.iter().cloned());
+    instructions.extend(ladder.into_iter().rev().skip(1));
+}
+
+/// Result of a benchmark run.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    /// Benchmarked circuit.
+    pub circuit: Circuit,
+
+    /// Simulator name.
+    pub backend: String,
+
+    /// Number of qubits.
+    pub qubits: usize,
+
+    /// Number of gates in the circuit.
+    pub gates: usize,
+
+    /// Best execution time over the repetitions, in seconds.
+    pub seconds: f64,
+
+    /// Gates executed per second in the best repetition.
+    pub gates_per_second: f64,
+
+    /// Increase of the resident memory while the simulator is alive, in bytes.
+    ///
+    /// Only available on Linux.
+    pub memory_bytes: Option<u64>,
+}
+
+/// Resident memory of the current process, in bytes.
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Runs the circuit `repeat` times on the simulator `S` and keeps the best time.
+pub fn run<S: QuantumExecution + 'static>(
+    backend: &str,
+    circuit: Circuit,
+    num_qubits: usize,
+    seed: u64,
+    repeat: usize,
+) -> Result<BenchResult> {
+    let instructions = circuit.instructions(num_qubits, seed);
+    let gates = instructions
+        .iter()
+        .filter(|instruction| matches!(instruction, Instruction::Gate { .. }))
+        .count();
+
+    let mut seconds = f64::INFINITY;
+    let mut memory_bytes = None;
+    for _ in 0..repeat.max(1) {
+        let baseline = resident_memory();
+        let mut simulator = QubitManager::<S>::new(num_qubits)?;
+
+        let start = Instant::now();
+        simulator.submit_execution(&instructions);
+        seconds = seconds.min(start.elapsed().as_secs_f64());
+
+        if let (Some(baseline), Some(current)) = (baseline, resident_memory()) {
+            let used = current.saturating_sub(baseline);
+            memory_bytes = Some(memory_bytes.map_or(used, |max: u64| max.max(used)));
+        }
+    }
+
+    Ok(BenchResult {
+        circuit,
+        backend: backend.to_string(),
+        qubits: num_qubits,
+        gates,
+        seconds,
+        gates_per_second: gates as f64 / seconds,
+        memory_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, Circuit};
+    use crate::{dense::Dense, sparse::Sparse};
+    use ket::{BatchExecution, Instruction};
+
+    use crate::quantum_execution::QubitManager;
+
+    #[test]
+    fn circuits_run_on_both_backends() {
+        for circuit in Circuit::ALL {
+            assert_eq!(Circuit::from_name(circuit.name()), Some(circuit));
+
+            let dense = run::<Dense>("dense", circuit, 5, 42, 1).unwrap();
+            let sparse = run::<Sparse>("sparse", circuit, 5, 42, 1).unwrap();
+            assert_eq!(dense.gates, sparse.gates);
+            assert!(dense.gates > 0);
+        }
+    }
+
+    #[test]
+    fn toffoli_ladder_uncomputes() {
+        let mut instructions = Circuit::ToffoliLadder.instructions(7, 0);
+        instructions.push(Instruction::Dump {
+            qubits: (0..7).collect(),
+            output: 0,
+        });
+
+        let mut simulator = QubitManager::<Sparse>::new(7).unwrap();
+        simulator.submit_execution(&instructions);
+        let result = simulator.get_result();
+
+        // The intermediate ancillas return to |0> and the last one holds the AND of
+        // the 4 controls.
+        assert_eq!(result.dumps[0].basis_states.len(), 16);
+        assert!(result.dumps[0].basis_states.iter().all(|state| {
+            let controls = state[0] >> 3;
+            state[0] & 0b110 == 0 && (state[0] & 1 == 1) == (controls == 0b1111)
+        }));
+    }
+}
@@ -0,0 +1,115 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Benchmarks the KBW simulators on standard circuits.
+//!
+//! Every combination of circuit, backend, and number of qubits is executed and the
+//! best time, the gate throughput, and the memory used by the simulator are reported.
+//!
+//! ```text
+//! ket-bench --circuits qft,ghz --qubits 8,12,16 --backends dense,sparse
+//! ```
+
+use std::error::Error;
+
+use clap::Parser;
+use kbw::{
+    bench::{run, BenchResult, Circuit},
+    dense::Dense,
+    sparse::Sparse,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "ket-bench",
+    version,
+    about = "Benchmark the KBW simulators on standard circuits"
+)]
+struct Args {
+    /// Comma-separated list of circuits: qft, clifford_t, ghz, toffoli_ladder
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "qft,clifford_t,ghz,toffoli_ladder"
+    )]
+    circuits: Vec<String>,
+
+    /// Comma-separated list of backends: dense, sparse
+    #[arg(long, value_delimiter = ',', default_value = "dense,sparse")]
+    backends: Vec<String>,
+
+    /// Comma-separated list of numbers of qubits
+    #[arg(long, value_delimiter = ',', default_value = "8,12,16")]
+    qubits: Vec<usize>,
+
+    /// Number of repetitions of each run; the best time is reported
+    #[arg(long, default_value_t = 3)]
+    repeat: usize,
+
+    /// Seed of the random circuits
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Print the results as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn format_bytes(bytes: Option<u64>) -> String {
+    match bytes {
+        Some(bytes) if bytes >= 1 << 20 => format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64),
+        Some(bytes) => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        None => "n/a".to_string(),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let circuits = args
+        .circuits
+        .iter()
+        .map(|name| {
+            Circuit::from_name(name.trim()).ok_or_else(|| format!("unknown circuit `{}`", name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut results: Vec<BenchResult> = Vec::new();
+    for circuit in &circuits {
+        for backend in &args.backends {
+            for num_qubits in &args.qubits {
+                let result = match backend.trim() {
+                    "dense" => {
+                        run::<Dense>("dense", *circuit, *num_qubits, args.seed, args.repeat)?
+                    }
+                    "sparse" => {
+                        run::<Sparse>("sparse", *circuit, *num_qubits, args.seed, args.repeat)?
+                    }
+                    other => return Err(format!("unknown backend `{}`", other).into()),
+                };
+
+                if !args.json {
+                    println!(
+                        "{:<16}{:<8}{:>7}{:>9}{:>13.6}{:>16.0}{:>13}",
+                        result.circuit.name(),
+                        result.backend,
+                        result.qubits,
+                        result.gates,
+                        result.seconds,
+                        result.gates_per_second,
+                        format_bytes(result.memory_bytes),
+                    );
+                }
+                results.push(result);
+            }
+        }
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,526 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Interactive exploration of circuits on the KBW simulator.
+//!
+//! Each line is a gate, applied at once to a live simulation of the qubits, a
+//! measurement, or a command:
+//!
+//! ```text
+//! $ ket-repl --qubits 2
+//! ket> h 0
+//! ket> cx 0 1
+//! ket> :draw
+//! q0: -H-*-
+//! q1: ---X-
+//! ket> :state
+//! ```
+//!
+//! A gate is written with its name, its angle for rotations, and its qubits, controls
+//! first: `h 0`, `rz pi/4 1`, `ccx 0 1 2`. Each leading `c` adds a control qubit. The
+//! commands are `:state`, `:draw`, `:optimize`, `:undo`, `:reset`, `:help`, and
+//! `:quit`. Each step takes a [`Checkpoint`] of the process, with the state of the
+//! simulation, so `:undo` goes back to it, and the earlier measurements keep the
+//! outcomes they had.
+
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+};
+
+use clap::{Parser, ValueEnum};
+use kbw::{dense::Dense, quantum_execution::QubitManager, sparse::Sparse};
+use ket::{
+    basis::to_bitstring,
+    error::KetError,
+    passes::{PassManager, Peephole, ResourceReport},
+    Angle, Checkpoint, Instruction, Process, QuantumGate,
+};
+
+const HELP: &str = "\
+gates:     x y z h s sdg t tdg <qubit>
+           rx ry rz p <angle> <qubit>
+           a leading `c` adds a control qubit, given first: cx 0 1, ccz 0 1 2
+angles:    a number in radians, or a fraction of pi: pi/4, -3pi/2
+measure:   measure <qubit>...
+commands:  :state     print the state of the qubits
+           :draw      draw the circuit
+           :optimize  simplify the circuit and print its resources
+           :undo      remove the last gate, measurement, or optimization
+           :reset     start again from |0...0>
+           :help      print this message
+           :quit      exit";
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Backend {
+    Dense,
+    Sparse,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "ket-repl",
+    version,
+    about = "Build and simulate a circuit interactively on the KBW simulator"
+)]
+struct Args {
+    /// Number of qubits
+    #[arg(long, default_value_t = 3)]
+    qubits: usize,
+
+    /// Simulator backend
+    #[arg(long, value_enum, default_value_t = Backend::Dense)]
+    backend: Backend,
+
+    /// Simulator seed, for reproducible sessions
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// Step of a session, undone by `:undo`.
+#[derive(Debug, Clone)]
+enum Step {
+    Gate {
+        gate: QuantumGate,
+        target: usize,
+        control: Vec<usize>,
+    },
+    Measure(Vec<usize>),
+    Optimize,
+}
+
+/// Input line.
+#[derive(Debug, Clone)]
+enum Command {
+    Step(Step),
+    State,
+    Draw,
+    Undo,
+    Reset,
+    Help,
+    Quit,
+}
+
+/// Parses an angle in radians or as a fraction of pi, as `pi/4` or `-3pi/2`.
+fn parse_angle(text: &str) -> Option<Angle> {
+    let (numerator, denominator) = match text.split_once('/') {
+        Some((numerator, denominator)) => (numerator, Some(denominator.parse::<u32>().ok()?)),
+        None => (text, None),
+    };
+    let Some(coefficient) = numerator.strip_suffix("pi") else {
+        let angle: f64 = numerator.parse().ok()?;
+        return Some(Angle::Scalar(angle / denominator.unwrap_or(1) as f64));
+    };
+    let top = match coefficient.trim_end_matches('*') {
+        "" => 1,
+        "-" => -1,
+        coefficient => coefficient.parse().ok()?,
+    };
+    match denominator {
+        Some(0) => None,
+        denominator => Some(Angle::pi_fraction(top, denominator.unwrap_or(1))),
+    }
+}
+
+/// Parses an input line, returning `None` for an empty line.
+fn parse(line: &str) -> Result<Option<Command>, String> {
+    let mut words = line.split_whitespace();
+    let Some(name) = words.next() else {
+        return Ok(None);
+    };
+    let command = match name {
+        ":state" => Command::State,
+        ":draw" => Command::Draw,
+        ":optimize" => Command::Step(Step::Optimize),
+        ":undo" => Command::Undo,
+        ":reset" => Command::Reset,
+        ":help" => Command::Help,
+        ":quit" | ":q" => Command::Quit,
+        name if name.starts_with(':') => return Err(format!("unknown command `{name}`")),
+        _ => return parse_step(name, words).map(|step| Some(Command::Step(step))),
+    };
+    Ok(Some(command))
+}
+
+fn parse_step<'a>(name: &str, mut words: impl Iterator<Item = &'a str>) -> Result<Step, String> {
+    let qubits = |words: &mut dyn Iterator<Item = &'a str>| {
+        words
+            .map(|word| {
+                word.parse::<usize>()
+                    .map_err(|_| format!("`{word}` is not a qubit"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    if name == "measure" {
+        let qubits = qubits(&mut words)?;
+        if qubits.is_empty() {
+            return Err("measure needs at least one qubit".to_string());
+        }
+        return Ok(Step::Measure(qubits));
+    }
+
+    let base = name.trim_start_matches('c');
+    let controls = name.len() - base.len();
+    let mut angle = || {
+        let word = words
+            .next()
+            .ok_or_else(|| format!("`{base}` needs an angle"))?;
+        parse_angle(word).ok_or_else(|| format!("`{word}` is not an angle"))
+    };
+    let gate = match base {
+        "x" => QuantumGate::PauliX,
+        "y" => QuantumGate::PauliY,
+        "z" => QuantumGate::PauliZ,
+        "h" => QuantumGate::Hadamard,
+        "s" => QuantumGate::Phase(Angle::pi_fraction(1, 2)),
+        "sdg" => QuantumGate::Phase(Angle::pi_fraction(-1, 2)),
+        "t" => QuantumGate::Phase(Angle::pi_fraction(1, 4)),
+        "tdg" => QuantumGate::Phase(Angle::pi_fraction(-1, 4)),
+        "rx" => QuantumGate::RotationX(angle()?),
+        "ry" => QuantumGate::RotationY(angle()?),
+        "rz" => QuantumGate::RotationZ(angle()?),
+        "p" => QuantumGate::Phase(angle()?),
+        _ => return Err(format!("unknown gate `{name}`, see :help")),
+    };
+    let mut qubits = qubits(&mut words)?;
+    if qubits.len() != controls + 1 {
+        return Err(format!(
+            "`{name}` acts on {} qubits, {} given",
+            controls + 1,
+            qubits.len()
+        ));
+    }
+    let target = qubits.pop().unwrap();
+    Ok(Step::Gate {
+        gate,
+        target,
+        control: qubits,
+    })
+}
+
+fn angle_label(angle: &Angle) -> String {
+    match angle {
+        Angle::Scalar(angle) => format!("{angle:.3}"),
+        Angle::PiFraction { top, bottom } => {
+            let top = match top {
+                1 => String::new(),
+                -1 => "-".to_string(),
+                top => top.to_string(),
+            };
+            if *bottom == 1 {
+                format!("{top}pi")
+            } else {
+                format!("{top}pi/{bottom}")
+            }
+        }
+    }
+}
+
+fn gate_label(gate: &QuantumGate) -> String {
+    match gate {
+        QuantumGate::PauliX => "X".to_string(),
+        QuantumGate::PauliY => "Y".to_string(),
+        QuantumGate::PauliZ => "Z".to_string(),
+        QuantumGate::Hadamard => "H".to_string(),
+        QuantumGate::RotationX(angle) => format!("RX({})", angle_label(angle)),
+        QuantumGate::RotationY(angle) => format!("RY({})", angle_label(angle)),
+        QuantumGate::RotationZ(angle) => format!("RZ({})", angle_label(angle)),
+        QuantumGate::Phase(angle) => format!("P({})", angle_label(angle)),
+    }
+}
+
+/// Draws the gates and measurements of a circuit, one column per instruction and one
+/// line per qubit, with `*` for the control qubits and `M` for the measured ones.
+fn draw(instructions: &[Instruction], num_qubits: usize) -> String {
+    let width = (num_qubits.max(1) - 1).to_string().len();
+    let mut lines: Vec<String> = (0..num_qubits)
+        .map(|qubit| format!("q{qubit:<width$}: -"))
+        .collect();
+
+    for instruction in instructions {
+        let mut cells = vec![String::new(); num_qubits];
+        match instruction {
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } => {
+                let low = control.iter().chain([target]).min().unwrap();
+                let high = control.iter().chain([target]).max().unwrap();
+                for cell in &mut cells[*low..=*high] {
+                    *cell = "|".to_string();
+                }
+                for qubit in control {
+                    cells[*qubit] = "*".to_string();
+                }
+                cells[*target] = gate_label(gate);
+            }
+            Instruction::Measure { qubits, .. } => {
+                for qubit in qubits {
+                    cells[*qubit] = "M".to_string();
+                }
+            }
+            _ => continue,
+        }
+
+        let column = cells.iter().map(String::len).max().unwrap_or_default();
+        for (line, cell) in lines.iter_mut().zip(&cells) {
+            line.push_str(&format!("{cell:-^column$}-"));
+        }
+    }
+    lines.join("\n")
+}
+
+fn state_table(process: &mut Process, num_qubits: usize) -> Result<String, KetError> {
+    let qubits: Vec<usize> = (0..num_qubits).collect();
+    let index = process.dump(&qubits)?;
+    let dump = process
+        .get_dump_data(index)
+        .ok_or(KetError::DataNotAvailable)?;
+
+    let width = num_qubits.max(5);
+    let mut table = format!(
+        "{:<width$}  {:>24}  {:>11}",
+        "state", "amplitude", "probability"
+    );
+    for ((state, real), imag) in dump
+        .basis_states
+        .iter()
+        .zip(&dump.amplitudes_real)
+        .zip(&dump.amplitudes_imag)
+    {
+        table.push_str(&format!(
+            "\n{:<width$}  {:>24}  {:>11.6}",
+            to_bitstring(state, num_qubits),
+            format!("{:.6}{:+.6}i", real, imag),
+            real * real + imag * imag
+        ));
+    }
+    Ok(table)
+}
+
+/// Live process with a checkpoint before each step that built it.
+struct Session {
+    backend: Backend,
+    num_qubits: usize,
+    checkpoints: Vec<Checkpoint>,
+    process: Process,
+}
+
+impl Session {
+    fn new(backend: Backend, num_qubits: usize) -> Result<Self, KetError> {
+        Ok(Self {
+            backend,
+            num_qubits,
+            checkpoints: Vec::new(),
+            process: Self::start(backend, num_qubits)?,
+        })
+    }
+
+    fn start(backend: Backend, num_qubits: usize) -> Result<Process, KetError> {
+        let configuration = match backend {
+            Backend::Dense => QubitManager::<Dense>::configuration(num_qubits, true, false),
+            Backend::Sparse => QubitManager::<Sparse>::configuration(num_qubits, true, false),
+        };
+        let mut process = Process::new(configuration);
+        for _ in 0..num_qubits {
+            process.allocate_qubit()?;
+        }
+        Ok(process)
+    }
+
+    /// Applies a step to the process, returning the text to print.
+    fn apply(&mut self, step: &Step) -> Result<Option<String>, KetError> {
+        match step {
+            Step::Gate {
+                gate,
+                target,
+                control,
+            } => {
+                self.process.ctrl_push(control)?;
+                let result = self.process.apply_gate(gate.clone(), *target);
+                self.process.ctrl_pop()?;
+                result?;
+                Ok(None)
+            }
+            Step::Measure(qubits) => {
+                let index = self.process.measure(qubits)?;
+                let result = self
+                    .process
+                    .get_measurement(index)
+                    .result
+                    .ok_or(KetError::DataNotAvailable)?;
+                Ok(Some(to_bitstring(&[result], qubits.len())))
+            }
+            Step::Optimize => {
+                let before = ResourceReport::new(&self.process);
+                self.process.optimize()?;
+                PassManager::new()
+                    .add_pass(Peephole)
+                    .run(&mut self.process)?;
+                Ok(Some(before.diff(&ResourceReport::new(&self.process))))
+            }
+        }
+    }
+
+    /// Applies a step, keeping a checkpoint to undo it if it succeeds.
+    fn run(&mut self, step: Step) -> Result<Option<String>, KetError> {
+        let checkpoint = self.process.checkpoint()?;
+        let output = self.apply(&step)?;
+        self.checkpoints.push(checkpoint);
+        Ok(output)
+    }
+
+    /// Goes back to the state before the last step, returning `false` if there is no
+    /// step to undo.
+    fn undo(&mut self) -> Result<bool, KetError> {
+        let Some(checkpoint) = self.checkpoints.pop() else {
+            return Ok(false);
+        };
+        self.process.rollback(&checkpoint)?;
+        Ok(true)
+    }
+
+    fn reset(&mut self) -> Result<(), KetError> {
+        *self = Self::new(self.backend, self.num_qubits)?;
+        Ok(())
+    }
+
+    /// Runs an input line, returning the text to print, or `None` to quit.
+    fn execute(&mut self, line: &str) -> Option<String> {
+        let command = match parse(line) {
+            Ok(Some(command)) => command,
+            Ok(None) => return Some(String::new()),
+            Err(message) => return Some(format!("error: {message}")),
+        };
+        let result = match command {
+            Command::Step(step) => self.run(step).map(Option::unwrap_or_default),
+            Command::State => state_table(&mut self.process, self.num_qubits),
+            Command::Draw => Ok(draw(self.process.instructions(), self.num_qubits)),
+            Command::Undo => self.undo().map(|undone| {
+                if undone {
+                    String::new()
+                } else {
+                    "nothing to undo".to_string()
+                }
+            }),
+            Command::Reset => self.reset().map(|_| String::new()),
+            Command::Help => Ok(HELP.to_string()),
+            Command::Quit => return None,
+        };
+        Some(result.unwrap_or_else(|error| format!("error: {error}")))
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    if let Some(seed) = args.seed {
+        std::env::set_var("KBW_SEED", seed.to_string());
+    }
+
+    let mut session = Session::new(args.backend, args.qubits)?;
+    println!(
+        "{} qubits on the {:?} simulator, type :help for the commands",
+        args.qubits, args.backend
+    );
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("ket> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            break;
+        };
+        match session.execute(&line?) {
+            Some(output) if output.is_empty() => {}
+            Some(output) => println!("{output}"),
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use super::{draw, parse, parse_angle, Backend, Command, Session, Step};
+    use ket::{Angle, QuantumGate};
+
+    fn debug(value: impl Debug) -> String {
+        format!("{value:?}")
+    }
+
+    #[test]
+    fn parse_lines() {
+        assert_eq!(
+            debug(parse_angle("pi/4")),
+            debug(Some(Angle::pi_fraction(1, 4)))
+        );
+        assert_eq!(
+            debug(parse_angle("-3pi/2")),
+            debug(Some(Angle::pi_fraction(-3, 2)))
+        );
+        assert_eq!(
+            debug(parse_angle("2*pi")),
+            debug(Some(Angle::pi_fraction(2, 1)))
+        );
+        assert_eq!(debug(parse_angle("0.5")), debug(Some(Angle::Scalar(0.5))));
+        assert!(parse_angle("pi/0").is_none());
+
+        assert_eq!(
+            debug(parse("ccrz pi/2 0 1 2")),
+            debug(Ok::<_, String>(Some(Command::Step(Step::Gate {
+                gate: QuantumGate::RotationZ(Angle::pi_fraction(1, 2)),
+                target: 2,
+                control: vec![0, 1],
+            }))))
+        );
+        assert!(matches!(
+            parse("measure 1 0"),
+            Ok(Some(Command::Step(Step::Measure(qubits)))) if qubits == [1, 0]
+        ));
+        assert!(matches!(parse("  "), Ok(None)));
+        assert!(parse("cx 0").is_err());
+        assert!(parse("rx 0").is_err());
+        assert!(matches!(parse(":state now"), Ok(Some(Command::State))));
+        assert!(parse(":states").is_err());
+    }
+
+    #[test]
+    fn draw_circuit() {
+        let mut session = Session::new(Backend::Dense, 3).unwrap();
+        for line in ["h 0", "cx 0 2", "rz pi/4 1", "measure 0"] {
+            session.execute(line).unwrap();
+        }
+        assert_eq!(
+            draw(session.process.instructions(), 3),
+            "q0: -H-*----------M-\n\
+             q1: ---|-RZ(pi/4)---\n\
+             q2: ---X------------"
+        );
+    }
+
+    #[test]
+    fn undo_keeps_outcomes() {
+        let mut session = Session::new(Backend::Sparse, 2).unwrap();
+        session.execute("h 0");
+        let outcome = session.execute("measure 0").unwrap();
+        session.execute("x 1");
+        assert_eq!(session.execute(":undo").unwrap(), "");
+        assert_eq!(session.checkpoints.len(), 2);
+        assert_eq!(
+            session.process.get_measurement(0).result,
+            Some(outcome.parse().unwrap())
+        );
+        assert!(session.execute(":state").unwrap().contains("1.000000"));
+
+        session.execute(":reset");
+        assert_eq!(session.execute(":undo").unwrap(), "nothing to undo");
+    }
+}
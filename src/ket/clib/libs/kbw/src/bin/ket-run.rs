@@ -0,0 +1,321 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs an OpenQASM 2.0 file on the KBW simulator.
+//!
+//! The circuit is executed once in batch mode. The measurement statements at the end
+//! are replaced by sampling the final state of all qubits. The mid-circuit measurements
+//! are kept, and each shot takes the branch of its own outcomes, so the `if`
+//! statements that test them apply per shot. Barriers are ignored.
+//!
+//! ```text
+//! ket-run bell.qasm --shots 1000 --format json
+//! ```
+//!
+//! With `--profile`, the wall time of the gates is written in the folded format of
+//! flamegraph, see [`kbw::profile`].
+
+use std::{
+    error::Error,
+    io::{self, Read},
+};
+
+use clap::{Parser, ValueEnum};
+use kbw::{
+    dense::Dense, half_precision::HalfPrecision, partitioned::Partitioned,
+    quantum_execution::QubitManager, sparse::Sparse,
+};
+use ket::{qasmv2::instruction_set::InstructionSet, DumpData, Process};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Backend {
+    Dense,
+    Sparse,
+    Partitioned,
+    HalfPrecision,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Table,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "ket-run",
+    version,
+    about = "Run an OpenQASM 2.0 file on the KBW simulator"
+)]
+struct Args {
+    /// OpenQASM 2.0 file, or `-` to read from the standard input
+    file: String,
+
+    /// Simulator backend
+    #[arg(long, value_enum, default_value_t = Backend::Sparse)]
+    backend: Backend,
+
+    /// Number of shots; use 0 to skip sampling
+    #[arg(long, default_value_t = 1024)]
+    shots: u64,
+
+    /// Optimize the circuit with ZX-calculus before the execution
+    #[arg(long)]
+    optimize: bool,
+
+    /// Print the final state vector
+    #[arg(long)]
+    dump: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+
+    /// Simulator seed, for reproducible runs
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Number of qubits of the simulator; defaults to the size of the `qreg` declarations
+    #[arg(long)]
+    qubits: Option<usize>,
+
+    /// Write the time of each gate, by gate and qubit, to a flamegraph folded file
+    #[arg(long)]
+    profile: Option<String>,
+}
+
+/// Removes comments, `barrier` statements, and the final `measure` statements from the
+/// source.
+///
+/// The measurements followed by other statements are kept, as `if` statements may test
+/// their bits.
+fn strip_final_measurements(qasm: &str) -> String {
+    let source: String = qasm
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let is_measure = |statement: &&str| statement.trim().starts_with("measure");
+    let statements: Vec<&str> = source
+        .split(';')
+        .filter(|statement| !statement.trim().starts_with("barrier"))
+        .collect();
+    let end = statements
+        .iter()
+        .rposition(|statement| !statement.trim().is_empty() && !is_measure(statement))
+        .map_or(0, |last| last + 1);
+
+    statements[..end]
+        .iter()
+        .chain(
+            statements[end..]
+                .iter()
+                .filter(|statement| !is_measure(statement)),
+        )
+        .copied()
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Sums the size of the `qreg` declarations.
+fn count_qubits(qasm: &str) -> usize {
+    qasm.split(';')
+        .filter_map(|statement| statement.trim().strip_prefix("qreg"))
+        .filter_map(|declaration| {
+            let open = declaration.find('[')?;
+            let close = declaration.find(']')?;
+            declaration[open + 1..close].trim().parse::<usize>().ok()
+        })
+        .sum()
+}
+
+fn bit_string(state: &[u64], size: usize) -> String {
+    ket::basis::to_bitstring(state, size)
+}
+
+fn print_json(
+    size: usize,
+    shots: u64,
+    counts: Option<&(Vec<u64>, Vec<u64>)>,
+    dump: Option<&DumpData>,
+) {
+    let mut output = serde_json::json!({ "qubits": size });
+
+    if let Some((states, counts)) = counts {
+        let counts: serde_json::Map<String, serde_json::Value> = states
+            .iter()
+            .zip(counts.iter())
+            .map(|(state, count)| (bit_string(&[*state], size), (*count).into()))
+            .collect();
+        output["shots"] = shots.into();
+        output["counts"] = counts.into();
+    }
+
+    if let Some(dump) = dump {
+        output["dump"] = serde_json::json!({
+            "basis_states": dump
+                .basis_states
+                .iter()
+                .map(|state| bit_string(state, size))
+                .collect::<Vec<_>>(),
+            "amplitudes": dump
+                .amplitudes_real
+                .iter()
+                .zip(dump.amplitudes_imag.iter())
+                .map(|(real, imag)| [*real, *imag])
+                .collect::<Vec<_>>(),
+        });
+    }
+
+    println!("{}", serde_json::to_string_pretty(&output).unwrap());
+}
+
+fn print_table(
+    size: usize,
+    shots: u64,
+    counts: Option<&(Vec<u64>, Vec<u64>)>,
+    dump: Option<&DumpData>,
+) {
+    let width = size.max(5);
+
+    if let Some((states, counts)) = counts {
+        println!(
+            "{:<width$}  {:>10}  {:>11}",
+            "state", "count", "probability"
+        );
+        for (state, count) in states.iter().zip(counts.iter()) {
+            println!(
+                "{:<width$}  {:>10}  {:>11.6}",
+                bit_string(&[*state], size),
+                count,
+                *count as f64 / shots as f64
+            );
+        }
+    }
+
+    if let Some(dump) = dump {
+        if counts.is_some() {
+            println!();
+        }
+        println!(
+            "{:<width$}  {:>24}  {:>11}",
+            "state", "amplitude", "probability"
+        );
+        for ((state, real), imag) in dump
+            .basis_states
+            .iter()
+            .zip(dump.amplitudes_real.iter())
+            .zip(dump.amplitudes_imag.iter())
+        {
+            println!(
+                "{:<width$}  {:>24}  {:>11.6}",
+                bit_string(state, size),
+                format!("{:.6}{:+.6}i", real, imag),
+                real * real + imag * imag
+            );
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let mut qasm = String::new();
+    if args.file == "-" {
+        io::stdin().read_to_string(&mut qasm)?;
+    } else {
+        qasm = std::fs::read_to_string(&args.file)?;
+    }
+
+    let qasm = strip_final_measurements(&qasm);
+
+    let num_qubits = args.qubits.unwrap_or_else(|| count_qubits(&qasm));
+
+    if let Some(seed) = args.seed {
+        std::env::set_var("KBW_SEED", seed.to_string());
+    }
+    if let Some(profile) = &args.profile {
+        std::env::set_var("KBW_PROFILE", profile);
+    }
+
+    let configuration = match args.backend {
+        Backend::Dense => QubitManager::<Dense>::configuration(num_qubits, false, false),
+        Backend::Sparse => QubitManager::<Sparse>::configuration(num_qubits, false, false),
+        Backend::Partitioned => {
+            QubitManager::<Partitioned>::configuration(num_qubits, false, false)
+        }
+        Backend::HalfPrecision => {
+            QubitManager::<HalfPrecision>::configuration(num_qubits, false, false)
+        }
+    };
+
+    let mut process = Process::new(configuration);
+    process.from_qasmv2(&qasm, InstructionSet::QELIB, false)?;
+
+    if args.optimize {
+        process.optimize()?;
+    }
+
+    let qubits: Vec<usize> = (0..process.qubits.len())
+        .filter(|qubit| process.get_qubit_status(*qubit).allocated)
+        .collect();
+
+    let sample = if args.shots > 0 {
+        Some(process.sample(&qubits, args.shots)?)
+    } else {
+        None
+    };
+    let dump = if args.dump {
+        Some(process.dump(&qubits)?)
+    } else {
+        None
+    };
+
+    process.prepare_for_execution()?;
+
+    let counts = sample.and_then(|index| process.get_sample(index).result.as_ref());
+    let dump = dump.and_then(|index| process.get_dump_data(index));
+
+    match args.format {
+        Format::Json => print_json(qubits.len(), args.shots, counts, dump.as_ref()),
+        Format::Table => print_table(qubits.len(), args.shots, counts, dump.as_ref()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bit_string, count_qubits, strip_final_measurements};
+
+    #[test]
+    fn strip_terminal_measurements() {
+        let qasm = "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\ncreg c[2];\n\
+                    h q[0]; // superposition\ncx q[0], q[1];\nbarrier q;\nmeasure q -> c;\n";
+
+        let stripped = strip_final_measurements(qasm);
+
+        assert!(!stripped.contains("measure"));
+        assert!(!stripped.contains("barrier"));
+        assert!(stripped.contains("cx q[0], q[1]"));
+        assert_eq!(count_qubits(&stripped), 2);
+
+        // The bit tested by the `if` statement is still measured.
+        let stripped = strip_final_measurements(
+            "qreg q[2]; creg c[2]; h q[0]; measure q[0] -> c[0]; \
+             if (c==1) x q[1]; measure q -> c;",
+        );
+        assert!(stripped.contains("measure q[0] -> c[0]"));
+        assert!(stripped.contains("if (c==1) x q[1]"));
+        assert!(!stripped.contains("measure q -> c"));
+    }
+
+    #[test]
+    fn format_bit_strings() {
+        assert_eq!(bit_string(&[0b01], 2), "01");
+        assert_eq!(bit_string(&[1, 0], 66), format!("01{}", "0".repeat(64)));
+    }
+}
@@ -3,31 +3,121 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use num::Integer;
+//! Bit manipulation of basis state indices.
+//!
+//! The functions are generic over the primitive integer used as the index, so the
+//! same primitives serve the dense simulator (`usize`), the sparse simulators (`u64`),
+//! and wider keys (`u128`). The `_vec` variants operate on indices split into 64-bit
+//! words, with qubit `i` at bit `i % 64` of word `i / 64`.
 
-pub(crate) fn bit_flip(state: usize, index: usize) -> usize {
-    state ^ (1 << index)
+use num::{Integer, PrimInt};
+
+/// Flips the bit at `index`.
+pub fn bit_flip<T: PrimInt>(state: T, index: usize) -> T {
+    state ^ (T::one() << index)
 }
 
-pub(crate) fn bit_flip_vec(mut state: Vec<u64>, index: usize) -> Vec<u64> {
+/// Flips the bit at `index` of a multi-word index.
+pub fn bit_flip_vec(mut state: Vec<u64>, index: usize) -> Vec<u64> {
     let (outer_index, inner_index) = index.div_mod_floor(&64);
-    state[outer_index] = bit_flip(state[outer_index] as usize, inner_index) as u64;
+    state[outer_index] = bit_flip(state[outer_index], inner_index);
     state
 }
 
-pub(crate) fn is_one_at(state: usize, target: usize) -> bool {
-    state & (1 << target) != 0
+/// Swaps the bits at `a` and `b`.
+pub fn bit_swap<T: PrimInt>(state: T, a: usize, b: usize) -> T {
+    if is_one_at(state, a) != is_one_at(state, b) {
+        state ^ (T::one() << a) ^ (T::one() << b)
+    } else {
+        state
+    }
+}
+
+/// Returns whether the bit at `target` is set.
+pub fn is_one_at<T: PrimInt>(state: T, target: usize) -> bool {
+    state & (T::one() << target) != T::zero()
 }
 
-pub(crate) fn is_one_at_vec(state: &[u64], target: usize) -> bool {
+/// Returns whether the bit at `target` of a multi-word index is set.
+pub fn is_one_at_vec(state: &[u64], target: usize) -> bool {
     let (outer_index, inner_index) = target.div_mod_floor(&64);
-    state[outer_index] & (1 << inner_index) != 0
+    is_one_at(state[outer_index], inner_index)
+}
+
+/// Returns a mask with the bits of the control qubits set.
+pub fn ctrl_mask<T: PrimInt>(control: &[usize]) -> T {
+    control
+        .iter()
+        .fold(T::zero(), |mask, control| mask | (T::one() << *control))
 }
 
-pub(crate) fn ctrl_check(state: usize, control: &[usize]) -> bool {
-    control.iter().all(|control| is_one_at(state, *control))
+/// Returns whether all bits of `mask` are set, see [`ctrl_mask`].
+pub fn ctrl_check_mask<T: PrimInt>(state: T, mask: T) -> bool {
+    state & mask == mask
 }
 
-pub(crate) fn ctrl_check_vec(state: &[u64], control: &[usize]) -> bool {
+/// Returns whether all control qubits are set.
+pub fn ctrl_check<T: PrimInt>(state: T, control: &[usize]) -> bool {
+    ctrl_check_mask(state, ctrl_mask(control))
+}
+
+/// Returns whether all control qubits of a multi-word index are set.
+pub fn ctrl_check_vec(state: &[u64], control: &[usize]) -> bool {
     control.iter().all(|control| is_one_at_vec(state, *control))
 }
+
+/// Gathers the bits of `qubits` into a compact value, the first qubit being the most
+/// significant bit, as in the results of dumps and measurements.
+pub fn extract_bits<T: PrimInt>(state: T, qubits: &[usize]) -> T {
+    qubits.iter().fold(T::zero(), |value, qubit| {
+        (value << 1) | if is_one_at(state, *qubit) { T::one() } else { T::zero() }
+    })
+}
+
+/// Scatters the bits of a compact value to the positions of `qubits`, the inverse of
+/// [`extract_bits`]. The other bits of the result are cleared.
+pub fn scatter_bits<T: PrimInt>(value: T, qubits: &[usize]) -> T {
+    qubits
+        .iter()
+        .rev()
+        .enumerate()
+        .fold(T::zero(), |state, (index, qubit)| {
+            if is_one_at(value, index) {
+                state | (T::one() << *qubit)
+            } else {
+                state
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generic_indices() {
+        assert_eq!(bit_flip(0b101usize, 1), 0b111);
+        assert_eq!(bit_flip(0u128, 100), 1 << 100);
+        assert!(is_one_at(1u64 << 63, 63));
+        assert_eq!(bit_swap(0b01u8, 0, 1), 0b10);
+
+        let mask: u128 = ctrl_mask(&[3, 90]);
+        assert!(ctrl_check_mask((1 << 90) | 0b1000, mask));
+        assert!(!ctrl_check(1u128 << 90, &[3, 90]));
+        assert!(ctrl_check(0usize, &[]));
+
+        let state = bit_flip_vec(vec![0, 0], 70);
+        assert!(is_one_at_vec(&state, 70));
+        assert!(ctrl_check_vec(&state, &[70]));
+    }
+
+    #[test]
+    fn extract_and_scatter() {
+        let qubits = [4, 0, 2];
+        assert_eq!(extract_bits(0b10001u32, &qubits), 0b110);
+        assert_eq!(scatter_bits(0b110u32, &qubits), 0b10001);
+        for value in 0..8u64 {
+            assert_eq!(extract_bits(scatter_bits(value, &qubits), &qubits), value);
+        }
+    }
+}
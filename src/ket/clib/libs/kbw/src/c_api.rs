@@ -65,17 +65,251 @@ pub extern "C" fn kbw_make_configuration(
     num_qubits: usize,
     live: bool,
     use_sparse: bool,
+    optimize: bool,
     result: &mut *mut ket::Configuration,
 ) -> i32 {
     if use_sparse {
         *result = Box::into_raw(Box::new(QubitManager::<Sparse>::configuration(
-            num_qubits, live,
+            num_qubits, live, optimize,
         )));
     } else {
         *result = Box::into_raw(Box::new(QubitManager::<Dense>::configuration(
-            num_qubits, live,
+            num_qubits, live, optimize,
         )));
     }
 
     KBWError::Success.error_code()
 }
+
+/// C header of the C API, `kbw.h`, which includes the header of Libket, `ket.h`.
+pub const HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/kbw.h"));
+
+#[cfg(test)]
+mod tests {
+    use ket::c_api::{
+        abi::{header_mismatches, python_mismatches, Signature},
+        objects::*,
+        process::*,
+    };
+
+    use super::{error::kbw_error_message, kbw_make_configuration, kbw_set_log_level, HEADER};
+    use crate::error::KBWError;
+
+    const _: unsafe extern "C" fn(u32) -> i32 = kbw_set_log_level;
+    const _: unsafe extern "C" fn(i32, *mut u8, usize, &mut usize) -> i32 = kbw_error_message;
+    const _: unsafe extern "C" fn(usize, bool, bool, bool, &mut *mut ket::Configuration) -> i32 =
+        kbw_make_configuration;
+
+    const KBW: &[Signature] = &[
+        Signature {
+            name: "kbw_set_log_level",
+            args: &["u32"],
+        },
+        Signature {
+            name: "kbw_error_message",
+            args: &["i32", "*mut u8", "usize", "&mut usize"],
+        },
+        Signature {
+            name: "kbw_make_configuration",
+            args: &["usize", "bool", "bool", "bool", "&mut *mut Configuration"],
+        },
+    ];
+
+    #[test]
+    fn header_declares_every_function() {
+        assert_eq!(header_mismatches(HEADER, "kbw_", KBW), Vec::<String>::new());
+    }
+
+    #[test]
+    fn python_layer_matches() {
+        let python = include_str!("../../../kbw.py");
+        assert_eq!(python_mismatches(python, KBW), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_export_through_c_abi() {
+        const SUCCESS: i32 = 0;
+        const HADAMARD: i32 = 0;
+        const PAULI_X: i32 = 1;
+        const PAULI_Z: i32 = 3;
+
+        assert_eq!(kbw_set_log_level(0), SUCCESS);
+
+        let mut configuration = std::ptr::null_mut();
+        assert_eq!(
+            kbw_make_configuration(2, false, false, false, &mut configuration),
+            SUCCESS
+        );
+        let mut process = std::ptr::null_mut();
+        assert_eq!(
+            unsafe { ket_process_new(configuration, &mut process) },
+            SUCCESS
+        );
+        let process = unsafe { &mut *process };
+
+        let mut qubits = [0; 2];
+        for qubit in &mut qubits {
+            assert_eq!(ket_process_allocate_qubit(process, qubit), SUCCESS);
+        }
+        assert_eq!(
+            ket_process_apply_gate(process, HADAMARD, 0, 0, 0.0, qubits[0]),
+            SUCCESS
+        );
+        assert_eq!(
+            unsafe { ket_process_ctrl_push(process, qubits.as_ptr(), 1) },
+            SUCCESS
+        );
+        assert_eq!(
+            ket_process_apply_gate(process, PAULI_X, 0, 0, 0.0, qubits[1]),
+            SUCCESS
+        );
+        assert_eq!(ket_process_ctrl_pop(process), SUCCESS);
+
+        let mut hamiltonian = std::ptr::null_mut();
+        assert_eq!(ket_hamiltonian_new(&mut hamiltonian), SUCCESS);
+        let paulis = [PAULI_Z; 2];
+        assert_eq!(
+            unsafe {
+                ket_hamiltonian_add(
+                    &mut *hamiltonian,
+                    paulis.as_ptr(),
+                    2,
+                    qubits.as_ptr(),
+                    2,
+                    1.0,
+                )
+            },
+            SUCCESS
+        );
+        let mut exp_value = 0;
+        assert_eq!(
+            unsafe { ket_process_exp_value(process, hamiltonian, &mut exp_value) },
+            SUCCESS
+        );
+
+        let mut sample = 0;
+        assert_eq!(
+            unsafe { ket_process_sample(process, qubits.as_ptr(), 2, 64, &mut sample) },
+            SUCCESS
+        );
+        let mut dump = 0;
+        assert_eq!(
+            unsafe { ket_process_dump(process, qubits.as_ptr(), 2, &mut dump) },
+            SUCCESS
+        );
+        let mut measurement = 0;
+        assert_eq!(
+            unsafe { ket_process_measure(process, qubits.as_ptr(), 2, &mut measurement) },
+            SUCCESS
+        );
+        assert_eq!(ket_process_prepare_for_execution(process), SUCCESS);
+
+        let mut available = false;
+        let mut value = 0.0;
+        assert_eq!(
+            ket_process_get_exp_value(process, exp_value, &mut available, &mut value),
+            SUCCESS
+        );
+        assert!(available);
+        assert!((value - 1.0).abs() < 1e-10);
+
+        let (mut states, mut counts, mut size) = (std::ptr::null(), std::ptr::null(), 0);
+        assert_eq!(
+            ket_process_get_sample(
+                process,
+                sample,
+                &mut available,
+                &mut states,
+                &mut counts,
+                &mut size
+            ),
+            SUCCESS
+        );
+        assert!(available);
+        let states = unsafe { std::slice::from_raw_parts(states, size) };
+        let counts = unsafe { std::slice::from_raw_parts(counts, size) };
+        assert!(states.iter().all(|state| *state == 0b00 || *state == 0b11));
+        assert_eq!(counts.iter().sum::<u64>(), 64);
+
+        assert_eq!(
+            ket_process_get_dump_size(process, dump, &mut available, &mut size),
+            SUCCESS
+        );
+        assert!(available);
+        assert_eq!(size, 2);
+        for iterator in 0..size {
+            let (mut state, mut state_size, mut real, mut imag) = (std::ptr::null(), 0, 0.0, 0.0);
+            assert_eq!(
+                unsafe {
+                    ket_process_get_dump(
+                        process,
+                        dump,
+                        iterator,
+                        &mut state,
+                        &mut state_size,
+                        &mut real,
+                        &mut imag,
+                    )
+                },
+                SUCCESS
+            );
+            assert!((real - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-10);
+            assert!(imag.abs() < 1e-10);
+        }
+
+        let mut result = 0;
+        assert_eq!(
+            ket_process_get_measurement(process, measurement, &mut available, &mut result),
+            SUCCESS
+        );
+        assert!(available);
+        assert!(result == 0b00 || result == 0b11);
+
+        let (mut allocated, mut measured) = (false, false);
+        assert_eq!(
+            ket_process_get_qubit_status(process, qubits[0], &mut allocated, &mut measured),
+            SUCCESS
+        );
+        assert!(allocated && measured);
+
+        let mut write_size = 0;
+        let mut buffer = [0u8; 8];
+        assert_eq!(
+            unsafe {
+                ket_process_instructions_json(
+                    process,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    &mut write_size,
+                )
+            },
+            SUCCESS
+        );
+        let mut buffer = vec![0u8; write_size];
+        assert_eq!(
+            unsafe {
+                ket_process_instructions_json(
+                    process,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    &mut write_size,
+                )
+            },
+            SUCCESS
+        );
+        assert_eq!(write_size, buffer.len());
+        assert!(buffer.starts_with(b"[") && buffer.ends_with(b"]"));
+
+        let message = KBWError::Success.to_string();
+        let mut buffer = vec![0u8; message.len()];
+        assert_eq!(
+            unsafe {
+                kbw_error_message(SUCCESS, buffer.as_mut_ptr(), buffer.len(), &mut write_size)
+            },
+            SUCCESS
+        );
+        assert_eq!(write_size, buffer.len());
+
+        assert_eq!(unsafe { ket_process_delete(process) }, SUCCESS);
+    }
+}
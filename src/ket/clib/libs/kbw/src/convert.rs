@@ -7,7 +7,8 @@ use std::collections::HashMap;
 
 use num::complex::{Complex64, ComplexFloat};
 use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
-use rayon::prelude::*;
+
+use crate::parallel::*;
 
 pub(crate) struct DumpProbability {
     pub(crate) basis_states: Vec<Vec<u64>>,
@@ -28,6 +29,10 @@ pub(crate) fn from_dump_to_prob(data: ket::DumpData) -> DumpProbability {
     }
 }
 
+/// Draws `shots` basis states.
+///
+/// The outcomes are the 64 least significant bits of the basis states, as the sample
+/// results are `u64`.
 pub(crate) fn from_prob_to_shots<R: Rng>(
     data: DumpProbability,
     shots: u64,
@@ -45,6 +50,6 @@ pub(crate) fn from_prob_to_shots<R: Rng>(
 
     count_map
         .drain()
-        .map(|(state, count)| (state[0], count))
+        .map(|(state, count)| (ket::basis::low_word(state), count))
         .unzip()
 }
@@ -0,0 +1,584 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wire cutting of circuits too large for a single simulator.
+//!
+//! Cutting the wire of a qubit replaces the identity channel at that point by the
+//! quasi-probability decomposition
+//!
+//! ```text
+//! ρ = ½ (Tr(ρ) (|0⟩⟨0| + |1⟩⟨1|) + Tr(Zρ) (|0⟩⟨0| - |1⟩⟨1|)
+//!       + Tr(Xρ) (|+⟩⟨+| - |-⟩⟨-|) + Tr(Yρ) (|+i⟩⟨+i| - |-i⟩⟨-i|)),
+//! ```
+//!
+//! so the circuit before the cut measures the qubit in a Pauli basis, and the circuit
+//! after the cut starts from an eigenstate of that basis. [`CutCircuit::new`] cuts the
+//! wires greedily until every fragment fits in `max_qubits` qubits, and
+//! [`CutCircuit::exp_values`] runs every preparation of every fragment and recombines
+//! the expected values of the circuit. The number of runs grows as `6^k` with the
+//! number `k` of cuts entering a fragment, and the recombination as `4^k` with the
+//! total number of cuts, so cutting is only practical for a few cuts.
+//!
+//! ```rust
+//! # use ket::{Configuration, PauliHamiltonian, Process, QuantumGate};
+//! use kbw::cutting::CutCircuit;
+//! use kbw::dense::Dense;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut process = Process::new(Configuration::new(3));
+//! let qubits: Vec<usize> = (0..3).map(|_| process.allocate_qubit()).collect::<Result<_, _>>()?;
+//! process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+//! for pair in qubits.windows(2) {
+//!     process.ctrl_push(&[pair[0]])?;
+//!     process.apply_gate(QuantumGate::PauliX, pair[1])?;
+//!     process.ctrl_pop()?;
+//! }
+//! process.exp_values(PauliHamiltonian::from_openfermion("1.0 [Z0 Z2]")?)?;
+//!
+//! let cut = CutCircuit::new(process.instructions(), 2)?;
+//! assert_eq!(cut.num_cuts(), 1);
+//! let z0_z2 = cut.exp_values::<Dense>()?[0];
+//! assert!((z0_z2 - 1.0).abs() < 1e-9);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, f64::consts::FRAC_PI_2};
+
+use ket::{
+    Angle, BatchExecution, Instruction, Pauli, PauliHamiltonian, PauliProduct, PauliTerm,
+    QuantumGate,
+};
+
+use crate::{
+    error::{KBWError, Result},
+    parallel::*,
+    quantum_execution::{QuantumExecution, QubitManager},
+    threads::ThreadConfig,
+};
+
+/// Bases measured before a cut, in the order of the decomposition.
+const BASES: [Option<Pauli>; 4] = [
+    None,
+    Some(Pauli::PauliZ),
+    Some(Pauli::PauliX),
+    Some(Pauli::PauliY),
+];
+
+/// Cut of the wire of a qubit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireCut {
+    /// Qubit of the circuit.
+    pub qubit: usize,
+
+    /// Index of the first instruction after the cut.
+    pub position: usize,
+
+    /// Fragment and qubit of the fragment measured before the cut.
+    pub upstream: (usize, usize),
+
+    /// Fragment and qubit of the fragment prepared after the cut.
+    pub downstream: (usize, usize),
+}
+
+/// Subcircuit on the qubits of a fragment.
+#[derive(Debug, Clone, Default)]
+struct Fragment {
+    num_qubits: usize,
+    gates: Vec<Instruction>,
+    /// Cuts that end in this fragment, in the order of the cuts.
+    upstream: Vec<usize>,
+    /// Cuts that start in this fragment, in the order of the cuts.
+    downstream: Vec<usize>,
+}
+
+/// Circuit split in fragments by wire cuts.
+#[derive(Debug, Clone)]
+pub struct CutCircuit {
+    fragments: Vec<Fragment>,
+    cuts: Vec<WireCut>,
+    /// Fragment and qubit of the fragment holding the final state of each qubit.
+    qubits: HashMap<usize, (usize, usize)>,
+    hamiltonians: Vec<PauliHamiltonian>,
+}
+
+/// Union-find of the wire segments, each segment being a qubit between two cuts.
+#[derive(Debug, Default)]
+struct Segments {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl Segments {
+    fn add(&mut self) -> usize {
+        self.parent.push(self.parent.len());
+        self.size.push(1);
+        self.parent.len() - 1
+    }
+
+    fn root(&mut self, segment: usize) -> usize {
+        let mut root = segment;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        self.parent[segment] = root;
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) -> usize {
+        let (a, b) = (self.root(a), self.root(b));
+        if a != b {
+            self.parent[b] = a;
+            self.size[a] += self.size[b];
+        }
+        a
+    }
+}
+
+impl CutCircuit {
+    /// Cuts the circuit in fragments of at most `max_qubits` qubits.
+    ///
+    /// The gates are placed in order. A multi-qubit gate joins the fragments of its
+    /// qubits if they fit together, and otherwise joins the fragment that needs the
+    /// fewest cuts, cutting the wires of its qubits in other fragments right before the
+    /// gate. The circuit can only have qubit allocations, gates, and expected values
+    /// after the last gate.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KBWError::InvalidCircuitCut`] if the circuit has other instructions or
+    /// a gate has more than `max_qubits` qubits.
+    pub fn new(instructions: &[Instruction], max_qubits: usize) -> Result<Self> {
+        // Diagonal gates and multiplexed rotations are cut as their CNOTs and rotations.
+        let instructions = &ket::multiplexor::lower(instructions)[..];
+        let mut segments = Segments::default();
+        let mut segment_of: HashMap<usize, usize> = HashMap::new();
+        // Gates with the segments of their qubits, and cuts with their segments.
+        let mut gates = Vec::new();
+        let mut cuts = Vec::new();
+        let mut hamiltonians = Vec::new();
+
+        for (position, instruction) in instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Alloc { target } => {
+                    segment_of.insert(*target, segments.add());
+                }
+                Instruction::Gate {
+                    target, control, ..
+                } if hamiltonians.is_empty() => {
+                    let qubits: Vec<usize> = control.iter().chain([target]).copied().collect();
+                    if qubits.len() > max_qubits
+                        || qubits.iter().any(|qubit| !segment_of.contains_key(qubit))
+                    {
+                        return Err(KBWError::InvalidCircuitCut);
+                    }
+
+                    let mut roots: Vec<usize> = qubits
+                        .iter()
+                        .map(|qubit| segments.root(segment_of[qubit]))
+                        .collect();
+                    roots.sort_unstable();
+                    roots.dedup();
+                    let width: usize = roots.iter().map(|root| segments.size[*root]).sum();
+
+                    // The gate joins the fragment that needs the fewest cuts.
+                    let outside = |segments: &mut Segments, root: usize| {
+                        let (mut added, mut cut) = (0, 0);
+                        for qubit in &qubits {
+                            let other = segments.root(segment_of[qubit]);
+                            if other != root {
+                                added += 1;
+                                cut += (segments.size[other] > 1) as usize;
+                            }
+                        }
+                        (cut, segments.size[root] + added)
+                    };
+                    let destination = if width <= max_qubits {
+                        roots[0]
+                    } else {
+                        roots
+                            .iter()
+                            .map(|root| (outside(&mut segments, *root), *root))
+                            .filter(|((_, size), _)| *size <= max_qubits)
+                            .min()
+                            .ok_or(KBWError::InvalidCircuitCut)?
+                            .1
+                    };
+                    for qubit in &qubits {
+                        let segment = segment_of[qubit];
+                        let root = segments.root(segment);
+                        if root == destination {
+                            continue;
+                        }
+                        if width > max_qubits && segments.size[root] > 1 {
+                            let next = segments.add();
+                            cuts.push((*qubit, position, segment, next));
+                            segment_of.insert(*qubit, next);
+                            segments.union(destination, next);
+                        } else {
+                            segments.union(destination, segment);
+                        }
+                    }
+
+                    let segments: Vec<usize> =
+                        qubits.iter().map(|qubit| segment_of[qubit]).collect();
+                    gates.push((instruction.clone(), segments));
+                }
+                Instruction::ExpValue { hamiltonian, .. } => {
+                    hamiltonians.push(hamiltonian.clone());
+                }
+                _ => return Err(KBWError::InvalidCircuitCut),
+            }
+        }
+
+        // The fragments are the sets of joined segments, numbered in order.
+        let mut fragment_of_root: HashMap<usize, usize> = HashMap::new();
+        let mut fragments: Vec<Fragment> = Vec::new();
+        let mut local = vec![(0, 0); segments.parent.len()];
+        for (segment, local) in local.iter_mut().enumerate() {
+            let root = segments.root(segment);
+            let fragment = *fragment_of_root.entry(root).or_insert_with(|| {
+                fragments.push(Fragment::default());
+                fragments.len() - 1
+            });
+            *local = (fragment, fragments[fragment].num_qubits);
+            fragments[fragment].num_qubits += 1;
+        }
+
+        for (instruction, qubits) in gates {
+            let Instruction::Gate { gate, .. } = instruction else {
+                unreachable!()
+            };
+            let (fragment, target) = local[*qubits.last().unwrap()];
+            let control = qubits[..qubits.len() - 1]
+                .iter()
+                .map(|segment| local[*segment].1)
+                .collect();
+            fragments[fragment].gates.push(Instruction::Gate {
+                gate,
+                target,
+                control,
+            });
+        }
+
+        let cuts: Vec<WireCut> = cuts
+            .into_iter()
+            .enumerate()
+            .map(|(index, (qubit, position, upstream, downstream))| {
+                fragments[local[upstream].0].upstream.push(index);
+                fragments[local[downstream].0].downstream.push(index);
+                WireCut {
+                    qubit,
+                    position,
+                    upstream: local[upstream],
+                    downstream: local[downstream],
+                }
+            })
+            .collect();
+
+        let qubits = segment_of
+            .into_iter()
+            .map(|(qubit, segment)| (qubit, local[segment]))
+            .collect();
+
+        Ok(Self {
+            fragments,
+            cuts,
+            qubits,
+            hamiltonians,
+        })
+    }
+
+    /// Returns the wire cuts.
+    pub fn cuts(&self) -> &[WireCut] {
+        &self.cuts
+    }
+
+    /// Returns the number of wire cuts.
+    pub fn num_cuts(&self) -> usize {
+        self.cuts.len()
+    }
+
+    /// Returns the number of qubits of each fragment.
+    pub fn fragment_qubits(&self) -> Vec<usize> {
+        self.fragments
+            .iter()
+            .map(|fragment| fragment.num_qubits)
+            .collect()
+    }
+
+    /// Returns the number of fragment runs of [`CutCircuit::exp_values`].
+    pub fn num_runs(&self) -> usize {
+        self.fragments
+            .iter()
+            .map(|fragment| 6usize.pow(fragment.downstream.len() as u32))
+            .sum()
+    }
+
+    /// Returns the expected values of the circuit, recombined from the runs of the
+    /// fragments on the simulator `S`.
+    ///
+    /// Every preparation of the cuts entering a fragment is a run, and the runs are
+    /// distributed across the rayon workers.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KBWError::InvalidCircuitCut`] if a Hamiltonian has a qubit that is not
+    /// in the circuit, and the errors of the simulator creation.
+    pub fn exp_values<S: QuantumExecution + 'static>(&self) -> Result<Vec<f64>> {
+        // Terms of the Hamiltonians, with the product of each fragment.
+        let terms = self
+            .hamiltonians
+            .iter()
+            .map(|hamiltonian| {
+                hamiltonian
+                    .products
+                    .iter()
+                    .zip(&hamiltonian.coefficients)
+                    .map(|(product, coefficient)| {
+                        let mut split = vec![PauliProduct::new(); self.fragments.len()];
+                        for term in product {
+                            let (fragment, qubit) = *self
+                                .qubits
+                                .get(&term.qubit)
+                                .ok_or(KBWError::InvalidCircuitCut)?;
+                            split[fragment].push(PauliTerm {
+                                pauli: term.pauli,
+                                qubit,
+                            });
+                        }
+                        Ok((*coefficient, split))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let products: Vec<&[PauliProduct]> = terms
+            .iter()
+            .flatten()
+            .map(|(_, split)| split.as_slice())
+            .collect();
+
+        let runs: Vec<(usize, usize)> = self
+            .fragments
+            .iter()
+            .enumerate()
+            .flat_map(|(index, fragment)| {
+                (0..6usize.pow(fragment.downstream.len() as u32)).map(move |run| (index, run))
+            })
+            .collect();
+        let results: HashMap<(usize, usize), Vec<f64>> = runs
+            .into_par_iter()
+            .map(|(fragment, run)| {
+                let values = self.run_fragment::<S>(fragment, run, &products)?;
+                Ok(((fragment, run), values))
+            })
+            .collect::<Result<_>>()?;
+
+        let mut product_index = 0;
+        Ok(terms
+            .iter()
+            .map(|terms| {
+                terms
+                    .iter()
+                    .map(|(coefficient, _)| {
+                        let value = self.recombine(product_index, &results);
+                        product_index += 1;
+                        coefficient * value
+                    })
+                    .sum()
+            })
+            .collect())
+    }
+
+    /// Returns the expected value of the product of index `product` from the expected
+    /// values of the runs.
+    fn recombine(&self, product: usize, results: &HashMap<(usize, usize), Vec<f64>>) -> f64 {
+        let num_bases = 4usize.pow(self.cuts.len() as u32);
+        let sum: f64 = (0..num_bases)
+            .map(|bases| {
+                let basis = |cut: usize| (bases / 4usize.pow(cut as u32)) % 4;
+                self.fragments
+                    .iter()
+                    .enumerate()
+                    .map(|(index, fragment)| {
+                        let upstream = fragment
+                            .upstream
+                            .iter()
+                            .rev()
+                            .fold(0, |observable, cut| observable * 4 + basis(*cut));
+                        let observable =
+                            product * 4usize.pow(fragment.upstream.len() as u32) + upstream;
+
+                        // Each entering cut is prepared in the two eigenstates of its
+                        // basis, the second with a negative weight except for the
+                        // identity.
+                        (0..1usize << fragment.downstream.len())
+                            .map(|signs| {
+                                let mut run = 0;
+                                let mut weight = 1.0;
+                                for (position, cut) in fragment.downstream.iter().enumerate().rev()
+                                {
+                                    let negative = (signs >> position) & 1;
+                                    let basis = basis(*cut);
+                                    run = run * 6 + state(basis, negative);
+                                    if negative == 1 && basis != 0 {
+                                        weight = -weight;
+                                    }
+                                }
+                                weight * results[&(index, run)][observable]
+                            })
+                            .sum::<f64>()
+                    })
+                    .product::<f64>()
+            })
+            .sum();
+        sum / 2f64.powi(self.cuts.len() as i32)
+    }
+
+    /// Runs a preparation of a fragment, returning the expected value of each product
+    /// for each basis of the cuts leaving the fragment.
+    fn run_fragment<S: QuantumExecution + 'static>(
+        &self,
+        index: usize,
+        run: usize,
+        products: &[&[PauliProduct]],
+    ) -> Result<Vec<f64>> {
+        let fragment = &self.fragments[index];
+        let mut instructions: Vec<Instruction> = (0..fragment.num_qubits)
+            .map(|target| Instruction::Alloc { target })
+            .collect();
+
+        let mut states = run;
+        for cut in &fragment.downstream {
+            let target = self.cuts[*cut].downstream.1;
+            let gate = |gate| Instruction::Gate {
+                gate,
+                target,
+                control: vec![],
+            };
+            let state = states % 6;
+            states /= 6;
+            if state % 2 == 1 {
+                instructions.push(gate(QuantumGate::PauliX));
+            }
+            if state >= 2 {
+                instructions.push(gate(QuantumGate::Hadamard));
+            }
+            if state >= 4 {
+                instructions.push(gate(QuantumGate::Phase(Angle::Scalar(FRAC_PI_2))));
+            }
+        }
+        instructions.extend(fragment.gates.iter().cloned());
+
+        let num_observables = 4usize.pow(fragment.upstream.len() as u32);
+        let mut observables = Vec::with_capacity(products.len() * num_observables);
+        for product in products {
+            for bases in 0..num_observables {
+                let mut observable = product[index].clone();
+                for (position, cut) in fragment.upstream.iter().enumerate() {
+                    if let Some(pauli) = BASES[(bases / 4usize.pow(position as u32)) % 4] {
+                        observable.push(PauliTerm {
+                            pauli,
+                            qubit: self.cuts[*cut].upstream.1,
+                        });
+                    }
+                }
+                observables.push(observable);
+            }
+        }
+
+        let mut values = vec![1.0; observables.len()];
+        let measured: Vec<usize> = (0..observables.len())
+            .filter(|index| !observables[*index].is_empty())
+            .collect();
+        for (output, index) in measured.iter().enumerate() {
+            instructions.push(Instruction::ExpValue {
+                hamiltonian: PauliHamiltonian {
+                    products: vec![observables[*index].clone()],
+                    coefficients: vec![1.0],
+                },
+                output,
+            });
+        }
+        if measured.is_empty() {
+            return Ok(values);
+        }
+
+        let mut simulator =
+            QubitManager::<S>::with_options(fragment.num_qubits, None, &ThreadConfig::default())?;
+        simulator.submit_execution(&instructions);
+        for (index, value) in measured.into_iter().zip(simulator.get_result().exp_values) {
+            values[index] = value;
+        }
+        Ok(values)
+    }
+}
+
+/// Index of the state prepared after a cut measured in `basis`: `|0⟩`, `|1⟩`, `|+⟩`,
+/// `|-⟩`, `|+i⟩`, and `|-i⟩`.
+fn state(basis: usize, negative: usize) -> usize {
+    match basis {
+        0 | 1 => negative,
+        _ => 2 * (basis - 1) + negative,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CutCircuit;
+    use crate::{dense::Dense, error::KBWError, quantum_execution::QubitManager};
+    use ket::{Angle, BatchExecution, Configuration, PauliHamiltonian, Process, QuantumGate};
+
+    #[test]
+    fn cut_exp_values() -> Result<(), Box<dyn std::error::Error>> {
+        let mut process = Process::new(Configuration::new(5));
+        let qubits: Vec<usize> = (0..5)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        for (index, qubit) in qubits.iter().enumerate() {
+            let angle = Angle::Scalar(0.3 + 0.4 * index as f64);
+            process.apply_gate(QuantumGate::RotationY(angle), *qubit)?;
+        }
+        for pair in qubits.windows(2) {
+            process.ctrl_push(&[pair[0]])?;
+            process.apply_gate(QuantumGate::PauliX, pair[1])?;
+            process.ctrl_pop()?;
+            process.apply_gate(QuantumGate::RotationX(Angle::Scalar(0.7)), pair[1])?;
+        }
+        process.ctrl_push(&[qubits[4]])?;
+        process.apply_gate(QuantumGate::PauliZ, qubits[0])?;
+        process.ctrl_pop()?;
+        process.exp_values(PauliHamiltonian::from_openfermion(
+            "1.0 [Z0 Z4] +\n0.5 [X1 Y2 X3] +\n-0.25 [Y0]",
+        )?)?;
+        process.exp_values(PauliHamiltonian::from_openfermion("1.0 [Z2]")?)?;
+
+        let cut = CutCircuit::new(process.instructions(), 4)?;
+        assert_eq!(cut.num_cuts(), 2);
+        assert!(cut.fragment_qubits().iter().all(|qubits| *qubits <= 4));
+        assert!(cut.num_runs() > 1);
+
+        let mut simulator = QubitManager::<Dense>::new(5)?;
+        simulator.submit_execution(process.instructions());
+        let expected = simulator.get_result().exp_values;
+        let values = cut.exp_values::<Dense>()?;
+        assert_eq!(values.len(), 2);
+        for (value, expected) in values.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-9, "{value} != {expected}");
+        }
+
+        // Without cuts, the circuit is a single fragment.
+        let whole = CutCircuit::new(process.instructions(), 5)?;
+        assert_eq!(whole.num_cuts(), 0);
+        assert_eq!(whole.fragment_qubits(), [5]);
+
+        process.measure(&qubits)?;
+        assert!(matches!(
+            CutCircuit::new(process.instructions(), 3),
+            Err(KBWError::InvalidCircuitCut)
+        ));
+
+        Ok(())
+    }
+}
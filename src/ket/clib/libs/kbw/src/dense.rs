@@ -4,23 +4,154 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::bitwise::*;
+use crate::density::gate_matrix;
 use crate::error::{KBWError, Result};
-use crate::quantum_execution::QuantumExecution;
+use crate::noise::Matrix2;
+use crate::parallel::*;
+use crate::quantum_execution::{choose_outcome, QuantumExecution};
+use crate::summation::CompensatedSum;
 use itertools::Itertools;
 use log::error;
-use num::{complex::Complex64, Zero};
-use rand::distributions::WeightedIndex;
+use num::{complex::Complex64, One, Zero};
 use rand::prelude::*;
-use rayon::prelude::*;
 use std::f64::consts::FRAC_1_SQRT_2;
 
+/// Default number of low-order qubits kept in cache-friendly positions, see
+/// [`Dense::with_block_qubits`].
+const DEFAULT_BLOCK_QUBITS: usize = 16;
+
+/// A qubit is moved to a low-order position only when it was targeted this many times
+/// more than the qubit it replaces, to avoid swapping back and forth.
+const REMAP_MARGIN: u64 = 2;
+
+/// Number of amplitudes summed by each task of [`QuantumExecution::joint_probabilities`].
+const PROBABILITY_CHUNK: usize = 1 << 14;
+
+#[derive(Clone)]
 pub struct Dense {
     state_0: Vec<Complex64>,
     state_1: Vec<Complex64>,
     state: bool,
+    /// Physical bit of each qubit in the amplitude index.
+    position: Vec<usize>,
+    /// Qubit at each physical bit, the inverse of `position`.
+    logical_at: Vec<usize>,
+    /// Number of gates that targeted each qubit.
+    hits: Vec<u64>,
+    block_qubits: usize,
 }
 
 impl Dense {
+    /// Creates a simulator that keeps the most targeted qubits in the `block_qubits`
+    /// lowest bits of the amplitude index.
+    ///
+    /// A gate on a qubit at a high bit pairs amplitudes that are far apart in memory,
+    /// which thrashes the cache on large states. When such a qubit is targeted often
+    /// enough, it is swapped with the least targeted qubit in the low bits, so the
+    /// following gates pair amplitudes within a cache-sized block. The qubit layout is
+    /// a permutation of the amplitude index, applied lazily, and is transparent to
+    /// measurements and dumps. Use `0` to disable the remapping.
+    ///
+    /// [`QuantumExecution::new`] uses the value of the `KBW_DENSE_BLOCK_QUBITS`
+    /// environment variable, or 16 if it is not set.
+    pub fn with_block_qubits(num_qubits: usize, block_qubits: usize) -> Result<Self> {
+        if num_qubits > 32 {
+            error!("dense implementation supports up to 32 qubits");
+            return Err(KBWError::UnsupportedNumberOfQubits);
+        }
+
+        // The vectors are zeroed in parallel so that the memory pages are first touched
+        // by the threads that will process them, see `ThreadConfig`.
+        let num_states = 1 << num_qubits;
+        let zeros = || {
+            (0..num_states)
+                .into_par_iter()
+                .map(|_| Complex64::zero())
+                .collect::<Vec<_>>()
+        };
+        let mut state_0 = zeros();
+        let state_1 = zeros();
+
+        state_0[0] = Complex64::new(1.0, 0.0);
+
+        Ok(Dense {
+            state: true,
+            state_0,
+            state_1,
+            position: (0..num_qubits).collect(),
+            logical_at: (0..num_qubits).collect(),
+            hits: vec![0; num_qubits],
+            block_qubits,
+        })
+    }
+
+    /// Records a gate on `target` and returns the physical bits of the target and
+    /// control qubits, moving the target to a low bit if needed.
+    fn remap(&mut self, target: usize, control: &[usize]) -> (usize, Vec<usize>) {
+        self.hits[target] += 1;
+
+        if self.position[target] >= self.block_qubits {
+            let victim = (0..self.block_qubits.min(self.position.len()))
+                .min_by_key(|physical| self.hits[self.logical_at[*physical]]);
+            if let Some(victim) = victim {
+                if self.hits[target] >= self.hits[self.logical_at[victim]] + REMAP_MARGIN {
+                    self.swap_physical(self.position[target], victim);
+                }
+            }
+        }
+
+        (
+            self.position[target],
+            control.iter().map(|qubit| self.position[*qubit]).collect(),
+        )
+    }
+
+    /// Index in the state vector of the amplitude of a basis state.
+    fn physical_index(&self, state: usize) -> usize {
+        self.position
+            .iter()
+            .enumerate()
+            .map(|(qubit, physical)| (is_one_at(state, qubit) as usize) << physical)
+            .fold(0, |a, b| a | b)
+    }
+
+    /// Applies a 2x2 matrix without control qubits.
+    ///
+    /// The state is split in blocks of `2 << target` amplitudes, whose first half has
+    /// the target bit cleared and whose second half has it set, so the amplitudes are
+    /// paired by position with no index arithmetic.
+    fn apply_uncontrolled(&mut self, matrix: [[Complex64; 2]; 2], target: usize) {
+        let (current_state, next_state) = self.get_states();
+        let half = 1 << target;
+
+        next_state
+            .par_chunks_mut(2 * half)
+            .zip(current_state.par_chunks(2 * half))
+            .for_each(|(next, current)| {
+                let (next_0, next_1) = next.split_at_mut(half);
+                let (current_0, current_1) = current.split_at(half);
+                for index in 0..half {
+                    let (amp_0, amp_1) = (current_0[index], current_1[index]);
+                    next_0[index] = matrix[0][0] * amp_0 + matrix[0][1] * amp_1;
+                    next_1[index] = matrix[1][0] * amp_0 + matrix[1][1] * amp_1;
+                }
+            });
+    }
+
+    /// Swaps two bits of the amplitude index.
+    fn swap_physical(&mut self, a: usize, b: usize) {
+        let (current_state, next_state) = self.get_states();
+
+        next_state
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(state, amp)| *amp = current_state[bit_swap(state, a, b)]);
+
+        self.logical_at.swap(a, b);
+        self.position[self.logical_at[a]] = a;
+        self.position[self.logical_at[b]] = b;
+    }
+
     fn get_states(&mut self) -> (&mut [Complex64], &mut [Complex64]) {
         self.state = !self.state;
         if self.state {
@@ -41,34 +172,32 @@ impl Dense {
 
 impl QuantumExecution for Dense {
     fn new(num_qubits: usize) -> Result<Self> {
-        if num_qubits > 32 {
-            error!("dense implementation supports up to 32 qubits");
-            return Err(KBWError::UnsupportedNumberOfQubits);
-        }
+        let block_qubits = std::env::var("KBW_DENSE_BLOCK_QUBITS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_BLOCK_QUBITS);
 
-        let num_states = 1 << num_qubits;
-        let mut state_0 = Vec::new();
-        let mut state_1 = Vec::new();
-        state_0.resize(num_states, Complex64::zero());
-        state_1.resize(num_states, Complex64::zero());
-
-        state_0[0] = Complex64::new(1.0, 0.0);
+        Dense::with_block_qubits(num_qubits, block_qubits)
+    }
 
-        Ok(Dense {
-            state: true,
-            state_0,
-            state_1,
-        })
+    fn snapshot(&self) -> Option<Self> {
+        Some(self.clone())
     }
 
     fn pauli_x(&mut self, target: usize, control: &[usize]) {
+        let (target, control) = self.remap(target, control);
+        if control.is_empty() {
+            let (zero, one) = (Complex64::zero(), Complex64::one());
+            return self.apply_uncontrolled([[zero, one], [one, zero]], target);
+        }
+        let mask = ctrl_mask(&control);
         let (current_state, next_state) = self.get_states();
 
         next_state
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                *amp = current_state[if ctrl_check(state, control) {
+                *amp = current_state[if ctrl_check_mask(state, mask) {
                     bit_flip(state, target)
                 } else {
                     state
@@ -77,13 +206,20 @@ impl QuantumExecution for Dense {
     }
 
     fn pauli_y(&mut self, target: usize, control: &[usize]) {
+        let (target, control) = self.remap(target, control);
+        if control.is_empty() {
+            let zero = Complex64::zero();
+            return self
+                .apply_uncontrolled([[zero, -Complex64::i()], [Complex64::i(), zero]], target);
+        }
+        let mask = ctrl_mask(&control);
         let (current_state, next_state) = self.get_states();
 
         next_state
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) {
+                if ctrl_check_mask(state, mask) {
                     *amp = current_state[bit_flip(state, target)]
                         * if is_one_at(state, target) {
                             Complex64::i()
@@ -97,13 +233,15 @@ impl QuantumExecution for Dense {
     }
 
     fn pauli_z(&mut self, target: usize, control: &[usize]) {
+        let (target, control) = self.remap(target, control);
+        let mask = ctrl_mask(&control);
         let (current_state, next_state) = self.get_states();
 
         next_state
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) && is_one_at(state, target) {
+                if ctrl_check_mask(state, mask) && is_one_at(state, target) {
                     *amp = -current_state[state];
                 } else {
                     *amp = current_state[state];
@@ -112,13 +250,19 @@ impl QuantumExecution for Dense {
     }
 
     fn hadamard(&mut self, target: usize, control: &[usize]) {
+        let (target, control) = self.remap(target, control);
+        if control.is_empty() {
+            let h = Complex64::from(FRAC_1_SQRT_2);
+            return self.apply_uncontrolled([[h, h], [h, -h]], target);
+        }
+        let mask = ctrl_mask(&control);
         let (current_state, next_state) = self.get_states();
 
         next_state
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) {
+                if ctrl_check_mask(state, mask) {
                     *amp = current_state[bit_flip(state, target)] * FRAC_1_SQRT_2;
                 } else {
                     *amp = Complex64::zero();
@@ -129,7 +273,7 @@ impl QuantumExecution for Dense {
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) {
+                if ctrl_check_mask(state, mask) {
                     *amp *= if is_one_at(state, target) {
                         -FRAC_1_SQRT_2
                     } else {
@@ -147,6 +291,8 @@ impl QuantumExecution for Dense {
     }
 
     fn phase(&mut self, lambda: f64, target: usize, control: &[usize]) {
+        let (target, control) = self.remap(target, control);
+        let mask = ctrl_mask(&control);
         let (current_state, next_state) = self.get_states();
 
         let phase = Complex64::exp(lambda * Complex64::i());
@@ -155,7 +301,7 @@ impl QuantumExecution for Dense {
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) && is_one_at(state, target) {
+                if ctrl_check_mask(state, mask) && is_one_at(state, target) {
                     *amp = current_state[state] * phase;
                 } else {
                     *amp = current_state[state];
@@ -164,6 +310,13 @@ impl QuantumExecution for Dense {
     }
 
     fn rx(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let (target, control) = self.remap(target, control);
+        if control.is_empty() {
+            let cos = Complex64::from(f64::cos(theta / 2.0));
+            let sin = -Complex64::i() * f64::sin(theta / 2.0);
+            return self.apply_uncontrolled([[cos, sin], [sin, cos]], target);
+        }
+        let mask = ctrl_mask(&control);
         let (current_state, next_state) = self.get_states();
 
         let cons_theta_2 = Complex64::from(f64::cos(theta / 2.0));
@@ -173,7 +326,7 @@ impl QuantumExecution for Dense {
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) {
+                if ctrl_check_mask(state, mask) {
                     *amp = current_state[bit_flip(state, target)] * sin_theta_2;
                 } else {
                     *amp = Complex64::zero();
@@ -184,7 +337,7 @@ impl QuantumExecution for Dense {
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) {
+                if ctrl_check_mask(state, mask) {
                     *amp *= cons_theta_2;
                 }
             });
@@ -198,6 +351,13 @@ impl QuantumExecution for Dense {
     }
 
     fn ry(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let (target, control) = self.remap(target, control);
+        if control.is_empty() {
+            let cos = Complex64::from(f64::cos(theta / 2.0));
+            let sin = Complex64::from(f64::sin(theta / 2.0));
+            return self.apply_uncontrolled([[cos, -sin], [sin, cos]], target);
+        }
+        let mask = ctrl_mask(&control);
         let (current_state, next_state) = self.get_states();
 
         let cons_theta_2 = Complex64::from(f64::cos(theta / 2.0));
@@ -208,7 +368,7 @@ impl QuantumExecution for Dense {
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) {
+                if ctrl_check_mask(state, mask) {
                     *amp = current_state[bit_flip(state, target)]
                         * if is_one_at(state, target) {
                             p_sin_theta_2
@@ -224,7 +384,7 @@ impl QuantumExecution for Dense {
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) {
+                if ctrl_check_mask(state, mask) {
                     *amp *= cons_theta_2;
                 }
             });
@@ -238,6 +398,8 @@ impl QuantumExecution for Dense {
     }
 
     fn rz(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let (target, control) = self.remap(target, control);
+        let mask = ctrl_mask(&control);
         let (current_state, next_state) = self.get_states();
 
         let phase_0 = Complex64::exp(-theta / 2.0 * Complex64::i());
@@ -247,7 +409,7 @@ impl QuantumExecution for Dense {
             .par_iter_mut()
             .enumerate()
             .for_each(|(state, amp)| {
-                if ctrl_check(state, control) {
+                if ctrl_check_mask(state, mask) {
                     *amp = current_state[state]
                         * if is_one_at(state, target) {
                             phase_1
@@ -260,27 +422,29 @@ impl QuantumExecution for Dense {
             });
     }
 
-    fn measure<R: Rng>(&mut self, target: usize, rng: &mut R) -> bool {
+    fn measure<R: Rng>(&mut self, target: usize, forced: Option<bool>, rng: &mut R) -> bool {
+        let target = self.position[target];
         let (current_state, next_state) = self.get_states();
 
-        let p1: f64 = current_state
+        let p1 = current_state
             .par_iter()
             .enumerate()
             .map(|(state, amp)| {
                 if is_one_at(state, target) {
-                    amp.norm().powi(2)
+                    amp.norm_sqr()
                 } else {
                     0.0
                 }
             })
-            .sum();
+            .sum::<CompensatedSum>()
+            .value();
 
         let p0 = match 1.0 - p1 {
             p0 if p0 >= 0.0 => p0,
             _ => 0.0,
         };
 
-        let result = WeightedIndex::new([p0, p1]).unwrap().sample(rng) == 1;
+        let result = choose_outcome(p0, p1, forced, rng);
 
         let p = 1.0 / f64::sqrt(if result { p1 } else { p0 });
 
@@ -300,20 +464,28 @@ impl QuantumExecution for Dense {
 
     fn dump(&mut self, qubits: &[usize]) -> ket::DumpData {
         let state = self.get_current_state();
-        let (basis_states, amplitudes_real, amplitudes_imag): (Vec<_>, Vec<_>, Vec<_>) = state
+        let remapped = self
+            .position
             .iter()
             .enumerate()
+            .any(|(qubit, physical)| qubit != *physical);
+
+        let (basis_states, amplitudes_real, amplitudes_imag): (Vec<_>, Vec<_>, Vec<_>) = (0..state
+            .len())
+            .map(|index| {
+                if remapped {
+                    (index, state[self.physical_index(index)])
+                } else {
+                    (index, state[index])
+                }
+            })
             .filter(|(_state, amp)| amp.norm() > 1e-15)
             .map(|(state, amp)| {
-                let state = qubits
-                    .iter()
-                    .rev()
-                    .enumerate()
-                    .map(|(index, qubit)| (is_one_at(state, *qubit) as usize) << index)
-                    .reduce(|a, b| a | b)
-                    .unwrap_or(0);
-
-                (Vec::from([state as u64]), amp.re, amp.im)
+                (
+                    Vec::from([extract_bits(state, qubits) as u64]),
+                    amp.re,
+                    amp.im,
+                )
             })
             .multiunzip();
 
@@ -324,17 +496,220 @@ impl QuantumExecution for Dense {
         }
     }
 
-    fn debug_state(&self) -> Option<String> {
+    fn dump_top_k(&mut self, qubits: &[usize], k: usize) -> ket::DumpData {
+        // The bits of the dumped qubits are read at their physical positions.
+        let qubits = qubits
+            .iter()
+            .map(|qubit| self.position[*qubit])
+            .collect_vec();
         let state = self.get_current_state();
-        let mut state_str = String::new();
-        state_str.push_str("State:\n");
-        for (index, amp) in state.iter().enumerate() {
-            if amp.norm() < 1e-15 {
-                continue;
+        let descending =
+            |a: &usize, b: &usize| state[*b].norm_sqr().total_cmp(&state[*a].norm_sqr());
+        let select = |mut indices: Vec<usize>| {
+            if indices.len() > k {
+                indices.select_nth_unstable_by(k, descending);
+                indices.truncate(k);
             }
-            state_str.push_str(&format!("{:032b}: {:?}\n", index, amp));
+            indices
+        };
+
+        // Each chunk keeps its k largest amplitudes, and the candidates of two chunks
+        // are merged by selecting again.
+        let mut indices = state
+            .par_chunks(PROBABILITY_CHUNK)
+            .enumerate()
+            .map(|(chunk, amplitudes)| {
+                select(
+                    amplitudes
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, amp)| amp.norm() > 1e-15)
+                        .map(|(offset, _)| chunk * PROBABILITY_CHUNK + offset)
+                        .collect(),
+                )
+            })
+            .reduce(Vec::new, |mut a, b| {
+                a.extend(b);
+                select(a)
+            });
+        indices.sort_unstable_by(descending);
+
+        ket::DumpData {
+            basis_states: indices
+                .iter()
+                .map(|index| Vec::from([extract_bits(*index, &qubits) as u64]))
+                .collect(),
+            amplitudes_real: indices.iter().map(|index| state[*index].re).collect(),
+            amplitudes_imag: indices.iter().map(|index| state[*index].im).collect(),
         }
-        Some(state_str)
+    }
+
+    fn apply_matrix(&mut self, matrix: Matrix2, target: usize) {
+        let (target, _) = self.remap(target, &[]);
+        self.apply_uncontrolled(matrix, target);
+    }
+
+    fn reduced_density_matrix(&self, target: usize) -> Matrix2 {
+        let target = self.position[target];
+        let state = self.get_current_state();
+
+        let (rho_00, rho_11, rho_01) = state
+            .par_iter()
+            .enumerate()
+            .filter(|(index, _)| !is_one_at(*index, target))
+            .map(|(index, amp_0)| {
+                let amp_1 = state[bit_flip(index, target)];
+                (amp_0.norm_sqr(), amp_1.norm_sqr(), amp_0 * amp_1.conj())
+            })
+            .reduce(
+                || (0.0, 0.0, Complex64::zero()),
+                |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2),
+            );
+
+        [[rho_00.into(), rho_01], [rho_01.conj(), rho_11.into()]]
+    }
+
+    /// Sums the probabilities of the amplitudes in a single parallel pass, with a table
+    /// for each chunk of the state, without building a dump.
+    fn joint_probabilities(&mut self, rows: &[usize], columns: &[usize]) -> Vec<Vec<f64>> {
+        let rows = rows.iter().map(|qubit| self.position[*qubit]).collect_vec();
+        let columns = columns
+            .iter()
+            .map(|qubit| self.position[*qubit])
+            .collect_vec();
+        let width = 1 << columns.len();
+        let size = width << rows.len();
+        let chunk_size = PROBABILITY_CHUNK.max(size);
+
+        let table = self
+            .get_current_state()
+            .par_chunks(chunk_size)
+            .enumerate()
+            .map(|(chunk, amplitudes)| {
+                let mut table = vec![0.0; size];
+                for (offset, amp) in amplitudes.iter().enumerate() {
+                    let index = chunk * chunk_size + offset;
+                    let cell = (extract_bits(index, &rows) << columns.len())
+                        | extract_bits(index, &columns);
+                    table[cell] += amp.norm_sqr();
+                }
+                table
+            })
+            .reduce(
+                || vec![0.0; size],
+                |mut a, b| {
+                    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                    a
+                },
+            );
+
+        table.chunks(width).map(<[f64]>::to_vec).collect()
+    }
+
+    fn apply_unitary(
+        &mut self,
+        matrix: &[Vec<Complex64>],
+        target: &[usize],
+        control: &[usize],
+    ) -> bool {
+        // Physical bit of each target, from the most significant bit of the matrix index.
+        let bits = target
+            .iter()
+            .map(|qubit| self.position[*qubit])
+            .collect_vec();
+        let target_mask: usize = ctrl_mask(&bits);
+        let control_mask: usize = ctrl_mask(
+            &control
+                .iter()
+                .map(|qubit| self.position[*qubit])
+                .collect_vec(),
+        );
+
+        // Offset of each column in the group of amplitudes that the matrix mixes, and
+        // the matrix in row-major order.
+        let offsets = (0..matrix.len())
+            .map(|column| {
+                bits.iter()
+                    .rev()
+                    .enumerate()
+                    .filter(|(bit, _)| is_one_at(column, *bit))
+                    .fold(0, |offset, (_, physical)| offset | 1 << physical)
+            })
+            .collect_vec();
+        let entries = matrix.iter().flatten().copied().collect_vec();
+        let size = matrix.len();
+
+        let (current_state, next_state) = self.get_states();
+        next_state
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(state, amp)| {
+                if !ctrl_check_mask(state, control_mask) {
+                    *amp = current_state[state];
+                    return;
+                }
+                let base = state & !target_mask;
+                let row = bits
+                    .iter()
+                    .fold(0, |row, bit| row << 1 | is_one_at(state, *bit) as usize);
+                *amp = entries[row * size..(row + 1) * size]
+                    .iter()
+                    .zip(&offsets)
+                    .map(|(entry, offset)| entry * current_state[base | offset])
+                    .sum();
+            });
+        true
+    }
+
+    fn diagonal(&mut self, phases: &[f64], qubits: &[usize]) -> bool {
+        let bits = qubits
+            .iter()
+            .map(|qubit| self.position[*qubit])
+            .collect_vec();
+        let factors = phases
+            .iter()
+            .map(|phase| Complex64::exp(phase * Complex64::i()))
+            .collect_vec();
+
+        let (current_state, next_state) = self.get_states();
+        next_state
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(state, amp)| {
+                let index = ket::multiplexor::index(&bits, |bit| is_one_at(state, bit));
+                *amp = current_state[state] * factors[index];
+            });
+        true
+    }
+
+    fn multiplexor(
+        &mut self,
+        axis: ket::Pauli,
+        angles: &[f64],
+        control: &[usize],
+        target: usize,
+    ) -> bool {
+        let bits = control
+            .iter()
+            .map(|qubit| self.position[*qubit])
+            .collect_vec();
+        let target = self.position[target];
+        let matrices = angles
+            .iter()
+            .map(|angle| gate_matrix(&ket::multiplexor::rotation(axis, *angle)))
+            .collect_vec();
+
+        let (current_state, next_state) = self.get_states();
+        next_state
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(state, amp)| {
+                let matrix = matrices[ket::multiplexor::index(&bits, |bit| is_one_at(state, bit))];
+                let row = matrix[is_one_at(state, target) as usize];
+                let zero = state & !(1 << target);
+                *amp = row[0] * current_state[zero] + row[1] * current_state[zero | 1 << target];
+            });
+        true
     }
 }
 
@@ -343,7 +718,9 @@ mod tests {
     #[test]
     fn bell_state_live() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let configuration =
-            crate::quantum_execution::QubitManager::<crate::sparse::Sparse>::configuration(2, true);
+            crate::quantum_execution::QubitManager::<crate::sparse::Sparse>::configuration(
+                2, true, false,
+            );
         let mut process = ket::Process::new(configuration);
         let qubit_a = process.allocate_qubit()?;
         let qubit_b = process.allocate_qubit()?;
@@ -353,15 +730,15 @@ mod tests {
         process.apply_gate(ket::QuantumGate::PauliX, qubit_b)?;
         process.ctrl_pop()?;
 
-        let d_ab = process.dump(&[qubit_a, qubit_b])?;
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        ket::assert_state!(process, &[("00", half), ("11", half)]);
+
         let m_a = process.measure(&[qubit_a])?;
         let m_b = process.measure(&[qubit_b])?;
-        let d_ab2 = process.dump(&[qubit_a, qubit_b])?;
-
-        println!("{:?}", process.get_dump(d_ab));
-        println!("{:?}", process.get_measurement(m_a));
-        println!("{:?}", process.get_measurement(m_b));
-        println!("{:?}", process.get_dump(d_ab2));
+        let result = process.get_measurement(m_a).result;
+        assert_eq!(result, process.get_measurement(m_b).result);
+        let bits = if result == Some(1) { "11" } else { "00" };
+        ket::assert_prob!(process, &[(bits, 1.0)]);
 
         Ok(())
     }
@@ -370,7 +747,7 @@ mod tests {
     fn bell_state_batch() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let configuration =
             crate::quantum_execution::QubitManager::<crate::sparse::Sparse>::configuration(
-                2, false,
+                2, false, false,
             );
         let mut process = ket::Process::new(configuration);
         let qubit_a = process.allocate_qubit()?;
@@ -384,13 +761,1112 @@ mod tests {
         let d_ab = process.dump(&[qubit_a, qubit_b])?;
         let m_a = process.measure(&[qubit_a])?;
         let m_b = process.measure(&[qubit_b])?;
-        let d_ab2 = process.dump(&[qubit_a, qubit_b])?;
+        process.prepare_for_execution()?;
+
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        ket::assert_state!(
+            process.get_dump_data(d_ab).unwrap(),
+            &[("00", half), ("11", half)]
+        );
+        assert_eq!(
+            process.get_measurement(m_a).result,
+            process.get_measurement(m_b).result
+        );
 
-        println!("{:?}", process.get_dump(d_ab));
-        println!("{:?}", process.get_measurement(m_a));
-        println!("{:?}", process.get_measurement(m_b));
-        println!("{:?}", process.get_dump(d_ab2));
+        Ok(())
+    }
+
+    #[test]
+    fn forced_measurements() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        for live in [true, false] {
+            for outcome in [0, 1] {
+                let mut process = ket::Process::new(Manager::configuration(2, live, false));
+                let qubit_a = process.allocate_qubit()?;
+                let qubit_b = process.allocate_qubit()?;
+                process.apply_gate(ket::QuantumGate::Hadamard, qubit_a)?;
+                process.ctrl_push(&[qubit_a])?;
+                process.apply_gate(ket::QuantumGate::PauliX, qubit_b)?;
+                process.ctrl_pop()?;
+
+                process.force_measurement(0, outcome)?;
+                process.measure(&[qubit_a])?;
+                process.measure(&[qubit_b])?;
+                process.prepare_for_execution()?;
+                assert_eq!(process.measurement_tape(), Some(vec![outcome, outcome]));
+            }
+        }
+
+        // |1> cannot collapse to 0.
+        let mut process = ket::Process::new(Manager::configuration(1, true, false));
+        let qubit = process.allocate_qubit()?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubit)?;
+        process.replay_measurements(&[0])?;
+        assert!(process.measure(&[qubit]).is_err());
+        assert!(process.force_measurement(0, 1).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn teleportation() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        for live in [true, false] {
+            let mut process = ket::Process::new(Manager::configuration(3, live, false));
+            let qubits = [
+                process.allocate_qubit()?,
+                process.allocate_qubit()?,
+                process.allocate_qubit()?,
+            ];
+            process.apply_gate(ket::QuantumGate::PauliX, qubits[0])?;
+            process.apply_gate(ket::QuantumGate::Hadamard, qubits[1])?;
+            process.ctrl_push(&[qubits[1]])?;
+            process.apply_gate(ket::QuantumGate::PauliX, qubits[2])?;
+            process.ctrl_pop()?;
+
+            process.ctrl_push(&[qubits[0]])?;
+            process.apply_gate(ket::QuantumGate::PauliX, qubits[1])?;
+            process.ctrl_pop()?;
+            process.apply_gate(ket::QuantumGate::Hadamard, qubits[0])?;
+            let m_0 = process.measure(&[qubits[0]])?;
+            let m_1 = process.measure(&[qubits[1]])?;
+
+            process.cond_push(&[(m_1, 1)])?;
+            process.apply_gate(ket::QuantumGate::PauliX, qubits[2])?;
+            process.cond_pop()?;
+            process.cond_push(&[(m_0, 1)])?;
+            process.apply_gate(ket::QuantumGate::PauliZ, qubits[2])?;
+            process.cond_pop()?;
+            assert!(process.cond_pop().is_err());
+
+            let m_2 = process.measure(&[qubits[2]])?;
+            process.prepare_for_execution()?;
+            assert_eq!(process.get_measurement(m_2).result, Some(1));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn conditional_gates_optimized() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(Manager::configuration(2, false, true));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[0])?;
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[1])?;
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[1])?;
+        let m_0 = process.measure(&[qubits[0]])?;
+
+        // The optimizer keeps the conditional gate after the measurement it tests.
+        process.cond_push(&[(m_0, 1)])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[1])?;
+        process.cond_pop()?;
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[0])?;
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[0])?;
+        let m_1 = process.measure(&[qubits[1]])?;
+        process.prepare_for_execution()?;
+        assert_eq!(process.get_measurement(m_1).result, Some(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn feedback_shots() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(Manager::configuration(2, false, false));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[0])?;
+        let m_0 = process.measure(&[qubits[0]])?;
+        process.cond_push(&[(m_0, 1)])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[1])?;
+        process.cond_pop()?;
+        let sample = process.sample(&qubits, 1000)?;
+        process.prepare_for_execution()?;
+
+        // Each shot takes the branch of its own measurement.
+        let (states, counts) = process.get_sample(sample).result.clone().unwrap();
+        assert_eq!(states, [0b00, 0b11]);
+        assert_eq!(counts.iter().sum::<u64>(), 1000);
+        assert!(counts.iter().all(|count| *count > 400));
+        assert!(process.get_measurement(m_0).result.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn feedback_shots_optimized() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(Manager::configuration(2, false, true));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[0])?;
+        process.apply_gate(ket::QuantumGate::PauliZ, qubits[1])?;
+        process.apply_gate(ket::QuantumGate::PauliZ, qubits[1])?;
+        let m_0 = process.measure(&[qubits[0]])?;
+        process.cond_push(&[(m_0, 1)])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[1])?;
+        process.cond_pop()?;
+        let sample = process.sample(&qubits, 1000)?;
+        process.prepare_for_execution()?;
+
+        let (states, counts) = process.get_sample(sample).result.clone().unwrap();
+        assert_eq!(states, [0b00, 0b11]);
+        assert_eq!(counts.iter().sum::<u64>(), 1000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn classical_step() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(Manager::configuration(2, true, false));
+        let qubit_a = process.allocate_qubit()?;
+        let qubit_b = process.allocate_qubit()?;
+
+        process.apply_gate(ket::QuantumGate::PauliX, qubit_a)?;
+        let m_a = process.measure(&[qubit_a])?;
+        process.classical_step(&[m_a], |results| {
+            let angle = std::f64::consts::PI * results[0] as f64;
+            vec![(
+                ket::QuantumGate::RotationY(ket::Angle::Scalar(angle)),
+                qubit_b,
+            )]
+        })?;
+        let m_b = process.measure(&[qubit_b])?;
+        assert_eq!(process.get_measurement(m_b).result, Some(1));
+        assert!(process.classical_step(&[m_b + 1], |_| vec![]).is_err());
+
+        let mut process = ket::Process::new(Manager::configuration(1, false, false));
+        let qubit = process.allocate_qubit()?;
+        let m = process.measure(&[qubit])?;
+        assert!(matches!(
+            process.classical_step(&[m], |_| vec![]),
+            Err(ket::error::KetError::LiveExecutionRequired)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn iterative_phase_estimation() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(Manager::configuration(2, true, false));
+        let target = process.allocate_qubit()?;
+        process.apply_gate(ket::QuantumGate::PauliX, target)?;
+
+        // |1> is an eigenstate of the phase gate with the eigenphase 5/8.
+        let estimate = ket::phase_estimation::iterative_phase_estimation(
+            &mut process,
+            3,
+            4,
+            |process, power| {
+                let angle = 2.0 * std::f64::consts::PI * 5.0 / 8.0 * power as f64;
+                process.apply_gate(ket::QuantumGate::Phase(ket::Angle::Scalar(angle)), target)
+            },
+        )?;
+        assert_eq!(estimate.samples, [0.625; 4]);
+        assert!((estimate.phase - 0.625).abs() < 1e-12);
+        assert!(estimate.std_error < 1e-12);
+        assert!(!process.get_qubit_status(1).allocated);
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_unitary() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::quantum_execution::QuantumExecution;
+        use ket::{Angle, Instruction, QuantumGate};
+
+        let gate = |gate, target, control: &[usize]| Instruction::Gate {
+            gate,
+            target,
+            control: control.to_vec(),
+        };
+        let gates = [
+            gate(QuantumGate::Hadamard, 0, &[]),
+            gate(QuantumGate::PauliX, 1, &[0]),
+            gate(QuantumGate::RotationY(Angle::Scalar(0.7)), 2, &[]),
+            gate(QuantumGate::Phase(Angle::Scalar(0.4)), 2, &[1]),
+            gate(QuantumGate::RotationX(Angle::Scalar(1.1)), 0, &[2]),
+        ];
+        let matrix = crate::unitary::unitary(
+            &(0..3)
+                .map(|target| Instruction::Alloc { target })
+                .chain(gates.iter().cloned())
+                .collect::<Vec<_>>(),
+        )?;
+
+        // The matrix on the qubits 3, 0, and 2, controlled by the qubit 4.
+        let (target, control) = ([3, 0, 2], [4]);
+        let mut expected = super::Dense::new(5)?;
+        let mut dense = super::Dense::new(5)?;
+        let mut sparse = crate::sparse::Sparse::new(5)?;
+        for qubit in 0..5 {
+            expected.hadamard(qubit, &[]);
+            dense.hadamard(qubit, &[]);
+            sparse.hadamard(qubit, &[]);
+        }
+
+        for instruction in &gates {
+            if let Instruction::Gate {
+                gate,
+                target: gate_target,
+                control: gate_control,
+            } = instruction
+            {
+                let mut controls: Vec<_> = gate_control.iter().map(|q| target[*q]).collect();
+                controls.extend(control);
+                match gate {
+                    QuantumGate::Hadamard => expected.hadamard(target[*gate_target], &controls),
+                    QuantumGate::PauliX => expected.pauli_x(target[*gate_target], &controls),
+                    QuantumGate::RotationY(Angle::Scalar(theta)) => {
+                        expected.ry(*theta, target[*gate_target], &controls)
+                    }
+                    QuantumGate::Phase(Angle::Scalar(lambda)) => {
+                        expected.phase(*lambda, target[*gate_target], &controls)
+                    }
+                    QuantumGate::RotationX(Angle::Scalar(theta)) => {
+                        expected.rx(*theta, target[*gate_target], &controls)
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+        assert!(dense.apply_unitary(&matrix, &target, &control));
+        assert!(sparse.apply_unitary(&matrix, &target, &control));
 
+        let qubits = [0, 1, 2, 3, 4];
+        let expected = expected.dump(&qubits);
+        for dump in [dense.dump(&qubits), sparse.dump(&qubits)] {
+            let mut amplitudes = std::collections::HashMap::new();
+            for ((state, re), im) in dump
+                .basis_states
+                .iter()
+                .zip(dump.amplitudes_real)
+                .zip(dump.amplitudes_imag)
+            {
+                amplitudes.insert(state.clone(), (re, im));
+            }
+            for ((state, re), im) in expected
+                .basis_states
+                .iter()
+                .zip(&expected.amplitudes_real)
+                .zip(&expected.amplitudes_imag)
+            {
+                let (actual_re, actual_im) = amplitudes.get(state).copied().unwrap_or_default();
+                assert!((actual_re - re).abs() < 1e-10 && (actual_im - im).abs() < 1e-10);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn multiplexor() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::quantum_execution::QubitManager;
+        use ket::{Pauli, QuantumGate};
+        use num::complex::Complex64;
+        use std::collections::HashMap;
+
+        let phases = [0.3, -1.2, 2.5, 0.0, 0.9, 1.7, -0.4, 3.1];
+        let angles = [0.5, -0.8, 1.9, 2.2];
+        fn run<S: crate::quantum_execution::QuantumExecution + 'static>(
+            decompose: bool,
+            phases: &[f64],
+            angles: &[f64],
+        ) -> ket::error::Result<HashMap<Vec<u64>, Complex64>> {
+            let mut process = ket::Process::new(ket::Configuration {
+                decompose,
+                ..QubitManager::<S>::configuration(4, true, false)
+            });
+            let qubits = (0..4)
+                .map(|_| process.allocate_qubit())
+                .collect::<ket::error::Result<Vec<_>>>()?;
+            for qubit in &qubits {
+                process.apply_gate(QuantumGate::Hadamard, *qubit)?;
+            }
+            process.apply_diagonal(phases, &[qubits[2], qubits[0], qubits[3]])?;
+            for axis in [Pauli::PauliX, Pauli::PauliY, Pauli::PauliZ] {
+                process.apply_multiplexor(axis, angles, &[qubits[3], qubits[1]], qubits[0])?;
+            }
+            let dump = process.dump(&qubits)?;
+            let dump = process.get_dump_data(dump).unwrap();
+            Ok(dump
+                .basis_states
+                .into_iter()
+                .zip(dump.amplitudes_real)
+                .zip(dump.amplitudes_imag)
+                .map(|((state, re), im)| (state, Complex64::new(re, im)))
+                .collect())
+        }
+
+        // The synthesis leaves out the global phase of the diagonal gate.
+        let global =
+            Complex64::from_polar(1.0, ket::multiplexor::diagonal_gates(&phases, &[0, 1, 2]).1);
+        let expected = run::<super::Dense>(true, &phases, &angles)?;
+        for amplitudes in [
+            run::<super::Dense>(false, &phases, &angles)?,
+            run::<crate::sparse::Sparse>(false, &phases, &angles)?,
+        ] {
+            for (state, amp) in &expected {
+                let actual = amplitudes.get(state).copied().unwrap_or_default();
+                assert!((actual - amp * global).norm() < 1e-10);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn amplitude_amplification() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        use ket::{amplification::AmplitudeAmplification, template::Template};
+
+        let uniform = Template::record(3, |process, qubits| {
+            for qubit in qubits {
+                process.apply_gate(ket::QuantumGate::Hadamard, *qubit)?;
+            }
+            Ok(())
+        })?;
+        // Marks the states |101> and |111>.
+        let oracle = Template::record(3, |process, qubits| {
+            process.ctrl_push(&qubits[..1])?;
+            process.apply_gate(ket::QuantumGate::PauliZ, qubits[2])?;
+            process.ctrl_pop()
+        })?;
+        let grover = AmplitudeAmplification::new(uniform.clone(), oracle)?;
+
+        // With p = 1/4, one iteration rotates the state onto the good states.
+        let mut process = ket::Process::new(Manager::configuration(4, true, false));
+        let qubits = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>, _>>()?;
+        let iterations = ket::amplification::optimal_iterations(0.25);
+        grover.apply(&mut process, &qubits, iterations)?;
+        ket::assert_prob!(process, &[("101", 0.5), ("111", 0.5)]);
+
+        // The eigenphases 1/6 and 5/6 are not exact with 6 bits.
+        let mut process = ket::Process::new(Manager::configuration(4, true, false));
+        let qubits = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>, _>>()?;
+        let estimate = grover.count(&mut process, &qubits, 6, 9)?;
+        assert!((estimate.count - 2.0).abs() < 0.5, "{estimate:?}");
+
+        // Marking half of the states gives the exact eigenphases 1/4 and 3/4.
+        let oracle = Template::record(3, |process, qubits| {
+            process.apply_gate(ket::QuantumGate::PauliZ, qubits[0])
+        })?;
+        let grover = AmplitudeAmplification::new(uniform, oracle)?;
+        let mut process = ket::Process::new(Manager::configuration(4, true, false));
+        let qubits = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>, _>>()?;
+        let estimate = grover.count(&mut process, &qubits, 3, 4)?;
+        assert!((estimate.count - 4.0).abs() < 1e-9, "{estimate:?}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn grover_rudolph() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(Manager::configuration(3, true, false));
+        let qubits = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let distribution = [0.05, 0.1, 0.0, 0.15, 0.2, 0.1, 0.3, 0.1];
+        ket::state_preparation::GroverRudolph::new(&distribution)?.apply(&mut process, &qubits)?;
+
+        let expected: Vec<_> = distribution
+            .iter()
+            .enumerate()
+            .filter(|(_, probability)| **probability > 0.0)
+            .map(|(index, probability)| (format!("{index:03b}"), *probability))
+            .collect();
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(bits, probability)| (bits.as_str(), *probability))
+            .collect();
+        ket::assert_prob!(process, &expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn npy_initial_state() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use num::complex::Complex64;
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let state: Vec<Complex64> = [
+            (0.1, 0.2),
+            (-0.3, 0.0),
+            (0.0, 0.0),
+            (0.2, -0.4),
+            (0.5, 0.1),
+            (0.0, -0.3),
+            (-0.2, -0.2),
+            (0.3, 0.3),
+        ]
+        .iter()
+        .map(|(re, im)| Complex64::new(*re, *im))
+        .collect();
+        let norm = state.iter().map(Complex64::norm_sqr).sum::<f64>().sqrt();
+        let state: Vec<_> = state.iter().map(|amplitude| amplitude / norm).collect();
+        let path = std::env::temp_dir().join(format!("kbw-state-{}.npy", std::process::id()));
+
+        for bit_order in [ket::BitOrder::MsbFirst, ket::BitOrder::LsbFirst] {
+            ket::npy::write(&path, &state)?;
+            let mut configuration = Manager::configuration(4, true, false);
+            configuration.bit_order = bit_order;
+            configuration.initial_state_from_npy(&path)?;
+            let mut process = ket::Process::new(configuration);
+            let qubits = (0..4)
+                .map(|_| process.allocate_qubit())
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // With the LSB first, the first qubit is the last bit of the index.
+            let first = process.measure(&qubits[..1])?;
+            let first = process.get_measurement(first).result.unwrap();
+            let bit = |index: usize| match bit_order {
+                ket::BitOrder::MsbFirst => index >> 2 & 1,
+                ket::BitOrder::LsbFirst => index & 1,
+            };
+            let collapsed: Vec<_> = state
+                .iter()
+                .enumerate()
+                .map(|(index, amplitude)| {
+                    if bit(index) as u64 == first {
+                        *amplitude
+                    } else {
+                        Complex64::default()
+                    }
+                })
+                .collect();
+
+            let dump = process.dump(&qubits[..3])?;
+            process.get_dump_data(dump).unwrap().to_npy(&path, 3)?;
+            let result = ket::npy::read(&path)?;
+
+            let (largest, _) = collapsed
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.norm().total_cmp(&b.1.norm()))
+                .unwrap();
+            // The collapsed state is not normalized, and the global phase is lost.
+            let factor = result[largest] / collapsed[largest];
+            for (a, b) in collapsed.iter().zip(&result) {
+                assert!((a * factor - b).norm() < 1e-9, "{a} {b}");
+            }
+        }
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn distribution_distances() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use ket::distribution::{hellinger_fidelity, linear_xeb, total_variation_distance};
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+
+        let mut process = ket::Process::new(Manager::configuration(3, true, false));
+        let qubits = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>, _>>()?;
+        for (index, qubit) in qubits.iter().enumerate() {
+            process.apply_gate(
+                ket::QuantumGate::RotationY(ket::Angle::Scalar(0.4 + index as f64)),
+                *qubit,
+            )?;
+        }
+        process.ctrl_push(&qubits[..1])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[2])?;
+        process.ctrl_pop()?;
+
+        let dump = process.dump(&qubits)?;
+        let ideal = process
+            .get_dump_data(dump)
+            .unwrap()
+            .probabilities()
+            .unwrap();
+        let sample = process.sample(&qubits, 20000)?;
+        let sampled = process.get_sample(sample).distribution().unwrap();
+
+        assert!(total_variation_distance(&ideal, &sampled) < 0.03);
+        assert!(hellinger_fidelity(&ideal, &sampled) > 0.99);
+        let noiseless = linear_xeb(&ideal, &ideal, 3);
+        assert!((linear_xeb(&sampled, &ideal, 3) - noiseless).abs() < 0.1);
+        Ok(())
+    }
+
+    #[test]
+    fn verify_arithmetic() -> Result<(), ket::error::KetError> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let configuration = || Manager::configuration(3, true, false);
+
+        // Adds one to the register, the first qubit being the most significant bit.
+        let increment = |process: &mut ket::Process, register: &[usize]| {
+            for (index, qubit) in register.iter().enumerate() {
+                process.ctrl_push(&register[index + 1..])?;
+                process.apply_gate(ket::QuantumGate::PauliX, *qubit)?;
+                process.ctrl_pop()?;
+            }
+            Ok(())
+        };
+
+        let report = ket::testing::verify_arithmetic(
+            configuration,
+            &[3],
+            |process, registers| increment(process, &registers[0]),
+            |inputs| vec![inputs[0] + 1],
+        )?;
+        assert_eq!(report.cases, 8);
+        assert!(report.passed(), "{report}");
+
+        // With the qubits reversed, the carry goes the wrong way, which only gives the
+        // right output when every bit flips.
+        let report = ket::testing::verify_arithmetic(
+            configuration,
+            &[3],
+            |process, registers| {
+                let reversed: Vec<usize> = registers[0].iter().rev().copied().collect();
+                increment(process, &reversed)
+            },
+            |inputs| vec![inputs[0] + 1],
+        )?;
+        assert_eq!(report.failures.len(), 7);
+        assert_eq!(report.failures[0].actual, [0b100]);
+        assert!(report
+            .to_string()
+            .contains("000 -> expected 001, measured 100"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunked_execution() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(ket::Configuration {
+            chunk_size: Some(4),
+            ..Manager::configuration(2, false, false)
+        });
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[0])?;
+        let m_0 = process.measure(&[qubits[0]])?;
+
+        // The first chunk was executed when it reached four instructions.
+        assert_eq!(process.streamed_len(), 4);
+        assert!(process.instructions().is_empty());
+        assert_eq!(process.get_measurement(m_0).result, Some(1));
+
+        for _ in 0..10 {
+            process.apply_gate(ket::QuantumGate::Hadamard, qubits[1])?;
+            assert!(process.instructions().len() < 4);
+        }
+        process.cond_push(&[(m_0, 1)])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[1])?;
+        process.cond_pop()?;
+        let sample = process.sample(&qubits, 10)?;
+        let m_1 = process.measure(&[qubits[1]])?;
+        let dump = process.dump(&qubits)?;
+        process.prepare_for_execution()?;
+
+        assert_eq!(process.streamed_len(), 18);
+        let (states, _) = process.get_sample(sample).result.clone().unwrap();
+        assert_eq!(states, [0b11]);
+        assert_eq!(process.get_measurement(m_1).result, Some(1));
+        assert_eq!(process.get_dump_data(dump).unwrap().basis_states, [[0b11]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bit_order() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use ket::BitOrder::{LsbFirst, MsbFirst};
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        // X on the first of two qubits.
+        for live in [true, false] {
+            for (bit_order, expected) in [(MsbFirst, 0b10), (LsbFirst, 0b01)] {
+                let mut process = ket::Process::new(ket::Configuration {
+                    bit_order,
+                    ..Manager::configuration(2, live, false)
+                });
+                let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+                process.apply_gate(ket::QuantumGate::PauliX, qubits[0])?;
+
+                let dump = process.dump(&qubits)?;
+                let sample = process.sample(&qubits, 10)?;
+                let measurement = process.measure(&qubits)?;
+                process.prepare_for_execution()?;
+
+                let dump = process.get_dump_data(dump).unwrap();
+                assert_eq!(dump.basis_states, [[expected]]);
+                let (states, _) = process.get_sample(sample).result.clone().unwrap();
+                assert_eq!(states, [expected]);
+                assert_eq!(process.get_measurement(measurement).result, Some(expected));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn replay_trace() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let run =
+            |trace: Option<&ket::trace::Trace>| -> Result<ket::Process, ket::error::KetError> {
+                let mut process = ket::Process::new(Manager::configuration(8, true, false));
+                match trace {
+                    Some(trace) => trace.replay(&mut process)?,
+                    None => {
+                        process.start_trace();
+                        for _ in 0..8 {
+                            let qubit = process.allocate_qubit()?;
+                            process.apply_gate(ket::QuantumGate::Hadamard, qubit)?;
+                            process.measure(&[qubit])?;
+                        }
+                    }
+                }
+                Ok(process)
+            };
+
+        let process = run(None)?;
+        let replayed = run(process.trace())?;
+        for index in 0..8 {
+            assert_eq!(
+                replayed.get_measurement(index).result,
+                process.get_measurement(index).result
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sample_without_collapse() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(Manager::configuration(1, true, false));
+        let qubit = process.allocate_qubit()?;
+        process.apply_gate(ket::QuantumGate::Hadamard, qubit)?;
+
+        let (states, counts) = process.sample_without_collapse(&[qubit], 1000)?;
+        assert_eq!(states.len(), 2);
+        assert_eq!(counts.iter().sum::<u64>(), 1000);
+
+        // H H |0> = |0> only if the state was not collapsed.
+        process.apply_gate(ket::QuantumGate::Hadamard, qubit)?;
+        let measurement = process.measure(&[qubit])?;
+        assert_eq!(process.get_measurement(measurement).result, Some(0));
+        assert!(process.instructions().len() == 4);
+
+        let mut batch = ket::Process::new(Manager::configuration(1, false, false));
+        let qubit = batch.allocate_qubit()?;
+        assert!(batch.sample_without_collapse(&[qubit], 10).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn kraus_channel() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::quantum_execution::QubitManager;
+        use ket::ir::KrausOperator;
+        use num::{complex::Complex64, One, Zero};
+
+        // Amplitude damping with gamma = 1 resets the qubit to |0>.
+        let (zero, one) = (Complex64::zero(), Complex64::one());
+        let reset = [
+            KrausOperator::from_matrix([[one, zero], [zero, zero]]),
+            KrausOperator::from_matrix([[zero, one], [zero, zero]]),
+        ];
+
+        for live in [true, false] {
+            for configuration in [
+                QubitManager::<super::Dense>::configuration(2, live, false),
+                QubitManager::<crate::sparse::Sparse>::configuration(2, live, false),
+            ] {
+                let mut process = ket::Process::new(configuration);
+                let qubit_a = process.allocate_qubit()?;
+                let qubit_b = process.allocate_qubit()?;
+                process.apply_gate(ket::QuantumGate::Hadamard, qubit_a)?;
+                process.ctrl_push(&[qubit_a])?;
+                process.apply_gate(ket::QuantumGate::PauliX, qubit_b)?;
+                process.ctrl_pop()?;
+
+                process.apply_kraus_channel(&reset, qubit_b)?;
+                let dump = process.dump(&[qubit_b])?;
+                let measurement = process.measure(&[qubit_b])?;
+                process.prepare_for_execution()?;
+
+                assert_eq!(process.get_measurement(measurement).result, Some(0));
+                let dump = process.get_dump_data(dump).unwrap();
+                assert_eq!(dump.basis_states, vec![vec![0]]);
+                assert!((dump.amplitudes_real[0].abs() - 1.0).abs() < 1e-12);
+            }
+        }
+
+        let mut process =
+            ket::Process::new(QubitManager::<super::Dense>::configuration(1, true, false));
+        let qubit = process.allocate_qubit()?;
+        assert!(process.apply_kraus_channel(&reset[..1], qubit).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn kraus_channel_optimized() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use ket::ir::KrausOperator;
+        use num::{complex::Complex64, One, Zero};
+
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let (zero, one) = (Complex64::zero(), Complex64::one());
+        let reset = [
+            KrausOperator::from_matrix([[one, zero], [zero, zero]]),
+            KrausOperator::from_matrix([[zero, one], [zero, zero]]),
+        ];
+
+        // The optimizer simplifies the gates around the channel and keeps it in place.
+        let mut process = ket::Process::new(Manager::configuration(2, false, true));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[0])?;
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[0])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[1])?;
+        process.apply_kraus_channel(&reset, qubits[1])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[0])?;
+        let measurement = process.measure(&qubits)?;
+        process.prepare_for_execution()?;
+        assert_eq!(process.get_measurement(measurement).result, Some(0b10));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cache_blocking_preserves_state() {
+        use super::Dense;
+        use crate::quantum_execution::QuantumExecution;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut blocked = Dense::with_block_qubits(6, 2).unwrap();
+        let mut reference = Dense::with_block_qubits(6, 0).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for _ in 0..200 {
+            // Favor the high qubits so they are moved to the block.
+            let target = if rng.gen_bool(0.7) {
+                rng.gen_range(3..6)
+            } else {
+                rng.gen_range(0..6)
+            };
+            let control = (target + rng.gen_range(1..6)) % 6;
+            let theta: f64 = rng.gen();
+            let gate = rng.gen_range(0..4);
+            for simulator in [&mut blocked, &mut reference] {
+                match gate {
+                    0 => simulator.hadamard(target, &[]),
+                    1 => simulator.rx(theta, target, &[control]),
+                    2 => simulator.phase(theta, target, &[]),
+                    _ => simulator.pauli_y(target, &[control]),
+                }
+            }
+        }
+
+        assert_ne!(blocked.position, (0..6).collect::<Vec<_>>());
+
+        let qubits: Vec<usize> = (0..6).collect();
+        let blocked = blocked.dump(&qubits);
+        let reference = reference.dump(&qubits);
+        assert_eq!(blocked.basis_states, reference.basis_states);
+        for (a, b) in blocked
+            .amplitudes_real
+            .iter()
+            .chain(&blocked.amplitudes_imag)
+            .zip(
+                reference
+                    .amplitudes_real
+                    .iter()
+                    .chain(&reference.amplitudes_imag),
+            )
+        {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn kernels_match_sparse() {
+        use super::Dense;
+        use crate::quantum_execution::QuantumExecution;
+        use crate::sparse::{Sparse, SparseMode};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut dense = Dense::with_block_qubits(5, 0).unwrap();
+        let mut sparse = Sparse::with_mode(5, SparseMode::Hash).unwrap();
+        let mut rng = StdRng::seed_from_u64(11);
+
+        for _ in 0..200 {
+            let target = rng.gen_range(0..5);
+            let control: Vec<usize> = (0..rng.gen_range(0..3))
+                .map(|offset| (target + offset + 1) % 5)
+                .collect();
+            let theta: f64 = rng.gen();
+            match rng.gen_range(0..8) {
+                0 => {
+                    dense.pauli_x(target, &control);
+                    sparse.pauli_x(target, &control);
+                }
+                1 => {
+                    dense.pauli_y(target, &control);
+                    sparse.pauli_y(target, &control);
+                }
+                2 => {
+                    dense.pauli_z(target, &control);
+                    sparse.pauli_z(target, &control);
+                }
+                3 => {
+                    dense.hadamard(target, &control);
+                    sparse.hadamard(target, &control);
+                }
+                4 => {
+                    dense.phase(theta, target, &control);
+                    sparse.phase(theta, target, &control);
+                }
+                5 => {
+                    dense.rx(theta, target, &control);
+                    sparse.rx(theta, target, &control);
+                }
+                6 => {
+                    dense.ry(theta, target, &control);
+                    sparse.ry(theta, target, &control);
+                }
+                _ => {
+                    dense.rz(theta, target, &control);
+                    sparse.rz(theta, target, &control);
+                }
+            };
+        }
+
+        let qubits: Vec<usize> = (0..5).collect();
+        let dense = dense.dump(&qubits);
+        let sparse = sparse.dump(&qubits);
+        assert_eq!(dense.basis_states, sparse.basis_states);
+        for (a, b) in dense.amplitudes_real.iter().zip(&sparse.amplitudes_real) {
+            assert!((a - b).abs() < 1e-10);
+        }
+        for (a, b) in dense.amplitudes_imag.iter().zip(&sparse.amplitudes_imag) {
+            assert!((a - b).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn reference_mode_matches_native() -> Result<(), ket::error::KetError> {
+        use crate::{dense::Dense, quantum_execution::QubitManager};
+        use ket::{Angle, Process, QuantumGate};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut native = Process::new(QubitManager::<Dense>::configuration(4, true, false));
+        let mut reference = Process::new(QubitManager::<Dense>::reference_configuration(4, true));
+        let mut rng = StdRng::seed_from_u64(5);
+
+        let mut qubits = Vec::new();
+        for process in [&mut native, &mut reference] {
+            qubits.push(
+                (0..4)
+                    .map(|_| process.allocate_qubit())
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+
+        for _ in 0..100 {
+            let target = rng.gen_range(0..4);
+            let control = (target + rng.gen_range(1..4)) % 4;
+            let controlled = rng.gen_bool(0.5);
+            let adjoint = rng.gen_bool(0.3);
+            let angle = Angle::Scalar(rng.gen_range(-3.0..3.0));
+            let gate = match rng.gen_range(0..8) {
+                0 => QuantumGate::PauliX,
+                1 => QuantumGate::PauliY,
+                2 => QuantumGate::PauliZ,
+                3 => QuantumGate::Hadamard,
+                4 => QuantumGate::Phase(angle),
+                5 => QuantumGate::RotationX(angle),
+                6 => QuantumGate::RotationY(angle),
+                _ => QuantumGate::RotationZ(Angle::PiFraction { top: 3, bottom: 8 }),
+            };
+
+            for (process, qubits) in [&mut native, &mut reference].into_iter().zip(&qubits) {
+                if controlled {
+                    process.ctrl_push(&[qubits[control]])?;
+                }
+                if adjoint {
+                    process.adj_begin()?;
+                }
+                process.apply_gate(gate.clone(), qubits[target])?;
+                if adjoint {
+                    process.adj_end()?;
+                }
+                if controlled {
+                    process.ctrl_pop()?;
+                }
+            }
+        }
+
+        let native_dump = native.dump(&qubits[0])?;
+        let reference_dump = reference.dump(&qubits[1])?;
+        let native_dump = native.get_dump_data(native_dump).unwrap();
+        let reference_dump = reference.get_dump_data(reference_dump).unwrap();
+
+        assert_eq!(native_dump.basis_states, reference_dump.basis_states);
+        for (a, b) in native_dump
+            .amplitudes_real
+            .iter()
+            .chain(&native_dump.amplitudes_imag)
+            .zip(
+                reference_dump
+                    .amplitudes_real
+                    .iter()
+                    .chain(&reference_dump.amplitudes_imag),
+            )
+        {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn bloch_trajectory() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let configuration =
+            crate::quantum_execution::QubitManager::<super::Dense>::configuration(2, true, false);
+        let mut process = ket::Process::new(configuration);
+        let qubit_a = process.allocate_qubit()?;
+        let qubit_b = process.allocate_qubit()?;
+
+        process.track_bloch_vector(&[qubit_b])?;
+        process.apply_gate(ket::QuantumGate::Hadamard, qubit_b)?;
+        process.apply_gate(
+            ket::QuantumGate::Phase(ket::Angle::PiFraction { top: 1, bottom: 2 }),
+            qubit_b,
+        )?;
+        process.ctrl_push(&[qubit_b])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubit_a)?;
+        process.ctrl_pop()?;
+
+        let expected = [
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0],
+        ];
+        let trajectory = process.get_bloch_trajectory(qubit_b).unwrap();
+        assert_eq!(trajectory.len(), expected.len());
+        for (point, expected) in trajectory.iter().zip(expected) {
+            assert!(point
+                .iter()
+                .zip(expected)
+                .all(|(x, expected)| (x - expected).abs() < 1e-12));
+        }
+
+        let bloch = process.bloch_vector(qubit_a)?;
+        let [x, y, z] = bloch.get(&process).unwrap();
+        assert!(x.abs() < 1e-12 && y.abs() < 1e-12 && z.abs() < 1e-12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn debugger_stepping() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use ket::debugger::{Breakpoint, Debugger, QubitEvent, Stop};
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+
+        let mut process = ket::Process::new(ket::Configuration::new(2));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[0])?;
+        process.ctrl_push(&qubits[..1])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[1])?;
+        process.ctrl_pop()?;
+        let measurement = process.measure(&qubits)?;
+
+        let mut debugger = Debugger::new(
+            process.instructions(),
+            Manager::configuration(2, true, false),
+        )?;
+        let entangle = debugger.add_breakpoint(Breakpoint::Qubit {
+            qubit: qubits[1],
+            event: QubitEvent::Gate,
+        });
+        let measure =
+            debugger.add_breakpoint(Breakpoint::Instruction(process.instructions().len() - 1));
+
+        // The first qubit is in superposition before the CNOT, and mixed after it.
+        assert_eq!(debugger.resume()?, Stop::Breakpoint(entangle));
+        let [x, _, z] = debugger.bloch_vector(qubits[0])?;
+        assert!((x - 1.0).abs() < 1e-9 && z.abs() < 1e-9);
+        assert_eq!(debugger.debug_state()?.lines().count(), 2);
+        assert_eq!(debugger.resume()?, Stop::Breakpoint(measure));
+        let [x, y, z] = debugger.bloch_vector(qubits[0])?;
+        assert!(x.abs() < 1e-9 && y.abs() < 1e-9 && z.abs() < 1e-9);
+        let rho = debugger.reduced_density_matrix(&qubits)?;
+        assert!((rho[0][3].re - 0.5).abs() < 1e-9);
+
+        assert_eq!(debugger.step()?, Stop::Step);
+        let result = debugger.measurement(measurement).unwrap();
+        assert!(result == 0 || result == 3);
+        assert_eq!(debugger.resume()?, Stop::Finished);
+        Ok(())
+    }
+
+    #[test]
+    fn live_rollback() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        let mut process = ket::Process::new(Manager::configuration(3, true, false));
+        let a = process.allocate_qubit()?;
+        let b = process.allocate_qubit()?;
+        process.apply_gate(ket::QuantumGate::PauliX, a)?;
+        let checkpoint = process.checkpoint()?;
+
+        process.apply_gate(ket::QuantumGate::PauliX, b)?;
+        process.allocate_qubit()?;
+        let index = process.measure(&[a, b])?;
+        assert_eq!(process.get_measurement(index).result, Some(0b11));
+
+        // The simulation goes back to the state before the second gate.
+        process.rollback(&checkpoint)?;
+        let index = process.measure(&[a, b])?;
+        assert_eq!(index, 0);
+        assert_eq!(process.get_measurement(index).result, Some(0b10));
+
+        process.rollback(&checkpoint)?;
+        process.apply_gate(ket::QuantumGate::Hadamard, a)?;
+        let dump = process.dump(&[a])?;
+        assert_eq!(process.get_dump_data(dump).unwrap().basis_states.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn dump_top_k() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        type Manager = crate::quantum_execution::QubitManager<super::Dense>;
+        for live in [true, false] {
+            let mut process = ket::Process::new(Manager::configuration(3, live, false));
+            let qubits = (0..3)
+                .map(|_| process.allocate_qubit())
+                .collect::<Result<Vec<_>, _>>()?;
+            for (qubit, angle) in qubits.iter().zip([0.4, 0.9, 1.3]) {
+                let gate = ket::QuantumGate::RotationY(ket::Angle::Scalar(angle));
+                process.apply_gate(gate, *qubit)?;
+            }
+            let full = process.dump(&qubits)?;
+            let top = process.dump_top_k(&qubits, 3)?;
+            process.prepare_for_execution()?;
+
+            // The kept states are the most probable of the full dump, in order.
+            let full = process.get_dump_data(full).unwrap();
+            let top = process.get_dump_data(top).unwrap();
+            assert_eq!(full.basis_states.len(), 8);
+            assert_eq!(top.basis_states, full.top_k(3).basis_states);
+            let probabilities = top
+                .amplitudes_real
+                .iter()
+                .zip(&top.amplitudes_imag)
+                .map(|(real, imag)| real * real + imag * imag)
+                .collect::<Vec<_>>();
+            assert!(probabilities.windows(2).all(|pair| pair[0] >= pair[1]));
+        }
         Ok(())
     }
 }
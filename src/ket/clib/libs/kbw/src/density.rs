@@ -0,0 +1,389 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Density matrix simulator for noisy circuits.
+//!
+//! The `d x d` matrix of `n` qubits is stored as a vector of `2n` bits, with the row
+//! index in the upper `n` bits and the column index in the lower `n` bits. Multiplying
+//! by a gate `U` on the left acts on the row bits, and multiplying by `U^dagger` on the
+//! right acts on the column bits as the complex conjugate of `U`, so the gate kernels
+//! are the same as in the state vector simulators.
+//!
+//! A qubit can have a third level `|2>` to model leakage, see
+//! [`DensityMatrix::with_leakage`]. Each of these qubits adds a leakage bit above the
+//! qubit bits, and its level `|2>` has the qubit bit clear and the leakage bit set. The
+//! gates act on the levels `|0>` and `|1>`, and a leaked control does not activate the
+//! gate.
+
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use ket::QuantumGate;
+use num::{complex::Complex64, One, Zero};
+
+use crate::{
+    bitwise::ctrl_mask,
+    error::{KBWError, Result},
+    noise::{Channel, Leakage, Matrix2, Matrix3, NoiseModel},
+    parallel::*,
+};
+
+/// Largest number of qubits of a density matrix.
+pub const MAX_DENSITY_QUBITS: usize = 14;
+
+/// Returns the matrix of a gate.
+pub fn gate_matrix(gate: &QuantumGate) -> Matrix2 {
+    let (zero, one, i) = (Complex64::zero(), Complex64::one(), Complex64::i());
+    match gate {
+        QuantumGate::PauliX => [[zero, one], [one, zero]],
+        QuantumGate::PauliY => [[zero, -i], [i, zero]],
+        QuantumGate::PauliZ => [[one, zero], [zero, -one]],
+        QuantumGate::Hadamard => {
+            let h = Complex64::from(FRAC_1_SQRT_2);
+            [[h, h], [h, -h]]
+        }
+        QuantumGate::Phase(lambda) => [[one, zero], [zero, (i * lambda.radians()).exp()]],
+        QuantumGate::RotationX(theta) => {
+            let theta = theta.radians();
+            let cos = Complex64::from(f64::cos(theta / 2.0));
+            let sin = -i * f64::sin(theta / 2.0);
+            [[cos, sin], [sin, cos]]
+        }
+        QuantumGate::RotationY(theta) => {
+            let theta = theta.radians();
+            let cos = Complex64::from(f64::cos(theta / 2.0));
+            let sin = Complex64::from(f64::sin(theta / 2.0));
+            [[cos, -sin], [sin, cos]]
+        }
+        QuantumGate::RotationZ(theta) => {
+            let theta = theta.radians();
+            [
+                [(-i * theta / 2.0).exp(), zero],
+                [zero, (i * theta / 2.0).exp()],
+            ]
+        }
+    }
+}
+
+/// Applies `matrix` to bit `target` of the index of every entry whose index has all
+/// bits of `mask` set and all bits of `clear` unset.
+fn apply_matrix(
+    state: &mut [Complex64],
+    matrix: Matrix2,
+    target: usize,
+    mask: usize,
+    clear: usize,
+) {
+    let half = 1 << target;
+    state
+        .par_chunks_mut(2 * half)
+        .enumerate()
+        .for_each(|(chunk, entries)| {
+            let (entries_0, entries_1) = entries.split_at_mut(half);
+            for (index, (entry_0, entry_1)) in entries_0.iter_mut().zip(entries_1).enumerate() {
+                let index = chunk * 2 * half + index;
+                if index & mask != mask || index & clear != 0 {
+                    continue;
+                }
+                let (amp_0, amp_1) = (*entry_0, *entry_1);
+                *entry_0 = matrix[0][0] * amp_0 + matrix[0][1] * amp_1;
+                *entry_1 = matrix[1][0] * amp_0 + matrix[1][1] * amp_1;
+            }
+        });
+}
+
+/// Applies `matrix` to the levels of a qutrit, `|0>` and `|1>` in bit `target` and `|2>`
+/// in bit `leak`, which is above `target`.
+fn apply_matrix3(state: &mut [Complex64], matrix: Matrix3, target: usize, leak: usize) {
+    let half = 1 << leak;
+    state.par_chunks_mut(2 * half).for_each(|entries| {
+        let (entries_0, entries_2) = entries.split_at_mut(half);
+        for index in (0..half).filter(|index| index & (1 << target) == 0) {
+            let amp = [
+                entries_0[index],
+                entries_0[index | (1 << target)],
+                entries_2[index],
+            ];
+            let [amp_0, amp_1, amp_2] =
+                matrix.map(|row| (0..3).map(|k| row[k] * amp[k]).sum::<Complex64>());
+            entries_0[index] = amp_0;
+            entries_0[index | (1 << target)] = amp_1;
+            entries_2[index] = amp_2;
+        }
+    });
+}
+
+fn conj<const N: usize>(matrix: [[Complex64; N]; N]) -> [[Complex64; N]; N] {
+    matrix.map(|row| row.map(|x| x.conj()))
+}
+
+/// Density matrix of up to [`MAX_DENSITY_QUBITS`] qubits, counting a qubit with a
+/// third level as two.
+#[derive(Debug, Clone)]
+pub struct DensityMatrix {
+    num_qubits: usize,
+    /// Qubits with a third level, in the order of their leakage bits.
+    leaky: Vec<usize>,
+    entries: Vec<Complex64>,
+}
+
+impl DensityMatrix {
+    /// Creates the state `|0><0|`.
+    pub fn new(num_qubits: usize) -> Result<Self> {
+        Self::basis_operator(num_qubits, 0, 0)
+    }
+
+    /// Creates the state `|0><0|` where the qubits in `leaky` have a third level `|2>`,
+    /// into which they leak with the [`Leakage`] of the noise model.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KBWError::UnsupportedNumberOfQubits`] if a qubit in `leaky` is out of
+    /// range, or if the qubits and the leaky qubits are more than [`MAX_DENSITY_QUBITS`].
+    pub fn with_leakage(num_qubits: usize, leaky: &[usize]) -> Result<Self> {
+        let mut leaky = leaky.to_vec();
+        leaky.sort_unstable();
+        leaky.dedup();
+        if leaky.iter().any(|qubit| *qubit >= num_qubits) {
+            return Err(KBWError::UnsupportedNumberOfQubits);
+        }
+        Self::zeroed(num_qubits, leaky, 0, 0)
+    }
+
+    /// Creates the operator `|row><column|`.
+    ///
+    /// The result is not a density matrix if `row != column`, but since the evolution
+    /// is linear, the operators of all pairs of basis states characterize a channel.
+    pub fn basis_operator(num_qubits: usize, row: usize, column: usize) -> Result<Self> {
+        Self::zeroed(num_qubits, Vec::new(), row, column)
+    }
+
+    fn zeroed(num_qubits: usize, leaky: Vec<usize>, row: usize, column: usize) -> Result<Self> {
+        let bits = num_qubits + leaky.len();
+        if bits > MAX_DENSITY_QUBITS {
+            return Err(KBWError::UnsupportedNumberOfQubits);
+        }
+
+        let mut entries = vec![Complex64::zero(); 1 << (2 * bits)];
+        entries[(row << bits) | column] = Complex64::one();
+        Ok(Self {
+            num_qubits,
+            leaky,
+            entries,
+        })
+    }
+
+    /// Number of qubits.
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    /// Qubits with a third level.
+    pub fn leaky_qubits(&self) -> &[usize] {
+        &self.leaky
+    }
+
+    /// Number of bits of a row or column index.
+    fn bits(&self) -> usize {
+        self.num_qubits + self.leaky.len()
+    }
+
+    /// Leakage bit of a qubit, if it has a third level.
+    fn leak_bit(&self, qubit: usize) -> Option<usize> {
+        self.leaky
+            .iter()
+            .position(|leaky| *leaky == qubit)
+            .map(|index| self.num_qubits + index)
+    }
+
+    /// Entry of the matrix, between two states of the computational subspace.
+    pub fn get(&self, row: usize, column: usize) -> Complex64 {
+        self.entries[(row << self.bits()) | column]
+    }
+
+    /// Probability of each basis state of the computational subspace, the diagonal of
+    /// the matrix. With leakage, they sum to one minus the probability that a qubit has
+    /// leaked.
+    pub fn probabilities(&self) -> Vec<f64> {
+        (0..1 << self.num_qubits)
+            .map(|state| self.get(state, state).re)
+            .collect()
+    }
+
+    /// Population of the level `|2>` of a qubit, `0` for a qubit without a third level.
+    pub fn leakage_population(&self, qubit: usize) -> f64 {
+        let Some(leak) = self.leak_bit(qubit) else {
+            return 0.0;
+        };
+        let bits = self.bits();
+        (0..1usize << bits)
+            .filter(|state| state & (1 << leak) != 0)
+            .map(|state| self.entries[(state << bits) | state].re)
+            .sum()
+    }
+
+    /// Computes `K rho K^dagger`.
+    fn conjugate_by(&mut self, matrix: Matrix2, target: usize, control: &[usize]) {
+        let bits = self.bits();
+        let mask = ctrl_mask::<usize>(control);
+        // The level |2> of the target is left unchanged.
+        let clear = self.leak_bit(target).map_or(0, |leak| 1 << leak);
+        apply_matrix(
+            &mut self.entries,
+            matrix,
+            target + bits,
+            mask << bits,
+            clear << bits,
+        );
+        apply_matrix(&mut self.entries, conj(matrix), target, mask, clear);
+    }
+
+    /// Computes `K rho K^dagger` for a matrix on the levels of a qutrit.
+    fn conjugate_by3(&mut self, matrix: Matrix3, target: usize, leak: usize) {
+        let bits = self.bits();
+        apply_matrix3(&mut self.entries, matrix, target + bits, leak + bits);
+        apply_matrix3(&mut self.entries, conj(matrix), target, leak);
+    }
+
+    /// Applies a gate.
+    pub fn gate(&mut self, gate: &QuantumGate, target: usize, control: &[usize]) {
+        self.conjugate_by(gate_matrix(gate), target, control);
+    }
+
+    /// Applies a single-qubit channel.
+    pub fn channel(&mut self, channel: &Channel, target: usize) {
+        self.kraus(&channel.kraus(), target);
+    }
+
+    /// Applies the channel of a list of Kraus operators, `sum_k K rho K^dagger`.
+    ///
+    /// On a qubit with a third level, the first operator leaves the level `|2>`
+    /// unchanged and the others remove it, so the channel still preserves the trace.
+    pub fn kraus(&mut self, operators: &[Matrix2], target: usize) {
+        if let Some(leak) = self.leak_bit(target) {
+            let operators = operators.iter().enumerate().map(|(index, kraus)| {
+                let mut matrix = [[Complex64::zero(); 3]; 3];
+                for (row, kraus_row) in kraus.iter().enumerate() {
+                    matrix[row][..2].copy_from_slice(kraus_row);
+                }
+                if index == 0 {
+                    matrix[2][2] = Complex64::one();
+                }
+                matrix
+            });
+            self.sum_terms(operators, |term, kraus| {
+                term.conjugate_by3(kraus, target, leak)
+            });
+        } else {
+            self.sum_terms(operators.iter().copied(), |term, kraus| {
+                term.conjugate_by(kraus, target, &[])
+            });
+        }
+    }
+
+    /// Applies the leakage to a qubit, if it has a third level.
+    pub fn leakage(&mut self, leakage: &Leakage, target: usize) {
+        if let Some(leak) = self.leak_bit(target) {
+            self.sum_terms(leakage.kraus(), |term, kraus| {
+                term.conjugate_by3(kraus, target, leak)
+            });
+        }
+    }
+
+    /// Replaces the matrix with the sum of the terms computed from each operator.
+    fn sum_terms<K>(
+        &mut self,
+        operators: impl IntoIterator<Item = K>,
+        conjugate: impl Fn(&mut Self, K),
+    ) {
+        let mut result = vec![Complex64::zero(); self.entries.len()];
+        for kraus in operators {
+            let mut term = self.clone();
+            conjugate(&mut term, kraus);
+            result
+                .par_iter_mut()
+                .zip(&term.entries)
+                .for_each(|(result, term)| *result += term);
+        }
+        self.entries = result;
+    }
+
+    /// Applies a gate followed by the noise of the model.
+    pub fn noisy_gate(
+        &mut self,
+        gate: &QuantumGate,
+        target: usize,
+        control: &[usize],
+        noise: &NoiseModel,
+    ) {
+        self.gate(gate, target, control);
+        if let Some(channel) = &noise.gate {
+            for qubit in control.iter().chain([&target]) {
+                self.channel(channel, *qubit);
+            }
+        }
+        if let Some(leakage) = &noise.leakage {
+            for qubit in control.iter().chain([&target]) {
+                self.leakage(leakage, *qubit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DensityMatrix;
+    use crate::noise::{Channel, Leakage, NoiseModel};
+    use ket::QuantumGate;
+
+    #[test]
+    fn noisy_bell_state() {
+        let mut rho = DensityMatrix::new(2).unwrap();
+        rho.gate(&QuantumGate::Hadamard, 0, &[]);
+        rho.gate(&QuantumGate::PauliX, 1, &[0]);
+        let probabilities = rho.probabilities();
+        assert!((probabilities[0b00] - 0.5).abs() < 1e-12);
+        assert!((probabilities[0b11] - 0.5).abs() < 1e-12);
+        assert!((rho.get(0b00, 0b11).re - 0.5).abs() < 1e-12);
+
+        let mut rho = DensityMatrix::new(1).unwrap();
+        rho.noisy_gate(
+            &QuantumGate::PauliX,
+            0,
+            &[],
+            &NoiseModel::after_gates(Channel::AmplitudeDamping(0.25)),
+        );
+        let probabilities = rho.probabilities();
+        assert!((probabilities[0] - 0.25).abs() < 1e-12);
+        assert!((probabilities[1] - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn leakage() {
+        let noise = NoiseModel::default().with_leakage(Leakage {
+            probability: 0.2,
+            seepage: 0.1,
+        });
+        let mut rho = DensityMatrix::with_leakage(2, &[0]).unwrap();
+        rho.noisy_gate(&QuantumGate::PauliX, 0, &[], &noise);
+        assert!((rho.leakage_population(0) - 0.2).abs() < 1e-12);
+        assert_eq!(rho.leakage_population(1), 0.0);
+
+        // A leaked control does not activate the gate.
+        rho.gate(&QuantumGate::PauliX, 1, &[0]);
+        let probabilities = rho.probabilities();
+        assert!((probabilities[0b11] - 0.8).abs() < 1e-12);
+        assert!((probabilities.iter().sum::<f64>() - 0.8).abs() < 1e-12);
+
+        // The channels preserve the trace on the three levels.
+        rho.channel(&Channel::BitFlip(0.3), 0);
+        rho.gate(&QuantumGate::Hadamard, 0, &[]);
+        rho.noisy_gate(&QuantumGate::PauliZ, 0, &[], &noise);
+        let trace = rho.probabilities().iter().sum::<f64>() + rho.leakage_population(0);
+        assert!((trace - 1.0).abs() < 1e-12);
+        assert!((rho.probabilities()[0b01] - 0.02).abs() < 1e-12);
+
+        assert!(DensityMatrix::with_leakage(2, &[2]).is_err());
+    }
+}
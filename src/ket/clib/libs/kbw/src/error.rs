@@ -34,6 +34,21 @@ pub enum KBWError {
 
     #[error("The data type is undefined.")]
     UndefinedDataType,
+
+    #[error("Failed to create the simulator thread pool.")]
+    ThreadPoolError,
+
+    #[error("The process contains an instruction that is not a unitary gate.")]
+    NonUnitaryInstruction,
+
+    #[error("The noise model has a probability outside [0, 1].")]
+    InvalidNoiseModel,
+
+    #[error("The number of trajectories must be at least one.")]
+    InvalidNumberOfTrajectories,
+
+    #[error("The circuit cannot be cut in fragments of the requested number of qubits.")]
+    InvalidCircuitCut,
 }
 
 /// Result type for KBW library functions.
@@ -0,0 +1,433 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fusion of repeated subcircuits.
+//!
+//! Error correction rounds and Trotter layers apply the same gate sequence many times,
+//! often on different qubits. The gates of a batch execution are split in blocks of
+//! consecutive gates on at most `max_qubits` qubits, and each block is identified by
+//! its gates with the qubits renamed in order of first use. A block that appears more
+//! than once is fused: its unitary matrix is computed once, kept in a [`FusionCache`]
+//! across executions, and applied to the state with a single pass, see
+//! [`QuantumExecution::apply_unitary`](crate::quantum_execution::QuantumExecution::apply_unitary).
+//!
+//! A fused block costs `2^k` multiplications per amplitude on `k` qubits, against two
+//! per gate, so only blocks with more than `2^(k-1)` gates are fused.
+//!
+//! A block that starts or ends with a gate sequence repeated `r` times in a row, such
+//! as the `U^(2^k)` of [`Process::apply_power`](ket::Process::apply_power), is split
+//! around the repetition, which is fused even if it appears once: the matrix of the
+//! sequence is raised to the power `r` by repeated squaring, see
+//! [`power`](crate::unitary::power).
+//!
+//! [`QubitManager`](crate::quantum_execution::QubitManager) fuses subcircuits of up to
+//! `KBW_FUSION_QUBITS` qubits when the environment variable is set, or after
+//! [`QubitManager::fuse_subcircuits`](crate::quantum_execution::QubitManager::fuse_subcircuits).
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use itertools::Itertools;
+use ket::Instruction;
+use log::debug;
+
+use crate::unitary::{power, unitary, Matrix, MAX_UNITARY_QUBITS};
+
+/// Unitary matrices of the repeated subcircuits.
+#[derive(Debug, Default)]
+pub struct FusionCache {
+    max_qubits: usize,
+    matrices: HashMap<String, Arc<Matrix>>,
+    fused: u64,
+}
+
+/// Step of a batch execution.
+pub(crate) enum Step<'a> {
+    Instruction(&'a Instruction),
+    Fused {
+        matrix: Arc<Matrix>,
+        /// Qubits of the block, the first one being the most significant bit of the
+        /// matrix index.
+        qubits: Vec<usize>,
+        /// Gates of the block, applied one by one if the simulator does not support
+        /// multi-qubit matrices.
+        gates: &'a [Instruction],
+    },
+}
+
+/// Consecutive gates and their qubits in order of first use.
+struct Block {
+    range: std::ops::Range<usize>,
+    qubits: Vec<usize>,
+    key: String,
+    /// Length of the sequence that the gates repeat, the number of gates if the block
+    /// is not a repetition.
+    period: usize,
+}
+
+impl FusionCache {
+    /// Creates a cache that fuses subcircuits of up to `max_qubits` qubits, at most
+    /// [`MAX_UNITARY_QUBITS`].
+    pub fn new(max_qubits: usize) -> Self {
+        Self {
+            max_qubits: max_qubits.min(MAX_UNITARY_QUBITS),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the number of distinct fused subcircuits.
+    pub fn len(&self) -> usize {
+        self.matrices.len()
+    }
+
+    /// Returns `true` if no subcircuit was fused.
+    pub fn is_empty(&self) -> bool {
+        self.matrices.is_empty()
+    }
+
+    /// Returns the number of times a fused subcircuit was planned.
+    pub fn fused(&self) -> u64 {
+        self.fused
+    }
+
+    /// Splits the instructions in steps, fusing the repeated blocks of gates.
+    pub(crate) fn plan<'a>(&mut self, instructions: &'a [Instruction]) -> Vec<Step<'a>> {
+        let blocks = self.blocks(instructions);
+        let mut counts = HashMap::<&str, usize>::new();
+        for block in &blocks {
+            *counts.entry(&block.key).or_default() += 1;
+        }
+        let repeated: HashSet<String> = blocks
+            .iter()
+            .filter(|block| {
+                let gates = block.range.len();
+                2 * gates > 1 << block.qubits.len()
+                    && (counts[block.key.as_str()] > 1
+                        || block.period < gates
+                        || self.matrices.contains_key(&block.key))
+            })
+            .map(|block| block.key.clone())
+            .collect();
+
+        let mut steps = Vec::with_capacity(instructions.len());
+        let mut next = 0;
+        for block in blocks {
+            steps.extend(
+                instructions[next..block.range.start]
+                    .iter()
+                    .map(Step::Instruction),
+            );
+            next = block.range.end;
+            let gates = &instructions[block.range];
+
+            let matrix = if repeated.contains(&block.key) {
+                self.matrix(&block.key, &block.qubits, gates, block.period)
+            } else {
+                None
+            };
+            match matrix {
+                Some(matrix) => {
+                    self.fused += 1;
+                    steps.push(Step::Fused {
+                        matrix,
+                        qubits: block.qubits,
+                        gates,
+                    })
+                }
+                None => steps.extend(gates.iter().map(Step::Instruction)),
+            }
+        }
+        steps.extend(instructions[next..].iter().map(Step::Instruction));
+        steps
+    }
+
+    /// Returns the cached matrix of the block, computing it on the first use.
+    fn matrix(
+        &mut self,
+        key: &str,
+        qubits: &[usize],
+        gates: &[Instruction],
+        period: usize,
+    ) -> Option<Arc<Matrix>> {
+        if let Some(matrix) = self.matrices.get(key) {
+            return Some(matrix.clone());
+        }
+
+        let local = |qubit: &usize| qubits.iter().position(|q| q == qubit).unwrap();
+        let instructions = (0..qubits.len())
+            .map(|target| Instruction::Alloc { target })
+            .chain(gates[..period].iter().map(|gate| relabel(gate, local)))
+            .collect_vec();
+        let mut matrix = unitary(&instructions).ok()?;
+        let repetitions = gates.len() / period;
+        if repetitions > 1 {
+            matrix = power(&matrix, repetitions as u64);
+        }
+        let matrix = Arc::new(matrix);
+
+        debug!(
+            "fused subcircuit qubits={} gates={} repetitions={}",
+            qubits.len(),
+            period,
+            repetitions
+        );
+        self.matrices.insert(key.to_string(), matrix.clone());
+        Some(matrix)
+    }
+
+    /// Splits the gates in maximal blocks of consecutive gates on at most
+    /// `max_qubits` qubits.
+    fn blocks(&self, instructions: &[Instruction]) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut current: Option<(usize, Vec<usize>)> = None;
+
+        let close = |blocks: &mut Vec<Block>, start: usize, end: usize, qubits: Vec<usize>| {
+            let local = |qubit: &usize| qubits.iter().position(|q| q == qubit).unwrap();
+            let gates = instructions[start..end]
+                .iter()
+                .map(|gate| format!("{:?}", relabel(gate, local)))
+                .collect_vec();
+
+            // The prefix and the suffix of a repetition are blocks on the same qubits,
+            // fused only if they repeat.
+            let mut push = |range: std::ops::Range<usize>, period: usize| {
+                if range.is_empty() {
+                    return;
+                }
+                let mut key = gates[range.start..range.start + period].join("; ");
+                if period < range.len() {
+                    key += &format!(" ^ {}", range.len() / period);
+                }
+                blocks.push(Block {
+                    range: start + range.start..start + range.end,
+                    qubits: qubits.clone(),
+                    key,
+                    period,
+                });
+            };
+            match repetition(&gates) {
+                Some((range, period)) => {
+                    push(0..range.start, range.start);
+                    push(range.clone(), period);
+                    push(range.end..gates.len(), gates.len() - range.end);
+                }
+                None => push(0..gates.len(), gates.len()),
+            }
+        };
+
+        for (index, instruction) in instructions.iter().enumerate() {
+            let Instruction::Gate {
+                target, control, ..
+            } = instruction
+            else {
+                if let Some((start, qubits)) = current.take() {
+                    close(&mut blocks, start, index, qubits);
+                }
+                continue;
+            };
+
+            let gate_qubits = control
+                .iter()
+                .chain([target])
+                .unique()
+                .copied()
+                .collect_vec();
+            if let Some((_, qubits)) = &current {
+                let new = gate_qubits.iter().filter(|qubit| !qubits.contains(qubit));
+                if qubits.len() + new.count() > self.max_qubits {
+                    let (start, qubits) = current.take().unwrap();
+                    close(&mut blocks, start, index, qubits);
+                }
+            }
+            let (_, qubits) = current.get_or_insert((index, Vec::new()));
+            for qubit in gate_qubits {
+                if !qubits.contains(&qubit) {
+                    qubits.push(qubit);
+                }
+            }
+        }
+        if let Some((start, qubits)) = current {
+            close(&mut blocks, start, instructions.len(), qubits);
+        }
+
+        blocks
+            .into_iter()
+            .filter(|block| block.qubits.len() <= self.max_qubits)
+            .collect()
+    }
+}
+
+/// Returns the longest prefix or suffix of the gates that repeats a sequence at least
+/// twice, and the length of the sequence.
+fn repetition(gates: &[String]) -> Option<(std::ops::Range<usize>, usize)> {
+    // With the prefix function, `s[..=i]` has the period `i + 1 - prefix[i]`, and is a
+    // repetition of its first `period` elements if the period divides its length.
+    let longest = |gates: &mut dyn Iterator<Item = &String>| {
+        let gates = gates.collect_vec();
+        let mut prefix = vec![0; gates.len()];
+        let mut best = None;
+        for i in 1..gates.len() {
+            let mut k = prefix[i - 1];
+            while k > 0 && gates[i] != gates[k] {
+                k = prefix[k - 1];
+            }
+            if gates[i] == gates[k] {
+                k += 1;
+            }
+            prefix[i] = k;
+            let period = i + 1 - k;
+            if k > 0 && (i + 1).is_multiple_of(period) {
+                best = Some((i + 1, period));
+            }
+        }
+        best
+    };
+
+    let prefix = longest(&mut gates.iter());
+    let suffix = longest(&mut gates.iter().rev());
+    match (prefix, suffix) {
+        (Some((len, period)), suffix) if suffix.is_none_or(|(other, _)| len >= other) => {
+            Some((0..len, period))
+        }
+        (_, Some((len, period))) => Some((gates.len() - len..gates.len(), period)),
+        _ => None,
+    }
+}
+
+/// Renames the qubits of a gate.
+fn relabel(gate: &Instruction, local: impl Fn(&usize) -> usize) -> Instruction {
+    match gate {
+        Instruction::Gate {
+            gate,
+            target,
+            control,
+        } => Instruction::Gate {
+            gate: gate.clone(),
+            target: local(target),
+            control: control.iter().map(&local).collect(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FusionCache;
+    use crate::{dense::Dense, quantum_execution::QubitManager};
+    use ket::{Angle, BatchExecution, Instruction, QuantumGate};
+
+    fn layer(qubits: [usize; 3]) -> Vec<Instruction> {
+        let gate = |gate, target, control: &[usize]| Instruction::Gate {
+            gate,
+            target,
+            control: control.to_vec(),
+        };
+        vec![
+            gate(QuantumGate::Hadamard, qubits[0], &[]),
+            gate(QuantumGate::PauliX, qubits[1], &qubits[..1]),
+            gate(QuantumGate::RotationZ(Angle::Scalar(0.3)), qubits[1], &[]),
+            gate(QuantumGate::PauliX, qubits[2], &qubits[1..2]),
+            gate(QuantumGate::RotationY(Angle::Scalar(0.2)), qubits[2], &[]),
+        ]
+    }
+
+    #[test]
+    fn fused_layers() -> Result<(), Box<dyn std::error::Error>> {
+        let mut instructions: Vec<_> = (0..6).map(|target| Instruction::Alloc { target }).collect();
+        for _ in 0..2 {
+            instructions.extend(layer([0, 1, 2]));
+            instructions.extend(layer([5, 3, 4]));
+            instructions.extend(layer([2, 4, 1]));
+        }
+        instructions.push(Instruction::Dump {
+            qubits: (0..6).collect(),
+            output: 0,
+        });
+
+        let mut expected = QubitManager::<Dense>::new(6)?;
+        expected.submit_execution(&instructions);
+        let expected = expected.get_result().dumps.remove(0);
+
+        let mut fused = QubitManager::<Dense>::new(6)?;
+        fused.fuse_subcircuits(3);
+        fused.submit_execution(&instructions);
+        let result = fused.get_result().dumps.remove(0);
+        let cache = fused.fusion_cache().unwrap();
+        assert_eq!((cache.len(), cache.fused()), (1, 6));
+
+        let amplitudes = |dump: &ket::DumpData| {
+            let mut state = vec![num::complex::Complex64::default(); 64];
+            for ((basis, re), im) in dump
+                .basis_states
+                .iter()
+                .zip(&dump.amplitudes_real)
+                .zip(&dump.amplitudes_imag)
+            {
+                state[basis[0] as usize] = num::complex::Complex64::new(*re, *im);
+            }
+            state
+        };
+        for (a, b) in amplitudes(&expected).iter().zip(amplitudes(&result)) {
+            assert!((a - b).norm() < 1e-10);
+        }
+
+        // A block that appears once is fused only if its matrix is in the cache.
+        let mut cache = FusionCache::new(3);
+        assert_eq!(cache.plan(&layer([0, 1, 2])).len(), 5);
+        assert_eq!(cache.plan(&instructions[..16]).len(), 8);
+        assert_eq!(cache.plan(&layer([3, 4, 5])).len(), 1);
+        assert_eq!(cache.fused(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn fused_power() -> Result<(), Box<dyn std::error::Error>> {
+        let template = ket::template::Template::record(2, |process, qubits| {
+            process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+            process.apply_gate(QuantumGate::RotationY(Angle::Scalar(0.4)), qubits[1])?;
+            process.ctrl_push(&qubits[..1])?;
+            process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+            process.ctrl_pop()
+        })?;
+        let mut process = ket::Process::new(ket::Configuration::new(3));
+        let qubits = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>, _>>()?;
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+        process.apply_power(&template, &qubits[1..], 3, &qubits[..1])?;
+        let mut instructions = process.instructions().to_vec();
+        instructions.push(Instruction::Dump {
+            qubits: qubits.clone(),
+            output: 0,
+        });
+
+        // The Hadamard gate is a prefix of the repetition.
+        let mut cache = FusionCache::new(3);
+        let steps = cache.plan(&instructions);
+        assert_eq!((steps.len(), cache.fused()), (3 + 1 + 1 + 1, 1));
+
+        let mut expected = QubitManager::<Dense>::new(3)?;
+        expected.submit_execution(&instructions);
+        let expected = expected.get_result().dumps.remove(0);
+
+        let mut fused = QubitManager::<Dense>::new(3)?;
+        fused.fuse_subcircuits(3);
+        fused.submit_execution(&instructions);
+        let result = fused.get_result().dumps.remove(0);
+
+        assert_eq!(expected.basis_states, result.basis_states);
+        for (a, b) in expected
+            .amplitudes_real
+            .iter()
+            .zip(&expected.amplitudes_imag)
+            .zip(result.amplitudes_real.iter().zip(&result.amplitudes_imag))
+        {
+            assert!((a.0 - b.0).abs() < 1e-10 && (a.1 - b.1).abs() < 1e-10);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! State vector stored in half precision.
+//!
+//! Each amplitude is stored in 4 bytes, against the 32 bytes of the two buffers of
+//! [`crate::dense::Dense`], so the same memory holds a state with three more qubits.
+//! Gates are computed in `f32` and rounded back to the storage type, updating the
+//! state in place.
+//!
+//! Rounding is the only source of error, as the gates are unitary: the distance
+//! between the stored and the exact state grows by at most the norm of the rounding
+//! made by each gate. [`HalfPrecision::error_estimate`] returns the sum of these
+//! norms, a bound on the distance to the exact state.
+//!
+//! [`f16`] keeps more mantissa bits, but the amplitudes of a uniform superposition of
+//! more than 28 qubits are subnormal and lose precision. [`bf16`] has the exponent
+//! range of `f32` and suits larger states.
+
+use crate::bitwise::*;
+use crate::error::{KBWError, Result};
+use crate::noise::Matrix2;
+use crate::parallel::*;
+use crate::quantum_execution::{choose_outcome, QuantumExecution};
+use crate::summation::CompensatedSum;
+use half::{bf16, f16};
+use itertools::Itertools;
+use log::error;
+use num::complex::{Complex32, Complex64};
+use rand::prelude::*;
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// Largest number of qubits.
+pub const MAX_QUBITS: usize = 34;
+
+/// Floating point type of the stored amplitudes.
+pub trait Storage: Copy + Send + Sync + 'static {
+    fn from_f32(value: f32) -> Self;
+    fn to_f32(self) -> f32;
+}
+
+impl Storage for f16 {
+    fn from_f32(value: f32) -> Self {
+        f16::from_f32(value)
+    }
+
+    fn to_f32(self) -> f32 {
+        f16::to_f32(self)
+    }
+}
+
+impl Storage for bf16 {
+    fn from_f32(value: f32) -> Self {
+        bf16::from_f32(value)
+    }
+
+    fn to_f32(self) -> f32 {
+        bf16::to_f32(self)
+    }
+}
+
+/// Rounds the amplitude to the storage type, returning the squared rounding error.
+fn store<F: Storage>(amplitude: Complex32, into: &mut [F; 2]) -> f64 {
+    *into = [F::from_f32(amplitude.re), F::from_f32(amplitude.im)];
+    (amplitude - load(into)).norm_sqr() as f64
+}
+
+fn load<F: Storage>(amplitude: &[F; 2]) -> Complex32 {
+    Complex32::new(amplitude[0].to_f32(), amplitude[1].to_f32())
+}
+
+#[derive(Clone)]
+pub struct HalfPrecision<F: Storage = f16> {
+    state: Vec<[F; 2]>,
+    error: f64,
+}
+
+impl<F: Storage> HalfPrecision<F> {
+    /// Returns a bound on the distance between the stored state and the state of an
+    /// exact simulation.
+    pub fn error_estimate(&self) -> f64 {
+        self.error
+    }
+
+    /// Applies a 2x2 matrix on the target qubit, if the control qubits are set.
+    fn apply(&mut self, matrix: [[Complex32; 2]; 2], target: usize, control: &[usize]) {
+        let mask: usize = ctrl_mask(control);
+        let half = 1 << target;
+
+        let squared_error: f64 = self
+            .state
+            .par_chunks_mut(2 * half)
+            .enumerate()
+            .map(|(chunk, amplitudes)| {
+                let (zeros, ones) = amplitudes.split_at_mut(half);
+                let mut squared_error = 0.0;
+                for (index, (amp_0, amp_1)) in zeros.iter_mut().zip(ones).enumerate() {
+                    if ctrl_check_mask(chunk * 2 * half + index, mask) {
+                        let (value_0, value_1) = (load(amp_0), load(amp_1));
+                        squared_error +=
+                            store(matrix[0][0] * value_0 + matrix[0][1] * value_1, amp_0);
+                        squared_error +=
+                            store(matrix[1][0] * value_0 + matrix[1][1] * value_1, amp_1);
+                    }
+                }
+                squared_error
+            })
+            .sum();
+
+        self.error += squared_error.sqrt();
+    }
+
+    fn apply_matrix64(&mut self, matrix: Matrix2, target: usize, control: &[usize]) {
+        let narrow = |value: Complex64| Complex32::new(value.re as f32, value.im as f32);
+        self.apply(
+            [
+                [narrow(matrix[0][0]), narrow(matrix[0][1])],
+                [narrow(matrix[1][0]), narrow(matrix[1][1])],
+            ],
+            target,
+            control,
+        );
+    }
+}
+
+impl<F: Storage> QuantumExecution for HalfPrecision<F> {
+    fn new(num_qubits: usize) -> Result<Self> {
+        if num_qubits > MAX_QUBITS {
+            error!("half precision implementation supports up to {MAX_QUBITS} qubits");
+            return Err(KBWError::UnsupportedNumberOfQubits);
+        }
+
+        let zero = [F::from_f32(0.0); 2];
+        let mut state: Vec<[F; 2]> = (0..1usize << num_qubits)
+            .into_par_iter()
+            .map(|_| zero)
+            .collect();
+        state[0][0] = F::from_f32(1.0);
+
+        Ok(Self { state, error: 0.0 })
+    }
+
+    fn snapshot(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn pauli_x(&mut self, target: usize, control: &[usize]) {
+        let (zero, one) = (Complex32::new(0.0, 0.0), Complex32::new(1.0, 0.0));
+        self.apply([[zero, one], [one, zero]], target, control);
+    }
+
+    fn pauli_y(&mut self, target: usize, control: &[usize]) {
+        let zero = Complex32::new(0.0, 0.0);
+        self.apply(
+            [[zero, -Complex32::i()], [Complex32::i(), zero]],
+            target,
+            control,
+        );
+    }
+
+    fn pauli_z(&mut self, target: usize, control: &[usize]) {
+        let (zero, one) = (Complex32::new(0.0, 0.0), Complex32::new(1.0, 0.0));
+        self.apply([[one, zero], [zero, -one]], target, control);
+    }
+
+    fn hadamard(&mut self, target: usize, control: &[usize]) {
+        let h = Complex32::new(FRAC_1_SQRT_2, 0.0);
+        self.apply([[h, h], [h, -h]], target, control);
+    }
+
+    fn phase(&mut self, lambda: f64, target: usize, control: &[usize]) {
+        let (zero, one) = (Complex64::new(0.0, 0.0), Complex64::new(1.0, 0.0));
+        let phase = Complex64::exp(lambda * Complex64::i());
+        self.apply_matrix64([[one, zero], [zero, phase]], target, control);
+    }
+
+    fn rx(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let cos = Complex64::from(f64::cos(theta / 2.0));
+        let sin = -Complex64::i() * f64::sin(theta / 2.0);
+        self.apply_matrix64([[cos, sin], [sin, cos]], target, control);
+    }
+
+    fn ry(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let cos = Complex64::from(f64::cos(theta / 2.0));
+        let sin = Complex64::from(f64::sin(theta / 2.0));
+        self.apply_matrix64([[cos, -sin], [sin, cos]], target, control);
+    }
+
+    fn rz(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let zero = Complex64::new(0.0, 0.0);
+        let phase_0 = Complex64::exp(-theta / 2.0 * Complex64::i());
+        let phase_1 = Complex64::exp(theta / 2.0 * Complex64::i());
+        self.apply_matrix64([[phase_0, zero], [zero, phase_1]], target, control);
+    }
+
+    fn measure<R: Rng>(&mut self, target: usize, forced: Option<bool>, rng: &mut R) -> bool {
+        let (p0, p1) = self
+            .state
+            .par_iter()
+            .enumerate()
+            .map(|(state, amp)| {
+                let p = CompensatedSum::from(load(amp).norm_sqr() as f64);
+                if is_one_at(state, target) {
+                    (CompensatedSum::default(), p)
+                } else {
+                    (p, CompensatedSum::default())
+                }
+            })
+            .reduce(Default::default, |a, b| (a.0.merge(b.0), a.1.merge(b.1)));
+        let (p0, p1) = (p0.value(), p1.value());
+
+        let result = choose_outcome(p0, p1, forced, rng);
+        let probability = if result { p1 } else { p0 };
+        let p = (1.0 / probability.sqrt()) as f32;
+
+        let squared_error: f64 = self
+            .state
+            .par_iter_mut()
+            .enumerate()
+            .map(|(state, amp)| {
+                let value = if is_one_at(state, target) == result {
+                    load(amp) * p
+                } else {
+                    Complex32::new(0.0, 0.0)
+                };
+                store(value, amp)
+            })
+            .sum();
+
+        // The normalization scales the error of the remaining amplitudes.
+        self.error = self.error / probability.sqrt() + squared_error.sqrt();
+
+        result
+    }
+
+    fn dump(&mut self, qubits: &[usize]) -> ket::DumpData {
+        let (basis_states, amplitudes_real, amplitudes_imag): (Vec<_>, Vec<_>, Vec<_>) = self
+            .state
+            .par_iter()
+            .enumerate()
+            .map(|(state, amp)| (state, load(amp)))
+            .filter(|(_, amp)| amp.norm() > 0.0)
+            .map(|(state, amp)| {
+                (
+                    Vec::from([extract_bits(state, qubits) as u64]),
+                    amp.re as f64,
+                    amp.im as f64,
+                )
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .multiunzip();
+
+        ket::DumpData {
+            basis_states,
+            amplitudes_real,
+            amplitudes_imag,
+        }
+    }
+
+    fn apply_matrix(&mut self, matrix: Matrix2, target: usize) {
+        self.apply_matrix64(matrix, target, &[]);
+    }
+
+    fn reduced_density_matrix(&self, target: usize) -> Matrix2 {
+        let (rho_00, rho_11, rho_01) = (0..self.state.len())
+            .into_par_iter()
+            .filter(|state| !is_one_at(*state, target))
+            .map(|state| {
+                let amp_0 = load(&self.state[state]);
+                let amp_1 = load(&self.state[bit_flip(state, target)]);
+                let amp_0 = Complex64::new(amp_0.re as f64, amp_0.im as f64);
+                let amp_1 = Complex64::new(amp_1.re as f64, amp_1.im as f64);
+                (amp_0.norm_sqr(), amp_1.norm_sqr(), amp_0 * amp_1.conj())
+            })
+            .reduce(
+                || (0.0, 0.0, Complex64::new(0.0, 0.0)),
+                |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2),
+            );
+
+        [[rho_00.into(), rho_01], [rho_01.conj(), rho_11.into()]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HalfPrecision;
+    use crate::{dense::Dense, quantum_execution::QuantumExecution};
+    use half::{bf16, f16};
+
+    fn circuit<S: QuantumExecution>(simulator: &mut S) {
+        for qubit in 0..4 {
+            simulator.hadamard(qubit, &[]);
+            simulator.ry(0.3 + 0.2 * qubit as f64, qubit, &[]);
+        }
+        simulator.pauli_x(3, &[0]);
+        simulator.rz(0.7, 1, &[2]);
+        simulator.phase(1.3, 0, &[3]);
+        simulator.rx(0.4, 2, &[]);
+    }
+
+    /// Returns the distance between the states of the dumps.
+    fn distance(a: ket::DumpData, b: ket::DumpData) -> f64 {
+        let mut state = vec![(0.0, 0.0); 16];
+        for (basis, (re, im)) in a
+            .basis_states
+            .iter()
+            .zip(a.amplitudes_real.iter().zip(&a.amplitudes_imag))
+        {
+            state[basis[0] as usize] = (*re, *im);
+        }
+        for (basis, (re, im)) in b
+            .basis_states
+            .iter()
+            .zip(b.amplitudes_real.iter().zip(&b.amplitudes_imag))
+        {
+            let amp = &mut state[basis[0] as usize];
+            *amp = (amp.0 - re, amp.1 - im);
+        }
+        state
+            .iter()
+            .map(|(re, im)| re * re + im * im)
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    #[test]
+    fn error_bound() {
+        let qubits = [0, 1, 2, 3];
+        let mut dense = Dense::new(4).unwrap();
+        circuit(&mut dense);
+        let exact = dense.dump(&qubits);
+
+        let mut half = HalfPrecision::<f16>::new(4).unwrap();
+        circuit(&mut half);
+        let mut brain = HalfPrecision::<bf16>::new(4).unwrap();
+        circuit(&mut brain);
+
+        let half_distance = distance(exact.clone(), half.dump(&qubits));
+        let brain_distance = distance(exact, brain.dump(&qubits));
+        assert!(half.error_estimate() > 0.0);
+        assert!(half_distance <= half.error_estimate() + 1e-6);
+        assert!(brain_distance <= brain.error_estimate() + 1e-6);
+        assert!(half_distance < 1e-2);
+        assert!(half.error_estimate() < brain.error_estimate());
+    }
+}
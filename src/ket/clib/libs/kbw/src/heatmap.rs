@@ -0,0 +1,137 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Joint probabilities of two subsets of qubits, for heatmap plots.
+//!
+//! A [`Heatmap`] has a row for each value of the row qubits and a column for each value
+//! of the column qubits, with the probability of measuring both. It is computed by
+//! [`QubitManager::heatmap`](crate::quantum_execution::QubitManager::heatmap) without a
+//! dump of the state, see
+//! [`QuantumExecution::joint_probabilities`](crate::quantum_execution::QuantumExecution::joint_probabilities),
+//! and exported as CSV or JSON:
+//!
+//! ```text
+//! rows\columns,0,1
+//! 00,0.5,0
+//! 01,0,0
+//! 10,0,0
+//! 11,0,0.5
+//! ```
+//!
+//! Rows and columns are labeled with the bitstring of their value, the first qubit of
+//! the subset being the leftmost bit.
+
+use std::fmt::Write;
+
+/// Joint probabilities of two subsets of qubits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heatmap {
+    /// Qubits of the row index, the first one being the most significant bit.
+    pub rows: Vec<usize>,
+    /// Qubits of the column index, the first one being the most significant bit.
+    pub columns: Vec<usize>,
+    /// Probability of each value of the row qubits together with each value of the
+    /// column qubits.
+    pub probabilities: Vec<Vec<f64>>,
+}
+
+/// Returns the bitstring labels of the values of `width` qubits.
+fn labels(width: usize) -> Vec<String> {
+    (0..1u64 << width)
+        .map(|value| ket::basis::to_bitstring(&[value], width))
+        .collect()
+}
+
+impl Heatmap {
+    /// Returns the table in CSV, with a header of column labels and the row label in
+    /// the first field.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("rows\\columns");
+        for label in labels(self.columns.len()) {
+            write!(csv, ",{}", label).unwrap();
+        }
+        csv.push('\n');
+
+        for (label, row) in labels(self.rows.len()).iter().zip(&self.probabilities) {
+            csv.push_str(label);
+            for probability in row {
+                write!(csv, ",{}", probability).unwrap();
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Returns the table in JSON, with the qubits and the labels of the rows and
+    /// columns.
+    pub fn to_json(&self) -> String {
+        let list = |items: Vec<String>| format!("[{}]", items.join(","));
+        let quoted = |labels: Vec<String>| {
+            list(
+                labels
+                    .into_iter()
+                    .map(|label| format!("\"{}\"", label))
+                    .collect(),
+            )
+        };
+
+        format!(
+            "{{\"rows\":{},\"columns\":{},\"row_labels\":{},\"column_labels\":{},\"probabilities\":{}}}",
+            list(self.rows.iter().map(usize::to_string).collect()),
+            list(self.columns.iter().map(usize::to_string).collect()),
+            quoted(labels(self.rows.len())),
+            quoted(labels(self.columns.len())),
+            list(
+                self.probabilities
+                    .iter()
+                    .map(|row| list(row.iter().map(f64::to_string).collect()))
+                    .collect()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{dense::Dense, quantum_execution::QubitManager, sparse::Sparse};
+    use ket::{LiveExecution, QuantumGate};
+
+    #[test]
+    fn bell_state_heatmap() -> Result<(), Box<dyn std::error::Error>> {
+        let mut dense = QubitManager::<Dense>::new(3)?;
+        let mut sparse = QubitManager::<Sparse>::new(3)?;
+        let managers: [&mut dyn LiveExecution; 2] = [&mut dense, &mut sparse];
+        for manager in managers {
+            (0..3).for_each(|qubit| manager.alloc(qubit));
+            manager.gate(&QuantumGate::Hadamard, 0, &[]);
+            manager.gate(&QuantumGate::PauliX, 2, &[0]);
+            manager.gate(&QuantumGate::PauliX, 1, &[]);
+        }
+
+        let heatmap = dense.heatmap(&[0, 1], &[2]);
+        let expected = sparse.heatmap(&[0, 1], &[2]);
+        for (row, expected) in heatmap.probabilities.iter().zip(&expected.probabilities) {
+            for (probability, expected) in row.iter().zip(expected) {
+                assert!((probability - expected).abs() < 1e-12);
+            }
+        }
+        let probability = |row: usize, column: usize| heatmap.probabilities[row][column];
+        assert!((probability(0b01, 0) - 0.5).abs() < 1e-12);
+        assert!((probability(0b11, 1) - 0.5).abs() < 1e-12);
+        assert!(probability(0b00, 0) < 1e-12);
+
+        let csv = heatmap.to_csv();
+        assert_eq!(csv.lines().next(), Some("rows\\columns,0,1"));
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.lines().nth(2).unwrap().starts_with("01,0.5"));
+
+        let json = heatmap.to_json();
+        assert!(json.starts_with(
+            "{\"rows\":[0,1],\"columns\":[2],\"row_labels\":[\"00\",\"01\",\"10\",\"11\"]"
+        ));
+
+        Ok(())
+    }
+}
@@ -3,10 +3,27 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(feature = "bench")]
+pub mod bench;
 pub mod bitwise;
 pub mod c_api;
 pub mod convert;
+pub mod cutting;
 pub mod dense;
+pub mod density;
 pub mod error;
+pub mod fusion;
+pub mod half_precision;
+pub mod heatmap;
+pub mod noise;
+mod parallel;
+pub mod partitioned;
+pub mod profile;
 pub mod quantum_execution;
+mod sorted;
 pub mod sparse;
+pub mod summation;
+pub mod threads;
+pub mod tomography;
+pub mod trajectories;
+pub mod unitary;
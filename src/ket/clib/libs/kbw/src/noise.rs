@@ -0,0 +1,211 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Noise model of the simulators.
+
+use num::{complex::Complex64, One, Zero};
+
+use crate::error::{KBWError, Result};
+
+/// Single-qubit 2x2 matrix.
+pub type Matrix2 = [[Complex64; 2]; 2];
+
+/// Single-qutrit 3x3 matrix, on the levels `|0>`, `|1>`, and `|2>`.
+pub type Matrix3 = [[Complex64; 3]; 3];
+
+/// Single-qubit quantum channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    /// Replaces the qubit state with the maximally mixed state with probability `p`.
+    Depolarizing(f64),
+    /// Applies a Pauli X with probability `p`.
+    BitFlip(f64),
+    /// Applies a Pauli Z with probability `p`.
+    PhaseFlip(f64),
+    /// Decays `|1>` into `|0>` with probability `gamma`.
+    AmplitudeDamping(f64),
+}
+
+impl Channel {
+    fn probability(&self) -> f64 {
+        match self {
+            Channel::Depolarizing(p)
+            | Channel::BitFlip(p)
+            | Channel::PhaseFlip(p)
+            | Channel::AmplitudeDamping(p) => *p,
+        }
+    }
+
+    /// Returns the Kraus operators of the channel.
+    pub fn kraus(&self) -> Vec<Matrix2> {
+        let (zero, one, i) = (Complex64::zero(), Complex64::one(), Complex64::i());
+        let scaled = |matrix: Matrix2, factor: f64| matrix.map(|row| row.map(|x| x * factor));
+        let identity = [[one, zero], [zero, one]];
+        let x = [[zero, one], [one, zero]];
+        let y = [[zero, -i], [i, zero]];
+        let z = [[one, zero], [zero, -one]];
+
+        match *self {
+            Channel::Depolarizing(p) => vec![
+                scaled(identity, (1.0 - 3.0 * p / 4.0).sqrt()),
+                scaled(x, (p / 4.0).sqrt()),
+                scaled(y, (p / 4.0).sqrt()),
+                scaled(z, (p / 4.0).sqrt()),
+            ],
+            Channel::BitFlip(p) => vec![scaled(identity, (1.0 - p).sqrt()), scaled(x, p.sqrt())],
+            Channel::PhaseFlip(p) => {
+                vec![scaled(identity, (1.0 - p).sqrt()), scaled(z, p.sqrt())]
+            }
+            Channel::AmplitudeDamping(gamma) => vec![
+                [[one, zero], [zero, Complex64::from((1.0 - gamma).sqrt())]],
+                [[zero, Complex64::from(gamma.sqrt())], [zero, zero]],
+            ],
+        }
+    }
+}
+
+/// Leakage of a qubit out of its computational subspace, into a third level `|2>`, as
+/// in a transmon.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Leakage {
+    /// Probability that `|1>` leaks into `|2>`.
+    pub probability: f64,
+
+    /// Probability that `|2>` returns to `|1>`.
+    pub seepage: f64,
+}
+
+impl Leakage {
+    /// Returns the Kraus operators of the leakage on the three levels.
+    pub fn kraus(&self) -> Vec<Matrix3> {
+        let (zero, one) = (Complex64::zero(), Complex64::one());
+        let keep = [
+            [one, zero, zero],
+            [zero, Complex64::from((1.0 - self.probability).sqrt()), zero],
+            [zero, zero, Complex64::from((1.0 - self.seepage).sqrt())],
+        ];
+        let mut leak = [[zero; 3]; 3];
+        leak[2][1] = Complex64::from(self.probability.sqrt());
+        let mut seep = [[zero; 3]; 3];
+        seep[1][2] = Complex64::from(self.seepage.sqrt());
+        vec![keep, leak, seep]
+    }
+}
+
+/// Noise added by the simulators to an ideal circuit.
+///
+/// The default model is noiseless.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NoiseModel {
+    /// Channel applied to the target and to every control qubit after each gate.
+    pub gate: Option<Channel>,
+
+    /// Leakage of the target and of every control qubit after each gate.
+    ///
+    /// Only the qubits with a third level in the
+    /// [`DensityMatrix`](crate::density::DensityMatrix) leak, the other simulators
+    /// ignore it.
+    pub leakage: Option<Leakage>,
+}
+
+impl NoiseModel {
+    /// Noise model that applies `channel` after each gate.
+    pub fn after_gates(channel: Channel) -> Self {
+        Self {
+            gate: Some(channel),
+            leakage: None,
+        }
+    }
+
+    /// Returns the model with the leakage after each gate.
+    pub fn with_leakage(self, leakage: Leakage) -> Self {
+        Self {
+            leakage: Some(leakage),
+            ..self
+        }
+    }
+
+    /// Returns `true` if the model adds no noise.
+    pub fn is_noiseless(&self) -> bool {
+        self.gate.is_none() && self.leakage.is_none()
+    }
+
+    /// Checks that every probability of the model is in `[0, 1]`.
+    pub fn validate(&self) -> Result<()> {
+        let probabilities = self.gate.iter().map(Channel::probability).chain(
+            self.leakage
+                .iter()
+                .flat_map(|leakage| [leakage.probability, leakage.seepage]),
+        );
+        for probability in probabilities {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(KBWError::InvalidNoiseModel);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Channel, Leakage, NoiseModel};
+    use num::{complex::Complex64, Zero};
+
+    #[test]
+    fn kraus_operators_are_complete() {
+        for channel in [
+            Channel::Depolarizing(0.3),
+            Channel::BitFlip(0.2),
+            Channel::PhaseFlip(0.1),
+            Channel::AmplitudeDamping(0.4),
+        ] {
+            // sum_k K^dagger K = I
+            let mut sum = [[Complex64::zero(); 2]; 2];
+            for kraus in channel.kraus() {
+                for (row, sum_row) in sum.iter_mut().enumerate() {
+                    for (column, sum) in sum_row.iter_mut().enumerate() {
+                        *sum += (0..2)
+                            .map(|k| kraus[k][row].conj() * kraus[k][column])
+                            .sum::<Complex64>();
+                    }
+                }
+            }
+            for (row, sum_row) in sum.iter().enumerate() {
+                for (column, sum) in sum_row.iter().enumerate() {
+                    let expected = if row == column { 1.0 } else { 0.0 };
+                    assert!((sum - expected).norm() < 1e-12);
+                }
+            }
+        }
+
+        // sum_k K^dagger K = I on the three levels of the leakage.
+        let leakage = Leakage {
+            probability: 0.3,
+            seepage: 0.1,
+        };
+        for row in 0..3 {
+            for column in 0..3 {
+                let sum: Complex64 = leakage
+                    .kraus()
+                    .iter()
+                    .flat_map(|kraus| (0..3).map(move |k| kraus[k][row].conj() * kraus[k][column]))
+                    .sum();
+                let expected = if row == column { 1.0 } else { 0.0 };
+                assert!((sum - expected).norm() < 1e-12);
+            }
+        }
+
+        assert!(NoiseModel::after_gates(Channel::BitFlip(1.5))
+            .validate()
+            .is_err());
+        assert!(NoiseModel::default()
+            .with_leakage(Leakage {
+                probability: -0.1,
+                seepage: 0.0,
+            })
+            .validate()
+            .is_err());
+    }
+}
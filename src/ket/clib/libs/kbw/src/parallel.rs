@@ -0,0 +1,184 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Parallel iterators of the simulators.
+//!
+//! With the `parallel` feature, this module re-exports the `rayon` prelude and thread
+//! pool. Without it, the simulators build without `rayon`, as for WebAssembly targets,
+//! and run on the calling thread: `par_iter` and the other methods of the prelude return
+//! sequential iterators with the interface of the `rayon` iterators used in this crate.
+
+#[cfg(feature = "parallel")]
+pub(crate) use rayon::{prelude::*, ThreadPool};
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) use sequential::*;
+
+#[cfg(not(feature = "parallel"))]
+mod sequential {
+    use std::iter::{once, Enumerate, Filter, FlatMap, Map, Once, Sum, Zip};
+
+    /// Thread pool that cannot be built, as there are no threads without the `parallel`
+    /// feature.
+    pub enum ThreadPool {}
+
+    impl ThreadPool {
+        pub fn install<R>(&self, _op: impl FnOnce() -> R) -> R {
+            match *self {}
+        }
+    }
+
+    /// Iterator with the interface of a `rayon` parallel iterator, run in order.
+    pub struct Sequential<I>(I);
+
+    impl<I: Iterator> IntoIterator for Sequential<I> {
+        type Item = I::Item;
+        type IntoIter = I;
+
+        fn into_iter(self) -> I {
+            self.0
+        }
+    }
+
+    pub trait ParallelIterator: IntoIterator + Sized {
+        fn map<F, R>(self, f: F) -> Sequential<Map<Self::IntoIter, F>>
+        where
+            F: FnMut(Self::Item) -> R,
+        {
+            Sequential(self.into_iter().map(f))
+        }
+
+        fn filter<P>(self, predicate: P) -> Sequential<Filter<Self::IntoIter, P>>
+        where
+            P: FnMut(&Self::Item) -> bool,
+        {
+            Sequential(self.into_iter().filter(predicate))
+        }
+
+        fn flat_map<F, U>(self, f: F) -> Sequential<FlatMap<Self::IntoIter, U, F>>
+        where
+            F: FnMut(Self::Item) -> U,
+            U: IntoIterator,
+        {
+            Sequential(self.into_iter().flat_map(f))
+        }
+
+        fn enumerate(self) -> Sequential<Enumerate<Self::IntoIter>> {
+            Sequential(self.into_iter().enumerate())
+        }
+
+        fn zip<Z: IntoIterator>(self, other: Z) -> Sequential<Zip<Self::IntoIter, Z::IntoIter>> {
+            Sequential(self.into_iter().zip(other))
+        }
+
+        fn for_each<F: FnMut(Self::Item)>(self, f: F) {
+            self.into_iter().for_each(f)
+        }
+
+        fn sum<S: Sum<Self::Item>>(self) -> S {
+            self.into_iter().sum()
+        }
+
+        fn reduce<ID, OP>(self, identity: ID, op: OP) -> Self::Item
+        where
+            ID: Fn() -> Self::Item,
+            OP: Fn(Self::Item, Self::Item) -> Self::Item,
+        {
+            self.into_iter().fold(identity(), op)
+        }
+
+        fn try_fold<T, E, ID, F>(self, identity: ID, fold_op: F) -> Sequential<Once<Result<T, E>>>
+        where
+            ID: Fn() -> T,
+            F: FnMut(T, Self::Item) -> Result<T, E>,
+        {
+            Sequential(once(self.into_iter().try_fold(identity(), fold_op)))
+        }
+
+        fn try_reduce<T, E, ID, OP>(self, identity: ID, op: OP) -> Result<T, E>
+        where
+            Self: IntoIterator<Item = Result<T, E>>,
+            ID: Fn() -> T,
+            OP: Fn(T, T) -> Result<T, E>,
+        {
+            self.into_iter()
+                .try_fold(identity(), |sum, item| op(sum, item?))
+        }
+
+        fn collect<C: FromIterator<Self::Item>>(self) -> C {
+            self.into_iter().collect()
+        }
+    }
+
+    impl<I: Iterator> ParallelIterator for Sequential<I> {}
+
+    pub trait IntoParallelIterator {
+        type Iter: ParallelIterator;
+
+        fn into_par_iter(self) -> Self::Iter;
+    }
+
+    impl<T: IntoIterator> IntoParallelIterator for T {
+        type Iter = Sequential<T::IntoIter>;
+
+        fn into_par_iter(self) -> Self::Iter {
+            Sequential(self.into_iter())
+        }
+    }
+
+    pub trait IntoParallelRefIterator<'data> {
+        type Iter: ParallelIterator;
+
+        fn par_iter(&'data self) -> Self::Iter;
+    }
+
+    impl<'data, T: 'data + ?Sized> IntoParallelRefIterator<'data> for T
+    where
+        &'data T: IntoIterator,
+    {
+        type Iter = Sequential<<&'data T as IntoIterator>::IntoIter>;
+
+        fn par_iter(&'data self) -> Self::Iter {
+            Sequential(self.into_iter())
+        }
+    }
+
+    pub trait IntoParallelRefMutIterator<'data> {
+        type Iter: ParallelIterator;
+
+        fn par_iter_mut(&'data mut self) -> Self::Iter;
+    }
+
+    impl<'data, T: 'data + ?Sized> IntoParallelRefMutIterator<'data> for T
+    where
+        &'data mut T: IntoIterator,
+    {
+        type Iter = Sequential<<&'data mut T as IntoIterator>::IntoIter>;
+
+        fn par_iter_mut(&'data mut self) -> Self::Iter {
+            Sequential(self.into_iter())
+        }
+    }
+
+    pub trait ParallelSlice<T> {
+        fn par_chunks(&self, size: usize) -> Sequential<std::slice::Chunks<'_, T>>;
+    }
+
+    impl<T> ParallelSlice<T> for [T] {
+        fn par_chunks(&self, size: usize) -> Sequential<std::slice::Chunks<'_, T>> {
+            Sequential(self.chunks(size))
+        }
+    }
+
+    pub trait ParallelSliceMut<T> {
+        fn par_chunks_mut(&mut self, size: usize) -> Sequential<std::slice::ChunksMut<'_, T>>;
+    }
+
+    impl<T> ParallelSliceMut<T> for [T] {
+        fn par_chunks_mut(&mut self, size: usize) -> Sequential<std::slice::ChunksMut<'_, T>> {
+            Sequential(self.chunks_mut(size))
+        }
+    }
+}
@@ -0,0 +1,424 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! State vector partitioned by its high-order qubits.
+//!
+//! The state of `n` qubits is split in `2^g` partitions of `2^(n-g)` amplitudes, one per
+//! value of the `g` global qubits, the highest ones. Each partition is a separate buffer,
+//! updated by its own task as a device would, so the state can exceed the memory of a
+//! single device. A gate on a local qubit pairs amplitudes within each partition. A gate
+//! on a global qubit pairs the amplitudes at the same position of two partitions, which
+//! is an explicit exchange between them, unless the gate is diagonal, in which case each
+//! partition is only scaled. The number of exchanged amplitudes is reported by
+//! [`Partitioned::exchanged_amplitudes`].
+
+use crate::bitwise::*;
+use crate::error::{KBWError, Result};
+use crate::noise::Matrix2;
+use crate::parallel::*;
+use crate::quantum_execution::{choose_outcome, QuantumExecution};
+use crate::summation::CompensatedSum;
+use itertools::Itertools;
+use log::error;
+use num::{complex::Complex64, One, Zero};
+use rand::prelude::*;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+/// Largest number of qubits of a partition, as in [`crate::dense::Dense`].
+pub const MAX_LOCAL_QUBITS: usize = 32;
+
+/// Largest number of global qubits.
+pub const MAX_GLOBAL_QUBITS: usize = 4;
+
+#[derive(Clone)]
+pub struct Partitioned {
+    partitions: Vec<Vec<Complex64>>,
+    local_qubits: usize,
+    exchanged: u64,
+}
+
+impl Partitioned {
+    /// Creates a simulator with `partitions` partitions, a power of two.
+    ///
+    /// [`QuantumExecution::new`] uses the value of the `KBW_PARTITIONS` environment
+    /// variable, or the fewest partitions with at most [`MAX_LOCAL_QUBITS`] qubits each.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KBWError::UnsupportedNumberOfQubits`] if `partitions` is not a power of
+    /// two, there are more partitions than states, or a partition has more than
+    /// [`MAX_LOCAL_QUBITS`] qubits, and the number of partitions is more than
+    /// `2^MAX_GLOBAL_QUBITS`.
+    pub fn with_partitions(num_qubits: usize, partitions: usize) -> Result<Self> {
+        let global_qubits = partitions.trailing_zeros() as usize;
+        if !partitions.is_power_of_two()
+            || global_qubits > num_qubits.min(MAX_GLOBAL_QUBITS)
+            || num_qubits - global_qubits > MAX_LOCAL_QUBITS
+        {
+            error!(
+                "partitioned implementation supports up to {} qubits in {} partitions",
+                MAX_LOCAL_QUBITS + MAX_GLOBAL_QUBITS,
+                1 << MAX_GLOBAL_QUBITS
+            );
+            return Err(KBWError::UnsupportedNumberOfQubits);
+        }
+
+        let local_qubits = num_qubits - global_qubits;
+        let mut partitions: Vec<Vec<Complex64>> = (0..partitions)
+            .into_par_iter()
+            .map(|_| vec![Complex64::zero(); 1 << local_qubits])
+            .collect();
+        partitions[0][0] = Complex64::one();
+
+        Ok(Self {
+            partitions,
+            local_qubits,
+            exchanged: 0,
+        })
+    }
+
+    /// Returns the number of partitions.
+    pub fn num_partitions(&self) -> usize {
+        self.partitions.len()
+    }
+
+    /// Returns the number of amplitudes sent between partitions so far.
+    pub fn exchanged_amplitudes(&self) -> u64 {
+        self.exchanged
+    }
+
+    /// Applies a 2x2 matrix on the target qubit, if the control qubits are set.
+    fn apply(&mut self, matrix: Matrix2, target: usize, control: &[usize]) {
+        let local_qubits = self.local_qubits;
+        let local_mask: usize = ctrl_mask(
+            &control
+                .iter()
+                .copied()
+                .filter(|qubit| *qubit < local_qubits)
+                .collect_vec(),
+        );
+        let global_mask: usize = ctrl_mask(
+            &control
+                .iter()
+                .filter(|qubit| **qubit >= local_qubits)
+                .map(|qubit| qubit - local_qubits)
+                .collect_vec(),
+        );
+        let diagonal = matrix[0][1].is_zero() && matrix[1][0].is_zero();
+
+        if target < local_qubits {
+            let half = 1 << target;
+            self.partitions
+                .par_iter_mut()
+                .enumerate()
+                .filter(|(partition, _)| ctrl_check_mask(*partition, global_mask))
+                .for_each(|(_, amplitudes)| {
+                    amplitudes
+                        .chunks_mut(2 * half)
+                        .enumerate()
+                        .for_each(|(chunk, amplitudes)| {
+                            let (zeros, ones) = amplitudes.split_at_mut(half);
+                            for (index, (amp_0, amp_1)) in zeros.iter_mut().zip(ones).enumerate() {
+                                if ctrl_check_mask(chunk * 2 * half + index, local_mask) {
+                                    (*amp_0, *amp_1) = (
+                                        matrix[0][0] * *amp_0 + matrix[0][1] * *amp_1,
+                                        matrix[1][0] * *amp_0 + matrix[1][1] * *amp_1,
+                                    );
+                                }
+                            }
+                        });
+                });
+            return;
+        }
+
+        let global = target - local_qubits;
+        if diagonal {
+            self.partitions
+                .par_iter_mut()
+                .enumerate()
+                .filter(|(partition, _)| ctrl_check_mask(*partition, global_mask))
+                .for_each(|(partition, amplitudes)| {
+                    let entry = matrix[is_one_at(partition, global) as usize]
+                        [is_one_at(partition, global) as usize];
+                    amplitudes
+                        .iter_mut()
+                        .enumerate()
+                        .filter(|(index, _)| ctrl_check_mask(*index, local_mask))
+                        .for_each(|(_, amp)| *amp *= entry);
+                });
+            return;
+        }
+
+        // Each pair of partitions that differ in the global target exchanges the
+        // amplitudes that satisfy the control qubits.
+        let half = 1 << global;
+        let pairs = self
+            .partitions
+            .chunks_mut(2 * half)
+            .enumerate()
+            .flat_map(|(chunk, partitions)| {
+                let (zeros, ones) = partitions.split_at_mut(half);
+                zeros
+                    .iter_mut()
+                    .zip(ones)
+                    .enumerate()
+                    .map(move |(index, pair)| (chunk * 2 * half + index, pair))
+            })
+            .filter(|(partition, _)| ctrl_check_mask(*partition, global_mask))
+            .collect_vec();
+        let exchanged: usize = pairs
+            .into_par_iter()
+            .map(|(_, (zeros, ones))| {
+                let mut exchanged = 0;
+                for (index, (amp_0, amp_1)) in zeros.iter_mut().zip(ones.iter_mut()).enumerate() {
+                    if ctrl_check_mask(index, local_mask) {
+                        (*amp_0, *amp_1) = (
+                            matrix[0][0] * *amp_0 + matrix[0][1] * *amp_1,
+                            matrix[1][0] * *amp_0 + matrix[1][1] * *amp_1,
+                        );
+                        exchanged += 2;
+                    }
+                }
+                exchanged
+            })
+            .sum();
+        self.exchanged += exchanged as u64;
+    }
+
+    /// Returns the amplitude of each basis state, in parallel over the partitions.
+    fn amplitudes(&self) -> impl ParallelIterator<Item = (usize, &Complex64)> {
+        let local_qubits = self.local_qubits;
+        self.partitions
+            .par_iter()
+            .enumerate()
+            .flat_map(move |(partition, amplitudes)| {
+                amplitudes
+                    .par_iter()
+                    .enumerate()
+                    .map(move |(index, amp)| ((partition << local_qubits) | index, amp))
+            })
+    }
+}
+
+impl QuantumExecution for Partitioned {
+    fn new(num_qubits: usize) -> Result<Self> {
+        let partitions = std::env::var("KBW_PARTITIONS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1 << num_qubits.saturating_sub(MAX_LOCAL_QUBITS));
+
+        Partitioned::with_partitions(num_qubits, partitions)
+    }
+
+    fn snapshot(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn pauli_x(&mut self, target: usize, control: &[usize]) {
+        let (zero, one) = (Complex64::zero(), Complex64::one());
+        self.apply([[zero, one], [one, zero]], target, control);
+    }
+
+    fn pauli_y(&mut self, target: usize, control: &[usize]) {
+        let zero = Complex64::zero();
+        self.apply(
+            [[zero, -Complex64::i()], [Complex64::i(), zero]],
+            target,
+            control,
+        );
+    }
+
+    fn pauli_z(&mut self, target: usize, control: &[usize]) {
+        let (zero, one) = (Complex64::zero(), Complex64::one());
+        self.apply([[one, zero], [zero, -one]], target, control);
+    }
+
+    fn hadamard(&mut self, target: usize, control: &[usize]) {
+        let h = Complex64::from(FRAC_1_SQRT_2);
+        self.apply([[h, h], [h, -h]], target, control);
+    }
+
+    fn phase(&mut self, lambda: f64, target: usize, control: &[usize]) {
+        let (zero, one) = (Complex64::zero(), Complex64::one());
+        let phase = Complex64::exp(lambda * Complex64::i());
+        self.apply([[one, zero], [zero, phase]], target, control);
+    }
+
+    fn rx(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let cos = Complex64::from(f64::cos(theta / 2.0));
+        let sin = -Complex64::i() * f64::sin(theta / 2.0);
+        self.apply([[cos, sin], [sin, cos]], target, control);
+    }
+
+    fn ry(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let cos = Complex64::from(f64::cos(theta / 2.0));
+        let sin = Complex64::from(f64::sin(theta / 2.0));
+        self.apply([[cos, -sin], [sin, cos]], target, control);
+    }
+
+    fn rz(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let zero = Complex64::zero();
+        let phase_0 = Complex64::exp(-theta / 2.0 * Complex64::i());
+        let phase_1 = Complex64::exp(theta / 2.0 * Complex64::i());
+        self.apply([[phase_0, zero], [zero, phase_1]], target, control);
+    }
+
+    fn measure<R: Rng>(&mut self, target: usize, forced: Option<bool>, rng: &mut R) -> bool {
+        let p1 = self
+            .amplitudes()
+            .filter(|(state, _)| is_one_at(*state, target))
+            .map(|(_, amp)| amp.norm_sqr())
+            .sum::<CompensatedSum>()
+            .value();
+        let p0 = (1.0 - p1).max(0.0);
+
+        let result = choose_outcome(p0, p1, forced, rng);
+        let p = 1.0 / f64::sqrt(if result { p1 } else { p0 });
+
+        let local_qubits = self.local_qubits;
+        self.partitions
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(partition, amplitudes)| {
+                for (index, amp) in amplitudes.iter_mut().enumerate() {
+                    let state = (partition << local_qubits) | index;
+                    *amp = if is_one_at(state, target) == result {
+                        *amp * p
+                    } else {
+                        Complex64::zero()
+                    };
+                }
+            });
+
+        result
+    }
+
+    fn dump(&mut self, qubits: &[usize]) -> ket::DumpData {
+        let (basis_states, amplitudes_real, amplitudes_imag): (Vec<_>, Vec<_>, Vec<_>) = self
+            .amplitudes()
+            .filter(|(_, amp)| amp.norm() > 1e-15)
+            .map(|(state, amp)| {
+                (
+                    Vec::from([extract_bits(state, qubits) as u64]),
+                    amp.re,
+                    amp.im,
+                )
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .multiunzip();
+
+        ket::DumpData {
+            basis_states,
+            amplitudes_real,
+            amplitudes_imag,
+        }
+    }
+
+    fn apply_matrix(&mut self, matrix: Matrix2, target: usize) {
+        self.apply(matrix, target, &[]);
+    }
+
+    fn reduced_density_matrix(&self, target: usize) -> Matrix2 {
+        let num_states = self.partitions.len() << self.local_qubits;
+        let amplitude = |state: usize| {
+            self.partitions[state >> self.local_qubits][state & ((1 << self.local_qubits) - 1)]
+        };
+
+        let (rho_00, rho_11, rho_01) = (0..num_states)
+            .into_par_iter()
+            .filter(|state| !is_one_at(*state, target))
+            .map(|state| {
+                let (amp_0, amp_1) = (amplitude(state), amplitude(bit_flip(state, target)));
+                (amp_0.norm_sqr(), amp_1.norm_sqr(), amp_0 * amp_1.conj())
+            })
+            .reduce(
+                || (0.0, 0.0, Complex64::zero()),
+                |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2),
+            );
+
+        [[rho_00.into(), rho_01], [rho_01.conj(), rho_11.into()]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Partitioned;
+    use crate::{dense::Dense, quantum_execution::QuantumExecution};
+    use num::complex::Complex64;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn amplitudes(data: ket::DumpData) -> Vec<Complex64> {
+        let mut state = vec![Complex64::new(0.0, 0.0); 32];
+        for ((basis, re), im) in data
+            .basis_states
+            .iter()
+            .zip(data.amplitudes_real)
+            .zip(data.amplitudes_imag)
+        {
+            state[basis[0] as usize] = Complex64::new(re, im);
+        }
+        state
+    }
+
+    #[test]
+    fn partitions_match_dense() {
+        let mut dense = Dense::new(5).unwrap();
+        let mut partitioned = Partitioned::with_partitions(5, 4).unwrap();
+        assert_eq!(partitioned.num_partitions(), 4);
+
+        for simulator in [&mut dense as &mut dyn Gates, &mut partitioned] {
+            simulator.circuit();
+        }
+        let qubits = [0, 1, 2, 3, 4];
+        let expected = amplitudes(dense.dump(&qubits));
+        let result = amplitudes(partitioned.dump(&qubits));
+        for (a, b) in expected.iter().zip(&result) {
+            assert!((a - b).norm() < 1e-12);
+        }
+        assert!(partitioned.exchanged_amplitudes() > 0);
+
+        for target in [1, 4] {
+            let (a, b) = (
+                dense.reduced_density_matrix(target),
+                partitioned.reduced_density_matrix(target),
+            );
+            assert!((0..2).all(|i| (0..2).all(|j| (a[i][j] - b[i][j]).norm() < 1e-12)));
+        }
+
+        // Diagonal gates on a global qubit do not exchange amplitudes.
+        let exchanged = partitioned.exchanged_amplitudes();
+        partitioned.rz(0.3, 4, &[3]);
+        partitioned.pauli_z(3, &[0]);
+        assert_eq!(partitioned.exchanged_amplitudes(), exchanged);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let outcome = partitioned.measure(4, None, &mut rng);
+        assert_eq!(partitioned.measure(4, None, &mut rng), outcome);
+
+        assert!(Partitioned::with_partitions(5, 3).is_err());
+        assert!(Partitioned::with_partitions(2, 8).is_err());
+    }
+
+    trait Gates {
+        fn circuit(&mut self);
+    }
+
+    impl<S: QuantumExecution> Gates for S {
+        fn circuit(&mut self) {
+            for qubit in 0..5 {
+                self.hadamard(qubit, &[]);
+                self.ry(0.2 + 0.3 * qubit as f64, qubit, &[]);
+            }
+            self.pauli_x(4, &[0]);
+            self.pauli_y(1, &[4, 3]);
+            self.rx(0.4, 3, &[1]);
+            self.phase(0.9, 4, &[2]);
+            self.hadamard(0, &[3]);
+            self.rz(1.1, 2, &[4]);
+            self.ry(0.5, 4, &[1, 2]);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wall time of the instructions of batch executions.
+//!
+//! The cost of a gate on a state vector depends on the stride of its target qubit, so
+//! a single gate on a high-order qubit, repeated in a loop, can dominate the runtime.
+//! A [`Profile`] aggregates the time of each instruction by its name and the simulator
+//! qubit of its target, and writes it in the folded format of
+//! [flamegraph](https://github.com/brendangregg/FlameGraph), one `name;qubit
+//! nanoseconds` line per entry:
+//!
+//! ```text
+//! cx;q0 1520
+//! h;q3 830411
+//! measure 2210
+//! ```
+//!
+//! Gates are named as in OpenQASM, with a `c` for each control qubit. Instructions
+//! without a single target, like measurements and fused subcircuits, have no qubit
+//! frame.
+//!
+//! [`QubitManager`](crate::quantum_execution::QubitManager) profiles the batch
+//! executions after [`QubitManager::enable_profile`](crate::quantum_execution::QubitManager::enable_profile),
+//! or when the `KBW_PROFILE` environment variable is set, in which case the folded
+//! profile is written to the file it names after each execution.
+
+use std::{collections::BTreeMap, fmt::Write, path::Path, time::Duration};
+
+use ket::QuantumGate;
+
+/// Time spent in the instructions with the same name and target qubit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// Number of instructions.
+    pub count: u64,
+    /// Total wall time.
+    pub time: Duration,
+}
+
+/// Wall time of the executed instructions, by name and target qubit.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    entries: BTreeMap<(String, Option<usize>), Entry>,
+}
+
+/// Returns the OpenQASM name of a gate with `controls` control qubits.
+pub(crate) fn gate_name(gate: &QuantumGate, controls: usize) -> String {
+    let name = match gate {
+        QuantumGate::PauliX => "x",
+        QuantumGate::PauliY => "y",
+        QuantumGate::PauliZ => "z",
+        QuantumGate::RotationX(_) => "rx",
+        QuantumGate::RotationY(_) => "ry",
+        QuantumGate::RotationZ(_) => "rz",
+        QuantumGate::Phase(_) => "p",
+        QuantumGate::Hadamard => "h",
+    };
+    format!("{}{}", "c".repeat(controls), name)
+}
+
+impl Profile {
+    /// Adds the time of an instruction.
+    pub fn record(&mut self, name: impl Into<String>, qubit: Option<usize>, time: Duration) {
+        let entry = self.entries.entry((name.into(), qubit)).or_default();
+        entry.count += 1;
+        entry.time += time;
+    }
+
+    /// Returns the entries by name and target qubit.
+    pub fn entries(&self) -> &BTreeMap<(String, Option<usize>), Entry> {
+        &self.entries
+    }
+
+    /// Returns the time of the instructions, by name.
+    pub fn by_instruction(&self) -> BTreeMap<&str, Entry> {
+        let mut result = BTreeMap::<&str, Entry>::new();
+        for ((name, _), entry) in &self.entries {
+            let total = result.entry(name.as_str()).or_default();
+            total.count += entry.count;
+            total.time += entry.time;
+        }
+        result
+    }
+
+    /// Returns the time of the instructions with a target qubit, by qubit.
+    pub fn by_qubit(&self) -> BTreeMap<usize, Entry> {
+        let mut result = BTreeMap::<usize, Entry>::new();
+        for ((_, qubit), entry) in &self.entries {
+            if let Some(qubit) = qubit {
+                let total = result.entry(*qubit).or_default();
+                total.count += entry.count;
+                total.time += entry.time;
+            }
+        }
+        result
+    }
+
+    /// Returns the total time of the instructions.
+    pub fn total(&self) -> Duration {
+        self.entries.values().map(|entry| entry.time).sum()
+    }
+
+    /// Returns the profile in the folded format, with the time in nanoseconds.
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        for ((name, qubit), entry) in &self.entries {
+            match qubit {
+                Some(qubit) => write!(folded, "{};q{}", name, qubit),
+                None => write!(folded, "{}", name),
+            }
+            .unwrap();
+            writeln!(folded, " {}", entry.time.as_nanos()).unwrap();
+        }
+        folded
+    }
+
+    /// Writes the profile in the folded format to a file.
+    pub fn write_folded(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.folded())
+    }
+
+    /// Removes the entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{dense::Dense, quantum_execution::QubitManager};
+    use ket::{BatchExecution, Instruction, QuantumGate};
+
+    use super::Profile;
+
+    #[test]
+    fn profile_by_gate_and_qubit() -> Result<(), Box<dyn std::error::Error>> {
+        let gate = |gate, target, control: &[usize]| Instruction::Gate {
+            gate,
+            target,
+            control: control.to_vec(),
+        };
+        let mut instructions: Vec<_> = (0..3).map(|target| Instruction::Alloc { target }).collect();
+        instructions.extend([
+            gate(QuantumGate::Hadamard, 0, &[]),
+            gate(QuantumGate::Hadamard, 0, &[]),
+            gate(QuantumGate::PauliX, 1, &[0]),
+            Instruction::Measure {
+                qubits: vec![0, 1],
+                output: 0,
+            },
+        ]);
+
+        let mut manager = QubitManager::<Dense>::new(3)?;
+        manager.enable_profile();
+        manager.submit_execution(&instructions);
+        let profile = manager.profile().unwrap();
+
+        let by_instruction = profile.by_instruction();
+        assert_eq!(by_instruction["h"].count, 2);
+        assert_eq!(by_instruction["cx"].count, 1);
+        assert_eq!(by_instruction["measure"].count, 1);
+        assert_eq!(by_instruction["alloc"].count, 3);
+        // Qubits are allocated from the top of the simulator stack.
+        assert_eq!(profile.by_qubit()[&2].count, 2);
+        assert_eq!(profile.by_qubit()[&1].count, 1);
+        assert_eq!(
+            profile.total(),
+            by_instruction.values().map(|entry| entry.time).sum()
+        );
+
+        let mut profile = Profile::default();
+        profile.record("h", Some(3), Duration::from_nanos(100));
+        profile.record("h", Some(3), Duration::from_nanos(50));
+        profile.record("measure", None, Duration::from_nanos(7));
+        assert_eq!(profile.folded(), "h;q3 150\nmeasure 7\n");
+
+        Ok(())
+    }
+}
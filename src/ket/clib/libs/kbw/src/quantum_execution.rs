@@ -3,17 +3,39 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Instant,
+};
+
 use itertools::Itertools;
-use ket::{Angle, LiveExecution};
-use log::{debug, info, trace};
-use num::Integer;
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use ket::{
+    state_format::{format_state, StateFormat},
+    Angle, LiveExecution,
+};
+use log::{debug, info, trace, warn};
+use num::{complex::Complex64, Integer};
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
     convert::{from_dump_to_prob, from_prob_to_shots},
+    dense::Dense,
     error::Result,
+    fusion::{FusionCache, Step},
+    half_precision::HalfPrecision,
+    heatmap::Heatmap,
+    noise::Matrix2,
+    parallel::ThreadPool,
+    partitioned::Partitioned,
+    profile::{gate_name, Profile},
+    sparse::Sparse,
+    summation::CompensatedSum,
+    threads::ThreadConfig,
 };
-pub trait QuantumExecution {
+pub trait QuantumExecution: Send {
     fn new(num_qubits: usize) -> Result<Self>
     where
         Self: Sized;
@@ -25,40 +47,252 @@ pub trait QuantumExecution {
     fn rx(&mut self, theta: f64, target: usize, control: &[usize]);
     fn ry(&mut self, theta: f64, target: usize, control: &[usize]);
     fn rz(&mut self, theta: f64, target: usize, control: &[usize]);
-    fn measure<R: Rng>(&mut self, target: usize, rng: &mut R) -> bool;
+    /// Measures the qubit, collapsing it to the `forced` outcome if it is possible, see
+    /// [`choose_outcome`].
+    fn measure<R: Rng>(&mut self, target: usize, forced: Option<bool>, rng: &mut R) -> bool;
     fn dump(&mut self, qubits: &[usize]) -> ket::DumpData;
-    fn debug_state(&self) -> Option<String> {
+    /// Dumps the `k` basis states of largest probability, from the most to the least
+    /// probable. The default selects the states from the whole dump.
+    fn dump_top_k(&mut self, qubits: &[usize], k: usize) -> ket::DumpData {
+        self.dump(qubits).top_k(k)
+    }
+    /// Multiplies the state by a 2x2 matrix on the target qubit, without normalizing
+    /// it, as the Kraus operators of a channel are not unitary.
+    fn apply_matrix(&mut self, matrix: Matrix2, target: usize);
+    /// Returns the density matrix of the target qubit, tracing out the others.
+    fn reduced_density_matrix(&self, target: usize) -> Matrix2;
+    /// Applies a unitary matrix on the target qubits, if the control qubits are set.
+    /// The first target is the most significant bit of the row and column indices.
+    /// Returns `false`, leaving the state unchanged, if the simulator does not support
+    /// multi-qubit matrices.
+    fn apply_unitary(
+        &mut self,
+        _matrix: &[Vec<Complex64>],
+        _target: &[usize],
+        _control: &[usize],
+    ) -> bool {
+        false
+    }
+    /// Multiplies each basis state by `exp(i phases[j])`, where `j` is the value of the
+    /// qubits, the first qubit being the most significant bit. Returns `false`, leaving
+    /// the state unchanged, if the simulator does not support diagonal gates.
+    fn diagonal(&mut self, _phases: &[f64], _qubits: &[usize]) -> bool {
+        false
+    }
+    /// Rotates the target qubit around `axis` by `angles[j]`, where `j` is the value of
+    /// the control qubits, the first control being the most significant bit. Returns
+    /// `false`, leaving the state unchanged, if the simulator does not support
+    /// multiplexed rotations.
+    fn multiplexor(
+        &mut self,
+        _axis: ket::Pauli,
+        _angles: &[f64],
+        _control: &[usize],
+        _target: usize,
+    ) -> bool {
+        false
+    }
+
+    /// Returns the probability of each value of the `rows` qubits, by row, together
+    /// with each value of the `columns` qubits, by column. The first qubit of each
+    /// subset is the most significant bit of its index.
+    ///
+    /// The default implementation sums the probabilities of a dump of both subsets.
+    fn joint_probabilities(&mut self, rows: &[usize], columns: &[usize]) -> Vec<Vec<f64>> {
+        let dump = self.dump(&[rows, columns].concat());
+        let mask = (1 << columns.len()) - 1;
+        let mut table = vec![vec![0.0; 1 << columns.len()]; 1 << rows.len()];
+        for ((state, real), imag) in dump
+            .basis_states
+            .iter()
+            .zip(&dump.amplitudes_real)
+            .zip(&dump.amplitudes_imag)
+        {
+            let state = ket::basis::to_u64(state).unwrap() as usize;
+            table[state >> columns.len()][state & mask] += real * real + imag * imag;
+        }
+        table
+    }
+
+    /// Returns a copy of the state, to go back to it, or `None` if the simulator cannot
+    /// copy its state.
+    fn snapshot(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
         None
     }
 }
 
+/// Smallest probability of a forced measurement outcome.
+const MIN_FORCED_PROBABILITY: f64 = 1e-12;
+
+/// Returns the outcome of a measurement with the probabilities `p0` and `p1`.
+///
+/// The `forced` outcome is kept if its probability is not negligible; otherwise, the
+/// outcome is drawn with `rng`, and the process reports that it could not be forced.
+pub(crate) fn choose_outcome<R: Rng>(p0: f64, p1: f64, forced: Option<bool>, rng: &mut R) -> bool {
+    match forced {
+        Some(outcome) if (if outcome { p1 } else { p0 }) > MIN_FORCED_PROBABILITY => outcome,
+        _ => WeightedIndex::new([p0, p1]).unwrap().sample(rng) == 1,
+    }
+}
+
+/// Returns `tr(K rho K^dagger)`, the probability of the Kraus operator `K` on `rho`.
+fn kraus_probability(kraus: &Matrix2, rho: &Matrix2) -> f64 {
+    (0..2)
+        .flat_map(|i| (0..2).flat_map(move |j| (0..2).map(move |l| (i, j, l))))
+        .map(|(i, j, l)| (kraus[i][j] * rho[j][l] * kraus[i][l].conj()).re)
+        .sum()
+}
+
 pub struct QubitManager<S: QuantumExecution> {
     simulator: S,
     qubit_stack: Vec<usize>,
     qubit_map: Vec<usize>,
     rng: StdRng,
     result: Option<ket::ir::ResultData>,
+    /// Number of expected values, samples, and dumps returned by the previous chunks,
+    /// see [`ket::BatchExecution::submit_chunk`]. Measurements are kept for the
+    /// conditional gates of the following chunks.
+    chunk_outputs: (usize, usize, usize),
+    forced_measurements: BTreeMap<usize, u64>,
+    pool: Option<Arc<ThreadPool>>,
+    fusion: Option<FusionCache>,
+    allocated: BTreeSet<usize>,
+    state_format: StateFormat,
+    profile: Option<Profile>,
+    /// File where the profile is written after each batch execution.
+    profile_path: Option<PathBuf>,
+    /// Bound of the rounding error of each expected value.
+    exp_value_errors: Vec<f64>,
 }
 
 impl<S: QuantumExecution + 'static> QubitManager<S> {
+    /// Creates a simulator with the thread configuration read from the environment, see
+    /// [`ThreadConfig::from_env`].
     pub fn new(num_qubits: usize) -> Result<Self> {
-        let seed = std::env::var("KBW_SEED")
-            .unwrap_or_default()
-            .parse::<u64>()
-            .unwrap_or_else(|_| rand::random());
+        Self::with_threads(num_qubits, &ThreadConfig::from_env())
+    }
+
+    /// Creates a simulator that runs on the threads described by `threads`.
+    pub fn with_threads(num_qubits: usize, threads: &ThreadConfig) -> Result<Self> {
+        Self::with_options(num_qubits, None, threads)
+    }
+
+    /// Creates a simulator with the given seed and threads.
+    ///
+    /// Without a seed, the `KBW_SEED` environment variable is used, or a random seed if
+    /// it is not set.
+    pub fn with_options(
+        num_qubits: usize,
+        seed: Option<u64>,
+        threads: &ThreadConfig,
+    ) -> Result<Self> {
+        let seed = seed.unwrap_or_else(|| {
+            std::env::var("KBW_SEED")
+                .unwrap_or_default()
+                .parse::<u64>()
+                .unwrap_or_else(|_| rand::random())
+        });
 
         info!("KBW seed={}", seed);
 
+        let pool = threads.build_pool()?;
+        let simulator = match &pool {
+            Some(pool) => pool.install(|| S::new(num_qubits))?,
+            None => S::new(num_qubits)?,
+        };
+
+        let profile_path = std::env::var_os("KBW_PROFILE").map(PathBuf::from);
+
         Ok(QubitManager {
-            simulator: S::new(num_qubits)?,
+            simulator,
             qubit_stack: (0..num_qubits).collect_vec(),
             qubit_map: (0..num_qubits).collect_vec(),
             rng: StdRng::seed_from_u64(seed),
             result: None,
+            chunk_outputs: (0, 0, 0),
+            forced_measurements: BTreeMap::new(),
+            pool,
+            fusion: std::env::var("KBW_FUSION_QUBITS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(FusionCache::new),
+            allocated: BTreeSet::new(),
+            state_format: StateFormat::default(),
+            profile: profile_path.as_ref().map(|_| Profile::default()),
+            profile_path,
+            exp_value_errors: Vec::new(),
         })
     }
 
-    pub fn configuration(num_qubits: usize, live: bool) -> ket::Configuration {
+    /// Sets the format of the state logged at the trace level after each gate and
+    /// measurement, see [`ket::state_format`].
+    pub fn set_state_format(&mut self, format: StateFormat) {
+        self.state_format = format;
+    }
+
+    /// Fuses the repeated subcircuits of up to `max_qubits` qubits in the batch
+    /// executions, see [`crate::fusion`].
+    pub fn fuse_subcircuits(&mut self, max_qubits: usize) {
+        self.fusion = Some(FusionCache::new(max_qubits));
+    }
+
+    /// Returns the matrices of the fused subcircuits, if the fusion is enabled.
+    pub fn fusion_cache(&self) -> Option<&FusionCache> {
+        self.fusion.as_ref()
+    }
+
+    /// Records the wall time of the instructions of the batch executions, see
+    /// [`crate::profile`].
+    pub fn enable_profile(&mut self) {
+        self.profile.get_or_insert_with(Profile::default);
+    }
+
+    /// Returns the profile of the batch executions, if it is enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Returns a bound of the rounding error of each expected value, in the order of
+    /// the results of the last batch execution, or of the live execution.
+    ///
+    /// The probabilities are added with compensated summation, see
+    /// [`crate::summation`], so the bound does not grow with the number of qubits.
+    pub fn exp_value_errors(&self) -> &[f64] {
+        &self.exp_value_errors
+    }
+
+    /// Returns the joint probabilities of two subsets of qubits, see [`crate::heatmap`].
+    pub fn heatmap(&mut self, rows: &[usize], columns: &[usize]) -> Heatmap {
+        let probabilities = self.install(|manager| {
+            let map = |qubits: &[usize]| qubits.iter().map(|x| manager.qubit_map[*x]).collect_vec();
+            let (physical_rows, physical_columns) = (map(rows), map(columns));
+            manager
+                .simulator
+                .joint_probabilities(&physical_rows, &physical_columns)
+        });
+        Heatmap {
+            rows: rows.to_vec(),
+            columns: columns.to_vec(),
+            probabilities,
+        }
+    }
+
+    /// Configuration of the reference mode.
+    ///
+    /// Libket decomposes every gate into X, H, and phase gates before it reaches the
+    /// simulator, so only these three kernels are exercised. Running the same program
+    /// in both modes cross-validates the native kernels of the other gates.
+    pub fn reference_configuration(num_qubits: usize, live: bool) -> ket::Configuration {
+        ket::Configuration {
+            decompose: true,
+            ..Self::configuration(num_qubits, live, false)
+        }
+    }
+
+    pub fn configuration(num_qubits: usize, live: bool, optimize: bool) -> ket::Configuration {
         ket::Configuration {
             allow_measure: true,
             allow_sample: true,
@@ -81,23 +315,203 @@ impl<S: QuantumExecution + 'static> QubitManager<S> {
                 Some(Box::new(Self::new(num_qubits).unwrap()))
             },
             execution_timeout: None,
+            optimize,
+            optimization_cache: None,
+            bit_order: ket::BitOrder::MsbFirst,
+            chunk_size: None,
+            initial_state: None,
+            import_limits: ket::qasmv2::ImportLimits::default(),
         }
     }
 }
 
-impl<S: QuantumExecution> ket::LiveExecution for QubitManager<S> {
+/// KBW simulator used by [`ket::ConfigurationBuilder::backend`].
+///
+/// ```
+/// # use kbw::quantum_execution::Backend;
+/// let configuration = ket::Configuration::builder()
+///     .qubits(2)
+///     .backend(Backend::Dense)
+///     .seed(7)
+///     .live(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// State vector simulator, see [`crate::dense::Dense`].
+    Dense,
+    /// Sparse simulator, see [`crate::sparse::Sparse`].
+    Sparse,
+    /// State vector partitioned by high-order qubits, see
+    /// [`crate::partitioned::Partitioned`].
+    Partitioned,
+    /// State vector stored in half precision, see
+    /// [`crate::half_precision::HalfPrecision`].
+    HalfPrecision,
+}
+
+impl ket::ExecutionFactory for Backend {
+    fn live(
+        &self,
+        num_qubits: usize,
+        seed: Option<u64>,
+    ) -> std::result::Result<Box<dyn ket::LiveExecution>, Box<dyn std::error::Error>> {
+        let threads = ThreadConfig::from_env();
+        Ok(match self {
+            Backend::Dense => Box::new(QubitManager::<Dense>::with_options(
+                num_qubits, seed, &threads,
+            )?),
+            Backend::Sparse => Box::new(QubitManager::<Sparse>::with_options(
+                num_qubits, seed, &threads,
+            )?),
+            Backend::Partitioned => Box::new(QubitManager::<Partitioned>::with_options(
+                num_qubits, seed, &threads,
+            )?),
+            Backend::HalfPrecision => Box::new(QubitManager::<HalfPrecision>::with_options(
+                num_qubits, seed, &threads,
+            )?),
+        })
+    }
+
+    fn batch(
+        &self,
+        num_qubits: usize,
+        seed: Option<u64>,
+    ) -> std::result::Result<Box<dyn ket::BatchExecution>, Box<dyn std::error::Error>> {
+        let threads = ThreadConfig::from_env();
+        Ok(match self {
+            Backend::Dense => Box::new(QubitManager::<Dense>::with_options(
+                num_qubits, seed, &threads,
+            )?),
+            Backend::Sparse => Box::new(QubitManager::<Sparse>::with_options(
+                num_qubits, seed, &threads,
+            )?),
+            Backend::Partitioned => Box::new(QubitManager::<Partitioned>::with_options(
+                num_qubits, seed, &threads,
+            )?),
+            Backend::HalfPrecision => Box::new(QubitManager::<HalfPrecision>::with_options(
+                num_qubits, seed, &threads,
+            )?),
+        })
+    }
+}
+
+impl<S: QuantumExecution + 'static> ket::LiveExecution for QubitManager<S> {
     fn alloc(&mut self, target: usize) {
         let qubit_index = self.qubit_stack.pop().unwrap();
         self.qubit_map[target] = qubit_index;
+        self.allocated.insert(target);
 
         debug!("alloc target={}->{}", target, qubit_index);
     }
 
     fn free(&mut self, target: usize) {
         self.qubit_stack.push(self.qubit_map[target]);
+        self.allocated.remove(&target);
     }
 
     fn gate(&mut self, gate: &ket::QuantumGate, target: usize, control: &[usize]) {
+        self.install(|manager| manager.apply_gate(gate, target, control))
+    }
+
+    fn measure(&mut self, qubits: &[usize]) -> u64 {
+        self.install(|manager| manager.measure_qubits(qubits, None))
+    }
+
+    fn measure_forced(&mut self, qubits: &[usize], outcome: u64) -> u64 {
+        self.install(|manager| manager.measure_qubits(qubits, Some(outcome)))
+    }
+
+    fn exp_value(&mut self, hamiltonian: &ket::PauliHamiltonian) -> f64 {
+        self.install(|manager| manager.exp_value_of(hamiltonian))
+    }
+
+    fn sample(&mut self, qubits: &[usize], shots: u64) -> (Vec<u64>, Vec<u64>) {
+        self.install(|manager| manager.sample_qubits(qubits, shots))
+    }
+
+    fn sample_without_collapse(
+        &mut self,
+        qubits: &[usize],
+        shots: u64,
+    ) -> Option<(Vec<u64>, Vec<u64>)> {
+        // Sampling reads the probabilities from a dump, so the state is not changed.
+        Some(self.sample(qubits, shots))
+    }
+
+    fn dump(&mut self, qubits: &[usize]) -> ket::DumpData {
+        self.install(|manager| manager.dump_qubits(qubits))
+    }
+
+    fn dump_top_k(&mut self, qubits: &[usize], k: usize) -> ket::DumpData {
+        self.install(|manager| {
+            let qubits = qubits.iter().map(|x| manager.qubit_map[*x]).collect_vec();
+            manager.simulator.dump_top_k(&qubits, k)
+        })
+    }
+
+    fn kraus_channel(&mut self, operators: &[ket::ir::KrausOperator], target: usize) -> bool {
+        self.install(|manager| manager.apply_kraus_channel(operators, target));
+        true
+    }
+
+    fn diagonal(&mut self, phases: &[f64], qubits: &[usize]) {
+        self.install(|manager| manager.apply_diagonal(phases, qubits))
+    }
+
+    fn multiplexor(&mut self, axis: ket::Pauli, angles: &[f64], control: &[usize], target: usize) {
+        self.install(|manager| manager.apply_multiplexor(axis, angles, control, target))
+    }
+
+    fn save_state(&mut self) -> Option<Box<dyn Any + Send>> {
+        Some(Box::new(LiveState {
+            simulator: self.simulator.snapshot()?,
+            qubit_stack: self.qubit_stack.clone(),
+            qubit_map: self.qubit_map.clone(),
+            allocated: self.allocated.clone(),
+            exp_value_errors: self.exp_value_errors.len(),
+        }))
+    }
+
+    fn restore_state(&mut self, state: &(dyn Any + Send)) -> bool {
+        let Some(state) = state.downcast_ref::<LiveState<S>>() else {
+            return false;
+        };
+        let Some(simulator) = state.simulator.snapshot() else {
+            return false;
+        };
+        self.simulator = simulator;
+        self.qubit_stack.clone_from(&state.qubit_stack);
+        self.qubit_map.clone_from(&state.qubit_map);
+        self.allocated.clone_from(&state.allocated);
+        self.exp_value_errors.truncate(state.exp_value_errors);
+        true
+    }
+}
+
+/// State of a live simulation saved by [`ket::LiveExecution::save_state`]. The random
+/// number generator is not saved, so the measurements after a rollback draw new
+/// outcomes.
+struct LiveState<S> {
+    simulator: S,
+    qubit_stack: Vec<usize>,
+    qubit_map: Vec<usize>,
+    allocated: BTreeSet<usize>,
+    /// Number of expected values.
+    exp_value_errors: usize,
+}
+
+impl<S: QuantumExecution + 'static> QubitManager<S> {
+    /// Runs `op` on the simulator thread pool.
+    fn install<R: Send>(&mut self, op: impl FnOnce(&mut Self) -> R + Send) -> R {
+        match self.pool.clone() {
+            Some(pool) => pool.install(|| op(self)),
+            None => op(self),
+        }
+    }
+
+    fn apply_gate(&mut self, gate: &ket::QuantumGate, target: usize, control: &[usize]) {
         let target = self.qubit_map[target];
         let control = &control.iter().map(|x| self.qubit_map[*x]).collect_vec();
 
@@ -150,34 +564,39 @@ impl<S: QuantumExecution> ket::LiveExecution for QubitManager<S> {
             gate,
             target,
             control,
-            self.simulator.debug_state().unwrap_or_default()
+            self.debug_state()
         );
     }
 
-    fn measure(&mut self, qubits: &[usize]) -> u64 {
+    fn measure_qubits(&mut self, qubits: &[usize], forced: Option<u64>) -> u64 {
         let qubits = qubits.iter().map(|x| self.qubit_map[*x]).collect_vec();
 
-        debug!("measuring qubits={:?}", qubits);
+        debug!("measuring qubits={:?}, forced={:?}", qubits, forced);
 
         let result = qubits
             .iter()
             .rev()
             .enumerate()
-            .map(|(index, qubit)| (self.simulator.measure(*qubit, &mut self.rng) as u64) << index)
+            .map(|(index, qubit)| {
+                let forced = forced.map(|outcome| (outcome >> index) & 1 == 1);
+                (self.simulator.measure(*qubit, forced, &mut self.rng) as u64) << index
+            })
             .reduce(|a, b| a | b)
             .unwrap_or(0);
 
         trace!(
             "after measurement qubits={:?}\n{}",
             qubits,
-            self.simulator.debug_state().unwrap_or_default()
+            self.debug_state()
         );
 
         result
     }
 
-    fn exp_value(&mut self, hamiltonian: &ket::PauliHamiltonian) -> f64 {
-        hamiltonian
+    /// Returns the expected value of the Hamiltonian, recording a bound of its
+    /// rounding error.
+    fn exp_value_of(&mut self, hamiltonian: &ket::PauliHamiltonian) -> f64 {
+        let (sum, error) = hamiltonian
             .products
             .iter()
             .map(|pauli_terms| {
@@ -202,7 +621,7 @@ impl<S: QuantumExecution> ket::LiveExecution for QubitManager<S> {
                 );
                 let probabilities = from_dump_to_prob(dump_data);
 
-                let result: f64 = probabilities
+                let result: CompensatedSum = probabilities
                     .basis_states
                     .iter()
                     .zip(probabilities.probabilities.iter())
@@ -236,65 +655,358 @@ impl<S: QuantumExecution> ket::LiveExecution for QubitManager<S> {
                 result
             })
             .zip(&hamiltonian.coefficients)
-            .map(|(result, coefficient)| result * *coefficient)
-            .sum()
+            .fold(
+                (CompensatedSum::default(), 0.0),
+                |(mut sum, error), (result, coefficient)| {
+                    sum.add(result.value() * coefficient);
+                    (sum, error + result.error() * coefficient.abs())
+                },
+            );
+
+        self.exp_value_errors.push(error + sum.error());
+        sum.value()
     }
 
-    fn sample(&mut self, qubits: &[usize], shots: u64) -> (Vec<u64>, Vec<u64>) {
+    fn sample_qubits(&mut self, qubits: &[usize], shots: u64) -> (Vec<u64>, Vec<u64>) {
         let qubits = qubits.iter().map(|x| self.qubit_map[*x]).collect_vec();
 
         let data = self.simulator.dump(&qubits);
         from_prob_to_shots(from_dump_to_prob(data), shots, &mut self.rng)
     }
 
-    fn dump(&mut self, qubits: &[usize]) -> ket::DumpData {
+    /// Returns the state of the allocated qubits as text, see [`ket::state_format`].
+    fn debug_state(&mut self) -> String {
+        let qubits = self.allocated.iter().copied().collect_vec();
+        let dump = self.dump_qubits(&qubits);
+        format_state(&dump, &qubits, &self.state_format)
+    }
+
+    fn dump_qubits(&mut self, qubits: &[usize]) -> ket::DumpData {
         let qubits = qubits.iter().map(|x| self.qubit_map[*x]).collect_vec();
 
         self.simulator.dump(&qubits)
     }
-}
 
-impl<S: QuantumExecution> ket::BatchExecution for QubitManager<S> {
-    fn submit_execution(&mut self, instructions: &[ket::Instruction]) {
-        self.result = Some(ket::ir::ResultData::default());
-        for instruction in instructions {
-            match instruction {
-                ket::Instruction::Alloc { target } => self.alloc(*target),
-                ket::Instruction::Free { target } => self.free(*target),
-                ket::Instruction::Gate {
-                    gate,
-                    target,
-                    control,
-                } => self.gate(gate, *target, control),
-                ket::Instruction::Measure { qubits, output } => {
-                    assert!(self.result.as_ref().unwrap().measurements.len() == *output);
-                    let result = self.measure(qubits);
-                    self.result.as_mut().unwrap().measurements.push(result);
-                }
-                ket::Instruction::ExpValue {
-                    hamiltonian,
-                    output,
-                } => {
-                    assert!(self.result.as_ref().unwrap().exp_values.len() == *output);
-                    let result = self.exp_value(hamiltonian);
-                    self.result.as_mut().unwrap().exp_values.push(result);
+    /// Executes the instructions, appending their outputs to the result.
+    fn run(&mut self, instructions: &[ket::Instruction]) {
+        self.install(|manager| {
+            let steps = match manager.fusion.as_mut() {
+                Some(fusion) => fusion.plan(instructions),
+                None => instructions.iter().map(Step::Instruction).collect(),
+            };
+            for step in steps {
+                let start = manager.profile.is_some().then(Instant::now);
+                let frame = match step {
+                    Step::Instruction(instruction) => {
+                        let frame = manager.profile_frame(instruction);
+                        manager.run_instruction(instruction);
+                        frame
+                    }
+                    Step::Fused {
+                        matrix,
+                        qubits,
+                        gates,
+                    } => {
+                        let qubits = qubits.iter().map(|x| manager.qubit_map[*x]).collect_vec();
+                        debug!("apply fused subcircuit qubits={:?}", qubits);
+                        if !manager.simulator.apply_unitary(&matrix, &qubits, &[]) {
+                            gates.iter().for_each(|gate| manager.run_instruction(gate));
+                        }
+                        ("fused".to_string(), None)
+                    }
+                };
+                if let (Some(profile), Some(start)) = (manager.profile.as_mut(), start) {
+                    profile.record(frame.0, frame.1, start.elapsed());
                 }
+            }
+        });
+        self.write_profile();
+    }
+
+    /// Runs every shot of the samples from the initial state, so the conditional gates
+    /// take the branch of the measurements of each shot, as in live execution.
+    ///
+    /// The measurements, expected values, and dumps are those of the first shot. The
+    /// chunks of a streamed execution keep a single branch, as their state carries over.
+    fn run_shots(&mut self, instructions: &[ket::Instruction]) {
+        let mut sample_shots = Vec::new();
+        let instructions = instructions
+            .iter()
+            .map(|instruction| match instruction {
                 ket::Instruction::Sample {
                     qubits,
                     shots,
                     output,
                 } => {
-                    assert!(self.result.as_ref().unwrap().samples.len() == *output);
-                    let result = self.sample(qubits, *shots);
-                    self.result.as_mut().unwrap().samples.push(result);
+                    sample_shots.push(*shots);
+                    ket::Instruction::Sample {
+                        qubits: qubits.clone(),
+                        shots: 1,
+                        output: *output,
+                    }
                 }
-                ket::Instruction::Dump { qubits, output } => {
-                    assert!(self.result.as_ref().unwrap().dumps.len() == *output);
-                    let result = self.dump(qubits);
-                    self.result.as_mut().unwrap().dumps.push(result);
+                instruction => instruction.clone(),
+            })
+            .collect_vec();
+        let num_shots = sample_shots.iter().copied().max().unwrap_or(1);
+
+        self.run(&instructions);
+        let mut first = self.result.take().unwrap();
+        let exp_values = self.exp_value_errors.len();
+        let mut counts = vec![BTreeMap::<u64, u64>::new(); sample_shots.len()];
+        let mut add = |shot: u64, samples: Vec<(Vec<u64>, Vec<u64>)>| {
+            for (sample, (states, _)) in samples.into_iter().enumerate() {
+                if let Some(state) = states.first().filter(|_| shot < sample_shots[sample]) {
+                    *counts[sample].entry(*state).or_default() += 1;
+                }
+            }
+        };
+        add(0, std::mem::take(&mut first.samples));
+
+        // The measurements of the other shots are not forced.
+        self.forced_measurements.clear();
+        for shot in 1..num_shots {
+            self.reset();
+            self.result = Some(ket::ir::ResultData::default());
+            self.run(&instructions);
+            add(shot, self.result.take().unwrap().samples);
+            self.exp_value_errors.truncate(exp_values);
+        }
+
+        first.samples = counts
+            .into_iter()
+            .map(|counts| counts.into_iter().unzip())
+            .collect();
+        self.result = Some(first);
+    }
+
+    /// Returns the simulator to the initial state, with every qubit free.
+    fn reset(&mut self) {
+        let num_qubits = self.qubit_map.len();
+        self.simulator = self
+            .install(|_| S::new(num_qubits))
+            .expect("the simulator was already created with this number of qubits");
+        self.qubit_stack = (0..num_qubits).collect_vec();
+        self.qubit_map = (0..num_qubits).collect_vec();
+        self.allocated.clear();
+    }
+
+    /// Returns the name and the simulator target qubit of an instruction in the
+    /// profile.
+    fn profile_frame(&self, instruction: &ket::Instruction) -> (String, Option<usize>) {
+        match instruction {
+            ket::Instruction::Alloc { .. } => ("alloc".to_string(), None),
+            ket::Instruction::Free { .. } => ("free".to_string(), None),
+            ket::Instruction::Gate {
+                gate,
+                target,
+                control,
+            }
+            | ket::Instruction::ConditionalGate {
+                gate,
+                target,
+                control,
+                ..
+            } => (
+                gate_name(gate, control.len()),
+                Some(self.qubit_map[*target]),
+            ),
+            ket::Instruction::Measure { .. } => ("measure".to_string(), None),
+            ket::Instruction::ExpValue { .. } => ("exp_value".to_string(), None),
+            ket::Instruction::Sample { .. } => ("sample".to_string(), None),
+            ket::Instruction::Dump { .. } => ("dump".to_string(), None),
+            ket::Instruction::KrausChannel { target, .. } => {
+                ("kraus".to_string(), Some(self.qubit_map[*target]))
+            }
+            ket::Instruction::Diagonal { .. } => ("diagonal".to_string(), None),
+            ket::Instruction::Multiplexor { target, .. } => {
+                ("multiplexor".to_string(), Some(self.qubit_map[*target]))
+            }
+        }
+    }
+
+    /// Writes the profile to the file of `KBW_PROFILE`, if it is set.
+    fn write_profile(&self) {
+        if let (Some(profile), Some(path)) = (&self.profile, &self.profile_path) {
+            if let Err(error) = profile.write_folded(path) {
+                warn!("could not write the profile to {:?}: {}", path, error);
+            }
+        }
+    }
+
+    /// Executes an instruction, appending its output to the result.
+    fn run_instruction(&mut self, instruction: &ket::Instruction) {
+        let (exp_values, samples, dumps) = self.chunk_outputs;
+        match instruction {
+            ket::Instruction::Alloc { target } => self.alloc(*target),
+            ket::Instruction::Free { target } => self.free(*target),
+            ket::Instruction::Gate {
+                gate,
+                target,
+                control,
+            } => self.apply_gate(gate, *target, control),
+            ket::Instruction::Measure { qubits, output } => {
+                assert!(self.result.as_ref().unwrap().measurements.len() == *output);
+                let forced = self.forced_measurements.get(output).copied();
+                let result = self.measure_qubits(qubits, forced);
+                self.result.as_mut().unwrap().measurements.push(result);
+            }
+            ket::Instruction::ExpValue {
+                hamiltonian,
+                output,
+            } => {
+                assert!(exp_values + self.result.as_ref().unwrap().exp_values.len() == *output);
+                let result = self.exp_value_of(hamiltonian);
+                self.result.as_mut().unwrap().exp_values.push(result);
+            }
+            ket::Instruction::Sample {
+                qubits,
+                shots,
+                output,
+            } => {
+                assert!(samples + self.result.as_ref().unwrap().samples.len() == *output);
+                let result = self.sample_qubits(qubits, *shots);
+                self.result.as_mut().unwrap().samples.push(result);
+            }
+            ket::Instruction::Dump { qubits, output } => {
+                assert!(dumps + self.result.as_ref().unwrap().dumps.len() == *output);
+                let result = self.dump_qubits(qubits);
+                self.result.as_mut().unwrap().dumps.push(result);
+            }
+            ket::Instruction::KrausChannel { operators, target } => {
+                self.apply_kraus_channel(operators, *target)
+            }
+            ket::Instruction::ConditionalGate {
+                gate,
+                target,
+                control,
+                condition,
+            } => {
+                let measurements = &self.result.as_ref().unwrap().measurements;
+                if condition.holds(|output| measurements.get(output).copied()) {
+                    self.apply_gate(gate, *target, control);
                 }
             }
+            ket::Instruction::Diagonal { phases, qubits } => self.apply_diagonal(phases, qubits),
+            ket::Instruction::Multiplexor {
+                axis,
+                angles,
+                control,
+                target,
+            } => self.apply_multiplexor(*axis, angles, control, *target),
+        }
+    }
+
+    /// Applies a diagonal gate with the kernel of the simulator, or with its CNOTs and
+    /// rotations if the simulator has none.
+    fn apply_diagonal(&mut self, phases: &[f64], qubits: &[usize]) {
+        let mapped = qubits.iter().map(|x| self.qubit_map[*x]).collect_vec();
+        debug!("apply diagonal qubits={:?}", mapped);
+
+        if !self.simulator.diagonal(phases, &mapped) {
+            let (gates, _) = ket::multiplexor::diagonal_gates(phases, qubits);
+            gates.iter().for_each(|gate| self.run_instruction(gate));
+        }
+    }
+
+    /// Applies a multiplexed rotation with the kernel of the simulator, or with its
+    /// CNOTs and rotations if the simulator has none.
+    fn apply_multiplexor(
+        &mut self,
+        axis: ket::Pauli,
+        angles: &[f64],
+        control: &[usize],
+        target: usize,
+    ) {
+        let mapped = control.iter().map(|x| self.qubit_map[*x]).collect_vec();
+        let mapped_target = self.qubit_map[target];
+        debug!(
+            "apply multiplexor axis={:?}, target={}, control={:?}",
+            axis, mapped_target, mapped
+        );
+
+        if !self
+            .simulator
+            .multiplexor(axis, angles, &mapped, mapped_target)
+        {
+            ket::multiplexor::multiplexor_gates(axis, angles, control, target)
+                .iter()
+                .for_each(|gate| self.run_instruction(gate));
+        }
+    }
+
+    /// Applies a channel by stochastic unraveling: the operator `K` is drawn with
+    /// probability `p = |K psi|^2` and the state becomes `K psi / sqrt(p)`, so the
+    /// average over many runs is the channel applied to the density matrix.
+    fn apply_kraus_channel(&mut self, operators: &[ket::ir::KrausOperator], target: usize) {
+        let target = self.qubit_map[target];
+        let rho = self.simulator.reduced_density_matrix(target);
+        let operators = operators.iter().map(|k| k.matrix()).collect_vec();
+        let probabilities = operators
+            .iter()
+            .map(|k| kraus_probability(k, &rho).max(0.0))
+            .collect_vec();
+
+        let index = WeightedIndex::new(&probabilities)
+            .unwrap()
+            .sample(&mut self.rng);
+        let norm = 1.0 / probabilities[index].sqrt();
+
+        debug!(
+            "kraus channel target={}, operator={}, probability={}",
+            target, index, probabilities[index]
+        );
+
+        self.simulator
+            .apply_matrix(operators[index].map(|row| row.map(|x| x * norm)), target);
+    }
+}
+
+/// Returns `true` if a sample follows a conditional gate, so each of its shots can take
+/// a different branch of the mid-circuit measurements.
+fn has_feedback(instructions: &[ket::Instruction]) -> bool {
+    instructions
+        .iter()
+        .skip_while(|instruction| !matches!(instruction, ket::Instruction::ConditionalGate { .. }))
+        .any(|instruction| matches!(instruction, ket::Instruction::Sample { .. }))
+}
+
+impl<S: QuantumExecution + 'static> ket::BatchExecution for QubitManager<S> {
+    fn force_measurements(&mut self, outcomes: &BTreeMap<usize, u64>) {
+        self.forced_measurements = outcomes.clone();
+    }
+
+    fn submit_execution(&mut self, instructions: &[ket::Instruction]) {
+        self.result = Some(ket::ir::ResultData::default());
+        self.chunk_outputs = (0, 0, 0);
+        self.exp_value_errors.clear();
+        if has_feedback(instructions) {
+            self.run_shots(instructions);
+        } else {
+            self.run(instructions);
         }
+        self.forced_measurements.clear();
+    }
+
+    fn submit_chunk(&mut self, instructions: &[ket::Instruction]) -> Option<ket::ResultData> {
+        let measurements = self
+            .result
+            .get_or_insert_with(Default::default)
+            .measurements
+            .len();
+        self.run(instructions);
+
+        let result = self.result.as_mut().unwrap();
+        self.chunk_outputs.0 += result.exp_values.len();
+        self.chunk_outputs.1 += result.samples.len();
+        self.chunk_outputs.2 += result.dumps.len();
+        Some(ket::ResultData {
+            measurements: result.measurements[measurements..].to_vec(),
+            exp_values: std::mem::take(&mut result.exp_values),
+            samples: std::mem::take(&mut result.samples),
+            dumps: std::mem::take(&mut result.dumps),
+            execution_time: None,
+        })
     }
 
     fn get_result(&mut self) -> ket::ResultData {
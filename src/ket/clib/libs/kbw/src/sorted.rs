@@ -0,0 +1,279 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sparse state stored as a vector of basis states sorted by index.
+//!
+//! A gate on the target qubit `t` pairs the basis states that only differ in bit `t`.
+//! In a sorted vector, the states that share the bits above `t` form a contiguous run,
+//! whose first half has bit `t` cleared and whose second half has it set, both sorted
+//! by the bits below `t`. Applying a gate merges the two halves of each run, so every
+//! gate is a single sequential pass with no hashing.
+//!
+//! Basis states are `u128` indices, with qubit `i` at bit `i`, so the representation
+//! holds up to [`MAX_QUBITS`] qubits.
+
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use num::{complex::Complex64, One, Zero};
+
+use crate::bitwise::ctrl_check;
+use crate::summation::CompensatedSum;
+
+/// Amplitudes with a smaller norm are dropped from the state.
+const EPSILON: f64 = 1e-15;
+
+/// Index of a basis state.
+pub(crate) type Key = u128;
+
+/// Largest number of qubits, as a gate on the last bit of a [`Key`] has no bits above
+/// its target.
+pub(crate) const MAX_QUBITS: usize = Key::BITS as usize - 1;
+
+/// Sparse state of up to [`MAX_QUBITS`] qubits.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SortedState {
+    states: Vec<(Key, Complex64)>,
+    next: Vec<(Key, Complex64)>,
+}
+
+impl SortedState {
+    /// Creates a state from basis states in any order.
+    pub(crate) fn from_unsorted(mut states: Vec<(Key, Complex64)>) -> Self {
+        states.sort_unstable_by_key(|(state, _)| *state);
+        Self {
+            states,
+            next: Vec::new(),
+        }
+    }
+
+    /// Returns the basis states and amplitudes, sorted by basis state.
+    pub(crate) fn states(&self) -> &[(Key, Complex64)] {
+        &self.states
+    }
+
+    /// Consumes the state, returning the basis states and amplitudes.
+    pub(crate) fn into_states(self) -> Vec<(Key, Complex64)> {
+        self.states
+    }
+
+    /// Number of basis states with non-zero amplitude.
+    pub(crate) fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Multiplies the amplitudes by a diagonal matrix.
+    fn apply_diagonal(&mut self, diagonal: [Complex64; 2], target: usize, control: &[usize]) {
+        let bit: Key = 1 << target;
+        for (state, amp) in self.states.iter_mut() {
+            if ctrl_check(*state, control) {
+                *amp *= diagonal[(*state & bit != 0) as usize];
+            }
+        }
+    }
+
+    /// Applies a 2x2 matrix, merging the halves of each run.
+    pub(crate) fn apply(&mut self, matrix: [[Complex64; 2]; 2], target: usize, control: &[usize]) {
+        if matrix[0][1].is_zero() && matrix[1][0].is_zero() {
+            return self.apply_diagonal([matrix[0][0], matrix[1][1]], target, control);
+        }
+
+        let bit: Key = 1 << target;
+        let high = !((bit << 1) - 1);
+        let states = &self.states;
+        let next = &mut self.next;
+        next.clear();
+        next.reserve(states.len());
+
+        let mut upper_half = Vec::new();
+        let mut begin = 0;
+        while begin < states.len() {
+            let prefix = states[begin].0 & high;
+            let end = begin + states[begin..].partition_point(|(state, _)| state & high == prefix);
+            let middle = begin + states[begin..end].partition_point(|(state, _)| state & bit == 0);
+
+            let (mut zero, mut one) = (begin, middle);
+            upper_half.clear();
+            while zero < middle || one < end {
+                // Basis state with bit `t` cleared and its amplitudes.
+                let (state, amp_0, amp_1) =
+                    if one == end || (zero < middle && states[zero].0 < states[one].0 ^ bit) {
+                        zero += 1;
+                        (states[zero - 1].0, states[zero - 1].1, Complex64::zero())
+                    } else if zero == middle || states[one].0 ^ bit < states[zero].0 {
+                        one += 1;
+                        (
+                            states[one - 1].0 ^ bit,
+                            Complex64::zero(),
+                            states[one - 1].1,
+                        )
+                    } else {
+                        zero += 1;
+                        one += 1;
+                        (states[zero - 1].0, states[zero - 1].1, states[one - 1].1)
+                    };
+
+                let (amp_0, amp_1) = if ctrl_check(state, control) {
+                    (
+                        matrix[0][0] * amp_0 + matrix[0][1] * amp_1,
+                        matrix[1][0] * amp_0 + matrix[1][1] * amp_1,
+                    )
+                } else {
+                    (amp_0, amp_1)
+                };
+
+                if amp_0.norm() >= EPSILON {
+                    next.push((state, amp_0));
+                }
+                if amp_1.norm() >= EPSILON {
+                    upper_half.push((state | bit, amp_1));
+                }
+            }
+            next.extend_from_slice(&upper_half);
+
+            begin = end;
+        }
+
+        std::mem::swap(&mut self.states, &mut self.next);
+    }
+
+    pub(crate) fn pauli_x(&mut self, target: usize, control: &[usize]) {
+        let (zero, one) = (Complex64::zero(), Complex64::one());
+        self.apply([[zero, one], [one, zero]], target, control);
+    }
+
+    pub(crate) fn pauli_y(&mut self, target: usize, control: &[usize]) {
+        let zero = Complex64::zero();
+        self.apply(
+            [[zero, -Complex64::i()], [Complex64::i(), zero]],
+            target,
+            control,
+        );
+    }
+
+    pub(crate) fn pauli_z(&mut self, target: usize, control: &[usize]) {
+        self.apply_diagonal([Complex64::one(), -Complex64::one()], target, control);
+    }
+
+    pub(crate) fn hadamard(&mut self, target: usize, control: &[usize]) {
+        let h = Complex64::from(FRAC_1_SQRT_2);
+        self.apply([[h, h], [h, -h]], target, control);
+    }
+
+    pub(crate) fn phase(&mut self, lambda: f64, target: usize, control: &[usize]) {
+        self.apply_diagonal(
+            [Complex64::one(), Complex64::exp(lambda * Complex64::i())],
+            target,
+            control,
+        );
+    }
+
+    pub(crate) fn rx(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let cos = Complex64::from(f64::cos(theta / 2.0));
+        let sin = -Complex64::i() * f64::sin(theta / 2.0);
+        self.apply([[cos, sin], [sin, cos]], target, control);
+    }
+
+    pub(crate) fn ry(&mut self, theta: f64, target: usize, control: &[usize]) {
+        let cos = Complex64::from(f64::cos(theta / 2.0));
+        let sin = Complex64::from(f64::sin(theta / 2.0));
+        self.apply([[cos, -sin], [sin, cos]], target, control);
+    }
+
+    pub(crate) fn rz(&mut self, theta: f64, target: usize, control: &[usize]) {
+        self.apply_diagonal(
+            [
+                Complex64::exp(-theta / 2.0 * Complex64::i()),
+                Complex64::exp(theta / 2.0 * Complex64::i()),
+            ],
+            target,
+            control,
+        );
+    }
+
+    /// Probability of measuring `|1>` on the target qubit.
+    pub(crate) fn probability_one(&self, target: usize) -> f64 {
+        self.states
+            .iter()
+            .filter(|(state, _)| state & (1 << target) != 0)
+            .map(|(_, amp)| amp.norm_sqr())
+            .sum::<CompensatedSum>()
+            .value()
+    }
+
+    /// Density matrix of the target qubit, tracing out the others.
+    pub(crate) fn reduced_density_matrix(&self, target: usize) -> [[Complex64; 2]; 2] {
+        let bit = 1 << target;
+        let (mut rho_00, mut rho_11, mut rho_01) = (0.0, 0.0, Complex64::zero());
+        for (state, amp) in &self.states {
+            if state & bit != 0 {
+                rho_11 += amp.norm_sqr();
+                continue;
+            }
+            rho_00 += amp.norm_sqr();
+            if let Ok(index) = self
+                .states
+                .binary_search_by_key(&(state | bit), |(s, _)| *s)
+            {
+                rho_01 += amp * self.states[index].1.conj();
+            }
+        }
+        [[rho_00.into(), rho_01], [rho_01.conj(), rho_11.into()]]
+    }
+
+    /// Collapses the target qubit to `result` and renormalizes with `norm`.
+    pub(crate) fn collapse(&mut self, target: usize, result: bool, norm: f64) {
+        self.states.retain_mut(|(state, amp)| {
+            *amp *= norm;
+            (*state & (1 << target) != 0) == result
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Key, SortedState, MAX_QUBITS};
+    use num::{complex::Complex64, One};
+
+    #[test]
+    fn merge_keeps_order() {
+        let mut state = SortedState::from_unsorted(vec![(0, Complex64::one())]);
+        state.hadamard(0, &[]);
+        state.hadamard(2, &[]);
+        state.pauli_x(1, &[0]);
+        state.ry(0.3, 1, &[]);
+        state.pauli_y(2, &[1]);
+        // Runs above the 64th bit.
+        state.pauli_x(MAX_QUBITS - 1, &[0]);
+        state.hadamard(70, &[]);
+
+        let states: Vec<Key> = state.states().iter().map(|(state, _)| *state).collect();
+        assert!(states.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let norm: f64 = state.states().iter().map(|(_, amp)| amp.norm_sqr()).sum();
+        assert!((norm - 1.0).abs() < 1e-12);
+
+        state.hadamard(2, &[]);
+        state.hadamard(2, &[]);
+        state.pauli_x(1, &[0]);
+        state.hadamard(70, &[]);
+        assert_eq!(state.len(), 8);
+    }
+
+    #[test]
+    fn top_qubit() {
+        let top = MAX_QUBITS - 1;
+        let mut state = SortedState::from_unsorted(vec![(0, Complex64::one())]);
+        state.hadamard(top, &[]);
+        state.pauli_x(0, &[top]);
+
+        let states: Vec<Key> = state.states().iter().map(|(state, _)| *state).collect();
+        assert_eq!(states, [0, (1 << top) | 1]);
+
+        state.pauli_x(0, &[top]);
+        state.hadamard(top, &[]);
+        assert_eq!(state.len(), 1);
+        assert!((state.states()[0].1.re - 1.0).abs() < 1e-12);
+    }
+}
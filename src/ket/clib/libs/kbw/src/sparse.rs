@@ -4,22 +4,70 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::bitwise::*;
-use crate::error::Result;
-use crate::quantum_execution::QuantumExecution;
+use crate::error::{KBWError, Result};
+use crate::noise::Matrix2;
+use crate::parallel::*;
+use crate::quantum_execution::{choose_outcome, QuantumExecution};
+use crate::sorted::{self, Key, SortedState};
+use crate::summation::CompensatedSum;
 use itertools::Itertools;
+use log::{debug, error};
 use num::complex::Complex64;
-use rand::distributions::WeightedIndex;
 use rand::prelude::*;
-use rayon::prelude::*;
 use std::{collections::HashMap, f64::consts::FRAC_1_SQRT_2};
 use twox_hash::RandomXxHashBuilder64;
 
 type StateMap = HashMap<Vec<u64>, Complex64, RandomXxHashBuilder64>;
 
+/// In [`SparseMode::Auto`], the state switches to the sorted vector when it has this many
+/// basis states.
+const SORTED_MIN_STATES: usize = 1024;
+
+/// In [`SparseMode::Auto`], the state switches back to the hash map when it has fewer
+/// basis states than this.
+const HASH_MAX_STATES: usize = 256;
+
+/// Representation of the sparse state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SparseMode {
+    /// Hash map from basis state to amplitude.
+    Hash,
+    /// Vector of basis states sorted by index, limited to 127 qubits.
+    ///
+    /// Gates are applied by merging sorted runs, which is faster than hashing when the
+    /// state has many basis states.
+    Sorted,
+    /// Switches between the two representations according to the number of basis
+    /// states. Uses the hash map for more than 127 qubits.
+    #[default]
+    Auto,
+}
+
+impl SparseMode {
+    /// Reads the mode from the `KBW_SPARSE_MODE` environment variable: `hash`,
+    /// `sorted`, or `auto` (default).
+    pub fn from_env() -> Self {
+        match std::env::var("KBW_SPARSE_MODE")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "hash" => SparseMode::Hash,
+            "sorted" => SparseMode::Sorted,
+            _ => SparseMode::Auto,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Sparse {
     state_0: StateMap,
     state_1: StateMap,
     state: bool,
+    /// Sorted representation of the state; when set, the hash maps are empty.
+    sorted: Option<SortedState>,
+    mode: SparseMode,
+    num_qubits: usize,
 }
 
 impl Sparse {
@@ -47,26 +95,120 @@ impl Sparse {
             &self.state_1
         }
     }
-}
-
-impl QuantumExecution for Sparse {
-    fn new(num_qubits: usize) -> Result<Self> {
-        let num_states = (num_qubits + 64) / 64;
 
+    /// Creates a simulator with the given state representation.
+    pub fn with_mode(num_qubits: usize, mode: SparseMode) -> Result<Self> {
         let mut state_0 = StateMap::default();
 
-        let zero = vec![0; num_states];
+        let zero = vec![0; ket::basis::words(num_qubits)];
 
         state_0.insert(zero, Complex64::new(1.0, 0.0));
 
-        Ok(Sparse {
+        let mut sparse = Sparse {
             state_0,
             state_1: StateMap::default(),
             state: true,
-        })
+            sorted: None,
+            mode,
+            num_qubits,
+        };
+
+        if mode == SparseMode::Sorted {
+            if num_qubits > sorted::MAX_QUBITS {
+                error!(
+                    "sorted sparse representation supports up to {} qubits",
+                    sorted::MAX_QUBITS
+                );
+                return Err(KBWError::UnsupportedNumberOfQubits);
+            }
+            sparse.switch_to_sorted();
+        }
+
+        Ok(sparse)
+    }
+
+    fn switch_to_sorted(&mut self) {
+        let states = self
+            .get_current_state_mut()
+            .drain()
+            .map(|(state, amp)| (to_key(&state), amp))
+            .collect();
+        self.sorted = Some(SortedState::from_unsorted(states));
+    }
+
+    fn switch_to_hash(&mut self) {
+        if let Some(sorted) = self.sorted.take() {
+            let words = ket::basis::words(self.num_qubits);
+            self.get_current_state_mut().extend(
+                sorted
+                    .into_states()
+                    .into_iter()
+                    .map(|(state, amp)| (from_key(state, words), amp)),
+            );
+        }
+    }
+
+    /// Switches the representation according to the number of basis states.
+    fn update_representation(&mut self) {
+        if self.mode != SparseMode::Auto {
+            return;
+        }
+
+        match &self.sorted {
+            Some(sorted) if sorted.len() < HASH_MAX_STATES => {
+                debug!("sparse representation: sorted -> hash");
+                self.switch_to_hash();
+            }
+            None if self.num_qubits <= sorted::MAX_QUBITS
+                && self.get_current_state().len() >= SORTED_MIN_STATES =>
+            {
+                debug!("sparse representation: hash -> sorted");
+                self.switch_to_sorted();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Basis state of `qubits`, the first qubit being the most significant bit, see
+/// [`ket::basis`].
+///
+/// The words of `state` are little-endian, the first holding the qubits 0 to 63.
+fn dump_state(state: &[u64], qubits: &[usize]) -> Vec<u64> {
+    ket::basis::from_bits(qubits.iter().map(|qubit| is_one_at_vec(state, *qubit)))
+}
+
+/// Index of the sorted representation of a basis state of up to 128 qubits.
+fn to_key(state: &[u64]) -> Key {
+    state
+        .iter()
+        .rev()
+        .fold(0, |key, word| key << u64::BITS | *word as Key)
+}
+
+/// Basis state of `words` words of an index of the sorted representation, the inverse
+/// of [`to_key`].
+fn from_key(key: Key, words: usize) -> Vec<u64> {
+    (0..words)
+        .map(|word| (key >> (word as u32 * u64::BITS)) as u64)
+        .collect()
+}
+
+impl QuantumExecution for Sparse {
+    fn new(num_qubits: usize) -> Result<Self> {
+        Sparse::with_mode(num_qubits, SparseMode::from_env())
+    }
+
+    fn snapshot(&self) -> Option<Self> {
+        Some(self.clone())
     }
 
     fn pauli_x(&mut self, target: usize, control: &[usize]) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.pauli_x(target, control);
+            return self.update_representation();
+        }
+
         let (current_state, next_state) = self.get_states();
 
         current_state.drain().for_each(|(state, amp)| {
@@ -79,9 +221,16 @@ impl QuantumExecution for Sparse {
                 amp,
             );
         });
+
+        self.update_representation();
     }
 
     fn pauli_y(&mut self, target: usize, control: &[usize]) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.pauli_y(target, control);
+            return self.update_representation();
+        }
+
         let (current_state, next_state) = self.get_states();
 
         current_state.drain().for_each(|(state, mut amp)| {
@@ -96,9 +245,16 @@ impl QuantumExecution for Sparse {
                 next_state.insert(state, amp);
             }
         });
+
+        self.update_representation();
     }
 
     fn pauli_z(&mut self, target: usize, control: &[usize]) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.pauli_z(target, control);
+            return self.update_representation();
+        }
+
         let current_state = self.get_current_state_mut();
 
         current_state.par_iter_mut().for_each(|(state, amp)| {
@@ -106,9 +262,16 @@ impl QuantumExecution for Sparse {
                 *amp = -*amp;
             }
         });
+
+        self.update_representation();
     }
 
     fn hadamard(&mut self, target: usize, control: &[usize]) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.hadamard(target, control);
+            return self.update_representation();
+        }
+
         let (current_state, next_state) = self.get_states();
 
         current_state.drain().for_each(|(state, mut amp)| {
@@ -149,9 +312,16 @@ impl QuantumExecution for Sparse {
                 next_state.insert(state, amp);
             }
         });
+
+        self.update_representation();
     }
 
     fn phase(&mut self, lambda: f64, target: usize, control: &[usize]) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.phase(lambda, target, control);
+            return self.update_representation();
+        }
+
         let current_state = self.get_current_state_mut();
 
         let phase = Complex64::exp(lambda * Complex64::i());
@@ -161,9 +331,16 @@ impl QuantumExecution for Sparse {
                 *amp *= phase;
             }
         });
+
+        self.update_representation();
     }
 
     fn rx(&mut self, theta: f64, target: usize, control: &[usize]) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.rx(theta, target, control);
+            return self.update_representation();
+        }
+
         let (current_state, next_state) = self.get_states();
 
         let cons_theta_2 = Complex64::from(f64::cos(theta / 2.0));
@@ -200,9 +377,16 @@ impl QuantumExecution for Sparse {
                 next_state.insert(state, amp);
             }
         });
+
+        self.update_representation();
     }
 
     fn ry(&mut self, theta: f64, target: usize, control: &[usize]) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.ry(theta, target, control);
+            return self.update_representation();
+        }
+
         let (current_state, next_state) = self.get_states();
 
         let cons_theta_2 = Complex64::from(f64::cos(theta / 2.0));
@@ -246,9 +430,16 @@ impl QuantumExecution for Sparse {
                 next_state.insert(state, amp);
             }
         });
+
+        self.update_representation();
     }
 
     fn rz(&mut self, theta: f64, target: usize, control: &[usize]) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.rz(theta, target, control);
+            return self.update_representation();
+        }
+
         let current_state = self.get_current_state_mut();
 
         let phase_0 = Complex64::exp(-theta / 2.0 * Complex64::i());
@@ -263,28 +454,48 @@ impl QuantumExecution for Sparse {
                 }
             }
         });
+
+        self.update_representation();
     }
 
-    fn measure<R: Rng>(&mut self, target: usize, rng: &mut R) -> bool {
+    fn measure<R: Rng>(&mut self, target: usize, forced: Option<bool>, rng: &mut R) -> bool {
+        if let Some(sorted) = &mut self.sorted {
+            let p1 = sorted.probability_one(target);
+            let p0 = match 1.0 - p1 {
+                p0 if p0 >= 0.0 => p0,
+                _ => 0.0,
+            };
+
+            let result = choose_outcome(p0, p1, forced, rng);
+            sorted.collapse(
+                target,
+                result,
+                1.0 / f64::sqrt(if result { p1 } else { p0 }),
+            );
+            self.update_representation();
+            return result;
+        }
+
         let (current_state, next_state) = self.get_states();
 
-        let p1: f64 = current_state
+        let p1 = current_state
             .iter()
             .map(|(state, amp)| {
                 if is_one_at_vec(state, target) {
-                    amp.norm().powi(2)
+                    amp.norm_sqr()
                 } else {
                     0.0
                 }
             })
-            .sum();
+            .sum::<CompensatedSum>()
+            .value();
 
         let p0 = match 1.0 - p1 {
             p0 if p0 >= 0.0 => p0,
             _ => 0.0,
         };
 
-        let result = WeightedIndex::new([p0, p1]).unwrap().sample(rng) == 1;
+        let result = choose_outcome(p0, p1, forced, rng);
 
         let p = 1.0 / f64::sqrt(if result { p1 } else { p0 });
 
@@ -294,35 +505,29 @@ impl QuantumExecution for Sparse {
             }
         });
 
+        self.update_representation();
+
         result
     }
 
     fn dump(&mut self, qubits: &[usize]) -> ket::DumpData {
-        let state = self.get_current_state();
-
-        let (basis_states, amplitudes_real, amplitudes_imag): (Vec<_>, Vec<_>, Vec<_>) = state
-            .iter()
-            .sorted_by_key(|x| x.0)
-            .map(|(state, amp)| {
-                let mut state: Vec<u64> = qubits
+        let (basis_states, amplitudes_real, amplitudes_imag): (Vec<_>, Vec<_>, Vec<_>) =
+            match &self.sorted {
+                Some(sorted) => sorted
+                    .states()
                     .iter()
-                    .rev()
-                    .chunks(64)
-                    .into_iter()
-                    .map(|qubits| {
-                        qubits
-                            .into_iter()
-                            .enumerate()
-                            .map(|(index, qubit)| (is_one_at_vec(state, *qubit) as usize) << index)
-                            .reduce(|a, b| a | b)
-                            .unwrap_or(0) as u64
+                    .map(|(state, amp)| {
+                        let state = qubits.iter().map(|qubit| is_one_at(*state, *qubit));
+                        (ket::basis::from_bits(state), amp.re, amp.im)
                     })
-                    .collect();
-                state.reverse();
-
-                (state, amp.re, amp.im)
-            })
-            .multiunzip();
+                    .multiunzip(),
+                None => self
+                    .get_current_state()
+                    .iter()
+                    .sorted_by(|a, b| a.0.iter().rev().cmp(b.0.iter().rev()))
+                    .map(|(state, amp)| (dump_state(state, qubits), amp.re, amp.im))
+                    .multiunzip(),
+            };
 
         ket::DumpData {
             basis_states,
@@ -331,18 +536,110 @@ impl QuantumExecution for Sparse {
         }
     }
 
-    fn debug_state(&self) -> Option<String> {
-        let states = self.get_current_state();
-        let mut state_str = String::new();
-        for (state, amp) in states {
-            let state = state
-                .iter()
-                .map(|x| format!("{:064b}", x))
-                .reduce(|a, b| a + &b)
-                .unwrap_or_default();
-            state_str.push_str(&format!("{:?}: {:?}\n", state, amp));
+    fn apply_matrix(&mut self, matrix: Matrix2, target: usize) {
+        if let Some(sorted) = &mut self.sorted {
+            sorted.apply(matrix, target, &[]);
+            return self.update_representation();
+        }
+
+        let (current_state, next_state) = self.get_states();
+
+        current_state.drain().for_each(|(state, amp)| {
+            let column = is_one_at_vec(&state, target) as usize;
+            let state_flipped = bit_flip_vec(Vec::clone(&state), target);
+            for (row, state) in [(column, state), (1 - column, state_flipped)] {
+                let amp = matrix[row][column] * amp;
+                match next_state.get_mut(&state) {
+                    Some(c_amp) => {
+                        *c_amp += amp;
+                        if c_amp.norm() < 1e-15 {
+                            next_state.remove(&state);
+                        }
+                    }
+                    None if amp.norm() >= 1e-15 => {
+                        next_state.insert(state, amp);
+                    }
+                    None => {}
+                }
+            }
+        });
+
+        self.update_representation();
+    }
+
+    fn apply_unitary(
+        &mut self,
+        matrix: &[Vec<Complex64>],
+        target: &[usize],
+        control: &[usize],
+    ) -> bool {
+        self.switch_to_hash();
+        let (current_state, next_state) = self.get_states();
+
+        current_state.drain().for_each(|(state, amp)| {
+            if !ctrl_check_vec(&state, control) {
+                *next_state.entry(state).or_default() += amp;
+                return;
+            }
+            let column = target.iter().fold(0, |column, qubit| {
+                column << 1 | is_one_at_vec(&state, *qubit) as usize
+            });
+            for (row, entries) in matrix.iter().enumerate() {
+                let amp = entries[column] * amp;
+                if amp.norm() < 1e-15 {
+                    continue;
+                }
+                let mut state = state.clone();
+                for (index, qubit) in target.iter().enumerate() {
+                    let bit = row >> (target.len() - index - 1) & 1 == 1;
+                    if is_one_at_vec(&state, *qubit) != bit {
+                        state = bit_flip_vec(state, *qubit);
+                    }
+                }
+                *next_state.entry(state).or_default() += amp;
+            }
+        });
+        next_state.retain(|_, amp| amp.norm() >= 1e-15);
+
+        self.update_representation();
+        true
+    }
+
+    fn diagonal(&mut self, phases: &[f64], qubits: &[usize]) -> bool {
+        self.switch_to_hash();
+        let factors = phases
+            .iter()
+            .map(|phase| Complex64::exp(phase * Complex64::i()))
+            .collect_vec();
+
+        let current_state = self.get_current_state_mut();
+        current_state.par_iter_mut().for_each(|(state, amp)| {
+            *amp *= factors[ket::multiplexor::index(qubits, |qubit| is_one_at_vec(state, qubit))];
+        });
+
+        self.update_representation();
+        true
+    }
+
+    fn reduced_density_matrix(&self, target: usize) -> Matrix2 {
+        if let Some(sorted) = &self.sorted {
+            return sorted.reduced_density_matrix(target);
+        }
+
+        let state = self.get_current_state();
+        let (mut rho_00, mut rho_11, mut rho_01) = (0.0, 0.0, Complex64::default());
+        for (basis_state, amp) in state {
+            if is_one_at_vec(basis_state, target) {
+                rho_11 += amp.norm_sqr();
+                continue;
+            }
+            rho_00 += amp.norm_sqr();
+            let flipped = bit_flip_vec(Vec::clone(basis_state), target);
+            if let Some(amp_1) = state.get(&flipped) {
+                rho_01 += amp * amp_1.conj();
+            }
         }
-        Some(state_str)
+        [[rho_00.into(), rho_01], [rho_01.conj(), rho_11.into()]]
     }
 }
 #[cfg(test)]
@@ -350,7 +647,9 @@ mod tests {
     #[test]
     fn bell_state() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let configuration =
-            crate::quantum_execution::QubitManager::<crate::sparse::Sparse>::configuration(2, true);
+            crate::quantum_execution::QubitManager::<crate::sparse::Sparse>::configuration(
+                2, true, false,
+            );
         let mut process = ket::Process::new(configuration);
         let qubit_a = process.allocate_qubit()?;
         let qubit_b = process.allocate_qubit()?;
@@ -360,16 +659,143 @@ mod tests {
         process.apply_gate(ket::QuantumGate::PauliX, qubit_b)?;
         process.ctrl_pop()?;
 
-        let d_ab = process.dump(&[qubit_a, qubit_b])?;
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        ket::assert_state!(process, &[("00", half), ("11", half)]);
+
         let m_a = process.measure(&[qubit_a])?;
         let m_b = process.measure(&[qubit_b])?;
-        let d_ab2 = process.dump(&[qubit_a, qubit_b])?;
+        let result = process.get_measurement(m_a).result;
+        assert_eq!(result, process.get_measurement(m_b).result);
+        let bits = if result == Some(1) { "11" } else { "00" };
+        ket::assert_prob!(process, &[(bits, 1.0)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn multi_word_state() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let configuration =
+            crate::quantum_execution::QubitManager::<crate::sparse::Sparse>::configuration(
+                70, true, false,
+            );
+        let mut process = ket::Process::new(configuration);
+        let qubits = (0..70)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[0])?;
+        process.apply_gate(ket::QuantumGate::Hadamard, qubits[69])?;
+        process.ctrl_push(&[qubits[69]])?;
+        process.apply_gate(ket::QuantumGate::PauliX, qubits[68])?;
+        process.ctrl_pop()?;
 
-        println!("{:?}", process.get_dump(d_ab));
-        println!("{:?}", process.get_measurement(m_a));
-        println!("{:?}", process.get_measurement(m_b));
-        println!("{:?}", process.get_dump(d_ab2));
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        let low = format!("1{}00", "0".repeat(67));
+        let high = format!("1{}11", "0".repeat(67));
+        ket::assert_state!(process, &[(low.as_str(), half), (high.as_str(), half)]);
+
+        let dump = process.dump(&qubits)?;
+        let dump = process.get_dump_data(dump).unwrap();
+        assert_eq!(dump.basis_states, [[0b100000, 0], [0b100000, 0b11]]);
+        assert_eq!(ket::basis::to_bitstring(&dump.basis_states[1], 70), high);
 
         Ok(())
     }
+
+    #[test]
+    fn representations_agree() {
+        use super::{Sparse, SparseMode};
+        use crate::quantum_execution::QuantumExecution;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut simulators = [
+            Sparse::with_mode(12, SparseMode::Hash).unwrap(),
+            Sparse::with_mode(12, SparseMode::Sorted).unwrap(),
+            Sparse::with_mode(12, SparseMode::Auto).unwrap(),
+        ];
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut switched = false;
+
+        for step in 0..300 {
+            let target = rng.gen_range(0..12);
+            let control = (target + rng.gen_range(1..12)) % 12;
+            let theta: f64 = rng.gen();
+            let gate = rng.gen_range(0..8);
+            for simulator in simulators.iter_mut() {
+                match gate {
+                    0 => simulator.pauli_x(target, &[control]),
+                    1 => simulator.pauli_y(target, &[]),
+                    2 => simulator.pauli_z(target, &[control]),
+                    3 | 4 => simulator.hadamard(target, &[]),
+                    5 => simulator.rx(theta, target, &[control]),
+                    6 => simulator.ry(theta, target, &[]),
+                    _ => simulator.rz(theta, target, &[control]),
+                }
+            }
+            switched |= simulators[2].sorted.is_some();
+
+            if step % 100 == 99 {
+                let qubits: Vec<usize> = (0..12).collect();
+                let dumps: Vec<_> = simulators.iter_mut().map(|s| s.dump(&qubits)).collect();
+                for dump in &dumps[1..] {
+                    assert_eq!(dump.basis_states, dumps[0].basis_states);
+                    for (a, b) in dump.amplitudes_real.iter().zip(&dumps[0].amplitudes_real) {
+                        assert!((a - b).abs() < 1e-10);
+                    }
+                    for (a, b) in dump.amplitudes_imag.iter().zip(&dumps[0].amplitudes_imag) {
+                        assert!((a - b).abs() < 1e-10);
+                    }
+                }
+            }
+        }
+
+        assert!(switched);
+    }
+
+    #[test]
+    fn wide_registers() {
+        use super::{Sparse, SparseMode};
+        use crate::quantum_execution::QuantumExecution;
+
+        // The first qubit of the dumps is the most significant bit.
+        let qubits: Vec<usize> = (0..100).rev().collect();
+        let mut dumps = Vec::new();
+        for mode in [SparseMode::Hash, SparseMode::Sorted, SparseMode::Auto] {
+            let mut sparse = Sparse::with_mode(100, mode).unwrap();
+            for qubit in 0..64 {
+                sparse.pauli_x(qubit, &[]);
+            }
+            sparse.hadamard(99, &[]);
+
+            // Adds one to the 99 low qubits, carrying into the second word.
+            for target in (0..99).rev() {
+                let control: Vec<usize> = (0..target).collect();
+                sparse.pauli_x(target, &control);
+            }
+            let dump = sparse.dump(&qubits);
+            let values: Vec<_> = dump
+                .basis_states
+                .iter()
+                .map(|state| ket::basis::to_u128(state))
+                .collect();
+            assert_eq!(values, [Some(1 << 64), Some(1 << 99 | 1 << 64)]);
+
+            // 2048 basis states switch the automatic mode to the sorted vector, and back.
+            for qubit in 89..99 {
+                sparse.hadamard(qubit, &[]);
+            }
+            assert_eq!(sparse.sorted.is_some(), mode != SparseMode::Hash);
+            dumps.push(sparse.dump(&qubits));
+            for qubit in 89..99 {
+                sparse.hadamard(qubit, &[]);
+            }
+            assert_eq!(sparse.sorted.is_some(), mode == SparseMode::Sorted);
+            assert_eq!(sparse.dump(&qubits).basis_states, dump.basis_states);
+        }
+
+        for dump in &dumps[1..] {
+            assert_eq!(dump.basis_states.len(), 2048);
+            assert_eq!(dump.basis_states, dumps[0].basis_states);
+        }
+    }
 }
@@ -0,0 +1,143 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Thread pool configuration of the simulators.
+
+use std::sync::Arc;
+
+use log::warn;
+#[cfg(feature = "parallel")]
+use {log::info, rayon::ThreadPoolBuilder};
+
+#[cfg(feature = "parallel")]
+use crate::error::KBWError;
+use crate::{error::Result, parallel::ThreadPool};
+
+/// Threads used by a simulator instance.
+///
+/// By default, the simulators run on the global rayon pool, shared with the rest of the
+/// program. A dedicated pool isolates the simulator from other parallel work and, with
+/// pinned threads, keeps each thread on the same core for the whole execution.
+///
+/// The dense simulator initializes its state vectors in parallel, so each memory page is
+/// first touched, and thus placed on the NUMA node of, the thread that processes it.
+/// With `interleave`, the pinned threads are spread over all cores of the machine, and
+/// the state vectors are distributed across the NUMA nodes.
+///
+/// Without the `parallel` feature, the simulators run on the calling thread and the
+/// configuration is ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadConfig {
+    /// Number of threads, or `0` to use the global pool.
+    pub num_threads: usize,
+
+    /// Pins each thread to a core.
+    pub pin_threads: bool,
+
+    /// Spreads the pinned threads evenly over all cores instead of using the first
+    /// `num_threads` cores.
+    pub interleave: bool,
+}
+
+fn env_flag(name: &str) -> bool {
+    matches!(
+        std::env::var(name)
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str(),
+        "1" | "true" | "yes"
+    )
+}
+
+impl ThreadConfig {
+    /// Reads the configuration from the environment variables `KBW_NUM_THREADS`,
+    /// `KBW_PIN_THREADS`, and `KBW_NUMA_INTERLEAVE`.
+    pub fn from_env() -> Self {
+        Self {
+            num_threads: std::env::var("KBW_NUM_THREADS")
+                .unwrap_or_default()
+                .parse()
+                .unwrap_or(0),
+            pin_threads: env_flag("KBW_PIN_THREADS"),
+            interleave: env_flag("KBW_NUMA_INTERLEAVE"),
+        }
+    }
+
+    /// Builds the thread pool, or returns `None` to use the global pool.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn build_pool(&self) -> Result<Option<Arc<ThreadPool>>> {
+        if self.num_threads == 0 && !self.pin_threads {
+            return Ok(None);
+        }
+
+        let cores = if self.pin_threads {
+            core_affinity::get_core_ids().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        if self.pin_threads && cores.is_empty() {
+            warn!("cannot read the available cores; threads will not be pinned");
+        }
+
+        let num_threads = match self.num_threads {
+            0 if !cores.is_empty() => cores.len(),
+            0 => rayon::current_num_threads(),
+            num_threads => num_threads,
+        };
+
+        let interleave = self.interleave;
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|index| format!("kbw-{}", index))
+            .start_handler(move |index| {
+                if cores.is_empty() {
+                    return;
+                }
+                let core = if interleave {
+                    cores[index * cores.len() / num_threads % cores.len()]
+                } else {
+                    cores[index % cores.len()]
+                };
+                if !core_affinity::set_for_current(core) {
+                    warn!("cannot pin thread {} to core {:?}", index, core);
+                }
+            })
+            .build()
+            .map_err(|_| KBWError::ThreadPoolError)?;
+
+        info!(
+            "KBW thread pool threads={} pinned={} interleave={}",
+            num_threads, self.pin_threads, self.interleave
+        );
+
+        Ok(Some(Arc::new(pool)))
+    }
+
+    /// Returns `None`, as there is no thread pool without the `parallel` feature.
+    #[cfg(not(feature = "parallel"))]
+    pub(crate) fn build_pool(&self) -> Result<Option<Arc<ThreadPool>>> {
+        if self.num_threads > 1 || self.pin_threads {
+            warn!("KBW was built without the parallel feature; running on a single thread");
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::ThreadConfig;
+
+    #[test]
+    fn dedicated_pool() {
+        let config = ThreadConfig {
+            num_threads: 2,
+            ..Default::default()
+        };
+        let pool = config.build_pool().unwrap().unwrap();
+        assert_eq!(pool.install(rayon::current_num_threads), 2);
+
+        assert!(ThreadConfig::default().build_pool().unwrap().is_none());
+    }
+}
@@ -0,0 +1,190 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Process tomography of noisy circuits.
+//!
+//! The Choi matrix of a channel `L` on `d`-dimensional states is
+//! `J = sum_ij |i><j| (x) L(|i><j|)`. It is computed by running the circuit with the
+//! noise model over every operator `|i><j|` of the input basis on the density matrix
+//! simulator. As in [`crate::unitary`], the first allocated qubit is the most
+//! significant bit of the basis state index.
+//!
+//! ```rust
+//! # use ket::{Configuration, Process, QuantumGate};
+//! use kbw::noise::{Channel, NoiseModel};
+//! use kbw::tomography::{choi, process_fidelity};
+//! use kbw::unitary::ToUnitary;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut process = Process::new(Configuration::new(1));
+//! let qubit = process.allocate_qubit()?;
+//! process.apply_gate(QuantumGate::Hadamard, qubit)?;
+//!
+//! let noise = NoiseModel::after_gates(Channel::Depolarizing(0.1));
+//! let fidelity = process_fidelity(&choi(process.instructions(), &noise)?, &process.to_unitary()?);
+//! assert!((fidelity - 0.925).abs() < 1e-10);
+//! # Ok(())
+//! # }
+//! ```
+
+use ket::Instruction;
+use num::{complex::Complex64, Zero};
+
+use crate::{
+    density::DensityMatrix,
+    error::{KBWError, Result},
+    noise::NoiseModel,
+    parallel::*,
+    unitary::Matrix,
+};
+
+/// Largest number of qubits accepted by [`choi`].
+///
+/// The Choi matrix of `n` qubits has `16^n` entries and takes `4^n` simulations of
+/// `4^n` entries each to compute.
+pub const MAX_CHOI_QUBITS: usize = 6;
+
+/// Computes the Choi matrix of the gates in a list of instructions under a noise model.
+///
+/// The matrix acts on every qubit allocated in the instructions, in order of
+/// allocation, and it is not normalized: its trace is `d = 2^n`. Kraus channels in the
+/// instructions are applied in place, in addition to the noise model. Dumps, samples,
+/// and expected values are ignored.
+///
+/// # Errors
+///
+/// Returns [`KBWError::UnsupportedNumberOfQubits`] if more than [`MAX_CHOI_QUBITS`]
+/// qubits are allocated, [`KBWError::NonUnitaryInstruction`] if the instructions
+/// include a measurement, and [`KBWError::InvalidNoiseModel`] if the noise model is
+/// not valid.
+pub fn choi(instructions: &[Instruction], noise: &NoiseModel) -> Result<Matrix> {
+    noise.validate()?;
+    // The global phases of the synthesis cancel out in the density matrix.
+    let instructions = &ket::multiplexor::lower(instructions)[..];
+
+    let mut qubits = Vec::new();
+    for instruction in instructions {
+        match instruction {
+            Instruction::Alloc { target } => qubits.push(*target),
+            Instruction::Measure { .. } => return Err(KBWError::NonUnitaryInstruction),
+            _ => {}
+        }
+    }
+
+    let num_qubits = qubits.len();
+    if num_qubits > MAX_CHOI_QUBITS {
+        return Err(KBWError::UnsupportedNumberOfQubits);
+    }
+
+    // The first allocated qubit is the most significant bit.
+    let qubit_of =
+        |target: &usize| num_qubits - 1 - qubits.iter().position(|qubit| qubit == target).unwrap();
+
+    let size = 1usize << num_qubits;
+    let outputs = (0..size * size)
+        .into_par_iter()
+        .map(|input| {
+            let mut rho = DensityMatrix::basis_operator(num_qubits, input / size, input % size)?;
+            for instruction in instructions {
+                match instruction {
+                    Instruction::Gate {
+                        gate,
+                        target,
+                        control,
+                    } => {
+                        let control: Vec<usize> = control.iter().map(qubit_of).collect();
+                        rho.noisy_gate(gate, qubit_of(target), &control, noise);
+                    }
+                    Instruction::KrausChannel { operators, target } => {
+                        let operators: Vec<_> = operators.iter().map(|k| k.matrix()).collect();
+                        rho.kraus(&operators, qubit_of(target));
+                    }
+                    _ => {}
+                }
+            }
+            Ok(rho)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut matrix = vec![vec![Complex64::zero(); size * size]; size * size];
+    for (input, rho) in outputs.iter().enumerate() {
+        let (i, j) = (input / size, input % size);
+        for a in 0..size {
+            for b in 0..size {
+                matrix[i * size + a][j * size + b] = rho.get(a, b);
+            }
+        }
+    }
+
+    Ok(matrix)
+}
+
+/// Process fidelity between the channel of a Choi matrix and a unitary matrix.
+///
+/// The result is `1` if the channel is the unitary, up to a global phase, and
+/// decreases as the noise degrades the circuit.
+pub fn process_fidelity(choi: &[Vec<Complex64>], unitary: &[Vec<Complex64>]) -> f64 {
+    let size = unitary.len();
+
+    // Vectorized unitary, |U>> = sum_i |i> (x) U|i>.
+    let vector: Vec<Complex64> = (0..size * size)
+        .map(|index| unitary[index % size][index / size])
+        .collect();
+
+    let fidelity: Complex64 = vector
+        .iter()
+        .zip(choi)
+        .map(|(left, row)| {
+            left.conj()
+                * row
+                    .iter()
+                    .zip(&vector)
+                    .map(|(entry, right)| entry * right)
+                    .sum::<Complex64>()
+        })
+        .sum();
+
+    fidelity.re / (size * size) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use ket::{Configuration, Process, QuantumGate};
+
+    use super::{choi, process_fidelity};
+    use crate::{
+        noise::{Channel, NoiseModel},
+        unitary::ToUnitary,
+    };
+
+    #[test]
+    fn choi_of_noisy_cnot() {
+        let mut process = Process::new(Configuration::new(2));
+        let a = process.allocate_qubit().unwrap();
+        let b = process.allocate_qubit().unwrap();
+        process.ctrl_push(&[a]).unwrap();
+        process.apply_gate(QuantumGate::PauliX, b).unwrap();
+        process.ctrl_pop().unwrap();
+        let unitary = process.to_unitary().unwrap();
+
+        let ideal = choi(process.instructions(), &NoiseModel::default()).unwrap();
+        assert!((process_fidelity(&ideal, &unitary) - 1.0).abs() < 1e-12);
+
+        // The ideal Choi matrix is |U>><<U|, so its entries are products of entries of U.
+        assert!((ideal[0][0].re - 1.0).abs() < 1e-12);
+        assert!((ideal[0b1011][0b1011].re - 1.0).abs() < 1e-12);
+        assert!(ideal[0b1010][0b1010].norm() < 1e-12);
+
+        // Two independent bit flips, one on each qubit.
+        let noisy = choi(
+            process.instructions(),
+            &NoiseModel::after_gates(Channel::BitFlip(0.1)),
+        )
+        .unwrap();
+        assert!((process_fidelity(&noisy, &unitary) - 0.81).abs() < 1e-12);
+
+        let trace: f64 = (0..16).map(|index| noisy[index][index].re).sum();
+        assert!((trace - 4.0).abs() < 1e-12);
+    }
+}
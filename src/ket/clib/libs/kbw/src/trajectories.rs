@@ -0,0 +1,534 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Monte Carlo simulation of noisy circuits by stochastic trajectories.
+//!
+//! Each trajectory runs the circuit on a state vector simulator, drawing one Kraus
+//! operator of every channel, see [`ket::Process::apply_kraus_channel`]. The average
+//! of a quantity over the trajectories converges to its value on the density matrix,
+//! with a standard error that decreases as `1/sqrt(N)`, and the memory of each
+//! trajectory is that of a state vector instead of a density matrix.
+//!
+//! [`simulate_shots`] and the [`NoisyExecution`] batch executor run each shot of the
+//! samples as a trajectory of its own, so the counts have the noisy statistics instead
+//! of being drawn from a single final state.
+//!
+//! ```rust
+//! # use ket::{Configuration, Process, QuantumGate};
+//! use kbw::dense::Dense;
+//! use kbw::noise::{Channel, NoiseModel};
+//! use kbw::trajectories::{simulate_trajectories, TrajectoryConfig};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut process = Process::new(Configuration::new(1));
+//! let qubit = process.allocate_qubit()?;
+//! process.apply_gate(QuantumGate::PauliX, qubit)?;
+//! process.measure(&[qubit])?;
+//!
+//! let config = TrajectoryConfig {
+//!     trajectories: 1000,
+//!     noise: NoiseModel::after_gates(Channel::BitFlip(0.1)),
+//!     seed: Some(42),
+//! };
+//! let result = simulate_trajectories::<Dense>(process.instructions(), &config)?;
+//! let one = result.probability(0, 1);
+//! assert!((one.mean - 0.9).abs() < 5.0 * one.standard_error);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use ket::{ir::KrausOperator, BatchExecution, Instruction};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    error::{KBWError, Result},
+    noise::NoiseModel,
+    parallel::*,
+    quantum_execution::{QuantumExecution, QubitManager},
+    threads::ThreadConfig,
+};
+
+/// Configuration of [`simulate_trajectories`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrajectoryConfig {
+    /// Number of trajectories.
+    pub trajectories: usize,
+
+    /// Noise added to the circuit, in addition to its Kraus channels.
+    pub noise: NoiseModel,
+
+    /// Seed of the trajectories, or `None` for a random seed.
+    ///
+    /// The seed of each trajectory is drawn from this seed before the trajectories
+    /// run, so the result does not depend on the number of threads.
+    pub seed: Option<u64>,
+}
+
+/// Mean of a quantity over the trajectories.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Sample mean.
+    pub mean: f64,
+
+    /// Standard error of the mean, `NaN` for a single trajectory.
+    pub standard_error: f64,
+}
+
+impl Estimate {
+    /// Estimate from the sum and the sum of squares of `n` values.
+    fn from_sums(sum: f64, sum_of_squares: f64, n: usize) -> Self {
+        let mean = sum / n as f64;
+        let variance = if n > 1 {
+            ((sum_of_squares - n as f64 * mean * mean) / (n - 1) as f64).max(0.0)
+        } else {
+            f64::NAN
+        };
+        Self {
+            mean,
+            standard_error: (variance / n as f64).sqrt(),
+        }
+    }
+}
+
+/// Results aggregated over the trajectories.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrajectoryResult {
+    /// Number of trajectories.
+    pub trajectories: usize,
+
+    /// Mean of each expected value.
+    pub exp_values: Vec<Estimate>,
+
+    /// Number of trajectories with each outcome, for each measurement.
+    pub measurements: Vec<BTreeMap<u64, u64>>,
+
+    /// Shots with each outcome, summed over the trajectories, for each sample.
+    pub samples: Vec<BTreeMap<u64, u64>>,
+
+    /// Outcome of each measurement in the first trajectory.
+    pub first_measurements: Vec<u64>,
+}
+
+impl TrajectoryResult {
+    /// Frequency of an outcome of a measurement, with its binomial standard error.
+    pub fn probability(&self, measurement: usize, outcome: u64) -> Estimate {
+        let count = self.measurements[measurement]
+            .get(&outcome)
+            .copied()
+            .unwrap_or(0);
+        let n = self.trajectories as f64;
+        let p = count as f64 / n;
+        Estimate {
+            mean: p,
+            standard_error: (p * (1.0 - p) / n).sqrt(),
+        }
+    }
+}
+
+/// Sums of the results of a set of trajectories.
+#[derive(Debug, Default)]
+struct Accumulator {
+    trajectories: usize,
+    exp_values: Vec<(f64, f64)>,
+    measurements: Vec<BTreeMap<u64, u64>>,
+    samples: Vec<BTreeMap<u64, u64>>,
+    /// Index and measurements of the first trajectory added.
+    first: Option<(usize, Vec<u64>)>,
+}
+
+fn merge_counts(a: &mut Vec<BTreeMap<u64, u64>>, b: Vec<BTreeMap<u64, u64>>) {
+    a.resize_with(a.len().max(b.len()), BTreeMap::new);
+    for (a, b) in a.iter_mut().zip(b) {
+        for (outcome, count) in b {
+            *a.entry(outcome).or_default() += count;
+        }
+    }
+}
+
+impl Accumulator {
+    /// Adds the result of the trajectory `index`, keeping only the samples that have
+    /// more than `index` shots in `sample_shots`, if given.
+    fn add(&mut self, index: usize, result: ket::ResultData, sample_shots: Option<&[u64]>) {
+        let exp_values = result
+            .exp_values
+            .iter()
+            .map(|value| (*value, value * value));
+        self.merge(Accumulator {
+            trajectories: 1,
+            exp_values: exp_values.collect(),
+            measurements: result
+                .measurements
+                .iter()
+                .map(|outcome| BTreeMap::from([(*outcome, 1)]))
+                .collect(),
+            samples: result
+                .samples
+                .into_iter()
+                .enumerate()
+                .map(|(sample, (states, counts))| match sample_shots {
+                    Some(shots) if shots[sample] <= index as u64 => BTreeMap::new(),
+                    _ => states.into_iter().zip(counts).collect(),
+                })
+                .collect(),
+            first: Some((index, result.measurements)),
+        });
+    }
+
+    fn merge(&mut self, other: Accumulator) {
+        self.trajectories += other.trajectories;
+        match (&self.first, other.first) {
+            (Some((index, _)), Some(other)) if other.0 < *index => self.first = Some(other),
+            (None, other) => self.first = other,
+            _ => {}
+        }
+        self.exp_values.resize(
+            self.exp_values.len().max(other.exp_values.len()),
+            (0.0, 0.0),
+        );
+        for (a, b) in self.exp_values.iter_mut().zip(other.exp_values) {
+            a.0 += b.0;
+            a.1 += b.1;
+        }
+        merge_counts(&mut self.measurements, other.measurements);
+        merge_counts(&mut self.samples, other.samples);
+    }
+}
+
+/// Returns the instructions with the Kraus channels of the noise model after each gate.
+///
+/// Diagonal gates and multiplexed rotations are replaced by their gates, so the noise
+/// follows each CNOT and rotation.
+fn with_noise(instructions: &[Instruction], noise: &NoiseModel) -> Vec<Instruction> {
+    let Some(channel) = &noise.gate else {
+        return instructions.to_vec();
+    };
+    let instructions = ket::multiplexor::lower(instructions);
+    let operators: Vec<KrausOperator> = channel
+        .kraus()
+        .into_iter()
+        .map(KrausOperator::from_matrix)
+        .collect();
+
+    let mut result = Vec::with_capacity(instructions.len());
+    for instruction in instructions.iter() {
+        result.push(instruction.clone());
+        if let Instruction::Gate {
+            target, control, ..
+        } = instruction
+        {
+            result.extend(
+                control
+                    .iter()
+                    .chain([target])
+                    .map(|qubit| Instruction::KrausChannel {
+                        operators: operators.clone(),
+                        target: *qubit,
+                    }),
+            );
+        }
+    }
+    result
+}
+
+/// Runs the instructions of a process in `config.trajectories` stochastic trajectories
+/// across the rayon workers, and aggregates the measurements, samples, and expected
+/// values. Dumps are ignored.
+///
+/// Each trajectory draws all the shots of a sample from its own final state, see
+/// [`simulate_shots`] to draw each shot from a different trajectory.
+///
+/// # Errors
+///
+/// Returns [`KBWError::InvalidNumberOfTrajectories`] if `config.trajectories` is `0`,
+/// [`KBWError::InvalidNoiseModel`] if the noise model is not valid, and the errors of
+/// the simulator creation.
+pub fn simulate_trajectories<S: QuantumExecution + 'static>(
+    instructions: &[Instruction],
+    config: &TrajectoryConfig,
+) -> Result<TrajectoryResult> {
+    if config.trajectories == 0 {
+        return Err(KBWError::InvalidNumberOfTrajectories);
+    }
+    config.noise.validate()?;
+
+    let instructions = with_noise(instructions, &config.noise);
+    run_trajectories::<S>(&instructions, config.trajectories, config.seed, None)
+}
+
+/// Runs every shot of the samples of a process as an independent trajectory, so the
+/// sampled counts follow the noisy statistics of the circuit.
+///
+/// The number of trajectories is the largest number of shots of a sample, and each
+/// trajectory draws one shot of the samples that have not yet reached their number of
+/// shots. The seed of each trajectory is drawn from `seed` before the trajectories run
+/// in parallel, so the counts do not depend on the number of threads.
+///
+/// # Errors
+///
+/// Returns [`KBWError::InvalidNoiseModel`] if the noise model is not valid, and the
+/// errors of the simulator creation.
+pub fn simulate_shots<S: QuantumExecution + 'static>(
+    instructions: &[Instruction],
+    noise: &NoiseModel,
+    seed: Option<u64>,
+) -> Result<TrajectoryResult> {
+    noise.validate()?;
+
+    let mut sample_shots = Vec::new();
+    let instructions: Vec<Instruction> = with_noise(instructions, noise)
+        .into_iter()
+        .map(|instruction| match instruction {
+            Instruction::Sample {
+                qubits,
+                shots,
+                output,
+            } => {
+                sample_shots.push(shots);
+                Instruction::Sample {
+                    qubits,
+                    shots: 1,
+                    output,
+                }
+            }
+            instruction => instruction,
+        })
+        .collect();
+    let trajectories = sample_shots.iter().copied().max().unwrap_or(1).max(1) as usize;
+
+    run_trajectories::<S>(&instructions, trajectories, seed, Some(&sample_shots))
+}
+
+fn run_trajectories<S: QuantumExecution + 'static>(
+    instructions: &[Instruction],
+    trajectories: usize,
+    seed: Option<u64>,
+    sample_shots: Option<&[u64]>,
+) -> Result<TrajectoryResult> {
+    let num_qubits = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Alloc { target } => Some(target + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut rng = StdRng::seed_from_u64(seed.unwrap_or_else(rand::random));
+    let seeds: Vec<u64> = (0..trajectories).map(|_| rng.gen()).collect();
+
+    let sums = seeds
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, seed)| {
+            let mut simulator =
+                QubitManager::<S>::with_options(num_qubits, Some(seed), &ThreadConfig::default())?;
+            simulator.submit_execution(instructions);
+            Ok((index, simulator.get_result()))
+        })
+        .try_fold(Accumulator::default, |mut sums, result: Result<_>| {
+            let (index, result) = result?;
+            sums.add(index, result, sample_shots);
+            Ok(sums)
+        })
+        .try_reduce(Accumulator::default, |mut a, b| {
+            a.merge(b);
+            Ok(a)
+        })?;
+
+    Ok(TrajectoryResult {
+        trajectories: sums.trajectories,
+        exp_values: sums
+            .exp_values
+            .into_iter()
+            .map(|(sum, sum_of_squares)| {
+                Estimate::from_sums(sum, sum_of_squares, sums.trajectories)
+            })
+            .collect(),
+        measurements: sums.measurements,
+        samples: sums.samples,
+        first_measurements: sums.first.map(|(_, first)| first).unwrap_or_default(),
+    })
+}
+
+/// Batch executor that runs every shot as a noisy trajectory, see [`simulate_shots`].
+///
+/// The sampled counts follow the noise model, the expected values are averaged over
+/// the trajectories, and the measurements, which are single-shot, come from the first
+/// trajectory. Dumps are not supported.
+pub struct NoisyExecution<S> {
+    noise: NoiseModel,
+    seed: Option<u64>,
+    result: Option<ket::ResultData>,
+    simulator: PhantomData<S>,
+}
+
+impl<S: QuantumExecution + 'static> NoisyExecution<S> {
+    /// Creates the executor.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KBWError::InvalidNoiseModel`] if the noise model is not valid.
+    pub fn new(noise: NoiseModel, seed: Option<u64>) -> Result<Self> {
+        noise.validate()?;
+        Ok(Self {
+            noise,
+            seed,
+            result: None,
+            simulator: PhantomData,
+        })
+    }
+
+    /// Returns a batch configuration with this executor.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KBWError::InvalidNoiseModel`] if the noise model is not valid.
+    pub fn configuration(
+        num_qubits: usize,
+        noise: NoiseModel,
+        seed: Option<u64>,
+    ) -> Result<ket::Configuration> {
+        Ok(ket::Configuration {
+            allow_measure: true,
+            allow_sample: true,
+            allow_exp_value: true,
+            allow_dump: false,
+            valid_after_measure: true,
+            continue_after_sample: true,
+            continue_after_exp_value: true,
+            continue_after_dump: false,
+            decompose: false,
+            live_quantum_execution: None,
+            num_qubits,
+            batch_execution: Some(Box::new(Self::new(noise, seed)?)),
+            execution_timeout: None,
+            optimize: false,
+            optimization_cache: None,
+            bit_order: ket::BitOrder::MsbFirst,
+            chunk_size: None,
+            initial_state: None,
+            import_limits: ket::qasmv2::ImportLimits::default(),
+        })
+    }
+}
+
+impl<S: QuantumExecution + 'static> BatchExecution for NoisyExecution<S> {
+    fn submit_execution(&mut self, instructions: &[Instruction]) {
+        let result = simulate_shots::<S>(instructions, &self.noise, self.seed)
+            .expect("the simulator of the noisy shots cannot be created");
+        self.result = Some(ket::ResultData {
+            measurements: result.first_measurements,
+            exp_values: result.exp_values.iter().map(|value| value.mean).collect(),
+            samples: result
+                .samples
+                .into_iter()
+                .map(|counts| counts.into_iter().unzip())
+                .collect(),
+            dumps: Vec::new(),
+            execution_time: None,
+        });
+    }
+
+    fn get_result(&mut self) -> ket::ResultData {
+        self.result.take().unwrap()
+    }
+
+    fn get_status(&self) -> ket::ExecutionStatus {
+        ket::ExecutionStatus::Completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simulate_shots, simulate_trajectories, NoisyExecution, TrajectoryConfig};
+    use crate::{
+        dense::Dense,
+        noise::{Channel, NoiseModel},
+        sparse::Sparse,
+    };
+    use ket::{Configuration, PauliHamiltonian, Process, QuantumGate};
+
+    #[test]
+    fn bit_flip_statistics() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mut process = Process::new(Configuration::new(2));
+        let qubit = process.allocate_qubit()?;
+        let other = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::PauliX, qubit)?;
+        process.exp_values(PauliHamiltonian::from_openfermion("1.0 [Z0]")?)?;
+        process.sample(&[qubit, other], 10)?;
+        process.measure(&[qubit])?;
+
+        let config = TrajectoryConfig {
+            trajectories: 2000,
+            noise: NoiseModel::after_gates(Channel::BitFlip(0.2)),
+            seed: Some(7),
+        };
+        let result = simulate_trajectories::<Dense>(process.instructions(), &config)?;
+        assert_eq!(result.trajectories, 2000);
+
+        // <Z> = 0.2 - 0.8
+        let z = result.exp_values[0];
+        assert!((z.mean + 0.6).abs() < 5.0 * z.standard_error);
+        assert!(z.standard_error > 0.0 && z.standard_error < 0.05);
+
+        let one = result.probability(0, 1);
+        assert!((one.mean - 0.8).abs() < 5.0 * one.standard_error);
+        assert_eq!(result.measurements[0].values().sum::<u64>(), 2000);
+        assert_eq!(result.samples[0].values().sum::<u64>(), 20000);
+
+        // The trajectories are seeded before they run.
+        let sparse = simulate_trajectories::<Sparse>(process.instructions(), &config)?;
+        assert_eq!(
+            sparse.measurements,
+            simulate_trajectories::<Sparse>(process.instructions(), &config)?.measurements
+        );
+
+        let config = TrajectoryConfig {
+            trajectories: 0,
+            ..config
+        };
+        assert!(simulate_trajectories::<Dense>(process.instructions(), &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn noisy_shots() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mut process = Process::new(Configuration::new(1));
+        let qubit = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::PauliX, qubit)?;
+        process.sample(&[qubit], 1000)?;
+        process.sample(&[qubit], 10)?;
+
+        let noise = NoiseModel::after_gates(Channel::BitFlip(0.1));
+        let result = simulate_shots::<Dense>(process.instructions(), &noise, Some(3))?;
+        assert_eq!(result.trajectories, 1000);
+        assert_eq!(result.samples[0].values().sum::<u64>(), 1000);
+        assert_eq!(result.samples[1].values().sum::<u64>(), 10);
+        let ones = result.samples[0][&1] as f64;
+        assert!((ones - 900.0).abs() < 5.0 * (1000.0 * 0.1 * 0.9f64).sqrt());
+        assert_eq!(
+            result.samples,
+            simulate_shots::<Dense>(process.instructions(), &noise, Some(3))?.samples
+        );
+
+        let mut process = Process::new(NoisyExecution::<Dense>::configuration(1, noise, Some(3))?);
+        let qubit = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::PauliX, qubit)?;
+        let sample = process.sample(&[qubit], 1000)?;
+        process.prepare_for_execution()?;
+        let (states, counts) = process.get_sample(sample).result.clone().unwrap();
+        assert_eq!(counts.iter().sum::<u64>(), 1000);
+        assert_eq!(states.len(), 2);
+
+        assert!(
+            NoisyExecution::<Dense>::new(NoiseModel::after_gates(Channel::BitFlip(1.5)), None)
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Unitary matrix of a quantum circuit.
+//!
+//! Column `j` of the matrix is the state obtained by applying the circuit to the basis
+//! state `|j>`, so the matrix is computed with one dense simulation per basis state.
+//! Rows and columns follow the ordering of the dumps: the first allocated qubit is the
+//! most significant bit of the basis state index.
+//!
+//! ```rust
+//! # use ket::{Configuration, Process, QuantumGate};
+//! use kbw::unitary::{equivalent, ToUnitary};
+//! # use num::complex::Complex64;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut process = Process::new(Configuration::new(1));
+//! let qubit = process.allocate_qubit()?;
+//! process.apply_gate(QuantumGate::Hadamard, qubit)?;
+//! process.apply_gate(QuantumGate::PauliZ, qubit)?;
+//! process.apply_gate(QuantumGate::Hadamard, qubit)?;
+//!
+//! let one = Complex64::from(1.0);
+//! let zero = Complex64::from(0.0);
+//! assert!(equivalent(&process.to_unitary()?, &[vec![zero, one], vec![one, zero]], 1e-10));
+//! # Ok(())
+//! # }
+//! ```
+
+use ket::{Instruction, LiveExecution, Process, QuantumGate};
+use num::complex::Complex64;
+
+use crate::{
+    dense::Dense,
+    error::{KBWError, Result},
+    parallel::*,
+    quantum_execution::QubitManager,
+    threads::ThreadConfig,
+};
+
+/// Largest number of qubits accepted by [`unitary`].
+///
+/// The matrix of `n` qubits has `4^n` entries and takes `2^n` simulations to compute.
+pub const MAX_UNITARY_QUBITS: usize = 12;
+
+/// Square complex matrix, indexed by row and then by column.
+pub type Matrix = Vec<Vec<Complex64>>;
+
+/// Computes the unitary matrix of the gates in a list of instructions.
+///
+/// The matrix acts on every qubit allocated in the instructions, in order of
+/// allocation. Freeing a qubit does not remove it from the matrix, and dumps,
+/// samples, and expected values are ignored, as they do not change the state.
+///
+/// # Errors
+///
+/// Returns [`KBWError::UnsupportedNumberOfQubits`] if more than
+/// [`MAX_UNITARY_QUBITS`] qubits are allocated, and
+/// [`KBWError::NonUnitaryInstruction`] if the instructions include a measurement or
+/// a Kraus channel.
+pub fn unitary(instructions: &[Instruction]) -> Result<Matrix> {
+    let mut qubits = Vec::new();
+    for instruction in instructions {
+        match instruction {
+            Instruction::Alloc { target } => qubits.push(*target),
+            Instruction::Measure { .. } | Instruction::KrausChannel { .. } => {
+                return Err(KBWError::NonUnitaryInstruction)
+            }
+            _ => {}
+        }
+    }
+
+    let num_qubits = qubits.len();
+    if num_qubits > MAX_UNITARY_QUBITS {
+        return Err(KBWError::UnsupportedNumberOfQubits);
+    }
+
+    let size = 1usize << num_qubits;
+
+    let columns = (0..size)
+        .into_par_iter()
+        .map(|column| {
+            let mut simulator =
+                QubitManager::<Dense>::with_options(num_qubits, Some(0), &ThreadConfig::default())?;
+            for (index, qubit) in qubits.iter().enumerate() {
+                simulator.alloc(*qubit);
+                if column >> (num_qubits - index - 1) & 1 == 1 {
+                    simulator.gate(&QuantumGate::PauliX, *qubit, &[]);
+                }
+            }
+
+            for instruction in instructions {
+                match instruction {
+                    Instruction::Gate {
+                        gate,
+                        target,
+                        control,
+                    } => simulator.gate(gate, *target, control),
+                    // The kernels keep the global phase that the synthesis leaves out.
+                    Instruction::Diagonal { phases, qubits } => simulator.diagonal(phases, qubits),
+                    Instruction::Multiplexor {
+                        axis,
+                        angles,
+                        control,
+                        target,
+                    } => simulator.multiplexor(*axis, angles, control, *target),
+                    _ => {}
+                }
+            }
+
+            let dump = simulator.dump(&qubits);
+            let mut amplitudes = vec![Complex64::default(); size];
+            for ((state, real), imag) in dump
+                .basis_states
+                .iter()
+                .zip(dump.amplitudes_real)
+                .zip(dump.amplitudes_imag)
+            {
+                amplitudes[ket::basis::to_u64(state).unwrap() as usize] =
+                    Complex64::new(real, imag);
+            }
+
+            Ok(amplitudes)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((0..size)
+        .map(|row| columns.iter().map(|column| column[row]).collect())
+        .collect())
+}
+
+/// Returns `true` if the matrices are equal up to a global phase, with each entry
+/// within `tolerance` of the other.
+pub fn equivalent(a: &[Vec<Complex64>], b: &[Vec<Complex64>], tolerance: f64) -> bool {
+    if a.len() != b.len() || a.iter().zip(b).any(|(a, b)| a.len() != b.len()) {
+        return false;
+    }
+
+    // The global phase is taken from the largest entry, which has the smallest relative
+    // rounding error.
+    let (row, column) = (0..a.len())
+        .flat_map(|row| (0..a[row].len()).map(move |column| (row, column)))
+        .max_by(|x, y| a[x.0][x.1].norm().total_cmp(&a[y.0][y.1].norm()))
+        .unwrap_or((0, 0));
+    let phase = match (a.get(row), b.get(row)) {
+        (Some(a_row), Some(b_row)) if a_row[column].norm() > tolerance => {
+            let ratio = b_row[column] / a_row[column];
+            ratio / ratio.norm()
+        }
+        _ => Complex64::from(1.0),
+    };
+
+    a.iter().zip(b).all(|(a, b)| {
+        a.iter()
+            .zip(b)
+            .all(|(a, b)| (a * phase - b).norm() <= tolerance)
+    })
+}
+
+/// Raises a square matrix to a power by repeated squaring.
+pub fn power(matrix: &[Vec<Complex64>], exponent: u64) -> Matrix {
+    let multiply = |a: &Matrix, b: &Matrix| -> Matrix {
+        a.par_iter()
+            .map(|row| {
+                (0..b.len())
+                    .map(|column| row.iter().zip(b).map(|(a, b)| a * b[column]).sum())
+                    .collect()
+            })
+            .collect()
+    };
+
+    let size = matrix.len();
+    let mut result: Matrix = (0..size)
+        .map(|row| {
+            (0..size)
+                .map(|column| Complex64::from(if row == column { 1.0 } else { 0.0 }))
+                .collect()
+        })
+        .collect();
+    let mut base = matrix.to_vec();
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(&result, &base);
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = multiply(&base, &base);
+        }
+    }
+    result
+}
+
+/// Computes the unitary matrix of a quantum circuit.
+pub trait ToUnitary {
+    /// Returns the unitary matrix of the circuit, see [`unitary`].
+    fn to_unitary(&self) -> Result<Matrix>;
+}
+
+impl ToUnitary for Process {
+    fn to_unitary(&self) -> Result<Matrix> {
+        unitary(self.instructions())
+    }
+}
+
+impl ToUnitary for [Instruction] {
+    fn to_unitary(&self) -> Result<Matrix> {
+        unitary(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use ket::{Configuration, Instruction, Process, QuantumGate};
+    use num::complex::Complex64;
+
+    use super::{equivalent, power, unitary, ToUnitary};
+    use crate::error::KBWError;
+
+    fn real(matrix: &[&[f64]]) -> Vec<Vec<Complex64>> {
+        matrix
+            .iter()
+            .map(|row| row.iter().map(|x| Complex64::from(*x)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn textbook_matrices() {
+        let mut process = Process::new(Configuration::new(2));
+        let a = process.allocate_qubit().unwrap();
+        process.allocate_qubit().unwrap();
+        process.apply_gate(QuantumGate::Hadamard, a).unwrap();
+        let h = process.to_unitary().unwrap();
+        let s = FRAC_1_SQRT_2;
+        assert!(equivalent(
+            &h,
+            &real(&[
+                &[s, 0.0, s, 0.0],
+                &[0.0, s, 0.0, s],
+                &[s, 0.0, -s, 0.0],
+                &[0.0, s, 0.0, -s],
+            ]),
+            1e-12
+        ));
+
+        let mut process = Process::new(Configuration::new(2));
+        let a = process.allocate_qubit().unwrap();
+        let b = process.allocate_qubit().unwrap();
+        process.ctrl_push(&[a]).unwrap();
+        process.apply_gate(QuantumGate::PauliX, b).unwrap();
+        process.ctrl_pop().unwrap();
+        let cnot = real(&[
+            &[1.0, 0.0, 0.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 1.0],
+            &[0.0, 0.0, 1.0, 0.0],
+        ]);
+        assert!(equivalent(&process.to_unitary().unwrap(), &cnot, 1e-12));
+        assert!(!equivalent(&h, &cnot, 1e-12));
+        assert!(equivalent(&power(&cnot, 5), &cnot, 1e-12));
+        assert!(equivalent(&power(&cnot, 6), &power(&h, 0), 1e-12));
+    }
+
+    #[test]
+    fn global_phase_and_errors() {
+        let gate = |gate, target| Instruction::Gate {
+            gate,
+            target,
+            control: vec![],
+        };
+        let z = unitary(&[
+            Instruction::Alloc { target: 0 },
+            gate(QuantumGate::PauliZ, 0),
+        ])
+        .unwrap();
+        let rz = [
+            Instruction::Alloc { target: 0 },
+            gate(
+                QuantumGate::RotationZ(ket::Angle::Scalar(std::f64::consts::PI)),
+                0,
+            ),
+        ]
+        .to_unitary()
+        .unwrap();
+        assert!(equivalent(&z, &rz, 1e-12));
+        assert!((z[0][0] - rz[0][0]).norm() > 0.5);
+
+        assert!(matches!(
+            unitary(&[
+                Instruction::Alloc { target: 0 },
+                Instruction::Measure {
+                    qubits: vec![0],
+                    output: 0
+                },
+            ]),
+            Err(KBWError::NonUnitaryInstruction)
+        ));
+
+        let alloc: Vec<_> = (0..13)
+            .map(|target| Instruction::Alloc { target })
+            .collect();
+        assert!(matches!(
+            unitary(&alloc),
+            Err(KBWError::UnsupportedNumberOfQubits)
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Circuit construction without a Libket `Process`.
+
+use alloc::vec::Vec;
+
+use super::instruction::{Instruction, PauliHamiltonian, QuantumGate};
+use crate::error::{KetError, Result};
+
+/// Destination of the instructions of a [`CircuitBuilder`].
+pub trait InstructionSink {
+    /// Receives the next instruction of the circuit.
+    fn push(&mut self, instruction: Instruction);
+}
+
+impl InstructionSink for Vec<Instruction> {
+    fn push(&mut self, instruction: Instruction) {
+        Vec::push(self, instruction);
+    }
+}
+
+/// Builds a circuit with the checks and the control and inverse scopes of a Libket
+/// `Process`, without executing it.
+///
+/// Each instruction is passed to the sink as soon as it is final: gates in an inverse
+/// scope are held until the outermost [`CircuitBuilder::adj_end`]. The qubits of
+/// measurements, samples, and dumps are given from the most to the least significant
+/// bit.
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBuilder<S = Vec<Instruction>> {
+    sink: S,
+    num_qubits: Option<usize>,
+    allocated: Vec<bool>,
+    ctrl_stack: Vec<Vec<usize>>,
+    adj_stack: Vec<Vec<Instruction>>,
+    measurements: usize,
+    exp_values: usize,
+    samples: usize,
+    dumps: usize,
+}
+
+impl CircuitBuilder {
+    /// Creates a builder that collects the instructions in a [`Vec`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S: InstructionSink> CircuitBuilder<S> {
+    /// Creates a builder that passes the instructions to `sink`.
+    pub fn with_sink(sink: S) -> Self {
+        Self {
+            sink,
+            num_qubits: None,
+            allocated: Vec::new(),
+            ctrl_stack: Vec::new(),
+            adj_stack: Vec::new(),
+            measurements: 0,
+            exp_values: 0,
+            samples: 0,
+            dumps: 0,
+        }
+    }
+
+    /// Limits the number of qubits allocated at the same time.
+    pub fn with_num_qubits(mut self, num_qubits: usize) -> Self {
+        self.num_qubits = Some(num_qubits);
+        self
+    }
+
+    /// Returns the sink.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns the sink, dropping the builder.
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    fn assert_not_adj(&self) -> Result<()> {
+        if self.adj_stack.is_empty() {
+            Ok(())
+        } else {
+            Err(KetError::NonGateInstructionInAdj)
+        }
+    }
+
+    fn assert_qubit_allocated(&self, qubit: usize) -> Result<()> {
+        match self.allocated.get(qubit) {
+            Some(true) => Ok(()),
+            Some(false) => Err(KetError::DeallocatedQubit),
+            None => Err(KetError::QubitIndexOutOfBounds),
+        }
+    }
+
+    /// Allocates a qubit and returns its index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in an inverse scope, or if the number of allocated qubits
+    /// exceeds the limit.
+    pub fn allocate_qubit(&mut self) -> Result<usize> {
+        self.assert_not_adj()?;
+        let allocated = self
+            .allocated
+            .iter()
+            .filter(|allocated| **allocated)
+            .count();
+        if self
+            .num_qubits
+            .is_some_and(|num_qubits| allocated >= num_qubits)
+        {
+            return Err(KetError::NumberOfQubitsExceeded);
+        }
+
+        let index = self.allocated.len();
+        self.allocated.push(true);
+        self.sink.push(Instruction::Alloc { target: index });
+        Ok(index)
+    }
+
+    /// Frees an allocated qubit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in an inverse scope, or if the qubit is not allocated.
+    pub fn free_qubit(&mut self, qubit: usize) -> Result<()> {
+        self.assert_not_adj()?;
+        self.assert_qubit_allocated(qubit)?;
+        self.allocated[qubit] = false;
+        self.sink.push(Instruction::Free { target: qubit });
+        Ok(())
+    }
+
+    /// Applies a gate to the target qubit, controlled by the qubits of the control
+    /// stack.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target is not allocated or is a control qubit.
+    pub fn apply_gate(&mut self, gate: QuantumGate, target: usize) -> Result<()> {
+        self.assert_qubit_allocated(target)?;
+        let control: Vec<usize> = self.ctrl_stack.iter().flatten().copied().collect();
+        if control.contains(&target) {
+            return Err(KetError::TargetInControl);
+        }
+
+        let gate = if self.adj_stack.len() % 2 == 1 {
+            gate.inverse()
+        } else {
+            gate
+        };
+        let instruction = Instruction::Gate {
+            gate,
+            target,
+            control,
+        };
+        match self.adj_stack.last_mut() {
+            Some(block) => block.push(instruction),
+            None => self.sink.push(instruction),
+        }
+        Ok(())
+    }
+
+    /// Adds qubits to the control stack.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a qubit is not allocated or is already a control qubit.
+    pub fn ctrl_push(&mut self, qubits: &[usize]) -> Result<()> {
+        for qubit in qubits {
+            self.assert_qubit_allocated(*qubit)?;
+            if self
+                .ctrl_stack
+                .iter()
+                .flatten()
+                .any(|control| control == qubit)
+            {
+                return Err(KetError::ControlTwice);
+            }
+        }
+        self.ctrl_stack.push(qubits.to_vec());
+        Ok(())
+    }
+
+    /// Removes the last qubits added to the control stack.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the control stack is empty.
+    pub fn ctrl_pop(&mut self) -> Result<()> {
+        self.ctrl_stack.pop().map(|_| ()).ok_or(KetError::NoCtrl)
+    }
+
+    /// Begins an inverse scope, where gates are inverted and applied in reverse order.
+    pub fn adj_begin(&mut self) {
+        self.adj_stack.push(Vec::new());
+    }
+
+    /// Ends the inverse scope.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no inverse scope.
+    pub fn adj_end(&mut self) -> Result<()> {
+        let block = self.adj_stack.pop().ok_or(KetError::NoAdj)?;
+        for instruction in block.into_iter().rev() {
+            match self.adj_stack.last_mut() {
+                Some(parent) => parent.push(instruction),
+                None => self.sink.push(instruction),
+            }
+        }
+        Ok(())
+    }
+
+    /// Measures the qubits and returns the index of the result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in an inverse scope, or if a qubit is not allocated.
+    pub fn measure(&mut self, qubits: &[usize]) -> Result<usize> {
+        self.assert_not_adj()?;
+        for qubit in qubits {
+            self.assert_qubit_allocated(*qubit)?;
+        }
+        let output = self.measurements;
+        self.measurements += 1;
+        self.sink.push(Instruction::Measure {
+            qubits: qubits.to_vec(),
+            output,
+        });
+        Ok(output)
+    }
+
+    /// Samples the qubits and returns the index of the result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in an inverse scope, or if a qubit is not allocated.
+    pub fn sample(&mut self, qubits: &[usize], shots: u64) -> Result<usize> {
+        self.assert_not_adj()?;
+        for qubit in qubits {
+            self.assert_qubit_allocated(*qubit)?;
+        }
+        let output = self.samples;
+        self.samples += 1;
+        self.sink.push(Instruction::Sample {
+            qubits: qubits.to_vec(),
+            shots,
+            output,
+        });
+        Ok(output)
+    }
+
+    /// Calculates the expected value of the Hamiltonian and returns the index of the
+    /// result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in an inverse scope, or if a qubit is not allocated.
+    pub fn exp_value(&mut self, hamiltonian: PauliHamiltonian) -> Result<usize> {
+        self.assert_not_adj()?;
+        for term in hamiltonian.products.iter().flatten() {
+            self.assert_qubit_allocated(term.qubit)?;
+        }
+        let output = self.exp_values;
+        self.exp_values += 1;
+        self.sink.push(Instruction::ExpValue {
+            hamiltonian,
+            output,
+        });
+        Ok(output)
+    }
+
+    /// Dumps the state of the qubits and returns the index of the result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in an inverse scope, or if a qubit is not allocated.
+    pub fn dump(&mut self, qubits: &[usize]) -> Result<usize> {
+        self.assert_not_adj()?;
+        for qubit in qubits {
+            self.assert_qubit_allocated(*qubit)?;
+        }
+        let output = self.dumps;
+        self.dumps += 1;
+        self.sink.push(Instruction::Dump {
+            qubits: qubits.to_vec(),
+            output,
+        });
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitBuilder;
+    use crate::{error::KetError, QuantumGate};
+
+    #[test]
+    fn checks() {
+        let mut builder = CircuitBuilder::new().with_num_qubits(1);
+        let qubit = builder.allocate_qubit().unwrap();
+        assert!(matches!(
+            builder.allocate_qubit(),
+            Err(KetError::NumberOfQubitsExceeded)
+        ));
+        builder.ctrl_push(&[qubit]).unwrap();
+        assert!(matches!(
+            builder.apply_gate(QuantumGate::PauliX, qubit),
+            Err(KetError::TargetInControl)
+        ));
+        builder.ctrl_pop().unwrap();
+        assert!(matches!(builder.ctrl_pop(), Err(KetError::NoCtrl)));
+        builder.adj_begin();
+        assert!(matches!(
+            builder.measure(&[qubit]),
+            Err(KetError::NonGateInstructionInAdj)
+        ));
+        builder.adj_end().unwrap();
+        assert!(matches!(builder.adj_end(), Err(KetError::NoAdj)));
+        builder.free_qubit(qubit).unwrap();
+        assert!(matches!(
+            builder.apply_gate(QuantumGate::PauliX, qubit),
+            Err(KetError::DeallocatedQubit)
+        ));
+        assert!(matches!(
+            builder.apply_gate(QuantumGate::PauliX, 5),
+            Err(KetError::QubitIndexOutOfBounds)
+        ));
+    }
+}
@@ -0,0 +1,487 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Gates and instructions of the quantum circuit.
+
+use alloc::{vec, vec::Vec};
+use core::{f64::consts::PI, fmt};
+
+use num::complex::Complex64;
+use serde::{Deserialize, Serialize};
+
+/// Enum representing different angle representations for quantum gates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Angle {
+    /// Scalar angle.
+    Scalar(f64),
+    /// Pi fraction angle.
+    PiFraction {
+        /// Top part of the fraction.
+        top: i32,
+        /// Bottom part of the fraction.
+        bottom: u32,
+    },
+}
+
+impl Angle {
+    /// Creates the angle `top * π / bottom` with the fraction in lowest terms.
+    ///
+    /// Keeping the exact fraction lets the optimizer recognize Clifford and T phases
+    /// and the QASM exporter print them as fractions of π.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bottom` is zero, see [`Angle::checked_pi_fraction`].
+    pub fn pi_fraction(top: i32, bottom: u32) -> Angle {
+        Angle::checked_pi_fraction(top, bottom).expect("the denominator of a fraction of π is zero")
+    }
+
+    /// Creates the angle `top * π / bottom` with the fraction in lowest terms, or
+    /// returns `None` if `bottom` is zero.
+    pub fn checked_pi_fraction(top: i32, bottom: u32) -> Option<Angle> {
+        if bottom == 0 {
+            return None;
+        }
+        if top == 0 {
+            return Some(Angle::PiFraction { top: 0, bottom: 1 });
+        }
+
+        let (mut a, mut b) = (top.unsigned_abs(), bottom);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        Some(Angle::PiFraction {
+            top: (top as i64 / a as i64) as i32,
+            bottom: bottom / a,
+        })
+    }
+
+    /// Returns the inverse of the angle.
+    pub fn inverse(&self) -> Angle {
+        match self {
+            Angle::Scalar(angle) => Angle::Scalar(-angle),
+            Angle::PiFraction { top, bottom } => match top.checked_neg() {
+                Some(top) => Angle::PiFraction {
+                    top,
+                    bottom: *bottom,
+                },
+                None => Angle::Scalar(-self.radians()),
+            },
+        }
+    }
+
+    /// Returns half of the angle.
+    pub fn half(&self) -> Angle {
+        match self {
+            Angle::Scalar(angle) => Angle::Scalar(angle / 2.0),
+            Angle::PiFraction { top, bottom } => {
+                let half = if top % 2 == 0 {
+                    Angle::checked_pi_fraction(top / 2, *bottom)
+                } else {
+                    bottom
+                        .checked_mul(2)
+                        .and_then(|bottom| Angle::checked_pi_fraction(*top, bottom))
+                };
+                half.unwrap_or_else(|| Angle::Scalar(self.radians() / 2.0))
+            }
+        }
+    }
+
+    /// Returns the angle in radians.
+    pub fn radians(&self) -> f64 {
+        match self {
+            Angle::Scalar(angle) => *angle,
+            Angle::PiFraction { top, bottom } => PI * *top as f64 / *bottom as f64,
+        }
+    }
+
+    /// Returns the numerator and denominator of the angle as a fraction of π in lowest
+    /// terms, or `None` for a scalar angle.
+    pub fn as_pi_fraction(&self) -> Option<(i32, u32)> {
+        match self {
+            Angle::PiFraction { top, bottom } => match Angle::checked_pi_fraction(*top, *bottom) {
+                Some(Angle::PiFraction { top, bottom }) => Some((top, bottom)),
+                _ => None,
+            },
+            Angle::Scalar(_) => None,
+        }
+    }
+
+    /// Returns an angle representing π.
+    pub fn pi() -> Angle {
+        Angle::PiFraction { top: 1, bottom: 1 }
+    }
+
+    /// Return the angle as a scalar value.
+    pub fn scalar(&self) -> f64 {
+        match self {
+            Angle::Scalar(angle) => *angle,
+            Angle::PiFraction { top, bottom } => {
+                let top = *top as f64;
+                let bottom = *bottom as f64;
+                top / bottom
+            }
+        }
+    }
+}
+
+impl fmt::Display for Angle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Angle::Scalar(angle) => write!(f, "{}", angle),
+            Angle::PiFraction { top, bottom } => {
+                if *top == 1 {
+                    write!(f, "pi/{}", bottom)
+                } else {
+                    write!(f, "({} * pi)/{}", top, bottom)
+                }
+            }
+        }
+    }
+}
+
+/// Enum representing various quantum gates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum QuantumGate {
+    /// Pauli X gate.
+    PauliX,
+    /// Pauli Y gate.
+    PauliY,
+    /// Pauli Z gate.
+    PauliZ,
+    /// X-axis rotation gate.
+    RotationX(Angle),
+    /// Y-axis rotation gate.
+    RotationY(Angle),
+    /// Z-axis rotation gate.
+    RotationZ(Angle),
+    /// Phase shift gate.
+    Phase(Angle),
+    /// Hadamard gate.
+    Hadamard,
+}
+
+impl QuantumGate {
+    /// Returns the inverse of the quantum gate.
+    pub fn inverse(&self) -> QuantumGate {
+        match self {
+            QuantumGate::RotationX(angle) => QuantumGate::RotationX(angle.inverse()),
+            QuantumGate::RotationY(angle) => QuantumGate::RotationY(angle.inverse()),
+            QuantumGate::RotationZ(angle) => QuantumGate::RotationZ(angle.inverse()),
+            QuantumGate::Phase(angle) => QuantumGate::Phase(angle.inverse()),
+            QuantumGate::Hadamard => QuantumGate::Hadamard,
+            QuantumGate::PauliX => QuantumGate::PauliX,
+            QuantumGate::PauliY => QuantumGate::PauliY,
+            QuantumGate::PauliZ => QuantumGate::PauliZ,
+        }
+    }
+
+    /// Returns whether the gate is in the minimal gate set: X, H, and phase.
+    pub fn is_minimal(&self) -> bool {
+        matches!(
+            self,
+            QuantumGate::PauliX | QuantumGate::Hadamard | QuantumGate::Phase(_)
+        )
+    }
+
+    /// Decomposes the gate into X, H, and phase gates, in application order.
+    ///
+    /// The decomposition is exact, including the global phase, so it remains valid when
+    /// the gate is controlled.
+    pub fn decompose_minimal(&self) -> Vec<QuantumGate> {
+        let s = || QuantumGate::Phase(Angle::PiFraction { top: 1, bottom: 2 });
+        let s_dagger = || QuantumGate::Phase(Angle::PiFraction { top: -1, bottom: 2 });
+        // RZ(θ) = X P(-θ/2) X P(θ/2)
+        let rz = |angle: &Angle| {
+            vec![
+                QuantumGate::PauliX,
+                QuantumGate::Phase(angle.half().inverse()),
+                QuantumGate::PauliX,
+                QuantumGate::Phase(angle.half()),
+            ]
+        };
+
+        match self {
+            QuantumGate::PauliX | QuantumGate::Hadamard | QuantumGate::Phase(_) => {
+                vec![self.clone()]
+            }
+            QuantumGate::PauliY => vec![s_dagger(), QuantumGate::PauliX, s()],
+            QuantumGate::PauliZ => vec![QuantumGate::Phase(Angle::pi())],
+            QuantumGate::RotationZ(angle) => rz(angle),
+            QuantumGate::RotationX(angle) => [
+                vec![QuantumGate::Hadamard],
+                rz(angle),
+                vec![QuantumGate::Hadamard],
+            ]
+            .concat(),
+            QuantumGate::RotationY(angle) => [
+                vec![s_dagger(), QuantumGate::Hadamard],
+                rz(angle),
+                vec![QuantumGate::Hadamard, s()],
+            ]
+            .concat(),
+        }
+    }
+}
+
+/// Enum representing Pauli operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Pauli {
+    /// Pauli X operator.
+    PauliX,
+    /// Pauli Y operator.
+    PauliY,
+    /// Pauli Z operator.
+    PauliZ,
+}
+
+/// Structure representing a term in a Pauli product.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauliTerm {
+    /// Pauli operator.
+    pub pauli: Pauli,
+
+    /// Qubit index.
+    pub qubit: usize,
+}
+
+/// Type representing a product of Pauli terms.
+pub type PauliProduct = Vec<PauliTerm>;
+
+/// Structure representing a Hamiltonian in terms of Pauli products.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PauliHamiltonian {
+    /// List of Pauli products.
+    pub products: Vec<PauliProduct>,
+
+    /// Coefficients associated with each Pauli product.
+    pub coefficients: Vec<f64>,
+}
+
+/// Operator of a single-qubit Kraus channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KrausOperator {
+    /// Real part of the entries, in row-major order.
+    pub real: [[f64; 2]; 2],
+
+    /// Imaginary part of the entries, in row-major order.
+    pub imag: [[f64; 2]; 2],
+}
+
+impl KrausOperator {
+    /// Largest deviation of `sum K^dagger K` from the identity in a valid channel.
+    const COMPLETENESS_TOLERANCE: f64 = 1e-8;
+
+    /// Creates the operator from a complex matrix.
+    pub fn from_matrix(matrix: [[Complex64; 2]; 2]) -> Self {
+        Self {
+            real: matrix.map(|row| row.map(|x| x.re)),
+            imag: matrix.map(|row| row.map(|x| x.im)),
+        }
+    }
+
+    /// Returns the operator as a complex matrix.
+    pub fn matrix(&self) -> [[Complex64; 2]; 2] {
+        [0, 1].map(|row| {
+            [0, 1].map(|column| Complex64::new(self.real[row][column], self.imag[row][column]))
+        })
+    }
+
+    /// Returns `true` if the operators describe a trace-preserving channel, that is,
+    /// if `sum K^dagger K` is the identity.
+    pub fn is_channel(operators: &[KrausOperator]) -> bool {
+        if operators.is_empty() {
+            return false;
+        }
+        let matrices: Vec<_> = operators.iter().map(KrausOperator::matrix).collect();
+        (0..2).all(|row| {
+            (0..2).all(|column| {
+                let sum: Complex64 = matrices
+                    .iter()
+                    .flat_map(|k| (0..2).map(move |i| k[i][row].conj() * k[i][column]))
+                    .sum();
+                let expected = if row == column { 1.0 } else { 0.0 };
+                (sum - expected).norm_sqr()
+                    <= Self::COMPLETENESS_TOLERANCE * Self::COMPLETENESS_TOLERANCE
+            })
+        })
+    }
+}
+
+/// Classical condition of an [`Instruction::ConditionalGate`].
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Condition {
+    /// Measurement indices with their expected results. The condition holds if every
+    /// measurement has its expected result.
+    pub expected: Vec<(usize, u64)>,
+
+    /// Measured qubits, so the gate is never moved before the measurements.
+    pub qubits: Vec<usize>,
+}
+
+impl Condition {
+    /// Returns `true` if every measurement has its expected result, given the result
+    /// of each measurement index.
+    pub fn holds(&self, result: impl Fn(usize) -> Option<u64>) -> bool {
+        self.expected
+            .iter()
+            .all(|(measurement, expected)| result(*measurement) == Some(*expected))
+    }
+}
+
+/// Enum representing different quantum instructions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Instruction {
+    /// Alloc instruction, allocating a qubit.
+    Alloc {
+        /// The target index of the allocated qubit.
+        target: usize,
+    },
+
+    /// Free instruction, freeing a previously allocated qubit.
+    Free {
+        /// The target index of the qubit to be freed.
+        target: usize,
+    },
+
+    /// Gate instruction, applying a quantum gate to a target qubit with optional control qubits.
+    Gate {
+        /// The quantum gate to be applied.
+        gate: QuantumGate,
+
+        /// The target index of the qubit on which the gate is applied.
+        target: usize,
+
+        /// The list of control qubits influencing the gate operation.
+        control: Vec<usize>,
+    },
+
+    /// Measure instruction, measuring specified qubits and recording the result.
+    Measure {
+        /// The list of qubits to be measured.
+        qubits: Vec<usize>,
+
+        /// The index to store the measurement result.
+        output: usize,
+    },
+
+    /// ExpValue instruction, calculating the expected value of a Hamiltonian.
+    ExpValue {
+        /// The Hamiltonian for which the expected value is calculated.
+        hamiltonian: PauliHamiltonian,
+
+        /// The index to store the calculated expected value.
+        output: usize,
+    },
+
+    /// Sample instruction, sampling specified qubits a certain number of times.
+    Sample {
+        /// The list of qubits to be sampled.
+        qubits: Vec<usize>,
+
+        /// The number of shots for the sampling process.
+        shots: u64,
+
+        /// The index to store the sampling results.
+        output: usize,
+    },
+
+    /// Dump instruction, capturing the state of specified qubits.
+    Dump {
+        /// The list of qubits to be dumped.
+        qubits: Vec<usize>,
+
+        /// The index to store the dump data.
+        output: usize,
+    },
+
+    /// Kraus channel instruction, applying a noisy operation to a qubit.
+    KrausChannel {
+        /// The Kraus operators of the channel.
+        operators: Vec<KrausOperator>,
+
+        /// The index of the qubit on which the channel is applied.
+        target: usize,
+    },
+
+    /// Gate instruction applied only if a condition on previous measurement results
+    /// holds.
+    ConditionalGate {
+        /// The quantum gate to be applied.
+        gate: QuantumGate,
+
+        /// The target index of the qubit on which the gate is applied.
+        target: usize,
+
+        /// The list of control qubits influencing the gate operation.
+        control: Vec<usize>,
+
+        /// The condition on the measurement results.
+        condition: Condition,
+    },
+
+    /// Diagonal gate, multiplying each basis state of the qubits by a phase.
+    Diagonal {
+        /// Phase of each basis state, the first qubit being the most significant bit of
+        /// its index.
+        phases: Vec<f64>,
+
+        /// The list of qubits of the gate.
+        qubits: Vec<usize>,
+    },
+
+    /// Multiplexed rotation, rotating the target qubit by a different angle for each
+    /// value of the control qubits.
+    Multiplexor {
+        /// The rotation axis.
+        axis: Pauli,
+
+        /// Rotation angle for each value of the control qubits, the first control being
+        /// the most significant bit of its index.
+        angles: Vec<f64>,
+
+        /// The list of control qubits selecting the angle.
+        control: Vec<usize>,
+
+        /// The target index of the rotated qubit.
+        target: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Angle;
+
+    #[test]
+    fn exact_angles() {
+        assert_eq!(Angle::pi_fraction(6, 8).as_pi_fraction(), Some((3, 4)));
+        assert_eq!(Angle::pi_fraction(-2, 4).as_pi_fraction(), Some((-1, 2)));
+        assert_eq!(Angle::pi_fraction(0, 7).as_pi_fraction(), Some((0, 1)));
+        assert!(Angle::checked_pi_fraction(1, 0).is_none());
+        assert!(Angle::PiFraction { top: 1, bottom: 0 }
+            .as_pi_fraction()
+            .is_none());
+
+        assert_eq!(
+            Angle::PiFraction { top: 2, bottom: 4 }
+                .half()
+                .as_pi_fraction(),
+            Some((1, 4))
+        );
+        assert_eq!(
+            Angle::PiFraction { top: 3, bottom: 4 }
+                .half()
+                .as_pi_fraction(),
+            Some((3, 8))
+        );
+        assert_eq!(
+            Angle::pi().half().half().inverse().as_pi_fraction(),
+            Some((-1, 4))
+        );
+        assert_eq!(Angle::pi_fraction(1, 4).to_string(), "pi/4");
+        assert!((Angle::pi_fraction(1, 4).radians() - std::f64::consts::FRAC_PI_4).abs() < 1e-15);
+        assert!(Angle::Scalar(0.5).as_pi_fraction().is_none());
+    }
+}
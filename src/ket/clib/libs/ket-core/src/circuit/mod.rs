@@ -0,0 +1,39 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Instruction model and circuit construction, without `std`.
+//!
+//! A [`CircuitBuilder`] checks the circuit as a Libket `Process` does, and passes each
+//! instruction to an [`InstructionSink`], which may stream it to an external executor:
+//!
+//! ```
+//! use ket_core::circuit::{CircuitBuilder, Instruction, QuantumGate};
+//!
+//! # fn main() -> Result<(), ket_core::error::KetError> {
+//! let mut builder = CircuitBuilder::new();
+//! let a = builder.allocate_qubit()?;
+//! let b = builder.allocate_qubit()?;
+//! builder.apply_gate(QuantumGate::Hadamard, a)?;
+//! builder.ctrl_push(&[a])?;
+//! builder.apply_gate(QuantumGate::PauliX, b)?;
+//! builder.ctrl_pop()?;
+//! builder.measure(&[a, b])?;
+//!
+//! let instructions = builder.into_sink();
+//! assert!(matches!(
+//!     &instructions[3],
+//!     Instruction::Gate { gate: QuantumGate::PauliX, target: 1, control } if control == &[0]
+//! ));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The types of [`instruction`] are also re-exported by the `ir` module of Libket.
+
+pub mod builder;
+pub mod instruction;
+
+pub use builder::{CircuitBuilder, InstructionSink};
+pub use instruction::*;
@@ -5,7 +5,7 @@
 
 //! This module defines the error types used in the quantum programming library.
 
-use std::result;
+use core::result;
 /// Enumeration of possible errors in the quantum processing library.
 #[derive(thiserror::Error, Debug, Clone, Copy)]
 #[repr(i32)]
@@ -84,6 +84,104 @@ pub enum KetError {
         "Ket can only export to qelib gate set, as it internally represents U gates as RZ RY RZ"
     )]
     PureQASMGateExportError,
+
+    #[error("The provided Hamiltonian is malformed or has non-real coefficients.")]
+    InvalidHamiltonian,
+
+    #[error("Failed to access the experiment database.")]
+    ExperimentDatabaseError,
+
+    #[error("The coupling map is malformed or its qubits are not connected.")]
+    InvalidCouplingMap,
+
+    #[error("The configuration options are inconsistent.")]
+    InvalidConfiguration,
+
+    #[error("The operation requires a live quantum execution.")]
+    LiveExecutionRequired,
+
+    #[error("The rotation cannot be approximated to the requested precision.")]
+    ApproximationFailed,
+
+    #[error("The measurement outcome could not be forced.")]
+    ForcedMeasurementFailed,
+
+    #[error("The executor cannot sample without collapsing the quantum state.")]
+    SampleWithoutCollapseNotSupported,
+
+    #[error("The Kraus operators do not form a channel, or the channel is in a control or inverse scope.")]
+    InvalidKrausChannel,
+
+    #[error("The executor does not support Kraus channels.")]
+    KrausChannelNotSupported,
+
+    #[error("The optimization problem cannot be encoded as an Ising Hamiltonian.")]
+    InvalidProblemEncoding,
+
+    #[error("The template is not valid or does not match the given qubits.")]
+    InvalidTemplate,
+
+    #[error("The remote execution failed.")]
+    RemoteExecutionFailed,
+
+    #[error("Cannot freeze within an inverse scope, or rewrite frozen instructions.")]
+    FrozenInstructions,
+
+    #[error("No conditional scope to end.")]
+    NoCond,
+
+    #[error("The condition refers to an unknown measurement, or is mixed with an inverse scope.")]
+    InvalidCondition,
+
+    #[error("The process is not being traced, or the trace file cannot be written or read.")]
+    TraceFailed,
+
+    #[error("The metadata extension is not valid JSON, or is not set.")]
+    InvalidMetadataExtension,
+
+    #[error("The phase estimation requires 1 to 63 bits and at least one repetition.")]
+    InvalidPhaseEstimation,
+
+    #[error("The probability distribution must have a power of two length, non-negative values, and a positive sum, and match the number of qubits.")]
+    InvalidDistribution,
+
+    #[error("The opaque block cannot start or end here, or the operation would rewrite an opaque block.")]
+    InvalidOpaqueBlock,
+
+    #[error("The state preparation and the oracle must have the same number of qubits.")]
+    InvalidAmplitudeAmplification,
+
+    #[error(
+        "The file is not a NumPy array of real or complex numbers, or cannot be read or written."
+    )]
+    InvalidNpyFile,
+
+    #[error("The initial state must be a normalized vector of a power of two length, set before any instruction other than allocations.")]
+    InvalidInitialState,
+
+    #[error("The fidelity target must be in (0, 1], and error budget sections cannot be nested, begin or end in an inverse scope or opaque block, nor be relabeled.")]
+    InvalidErrorBudget,
+
+    #[error("The executor callbacks must include at least the gate and measure callbacks.")]
+    InvalidCallbacks,
+
+    #[error("The thread of the process has stopped.")]
+    ProcessHandleClosed,
+
+    #[error("The OpenQASM program exceeds the import limits of the configuration.")]
+    ImportLimitExceeded,
+
+    #[error("The number of phases of a diagonal gate or of angles of a multiplexed rotation must be two to the number of its qubits, and its qubits must be distinct.")]
+    InvalidMultiplexor,
+
+    #[error("The file and function of a source location must be UTF-8 encoded.")]
+    InvalidSourceLocation,
+
+    #[error("The live execution cannot save or restore its state, or the process executed a chunk since the checkpoint.")]
+    InvalidCheckpoint,
+
+    #[error("An ancilla qubit is not returned to the |0> state before it is freed or measured as garbage.")]
+    DirtyAncilla,
 }
 
 /// Alias for a `Result` type using `KetError` as the error variant.
@@ -101,7 +199,7 @@ impl KetError {
     ///
     /// This function is unsafe because it assumes that the error code is valid.
     pub unsafe fn from_error_code(error_code: i32) -> KetError {
-        unsafe { std::mem::transmute(error_code) }
+        unsafe { core::mem::transmute(error_code) }
     }
 }
 
@@ -0,0 +1,303 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module provides utilities for building and serializing Pauli Hamiltonians.
+//!
+//! Hamiltonians can be imported from the string representation of an OpenFermion
+//! `QubitOperator` (as produced by `str(operator)` or by `save_operator(..., plain_text=True)`)
+//! or from a plain JSON schema:
+//!
+//! ```json
+//! {
+//!     "terms": [
+//!         { "coefficient": -0.0971, "paulis": [] },
+//!         { "coefficient": 0.1712, "paulis": [{ "pauli": "Z", "qubit": 0 }] },
+//!         { "coefficient": 0.045, "paulis": [{ "pauli": "X", "qubit": 0 }, { "pauli": "Y", "qubit": 1 }] }
+//!     ]
+//! }
+//! ```
+//!
+//! Qubit indices are used as given, so they must match the indices returned by
+//! `Process::allocate_qubit` of Libket. An empty Pauli product represents the identity
+//! term.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    circuit::instruction::{Pauli, PauliHamiltonian, PauliProduct, PauliTerm},
+    error::{KetError, Result},
+};
+
+/// Imaginary parts smaller than this are considered numerical noise.
+const IMAGINARY_TOLERANCE: f64 = 1e-10;
+
+/// JSON representation of a Hamiltonian.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HamiltonianJson {
+    /// Terms of the Hamiltonian.
+    pub terms: Vec<HamiltonianTermJson>,
+}
+
+/// JSON representation of a Hamiltonian term.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HamiltonianTermJson {
+    /// Real coefficient of the term.
+    pub coefficient: f64,
+
+    /// Pauli operators of the term. Empty for the identity.
+    #[serde(default)]
+    pub paulis: Vec<PauliTermJson>,
+}
+
+/// JSON representation of a single Pauli operator acting on a qubit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauliTermJson {
+    /// Pauli operator, `"X"`, `"Y"`, or `"Z"`.
+    pub pauli: String,
+
+    /// Qubit index.
+    pub qubit: usize,
+}
+
+impl PauliHamiltonian {
+    /// Parses the string representation of an OpenFermion `QubitOperator`.
+    ///
+    /// Each term has the form `coefficient [P0 P1 ...]`, where the coefficient may be a
+    /// real number or a Python complex literal such as `(0.5+0j)`. Terms are separated
+    /// by `+`, usually followed by a line break.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidHamiltonian`] if the input is malformed or if a
+    /// coefficient has a non-negligible imaginary part.
+    pub fn from_openfermion(operator: &str) -> Result<Self> {
+        // Drop the `save_operator` header line, if present.
+        let operator = operator
+            .lines()
+            .filter(|line| !line.trim().ends_with(':'))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut hamiltonian = PauliHamiltonian::default();
+
+        // `str(QubitOperator())` is `0`.
+        if operator.trim().is_empty() || operator.trim() == "0" {
+            return Ok(hamiltonian);
+        }
+
+        let mut terms: Vec<&str> = operator.split(']').collect();
+        if !terms.pop().unwrap_or_default().trim().is_empty() {
+            return Err(KetError::InvalidHamiltonian);
+        }
+
+        for term in terms {
+            let term = term.trim();
+            let term = term.strip_prefix('+').unwrap_or(term).trim();
+
+            let open = term.find('[').ok_or(KetError::InvalidHamiltonian)?;
+            let coefficient = parse_coefficient(term[..open].trim())?;
+
+            let product = term[open + 1..]
+                .split_whitespace()
+                .map(parse_openfermion_pauli)
+                .collect::<Result<PauliProduct>>()?;
+
+            hamiltonian.products.push(product);
+            hamiltonian.coefficients.push(coefficient);
+        }
+
+        Ok(hamiltonian)
+    }
+
+    /// Parses a Hamiltonian from the plain JSON schema described in the module documentation.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidHamiltonian`] if the JSON does not follow the schema.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let json: HamiltonianJson =
+            serde_json::from_str(json).map_err(|_| KetError::InvalidHamiltonian)?;
+
+        let mut hamiltonian = PauliHamiltonian::default();
+        for term in json.terms {
+            let product = term
+                .paulis
+                .iter()
+                .map(|term| {
+                    Ok(PauliTerm {
+                        pauli: parse_pauli(&term.pauli)?,
+                        qubit: term.qubit,
+                    })
+                })
+                .collect::<Result<PauliProduct>>()?;
+
+            hamiltonian.products.push(product);
+            hamiltonian.coefficients.push(term.coefficient);
+        }
+
+        Ok(hamiltonian)
+    }
+
+    /// Serializes the Hamiltonian using the plain JSON schema.
+    pub fn to_json(&self) -> String {
+        let terms = self
+            .products
+            .iter()
+            .zip(self.coefficients.iter())
+            .map(|(product, coefficient)| HamiltonianTermJson {
+                coefficient: *coefficient,
+                paulis: product
+                    .iter()
+                    .map(|term| PauliTermJson {
+                        pauli: match term.pauli {
+                            Pauli::PauliX => "X",
+                            Pauli::PauliY => "Y",
+                            Pauli::PauliZ => "Z",
+                        }
+                        .to_string(),
+                        qubit: term.qubit,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        serde_json::to_string(&HamiltonianJson { terms }).unwrap()
+    }
+
+    /// Returns the number of terms in the Hamiltonian.
+    pub fn len(&self) -> usize {
+        self.products.len()
+    }
+
+    /// Returns `true` if the Hamiltonian has no terms.
+    pub fn is_empty(&self) -> bool {
+        self.products.is_empty()
+    }
+}
+
+fn parse_pauli(pauli: &str) -> Result<Pauli> {
+    match pauli {
+        "X" | "x" => Ok(Pauli::PauliX),
+        "Y" | "y" => Ok(Pauli::PauliY),
+        "Z" | "z" => Ok(Pauli::PauliZ),
+        _ => Err(KetError::InvalidHamiltonian),
+    }
+}
+
+/// Parses an OpenFermion operator such as `X0` or `Z12`.
+fn parse_openfermion_pauli(term: &str) -> Result<PauliTerm> {
+    if term.len() < 2 || !term.is_char_boundary(1) {
+        return Err(KetError::InvalidHamiltonian);
+    }
+    let (pauli, qubit) = term.split_at(1);
+
+    Ok(PauliTerm {
+        pauli: parse_pauli(pauli)?,
+        qubit: qubit.parse().map_err(|_| KetError::InvalidHamiltonian)?,
+    })
+}
+
+/// Parses a real number or a Python complex literal, rejecting non-real values.
+fn parse_coefficient(coefficient: &str) -> Result<f64> {
+    let coefficient = coefficient
+        .strip_prefix('(')
+        .and_then(|c| c.strip_suffix(')'))
+        .unwrap_or(coefficient);
+
+    if let Ok(real) = coefficient.parse::<f64>() {
+        return Ok(real);
+    }
+
+    let imag_part = coefficient
+        .strip_suffix('j')
+        .ok_or(KetError::InvalidHamiltonian)?;
+
+    // Split at the sign that separates the real and imaginary parts, ignoring the
+    // leading sign and exponent signs (e.g., `1e-05`).
+    let bytes = imag_part.as_bytes();
+    let split = (1..bytes.len())
+        .rev()
+        .find(|&i| (bytes[i] == b'+' || bytes[i] == b'-') && !matches!(bytes[i - 1], b'e' | b'E'));
+
+    let (real, imag) = match split {
+        Some(split) => (
+            imag_part[..split]
+                .parse::<f64>()
+                .map_err(|_| KetError::InvalidHamiltonian)?,
+            imag_part[split..]
+                .parse::<f64>()
+                .map_err(|_| KetError::InvalidHamiltonian)?,
+        ),
+        None => (
+            0.0,
+            imag_part
+                .parse::<f64>()
+                .map_err(|_| KetError::InvalidHamiltonian)?,
+        ),
+    };
+
+    if !(-IMAGINARY_TOLERANCE..=IMAGINARY_TOLERANCE).contains(&imag) {
+        Err(KetError::InvalidHamiltonian)
+    } else {
+        Ok(real)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::KetError;
+    use crate::{Pauli, PauliHamiltonian};
+
+    #[test]
+    fn openfermion_import() -> Result<(), KetError> {
+        let operator = "QubitOperator:
+        -0.0971 [] +
+        0.1712 [Z0] +
+        (-0.2228+0j) [Z2 Z3] +
+        (0.045-0j) [X0 Y1 Y2 X3]";
+
+        let hamiltonian = PauliHamiltonian::from_openfermion(operator)?;
+
+        assert_eq!(hamiltonian.len(), 4);
+        assert_eq!(
+            hamiltonian.coefficients,
+            vec![-0.0971, 0.1712, -0.2228, 0.045]
+        );
+        assert!(hamiltonian.products[0].is_empty());
+        assert_eq!(hamiltonian.products[3].len(), 4);
+        assert!(matches!(hamiltonian.products[3][1].pauli, Pauli::PauliY));
+        assert_eq!(hamiltonian.products[2][1].qubit, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn openfermion_rejects_complex() {
+        assert!(PauliHamiltonian::from_openfermion("(0.5+0.5j) [X0]").is_err());
+        assert!(PauliHamiltonian::from_openfermion("0.5 [W0]").is_err());
+    }
+
+    #[test]
+    fn json_round_trip() -> Result<(), KetError> {
+        let json = r#"{"terms": [
+            {"coefficient": 1.5, "paulis": [{"pauli": "X", "qubit": 0}, {"pauli": "Z", "qubit": 2}]},
+            {"coefficient": -0.5}
+        ]}"#;
+
+        let hamiltonian = PauliHamiltonian::from_json(json)?;
+        let hamiltonian = PauliHamiltonian::from_json(&hamiltonian.to_json())?;
+
+        assert_eq!(hamiltonian.coefficients, vec![1.5, -0.5]);
+        assert_eq!(hamiltonian.products[0].len(), 2);
+        assert!(hamiltonian.products[1].is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+#![cfg_attr(not(test), no_std)]
+#![doc(html_favicon_url = "https://quantumket.org/_static/favicon.ico")]
+
+//! # Ket Core
+//!
+//! The instruction model of the Ket programming language, without `std`.
+//!
+//! This crate only depends on `core` and `alloc`, so it builds for targets without an
+//! operating system. It holds the gates and instructions of a quantum circuit, the
+//! [`CircuitBuilder`], the errors, and the Pauli Hamiltonians. Libket re-exports its
+//! modules, so a program with `std` depends on `libket` instead.
+
+extern crate alloc;
+
+pub mod circuit;
+pub mod error;
+pub mod hamiltonian;
+
+pub use circuit::{instruction::*, CircuitBuilder, InstructionSink};
@@ -0,0 +1,273 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Amplitude amplification and quantum counting.
+//!
+//! A state preparation `A` and an oracle `O`, both [`Template`]s on the same qubits,
+//! define the Grover iterate `Q = (2|ψ><ψ| - I) O`, where `|ψ> = A|0>` and the oracle
+//! flips the phase of the good basis states. If `p = sin²(θ)` is the probability of
+//! measuring a good state in `|ψ>`, each application of `Q` rotates the state by `2θ`
+//! towards the good states, so [`optimal_iterations`] applications after `A` measure a
+//! good state with a probability close to one.
+//!
+//! The eigenvalues of `Q` are `e^(±2iθ)`, so phase estimation on `|ψ>` measures `θ`,
+//! and `p`, without knowing the good states. [`AmplitudeAmplification::count`] builds
+//! on [`iterative_phase_estimation`], and returns the number of good states when `A`
+//! prepares the uniform superposition.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::{amplification::AmplitudeAmplification, template::Template};
+//! use ket::{Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let uniform = Template::record(2, |process, qubits| {
+//!     for qubit in qubits {
+//!         process.apply_gate(QuantumGate::Hadamard, *qubit)?;
+//!     }
+//!     Ok(())
+//! })?;
+//! // Marks the state |11>.
+//! let oracle = Template::record(2, |process, qubits| {
+//!     process.ctrl_push(&qubits[..1])?;
+//!     process.apply_gate(QuantumGate::PauliZ, qubits[1])?;
+//!     process.ctrl_pop()
+//! })?;
+//! let grover = AmplitudeAmplification::new(uniform, oracle)?;
+//!
+//! let mut process = Process::new(Configuration::new(2));
+//! let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+//! grover.apply(&mut process, &qubits, ket::amplification::optimal_iterations(0.25))?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::{
+    error::{KetError, Result},
+    ir::QuantumGate,
+    phase_estimation::iterative_phase_estimation,
+    template::Template,
+    Angle, Process,
+};
+
+/// Returns the number of Grover iterations that maximizes the probability of a good
+/// state, given its probability before the amplification.
+pub fn optimal_iterations(probability: f64) -> usize {
+    let probability = probability.clamp(0.0, 1.0);
+    if probability == 0.0 {
+        return 0;
+    }
+    let theta = probability.sqrt().asin();
+    (PI / (4.0 * theta) - 0.5).round().max(0.0) as usize
+}
+
+/// Applies `2|0...0><0...0| - I` on the qubits.
+pub fn reflect_zero(process: &mut Process, qubits: &[usize]) -> Result<()> {
+    let Some((last, controls)) = qubits.split_last() else {
+        return Ok(());
+    };
+    for qubit in qubits {
+        process.apply_gate(QuantumGate::PauliX, *qubit)?;
+    }
+    if !controls.is_empty() {
+        process.ctrl_push(controls)?;
+    }
+    process.apply_gate(QuantumGate::PauliZ, *last)?;
+    if !controls.is_empty() {
+        process.ctrl_pop()?;
+    }
+    for qubit in qubits {
+        process.apply_gate(QuantumGate::PauliX, *qubit)?;
+    }
+    // The gates above apply I - 2|0><0|, which differs by a phase that matters when
+    // the reflection is controlled.
+    process.apply_global_phase(Angle::Scalar(PI))
+}
+
+/// Applies `2|ψ><ψ| - I` on the qubits, where `|ψ>` is the state that `preparation`
+/// prepares from `|0...0>`.
+///
+/// # Errors
+///
+/// Returns [`KetError::InvalidTemplate`] if the number of qubits does not match the
+/// template.
+pub fn reflect(process: &mut Process, preparation: &Template, qubits: &[usize]) -> Result<()> {
+    process.adj_begin()?;
+    let result = process.apply_template(preparation, qubits);
+    process.adj_end()?;
+    result?;
+    reflect_zero(process, qubits)?;
+    process.apply_template(preparation, qubits)
+}
+
+/// Result of [`AmplitudeAmplification::count`].
+#[derive(Debug, Clone)]
+pub struct CountEstimate {
+    /// Estimated probability of a good state after the preparation, the median of the
+    /// samples.
+    pub probability: f64,
+
+    /// Estimated number of good states, the probability times the number of basis
+    /// states, exact if the preparation is the uniform superposition.
+    pub count: f64,
+
+    /// Standard error of the probability across the repetitions.
+    pub std_error: f64,
+
+    /// Probability estimated in each repetition.
+    pub samples: Vec<f64>,
+}
+
+/// Grover iterate of a state preparation and an oracle.
+#[derive(Debug, Clone)]
+pub struct AmplitudeAmplification {
+    preparation: Template,
+    oracle: Template,
+}
+
+impl AmplitudeAmplification {
+    /// Creates the amplification of the good states marked by `oracle` in the state
+    /// prepared by `preparation`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidAmplitudeAmplification`] if the templates do not have
+    /// the same number of qubits, or have none.
+    pub fn new(preparation: Template, oracle: Template) -> Result<Self> {
+        if preparation.num_qubits() != oracle.num_qubits() || preparation.num_qubits() == 0 {
+            return Err(KetError::InvalidAmplitudeAmplification);
+        }
+        Ok(Self {
+            preparation,
+            oracle,
+        })
+    }
+
+    /// Returns the number of qubits of the templates.
+    pub fn num_qubits(&self) -> usize {
+        self.preparation.num_qubits()
+    }
+
+    /// Applies the Grover iterate `(2|ψ><ψ| - I) O` on the qubits.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidTemplate`] if the number of qubits does not match.
+    pub fn iterate(&self, process: &mut Process, qubits: &[usize]) -> Result<()> {
+        process.apply_template(&self.oracle, qubits)?;
+        reflect(process, &self.preparation, qubits)
+    }
+
+    /// Prepares `|ψ>` on the qubits, which must be in the `|0...0>` state, and applies
+    /// `iterations` Grover iterates.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidTemplate`] if the number of qubits does not match.
+    pub fn apply(&self, process: &mut Process, qubits: &[usize], iterations: usize) -> Result<()> {
+        process.apply_template(&self.preparation, qubits)?;
+        for _ in 0..iterations {
+            self.iterate(process, qubits)?;
+        }
+        Ok(())
+    }
+
+    /// Estimates the probability of the good states with `bits` bits of phase
+    /// precision, repeating the estimation `repetitions` times.
+    ///
+    /// The qubits must be in the `|0...0>` state, and are left close to an eigenstate of
+    /// the Grover iterate.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`iterative_phase_estimation`], which requires a live
+    /// quantum execution, and [`KetError::InvalidTemplate`] if the number of qubits
+    /// does not match.
+    pub fn count(
+        &self,
+        process: &mut Process,
+        qubits: &[usize],
+        bits: usize,
+        repetitions: usize,
+    ) -> Result<CountEstimate> {
+        process.apply_template(&self.preparation, qubits)?;
+        let estimate = iterative_phase_estimation(process, bits, repetitions, |process, power| {
+            for _ in 0..power {
+                self.iterate(process, qubits)?;
+            }
+            Ok(())
+        })?;
+
+        // The eigenphases θ/π and 1 - θ/π give the same probability, so the samples
+        // are combined after the conversion, not as phases.
+        let samples: Vec<f64> = estimate
+            .samples
+            .iter()
+            .map(|phase| (PI * phase).sin().powi(2))
+            .collect();
+        let (probability, std_error) = median_and_std_error(&samples);
+
+        Ok(CountEstimate {
+            probability,
+            count: probability * (1u64 << qubits.len()) as f64,
+            std_error,
+            samples,
+        })
+    }
+}
+
+/// Returns the median of the samples and the standard error of their mean.
+///
+/// A phase that is not exact with the estimated bits is off by more than one bit in
+/// a few repetitions, which the median ignores.
+fn median_and_std_error(samples: &[f64]) -> (f64, f64) {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    };
+
+    let len = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / len;
+    if samples.len() < 2 {
+        return (median, 0.0);
+    }
+    let variance = samples
+        .iter()
+        .map(|sample| (sample - mean).powi(2))
+        .sum::<f64>()
+        / (len - 1.0);
+    (median, (variance / len).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{optimal_iterations, AmplitudeAmplification};
+    use crate::{error::KetError, template::Template, QuantumGate};
+
+    #[test]
+    fn iterations() -> Result<(), KetError> {
+        assert_eq!(optimal_iterations(0.25), 1);
+        assert_eq!(optimal_iterations(1.0 / 1024.0), 25);
+        assert_eq!(optimal_iterations(0.0), 0);
+        assert_eq!(optimal_iterations(1.0), 0);
+
+        let hadamard = |num_qubits| {
+            Template::record(num_qubits, |process, qubits| {
+                process.apply_gate(QuantumGate::Hadamard, qubits[0])
+            })
+        };
+        assert!(matches!(
+            AmplitudeAmplification::new(hadamard(2)?, hadamard(1)?),
+            Err(KetError::InvalidAmplitudeAmplification)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-FileCopyrightText: 2024 Gabriel da Silva Cardoso <cardoso.gabriel@grad.ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Basis states of any number of qubits.
+//!
+//! The basis states of a [`DumpData`](crate::DumpData) are big-endian lists of `u64`
+//! words: the last word holds the 64 least significant bits, and the first qubit of the
+//! dump is the most significant bit. A dump of up to 64 qubits has a single word. Leading
+//! zero words do not change the value of a state, so the functions of this module
+//! compare states by value, whatever their number of words.
+//!
+//! ```
+//! use ket::basis;
+//!
+//! let state = basis::from_bitstring(&format!("11{}", "0".repeat(64))).unwrap();
+//! assert_eq!(state, [0b11, 0]);
+//! assert_eq!(basis::to_u128(&state), Some(0b11 << 64));
+//! assert!(basis::bit(&state, 65));
+//! assert!(basis::equal(&[0, 0, 1], &[1]));
+//! assert_eq!(basis::to_bitstring(&[1], 3), "001");
+//! ```
+
+use std::cmp::Ordering;
+
+/// Number of bits of a word.
+pub const WORD_BITS: usize = u64::BITS as usize;
+
+/// Returns the number of words of a basis state of `num_qubits` qubits.
+pub fn words(num_qubits: usize) -> usize {
+    num_qubits.div_ceil(WORD_BITS).max(1)
+}
+
+/// Returns the state without its leading zero words.
+pub fn trim(state: &[u64]) -> &[u64] {
+    let zeros = state.iter().take_while(|word| **word == 0).count();
+    &state[zeros..]
+}
+
+/// Compares two states by value.
+pub fn compare(a: &[u64], b: &[u64]) -> Ordering {
+    let (a, b) = (trim(a), trim(b));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// Returns whether two states have the same value.
+pub fn equal(a: &[u64], b: &[u64]) -> bool {
+    trim(a) == trim(b)
+}
+
+/// Returns the bit of the state at `index`, counted from the least significant bit.
+pub fn bit(state: &[u64], index: usize) -> bool {
+    let word = index / WORD_BITS;
+    word < state.len() && (state[state.len() - 1 - word] >> (index % WORD_BITS)) & 1 == 1
+}
+
+/// Sets the bit of the state at `index`, counted from the least significant bit, adding
+/// words if needed.
+pub fn set_bit(state: &mut Vec<u64>, index: usize, value: bool) {
+    let word = index / WORD_BITS;
+    if word >= state.len() {
+        if !value {
+            return;
+        }
+        let missing = word + 1 - state.len();
+        state.splice(0..0, std::iter::repeat_n(0, missing));
+    }
+    let word = state.len() - 1 - word;
+    if value {
+        state[word] |= 1 << (index % WORD_BITS);
+    } else {
+        state[word] &= !(1 << (index % WORD_BITS));
+    }
+}
+
+/// Builds a state from its bits, the first being the most significant.
+pub fn from_bits(bits: impl IntoIterator<Item = bool>) -> Vec<u64> {
+    let bits: Vec<bool> = bits.into_iter().collect();
+    let mut state: Vec<u64> = bits
+        .rchunks(WORD_BITS)
+        .map(|chunk| chunk.iter().fold(0, |word, bit| (word << 1) | *bit as u64))
+        .collect();
+    state.reverse();
+    if state.is_empty() {
+        state.push(0);
+    }
+    state
+}
+
+/// Parses a bitstring of `0` and `1`, the first character being the most significant
+/// bit.
+///
+/// Returns `None` if the bitstring is empty or has other characters.
+pub fn from_bitstring(bitstring: &str) -> Option<Vec<u64>> {
+    if bitstring.is_empty() || !bitstring.chars().all(|bit| bit == '0' || bit == '1') {
+        return None;
+    }
+    Some(from_bits(bitstring.chars().map(|bit| bit == '1')))
+}
+
+/// Writes the `width` least significant bits of the state as a bitstring, the most
+/// significant first.
+pub fn to_bitstring(state: &[u64], width: usize) -> String {
+    (0..width)
+        .rev()
+        .map(|index| if bit(state, index) { '1' } else { '0' })
+        .collect()
+}
+
+/// Returns the value of the state, or `None` if it does not fit in a `u64`.
+pub fn to_u64(state: &[u64]) -> Option<u64> {
+    match trim(state) {
+        [] => Some(0),
+        [word] => Some(*word),
+        _ => None,
+    }
+}
+
+/// Returns the value of the state, or `None` if it does not fit in a `u128`.
+pub fn to_u128(state: &[u64]) -> Option<u128> {
+    match trim(state) {
+        [] => Some(0),
+        [word] => Some(*word as u128),
+        [high, low] => Some(((*high as u128) << WORD_BITS) | *low as u128),
+        _ => None,
+    }
+}
+
+/// Returns the state of a `u128` value, with one word if it fits in a `u64`.
+pub fn from_u128(value: u128) -> Vec<u64> {
+    let (high, low) = ((value >> WORD_BITS) as u64, value as u64);
+    if high == 0 {
+        vec![low]
+    } else {
+        vec![high, low]
+    }
+}
+
+/// Returns the 64 least significant bits of the state.
+pub fn low_word(state: &[u64]) -> u64 {
+    state.last().copied().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{
+        bit, compare, from_bits, from_bitstring, from_u128, set_bit, to_bitstring, to_u128, to_u64,
+        words,
+    };
+
+    #[test]
+    fn multi_word_states() {
+        let bitstring = format!("101{}", "0".repeat(63));
+        let state = from_bitstring(&bitstring).unwrap();
+        assert_eq!(state, [0b10, 1 << 63]);
+        assert_eq!(to_bitstring(&state, bitstring.len()), bitstring);
+        assert_eq!(to_u128(&state), Some(0b101 << 63));
+        assert_eq!(from_u128(0b101 << 63), state);
+        assert_eq!(to_u64(&state), None);
+        assert_eq!(to_u64(&[0, 7]), Some(7));
+        assert!(bit(&state, 63) && !bit(&state, 64) && bit(&state, 65));
+        assert!(!bit(&state, 200));
+
+        assert_eq!(compare(&[0, 0, 5], &[5]), Ordering::Equal);
+        assert_eq!(compare(&[1, 0], &[u64::MAX]), Ordering::Greater);
+        assert_eq!(compare(&[3], &[0, 4]), Ordering::Less);
+
+        let mut state = vec![1];
+        set_bit(&mut state, 130, true);
+        assert_eq!(state, [4, 0, 1]);
+        set_bit(&mut state, 0, false);
+        set_bit(&mut state, 300, false);
+        assert_eq!(state, [4, 0, 0]);
+
+        assert_eq!(from_bits([true, false, true]), [0b101]);
+        assert_eq!(from_bits([]), [0]);
+        assert!(from_bitstring("012").is_none());
+        assert_eq!((words(0), words(64), words(65)), (1, 1, 2));
+    }
+}
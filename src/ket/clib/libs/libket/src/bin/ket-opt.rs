@@ -0,0 +1,134 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Optimizes an OpenQASM 2.0 file with a pipeline of passes.
+//!
+//! The optimized circuit is written to the standard output, or to the file given with
+//! `--output`, and a resource report comparing the input and the output circuits is
+//! written to the standard error.
+//!
+//! ```text
+//! ket-opt circuit.qasm --passes peephole,clifford_simp,routing,scheduling --target device.json
+//! ```
+
+use std::{
+    error::Error,
+    io::{self, Read},
+};
+
+use clap::Parser;
+use ket::{
+    passes::{
+        CouplingMap, LightCone, PassManager, Peephole, ResourceReport, Routing, Scheduling,
+        TwoQubitResynthesis, ZXSimplify,
+    },
+    qasmv2::instruction_set::InstructionSet,
+    zx::optimize::ZXStrategy,
+    Configuration, Process,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "ket-opt",
+    version,
+    about = "Optimize an OpenQASM 2.0 file with a pipeline of passes"
+)]
+struct Args {
+    /// OpenQASM 2.0 file, or `-` to read from the standard input
+    file: String,
+
+    /// Comma-separated list of passes: clifford_simp, full_simp, light_cone, peephole,
+    /// resynthesis, routing, scheduling
+    #[arg(long, value_delimiter = ',', default_value = "peephole,clifford_simp")]
+    passes: Vec<String>,
+
+    /// Coupling map in JSON, required by the routing and scheduling passes
+    #[arg(long)]
+    target: Option<String>,
+
+    /// Output file for the optimized circuit
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Print the report after every pass instead of only the final difference
+    #[arg(long)]
+    verbose: bool,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn pipeline(passes: &[String], target: Option<&CouplingMap>) -> Result<PassManager, String> {
+    let mut manager = PassManager::new();
+    for pass in passes {
+        manager = match pass.trim() {
+            "clifford_simp" => manager.add_pass(ZXSimplify(ZXStrategy::Clifford)),
+            "full_simp" => manager.add_pass(ZXSimplify(ZXStrategy::Full)),
+            "light_cone" => manager.add_pass(LightCone::default()),
+            "peephole" => manager.add_pass(Peephole),
+            "resynthesis" => manager.add_pass(TwoQubitResynthesis),
+            "routing" => manager.add_pass(Routing {
+                coupling_map: target
+                    .ok_or("the routing pass requires a --target coupling map")?
+                    .clone(),
+            }),
+            "scheduling" => manager.add_pass(Scheduling {
+                coupling_map: target
+                    .ok_or("the scheduling pass requires a --target coupling map")?
+                    .clone(),
+            }),
+            other => return Err(format!("unknown pass `{}`", other)),
+        };
+    }
+    Ok(manager)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let mut qasm = String::new();
+    if args.file == "-" {
+        io::stdin().read_to_string(&mut qasm)?;
+    } else {
+        qasm = std::fs::read_to_string(&args.file)?;
+    }
+
+    let target = match &args.target {
+        Some(path) => Some(CouplingMap::from_json(&std::fs::read_to_string(path)?)?),
+        None => None,
+    };
+
+    let manager = pipeline(&args.passes, target.as_ref())?;
+
+    let mut configuration = Configuration::new(usize::MAX);
+    configuration.optimize = false;
+    let mut process = Process::new(configuration);
+    process.from_qasmv2(&qasm, InstructionSet::QELIB, false)?;
+
+    let reports = manager.run(&mut process)?;
+
+    let optimized = process.to_qasmv2(true, InstructionSet::QELIB)?;
+    match &args.output {
+        Some(path) => std::fs::write(path, optimized)?,
+        None => print!("{}", optimized),
+    }
+
+    if args.json {
+        eprintln!("{}", serde_json::to_string_pretty(&reports)?);
+    } else if args.verbose {
+        for pair in reports.windows(2) {
+            eprintln!("== {}", pair[1].0);
+            eprint!("{}", pair[0].1.diff(&pair[1].1));
+        }
+    } else {
+        let input: &ResourceReport = &reports.first().unwrap().1;
+        let output: &ResourceReport = &reports.last().unwrap().1;
+        eprintln!("== {}", manager.names().join(", "));
+        eprint!("{}", input.diff(output));
+    }
+
+    Ok(())
+}
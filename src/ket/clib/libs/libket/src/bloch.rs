@@ -0,0 +1,174 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reduced density matrices and Bloch vectors.
+//!
+//! The reduced density matrix of a subset of qubits is computed from a dump of every
+//! allocated qubit, tracing out the others. Amplitudes whose basis states only differ in
+//! the kept qubits share the same state of the environment, and only these pairs
+//! contribute to the coherences of the reduced matrix.
+
+use std::collections::HashMap;
+
+use num::{complex::Complex64, Zero};
+
+use crate::{basis, ir::DumpData, Process};
+
+/// Bloch vector `(x, y, z)` of a single qubit.
+pub type BlochVector = [f64; 3];
+
+impl DumpData {
+    /// Reduced density matrix of the qubits at `positions` of the dump.
+    ///
+    /// The dump must include all `num_qubits` allocated qubits, otherwise the
+    /// coherences between the kept qubits and the rest of the state are lost. The
+    /// first position is the most significant bit of the matrix index.
+    pub fn reduced_density_matrix(
+        &self,
+        num_qubits: usize,
+        positions: &[usize],
+    ) -> Vec<Vec<Complex64>> {
+        let mut environment: HashMap<Vec<u64>, Vec<(usize, Complex64)>> = HashMap::new();
+        for ((state, real), imag) in self
+            .basis_states
+            .iter()
+            .zip(&self.amplitudes_real)
+            .zip(&self.amplitudes_imag)
+        {
+            let mut rest = state.clone();
+            let mut index = 0;
+            for position in positions {
+                // The first qubit is the most significant bit of the basis state.
+                let bit = num_qubits - position - 1;
+                index <<= 1;
+                if basis::bit(state, bit) {
+                    index |= 1;
+                    basis::set_bit(&mut rest, bit, false);
+                }
+            }
+            environment
+                .entry(basis::trim(&rest).to_vec())
+                .or_default()
+                .push((index, Complex64::new(*real, *imag)));
+        }
+
+        let size = 1 << positions.len();
+        let mut matrix = vec![vec![Complex64::zero(); size]; size];
+        for amplitudes in environment.values() {
+            for (row, amp_row) in amplitudes {
+                for (column, amp_column) in amplitudes {
+                    matrix[*row][*column] += amp_row * amp_column.conj();
+                }
+            }
+        }
+        matrix
+    }
+
+    /// Bloch vector of the qubit at `position` of the dump.
+    ///
+    /// See [`DumpData::reduced_density_matrix`].
+    pub fn bloch_vector(&self, num_qubits: usize, position: usize) -> BlochVector {
+        bloch_vector(&self.reduced_density_matrix(num_qubits, &[position]))
+    }
+}
+
+/// Bloch vector of a single-qubit density matrix.
+pub fn bloch_vector(rho: &[Vec<Complex64>]) -> BlochVector {
+    [
+        2.0 * rho[0][1].re,
+        -2.0 * rho[0][1].im,
+        rho[0][0].re - rho[1][1].re,
+    ]
+}
+
+/// Handle for a Bloch vector scheduled with [`Process::bloch_vector`].
+#[derive(Debug, Clone)]
+pub struct BlochHandle {
+    /// Index of the dump of all allocated qubits.
+    pub dump: usize,
+
+    /// Position of the qubit in the dump.
+    pub position: usize,
+}
+
+impl BlochHandle {
+    /// Returns the Bloch vector, or `None` if the process has not been executed.
+    pub fn get(&self, process: &Process) -> Option<BlochVector> {
+        let num_qubits = process.get_dump(self.dump).qubits.len();
+        process
+            .get_dump_data(self.dump)
+            .map(|data| data.bloch_vector(num_qubits, self.position))
+    }
+}
+
+/// Bloch vectors of the tracked qubits after each gate of a live execution.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlochTrajectories {
+    pub(crate) trajectories: Vec<(usize, Vec<BlochVector>)>,
+}
+
+impl BlochTrajectories {
+    /// Returns `true` if no qubit is tracked.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.trajectories.is_empty()
+    }
+
+    /// Appends the Bloch vector of every tracked qubit that is in the dump.
+    pub(crate) fn record(&mut self, qubits: &[usize], data: &DumpData) {
+        for (qubit, trajectory) in self.trajectories.iter_mut() {
+            if let Some(position) = qubits.iter().position(|dumped| dumped == qubit) {
+                trajectory.push(data.bloch_vector(qubits.len(), position));
+            }
+        }
+    }
+
+    /// Returns the trajectory of a tracked qubit.
+    pub(crate) fn get(&self, qubit: usize) -> Option<&[BlochVector]> {
+        self.trajectories
+            .iter()
+            .find(|(tracked, _)| *tracked == qubit)
+            .map(|(_, trajectory)| trajectory.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use crate::ir::DumpData;
+
+    #[test]
+    fn bloch_vectors() {
+        // |+> (x) |0>, with the first qubit as the most significant bit.
+        let plus = DumpData {
+            basis_states: vec![vec![0b00], vec![0b10]],
+            amplitudes_real: vec![FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+            amplitudes_imag: vec![0.0, 0.0],
+        };
+        let [x, y, z] = plus.bloch_vector(2, 0);
+        assert!((x - 1.0).abs() < 1e-12 && y.abs() < 1e-12 && z.abs() < 1e-12);
+        let [x, y, z] = plus.bloch_vector(2, 1);
+        assert!(x.abs() < 1e-12 && y.abs() < 1e-12 && (z - 1.0).abs() < 1e-12);
+
+        // (|00> + i|11>)/sqrt(2): each qubit is maximally mixed.
+        let bell = DumpData {
+            basis_states: vec![vec![0b00], vec![0b11]],
+            amplitudes_real: vec![FRAC_1_SQRT_2, 0.0],
+            amplitudes_imag: vec![0.0, FRAC_1_SQRT_2],
+        };
+        assert!(bell.bloch_vector(2, 1).iter().all(|x| x.abs() < 1e-12));
+        let rho = bell.reduced_density_matrix(2, &[1, 0]);
+        assert!((rho[3][0].im - 0.5).abs() < 1e-12);
+
+        // |-i> on the first of 65 qubits, stored in the first word.
+        let minus_i = DumpData {
+            basis_states: vec![vec![0, 0], vec![1, 0]],
+            amplitudes_real: vec![FRAC_1_SQRT_2, 0.0],
+            amplitudes_imag: vec![0.0, -FRAC_1_SQRT_2],
+        };
+        let [x, y, z] = minus_i.bloch_vector(65, 0);
+        assert!(x.abs() < 1e-12 && (y + 1.0).abs() < 1e-12 && z.abs() < 1e-12);
+    }
+}
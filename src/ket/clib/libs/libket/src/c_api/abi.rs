@@ -0,0 +1,987 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! ABI stability checks of the C API.
+//!
+//! [`LIBKET`] lists every exported function with the Rust types of its arguments, and
+//! the build fails if a function no longer has the listed signature. The list is kept
+//! by hand, so the tests compare its names with the `#[no_mangle]` functions of the
+//! sources, see [`source_mismatches`], to catch a new function that was not listed,
+//! or a listed one that is no longer exported. The list is then
+//! compared with the header generated by `cbindgen`, see [`header_mismatches`], and
+//! with the `ctypes` declarations of the Python layer, see [`python_mismatches`], so
+//! a signature changed on one side only is caught by the tests. The tests also call
+//! every function of [`LIBKET`] through the lifecycle of a process, from its creation
+//! to its results and its debugger. KBW checks its own C API with the same functions.
+
+#[cfg(feature = "qasm")]
+use std::ffi::c_char;
+
+use super::{
+    debugger::*, error::ket_error_message, executor::*, ket_set_log_level, objects::*, process::*,
+};
+use crate::{debugger::Debugger, Checkpoint, Configuration, PauliHamiltonian, Process};
+
+/// Exported function of a C API, with the Rust types of its arguments.
+///
+/// Every function returns an `i32` error code.
+#[derive(Debug, Clone, Copy)]
+pub struct Signature {
+    /// Name of the function.
+    pub name: &'static str,
+
+    /// Rust types of the arguments, in order.
+    pub args: &'static [&'static str],
+}
+
+/// Lists the functions in [`LIBKET`] and checks at compile time that each one still
+/// has the listed signature.
+macro_rules! signatures {
+    ($($(#[$meta:meta])* $name:ident($($arg:ty),* $(,)?);)*) => {
+        $(
+            $(#[$meta])*
+            const _: unsafe extern "C" fn($($arg),*) -> i32 = $name;
+        )*
+
+        /// Functions of the C API of Libket.
+        pub const LIBKET: &[Signature] = &[$(
+            Signature {
+                name: stringify!($name),
+                args: &[$(stringify!($arg)),*],
+            },
+        )*];
+    };
+}
+
+signatures! {
+    ket_set_log_level(u32);
+    ket_error_message(i32, *mut u8, usize, &mut usize);
+
+    ket_process_new(*mut Configuration, &mut *mut Process);
+    ket_process_delete(*mut Process);
+    ket_process_allocate_qubit(&mut Process, &mut usize);
+    ket_process_free_qubit(&mut Process, usize);
+    ket_process_optimize(&mut Process);
+    ket_process_apply_gate(&mut Process, i32, i32, u32, f64, usize);
+    ket_process_apply_global_phase(&mut Process, i32, u32, f64);
+    ket_process_measure(&mut Process, *const usize, usize, &mut usize);
+    ket_process_force_measurement(&mut Process, usize, u64);
+    ket_process_apply_kraus_channel(&mut Process, *const f64, *const f64, usize, usize);
+    ket_process_apply_diagonal(&mut Process, *const f64, *const usize, usize);
+    ket_process_apply_multiplexor(&mut Process, i32, *const f64, *const usize, usize, usize);
+    ket_hamiltonian_new(&mut *mut PauliHamiltonian);
+    ket_hamiltonian_from_openfermion(*const u8, usize, &mut *mut PauliHamiltonian);
+    ket_hamiltonian_from_json(*const u8, usize, &mut *mut PauliHamiltonian);
+    ket_hamiltonian_add(&mut PauliHamiltonian, *const i32, usize, *const usize, usize, f64);
+    ket_process_exp_value(&mut Process, *mut PauliHamiltonian, &mut usize);
+    ket_process_sample(&mut Process, *const usize, usize, u64, &mut usize);
+    ket_process_dump(&mut Process, *const usize, usize, &mut usize);
+    ket_process_dump_top_k(&mut Process, *const usize, usize, usize, &mut usize);
+    ket_process_ctrl_push(&mut Process, *const usize, usize);
+    ket_process_ctrl_pop(&mut Process);
+    ket_process_adj_begin(&mut Process);
+    ket_process_adj_end(&mut Process);
+    ket_process_opaque_begin(&mut Process);
+    ket_process_opaque_end(&mut Process);
+    ket_process_prepare_for_execution(&mut Process);
+    ket_process_instructions_json(&mut Process, *mut u8, usize, &mut usize);
+    ket_process_structure_json(&mut Process, *mut u8, usize, &mut usize);
+    #[cfg(feature = "qasm")]
+    ket_process_to_qasmv2(&mut Process, *mut u8, usize, &mut usize);
+    #[cfg(feature = "qasm")]
+    ket_process_from_qasmv2(&mut Process, *const c_char);
+    ket_process_metadata_json(&mut Process, *mut u8, usize, &mut usize);
+    ket_process_set_metadata_extension(&mut Process, *const u8, usize, *const u8, usize);
+    ket_process_get_metadata_extension(&Process, *const u8, usize, *mut u8, usize, &mut usize);
+    ket_process_set_source_location(&mut Process, *const u8, usize, u32, *const u8, usize);
+    ket_process_clear_source_location(&mut Process);
+    ket_process_provenance_json(&Process, *mut u8, usize, &mut usize);
+    ket_process_checkpoint(&mut Process, &mut *mut Checkpoint);
+    ket_process_rollback(&mut Process, &Checkpoint);
+    ket_checkpoint_delete(*mut Checkpoint);
+    ket_process_start_trace(&mut Process);
+    ket_process_save_trace(&Process, *const u8, usize);
+    ket_process_replay_trace(&mut Process, *const u8, usize);
+
+    ket_process_get_qubit_status(&Process, usize, &mut bool, &mut bool);
+    ket_process_get_measurement(&Process, usize, &mut bool, &mut u64);
+    ket_process_get_measurement_as_integer(&Process, usize, bool, bool, &mut bool, &mut i64);
+    ket_process_get_exp_value(&Process, usize, &mut bool, &mut f64);
+    ket_process_get_sample(&Process, usize, &mut bool, &mut *const u64, &mut *const u64, &mut usize);
+    ket_process_get_dump_size(&Process, usize, &mut bool, &mut usize);
+    ket_process_get_dump(&mut Process, usize, usize, &mut *const u64, &mut usize, &mut f64, &mut f64);
+
+    ket_debugger_new(*mut Configuration, &Process, &mut *mut Debugger);
+    ket_debugger_delete(*mut Debugger);
+    ket_debugger_add_instruction_breakpoint(&mut Debugger, usize, &mut usize);
+    ket_debugger_add_qubit_breakpoint(&mut Debugger, usize, i32, &mut usize);
+    ket_debugger_remove_breakpoint(&mut Debugger, usize, &mut bool);
+    ket_debugger_step(&mut Debugger, &mut i32, &mut usize);
+    ket_debugger_resume(&mut Debugger, &mut i32, &mut usize);
+    ket_debugger_position(&Debugger, &mut usize);
+    ket_debugger_get_measurement(&Debugger, usize, &mut bool, &mut u64);
+    ket_debugger_debug_state(&mut Debugger, *mut u8, usize, &mut usize);
+    ket_debugger_set_state_format(&mut Debugger, *const usize, usize, bool, bool, usize, usize);
+    ket_debugger_reduced_density_matrix(&mut Debugger, *const usize, usize, *mut f64, *mut f64);
+
+    ket_make_callback_configuration(usize, &ExecutorCallbacks, bool, &mut *mut Configuration);
+}
+
+/// `ctypes` scalar types and the Rust types they are passed as.
+const CTYPES: &[(&str, &str)] = &[
+    ("c_bool", "bool"),
+    ("c_uint8", "u8"),
+    ("c_int32", "i32"),
+    ("c_uint32", "u32"),
+    ("c_int64", "i64"),
+    ("c_uint64", "u64"),
+    ("c_size_t", "usize"),
+    ("c_double", "f64"),
+];
+
+/// Returns the differences between the functions declared in a C header and `abi`.
+///
+/// Only the functions whose names start with `prefix` are compared. The `#if`
+/// guards of the header are ignored, so the functions of every feature are declared.
+pub fn header_mismatches(header: &str, prefix: &str, abi: &[Signature]) -> Vec<String> {
+    let declared: Vec<&str> = header
+        .lines()
+        .map(str::trim_start)
+        .filter(|line| !line.starts_with('*') && !line.starts_with('/'))
+        .filter_map(|line| line.split_once('('))
+        .filter_map(|(head, _)| head.split_whitespace().last())
+        .map(|name| name.trim_start_matches('*'))
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+
+    let missing = abi
+        .iter()
+        .filter(|function| !declared.contains(&function.name))
+        .map(|function| format!("{}: missing from the header", function.name));
+    let unlisted = declared
+        .iter()
+        .filter(|name| !abi.iter().any(|function| function.name == **name))
+        .map(|name| format!("{name}: declared in the header but not listed"));
+    missing.chain(unlisted).collect()
+}
+
+/// Returns the differences between the `#[no_mangle]` functions of the Rust `sources`
+/// and `abi`.
+pub fn source_mismatches(sources: &[&str], abi: &[Signature]) -> Vec<String> {
+    let mut exported = Vec::new();
+    for source in sources {
+        let mut no_mangle = false;
+        for line in source.lines().map(str::trim) {
+            if line == "#[no_mangle]" {
+                no_mangle = true;
+            } else if let Some((_, after)) = line.split_once("fn ").filter(|_| no_mangle) {
+                exported.extend(after.split('(').next());
+                no_mangle = false;
+            }
+        }
+    }
+
+    let missing = abi
+        .iter()
+        .filter(|function| !exported.contains(&function.name))
+        .map(|function| format!("{}: listed but not exported", function.name));
+    let unlisted = exported
+        .iter()
+        .filter(|name| !abi.iter().any(|function| function.name == **name))
+        .map(|name| format!("{name}: exported but not listed"));
+    missing.chain(unlisted).collect()
+}
+
+/// Returns the differences between the `API_argtypes` table of a Python wrapper and
+/// `abi`.
+///
+/// Each entry of the table has the `ctypes` types of the input and output arguments,
+/// and the output arguments are passed by pointer, so they must be `&mut` in Rust.
+/// Functions of `abi` missing from the table are not reported, since the Python layer
+/// does not wrap every function.
+pub fn python_mismatches(python: &str, abi: &[Signature]) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    for (name, inputs, outputs) in python_argtypes(python) {
+        let Some(function) = abi.iter().find(|function| function.name == name) else {
+            mismatches.push(format!("{name}: declared in Python but not exported"));
+            continue;
+        };
+
+        if inputs.len() + outputs.len() != function.args.len() {
+            mismatches.push(format!(
+                "{name}: {} arguments in Python and {} in Rust",
+                inputs.len() + outputs.len(),
+                function.args.len()
+            ));
+            continue;
+        }
+
+        let arguments = inputs
+            .iter()
+            .map(|ctype| (ctype, false))
+            .chain(outputs.iter().map(|ctype| (ctype, true)));
+        for (index, ((ctype, output), rust)) in arguments.zip(function.args).enumerate() {
+            let rust: String = rust.split_whitespace().collect();
+            let matches = if output {
+                rust.strip_prefix("&mut")
+                    .is_some_and(|pointee| ctype_matches(ctype, pointee))
+            } else {
+                ctype_matches(ctype, &rust)
+            };
+            if !matches {
+                mismatches.push(format!(
+                    "{name}: argument {index} is {ctype} in Python and {rust} in Rust"
+                ));
+            }
+        }
+    }
+    mismatches
+}
+
+/// Returns whether a `ctypes` type is passed as the Rust type, without whitespace.
+fn ctype_matches(ctype: &str, rust: &str) -> bool {
+    if let Some(pointee) = ctype
+        .strip_prefix("POINTER(")
+        .and_then(|ctype| ctype.strip_suffix(')'))
+    {
+        return ["*const", "*mut", "&mut", "&"].iter().any(|pointer| {
+            rust.strip_prefix(pointer)
+                .is_some_and(|rust| ctype_matches(pointee, rust))
+        });
+    }
+
+    match ctype {
+        "c_void_p" => rust.starts_with('*') || rust.starts_with('&'),
+        "c_char_p" => rust == "*constc_char",
+        _ => CTYPES.contains(&(ctype, rust)),
+    }
+}
+
+/// Parses the `API_argtypes` table of a Python wrapper, returning the name and the
+/// input and output types of each function.
+fn python_argtypes(python: &str) -> Vec<(String, Vec<String>, Vec<String>)> {
+    let Some(start) = python.find("API_argtypes = {") else {
+        return Vec::new();
+    };
+    let table = &python[start..];
+    let table = &table[..table.find("\n}").unwrap_or(table.len())];
+    let table: String = table
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut entries = Vec::new();
+    let mut rest = table.as_str();
+    while let Some((_, after)) = rest.split_once('"') {
+        let Some((name, after)) = after.split_once('"') else {
+            break;
+        };
+        let Some((inputs, after)) = python_list(after) else {
+            break;
+        };
+        let Some((outputs, after)) = python_list(after) else {
+            break;
+        };
+        entries.push((name.to_string(), inputs, outputs));
+        rest = after;
+    }
+    entries
+}
+
+/// Returns the items of the first Python list in `text`, without whitespace, and the
+/// text after the list.
+fn python_list(text: &str) -> Option<(Vec<String>, &str)> {
+    let start = text.find('[')?;
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut depth = 0;
+    for (index, c) in text[start..].char_indices() {
+        match c {
+            '[' | '(' if depth > 0 => {
+                item.push(c);
+                depth += 1;
+            }
+            '[' | '(' => depth += 1,
+            ']' | ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    if !item.is_empty() {
+                        items.push(item);
+                    }
+                    return Some((items, &text[start + index + 1..]));
+                }
+                item.push(c);
+            }
+            ',' if depth == 1 && !item.is_empty() => items.push(std::mem::take(&mut item)),
+            ',' if depth == 1 => {}
+            c if !c.is_whitespace() => item.push(c),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, ffi::c_void};
+
+    use super::{header_mismatches, python_mismatches, source_mismatches, Signature, LIBKET};
+    use crate::{
+        c_api::{
+            debugger::*, error::ket_error_message, executor::*, ket_set_log_level, objects::*,
+            process::*, HEADER,
+        },
+        error::KetError,
+        ir::{DumpData, Instruction, KrausOperator, PauliHamiltonian, QuantumGate, ResultData},
+        BatchExecution, Configuration, ExecutionStatus, LiveExecution,
+    };
+
+    /// Executor that keeps every qubit in `|0⟩`, except that the measurements return
+    /// their forced outcomes in batch mode.
+    #[derive(Default)]
+    struct Ground {
+        forced: BTreeMap<usize, u64>,
+        result: ResultData,
+    }
+
+    fn ground_state() -> DumpData {
+        DumpData {
+            basis_states: vec![vec![0]],
+            amplitudes_real: vec![1.0],
+            amplitudes_imag: vec![0.0],
+        }
+    }
+
+    impl LiveExecution for Ground {
+        fn alloc(&mut self, _: usize) {}
+        fn free(&mut self, _: usize) {}
+        fn gate(&mut self, _: &QuantumGate, _: usize, _: &[usize]) {}
+        fn measure(&mut self, _: &[usize]) -> u64 {
+            0
+        }
+        fn exp_value(&mut self, _: &PauliHamiltonian) -> f64 {
+            1.0
+        }
+        fn sample(&mut self, _: &[usize], shots: u64) -> (Vec<u64>, Vec<u64>) {
+            (vec![0], vec![shots])
+        }
+        fn dump(&mut self, _: &[usize]) -> DumpData {
+            ground_state()
+        }
+        fn kraus_channel(&mut self, _: &[KrausOperator], _: usize) -> bool {
+            true
+        }
+    }
+
+    impl BatchExecution for Ground {
+        fn submit_execution(&mut self, instructions: &[Instruction]) {
+            for instruction in instructions {
+                match instruction {
+                    Instruction::Measure { output, .. } => self
+                        .result
+                        .measurements
+                        .push(self.forced.get(output).copied().unwrap_or(0)),
+                    Instruction::ExpValue { .. } => self.result.exp_values.push(1.0),
+                    Instruction::Sample { shots, .. } => {
+                        self.result.samples.push((vec![0], vec![*shots]))
+                    }
+                    Instruction::Dump { .. } => self.result.dumps.push(ground_state()),
+                    _ => {}
+                }
+            }
+        }
+
+        fn force_measurements(&mut self, outcomes: &BTreeMap<usize, u64>) {
+            self.forced = outcomes.clone();
+        }
+
+        fn get_result(&mut self) -> ResultData {
+            std::mem::take(&mut self.result)
+        }
+
+        fn get_status(&self) -> ExecutionStatus {
+            ExecutionStatus::Completed
+        }
+    }
+
+    unsafe extern "C" fn gate(
+        _: *mut c_void,
+        _: i32,
+        _: i32,
+        _: u32,
+        _: f64,
+        _: usize,
+        _: *const usize,
+        _: usize,
+    ) {
+    }
+
+    unsafe extern "C" fn measure(_: *mut c_void, _: *const usize, _: usize) -> u64 {
+        0
+    }
+
+    /// Calls an exported function, checks that it succeeds, and records its name.
+    macro_rules! call {
+        ($called:ident, $name:ident($($arg:expr),* $(,)?)) => {{
+            $called.push(stringify!($name));
+            #[allow(unused_unsafe)]
+            let code = unsafe { $name($($arg),*) };
+            assert_eq!(code, KetError::Success.error_code(), stringify!($name));
+        }};
+    }
+
+    #[test]
+    fn lists_every_export() {
+        let sources = [
+            include_str!("mod.rs"),
+            include_str!("debugger.rs"),
+            include_str!("error.rs"),
+            include_str!("executor.rs"),
+            include_str!("objects.rs"),
+            include_str!("process.rs"),
+        ];
+        assert_eq!(source_mismatches(&sources, LIBKET), Vec::<String>::new());
+    }
+
+    #[test]
+    fn header_declares_every_function() {
+        assert_eq!(
+            header_mismatches(HEADER, "ket_", LIBKET),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn python_layer_matches() {
+        let python = include_str!("../../../../libket.py");
+        assert_eq!(python_mismatches(python, LIBKET), Vec::<String>::new());
+    }
+
+    #[test]
+    fn drives_every_export() {
+        const HADAMARD: i32 = 0;
+        const PAULI_X: i32 = 1;
+        const ROTATION_Z: i32 = 30;
+        const PAULI_Z: i32 = 3;
+
+        let mut called = Vec::new();
+        let mut buffer = vec![0u8; 1 << 16];
+        let mut write_size = 0;
+        let (mut available, mut id, mut stop, mut removed) = (false, 0, 0, false);
+
+        call!(called, ket_set_log_level(0));
+        call!(
+            called,
+            ket_error_message(
+                KetError::Success.error_code(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut write_size,
+            )
+        );
+
+        let configuration = Box::into_raw(Box::new(Configuration {
+            batch_execution: Some(Box::new(Ground::default())),
+            ..Configuration::new(4)
+        }));
+        let mut process = std::ptr::null_mut();
+        call!(called, ket_process_new(configuration, &mut process));
+        let process = unsafe { &mut *process };
+        call!(called, ket_process_start_trace(process));
+
+        let mut qubits = [0; 4];
+        for qubit in &mut qubits {
+            call!(called, ket_process_allocate_qubit(process, qubit));
+        }
+
+        let mut checkpoint = std::ptr::null_mut();
+        call!(called, ket_process_checkpoint(process, &mut checkpoint));
+        call!(
+            called,
+            ket_process_apply_gate(process, PAULI_X, 0, 0, 0.0, qubits[3])
+        );
+        call!(called, ket_process_rollback(process, &*checkpoint));
+        call!(called, ket_checkpoint_delete(checkpoint));
+        call!(called, ket_process_free_qubit(process, qubits[3]));
+
+        let (file, function) = ("main.py", "main");
+        call!(
+            called,
+            ket_process_set_source_location(
+                process,
+                file.as_ptr(),
+                file.len(),
+                1,
+                function.as_ptr(),
+                function.len(),
+            )
+        );
+        call!(
+            called,
+            ket_process_apply_gate(process, HADAMARD, 0, 0, 0.0, qubits[0])
+        );
+        call!(called, ket_process_clear_source_location(process));
+        call!(called, ket_process_ctrl_push(process, qubits.as_ptr(), 1));
+        call!(
+            called,
+            ket_process_apply_gate(process, PAULI_X, 0, 0, 0.0, qubits[1])
+        );
+        call!(called, ket_process_ctrl_pop(process));
+        call!(called, ket_process_adj_begin(process));
+        call!(
+            called,
+            ket_process_apply_gate(process, ROTATION_Z, 1, 4, 0.0, qubits[2])
+        );
+        call!(called, ket_process_adj_end(process));
+        call!(called, ket_process_opaque_begin(process));
+        call!(
+            called,
+            ket_process_apply_gate(process, HADAMARD, 0, 0, 0.0, qubits[2])
+        );
+        call!(called, ket_process_opaque_end(process));
+        call!(called, ket_process_apply_global_phase(process, 1, 2, 0.0));
+        let (real, imag) = ([1.0, 0.0, 0.0, 1.0], [0.0; 4]);
+        call!(
+            called,
+            ket_process_apply_kraus_channel(process, real.as_ptr(), imag.as_ptr(), 1, qubits[2])
+        );
+        let phases = [0.0, 0.5, 0.5, 0.0];
+        call!(
+            called,
+            ket_process_apply_diagonal(process, phases.as_ptr(), qubits.as_ptr(), 2)
+        );
+        let angles = [0.25, 0.75];
+        call!(
+            called,
+            ket_process_apply_multiplexor(
+                process,
+                2,
+                angles.as_ptr(),
+                qubits.as_ptr(),
+                1,
+                qubits[2]
+            )
+        );
+        call!(called, ket_process_optimize(process));
+
+        let mut exp_values = [0; 3];
+        let mut hamiltonian = std::ptr::null_mut();
+        call!(called, ket_hamiltonian_new(&mut hamiltonian));
+        let paulis = [PAULI_Z; 2];
+        call!(
+            called,
+            ket_hamiltonian_add(
+                &mut *hamiltonian,
+                paulis.as_ptr(),
+                2,
+                qubits.as_ptr(),
+                2,
+                1.0
+            )
+        );
+        call!(
+            called,
+            ket_process_exp_value(process, hamiltonian, &mut exp_values[0])
+        );
+        let operator = "0.5 [Z0 Z1] +\n0.5 [X2]";
+        call!(
+            called,
+            ket_hamiltonian_from_openfermion(operator.as_ptr(), operator.len(), &mut hamiltonian)
+        );
+        call!(
+            called,
+            ket_process_exp_value(process, hamiltonian, &mut exp_values[1])
+        );
+        let json = r#"{"terms": [{"coefficient": 1.0, "paulis": [{"pauli": "Z", "qubit": 0}]}]}"#;
+        call!(
+            called,
+            ket_hamiltonian_from_json(json.as_ptr(), json.len(), &mut hamiltonian)
+        );
+        call!(
+            called,
+            ket_process_exp_value(process, hamiltonian, &mut exp_values[2])
+        );
+
+        let (mut sample, mut dump, mut top_k, mut measurement) = (0, 0, 0, 0);
+        call!(
+            called,
+            ket_process_sample(process, qubits.as_ptr(), 3, 16, &mut sample)
+        );
+        call!(
+            called,
+            ket_process_dump(process, qubits.as_ptr(), 3, &mut dump)
+        );
+        call!(
+            called,
+            ket_process_dump_top_k(process, qubits.as_ptr(), 3, 1, &mut top_k)
+        );
+        call!(
+            called,
+            ket_process_measure(process, qubits.as_ptr(), 3, &mut measurement)
+        );
+        call!(
+            called,
+            ket_process_force_measurement(process, measurement, 0b101)
+        );
+
+        let (key, value) = ("run", br#"{"shots": 16}"#);
+        call!(
+            called,
+            ket_process_set_metadata_extension(
+                process,
+                key.as_ptr(),
+                key.len(),
+                value.as_ptr(),
+                value.len(),
+            )
+        );
+        call!(
+            called,
+            ket_process_get_metadata_extension(
+                process,
+                key.as_ptr(),
+                key.len(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut write_size,
+            )
+        );
+        assert_eq!(&buffer[..write_size], br#"{"shots":16}"#);
+
+        let path = std::env::temp_dir().join(format!("ket-abi-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        call!(
+            called,
+            ket_process_save_trace(process, path.as_ptr(), path.len())
+        );
+        let mut replayed = std::ptr::null_mut();
+        call!(
+            called,
+            ket_process_new(
+                Box::into_raw(Box::new(Configuration::new(4))),
+                &mut replayed
+            )
+        );
+        call!(
+            called,
+            ket_process_replay_trace(&mut *replayed, path.as_ptr(), path.len())
+        );
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            unsafe { &*replayed }.instructions().len(),
+            process.instructions().len()
+        );
+        call!(called, ket_process_delete(replayed));
+
+        call!(called, ket_process_prepare_for_execution(process));
+        call!(
+            called,
+            ket_process_instructions_json(
+                process,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut write_size
+            )
+        );
+        call!(
+            called,
+            ket_process_structure_json(process, buffer.as_mut_ptr(), buffer.len(), &mut write_size)
+        );
+        call!(
+            called,
+            ket_process_metadata_json(process, buffer.as_mut_ptr(), buffer.len(), &mut write_size)
+        );
+        call!(
+            called,
+            ket_process_provenance_json(
+                process,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut write_size
+            )
+        );
+
+        let (mut allocated, mut measured) = (false, false);
+        call!(
+            called,
+            ket_process_get_qubit_status(process, qubits[0], &mut allocated, &mut measured)
+        );
+        assert!(allocated && measured);
+
+        let mut result = 0;
+        call!(
+            called,
+            ket_process_get_measurement(process, measurement, &mut available, &mut result)
+        );
+        assert!(available);
+        assert_eq!(result, 0b101);
+        let mut integer = 0;
+        call!(
+            called,
+            ket_process_get_measurement_as_integer(
+                process,
+                measurement,
+                false,
+                true,
+                &mut available,
+                &mut integer,
+            )
+        );
+        assert_eq!(integer, -3);
+
+        let mut value = 0.0;
+        for exp_value in exp_values {
+            call!(
+                called,
+                ket_process_get_exp_value(process, exp_value, &mut available, &mut value)
+            );
+            assert!(available);
+            assert_eq!(value, 1.0);
+        }
+
+        let (mut states, mut counts, mut size) = (std::ptr::null(), std::ptr::null(), 0);
+        call!(
+            called,
+            ket_process_get_sample(
+                process,
+                sample,
+                &mut available,
+                &mut states,
+                &mut counts,
+                &mut size,
+            )
+        );
+        assert!(available);
+        assert_eq!(unsafe { std::slice::from_raw_parts(counts, size) }, [16]);
+
+        for dump in [dump, top_k] {
+            call!(
+                called,
+                ket_process_get_dump_size(process, dump, &mut available, &mut size)
+            );
+            assert!(available);
+            assert_eq!(size, 1);
+            let (mut state, mut state_size, mut real, mut imag) = (std::ptr::null(), 0, 0.0, 0.0);
+            call!(
+                called,
+                ket_process_get_dump(
+                    process,
+                    dump,
+                    0,
+                    &mut state,
+                    &mut state_size,
+                    &mut real,
+                    &mut imag,
+                )
+            );
+            assert_eq!(
+                unsafe { std::slice::from_raw_parts(state, state_size) },
+                [0]
+            );
+            assert_eq!((real, imag), (1.0, 0.0));
+        }
+
+        let configuration = Box::into_raw(Box::new(Configuration {
+            live_quantum_execution: Some(Box::new(Ground::default())),
+            ..Configuration::new(4)
+        }));
+        let mut debugger = std::ptr::null_mut();
+        call!(
+            called,
+            ket_debugger_new(configuration, process, &mut debugger)
+        );
+        let debugger = unsafe { &mut *debugger };
+        let mut breakpoint = 0;
+        call!(
+            called,
+            ket_debugger_add_instruction_breakpoint(debugger, 1, &mut breakpoint)
+        );
+        call!(
+            called,
+            ket_debugger_add_qubit_breakpoint(debugger, qubits[0], 1, &mut id)
+        );
+        call!(
+            called,
+            ket_debugger_remove_breakpoint(debugger, id, &mut removed)
+        );
+        assert!(removed);
+        call!(called, ket_debugger_resume(debugger, &mut stop, &mut id));
+        assert_eq!((stop, id), (1, breakpoint));
+        call!(called, ket_debugger_step(debugger, &mut stop, &mut id));
+        assert_eq!(stop, 0);
+        let mut position = 0;
+        call!(called, ket_debugger_position(debugger, &mut position));
+        assert_eq!(position, 2);
+        call!(called, ket_debugger_resume(debugger, &mut stop, &mut id));
+        assert_eq!(stop, 2);
+        call!(
+            called,
+            ket_debugger_get_measurement(debugger, measurement, &mut available, &mut result)
+        );
+        assert!(available);
+        call!(
+            called,
+            ket_debugger_set_state_format(debugger, std::ptr::null(), 0, false, false, 0, 1)
+        );
+        call!(
+            called,
+            ket_debugger_debug_state(debugger, buffer.as_mut_ptr(), buffer.len(), &mut write_size)
+        );
+        assert_eq!(&buffer[..write_size], "|000⟩ 1.0+0.0i p=1.0\n".as_bytes());
+        let (mut real, mut imag) = ([0.0; 4], [0.0; 4]);
+        call!(
+            called,
+            ket_debugger_reduced_density_matrix(
+                debugger,
+                qubits.as_ptr(),
+                1,
+                real.as_mut_ptr(),
+                imag.as_mut_ptr(),
+            )
+        );
+        assert_eq!((real, imag), ([1.0, 0.0, 0.0, 0.0], [0.0; 4]));
+        call!(called, ket_debugger_delete(debugger));
+        call!(called, ket_process_delete(process));
+
+        #[cfg(feature = "qasm")]
+        {
+            let mut process = std::ptr::null_mut();
+            call!(
+                called,
+                ket_process_new(Box::into_raw(Box::new(Configuration::new(2))), &mut process)
+            );
+            let process = unsafe { &mut *process };
+            let qasm = std::ffi::CString::new(
+                "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\nh q[0];\ncx q[0], q[1];\n",
+            )
+            .unwrap();
+            call!(called, ket_process_from_qasmv2(process, qasm.as_ptr()));
+            call!(
+                called,
+                ket_process_to_qasmv2(process, buffer.as_mut_ptr(), buffer.len(), &mut write_size)
+            );
+            assert!(std::str::from_utf8(&buffer[..write_size])
+                .unwrap()
+                .contains("cx q[0], q[1];"));
+            call!(called, ket_process_delete(process));
+        }
+
+        let callbacks = ExecutorCallbacks {
+            context: std::ptr::null_mut(),
+            alloc: None,
+            free: None,
+            gate: Some(gate),
+            measure: Some(measure),
+            sample: None,
+            release: None,
+        };
+        let mut configuration = std::ptr::null_mut();
+        call!(
+            called,
+            ket_make_callback_configuration(1, &callbacks, false, &mut configuration)
+        );
+        let mut process = std::ptr::null_mut();
+        call!(called, ket_process_new(configuration, &mut process));
+        let process = unsafe { &mut *process };
+        call!(called, ket_process_allocate_qubit(process, &mut qubits[0]));
+        call!(
+            called,
+            ket_process_apply_gate(process, HADAMARD, 0, 0, 0.0, qubits[0])
+        );
+        call!(
+            called,
+            ket_process_measure(process, qubits.as_ptr(), 1, &mut measurement)
+        );
+        call!(called, ket_process_delete(process));
+
+        // `LIBKET` lists the QASM functions even when they are not built.
+        let mut missing: Vec<_> = LIBKET
+            .iter()
+            .map(|function| function.name)
+            .filter(|name| cfg!(feature = "qasm") || !name.contains("qasm"))
+            .filter(|name| !called.contains(name))
+            .collect();
+        missing.sort_unstable();
+        assert_eq!(missing, Vec::<&str>::new());
+    }
+
+    #[test]
+    fn signature_drift() {
+        let abi = [
+            Signature {
+                name: "ket_free",
+                args: &["&mut Process", "usize"],
+            },
+            Signature {
+                name: "ket_get",
+                args: &["&Process", "usize", "&mut bool", "&mut *const u64"],
+            },
+        ];
+
+        let python = r#"
+API_argtypes = {
+    # 'ket_type_method': ([input_list], [output_list]),
+    "ket_free": ([c_void_p, c_size_t], []),
+    "ket_get": (
+        [c_void_p, c_size_t],
+        [c_bool, POINTER(c_uint64)],
+    ),
+}
+"#;
+        assert!(python_mismatches(python, &abi).is_empty());
+
+        let python = r#"
+API_argtypes = {
+    "ket_free": ([c_void_p, c_void_p], []),
+    "ket_get": ([c_void_p, c_size_t], [c_bool]),
+    "ket_new": ([], [c_void_p]),
+}
+"#;
+        assert_eq!(
+            python_mismatches(python, &abi),
+            [
+                "ket_free: argument 1 is c_void_p in Python and usize in Rust",
+                "ket_get: 3 arguments in Python and 4 in Rust",
+                "ket_new: declared in Python but not exported",
+            ]
+        );
+
+        let header = "\
+/**
+ * Frees a qubit, see ket_free(.
+ */
+int32_t ket_free(Process *process, size_t qubit);
+
+int32_t ket_new(Process **process);
+";
+        assert_eq!(
+            header_mismatches(header, "ket_", &abi),
+            [
+                "ket_get: missing from the header",
+                "ket_new: declared in the header but not listed",
+            ]
+        );
+
+        let source = "\
+#[no_mangle]
+pub unsafe extern \"C\" fn ket_free(process: &mut Process, qubit: usize) -> i32 {
+    0
+}
+
+#[no_mangle]
+pub extern \"C\" fn ket_new(process: &mut *mut Process) -> i32 {
+    0
+}
+";
+        assert_eq!(
+            source_mismatches(&[source], &abi),
+            [
+                "ket_get: listed but not exported",
+                "ket_new: exported but not listed",
+            ]
+        );
+    }
+}
@@ -0,0 +1,361 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! C API for the `Debugger` struct.
+//!
+//! The debugger stops are returned as a code: `0` for a step, `1` for a breakpoint,
+//! whose identifier is also returned, and `2` at the end of the instructions.
+
+use crate::{
+    debugger::{Breakpoint, Debugger, QubitEvent, Stop},
+    error::{KetError, Result},
+    process::Process,
+    state_format::{Notation, StateFormat},
+    Configuration,
+};
+
+use super::error::wrapper;
+
+/// Creates a debugger for the instructions of the `Process` instance.
+///
+/// # Arguments
+///
+/// * `config` -  \[in\] A mutable pointer to a `Configuration` instance with a live executor.
+/// * `process` -  \[in\] A reference to the `Process` instance.
+/// * `debugger` -  \[out\] A mutable pointer to a `Debugger` pointer.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe because it deals with raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_debugger_new(
+    config: *mut Configuration,
+    process: &Process,
+    debugger: &mut *mut Debugger,
+) -> i32 {
+    let config = unsafe { Box::from_raw(config) };
+    match Debugger::new(process.instructions(), *config) {
+        Ok(new) => {
+            *debugger = Box::into_raw(Box::new(new));
+            KetError::Success.error_code()
+        }
+        Err(error) => error.error_code(),
+    }
+}
+
+/// Deletes the `Debugger` instance.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A pointer to the `Debugger` instance to be deleted.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe because it deals with raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_debugger_delete(debugger: *mut Debugger) -> i32 {
+    unsafe {
+        let _ = Box::from_raw(debugger);
+    }
+    KetError::Success.error_code()
+}
+
+/// Adds a breakpoint before the instruction at `index`.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A mutable reference to the `Debugger` instance.
+/// * `index` -  \[in\] The index of the instruction.
+/// * `id` -  \[out\] A mutable pointer to the identifier of the breakpoint.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_debugger_add_instruction_breakpoint(
+    debugger: &mut Debugger,
+    index: usize,
+    id: &mut usize,
+) -> i32 {
+    *id = debugger.add_breakpoint(Breakpoint::Instruction(index));
+    KetError::Success.error_code()
+}
+
+/// Adds a breakpoint on an event of a qubit.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A mutable reference to the `Debugger` instance.
+/// * `qubit` -  \[in\] The index of the qubit.
+/// * `event` -  \[in\] `0` for any instruction, `1` for gates, `2` for measurements, `3` for the allocation, and `4` for the release of the qubit.
+/// * `id` -  \[out\] A mutable pointer to the identifier of the breakpoint.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_debugger_add_qubit_breakpoint(
+    debugger: &mut Debugger,
+    qubit: usize,
+    event: i32,
+    id: &mut usize,
+) -> i32 {
+    let event = match event {
+        0 => QubitEvent::Any,
+        1 => QubitEvent::Gate,
+        2 => QubitEvent::Measure,
+        3 => QubitEvent::Alloc,
+        4 => QubitEvent::Free,
+        _ => return KetError::UndefinedError.error_code(),
+    };
+    *id = debugger.add_breakpoint(Breakpoint::Qubit { qubit, event });
+    KetError::Success.error_code()
+}
+
+/// Removes a breakpoint.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A mutable reference to the `Debugger` instance.
+/// * `id` -  \[in\] The identifier of the breakpoint.
+/// * `removed` -  \[out\] A mutable pointer to a `bool`, `false` if there was no such breakpoint.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_debugger_remove_breakpoint(
+    debugger: &mut Debugger,
+    id: usize,
+    removed: &mut bool,
+) -> i32 {
+    *removed = debugger.remove_breakpoint(id);
+    KetError::Success.error_code()
+}
+
+fn stop_code(stop: Result<Stop>, code: &mut i32, id: &mut usize) -> i32 {
+    *code = match stop {
+        Ok(Stop::Step) => 0,
+        Ok(Stop::Breakpoint(breakpoint)) => {
+            *id = breakpoint;
+            1
+        }
+        Ok(Stop::Finished) => 2,
+        Err(error) => return error.error_code(),
+    };
+    KetError::Success.error_code()
+}
+
+/// Executes the next instruction.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A mutable reference to the `Debugger` instance.
+/// * `stop` -  \[out\] A mutable pointer to the stop code.
+/// * `id` -  \[out\] A mutable pointer to the identifier of the breakpoint, if any.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_debugger_step(
+    debugger: &mut Debugger,
+    stop: &mut i32,
+    id: &mut usize,
+) -> i32 {
+    stop_code(debugger.step(), stop, id)
+}
+
+/// Executes instructions until a breakpoint, or to the end.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A mutable reference to the `Debugger` instance.
+/// * `stop` -  \[out\] A mutable pointer to the stop code.
+/// * `id` -  \[out\] A mutable pointer to the identifier of the breakpoint, if any.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_debugger_resume(
+    debugger: &mut Debugger,
+    stop: &mut i32,
+    id: &mut usize,
+) -> i32 {
+    stop_code(debugger.resume(), stop, id)
+}
+
+/// Gets the index of the next instruction to execute.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A reference to the `Debugger` instance.
+/// * `position` -  \[out\] A mutable pointer to the index.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_debugger_position(debugger: &Debugger, position: &mut usize) -> i32 {
+    *position = debugger.position();
+    KetError::Success.error_code()
+}
+
+/// Gets the result of a measurement executed by the debugger.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A reference to the `Debugger` instance.
+/// * `index` -  \[in\] The output index of the measurement.
+/// * `available` -  \[out\] A mutable pointer to a `bool` indicating if the result is available.
+/// * `result` -  \[out\] A mutable pointer to the result.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_debugger_get_measurement(
+    debugger: &Debugger,
+    index: usize,
+    available: &mut bool,
+    result: &mut u64,
+) -> i32 {
+    match debugger.measurement(index) {
+        Some(value) => {
+            *result = value;
+            *available = true;
+        }
+        None => *available = false,
+    }
+    KetError::Success.error_code()
+}
+
+/// Gets the state of the allocated qubits as text, see `Debugger::debug_state`.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A mutable reference to the `Debugger` instance.
+/// * `buffer` -  \[in/out\] A mutable pointer to a buffer to store the text.
+/// * `buffer_size` -  \[in\] The size of the provided buffer.
+/// * `write_size` -  \[out\] A mutable pointer to the actual size of the written data.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_debugger_debug_state(
+    debugger: &mut Debugger,
+    buffer: *mut u8,
+    buffer_size: usize,
+    write_size: &mut usize,
+) -> i32 {
+    let state = match debugger.debug_state() {
+        Ok(state) => state,
+        Err(error) => return error.error_code(),
+    };
+    let state = state.as_bytes();
+    *write_size = state.len();
+    if buffer_size >= *write_size {
+        let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, buffer_size) };
+        buffer[..*write_size].copy_from_slice(state);
+    }
+
+    KetError::Success.error_code()
+}
+
+/// Sets the format of the state returned by `ket_debugger_debug_state`.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A mutable reference to the `Debugger` instance.
+/// * `qubits` -  \[in\] A pointer to the array of qubits to show, tracing out the others.
+/// * `qubits_size` -  \[in\] The number of qubits to show, or `0` to show all qubits.
+/// * `polar` -  \[in\] Whether the amplitudes are written in polar instead of cartesian form.
+/// * `sort_by_probability` -  \[in\] Whether the basis states are sorted by decreasing probability.
+/// * `max_lines` -  \[in\] The largest number of basis states shown, or `0` for no limit.
+/// * `precision` -  \[in\] The number of decimal places.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_debugger_set_state_format(
+    debugger: &mut Debugger,
+    qubits: *const usize,
+    qubits_size: usize,
+    polar: bool,
+    sort_by_probability: bool,
+    max_lines: usize,
+    precision: usize,
+) -> i32 {
+    let qubits = (qubits_size > 0)
+        .then(|| unsafe { std::slice::from_raw_parts(qubits, qubits_size) }.to_vec());
+    debugger.set_state_format(StateFormat {
+        qubits,
+        notation: if polar {
+            Notation::Polar
+        } else {
+            Notation::Cartesian
+        },
+        sort_by_probability,
+        max_lines: (max_lines > 0).then_some(max_lines),
+        precision,
+    });
+    KetError::Success.error_code()
+}
+
+/// Gets the reduced density matrix of qubits, in row-major order.
+///
+/// # Arguments
+///
+/// * `debugger` -  \[in\] A mutable reference to the `Debugger` instance.
+/// * `qubits` -  \[in\] A pointer to the array of qubit indices.
+/// * `qubits_size` -  \[in\] The number of qubits.
+/// * `real` -  \[out\] A mutable pointer to an array of `4^qubits_size` real parts.
+/// * `imag` -  \[out\] A mutable pointer to an array of `4^qubits_size` imaginary parts.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_debugger_reduced_density_matrix(
+    debugger: &mut Debugger,
+    qubits: *const usize,
+    qubits_size: usize,
+    real: *mut f64,
+    imag: *mut f64,
+) -> i32 {
+    let qubits = unsafe { std::slice::from_raw_parts(qubits, qubits_size) };
+    wrapper(debugger.reduced_density_matrix(qubits).map(|matrix| {
+        let size = matrix.len() * matrix.len();
+        let real = unsafe { std::slice::from_raw_parts_mut(real, size) };
+        let imag = unsafe { std::slice::from_raw_parts_mut(imag, size) };
+        for (index, value) in matrix.iter().flatten().enumerate() {
+            real[index] = value.re;
+            imag[index] = value.im;
+        }
+    }))
+}
@@ -5,7 +5,7 @@
 
 //! Error handler for the C API.
 
-use log::trace;
+use tracing::trace;
 
 use crate::error::{KetError, Result};
 
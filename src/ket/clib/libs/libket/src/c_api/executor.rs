@@ -0,0 +1,347 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! C API for quantum executors implemented with callbacks.
+//!
+//! A foreign runtime, like the control stack of a quantum computer, fills an
+//! [`ExecutorCallbacks`] and creates a live `Configuration` with
+//! [`ket_make_callback_configuration`]. The process created with this configuration
+//! calls the callbacks as each instruction is added, so the circuit is never
+//! serialized.
+//!
+//! Gates use the codes of [`ket_process_apply_gate`](super::process::ket_process_apply_gate):
+//! `0` for H, `1` for X, `2` for Y, `3` for Z, `10` for RX, `20` for RY, `30` for RZ, and
+//! `31` for the phase gate. The angle is given in radians in `scalar` and, if it is a
+//! fraction of π, as `pi_fraction_top * π / pi_fraction_bottom`, otherwise
+//! `pi_fraction_bottom` is `0`. The first qubit of a measurement or sample is the most
+//! significant bit of its result.
+
+use std::{collections::BTreeMap, ffi::c_void};
+
+use tracing::trace;
+
+use crate::{
+    error::KetError,
+    ir::{Angle, DumpData, PauliHamiltonian, QuantumGate},
+    Configuration, LiveExecution,
+};
+
+/// Callbacks of a quantum executor.
+///
+/// Each callback receives `context` as its first argument. The `gate` and `measure`
+/// callbacks are required. Without `sample`, sampling is disabled, and the expected
+/// value and dump instructions are always disabled.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExecutorCallbacks {
+    /// Pointer passed to every callback.
+    pub context: *mut c_void,
+
+    /// Allocates a qubit.
+    pub alloc: Option<unsafe extern "C" fn(context: *mut c_void, qubit: usize)>,
+
+    /// Frees a qubit.
+    pub free: Option<unsafe extern "C" fn(context: *mut c_void, qubit: usize)>,
+
+    /// Applies a gate to the target qubit, controlled by the `control` qubits.
+    pub gate: Option<
+        unsafe extern "C" fn(
+            context: *mut c_void,
+            gate: i32,
+            pi_fraction_top: i32,
+            pi_fraction_bottom: u32,
+            scalar: f64,
+            target: usize,
+            control: *const usize,
+            control_size: usize,
+        ),
+    >,
+
+    /// Measures the qubits and returns the result.
+    pub measure: Option<
+        unsafe extern "C" fn(context: *mut c_void, qubits: *const usize, qubits_size: usize) -> u64,
+    >,
+
+    /// Samples the qubits, writing the result of each of the `shots` in `results`.
+    pub sample: Option<
+        unsafe extern "C" fn(
+            context: *mut c_void,
+            qubits: *const usize,
+            qubits_size: usize,
+            shots: u64,
+            results: *mut u64,
+        ),
+    >,
+
+    /// Called when the process is deleted, to release the context.
+    pub release: Option<unsafe extern "C" fn(context: *mut c_void)>,
+}
+
+/// Live executor that forwards the instructions to the callbacks.
+struct CallbackExecution(ExecutorCallbacks);
+
+/// Returns the gate code and the angle as a fraction of π and in radians.
+fn gate_code(gate: &QuantumGate) -> (i32, i32, u32, f64) {
+    let angle = |code, angle: &Angle| match angle.as_pi_fraction() {
+        Some((top, bottom)) => (code, top, bottom, angle.radians()),
+        None => (code, 0, 0, angle.radians()),
+    };
+
+    match gate {
+        QuantumGate::Hadamard => (0, 0, 0, 0.0),
+        QuantumGate::PauliX => (1, 0, 0, 0.0),
+        QuantumGate::PauliY => (2, 0, 0, 0.0),
+        QuantumGate::PauliZ => (3, 0, 0, 0.0),
+        QuantumGate::RotationX(param) => angle(10, param),
+        QuantumGate::RotationY(param) => angle(20, param),
+        QuantumGate::RotationZ(param) => angle(30, param),
+        QuantumGate::Phase(param) => angle(31, param),
+    }
+}
+
+impl LiveExecution for CallbackExecution {
+    fn alloc(&mut self, target: usize) {
+        if let Some(alloc) = self.0.alloc {
+            unsafe { alloc(self.0.context, target) }
+        }
+    }
+
+    fn free(&mut self, target: usize) {
+        if let Some(free) = self.0.free {
+            unsafe { free(self.0.context, target) }
+        }
+    }
+
+    fn gate(&mut self, gate: &QuantumGate, target: usize, control: &[usize]) {
+        let (code, top, bottom, scalar) = gate_code(gate);
+        let callback = self.0.gate.expect("the gate callback is required");
+        unsafe {
+            callback(
+                self.0.context,
+                code,
+                top,
+                bottom,
+                scalar,
+                target,
+                control.as_ptr(),
+                control.len(),
+            )
+        }
+    }
+
+    fn measure(&mut self, qubits: &[usize]) -> u64 {
+        let callback = self.0.measure.expect("the measure callback is required");
+        unsafe { callback(self.0.context, qubits.as_ptr(), qubits.len()) }
+    }
+
+    fn exp_value(&mut self, _: &PauliHamiltonian) -> f64 {
+        unreachable!("expected values are disabled for callback executors")
+    }
+
+    fn sample(&mut self, qubits: &[usize], shots: u64) -> (Vec<u64>, Vec<u64>) {
+        let callback = self
+            .0
+            .sample
+            .expect("sampling is disabled without a callback");
+        let mut results = vec![0; shots as usize];
+        unsafe {
+            callback(
+                self.0.context,
+                qubits.as_ptr(),
+                qubits.len(),
+                shots,
+                results.as_mut_ptr(),
+            )
+        }
+
+        let mut counts = BTreeMap::new();
+        for result in results {
+            *counts.entry(result).or_insert(0) += 1;
+        }
+        counts.into_iter().unzip()
+    }
+
+    fn dump(&mut self, _: &[usize]) -> DumpData {
+        unreachable!("dumps are disabled for callback executors")
+    }
+}
+
+impl Drop for CallbackExecution {
+    fn drop(&mut self) {
+        if let Some(release) = self.0.release {
+            unsafe { release(self.0.context) }
+        }
+    }
+}
+
+/// Creates a live `Configuration` that executes the process with the callbacks.
+///
+/// # Arguments
+///
+/// * `num_qubits` -  \[in\] The maximum number of qubits.
+/// * `callbacks` -  \[in\] A reference to the callbacks, which are copied.
+/// * `decompose` -  \[in\] Whether the gates are decomposed into X, H, and phase gates.
+/// * `configuration` -  \[out\] A mutable pointer to a `Configuration` pointer, to be
+///   passed to `ket_process_new`.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_make_callback_configuration(
+    num_qubits: usize,
+    callbacks: &ExecutorCallbacks,
+    decompose: bool,
+    configuration: &mut *mut Configuration,
+) -> i32 {
+    if callbacks.gate.is_none() || callbacks.measure.is_none() {
+        return KetError::InvalidCallbacks.error_code();
+    }
+
+    trace!(
+        "ket_make_callback_configuration( num_qubits={}, decompose={} )",
+        num_qubits,
+        decompose
+    );
+
+    *configuration = Box::into_raw(Box::new(Configuration {
+        allow_sample: callbacks.sample.is_some(),
+        allow_exp_value: false,
+        allow_dump: false,
+        decompose,
+        live_quantum_execution: Some(Box::new(CallbackExecution(*callbacks))),
+        optimize: false,
+        ..Configuration::new(num_qubits)
+    }));
+
+    KetError::Success.error_code()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::c_void;
+
+    use super::{ket_make_callback_configuration, ExecutorCallbacks};
+    use crate::{error::KetError, ir::QuantumGate, Angle, PauliHamiltonian, Process};
+
+    /// Calls received by the callbacks.
+    #[derive(Default)]
+    struct Log {
+        calls: Vec<String>,
+        released: bool,
+    }
+
+    unsafe fn log<'a>(context: *mut c_void) -> &'a mut Log {
+        unsafe { &mut *context.cast::<Log>() }
+    }
+
+    unsafe extern "C" fn alloc(context: *mut c_void, qubit: usize) {
+        unsafe { log(context) }.calls.push(format!("alloc {qubit}"));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe extern "C" fn gate(
+        context: *mut c_void,
+        gate: i32,
+        top: i32,
+        bottom: u32,
+        _: f64,
+        target: usize,
+        control: *const usize,
+        control_size: usize,
+    ) {
+        let control = unsafe { std::slice::from_raw_parts(control, control_size) };
+        unsafe { log(context) }
+            .calls
+            .push(format!("gate {gate} {top}/{bottom} {target} {control:?}"));
+    }
+
+    unsafe extern "C" fn measure(context: *mut c_void, qubits: *const usize, size: usize) -> u64 {
+        let qubits = unsafe { std::slice::from_raw_parts(qubits, size) };
+        let log = unsafe { log(context) };
+        log.calls.push(format!("measure {qubits:?}"));
+        0b10
+    }
+
+    unsafe extern "C" fn sample(
+        _: *mut c_void,
+        _: *const usize,
+        _: usize,
+        shots: u64,
+        results: *mut u64,
+    ) {
+        let results = unsafe { std::slice::from_raw_parts_mut(results, shots as usize) };
+        for (shot, result) in results.iter_mut().enumerate() {
+            *result = shot as u64 % 2;
+        }
+    }
+
+    unsafe extern "C" fn release(context: *mut c_void) {
+        unsafe { log(context) }.released = true;
+    }
+
+    #[test]
+    fn drives_callbacks() -> Result<(), KetError> {
+        let mut log = Log::default();
+        let callbacks = ExecutorCallbacks {
+            context: (&mut log as *mut Log).cast(),
+            alloc: Some(alloc),
+            free: None,
+            gate: Some(gate),
+            measure: Some(measure),
+            sample: Some(sample),
+            release: Some(release),
+        };
+
+        let mut configuration = std::ptr::null_mut();
+        assert_eq!(
+            ket_make_callback_configuration(2, &callbacks, false, &mut configuration),
+            KetError::Success.error_code()
+        );
+        let mut process = Process::new(*unsafe { Box::from_raw(configuration) });
+
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(QuantumGate::Phase(Angle::pi_fraction(1, 4)), qubits[0])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.ctrl_pop()?;
+        let measurement = process.measure(&qubits)?;
+        let sample = process.sample(&qubits, 4)?;
+        assert!(matches!(
+            process.exp_values(PauliHamiltonian::default()),
+            Err(KetError::ExpValueNotAllowed)
+        ));
+
+        assert_eq!(process.get_measurement(measurement).result, Some(0b10));
+        assert_eq!(
+            process.get_sample(sample).result,
+            Some((vec![0, 1], vec![2, 2]))
+        );
+        drop(process);
+
+        assert_eq!(
+            log.calls,
+            [
+                "alloc 0",
+                "alloc 1",
+                "gate 31 1/4 0 []",
+                "gate 1 0/0 1 [0]",
+                "measure [0, 1]",
+            ]
+        );
+        assert!(log.released);
+
+        let callbacks = ExecutorCallbacks {
+            measure: None,
+            ..callbacks
+        };
+        assert_eq!(
+            ket_make_callback_configuration(2, &callbacks, false, &mut configuration),
+            KetError::InvalidCallbacks.error_code()
+        );
+
+        Ok(())
+    }
+}
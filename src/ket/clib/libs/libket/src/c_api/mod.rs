@@ -12,6 +12,12 @@
 //! The `ket_error_message` function allows retrieving error messages associated with error codes.
 //! Given an error code, it returns the corresponding error message string.
 //!
+//! ## Header
+//!
+//! The C header is generated by `cbindgen` when Libket is built, and is available as
+//! [`HEADER`]. The [`abi`] module checks that the header and the Python layer agree
+//! with the exported functions.
+//!
 //! # Safety
 //!
 //! Care should be taken when using C functions and data structures.
@@ -21,11 +27,20 @@ use log::LevelFilter;
 
 use crate::error::KetError;
 
+pub mod abi;
+pub mod debugger;
 pub mod error;
+pub mod executor;
 pub mod objects;
 pub mod process;
 
+/// C header of the C API, `ket.h`.
+pub const HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/ket.h"));
+
 /// Sets the log level for Libket.
+///
+/// Installs a `log` logger on stderr, which receives the `tracing` events of Libket when
+/// no `tracing` subscriber is set.
 #[no_mangle]
 pub extern "C" fn ket_set_log_level(level: u32) -> i32 {
     let level = match level {
@@ -5,9 +5,9 @@
 
 //! C API for the objects module.
 
-use log::trace;
+use tracing::trace;
 
-use crate::{error::KetError, Process};
+use crate::{error::KetError, BitOrder, Process};
 
 /// Retrieves the status of a qubit in the `Process` instance.
 ///
@@ -69,6 +69,53 @@ pub extern "C" fn ket_process_get_measurement(
     KetError::Success.error_code()
 }
 
+/// Retrieves the measurement result as an integer with an explicit bit order.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A reference to the `Process` instance.
+/// * `index` -  \[in\] The index of the measurement to query.
+/// * `lsb_first` -  \[in\] If the first measured qubit is the least significant bit.
+/// * `signed` -  \[in\] If the result is read in two's complement.
+/// * `available` -  \[out\] A mutable pointer to a `bool` indicating if the result is available.
+/// * `result` -  \[out\] A mutable pointer to a `i64` storing the measurement result,
+///   to be cast to `u64` if `signed` is false.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_process_get_measurement_as_integer(
+    process: &Process,
+    index: usize,
+    lsb_first: bool,
+    signed: bool,
+    available: &mut bool,
+    result: &mut i64,
+) -> i32 {
+    let order = if lsb_first {
+        BitOrder::LsbFirst
+    } else {
+        BitOrder::MsbFirst
+    };
+    let value = if signed {
+        process.get_measurement_as_i64(index, order)
+    } else {
+        process
+            .get_measurement_as_u64(index, order)
+            .map(|value| value as i64)
+    };
+
+    *available = value.is_some();
+    if let Some(value) = value {
+        *result = value;
+    }
+
+    trace!("{:?}", value);
+
+    KetError::Success.error_code()
+}
+
 /// Retrieves the expected value from the `Process` instance.
 ///
 /// # Arguments
@@ -154,8 +201,7 @@ pub extern "C" fn ket_process_get_dump_size(
     available: &mut bool,
     size: &mut usize,
 ) -> i32 {
-    let dump = process.get_dump(index);
-    if let Some(dump) = dump.result.as_ref() {
+    if let Some(dump) = process.get_dump_data(index) {
         *size = dump.basis_states.len();
         *available = true;
     } else {
@@ -167,9 +213,12 @@ pub extern "C" fn ket_process_get_dump_size(
 
 /// Retrieves the dump data from the `Process` instance.
 ///
+/// A compressed dump is decompressed on the first call, see
+/// [`Process::decompress_dump`].
+///
 /// # Arguments
 ///
-/// * `process` -  \[in\] A reference to the `Process` instance.
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
 /// * `index` -  \[in\] The index of the dump to query.
 /// * `iterator` -  \[in\] The iterator for accessing individual basis states in the dump.
 /// * `basis_state` -  \[out\] A mutable pointer to the array of `u64` storing the basis state.
@@ -179,14 +228,15 @@ pub extern "C" fn ket_process_get_dump_size(
 ///
 /// # Returns
 ///
-/// An integer representing the error code. `0` indicates success.
+/// An integer representing the error code. `0` indicates success, and
+/// [`KetError::DataNotAvailable`] that the dump has no result yet.
 ///
 /// # Safety
 ///
 /// This function is marked as unsafe due to the use of raw pointers.
 #[no_mangle]
 pub unsafe extern "C" fn ket_process_get_dump(
-    process: &Process,
+    process: &mut Process,
     index: usize,
     iterator: usize,
     basis_state: &mut *const u64,
@@ -194,7 +244,9 @@ pub unsafe extern "C" fn ket_process_get_dump(
     amplitude_real: &mut f64,
     amplitude_imag: &mut f64,
 ) -> i32 {
-    let dump = process.get_dump(index).result.as_ref().unwrap();
+    let Some(dump) = process.decompress_dump(index) else {
+        return KetError::DataNotAvailable.error_code();
+    };
     let state = dump.basis_states[iterator].as_ptr();
     let size = dump.basis_states[iterator].len();
     *basis_state = state;
@@ -5,13 +5,18 @@
 
 //! C API for the `Process` struct.
 
-use log::trace;
+use tracing::trace;
 
+#[cfg(feature = "qasm")]
+use crate::qasmv2::instruction_set::InstructionSet::QELIB;
+use crate::trace::Trace;
 use crate::{
-    error::KetError, process::Process, Angle, Configuration, Pauli, PauliHamiltonian, PauliProduct,
-    PauliTerm, QuantumGate,
+    error::KetError,
+    ir::KrausOperator,
+    process::{Checkpoint, Process},
+    provenance::SourceLocation,
+    Angle, Configuration, Pauli, PauliHamiltonian, PauliProduct, PauliTerm, QuantumGate,
 };
-use crate::qasmv2::instruction_set::InstructionSet::QELIB;
 
 use super::error::wrapper;
 
@@ -96,7 +101,6 @@ pub extern "C" fn ket_process_free_qubit(process: &mut Process, qubit: usize) ->
     wrapper(process.free_qubit(qubit))
 }
 
-
 #[no_mangle]
 pub extern "C" fn ket_process_optimize(process: &mut Process) -> i32 {
     wrapper(process.optimize())
@@ -109,7 +113,7 @@ pub extern "C" fn ket_process_optimize(process: &mut Process) -> i32 {
 /// * `process` -  \[in\] A mutable reference to the `Process` instance.
 /// * `gate` -  \[in\] An integer representing the gate type. See the function body for the mapping of gate values to gate types.
 /// * `pi_fraction_top` -  \[in\] The numerator of the fraction part of the angle, used by certain gate types.
-/// * `pi_fraction_bottom` -  \[in\] The denominator of the fraction part of the angle, used by certain gate types.
+/// * `pi_fraction_bottom` -  \[in\] The denominator of the fraction part of the angle, used by certain gate types, or `0` to use `scalar`.
 /// * `scalar` -  \[in\] A floating-point parameter value used by certain gate types.
 /// * `target` -  \[in\] A reference to the target `Qubit` instance.
 ///
@@ -125,13 +129,10 @@ pub extern "C" fn ket_process_apply_gate(
     scalar: f64,
     target: usize,
 ) -> i32 {
-    let param = if pi_fraction_top == 0 && pi_fraction_bottom == 0 {
+    let param = if pi_fraction_bottom == 0 {
         Angle::Scalar(scalar)
     } else {
-        Angle::PiFraction {
-            top: pi_fraction_top,
-            bottom: pi_fraction_bottom,
-        }
+        Angle::pi_fraction(pi_fraction_top, pi_fraction_bottom)
     };
     let gate = match gate {
         1 => QuantumGate::PauliX,
@@ -160,7 +161,7 @@ pub extern "C" fn ket_process_apply_gate(
 ///
 /// * `process` -  \[in\] A mutable reference to the `Process` instance.
 /// * `pi_fraction_top` -  \[in\] The numerator of the fraction part of the angle.
-/// * `pi_fraction_bottom` -  \[in\] The denominator of the fraction part of the angle.
+/// * `pi_fraction_bottom` -  \[in\] The denominator of the fraction part of the angle, or `0` to use `scalar`.
 /// * `scalar` -  \[in\] A floating-point parameter value.
 ///
 /// # Returns
@@ -173,13 +174,10 @@ pub extern "C" fn ket_process_apply_global_phase(
     pi_fraction_bottom: u32,
     scalar: f64,
 ) -> i32 {
-    let param = if pi_fraction_top == 0 && pi_fraction_bottom == 0 {
+    let param = if pi_fraction_bottom == 0 {
         Angle::Scalar(scalar)
     } else {
-        Angle::PiFraction {
-            top: pi_fraction_top,
-            bottom: pi_fraction_bottom,
-        }
+        Angle::pi_fraction(pi_fraction_top, pi_fraction_bottom)
     };
 
     trace!("ket_process_apply_global_phase( param={:?})", param);
@@ -227,6 +225,159 @@ pub unsafe extern "C" fn ket_process_measure(
     }
 }
 
+/// Forces the outcome of a measurement in the `Process` instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `index` -  \[in\] The index of the measurement, as returned by `ket_process_measure`.
+/// * `outcome` -  \[in\] The forced measurement outcome.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_process_force_measurement(
+    process: &mut Process,
+    index: usize,
+    outcome: u64,
+) -> i32 {
+    trace!(
+        "ket_process_force_measurement( index={}, outcome={} )",
+        index,
+        outcome
+    );
+
+    wrapper(process.force_measurement(index, outcome))
+}
+
+/// Applies a single-qubit Kraus channel in the `Process` instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `real` -  \[in\] A pointer to the real parts of the operators, 4 entries per operator in row-major order.
+/// * `imag` -  \[in\] A pointer to the imaginary parts of the operators, in the same layout as `real`.
+/// * `num_operators` -  \[in\] The number of Kraus operators.
+/// * `target` -  \[in\] The index of the target qubit.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_apply_kraus_channel(
+    process: &mut Process,
+    real: *const f64,
+    imag: *const f64,
+    num_operators: usize,
+    target: usize,
+) -> i32 {
+    let real = unsafe { std::slice::from_raw_parts(real, 4 * num_operators) };
+    let imag = unsafe { std::slice::from_raw_parts(imag, 4 * num_operators) };
+    let operators: Vec<KrausOperator> = real
+        .chunks_exact(4)
+        .zip(imag.chunks_exact(4))
+        .map(|(real, imag)| KrausOperator {
+            real: [[real[0], real[1]], [real[2], real[3]]],
+            imag: [[imag[0], imag[1]], [imag[2], imag[3]]],
+        })
+        .collect();
+
+    trace!(
+        "ket_process_apply_kraus_channel( operators={:?}, target={} )",
+        operators,
+        target
+    );
+
+    wrapper(process.apply_kraus_channel(&operators, target))
+}
+
+/// Applies a diagonal gate in the `Process` instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `phases` -  \[in\] A pointer to the phase of each basis state, `2^num_qubits` entries.
+/// * `qubits` -  \[in\] A pointer to the qubits, the first one being the most significant bit.
+/// * `num_qubits` -  \[in\] The number of qubits.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_apply_diagonal(
+    process: &mut Process,
+    phases: *const f64,
+    qubits: *const usize,
+    num_qubits: usize,
+) -> i32 {
+    let phases = unsafe { std::slice::from_raw_parts(phases, 1 << num_qubits) };
+    let qubits = unsafe { std::slice::from_raw_parts(qubits, num_qubits) };
+
+    trace!(
+        "ket_process_apply_diagonal( phases={:?}, qubits={:?} )",
+        phases,
+        qubits
+    );
+
+    wrapper(process.apply_diagonal(phases, qubits))
+}
+
+/// Applies a multiplexed rotation in the `Process` instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `axis` -  \[in\] The rotation axis, 1 for X, 2 for Y, and 3 for Z.
+/// * `angles` -  \[in\] A pointer to the angle of each value of the controls, `2^num_controls` entries.
+/// * `control` -  \[in\] A pointer to the control qubits, the first one being the most significant bit.
+/// * `num_controls` -  \[in\] The number of control qubits.
+/// * `target` -  \[in\] The index of the target qubit.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_apply_multiplexor(
+    process: &mut Process,
+    axis: i32,
+    angles: *const f64,
+    control: *const usize,
+    num_controls: usize,
+    target: usize,
+) -> i32 {
+    let angles = unsafe { std::slice::from_raw_parts(angles, 1 << num_controls) };
+    let control = unsafe { std::slice::from_raw_parts(control, num_controls) };
+    let axis = match axis {
+        1 => Pauli::PauliX,
+        2 => Pauli::PauliY,
+        3 => Pauli::PauliZ,
+        _ => panic!("Undefined Pauli index. Use 1 for X, 2 for Y, and 3 for Z"),
+    };
+
+    trace!(
+        "ket_process_apply_multiplexor( axis={:?}, angles={:?}, control={:?}, target={} )",
+        axis,
+        angles,
+        control,
+        target
+    );
+
+    wrapper(process.apply_multiplexor(axis, angles, control, target))
+}
+
 /// Creates a new `PauliHamiltonian` instance.
 ///
 /// # Arguments
@@ -243,6 +394,82 @@ pub extern "C" fn ket_hamiltonian_new(hamiltonian: &mut *mut PauliHamiltonian) -
     KetError::Success.error_code()
 }
 
+/// Creates a new `PauliHamiltonian` instance from an OpenFermion `QubitOperator` string.
+///
+/// # Arguments
+///
+/// * `operator` -  \[in\] A pointer to the UTF-8 encoded operator string.
+/// * `operator_size` -  \[in\] The size of the `operator` buffer.
+/// * `hamiltonian` -  \[out\] A mutable pointer to a `PauliHamiltonian` pointer.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_hamiltonian_from_openfermion(
+    operator: *const u8,
+    operator_size: usize,
+    hamiltonian: &mut *mut PauliHamiltonian,
+) -> i32 {
+    let operator = unsafe { std::slice::from_raw_parts(operator, operator_size) };
+    let operator = match std::str::from_utf8(operator) {
+        Ok(operator) => operator,
+        Err(_) => return KetError::InvalidHamiltonian.error_code(),
+    };
+
+    trace!("ket_hamiltonian_from_openfermion( operator={} )", operator);
+
+    match PauliHamiltonian::from_openfermion(operator) {
+        Ok(result) => {
+            *hamiltonian = Box::into_raw(Box::new(result));
+            KetError::Success.error_code()
+        }
+        Err(error) => error.error_code(),
+    }
+}
+
+/// Creates a new `PauliHamiltonian` instance from its JSON representation.
+///
+/// # Arguments
+///
+/// * `json` -  \[in\] A pointer to the UTF-8 encoded JSON string.
+/// * `json_size` -  \[in\] The size of the `json` buffer.
+/// * `hamiltonian` -  \[out\] A mutable pointer to a `PauliHamiltonian` pointer.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_hamiltonian_from_json(
+    json: *const u8,
+    json_size: usize,
+    hamiltonian: &mut *mut PauliHamiltonian,
+) -> i32 {
+    let json = unsafe { std::slice::from_raw_parts(json, json_size) };
+    let json = match std::str::from_utf8(json) {
+        Ok(json) => json,
+        Err(_) => return KetError::InvalidHamiltonian.error_code(),
+    };
+
+    trace!("ket_hamiltonian_from_json( json={} )", json);
+
+    match PauliHamiltonian::from_json(json) {
+        Ok(result) => {
+            *hamiltonian = Box::into_raw(Box::new(result));
+            KetError::Success.error_code()
+        }
+        Err(error) => error.error_code(),
+    }
+}
+
 /// Adds a term to the `PauliHamiltonian`.
 ///
 /// # Arguments
@@ -417,6 +644,51 @@ pub unsafe extern "C" fn ket_process_dump(
     }
 }
 
+/// Dumps the `k` basis states of largest probability of the specified qubits in the
+/// `Process` instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `qubits` -  \[in\] A pointer to an array of qubit indices to be dumped.
+/// * `qubits_size` -  \[in\] The size of the `qubits` array.
+/// * `k` -  \[in\] The number of basis states to keep.
+/// * `result` -  \[out\] A mutable pointer to a `usize` representing the result index of the dump.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_dump_top_k(
+    process: &mut Process,
+    qubits: *const usize,
+    qubits_size: usize,
+    k: usize,
+    result: &mut usize,
+) -> i32 {
+    let qubits = unsafe { std::slice::from_raw_parts(qubits, qubits_size) };
+
+    match process.dump_top_k(qubits, k) {
+        Ok(result_id) => {
+            *result = result_id;
+
+            trace!(
+                "ket_process_dump_top_k( qubits={:?}, k={}, result={:?} )",
+                qubits,
+                k,
+                result
+            );
+
+            KetError::Success.error_code()
+        }
+        Err(error) => error.error_code(),
+    }
+}
+
 /// Pushes control qubits onto the control stack in the `Process` instance.
 ///
 /// # Arguments
@@ -493,6 +765,38 @@ pub extern "C" fn ket_process_adj_end(process: &mut Process) -> i32 {
     wrapper(process.adj_end())
 }
 
+/// Begins an opaque block in the `Process` instance, see [`Process::opaque_begin`].
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_process_opaque_begin(process: &mut Process) -> i32 {
+    trace!("ket_process_opaque_begin()");
+
+    wrapper(process.opaque_begin())
+}
+
+/// Ends an opaque block in the `Process` instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_process_opaque_end(process: &mut Process) -> i32 {
+    trace!("ket_process_opaque_end()");
+
+    wrapper(process.opaque_end())
+}
+
 /// Prepares the `Process` instance for execution.
 ///
 /// # Arguments
@@ -543,25 +847,108 @@ pub unsafe extern "C" fn ket_process_instructions_json(
     KetError::Success.error_code()
 }
 
-fn string_transmitter(bytes:  &[u8],
-                      buffer: *mut u8,
-                      buffer_size: usize,
-                      write_size: &mut usize) {
-    *write_size = bytes.len();
+/// Gets the JSON representation of the hierarchical view of the `Process` instance,
+/// see [`Process::structure`].
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `buffer` -  \[in/out\] A mutable pointer to a buffer to store the JSON representation.
+/// * `buffer_size` -  \[in\] The size of the provided buffer.
+/// * `write_size` -  \[out\] A mutable pointer to the actual size of the written data.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_structure_json(
+    process: &mut Process,
+    buffer: *mut u8,
+    buffer_size: usize,
+    write_size: &mut usize,
+) -> i32 {
+    let structure = process.structure_json();
+    let structure = structure.as_bytes();
+    *write_size = structure.len();
     if buffer_size >= *write_size {
         let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, buffer_size) };
-        buffer[..*write_size].copy_from_slice(bytes);
+        buffer[..*write_size].copy_from_slice(structure);
     }
+
+    KetError::Success.error_code()
 }
+
+/// Gets the OpenQASM v2 representation of the `Process` instance, without
+/// measurements, using the gates of `qelib1.inc`.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `buffer` -  \[in/out\] A mutable pointer to a buffer to store the OpenQASM code.
+/// * `buffer_size` -  \[in\] The size of the provided buffer.
+/// * `write_size` -  \[out\] A mutable pointer to the actual size of the written data.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[cfg(feature = "qasm")]
 #[no_mangle]
+pub unsafe extern "C" fn ket_process_to_qasmv2(
+    process: &mut Process,
+    buffer: *mut u8,
+    buffer_size: usize,
+    write_size: &mut usize,
+) -> i32 {
+    let qasm = match process.to_qasmv2(false, QELIB) {
+        Ok(qasm) => qasm,
+        Err(error) => return error.error_code(),
+    };
+    let qasm = qasm.as_bytes();
+    *write_size = qasm.len();
+    if buffer_size >= *write_size {
+        let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, buffer_size) };
+        buffer[..*write_size].copy_from_slice(qasm);
+    }
 
-pub fn ket_process_to_qasmv2(    process: &mut Process,
-                                 buffer: *mut u8,
-                                 buffer_size: usize,
-                                 write_size: &mut usize) -> i32 {
-    string_transmitter(process.to_qasmv2(false, QELIB).unwrap().as_bytes(), buffer, buffer_size, write_size);
     KetError::Success.error_code()
+}
+
+/// Appends the instructions of an OpenQASM v2 circuit, using the gates of
+/// `qelib1.inc`, to the `Process` instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `qasm` -  \[in\] A null-terminated string with the OpenQASM code.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[cfg(feature = "qasm")]
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_from_qasmv2(
+    process: &mut Process,
+    qasm: *const std::ffi::c_char,
+) -> i32 {
+    let Ok(qasm) = unsafe { std::ffi::CStr::from_ptr(qasm) }.to_str() else {
+        return KetError::InvalidQASM.error_code();
+    };
+
+    trace!("ket_process_from_qasmv2( qasm={} )", qasm);
 
+    wrapper(process.from_qasmv2(qasm, QELIB, false))
 }
 
 /// Gets the JSON representation of the metadata in the `Process` instance.
@@ -598,3 +985,341 @@ pub unsafe extern "C" fn ket_process_metadata_json(
 
     KetError::Success.error_code()
 }
+
+/// Attaches custom data to the metadata of the `Process` instance, see
+/// [`Process::set_metadata_extension`].
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `key` -  \[in\] A pointer to the UTF-8 encoded key.
+/// * `key_size` -  \[in\] The size of the `key` buffer.
+/// * `value` -  \[in\] A pointer to the UTF-8 encoded JSON value.
+/// * `value_size` -  \[in\] The size of the `value` buffer.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_set_metadata_extension(
+    process: &mut Process,
+    key: *const u8,
+    key_size: usize,
+    value: *const u8,
+    value_size: usize,
+) -> i32 {
+    let key = unsafe { std::slice::from_raw_parts(key, key_size) };
+    let value = unsafe { std::slice::from_raw_parts(value, value_size) };
+    let (Ok(key), Ok(value)) = (std::str::from_utf8(key), serde_json::from_slice(value)) else {
+        return KetError::InvalidMetadataExtension.error_code();
+    };
+
+    trace!("ket_process_set_metadata_extension( key={} )", key);
+
+    process.set_metadata_extension(key, value);
+    KetError::Success.error_code()
+}
+
+/// Gets the JSON representation of the custom data attached to the metadata of the
+/// `Process` instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A reference to the `Process` instance.
+/// * `key` -  \[in\] A pointer to the UTF-8 encoded key.
+/// * `key_size` -  \[in\] The size of the `key` buffer.
+/// * `buffer` -  \[in/out\] A mutable pointer to a buffer to store the JSON representation.
+/// * `buffer_size` -  \[in\] The size of the provided buffer.
+/// * `write_size` -  \[out\] A mutable pointer to the actual size of the written data.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_get_metadata_extension(
+    process: &Process,
+    key: *const u8,
+    key_size: usize,
+    buffer: *mut u8,
+    buffer_size: usize,
+    write_size: &mut usize,
+) -> i32 {
+    let key = unsafe { std::slice::from_raw_parts(key, key_size) };
+    let Some(value) = std::str::from_utf8(key)
+        .ok()
+        .and_then(|key| process.get_metadata_extension(key))
+    else {
+        return KetError::InvalidMetadataExtension.error_code();
+    };
+
+    let value = value.to_string();
+    let value = value.as_bytes();
+    *write_size = value.len();
+
+    if buffer_size >= *write_size {
+        let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, buffer_size) };
+        buffer[..*write_size].copy_from_slice(value);
+    }
+
+    KetError::Success.error_code()
+}
+
+/// Sets the source location of the instructions added afterwards to the `Process`
+/// instance, see [`Process::set_source_location`].
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `file` -  \[in\] A pointer to the UTF-8 encoded path of the source file.
+/// * `file_size` -  \[in\] The size of the `file` buffer.
+/// * `line` -  \[in\] The line in the source file.
+/// * `function` -  \[in\] A pointer to the UTF-8 encoded name of the function.
+/// * `function_size` -  \[in\] The size of the `function` buffer.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_set_source_location(
+    process: &mut Process,
+    file: *const u8,
+    file_size: usize,
+    line: u32,
+    function: *const u8,
+    function_size: usize,
+) -> i32 {
+    let file = unsafe { std::slice::from_raw_parts(file, file_size) };
+    let function = unsafe { std::slice::from_raw_parts(function, function_size) };
+    let (Ok(file), Ok(function)) = (std::str::from_utf8(file), std::str::from_utf8(function))
+    else {
+        return KetError::InvalidSourceLocation.error_code();
+    };
+
+    trace!(
+        "ket_process_set_source_location( file={}, line={}, function={} )",
+        file,
+        line,
+        function
+    );
+
+    process.set_source_location(Some(SourceLocation::new(file, line, function)));
+    KetError::Success.error_code()
+}
+
+/// Clears the source location of the instructions added afterwards to the `Process`
+/// instance.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_process_clear_source_location(process: &mut Process) -> i32 {
+    trace!("ket_process_clear_source_location()");
+
+    process.set_source_location(None);
+    KetError::Success.error_code()
+}
+
+/// Gets the JSON representation of the source location of each instruction in the
+/// `Process` instance, `null` for the instructions without location, see
+/// [`provenance`](crate::provenance).
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A reference to the `Process` instance.
+/// * `buffer` -  \[in/out\] A mutable pointer to a buffer to store the JSON representation.
+/// * `buffer_size` -  \[in\] The size of the provided buffer.
+/// * `write_size` -  \[out\] A mutable pointer to the actual size of the written data.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_provenance_json(
+    process: &Process,
+    buffer: *mut u8,
+    buffer_size: usize,
+    write_size: &mut usize,
+) -> i32 {
+    let provenance = process.provenance_json();
+    let provenance = provenance.as_bytes();
+    *write_size = provenance.len();
+
+    if buffer_size >= *write_size {
+        let buffer = unsafe { std::slice::from_raw_parts_mut(buffer, buffer_size) };
+        buffer[..*write_size].copy_from_slice(provenance);
+    }
+
+    KetError::Success.error_code()
+}
+
+/// Saves the state of the `Process` instance, see [`Process::checkpoint`].
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `checkpoint` -  \[out\] A mutable pointer to a `Checkpoint` pointer.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_process_checkpoint(
+    process: &mut Process,
+    checkpoint: &mut *mut Checkpoint,
+) -> i32 {
+    trace!("ket_process_checkpoint()");
+
+    match process.checkpoint() {
+        Ok(result) => {
+            *checkpoint = Box::into_raw(Box::new(result));
+            KetError::Success.error_code()
+        }
+        Err(error) => error.error_code(),
+    }
+}
+
+/// Goes back to the state saved in the `Checkpoint` instance, see [`Process::rollback`].
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `checkpoint` -  \[in\] A reference to the `Checkpoint` instance.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_process_rollback(process: &mut Process, checkpoint: &Checkpoint) -> i32 {
+    trace!("ket_process_rollback()");
+
+    wrapper(process.rollback(checkpoint))
+}
+
+/// Deletes the `Checkpoint` instance.
+///
+/// # Arguments
+///
+/// * `checkpoint` -  \[in\] A pointer to the `Checkpoint` instance to be deleted.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe because it deals with raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_checkpoint_delete(checkpoint: *mut Checkpoint) -> i32 {
+    unsafe {
+        let _ = Box::from_raw(checkpoint);
+    }
+    KetError::Success.error_code()
+}
+
+/// Starts recording the calls made on the `Process` instance, see [`Process::start_trace`].
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+#[no_mangle]
+pub extern "C" fn ket_process_start_trace(process: &mut Process) -> i32 {
+    trace!("ket_process_start_trace()");
+
+    process.start_trace();
+    KetError::Success.error_code()
+}
+
+/// Saves the calls recorded on the `Process` instance to a trace file.
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A reference to the `Process` instance.
+/// * `path` -  \[in\] A pointer to the UTF-8 encoded path of the trace file.
+/// * `path_size` -  \[in\] The size of the `path` buffer.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_save_trace(
+    process: &Process,
+    path: *const u8,
+    path_size: usize,
+) -> i32 {
+    let path = unsafe { std::slice::from_raw_parts(path, path_size) };
+    let Ok(path) = std::str::from_utf8(path) else {
+        return KetError::TraceFailed.error_code();
+    };
+
+    trace!("ket_process_save_trace( path={} )", path);
+
+    match process.trace().map(|trace| trace.save(path)) {
+        Some(Ok(())) => KetError::Success.error_code(),
+        _ => KetError::TraceFailed.error_code(),
+    }
+}
+
+/// Makes the calls recorded in a trace file on the `Process` instance, see
+/// [`Trace::replay`].
+///
+/// # Arguments
+///
+/// * `process` -  \[in\] A mutable reference to the `Process` instance.
+/// * `path` -  \[in\] A pointer to the UTF-8 encoded path of the trace file.
+/// * `path_size` -  \[in\] The size of the `path` buffer.
+///
+/// # Returns
+///
+/// An integer representing the error code. `0` indicates success. Otherwise, it is the
+/// error of the failed call.
+///
+/// # Safety
+///
+/// This function is marked as unsafe due to the use of raw pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ket_process_replay_trace(
+    process: &mut Process,
+    path: *const u8,
+    path_size: usize,
+) -> i32 {
+    let path = unsafe { std::slice::from_raw_parts(path, path_size) };
+    let Ok(path) = std::str::from_utf8(path) else {
+        return KetError::TraceFailed.error_code();
+    };
+
+    trace!("ket_process_replay_trace( path={} )", path);
+
+    match Trace::load(path) {
+        Ok(trace) => wrapper(trace.replay(process)),
+        Err(_) => KetError::TraceFailed.error_code(),
+    }
+}
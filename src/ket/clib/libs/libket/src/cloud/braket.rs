@@ -0,0 +1,161 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Amazon Braket OpenQASM programs.
+
+use serde_json::json;
+
+use crate::{
+    error::{KetError, Result},
+    ir::{Instruction, QuantumGate},
+    multiplexor,
+    qasmv2::formatter::QasmFormat,
+    Process,
+};
+
+use super::num_qubits;
+
+/// Returns the gate statement with the Braket gate names.
+///
+/// Gates without a Braket name for their number of control qubits use the `ctrl`
+/// modifier, which is only supported by the Braket simulators.
+fn gate_statement(gate: &QuantumGate, target: usize, control: &[usize]) -> String {
+    let format = QasmFormat::canonical();
+    let with_angle = |name: &str, angle| format!("{}({})", name, format.angle(angle));
+    let (name, modifier) = match (gate, control.len()) {
+        (QuantumGate::PauliX, 1) => ("cnot".to_string(), false),
+        (QuantumGate::PauliX, 2) => ("ccnot".to_string(), false),
+        (QuantumGate::PauliY, 1) => ("cy".to_string(), false),
+        (QuantumGate::PauliZ, 1) => ("cz".to_string(), false),
+        (QuantumGate::Phase(angle), 1) => (with_angle("cphaseshift", angle), false),
+        (gate, n) => {
+            let name = match gate {
+                QuantumGate::PauliX => "x".to_string(),
+                QuantumGate::PauliY => "y".to_string(),
+                QuantumGate::PauliZ => "z".to_string(),
+                QuantumGate::Hadamard => "h".to_string(),
+                QuantumGate::RotationX(angle) => with_angle("rx", angle),
+                QuantumGate::RotationY(angle) => with_angle("ry", angle),
+                QuantumGate::RotationZ(angle) => with_angle("rz", angle),
+                QuantumGate::Phase(angle) => with_angle("phaseshift", angle),
+            };
+            (name, n > 0)
+        }
+    };
+
+    let modifier = match (modifier, control.len()) {
+        (false, _) => String::new(),
+        (true, 1) => "ctrl @ ".to_string(),
+        (true, n) => format!("ctrl({}) @ ", n),
+    };
+    let operands: Vec<String> = control
+        .iter()
+        .chain([&target])
+        .map(|qubit| format!("q[{}]", qubit))
+        .collect();
+    format!("{}{} {};\n", modifier, name, operands.join(", "))
+}
+
+/// Returns the circuit of the process in the OpenQASM 3.0 flavor of Amazon Braket.
+///
+/// # Errors
+///
+/// Returns [`KetError::UnsuportedGateExport`] if there is an instruction other than a
+/// gate or a measurement.
+pub fn to_braket_qasm(process: &Process) -> Result<String> {
+    let mut body = String::new();
+    let mut bits = 0;
+
+    // Diagonal gates and multiplexed rotations are exported as CNOTs and rotations.
+    for instruction in multiplexor::lower(&process.instructions).iter() {
+        match instruction {
+            Instruction::Alloc { .. } | Instruction::Free { .. } => {}
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } => body += &gate_statement(gate, *target, control),
+            Instruction::Measure { qubits, .. } => {
+                for qubit in qubits {
+                    body += &format!("b[{}] = measure q[{}];\n", bits, qubit);
+                    bits += 1;
+                }
+            }
+            _ => return Err(KetError::UnsuportedGateExport),
+        }
+    }
+
+    let mut header = format!(
+        "OPENQASM 3.0;\nqubit[{}] q;\n",
+        num_qubits(&process.instructions)
+    );
+    if bits > 0 {
+        header += &format!("bit[{}] b;\n", bits);
+    }
+    Ok(header + &body)
+}
+
+/// Returns a Braket OpenQASM program, the action of a Braket quantum task, with the
+/// circuit of [`to_braket_qasm`].
+///
+/// # Errors
+///
+/// Returns the errors of [`to_braket_qasm`].
+pub fn to_braket(process: &Process) -> Result<String> {
+    Ok(json!({
+        "braketSchemaHeader": {
+            "name": "braket.ir.openqasm.program",
+            "version": "1",
+        },
+        "source": to_braket_qasm(process)?,
+    })
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_braket, to_braket_qasm};
+    use crate::{error::KetError, Angle, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn braket_gate_names() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        let qubits: Vec<usize> = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        process.apply_gate(QuantumGate::Phase(Angle::pi_fraction(1, 4)), qubits[0])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.apply_gate(QuantumGate::RotationX(Angle::Scalar(0.5)), qubits[1])?;
+        process.ctrl_push(&[qubits[1]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[2])?;
+        process.apply_gate(QuantumGate::Hadamard, qubits[2])?;
+        process.ctrl_pop()?;
+        process.ctrl_pop()?;
+        process.measure(&qubits[1..])?;
+
+        assert_eq!(
+            to_braket_qasm(&process)?,
+            "OPENQASM 3.0;\n\
+             qubit[3] q;\n\
+             bit[2] b;\n\
+             phaseshift(pi/4) q[0];\n\
+             cnot q[0], q[1];\n\
+             ctrl @ rx(0.5) q[0], q[1];\n\
+             ccnot q[0], q[1], q[2];\n\
+             ctrl(2) @ h q[0], q[1], q[2];\n\
+             b[0] = measure q[1];\n\
+             b[1] = measure q[2];\n"
+        );
+
+        let program: serde_json::Value = serde_json::from_str(&to_braket(&process)?).unwrap();
+        assert_eq!(
+            program["braketSchemaHeader"]["name"],
+            "braket.ir.openqasm.program"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! IBM Runtime sampler jobs.
+
+use serde_json::json;
+
+use crate::{
+    error::{KetError, Result},
+    ir::{Angle, Instruction, QuantumGate},
+    multiplexor,
+    qasmv2::formatter::QasmFormat,
+    Process,
+};
+
+/// Native operation of IBM devices.
+#[derive(Debug, Clone)]
+enum Native {
+    Rz(Angle, usize),
+    Sx(usize),
+    X(usize),
+    Cz(usize, usize),
+}
+
+/// Appends the gate without control qubits, up to a global phase.
+fn single(gate: &QuantumGate, qubit: usize, output: &mut Vec<Native>) {
+    for gate in gate.decompose_minimal() {
+        match gate {
+            QuantumGate::PauliX => output.push(Native::X(qubit)),
+            // H = RZ(π/2) SX RZ(π/2), up to a global phase
+            QuantumGate::Hadamard => output.extend([
+                Native::Rz(Angle::pi_fraction(1, 2), qubit),
+                Native::Sx(qubit),
+                Native::Rz(Angle::pi_fraction(1, 2), qubit),
+            ]),
+            QuantumGate::Phase(angle) => output.push(Native::Rz(angle, qubit)),
+            _ => unreachable!("not a minimal gate"),
+        }
+    }
+}
+
+fn cnot(control: usize, target: usize, output: &mut Vec<Native>) {
+    single(&QuantumGate::Hadamard, target, output);
+    output.push(Native::Cz(control, target));
+    single(&QuantumGate::Hadamard, target, output);
+}
+
+/// Appends the gate with one control qubit, including its phase.
+fn controlled(gate: &QuantumGate, control: usize, target: usize, output: &mut Vec<Native>) {
+    for gate in gate.decompose_minimal() {
+        match gate {
+            QuantumGate::PauliX => cnot(control, target, output),
+            // H = RY(π/4) Z RY(-π/4)
+            QuantumGate::Hadamard => {
+                single(
+                    &QuantumGate::RotationY(Angle::pi_fraction(-1, 4)),
+                    target,
+                    output,
+                );
+                output.push(Native::Cz(control, target));
+                single(
+                    &QuantumGate::RotationY(Angle::pi_fraction(1, 4)),
+                    target,
+                    output,
+                );
+            }
+            QuantumGate::Phase(angle) => {
+                let half = angle.half();
+                single(&QuantumGate::Phase(half.clone()), control, output);
+                cnot(control, target, output);
+                single(&QuantumGate::Phase(half.inverse()), target, output);
+                cnot(control, target, output);
+                single(&QuantumGate::Phase(half), target, output);
+            }
+            _ => unreachable!("not a minimal gate"),
+        }
+    }
+}
+
+/// Returns the circuit of the process in OpenQASM 3.0 on the native gates of IBM devices,
+/// `rz`, `sx`, `x`, and `cz`, with qubit `i` as the physical qubit `$i`.
+///
+/// # Errors
+///
+/// Returns [`KetError::UnsuportedGateExport`] if a gate has more than one control qubit,
+/// which must be decomposed beforehand, or if there is an instruction other than a gate
+/// or a measurement.
+pub fn to_ibm_qasm(process: &Process) -> Result<String> {
+    let format = QasmFormat::canonical();
+    let mut body = String::new();
+    let mut bits = 0;
+
+    // Diagonal gates and multiplexed rotations are exported as CNOTs and rotations.
+    for instruction in multiplexor::lower(&process.instructions).iter() {
+        let mut natives = Vec::new();
+        match instruction {
+            Instruction::Alloc { .. } | Instruction::Free { .. } => {}
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } => match control[..] {
+                [] => single(gate, *target, &mut natives),
+                [control] => controlled(gate, control, *target, &mut natives),
+                _ => return Err(KetError::UnsuportedGateExport),
+            },
+            Instruction::Measure { qubits, .. } => {
+                for qubit in qubits {
+                    body += &format!("c[{}] = measure ${};\n", bits, qubit);
+                    bits += 1;
+                }
+            }
+            _ => return Err(KetError::UnsuportedGateExport),
+        }
+
+        for native in natives {
+            body += &match native {
+                Native::Rz(angle, qubit) => format!("rz({}) ${};\n", format.angle(&angle), qubit),
+                Native::Sx(qubit) => format!("sx ${};\n", qubit),
+                Native::X(qubit) => format!("x ${};\n", qubit),
+                Native::Cz(control, target) => format!("cz ${}, ${};\n", control, target),
+            };
+        }
+    }
+
+    let mut header = "OPENQASM 3.0;\ninclude \"stdgates.inc\";\n".to_string();
+    if bits > 0 {
+        header += &format!("bit[{}] c;\n", bits);
+    }
+    Ok(header + &body)
+}
+
+/// Returns an IBM Runtime sampler job that runs the process `shots` times on `backend`,
+/// with the circuit of [`to_ibm_qasm`].
+///
+/// # Errors
+///
+/// Returns the errors of [`to_ibm_qasm`].
+pub fn to_ibm_runtime(process: &Process, backend: &str, shots: u64) -> Result<String> {
+    Ok(json!({
+        "program_id": "sampler",
+        "backend": backend,
+        "params": {
+            "pubs": [[to_ibm_qasm(process)?, null, shots]],
+            "version": 2,
+        },
+    })
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use num::complex::Complex64;
+
+    use super::{controlled, to_ibm_runtime, Native};
+    use crate::{error::KetError, Angle, Configuration, Process, QuantumGate};
+
+    type Matrix = [[Complex64; 4]; 4];
+
+    fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+        let mut result = [[Complex64::default(); 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, entry) in row.iter_mut().enumerate() {
+                *entry = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        result
+    }
+
+    /// Two-qubit matrix of a gate on qubit 0 (the most significant bit) or 1.
+    fn lift(gate: [[Complex64; 2]; 2], qubit: usize) -> Matrix {
+        let mut result = [[Complex64::default(); 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, entry) in row.iter_mut().enumerate() {
+                let (bit, other) = if qubit == 0 { (1, 0) } else { (0, 1) };
+                if (i >> other) & 1 == (j >> other) & 1 {
+                    *entry = gate[(i >> bit) & 1][(j >> bit) & 1];
+                }
+            }
+        }
+        result
+    }
+
+    fn unitary(natives: &[Native]) -> Matrix {
+        let (zero, one) = (Complex64::default(), Complex64::from(1.0));
+        let mut result = lift([[one, zero], [zero, one]], 0);
+        for native in natives {
+            let gate = match native {
+                Native::Rz(angle, qubit) => {
+                    let phase = Complex64::from_polar(1.0, angle.radians() / 2.0);
+                    lift([[phase.conj(), zero], [zero, phase]], *qubit)
+                }
+                Native::Sx(qubit) => {
+                    let (a, b) = (Complex64::new(0.5, 0.5), Complex64::new(0.5, -0.5));
+                    lift([[a, b], [b, a]], *qubit)
+                }
+                Native::X(qubit) => lift([[zero, one], [one, zero]], *qubit),
+                Native::Cz(..) => {
+                    let mut cz = lift([[one, zero], [zero, one]], 0);
+                    cz[3][3] = -one;
+                    cz
+                }
+            };
+            result = multiply(&gate, &result);
+        }
+        result
+    }
+
+    #[test]
+    fn controlled_gates_are_exact() {
+        let (zero, one, i) = (Complex64::default(), Complex64::from(1.0), Complex64::i());
+        let h = Complex64::from(std::f64::consts::FRAC_1_SQRT_2);
+        let (cos, sin) = (
+            Complex64::from(0.55f64.cos()),
+            Complex64::from(0.55f64.sin()),
+        );
+        let gates = [
+            (QuantumGate::PauliX, [[zero, one], [one, zero]]),
+            (QuantumGate::PauliY, [[zero, -i], [i, zero]]),
+            (QuantumGate::Hadamard, [[h, h], [h, -h]]),
+            (
+                QuantumGate::Phase(Angle::Scalar(0.7)),
+                [[one, zero], [zero, Complex64::from_polar(1.0, 0.7)]],
+            ),
+            (
+                QuantumGate::RotationY(Angle::Scalar(1.1)),
+                [[cos, -sin], [sin, cos]],
+            ),
+        ];
+
+        for (gate, matrix) in gates {
+            let mut natives = Vec::new();
+            controlled(&gate, 0, 1, &mut natives);
+            let result = unitary(&natives);
+
+            let mut expected = lift([[one, zero], [zero, one]], 0);
+            for row in 0..2 {
+                for column in 0..2 {
+                    expected[2 + row][2 + column] = matrix[row][column];
+                }
+            }
+            // Only a global phase is allowed.
+            let phase = result[0][0];
+            for (row, expected) in result.iter().zip(expected) {
+                for (entry, expected) in row.iter().zip(expected) {
+                    assert!((entry - expected * phase).norm() < 1e-9, "{:?}", gate);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sampler_job() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        let qubits: Vec<usize> = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        process.apply_gate(QuantumGate::PauliX, qubits[0])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliZ, qubits[2])?;
+        process.ctrl_pop()?;
+        process.measure(&[qubits[2], qubits[0]])?;
+
+        let job: serde_json::Value =
+            serde_json::from_str(&to_ibm_runtime(&process, "ibm_test", 100)?).unwrap();
+        assert_eq!(job["backend"], "ibm_test");
+        assert_eq!(job["params"]["pubs"][0][2], 100);
+        let qasm = job["params"]["pubs"][0][0].as_str().unwrap();
+        assert!(qasm.contains("bit[2] c;\nx $0;\n"));
+        assert!(qasm.contains("cz $0, $2;\n"));
+        assert!(qasm.ends_with("c[0] = measure $2;\nc[1] = measure $0;\n"));
+
+        process.ctrl_push(&qubits[..2])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[2])?;
+        process.ctrl_pop()?;
+        assert!(to_ibm_runtime(&process, "ibm_test", 100).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! IonQ JSON circuit format.
+
+use std::collections::HashSet;
+
+use serde_json::{json, Value};
+
+use crate::{
+    error::{KetError, Result},
+    ir::{Instruction, QuantumGate},
+    multiplexor, Process,
+};
+
+use super::num_qubits;
+
+/// Appends the gate to the IonQ circuit.
+///
+/// IonQ has no phase gate, so a phase that is not a Z, S, or T gate is written as
+/// `P(θ) = e^(iθ/2) RZ(θ)`, with the global phase `e^(iθ/2)` as a phase gate on the
+/// last control qubit.
+fn push_gate(gate: &QuantumGate, target: usize, control: &[usize], circuit: &mut Vec<Value>) {
+    let (name, rotation) = match gate {
+        QuantumGate::PauliX if control.is_empty() => ("x", None),
+        QuantumGate::PauliX => ("cnot", None),
+        QuantumGate::PauliY => ("y", None),
+        QuantumGate::PauliZ => ("z", None),
+        QuantumGate::Hadamard => ("h", None),
+        QuantumGate::RotationX(angle) => ("rx", Some(angle.radians())),
+        QuantumGate::RotationY(angle) => ("ry", Some(angle.radians())),
+        QuantumGate::RotationZ(angle) => ("rz", Some(angle.radians())),
+        QuantumGate::Phase(angle) => match angle.as_pi_fraction() {
+            Some((1 | -1, 1)) => ("z", None),
+            Some((1, 2)) => ("s", None),
+            Some((-1, 2)) => ("si", None),
+            Some((1, 4)) => ("t", None),
+            Some((-1, 4)) => ("ti", None),
+            _ => {
+                push_gate(
+                    &QuantumGate::RotationZ(angle.clone()),
+                    target,
+                    control,
+                    circuit,
+                );
+                if let Some((last, control)) = control.split_last() {
+                    push_gate(&QuantumGate::Phase(angle.half()), *last, control, circuit);
+                }
+                return;
+            }
+        },
+    };
+
+    let mut entry = json!({ "gate": name, "target": target });
+    match control {
+        [] => {}
+        [control] => entry["control"] = json!(control),
+        control => entry["controls"] = json!(control),
+    }
+    if let Some(rotation) = rotation {
+        entry["rotation"] = json!(rotation);
+    }
+    circuit.push(entry);
+}
+
+/// Returns the process in the IonQ JSON circuit format, the `input` of an IonQ job.
+///
+/// IonQ measures every qubit at the end of the circuit, so a measured qubit cannot be
+/// used again.
+///
+/// # Errors
+///
+/// Returns [`KetError::UnsuportedGateExport`] if a gate acts on a measured qubit or if
+/// there is an instruction other than a gate or a measurement.
+pub fn to_ionq(process: &Process) -> Result<String> {
+    let mut circuit = Vec::new();
+    let mut measured = HashSet::new();
+
+    // Diagonal gates and multiplexed rotations are exported as CNOTs and rotations.
+    for instruction in multiplexor::lower(&process.instructions).iter() {
+        match instruction {
+            Instruction::Alloc { .. } | Instruction::Free { .. } => {}
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } => {
+                if control
+                    .iter()
+                    .chain([target])
+                    .any(|qubit| measured.contains(qubit))
+                {
+                    return Err(KetError::UnsuportedGateExport);
+                }
+                push_gate(gate, *target, control, &mut circuit);
+            }
+            Instruction::Measure { qubits, .. } => measured.extend(qubits.iter().copied()),
+            _ => return Err(KetError::UnsuportedGateExport),
+        }
+    }
+
+    Ok(json!({
+        "format": "ionq.circuit.v0",
+        "qubits": num_qubits(&process.instructions),
+        "circuit": circuit,
+    })
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::to_ionq;
+    use crate::{error::KetError, Angle, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn native_gates() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        let qubits: Vec<usize> = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+        process.ctrl_push(&qubits[..2])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[2])?;
+        process.apply_gate(QuantumGate::Phase(Angle::Scalar(0.5)), qubits[2])?;
+        process.ctrl_pop()?;
+        process.apply_gate(QuantumGate::Phase(Angle::pi_fraction(-1, 4)), qubits[1])?;
+        process.measure(&qubits)?;
+
+        let job: Value = serde_json::from_str(&to_ionq(&process)?).unwrap();
+        assert_eq!(job["qubits"], 3);
+        assert_eq!(
+            job["circuit"],
+            json!([
+                { "gate": "h", "target": 0 },
+                { "gate": "cnot", "controls": [0, 1], "target": 2 },
+                { "gate": "rz", "controls": [0, 1], "target": 2, "rotation": 0.5 },
+                { "gate": "rz", "control": 0, "target": 1, "rotation": 0.25 },
+                { "gate": "rz", "target": 0, "rotation": 0.125 },
+                { "gate": "ti", "target": 1 },
+            ])
+        );
+
+        process.apply_gate(QuantumGate::PauliX, qubits[0])?;
+        assert!(to_ionq(&process).is_err());
+
+        Ok(())
+    }
+}
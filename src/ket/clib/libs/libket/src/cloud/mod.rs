@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Job formats of quantum cloud providers.
+//!
+//! Each exporter writes the gates of a process with the gate set accepted by the
+//! provider, decomposing the gates it does not support:
+//!
+//! * [`to_ionq`]: IonQ JSON circuit, with measurements of all qubits at the end.
+//! * [`to_ibm_runtime`]: IBM Runtime sampler job, with the circuit in OpenQASM 3.0 on the
+//!   native `rz`, `sx`, `x`, and `cz` gates.
+//! * [`to_braket`]: Amazon Braket OpenQASM program, with the Braket gate names.
+//!
+//! Qubit indices are written as they are in the instructions, so the circuit should be
+//! routed to the device, or compacted with
+//! [`QubitCompaction`](crate::passes::QubitCompaction), first. Each measured qubit is a
+//! classical bit, in the order of the measurements, so the outcome of
+//! [`Process::measure`](crate::Process::measure) on `n` qubits is `n` consecutive bits,
+//! the first qubit being the most significant bit.
+//!
+//! Samples, dumps, expected values, and Kraus channels have no equivalent in these formats
+//! and make the exporters return
+//! [`KetError::UnsuportedGateExport`](crate::error::KetError::UnsuportedGateExport).
+
+pub mod braket;
+pub mod ibm;
+pub mod ionq;
+
+pub use braket::to_braket;
+pub use ibm::to_ibm_runtime;
+pub use ionq::to_ionq;
+
+use crate::{ir::Instruction, passes::peephole::qubits};
+
+/// Number of qubits of the register used by the instructions.
+fn num_qubits(instructions: &[Instruction]) -> usize {
+    instructions
+        .iter()
+        .flat_map(qubits)
+        .map(|qubit| qubit + 1)
+        .max()
+        .unwrap_or(0)
+}
@@ -0,0 +1,424 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compressed storage for quantum state dumps.
+//!
+//! Scripts that dump the state many times usually produce a sequence of very similar
+//! [`DumpData`]. With [`DumpCompression::Delta`], each dump only stores the basis states
+//! whose amplitudes changed since the previous dump of the same qubits, which is
+//! lossless. With [`DumpCompression::Quantized`], the amplitudes are stored as
+//! fixed-point integers that share a single exponent, trading precision for memory.
+//!
+//! The compression is selected with [`Process::set_dump_compression`](crate::Process::set_dump_compression),
+//! and the dumps are recovered with [`Process::get_dump_data`](crate::Process::get_dump_data).
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ir::DumpData;
+
+/// Maximum number of chained deltas before a full dump is stored again.
+const MAX_DELTA_DEPTH: usize = 16;
+
+/// Compression mode for quantum state dumps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DumpCompression {
+    /// Dumps are stored as returned by the quantum executor.
+    #[default]
+    None,
+
+    /// Lossless delta encoding from the previous dump of the same qubits.
+    Delta,
+
+    /// Lossy fixed-point encoding with `bits` bits per amplitude component and a
+    /// shared exponent. `bits` is clamped to the range `2..=32`.
+    Quantized {
+        /// Bits per amplitude component, including the sign.
+        bits: u32,
+    },
+}
+
+/// Compressed quantum state dump.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CompressedDump {
+    /// Amplitude changes from a reference dump.
+    Delta {
+        /// Index of the reference dump, or `None` if this is a full dump.
+        reference: Option<usize>,
+
+        /// Number of deltas between this dump and the last full dump.
+        depth: usize,
+
+        /// Basis states of the reference dump that are not present in this dump.
+        removed: Vec<Vec<u64>>,
+
+        /// Basis states that are new or whose amplitudes changed.
+        basis_states: Vec<Vec<u64>>,
+
+        /// Real part of the new amplitudes.
+        amplitudes_real: Vec<f64>,
+
+        /// Imaginary part of the new amplitudes.
+        amplitudes_imag: Vec<f64>,
+    },
+
+    /// Fixed-point amplitudes with a shared exponent.
+    Quantized {
+        /// Amplitude components are multiplied by `2^(-scale)` when decoded.
+        scale: i32,
+
+        /// Basis states of the quantum state.
+        basis_states: Vec<Vec<u64>>,
+
+        /// Quantized real part of the amplitudes.
+        amplitudes_real: Vec<i32>,
+
+        /// Quantized imaginary part of the amplitudes.
+        amplitudes_imag: Vec<i32>,
+    },
+}
+
+fn is_sorted(basis_states: &[Vec<u64>]) -> bool {
+    basis_states.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+impl CompressedDump {
+    /// Compresses a dump.
+    ///
+    /// For [`DumpCompression::Delta`], `previous` holds the index, the compressed form,
+    /// and the data of the previous dump of the same qubits, if any. A full dump is
+    /// stored when there is no previous dump, when the delta chain is too long, or when
+    /// the basis states are not in ascending order, so the original data is always
+    /// recovered exactly.
+    ///
+    /// Returns `None` for [`DumpCompression::None`].
+    pub fn compress(
+        data: &DumpData,
+        compression: DumpCompression,
+        previous: Option<(usize, &CompressedDump, &DumpData)>,
+    ) -> Option<Self> {
+        match compression {
+            DumpCompression::None => None,
+            DumpCompression::Delta => Some(Self::delta(data, previous)),
+            DumpCompression::Quantized { bits } => Some(Self::quantize(data, bits.clamp(2, 32))),
+        }
+    }
+
+    fn delta(data: &DumpData, previous: Option<(usize, &CompressedDump, &DumpData)>) -> Self {
+        let previous = previous.and_then(|(index, compressed, previous)| match compressed {
+            CompressedDump::Delta { depth, .. } if depth + 1 < MAX_DELTA_DEPTH => {
+                Some((index, depth + 1, previous))
+            }
+            _ => None,
+        });
+
+        let (reference, depth, previous) = match previous {
+            Some(previous) if is_sorted(&data.basis_states) => previous,
+            _ => {
+                return CompressedDump::Delta {
+                    reference: None,
+                    depth: 0,
+                    removed: Vec::new(),
+                    basis_states: data.basis_states.clone(),
+                    amplitudes_real: data.amplitudes_real.clone(),
+                    amplitudes_imag: data.amplitudes_imag.clone(),
+                }
+            }
+        };
+
+        let mut old: BTreeMap<&Vec<u64>, (u64, u64)> = previous
+            .basis_states
+            .iter()
+            .zip(previous.amplitudes_real.iter())
+            .zip(previous.amplitudes_imag.iter())
+            .map(|((state, real), imag)| (state, (real.to_bits(), imag.to_bits())))
+            .collect();
+
+        let mut basis_states = Vec::new();
+        let mut amplitudes_real = Vec::new();
+        let mut amplitudes_imag = Vec::new();
+
+        for ((state, real), imag) in data
+            .basis_states
+            .iter()
+            .zip(data.amplitudes_real.iter())
+            .zip(data.amplitudes_imag.iter())
+        {
+            if old.remove(state) != Some((real.to_bits(), imag.to_bits())) {
+                basis_states.push(state.clone());
+                amplitudes_real.push(*real);
+                amplitudes_imag.push(*imag);
+            }
+        }
+
+        CompressedDump::Delta {
+            reference: Some(reference),
+            depth,
+            removed: old.into_keys().cloned().collect(),
+            basis_states,
+            amplitudes_real,
+            amplitudes_imag,
+        }
+    }
+
+    fn quantize(data: &DumpData, bits: u32) -> Self {
+        let max_abs = data
+            .amplitudes_real
+            .iter()
+            .chain(data.amplitudes_imag.iter())
+            .fold(0.0f64, |max, x| max.max(x.abs()));
+
+        // Smallest exponent such that every component is bounded by 2^exponent.
+        let exponent = if max_abs > 0.0 {
+            max_abs.log2().ceil() as i32
+        } else {
+            0
+        };
+        let scale = bits as i32 - 1 - exponent;
+        let limit = ((1i64 << (bits - 1)) - 1) as f64;
+
+        let quantize = |x: &f64| (x * 2f64.powi(scale)).round().clamp(-limit, limit) as i32;
+
+        CompressedDump::Quantized {
+            scale,
+            basis_states: data.basis_states.clone(),
+            amplitudes_real: data.amplitudes_real.iter().map(quantize).collect(),
+            amplitudes_imag: data.amplitudes_imag.iter().map(quantize).collect(),
+        }
+    }
+
+    /// Returns the index of the dump required to decompress this one, if any.
+    pub fn reference(&self) -> Option<usize> {
+        match self {
+            CompressedDump::Delta { reference, .. } => *reference,
+            CompressedDump::Quantized { .. } => None,
+        }
+    }
+
+    /// Decompresses the dump.
+    ///
+    /// `reference` must be the decompressed data of the dump returned by
+    /// [`CompressedDump::reference`].
+    pub fn decompress(&self, reference: Option<&DumpData>) -> DumpData {
+        match self {
+            CompressedDump::Delta {
+                reference: None,
+                basis_states,
+                amplitudes_real,
+                amplitudes_imag,
+                ..
+            } => DumpData {
+                basis_states: basis_states.clone(),
+                amplitudes_real: amplitudes_real.clone(),
+                amplitudes_imag: amplitudes_imag.clone(),
+            },
+            CompressedDump::Delta {
+                removed,
+                basis_states,
+                amplitudes_real,
+                amplitudes_imag,
+                ..
+            } => {
+                let reference = reference.expect("delta dump requires its reference");
+
+                let mut state: BTreeMap<&Vec<u64>, (f64, f64)> = reference
+                    .basis_states
+                    .iter()
+                    .zip(reference.amplitudes_real.iter())
+                    .zip(reference.amplitudes_imag.iter())
+                    .map(|((state, real), imag)| (state, (*real, *imag)))
+                    .collect();
+
+                for basis in removed {
+                    state.remove(basis);
+                }
+
+                for ((basis, real), imag) in basis_states
+                    .iter()
+                    .zip(amplitudes_real.iter())
+                    .zip(amplitudes_imag.iter())
+                {
+                    state.insert(basis, (*real, *imag));
+                }
+
+                let mut data = DumpData {
+                    basis_states: Vec::with_capacity(state.len()),
+                    amplitudes_real: Vec::with_capacity(state.len()),
+                    amplitudes_imag: Vec::with_capacity(state.len()),
+                };
+                for (basis, (real, imag)) in state {
+                    data.basis_states.push(basis.clone());
+                    data.amplitudes_real.push(real);
+                    data.amplitudes_imag.push(imag);
+                }
+                data
+            }
+            CompressedDump::Quantized {
+                scale,
+                basis_states,
+                amplitudes_real,
+                amplitudes_imag,
+            } => {
+                let factor = 2f64.powi(-scale);
+                DumpData {
+                    basis_states: basis_states.clone(),
+                    amplitudes_real: amplitudes_real.iter().map(|x| *x as f64 * factor).collect(),
+                    amplitudes_imag: amplitudes_imag.iter().map(|x| *x as f64 * factor).collect(),
+                }
+            }
+        }
+    }
+
+    /// Returns an estimate of the heap memory used by the compressed dump, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        let states_size = |states: &[Vec<u64>]| -> usize {
+            states
+                .iter()
+                .map(|state| state.len() * std::mem::size_of::<u64>())
+                .sum()
+        };
+
+        match self {
+            CompressedDump::Delta {
+                removed,
+                basis_states,
+                ..
+            } => {
+                states_size(removed)
+                    + states_size(basis_states)
+                    + 2 * basis_states.len() * std::mem::size_of::<f64>()
+            }
+            CompressedDump::Quantized { basis_states, .. } => {
+                states_size(basis_states) + 2 * basis_states.len() * std::mem::size_of::<i32>()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompressedDump, DumpCompression};
+    use crate::c_api::objects::{ket_process_get_dump, ket_process_get_dump_size};
+    use crate::error::KetError;
+    use crate::ir::DumpData;
+    use crate::{Configuration, Process, ResultData};
+
+    fn dump(amplitudes: &[(u64, f64)]) -> DumpData {
+        DumpData {
+            basis_states: amplitudes.iter().map(|(state, _)| vec![*state]).collect(),
+            amplitudes_real: amplitudes.iter().map(|(_, amp)| *amp).collect(),
+            amplitudes_imag: vec![0.0; amplitudes.len()],
+        }
+    }
+
+    #[test]
+    fn delta_is_lossless() {
+        let first = dump(&[(0, 0.5), (1, 0.5), (2, 0.5), (3, 0.5)]);
+        let second = dump(&[(0, 0.5), (1, -0.5), (3, 0.5), (4, 0.5)]);
+
+        let compressed_first =
+            CompressedDump::compress(&first, DumpCompression::Delta, None).unwrap();
+        let compressed_second = CompressedDump::compress(
+            &second,
+            DumpCompression::Delta,
+            Some((0, &compressed_first, &first)),
+        )
+        .unwrap();
+
+        assert_eq!(compressed_second.reference(), Some(0));
+        match &compressed_second {
+            CompressedDump::Delta {
+                removed,
+                basis_states,
+                ..
+            } => {
+                assert_eq!(removed, &vec![vec![2]]);
+                assert_eq!(basis_states, &vec![vec![1], vec![4]]);
+            }
+            _ => unreachable!(),
+        }
+
+        let decoded = compressed_second.decompress(Some(&first));
+        assert_eq!(decoded.basis_states, second.basis_states);
+        assert_eq!(decoded.amplitudes_real, second.amplitudes_real);
+        assert_eq!(decoded.amplitudes_imag, second.amplitudes_imag);
+    }
+
+    #[test]
+    fn quantized_error_is_bounded() {
+        let data = dump(&[(0, 0.6), (1, -0.8), (2, 1e-3)]);
+
+        let compressed =
+            CompressedDump::compress(&data, DumpCompression::Quantized { bits: 16 }, None).unwrap();
+        let decoded = compressed.decompress(None);
+
+        for (original, decoded) in data.amplitudes_real.iter().zip(decoded.amplitudes_real) {
+            assert!((original - decoded).abs() <= 2f64.powi(-15));
+        }
+    }
+
+    #[test]
+    fn process_delta_dumps() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(1));
+        process.config.optimize = false;
+        process.set_dump_compression(DumpCompression::Delta);
+        let qubit = process.allocate_qubit()?;
+
+        let dumps: Vec<usize> = (0..3)
+            .map(|_| process.dump(&[qubit]))
+            .collect::<Result<_, _>>()?;
+
+        process.prepare_for_execution()?;
+
+        let results = vec![
+            dump(&[(0, 1.0)]),
+            dump(&[(0, 1.0)]),
+            dump(&[(0, 0.6), (1, 0.8)]),
+        ];
+        process.set_result(ResultData {
+            dumps: results.clone(),
+            ..Default::default()
+        })?;
+
+        assert!(process.get_dump(dumps[1]).result.is_none());
+        assert_eq!(
+            process
+                .get_dump(dumps[2])
+                .compressed
+                .as_ref()
+                .unwrap()
+                .reference(),
+            Some(1)
+        );
+        for (index, expected) in dumps.iter().zip(results) {
+            let data = process.get_dump_data(*index).unwrap();
+            assert_eq!(data.basis_states, expected.basis_states);
+            assert_eq!(data.amplitudes_real, expected.amplitudes_real);
+        }
+
+        // The C API reads the compressed dumps too.
+        let (mut available, mut size) = (false, 0);
+        ket_process_get_dump_size(&process, dumps[2], &mut available, &mut size);
+        assert_eq!((available, size), (true, 2));
+        let (mut state, mut state_size) = (std::ptr::null(), 0);
+        let (mut real, mut imag) = (0.0, 0.0);
+        let code = unsafe {
+            ket_process_get_dump(
+                &mut process,
+                dumps[2],
+                1,
+                &mut state,
+                &mut state_size,
+                &mut real,
+                &mut imag,
+            )
+        };
+        assert_eq!(code, KetError::Success.error_code());
+        assert_eq!((unsafe { *state }, state_size, real), (1, 1, 0.8));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Step-by-step execution of a circuit, for debuggers.
+//!
+//! A [`Debugger`] runs the instructions of a process on a live executor one at a time.
+//! It stops before the instructions that match a [`Breakpoint`], either an instruction
+//! index or an event on a qubit, and the state of the allocated qubits can be inspected
+//! at every stop with [`Debugger::debug_state`] or as reduced density matrices, as an IDE
+//! shows the variables of a paused program. The same interface is in the C API, see
+//! [`c_api::debugger`](crate::c_api::debugger).
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::debugger::{Breakpoint, Debugger, QubitEvent, Stop};
+//! use ket::{Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut process = Process::new(Configuration::new(2));
+//! let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+//! process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+//! process.ctrl_push(&qubits[..1])?;
+//! process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+//! process.ctrl_pop()?;
+//!
+//! # struct Executor;
+//! # impl ket::LiveExecution for Executor {
+//! #     fn alloc(&mut self, _: usize) {}
+//! #     fn free(&mut self, _: usize) {}
+//! #     fn gate(&mut self, _: &QuantumGate, _: usize, _: &[usize]) {}
+//! #     fn measure(&mut self, _: &[usize]) -> u64 { 0 }
+//! #     fn exp_value(&mut self, _: &ket::PauliHamiltonian) -> f64 { 0.0 }
+//! #     fn sample(&mut self, _: &[usize], _: u64) -> (Vec<u64>, Vec<u64>) { (vec![], vec![]) }
+//! #     fn dump(&mut self, _: &[usize]) -> ket::DumpData {
+//! #         ket::DumpData { basis_states: vec![vec![0]], amplitudes_real: vec![1.0], amplitudes_imag: vec![0.0] }
+//! #     }
+//! # }
+//! // A configuration with a live executor, as the simulators provide.
+//! let configuration = Configuration {
+//!     live_quantum_execution: Some(Box::new(Executor)),
+//!     ..Configuration::new(2)
+//! };
+//! let mut debugger = Debugger::new(process.instructions(), configuration)?;
+//! let breakpoint = debugger.add_breakpoint(Breakpoint::Qubit {
+//!     qubit: qubits[1],
+//!     event: QubitEvent::Gate,
+//! });
+//!
+//! assert_eq!(debugger.resume()?, Stop::Breakpoint(breakpoint));
+//! println!("{}", debugger.debug_state()?);
+//! let rho = debugger.reduced_density_matrix(&qubits[..1])?;
+//! assert_eq!(rho.len(), 2);
+//! assert_eq!(debugger.step()?, Stop::Step);
+//! assert_eq!(debugger.resume()?, Stop::Finished);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use num::complex::Complex64;
+
+use crate::{
+    bloch::{bloch_vector, BlochVector},
+    error::{KetError, Result},
+    execution::{Configuration, LiveExecution},
+    ir::{DumpData, Instruction},
+    passes::peephole::qubits,
+    state_format::{format_state, StateFormat},
+};
+
+/// Event on a qubit that stops a [`Debugger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QubitEvent {
+    /// Any instruction on the qubit.
+    Any,
+    /// A gate with the qubit as target or control.
+    Gate,
+    /// A measurement of the qubit.
+    Measure,
+    /// The allocation of the qubit.
+    Alloc,
+    /// The release of the qubit.
+    Free,
+}
+
+/// Condition to stop a [`Debugger`] before an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Stops before the instruction at this index.
+    Instruction(usize),
+    /// Stops before the instructions with this event on the qubit.
+    Qubit {
+        /// Index of the qubit.
+        qubit: usize,
+        /// Event on the qubit.
+        event: QubitEvent,
+    },
+}
+
+impl Breakpoint {
+    /// Returns `true` if the debugger stops before the instruction at `index`.
+    pub fn matches(&self, index: usize, instruction: &Instruction) -> bool {
+        match *self {
+            Breakpoint::Instruction(position) => position == index,
+            Breakpoint::Qubit { qubit, event } => match (event, instruction) {
+                (QubitEvent::Any, instruction) => qubits(instruction).contains(&qubit),
+                (
+                    QubitEvent::Gate,
+                    Instruction::Gate {
+                        target, control, ..
+                    }
+                    | Instruction::ConditionalGate {
+                        target, control, ..
+                    },
+                ) => *target == qubit || control.contains(&qubit),
+                (QubitEvent::Measure, Instruction::Measure { qubits, .. }) => {
+                    qubits.contains(&qubit)
+                }
+                (QubitEvent::Alloc, Instruction::Alloc { target })
+                | (QubitEvent::Free, Instruction::Free { target }) => *target == qubit,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Reason a [`Debugger`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// One instruction was executed by [`Debugger::step`].
+    Step,
+    /// The next instruction matches the breakpoint with this identifier.
+    Breakpoint(usize),
+    /// Every instruction was executed.
+    Finished,
+}
+
+/// Runs instructions on a live executor, stopping at breakpoints, see
+/// [`debugger`](crate::debugger).
+pub struct Debugger {
+    instructions: Vec<Instruction>,
+    execution: Box<dyn LiveExecution>,
+    position: usize,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_breakpoint: usize,
+    allocated: BTreeSet<usize>,
+    measurements: BTreeMap<usize, u64>,
+    exp_values: BTreeMap<usize, f64>,
+    samples: BTreeMap<usize, (Vec<u64>, Vec<u64>)>,
+    dumps: BTreeMap<usize, DumpData>,
+    format: StateFormat,
+}
+
+impl Debugger {
+    /// Creates a debugger that runs the instructions on the live executor of the
+    /// configuration, usually a simulator.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::LiveExecutionRequired`] if the configuration has no live
+    /// executor.
+    pub fn new(instructions: &[Instruction], configuration: Configuration) -> Result<Self> {
+        let execution = configuration
+            .live_quantum_execution
+            .ok_or(KetError::LiveExecutionRequired)?;
+        Ok(Self {
+            instructions: instructions.to_vec(),
+            execution,
+            position: 0,
+            breakpoints: BTreeMap::new(),
+            next_breakpoint: 0,
+            allocated: BTreeSet::new(),
+            measurements: BTreeMap::new(),
+            exp_values: BTreeMap::new(),
+            samples: BTreeMap::new(),
+            dumps: BTreeMap::new(),
+            format: StateFormat::default(),
+        })
+    }
+
+    /// Adds a breakpoint and returns its identifier.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.breakpoints.insert(id, breakpoint);
+        id
+    }
+
+    /// Removes a breakpoint, returning `false` if there is none with this identifier.
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.breakpoints.remove(&id).is_some()
+    }
+
+    /// Returns the breakpoints by identifier.
+    pub fn breakpoints(&self) -> &BTreeMap<usize, Breakpoint> {
+        &self.breakpoints
+    }
+
+    /// Returns the index of the next instruction to execute.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the next instruction to execute, or `None` at the end.
+    pub fn next_instruction(&self) -> Option<&Instruction> {
+        self.instructions.get(self.position)
+    }
+
+    /// Returns the qubits allocated so far and not freed.
+    pub fn allocated_qubits(&self) -> Vec<usize> {
+        self.allocated.iter().copied().collect()
+    }
+
+    /// Returns the result of the measurement with this output index, if it was executed.
+    pub fn measurement(&self, index: usize) -> Option<u64> {
+        self.measurements.get(&index).copied()
+    }
+
+    /// Returns the result of the expected value with this output index, if it was
+    /// executed.
+    pub fn exp_value(&self, index: usize) -> Option<f64> {
+        self.exp_values.get(&index).copied()
+    }
+
+    /// Returns the states and counts of the sample with this output index, if it was
+    /// executed.
+    pub fn sample(&self, index: usize) -> Option<&(Vec<u64>, Vec<u64>)> {
+        self.samples.get(&index)
+    }
+
+    /// Returns the dump with this output index, if it was executed.
+    pub fn dump(&self, index: usize) -> Option<&DumpData> {
+        self.dumps.get(&index)
+    }
+
+    /// Executes the next instruction.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::KrausChannelNotSupported`] if the instruction is a Kraus
+    /// channel that the executor does not support, without moving to the next
+    /// instruction.
+    pub fn step(&mut self) -> Result<Stop> {
+        let Some(instruction) = self.instructions.get(self.position) else {
+            return Ok(Stop::Finished);
+        };
+        let execution = &mut self.execution;
+        match instruction {
+            Instruction::Alloc { target } => {
+                execution.alloc(*target);
+                self.allocated.insert(*target);
+            }
+            Instruction::Free { target } => {
+                execution.free(*target);
+                self.allocated.remove(target);
+            }
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } => execution.gate(gate, *target, control),
+            Instruction::ConditionalGate {
+                gate,
+                target,
+                control,
+                condition,
+            } => {
+                if condition.holds(|index| self.measurements.get(&index).copied()) {
+                    execution.gate(gate, *target, control);
+                }
+            }
+            Instruction::Measure { qubits, output } => {
+                self.measurements.insert(*output, execution.measure(qubits));
+            }
+            Instruction::ExpValue {
+                hamiltonian,
+                output,
+            } => {
+                self.exp_values
+                    .insert(*output, execution.exp_value(hamiltonian));
+            }
+            Instruction::Sample {
+                qubits,
+                shots,
+                output,
+            } => {
+                self.samples
+                    .insert(*output, execution.sample(qubits, *shots));
+            }
+            Instruction::Dump { qubits, output } => {
+                self.dumps.insert(*output, execution.dump(qubits));
+            }
+            Instruction::KrausChannel { operators, target } => {
+                if !execution.kraus_channel(operators, *target) {
+                    return Err(KetError::KrausChannelNotSupported);
+                }
+            }
+            Instruction::Diagonal { phases, qubits } => execution.diagonal(phases, qubits),
+            Instruction::Multiplexor {
+                axis,
+                angles,
+                control,
+                target,
+            } => execution.multiplexor(*axis, angles, control, *target),
+        }
+        self.position += 1;
+        Ok(Stop::Step)
+    }
+
+    /// Executes instructions until the next one matches a breakpoint, or to the end.
+    ///
+    /// The next instruction is always executed, so resuming from a breakpoint moves
+    /// past it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`Debugger::step`].
+    pub fn resume(&mut self) -> Result<Stop> {
+        loop {
+            if self.step()? == Stop::Finished {
+                return Ok(Stop::Finished);
+            }
+            let Some(instruction) = self.instructions.get(self.position) else {
+                return Ok(Stop::Finished);
+            };
+            let hit = self
+                .breakpoints
+                .iter()
+                .find(|(_, breakpoint)| breakpoint.matches(self.position, instruction));
+            if let Some((id, _)) = hit {
+                return Ok(Stop::Breakpoint(*id));
+            }
+        }
+    }
+
+    /// Dumps every allocated qubit, in increasing index order.
+    pub fn state(&mut self) -> DumpData {
+        let qubits = self.allocated_qubits();
+        self.execution.dump(&qubits)
+    }
+
+    /// Sets the format of [`Debugger::debug_state`].
+    pub fn set_state_format(&mut self, format: StateFormat) {
+        self.format = format;
+    }
+
+    /// Returns the state of the allocated qubits as text, one basis state per line, in
+    /// the format of [`Debugger::set_state_format`], see
+    /// [`state_format`](crate::state_format).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::DumpNotAllowed`] if no qubit is allocated.
+    pub fn debug_state(&mut self) -> Result<String> {
+        if self.allocated.is_empty() {
+            return Err(KetError::DumpNotAllowed);
+        }
+        let state = self.state();
+        Ok(format_state(&state, &self.allocated_qubits(), &self.format))
+    }
+
+    /// Returns the reduced density matrix of the qubits, tracing out the other
+    /// allocated qubits. The first qubit is the most significant bit of the matrix
+    /// index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::DeallocatedQubit`] if a qubit is not allocated.
+    pub fn reduced_density_matrix(&mut self, qubits: &[usize]) -> Result<Vec<Vec<Complex64>>> {
+        let allocated = self.allocated_qubits();
+        let positions = qubits
+            .iter()
+            .map(|qubit| allocated.binary_search(qubit))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| KetError::DeallocatedQubit)?;
+        Ok(self
+            .state()
+            .reduced_density_matrix(allocated.len(), &positions))
+    }
+
+    /// Returns the Bloch vector of the qubit, see
+    /// [`Debugger::reduced_density_matrix`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::DeallocatedQubit`] if the qubit is not allocated.
+    pub fn bloch_vector(&mut self, qubit: usize) -> Result<BlochVector> {
+        Ok(bloch_vector(&self.reduced_density_matrix(&[qubit])?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Breakpoint, Debugger, QubitEvent, Stop};
+    use crate::{
+        error::KetError,
+        ir::{DumpData, PauliHamiltonian, QuantumGate},
+        state_format::StateFormat,
+        Configuration, LiveExecution, Process,
+    };
+
+    /// Measures and dumps the number of applied gates.
+    struct Counter(u64);
+
+    impl LiveExecution for Counter {
+        fn alloc(&mut self, _: usize) {}
+        fn free(&mut self, _: usize) {}
+        fn gate(&mut self, _: &QuantumGate, _: usize, _: &[usize]) {
+            self.0 += 1;
+        }
+        fn measure(&mut self, _: &[usize]) -> u64 {
+            self.0
+        }
+        fn exp_value(&mut self, _: &PauliHamiltonian) -> f64 {
+            0.0
+        }
+        fn sample(&mut self, _: &[usize], _: u64) -> (Vec<u64>, Vec<u64>) {
+            (vec![self.0], vec![1])
+        }
+        fn dump(&mut self, _: &[usize]) -> DumpData {
+            DumpData {
+                basis_states: vec![vec![self.0]],
+                amplitudes_real: vec![1.0],
+                amplitudes_imag: vec![0.0],
+            }
+        }
+    }
+
+    #[test]
+    fn breakpoints() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(2));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[0])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        let measurement = process.measure(&qubits)?;
+
+        assert!(matches!(
+            Debugger::new(process.instructions(), Configuration::new(2)),
+            Err(KetError::LiveExecutionRequired)
+        ));
+        let mut debugger = Debugger::new(
+            process.instructions(),
+            Configuration {
+                live_quantum_execution: Some(Box::new(Counter(0))),
+                ..Configuration::new(2)
+            },
+        )?;
+        let on_gate = debugger.add_breakpoint(Breakpoint::Qubit {
+            qubit: qubits[1],
+            event: QubitEvent::Gate,
+        });
+        let on_measure = debugger.add_breakpoint(Breakpoint::Qubit {
+            qubit: qubits[0],
+            event: QubitEvent::Measure,
+        });
+        let at_start = debugger.add_breakpoint(Breakpoint::Instruction(0));
+        assert!(debugger.remove_breakpoint(at_start));
+        assert!(!debugger.remove_breakpoint(at_start));
+
+        assert_eq!(debugger.resume()?, Stop::Breakpoint(on_gate));
+        assert_eq!(debugger.position(), 4);
+        assert_eq!(debugger.allocated_qubits(), qubits);
+        assert_eq!(
+            debugger.debug_state()?,
+            "|10⟩ 1.000000+0.000000i p=1.000000\n"
+        );
+        debugger.set_state_format(StateFormat {
+            qubits: Some(vec![qubits[1]]),
+            precision: 1,
+            ..Default::default()
+        });
+        assert_eq!(debugger.debug_state()?, "|0⟩ p=1.0\n");
+
+        assert_eq!(debugger.resume()?, Stop::Breakpoint(on_measure));
+        assert_eq!(debugger.measurement(measurement), None);
+        assert_eq!(debugger.step()?, Stop::Step);
+        assert_eq!(debugger.measurement(measurement), Some(3));
+        assert_eq!(debugger.resume()?, Stop::Finished);
+        assert_eq!(debugger.step()?, Stop::Finished);
+        assert!(matches!(
+            debugger.reduced_density_matrix(&[5]),
+            Err(KetError::DeallocatedQubit)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Distances between probability distributions of outcomes.
+//!
+//! The counts of a [`Sample`] are compared with an ideal distribution, usually the
+//! probabilities of a [`DumpData`] from a noiseless simulation of the same circuit:
+//!
+//! * [`total_variation_distance`], `½ Σ |p(x) - q(x)|`, from zero for equal
+//!   distributions to one for disjoint ones;
+//! * [`hellinger_fidelity`], `(Σ sqrt(p(x) q(x)))²`, from one for equal distributions
+//!   to zero for disjoint ones;
+//! * [`linear_xeb`], the linear cross-entropy benchmarking score `2^n Σ q(x) p(x) - 1`
+//!   of the samples `q` against the ideal `p` of `n` qubits, close to one for a
+//!   noiseless random circuit and to zero for uniform noise.
+//!
+//! ```
+//! use ket::distribution::{from_counts, hellinger_fidelity, total_variation_distance};
+//!
+//! let ideal = from_counts(&[0, 3], &[1, 1]);
+//! let measured = from_counts(&[0, 1, 3], &[480, 40, 504]);
+//! assert!(total_variation_distance(&ideal, &measured) < 0.05);
+//! assert!(hellinger_fidelity(&ideal, &measured) > 0.95);
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::{basis, ir::DumpData, objects::Sample};
+
+/// Probability of each outcome, the outcomes with zero probability being absent.
+pub type Distribution = BTreeMap<u64, f64>;
+
+/// Returns the distribution of the outcomes `states`, observed `counts` times each.
+pub fn from_counts(states: &[u64], counts: &[u64]) -> Distribution {
+    let total: u64 = counts.iter().sum();
+    let mut distribution = Distribution::new();
+    if total == 0 {
+        return distribution;
+    }
+    for (state, count) in states.iter().zip(counts) {
+        if *count > 0 {
+            *distribution.entry(*state).or_default() += *count as f64 / total as f64;
+        }
+    }
+    distribution
+}
+
+/// Returns `½ Σ |p(x) - q(x)|`.
+pub fn total_variation_distance(p: &Distribution, q: &Distribution) -> f64 {
+    let only_p: f64 = p
+        .iter()
+        .filter(|(state, _)| !q.contains_key(state))
+        .map(|(_, probability)| probability)
+        .sum();
+    let rest: f64 = q
+        .iter()
+        .map(|(state, probability)| (p.get(state).unwrap_or(&0.0) - probability).abs())
+        .sum();
+    (only_p + rest) / 2.0
+}
+
+/// Returns `(Σ sqrt(p(x) q(x)))²`, the square of the Bhattacharyya coefficient.
+pub fn hellinger_fidelity(p: &Distribution, q: &Distribution) -> f64 {
+    p.iter()
+        .filter_map(|(state, probability)| Some((probability * q.get(state)?).sqrt()))
+        .sum::<f64>()
+        .powi(2)
+}
+
+/// Returns the linear cross-entropy benchmarking score `2^n Σ q(x) p(x) - 1` of the
+/// sampled distribution `sampled` against the ideal distribution `ideal` of
+/// `num_qubits` qubits.
+///
+/// The score is the mean ideal probability of the sampled outcomes, scaled so that
+/// uniformly random outcomes score zero. A noiseless circuit scores `2^n Σ p(x)² - 1`,
+/// which is one for the Porter-Thomas distribution of a random circuit.
+pub fn linear_xeb(sampled: &Distribution, ideal: &Distribution, num_qubits: usize) -> f64 {
+    let mean: f64 = sampled
+        .iter()
+        .filter_map(|(state, probability)| Some(probability * ideal.get(state)?))
+        .sum();
+    2f64.powi(num_qubits as i32) * mean - 1.0
+}
+
+impl Sample {
+    /// Returns the distribution of the sampled outcomes.
+    ///
+    /// Returns `None` if the result is not yet available.
+    pub fn distribution(&self) -> Option<Distribution> {
+        let (states, counts) = self.result.as_ref()?;
+        Some(from_counts(states, counts))
+    }
+}
+
+impl DumpData {
+    /// Returns the probability of each basis state.
+    ///
+    /// Returns `None` if a basis state does not fit in 64 bits.
+    pub fn probabilities(&self) -> Option<Distribution> {
+        let mut distribution = Distribution::new();
+        for ((state, real), imag) in self
+            .basis_states
+            .iter()
+            .zip(&self.amplitudes_real)
+            .zip(&self.amplitudes_imag)
+        {
+            let probability = real * real + imag * imag;
+            if probability > 0.0 {
+                distribution.insert(basis::to_u64(state)?, probability);
+            }
+        }
+        Some(distribution)
+    }
+
+    /// Returns the `k` basis states of largest probability, from the most to the least
+    /// probable.
+    ///
+    /// The states are chosen with a partial selection, so the cost is linear in the size
+    /// of the dump plus `k log k` to sort the kept states.
+    pub fn top_k(&self, k: usize) -> DumpData {
+        let probability = |index: usize| {
+            self.amplitudes_real[index].powi(2) + self.amplitudes_imag[index].powi(2)
+        };
+        let descending = |a: &usize, b: &usize| probability(*b).total_cmp(&probability(*a));
+
+        let mut indices: Vec<usize> = (0..self.basis_states.len()).collect();
+        if k < indices.len() {
+            indices.select_nth_unstable_by(k, descending);
+            indices.truncate(k);
+        }
+        indices.sort_unstable_by(descending);
+
+        DumpData {
+            basis_states: indices
+                .iter()
+                .map(|index| self.basis_states[*index].clone())
+                .collect(),
+            amplitudes_real: indices
+                .iter()
+                .map(|index| self.amplitudes_real[*index])
+                .collect(),
+            amplitudes_imag: indices
+                .iter()
+                .map(|index| self.amplitudes_imag[*index])
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_counts, hellinger_fidelity, linear_xeb, total_variation_distance};
+    use crate::DumpData;
+
+    #[test]
+    fn distances() {
+        let p = from_counts(&[0, 1], &[1, 1]);
+        let q = from_counts(&[1, 2, 1], &[1, 2, 1]);
+        assert_eq!(q.len(), 2);
+        assert!((total_variation_distance(&p, &p)).abs() < 1e-12);
+        assert!((total_variation_distance(&p, &q) - 0.5).abs() < 1e-12);
+        assert!((total_variation_distance(&q, &p) - 0.5).abs() < 1e-12);
+        assert!((hellinger_fidelity(&p, &p) - 1.0).abs() < 1e-12);
+        assert!((hellinger_fidelity(&p, &q) - 0.25).abs() < 1e-12);
+        assert_eq!(hellinger_fidelity(&p, &from_counts(&[3], &[5])), 0.0);
+        assert!(from_counts(&[1], &[0]).is_empty());
+
+        // The Bell state scores one, and the uniform distribution zero.
+        let bell = DumpData {
+            basis_states: vec![vec![0], vec![3]],
+            amplitudes_real: vec![0.5f64.sqrt(), 0.0],
+            amplitudes_imag: vec![0.0, -(0.5f64.sqrt())],
+        }
+        .probabilities()
+        .unwrap();
+        assert!((linear_xeb(&bell, &bell, 2) - 1.0).abs() < 1e-12);
+        let uniform = from_counts(&[0, 1, 2, 3], &[1; 4]);
+        assert!(linear_xeb(&uniform, &bell, 2).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Declarative circuit syntax.
+//!
+//! The [`ket!`](crate::ket!) macro expands each statement to the [`Process`](crate::Process)
+//! call that it names, in order, propagating errors with `?`, so it must be used in a
+//! function that returns a [`Result`] whose error is built from a
+//! [`KetError`](crate::error::KetError). The variables it binds are visible after the
+//! macro.
+//!
+//! | Statement                          | Expansion                                       |
+//! |------------------------------------|-------------------------------------------------|
+//! | `alloc a, b;`                      | `let a = process.allocate_qubit()?;` for each   |
+//! | `free a;`                          | `process.free_qubit(a)?;`                       |
+//! | `x q;`, `y q;`, `z q;`, `h q;`     | `process.apply_gate(gate, q)?;`                 |
+//! | `rx(θ) q;`, `ry(θ) q;`, `rz(θ) q;` | `process.apply_gate(gate(Angle::Scalar(θ)), q)?;` |
+//! | `p(λ) q;`                          | `process.apply_gate(Phase(Angle::Scalar(λ)), q)?;` |
+//! | `ctrl a { ... };`                  | `ctrl_push(&[a])`, the statements, `ctrl_pop()` |
+//! | `ctrl [a, b] { ... };`             | the same, with the qubits `a` and `b`           |
+//! | `adj { ... };`                     | `adj_begin()`, the statements, `adj_end()`      |
+//! | `measure a -> m;`                  | `let m = process.measure(&[a])?;`               |
+//! | `measure [a, b] -> m;`             | `let m = process.measure(&[a, b])?;`            |
+//!
+//! A qubit of a gate is any expression, while the qubits of `ctrl` and `measure` are
+//! identifiers or bracketed lists of expressions.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::{ket, Configuration, Process};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut process = Process::new(Configuration::new(2));
+//! ket! { process =>
+//!     alloc q0, q1;
+//!     h q0;
+//!     ctrl q0 { x q1; };
+//!     measure [q0, q1] -> m0;
+//! }
+//! assert_eq!(process.get_measurement(m0).qubits, [q0, q1]);
+//! # Ok(())
+//! # }
+//! ```
+
+/// Writes a circuit as a sequence of statements, see the [`dsl`](crate::dsl) module.
+#[macro_export]
+macro_rules! ket {
+    ($process:ident => $($body:tt)*) => {
+        $crate::ket!(@stmts $process; $($body)*);
+    };
+
+    (@stmts $process:ident;) => {};
+    (@stmts $process:ident; alloc $($qubit:ident),+; $($rest:tt)*) => {
+        $(let $qubit = $process.allocate_qubit()?;)+
+        $crate::ket!(@stmts $process; $($rest)*);
+    };
+    (@stmts $process:ident; free $($qubit:expr),+; $($rest:tt)*) => {
+        $($process.free_qubit($qubit)?;)+
+        $crate::ket!(@stmts $process; $($rest)*);
+    };
+    (@stmts $process:ident; ctrl $control:ident { $($body:tt)* }; $($rest:tt)*) => {
+        $crate::ket!(@stmts $process; ctrl [$control] { $($body)* }; $($rest)*);
+    };
+    (@stmts $process:ident; ctrl [$($control:expr),+ $(,)?] { $($body:tt)* }; $($rest:tt)*) => {
+        $process.ctrl_push(&[$($control),+])?;
+        $crate::ket!(@stmts $process; $($body)*);
+        $process.ctrl_pop()?;
+        $crate::ket!(@stmts $process; $($rest)*);
+    };
+    (@stmts $process:ident; adj { $($body:tt)* }; $($rest:tt)*) => {
+        $process.adj_begin()?;
+        $crate::ket!(@stmts $process; $($body)*);
+        $process.adj_end()?;
+        $crate::ket!(@stmts $process; $($rest)*);
+    };
+    (@stmts $process:ident; measure $qubit:ident -> $result:ident; $($rest:tt)*) => {
+        $crate::ket!(@stmts $process; measure [$qubit] -> $result; $($rest)*);
+    };
+    (@stmts $process:ident; measure [$($qubit:expr),+ $(,)?] -> $result:ident; $($rest:tt)*) => {
+        let $result = $process.measure(&[$($qubit),+])?;
+        $crate::ket!(@stmts $process; $($rest)*);
+    };
+    (@stmts $process:ident; x $target:expr; $($rest:tt)*) => {
+        $crate::ket!(@gate $process; $crate::QuantumGate::PauliX, $target; $($rest)*);
+    };
+    (@stmts $process:ident; y $target:expr; $($rest:tt)*) => {
+        $crate::ket!(@gate $process; $crate::QuantumGate::PauliY, $target; $($rest)*);
+    };
+    (@stmts $process:ident; z $target:expr; $($rest:tt)*) => {
+        $crate::ket!(@gate $process; $crate::QuantumGate::PauliZ, $target; $($rest)*);
+    };
+    (@stmts $process:ident; h $target:expr; $($rest:tt)*) => {
+        $crate::ket!(@gate $process; $crate::QuantumGate::Hadamard, $target; $($rest)*);
+    };
+    (@stmts $process:ident; rx($angle:expr) $target:expr; $($rest:tt)*) => {
+        $crate::ket!(@gate $process; $crate::QuantumGate::RotationX($crate::Angle::Scalar($angle)), $target; $($rest)*);
+    };
+    (@stmts $process:ident; ry($angle:expr) $target:expr; $($rest:tt)*) => {
+        $crate::ket!(@gate $process; $crate::QuantumGate::RotationY($crate::Angle::Scalar($angle)), $target; $($rest)*);
+    };
+    (@stmts $process:ident; rz($angle:expr) $target:expr; $($rest:tt)*) => {
+        $crate::ket!(@gate $process; $crate::QuantumGate::RotationZ($crate::Angle::Scalar($angle)), $target; $($rest)*);
+    };
+    (@stmts $process:ident; p($angle:expr) $target:expr; $($rest:tt)*) => {
+        $crate::ket!(@gate $process; $crate::QuantumGate::Phase($crate::Angle::Scalar($angle)), $target; $($rest)*);
+    };
+
+    (@gate $process:ident; $gate:expr, $target:expr; $($rest:tt)*) => {
+        $process.apply_gate($gate, $target)?;
+        $crate::ket!(@stmts $process; $($rest)*);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::KetError, Angle, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn same_instructions() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        ket! { process =>
+            alloc a, b, c;
+            h a;
+            rz(0.5) b;
+            ctrl [a, b] { x c; p(0.25) c; };
+            adj { ry(0.125) a; ctrl a { y b; }; };
+            z c;
+            measure a -> m0;
+            measure [b, c] -> m1;
+            free c;
+        }
+
+        let mut expected = Process::new(Configuration::new(3));
+        let (a, b, c) = (
+            expected.allocate_qubit()?,
+            expected.allocate_qubit()?,
+            expected.allocate_qubit()?,
+        );
+        expected.apply_gate(QuantumGate::Hadamard, a)?;
+        expected.apply_gate(QuantumGate::RotationZ(Angle::Scalar(0.5)), b)?;
+        expected.ctrl_push(&[a, b])?;
+        expected.apply_gate(QuantumGate::PauliX, c)?;
+        expected.apply_gate(QuantumGate::Phase(Angle::Scalar(0.25)), c)?;
+        expected.ctrl_pop()?;
+        expected.adj_begin()?;
+        expected.apply_gate(QuantumGate::RotationY(Angle::Scalar(0.125)), a)?;
+        expected.ctrl_push(&[a])?;
+        expected.apply_gate(QuantumGate::PauliY, b)?;
+        expected.ctrl_pop()?;
+        expected.adj_end()?;
+        expected.apply_gate(QuantumGate::PauliZ, c)?;
+        assert_eq!(m0, expected.measure(&[a])?);
+        assert_eq!(m1, expected.measure(&[b, c])?);
+        expected.free_qubit(c)?;
+
+        assert_eq!(
+            serde_json::to_string(process.instructions()).unwrap(),
+            serde_json::to_string(expected.instructions()).unwrap()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encoding of combinatorial optimization problems as Ising Hamiltonians.
+//!
+//! A problem on `n` binary variables is encoded in `n` qubits, with the variable `x_i`
+//! as the computational basis state of qubit `i` and the spin `z_i = 1 - 2 x_i` as the
+//! eigenvalue of `Z_i`. The energy of a basis state, its eigenvalue in the Hamiltonian,
+//! is the objective of the problem to be minimized, so the ground state is an optimal
+//! solution, as expected by QAOA and annealing.
+//!
+//! Sampled or measured outcomes are decoded with the same bit order as
+//! [`Process::measure`](crate::Process::measure): the first qubit is the most
+//! significant bit of the outcome.
+//!
+//! ```
+//! # use ket::encoders::IsingModel;
+//! # fn main() -> Result<(), ket::error::KetError> {
+//! // Triangle with a heavy edge: the best cut separates 0 and 1.
+//! let model = IsingModel::max_cut(3, &[(0, 1, 2.0), (1, 2, 1.0), (0, 2, 1.0)])?;
+//! let solutions = model.decode_samples(&[0b011, 0b001, 0b000], &[5, 3, 2])?;
+//! assert_eq!(solutions[0].bits, [false, true, true]);
+//! assert_eq!(solutions[0].objective, 3.0);
+//! assert_eq!(solutions[2].objective, 0.0);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::{
+    error::{KetError, Result},
+    ir::{Pauli, PauliHamiltonian, PauliTerm},
+};
+
+/// Largest number of variables of a decoded outcome.
+const MAX_DECODED_VARIABLES: usize = 64;
+
+/// Ising model `offset + sum_i h_i z_i + sum_i<j J_ij z_i z_j` on spins `z_i = ±1`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IsingModel {
+    /// Number of variables.
+    pub num_variables: usize,
+
+    /// Constant energy.
+    pub offset: f64,
+
+    /// Coefficient `h_i` of each spin.
+    pub linear: Vec<f64>,
+
+    /// Coefficient `J_ij` of each pair of spins, with `i < j`.
+    pub quadratic: BTreeMap<(usize, usize), f64>,
+
+    /// Set if the problem maximizes the objective, which is then the negated energy.
+    pub maximize: bool,
+}
+
+/// Solution decoded from a measured outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    /// Value of each variable.
+    pub bits: Vec<bool>,
+
+    /// Energy of the solution in the Ising model.
+    pub energy: f64,
+
+    /// Objective of the solution in the original problem.
+    pub objective: f64,
+
+    /// Number of times the outcome was observed.
+    pub count: u64,
+}
+
+impl IsingModel {
+    fn new(num_variables: usize) -> Self {
+        Self {
+            num_variables,
+            linear: vec![0.0; num_variables],
+            ..Default::default()
+        }
+    }
+
+    fn add_quadratic(&mut self, i: usize, j: usize, coefficient: f64) {
+        let key = (i.min(j), i.max(j));
+        *self.quadratic.entry(key).or_default() += coefficient;
+    }
+
+    /// Encodes the QUBO problem of minimizing `x^T Q x` over `x` in `{0, 1}^n`.
+    ///
+    /// Both triangles of `Q` are used, so an upper triangular or a symmetric matrix
+    /// describes the same problem as long as the sum `Q_ij + Q_ji` is the same.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidProblemEncoding`] if `matrix` is not square or has a
+    /// non-finite entry.
+    pub fn from_qubo(matrix: &[Vec<f64>]) -> Result<Self> {
+        let n = matrix.len();
+        if matrix
+            .iter()
+            .any(|row| row.len() != n || row.iter().any(|x| !x.is_finite()))
+        {
+            return Err(KetError::InvalidProblemEncoding);
+        }
+
+        let mut model = Self::new(n);
+        for (i, row) in matrix.iter().enumerate() {
+            for (j, q) in row.iter().enumerate() {
+                if i == j {
+                    // x_i^2 = x_i = (1 - z_i) / 2
+                    model.offset += q / 2.0;
+                    model.linear[i] -= q / 2.0;
+                } else {
+                    // x_i x_j = (1 - z_i - z_j + z_i z_j) / 4
+                    model.offset += q / 4.0;
+                    model.linear[i] -= q / 4.0;
+                    model.linear[j] -= q / 4.0;
+                    model.add_quadratic(i, j, q / 4.0);
+                }
+            }
+        }
+        Ok(model)
+    }
+
+    /// Encodes the weighted MaxCut problem of a graph with `num_nodes` nodes and the
+    /// edges `(i, j, weight)`.
+    ///
+    /// The objective is the total weight of the edges between the nodes with `x_i = 0`
+    /// and `x_i = 1`, and the energy is the negated cut.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidProblemEncoding`] if an edge is a loop, refers to a
+    /// node out of range, or has a non-finite weight.
+    pub fn max_cut(num_nodes: usize, edges: &[(usize, usize, f64)]) -> Result<Self> {
+        let mut model = Self::new(num_nodes);
+        model.maximize = true;
+        for (i, j, weight) in edges {
+            if i == j || *i >= num_nodes || *j >= num_nodes || !weight.is_finite() {
+                return Err(KetError::InvalidProblemEncoding);
+            }
+            // -w (x_i XOR x_j) = -w (1 - z_i z_j) / 2
+            model.offset -= weight / 2.0;
+            model.add_quadratic(*i, *j, weight / 2.0);
+        }
+        Ok(model)
+    }
+
+    /// Returns the Hamiltonian of the model, with variable `i` on `qubits[i]`.
+    ///
+    /// The offset is the coefficient of the identity, an empty Pauli product, and the
+    /// terms with a zero coefficient are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidProblemEncoding`] if there is not one qubit per
+    /// variable.
+    pub fn to_hamiltonian(&self, qubits: &[usize]) -> Result<PauliHamiltonian> {
+        if qubits.len() != self.num_variables {
+            return Err(KetError::InvalidProblemEncoding);
+        }
+        let z = |i: usize| PauliTerm {
+            pauli: Pauli::PauliZ,
+            qubit: qubits[i],
+        };
+
+        let terms = [(Vec::new(), self.offset)]
+            .into_iter()
+            .chain(
+                self.linear
+                    .iter()
+                    .enumerate()
+                    .map(|(i, h)| (vec![z(i)], *h)),
+            )
+            .chain(
+                self.quadratic
+                    .iter()
+                    .map(|((i, j), coefficient)| (vec![z(*i), z(*j)], *coefficient)),
+            )
+            .filter(|(_, coefficient)| *coefficient != 0.0);
+
+        let mut hamiltonian = PauliHamiltonian::default();
+        for (product, coefficient) in terms {
+            hamiltonian.products.push(product);
+            hamiltonian.coefficients.push(coefficient);
+        }
+        Ok(hamiltonian)
+    }
+
+    /// Energy of an assignment of the variables.
+    pub fn energy(&self, bits: &[bool]) -> f64 {
+        let spin = |i: usize| if bits[i] { -1.0 } else { 1.0 };
+        self.offset
+            + self
+                .linear
+                .iter()
+                .enumerate()
+                .map(|(i, h)| h * spin(i))
+                .sum::<f64>()
+            + self
+                .quadratic
+                .iter()
+                .map(|((i, j), coefficient)| coefficient * spin(*i) * spin(*j))
+                .sum::<f64>()
+    }
+
+    /// Objective of an assignment of the variables in the original problem.
+    pub fn objective(&self, bits: &[bool]) -> f64 {
+        let energy = self.energy(bits);
+        if self.maximize {
+            -energy
+        } else {
+            energy
+        }
+    }
+
+    /// Returns the value of each variable in a measured outcome.
+    ///
+    /// The first variable is the most significant bit, as in the default
+    /// [`BitOrder`](crate::BitOrder).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidProblemEncoding`] if the model has more than 64
+    /// variables.
+    pub fn decode(&self, outcome: u64) -> Result<Vec<bool>> {
+        let n = self.num_variables;
+        if n > MAX_DECODED_VARIABLES {
+            return Err(KetError::InvalidProblemEncoding);
+        }
+        Ok((0..n).map(|i| (outcome >> (n - 1 - i)) & 1 == 1).collect())
+    }
+
+    /// Decodes the outcomes of a sample, as returned by
+    /// [`Process::sample`](crate::Process::sample), into solutions ordered from the
+    /// best to the worst objective.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidProblemEncoding`] if the model has more than 64
+    /// variables or if `states` and `counts` have different lengths.
+    pub fn decode_samples(&self, states: &[u64], counts: &[u64]) -> Result<Vec<Solution>> {
+        if states.len() != counts.len() {
+            return Err(KetError::InvalidProblemEncoding);
+        }
+
+        let mut solutions = states
+            .iter()
+            .zip(counts)
+            .map(|(state, count)| {
+                let bits = self.decode(*state)?;
+                Ok(Solution {
+                    energy: self.energy(&bits),
+                    objective: self.objective(&bits),
+                    bits,
+                    count: *count,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        solutions.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+        Ok(solutions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IsingModel;
+
+    fn assignments(n: usize) -> impl Iterator<Item = Vec<bool>> {
+        (0..1u64 << n).map(move |x| (0..n).map(|i| (x >> i) & 1 == 1).collect())
+    }
+
+    #[test]
+    fn qubo_energy_matches_objective() {
+        let matrix = vec![
+            vec![-1.0, 2.0, 0.0],
+            vec![0.0, -1.0, 0.5],
+            vec![1.5, 0.0, 3.0],
+        ];
+        let model = IsingModel::from_qubo(&matrix).unwrap();
+        for bits in assignments(3) {
+            let x: Vec<f64> = bits.iter().map(|b| *b as u8 as f64).collect();
+            let qubo: f64 = (0..3)
+                .flat_map(|i| (0..3).map(move |j| (i, j)))
+                .map(|(i, j)| x[i] * matrix[i][j] * x[j])
+                .sum();
+            assert!((model.energy(&bits) - qubo).abs() < 1e-12);
+        }
+
+        let hamiltonian = model.to_hamiltonian(&[4, 5, 6]).unwrap();
+        assert!(hamiltonian.products[0].is_empty());
+        assert_eq!(hamiltonian.len(), 1 + 3 + 3);
+        assert!(IsingModel::from_qubo(&[vec![1.0, 2.0]]).is_err());
+    }
+
+    #[test]
+    fn max_cut_decoding() {
+        let edges = [(0, 1, 1.0), (1, 2, 1.0), (2, 3, 1.0), (3, 0, 1.0)];
+        let model = IsingModel::max_cut(4, &edges).unwrap();
+        for bits in assignments(4) {
+            let cut: f64 = edges
+                .iter()
+                .filter(|(i, j, _)| bits[*i] != bits[*j])
+                .map(|(_, _, w)| w)
+                .sum();
+            assert_eq!(model.objective(&bits), cut);
+        }
+
+        let solutions = model.decode_samples(&[0b0000, 0b0101], &[7, 3]).unwrap();
+        assert_eq!(solutions[0].bits, [false, true, false, true]);
+        assert_eq!(solutions[0].objective, 4.0);
+        assert_eq!(solutions[0].count, 3);
+        assert_eq!(solutions[1].energy, 0.0);
+
+        assert!(IsingModel::max_cut(2, &[(0, 2, 1.0)]).is_err());
+        assert!(model.to_hamiltonian(&[0, 1]).is_err());
+    }
+}
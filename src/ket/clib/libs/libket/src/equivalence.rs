@@ -0,0 +1,393 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Equivalence of circuits up to a relabeling of the qubits.
+//!
+//! A circuit placed on other qubits, as by an initial layout before routing, has the
+//! same instructions with other qubit indices. [`find_relabeling`] searches a map from
+//! the qubits of one circuit to those of the other under which both are equivalent.
+//!
+//! The candidates come from the interaction structure of the circuits. Each qubit is
+//! colored by the instructions that act on it and by its role in them, and the colors
+//! are refined with the colors of the qubits it interacts with, until they are stable,
+//! as in the Weisfeiler-Lehman graph canonization. Only qubits of the same color are
+//! matched, and a partial map is dropped as soon as it does not keep the number of
+//! instructions between two qubits. Each complete map is confirmed if the relabeled
+//! circuit is equal to the other in the [canonical order](crate::passes::Canonicalize)
+//! of commuting gates, or, for gates on at most
+//! [`MAX_CHECKED_QUBITS`](crate::passes::canonicalize::MAX_CHECKED_QUBITS) qubits, if
+//! both have the same action on a state.
+//!
+//! Allocations and frees are not compared, so the unused qubits of a routed circuit are
+//! ignored.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::{Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut a = Process::new(Configuration::new(3));
+//! let mut b = Process::new(Configuration::new(3));
+//! for process in [&mut a, &mut b] {
+//!     for _ in 0..3 {
+//!         process.allocate_qubit()?;
+//!     }
+//! }
+//!
+//! a.apply_gate(QuantumGate::Hadamard, 0)?;
+//! a.ctrl_push(&[0])?;
+//! a.apply_gate(QuantumGate::PauliX, 1)?;
+//! a.ctrl_pop()?;
+//!
+//! b.apply_gate(QuantumGate::Hadamard, 2)?;
+//! b.ctrl_push(&[2])?;
+//! b.apply_gate(QuantumGate::PauliX, 0)?;
+//! b.ctrl_pop()?;
+//!
+//! let relabeling = a.find_relabeling(&b).unwrap();
+//! assert_eq!(relabeling[&0], 2);
+//! assert_eq!(relabeling[&1], 0);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    hashing::structurally_equal,
+    ir::Instruction,
+    passes::{
+        canonicalize::{action, canonicalize},
+        lifetime::remap,
+        peephole::qubits,
+    },
+};
+
+/// Largest number of complete maps confirmed before the search gives up.
+pub const MAX_CANDIDATES: usize = 4096;
+
+/// Interaction structure of a circuit.
+struct Structure {
+    instructions: Vec<Instruction>,
+    /// Qubits, in increasing order.
+    qubits: Vec<usize>,
+    /// Number of instructions on each pair of qubits, by their positions in `qubits`.
+    interactions: HashMap<(usize, usize), usize>,
+}
+
+impl Structure {
+    fn new(instructions: &[Instruction]) -> Self {
+        let instructions: Vec<Instruction> = instructions
+            .iter()
+            .filter(|instruction| {
+                !matches!(
+                    instruction,
+                    Instruction::Alloc { .. } | Instruction::Free { .. }
+                )
+            })
+            .cloned()
+            .collect();
+        let mut qubits: Vec<usize> = instructions.iter().flat_map(qubits).collect();
+        qubits.sort_unstable();
+        qubits.dedup();
+
+        let mut interactions = HashMap::new();
+        for instruction in &instructions {
+            let positions = Self::positions(&qubits, instruction);
+            for a in &positions {
+                for b in positions.iter().filter(|b| *b != a) {
+                    *interactions.entry((*a, *b)).or_default() += 1;
+                }
+            }
+        }
+
+        Self {
+            instructions,
+            qubits,
+            interactions,
+        }
+    }
+
+    fn positions(all: &[usize], instruction: &Instruction) -> Vec<usize> {
+        let mut positions: Vec<usize> = qubits(instruction)
+            .iter()
+            .map(|qubit| all.binary_search(qubit).unwrap())
+            .collect();
+        positions.dedup();
+        positions
+    }
+
+    fn interactions(&self, a: usize, b: usize) -> usize {
+        self.interactions.get(&(a, b)).copied().unwrap_or_default()
+    }
+
+    /// Description of the role of each qubit in each of its instructions, which does
+    /// not depend on the qubit indices.
+    fn roles(&self) -> Vec<Vec<String>> {
+        let blank = vec![0; self.qubits.last().map_or(0, |qubit| qubit + 1)];
+        let mut roles = vec![Vec::new(); self.qubits.len()];
+        for instruction in &self.instructions {
+            let description = serde_json::to_string(&remap(instruction, &blank))
+                .expect("instructions are serializable");
+            for (index, qubit) in qubits(instruction).iter().enumerate() {
+                // The controls of a gate are interchangeable.
+                let role = match instruction {
+                    Instruction::Gate { target, .. } if target == qubit => "target".to_string(),
+                    Instruction::Gate { .. } => "control".to_string(),
+                    _ => index.to_string(),
+                };
+                let position = self.qubits.binary_search(qubit).unwrap();
+                roles[position].push(format!("{role}:{description}"));
+            }
+        }
+        for roles in &mut roles {
+            roles.sort();
+        }
+        roles
+    }
+}
+
+/// Returns the colors of the qubits of both circuits, refined until they are stable.
+/// Qubits of the same color have the same roles and interact in the same way with
+/// qubits of each color.
+fn colors(a: &Structure, b: &Structure) -> [Vec<usize>; 2] {
+    let mut ids = BTreeMap::new();
+    let mut colors = [a, b].map(|structure| {
+        structure
+            .roles()
+            .into_iter()
+            .map(|roles| {
+                let id = ids.len();
+                *ids.entry(roles).or_insert(id)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let classes = |colors: &[Vec<usize>; 2]| {
+        let mut classes: Vec<usize> = colors.concat();
+        classes.sort_unstable();
+        classes.dedup();
+        classes.len()
+    };
+    loop {
+        let mut ids = BTreeMap::new();
+        let refined = [0, 1].map(|index| {
+            let (structure, colors) = ([a, b][index], &colors[index]);
+            (0..structure.qubits.len())
+                .map(|qubit| {
+                    let mut neighbors: Vec<(usize, usize)> = (0..structure.qubits.len())
+                        .map(|other| (colors[other], structure.interactions(qubit, other)))
+                        .filter(|(_, interactions)| *interactions > 0)
+                        .collect();
+                    neighbors.sort_unstable();
+                    let id = ids.len();
+                    *ids.entry((colors[qubit], neighbors)).or_insert(id)
+                })
+                .collect::<Vec<_>>()
+        });
+        if classes(&refined) == classes(&colors) {
+            return refined;
+        }
+        colors = refined;
+    }
+}
+
+/// Search of the maps from the qubits of `a` to those of `b`.
+struct Search<'a> {
+    a: &'a Structure,
+    b: &'a Structure,
+    colors: [Vec<usize>; 2],
+    /// Qubits of `a` in the order they are matched.
+    order: Vec<usize>,
+    /// Qubit of `b` of each qubit of `a`.
+    matched: Vec<Option<usize>>,
+    used: Vec<bool>,
+    candidates: usize,
+}
+
+impl Search<'_> {
+    fn run(&mut self, depth: usize) -> bool {
+        if depth == self.order.len() {
+            self.candidates += 1;
+            return self.confirm();
+        }
+        let qubit = self.order[depth];
+        for other in 0..self.b.qubits.len() {
+            if self.candidates >= MAX_CANDIDATES {
+                return false;
+            }
+            if self.used[other] || self.colors[1][other] != self.colors[0][qubit] {
+                continue;
+            }
+            let consistent = self.order[..depth].iter().all(|matched| {
+                let image = self.matched[*matched].unwrap();
+                self.a.interactions(qubit, *matched) == self.b.interactions(other, image)
+            });
+            if !consistent {
+                continue;
+            }
+            self.matched[qubit] = Some(other);
+            self.used[other] = true;
+            if self.run(depth + 1) {
+                return true;
+            }
+            self.matched[qubit] = None;
+            self.used[other] = false;
+        }
+        false
+    }
+
+    fn confirm(&self) -> bool {
+        let mut slot_of = vec![0; self.a.qubits.last().map_or(0, |qubit| qubit + 1)];
+        for (position, qubit) in self.a.qubits.iter().enumerate() {
+            slot_of[*qubit] = self.b.qubits[self.matched[position].unwrap()];
+        }
+        let relabeled: Vec<Instruction> = self
+            .a
+            .instructions
+            .iter()
+            .map(|instruction| remap(instruction, &slot_of))
+            .collect();
+
+        if structurally_equal(
+            &canonicalize(&relabeled),
+            &canonicalize(&self.b.instructions),
+        ) {
+            return true;
+        }
+        match (
+            action(&relabeled, &self.b.qubits),
+            action(&self.b.instructions, &self.b.qubits),
+        ) {
+            (Some(a), Some(b)) => a.iter().zip(b).all(|(a, b)| (a - b).norm() < 1e-9),
+            _ => false,
+        }
+    }
+}
+
+/// Returns a map from the qubits of `a` to the qubits of `b` under which both
+/// instruction lists are equivalent, or `None` if no map is found, see
+/// [`equivalence`](self).
+///
+/// The search stops after [`MAX_CANDIDATES`] maps, which only happens for circuits
+/// with many qubits that cannot be told apart by their interactions.
+pub fn find_relabeling(a: &[Instruction], b: &[Instruction]) -> Option<BTreeMap<usize, usize>> {
+    let (a, b) = (Structure::new(a), Structure::new(b));
+    if a.instructions.len() != b.instructions.len() || a.qubits.len() != b.qubits.len() {
+        return None;
+    }
+
+    let colors = colors(&a, &b);
+    let mut histograms = colors.clone();
+    for histogram in &mut histograms {
+        histogram.sort_unstable();
+    }
+    if histograms[0] != histograms[1] {
+        return None;
+    }
+
+    // The qubits of the rarest colors have the fewest candidates.
+    let mut order: Vec<usize> = (0..a.qubits.len()).collect();
+    order.sort_by_key(|qubit| {
+        let color = colors[0][*qubit];
+        (
+            colors[0].iter().filter(|other| **other == color).count(),
+            *qubit,
+        )
+    });
+
+    let mut search = Search {
+        a: &a,
+        b: &b,
+        colors,
+        order,
+        matched: vec![None; a.qubits.len()],
+        used: vec![false; b.qubits.len()],
+        candidates: 0,
+    };
+    if !search.run(0) {
+        return None;
+    }
+    Some(
+        a.qubits
+            .iter()
+            .zip(&search.matched)
+            .map(|(qubit, matched)| (*qubit, b.qubits[matched.unwrap()]))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_relabeling;
+    use crate::{error::KetError, Angle, Configuration, Instruction, Process, QuantumGate};
+
+    fn gate(gate: QuantumGate, target: usize, control: &[usize]) -> Instruction {
+        Instruction::Gate {
+            gate,
+            target,
+            control: control.to_vec(),
+        }
+    }
+
+    #[test]
+    fn relabeled_circuits() -> Result<(), KetError> {
+        let rz = |angle| QuantumGate::RotationZ(Angle::Scalar(angle));
+        let a = [
+            gate(QuantumGate::Hadamard, 0, &[]),
+            gate(QuantumGate::PauliX, 1, &[0]),
+            gate(QuantumGate::PauliX, 2, &[1]),
+            gate(rz(0.5), 2, &[]),
+            gate(rz(0.25), 3, &[]),
+            gate(QuantumGate::PauliZ, 3, &[0]),
+        ];
+        // Qubits 0, 1, 2, 3 of `a` are 3, 0, 2, 1, with commuting gates swapped.
+        let b = [
+            gate(rz(0.25), 1, &[]),
+            gate(QuantumGate::Hadamard, 3, &[]),
+            gate(QuantumGate::PauliX, 0, &[3]),
+            gate(QuantumGate::PauliZ, 1, &[3]),
+            gate(QuantumGate::PauliX, 2, &[0]),
+            gate(rz(0.5), 2, &[]),
+        ];
+        let relabeling = find_relabeling(&a, &b).unwrap();
+        assert_eq!(
+            relabeling.into_iter().collect::<Vec<_>>(),
+            [(0, 3), (1, 0), (2, 2), (3, 1)]
+        );
+
+        // A different angle has no relabeling.
+        let mut c = b.clone();
+        c[5] = gate(rz(0.25), 2, &[]);
+        assert!(find_relabeling(&a, &c).is_none());
+
+        // The qubits that are only allocated are ignored.
+        let mut process = Process::new(Configuration::new(5));
+        for _ in 0..5 {
+            process.allocate_qubit()?;
+        }
+        for instruction in &a {
+            let Instruction::Gate {
+                gate,
+                target,
+                control,
+            } = instruction
+            else {
+                unreachable!()
+            };
+            process.ctrl_push(control)?;
+            process.apply_gate(gate.clone(), *target)?;
+            process.ctrl_pop()?;
+        }
+        let other = Process::new(Configuration::new(4));
+        assert!(process.find_relabeling(&other).is_none());
+        assert_eq!(
+            find_relabeling(process.instructions(), &b),
+            find_relabeling(&a, &b)
+        );
+
+        Ok(())
+    }
+}
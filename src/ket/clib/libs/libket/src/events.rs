@@ -0,0 +1,176 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Events emitted during live execution.
+//!
+//! A front end calls [`Process::subscribe`](crate::Process::subscribe) to receive every
+//! instruction as soon as the live executor runs it, so it can animate the circuit
+//! while the program is still executing. Receivers that are dropped are unsubscribed on
+//! the next event.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ir::QuantumGate;
+
+/// Event emitted by a live execution.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExecutionEvent {
+    /// A qubit was allocated.
+    Allocated {
+        /// Index of the qubit.
+        qubit: usize,
+    },
+
+    /// A qubit was freed.
+    Freed {
+        /// Index of the qubit.
+        qubit: usize,
+    },
+
+    /// A gate was applied.
+    GateApplied {
+        /// Applied gate, already inverted if it was in an inverse scope.
+        gate: QuantumGate,
+        /// Target qubit.
+        target: usize,
+        /// Control qubits.
+        control: Vec<usize>,
+    },
+
+    /// Qubits were measured.
+    Measured {
+        /// Index of the measurement in the process.
+        index: usize,
+        /// Measured qubits.
+        qubits: Vec<usize>,
+        /// Measurement result.
+        result: u64,
+    },
+
+    /// An expected value was calculated.
+    ExpValueCalculated {
+        /// Index of the expected value in the process.
+        index: usize,
+        /// Expected value.
+        result: f64,
+    },
+
+    /// Qubits were sampled.
+    Sampled {
+        /// Index of the sample in the process.
+        index: usize,
+    },
+
+    /// Qubits were dumped.
+    Dumped {
+        /// Index of the dump in the process, see
+        /// [`Process::get_dump_data`](crate::Process::get_dump_data).
+        index: usize,
+    },
+}
+
+/// Senders of the subscribed receivers.
+#[derive(Debug, Default)]
+pub(crate) struct Subscribers {
+    senders: Vec<Sender<ExecutionEvent>>,
+}
+
+impl Subscribers {
+    /// Returns a receiver for the next events.
+    pub(crate) fn subscribe(&mut self) -> Receiver<ExecutionEvent> {
+        let (sender, receiver) = channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    /// Sends the event to every receiver, dropping the disconnected ones.
+    ///
+    /// The event is only built if there is a receiver.
+    pub(crate) fn emit(&mut self, event: impl FnOnce() -> ExecutionEvent) {
+        if self.senders.is_empty() {
+            return;
+        }
+        let event = event();
+        self.senders
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExecutionEvent;
+    use crate::{
+        ir::{DumpData, PauliHamiltonian, QuantumGate},
+        Configuration, LiveExecution, Process,
+    };
+
+    struct AlwaysOne;
+
+    impl LiveExecution for AlwaysOne {
+        fn alloc(&mut self, _: usize) {}
+        fn free(&mut self, _: usize) {}
+        fn gate(&mut self, _: &QuantumGate, _: usize, _: &[usize]) {}
+        fn measure(&mut self, _: &[usize]) -> u64 {
+            1
+        }
+        fn exp_value(&mut self, _: &PauliHamiltonian) -> f64 {
+            0.0
+        }
+        fn sample(&mut self, _: &[usize], _: u64) -> (Vec<u64>, Vec<u64>) {
+            (vec![1], vec![1])
+        }
+        fn dump(&mut self, _: &[usize]) -> DumpData {
+            DumpData {
+                basis_states: vec![vec![1]],
+                amplitudes_real: vec![1.0],
+                amplitudes_imag: vec![0.0],
+            }
+        }
+    }
+
+    #[test]
+    fn live_events() {
+        let mut process = Process::new(Configuration {
+            live_quantum_execution: Some(Box::new(AlwaysOne)),
+            ..Configuration::new(2)
+        });
+        let events = process.subscribe();
+        let dropped = process.subscribe();
+        drop(dropped);
+
+        let qubit = process.allocate_qubit().unwrap();
+        process.adj_begin().unwrap();
+        process
+            .apply_gate(QuantumGate::Phase(crate::Angle::Scalar(0.5)), qubit)
+            .unwrap();
+        process.adj_end().unwrap();
+        process.measure(&[qubit]).unwrap();
+        process.dump(&[qubit]).unwrap();
+
+        let events: Vec<_> = events.try_iter().collect();
+        assert!(matches!(events[0], ExecutionEvent::Allocated { qubit: 0 }));
+        assert!(matches!(
+            &events[1],
+            ExecutionEvent::GateApplied {
+                gate: QuantumGate::Phase(crate::Angle::Scalar(angle)),
+                target: 0,
+                ..
+            } if *angle == -0.5
+        ));
+        assert!(matches!(
+            events[2],
+            ExecutionEvent::Measured {
+                index: 0,
+                result: 1,
+                ..
+            }
+        ));
+        assert!(matches!(events[3], ExecutionEvent::Dumped { index: 0 }));
+        assert_eq!(events.len(), 4);
+        assert_eq!(process.subscribers.senders.len(), 1);
+    }
+}
@@ -5,8 +5,15 @@
 
 //! This module provides traits and structures for configuring quantum execution.
 
+use std::{any::Any, collections::BTreeMap, path::PathBuf};
+
+use num::complex::Complex64;
+
 use crate::{
-    ir::{DumpData, PauliHamiltonian, QuantumGate},
+    ir::{DumpData, KrausOperator, Pauli, PauliHamiltonian, QuantumGate},
+    multiplexor,
+    qasmv2::ImportLimits,
+    zx::cache::OptimizationCache,
     Instruction, ResultData,
 };
 
@@ -24,14 +31,105 @@ pub trait LiveExecution {
     /// Measures the specified qubits.
     fn measure(&mut self, qubits: &[usize]) -> u64;
 
+    /// Measures the specified qubits, collapsing them to `outcome` if it has a nonzero
+    /// probability, see [`Process::force_measurement`](crate::Process::force_measurement).
+    ///
+    /// Executors that cannot force outcomes measure the qubits normally.
+    fn measure_forced(&mut self, qubits: &[usize], outcome: u64) -> u64 {
+        let _ = outcome;
+        self.measure(qubits)
+    }
+
     /// Calculates the expected value for a given Hamiltonian.
     fn exp_value(&mut self, hamiltonian: &PauliHamiltonian) -> f64;
 
     /// Performs qubit sampling.
     fn sample(&mut self, qubits: &[usize], shots: u64) -> (Vec<u64>, Vec<u64>);
 
+    /// Samples the qubits from the current state without changing it, see
+    /// [`Process::sample_without_collapse`](crate::Process::sample_without_collapse).
+    ///
+    /// Returns `None` if the executor cannot leave the state untouched, which is the
+    /// default.
+    fn sample_without_collapse(
+        &mut self,
+        qubits: &[usize],
+        shots: u64,
+    ) -> Option<(Vec<u64>, Vec<u64>)> {
+        let _ = (qubits, shots);
+        None
+    }
+
     /// Dumps the state of the specified qubits.
     fn dump(&mut self, qubits: &[usize]) -> DumpData;
+
+    /// Dumps the `k` basis states of largest probability of the specified qubits, see
+    /// [`Process::dump_top_k`](crate::Process::dump_top_k).
+    ///
+    /// The default dumps the whole state and selects the states afterwards.
+    fn dump_top_k(&mut self, qubits: &[usize], k: usize) -> DumpData {
+        self.dump(qubits).top_k(k)
+    }
+
+    /// Applies a Kraus channel to the target qubit, see
+    /// [`Process::apply_kraus_channel`](crate::Process::apply_kraus_channel).
+    ///
+    /// Returns `false` if the executor does not support channels, which is the default.
+    fn kraus_channel(&mut self, operators: &[KrausOperator], target: usize) -> bool {
+        let _ = (operators, target);
+        false
+    }
+
+    /// Applies a diagonal gate, see
+    /// [`Process::apply_diagonal`](crate::Process::apply_diagonal).
+    ///
+    /// The default implementation applies its CNOTs and rotations, see
+    /// [`multiplexor`](crate::multiplexor).
+    fn diagonal(&mut self, phases: &[f64], qubits: &[usize]) {
+        let (gates, _) = multiplexor::diagonal_gates(phases, qubits);
+        self.apply_gates(&gates);
+    }
+
+    /// Applies a multiplexed rotation, see
+    /// [`Process::apply_multiplexor`](crate::Process::apply_multiplexor).
+    ///
+    /// The default implementation applies its CNOTs and rotations, see
+    /// [`multiplexor`](crate::multiplexor).
+    fn multiplexor(&mut self, axis: Pauli, angles: &[f64], control: &[usize], target: usize) {
+        let gates = multiplexor::multiplexor_gates(axis, angles, control, target);
+        self.apply_gates(&gates);
+    }
+
+    /// Applies the gate instructions, ignoring the others.
+    fn apply_gates(&mut self, instructions: &[Instruction]) {
+        for instruction in instructions {
+            if let Instruction::Gate {
+                gate,
+                target,
+                control,
+            } = instruction
+            {
+                self.gate(gate, *target, control);
+            }
+        }
+    }
+
+    /// Saves the state of the simulation, see
+    /// [`Process::checkpoint`](crate::Process::checkpoint).
+    ///
+    /// Returns `None` if the executor cannot save its state, which is the default.
+    fn save_state(&mut self) -> Option<Box<dyn Any + Send>> {
+        None
+    }
+
+    /// Restores a state returned by [`LiveExecution::save_state`], see
+    /// [`Process::rollback`](crate::Process::rollback).
+    ///
+    /// Returns `false`, leaving the state unchanged, if the state was not saved by this
+    /// kind of executor, which is the default.
+    fn restore_state(&mut self, _state: &(dyn Any + Send)) -> bool {
+        false
+    }
 }
 
 /// Enum representing the status of a quantum execution.
@@ -57,13 +155,55 @@ pub trait BatchExecution {
     /// Submits a set of quantum instructions for execution.
     fn submit_execution(&mut self, instructions: &[Instruction]);
 
+    /// Forces the outcomes of the measurements of the next execution, by measurement
+    /// index, see [`LiveExecution::measure_forced`].
+    ///
+    /// Executors that cannot force outcomes ignore them.
+    fn force_measurements(&mut self, outcomes: &BTreeMap<usize, u64>) {
+        let _ = outcomes;
+    }
+
     /// Retrieves the result of the quantum execution.
     fn get_result(&mut self) -> ResultData;
 
+    /// Executes the next chunk of a streamed execution, see
+    /// [`Configuration::chunk_size`].
+    ///
+    /// The state is kept from the previous chunks, and the output indices of the
+    /// instructions count from the start of the process. Returns the results of the
+    /// outputs in this chunk only, in order, or `None` if the executor does not support
+    /// chunks, which is the default.
+    fn submit_chunk(&mut self, instructions: &[Instruction]) -> Option<ResultData> {
+        let _ = instructions;
+        None
+    }
+
     /// Retrieves the current status of the quantum execution.
     fn get_status(&self) -> ExecutionStatus;
 }
 
+/// Order of the qubits in the bits of measurement results, sample outcomes, and dump
+/// basis states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOrder {
+    /// The first qubit is the most significant bit, so `|q0 q1>` reads left to right.
+    #[default]
+    MsbFirst,
+    /// The first qubit is the least significant bit, as in Qiskit.
+    LsbFirst,
+}
+
+impl BitOrder {
+    /// Returns the qubits from the most to the least significant bit, the order used by
+    /// the executors.
+    pub fn arrange(self, qubits: &[usize]) -> Vec<usize> {
+        match self {
+            Self::MsbFirst => qubits.to_vec(),
+            Self::LsbFirst => qubits.iter().rev().copied().collect(),
+        }
+    }
+}
+
 /// Configuration struct for controlling quantum execution behavior.
 pub struct Configuration {
     /// Flag indicating whether measurement operations are allowed.
@@ -90,7 +230,8 @@ pub struct Configuration {
     /// Flag indicating whether execution continues after quantum state dumps.
     pub continue_after_dump: bool,
 
-    /// Flag indicating whether quantum gates should be decomposed.
+    /// Flag indicating whether quantum gates should be decomposed into X, H, and phase
+    /// gates, see [`QuantumGate::decompose_minimal`].
     pub decompose: bool,
 
     /// Option for live quantum execution, specifying a processor implementing LiveExecution.
@@ -105,10 +246,57 @@ pub struct Configuration {
     /// Optional timeout for quantum execution.
     pub execution_timeout: Option<f64>,
 
-    pub optimize: bool
+    pub optimize: bool,
+
+    /// Directory of previously optimized circuits, used by [`Process::optimize`] to skip
+    /// the ZX-calculus optimization of a circuit already optimized.
+    ///
+    /// [`Process::optimize`]: crate::Process::optimize
+    pub optimization_cache: Option<OptimizationCache>,
+
+    /// Order of the qubits in the results of measurements, samples, and dumps.
+    pub bit_order: BitOrder,
+
+    /// Maximum number of instructions kept by a process in batch mode.
+    ///
+    /// When the limit is reached, the instructions are executed as a chunk and removed
+    /// from the process, so circuits with millions of gates run in bounded memory, and
+    /// the results of their measurements are available before the process ends. See
+    /// [`BatchExecution::submit_chunk`]. If the executor does not support chunks, the
+    /// whole process is executed at once.
+    pub chunk_size: Option<usize>,
+
+    /// Initial state of the first allocated qubits, instead of `|0...0>`.
+    ///
+    /// A vector of `2^n` amplitudes is prepared with a circuit on the first `n` qubits
+    /// when the last of them is allocated, see
+    /// [`GroverRudolph::from_amplitudes`](crate::state_preparation::GroverRudolph::from_amplitudes).
+    /// The index of an
+    /// amplitude follows [`Configuration::bit_order`], see [`npy`](crate::npy).
+    pub initial_state: Option<Vec<Complex64>>,
+
+    /// Limits of the OpenQASM imports, see [`ImportLimits`].
+    pub import_limits: ImportLimits,
 }
 
 impl Configuration {
+    /// Returns a builder for a validated configuration.
+    ///
+    /// ```
+    /// # use ket::Configuration;
+    /// let configuration = Configuration::builder()
+    ///     .qubits(2)
+    ///     .optimize(false)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(configuration.num_qubits, 2);
+    ///
+    /// assert!(Configuration::builder().build().is_err());
+    /// ```
+    pub fn builder() -> ConfigurationBuilder {
+        ConfigurationBuilder::default()
+    }
+
     /// Creates a new Configuration instance with the specified number of qubits.
     ///
     /// This is used for testing purpose only. The quantum executor must provide
@@ -129,6 +317,334 @@ impl Configuration {
             num_qubits,
             execution_timeout: None,
             optimize: true,
+            optimization_cache: None,
+            bit_order: BitOrder::MsbFirst,
+            chunk_size: None,
+            initial_state: None,
+            import_limits: ImportLimits::default(),
         }
     }
 }
+
+/// Creates the quantum executor of a configuration, see [`ConfigurationBuilder::backend`].
+pub trait ExecutionFactory {
+    /// Creates an executor that runs each instruction as it is added to the process.
+    fn live(
+        &self,
+        num_qubits: usize,
+        seed: Option<u64>,
+    ) -> std::result::Result<Box<dyn LiveExecution>, Box<dyn std::error::Error>>;
+
+    /// Creates an executor that runs the whole process at once.
+    fn batch(
+        &self,
+        num_qubits: usize,
+        seed: Option<u64>,
+    ) -> std::result::Result<Box<dyn BatchExecution>, Box<dyn std::error::Error>>;
+}
+
+/// Errors found when building a [`Configuration`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ConfigurationError {
+    #[error("The number of qubits must be set to a value greater than zero.")]
+    MissingQubits,
+
+    #[error("Both a backend and an explicit executor were provided; use only one.")]
+    ConflictingExecutors,
+
+    #[error("A live executor was provided, but the configuration is in batch mode.")]
+    LiveExecutorInBatchMode,
+
+    #[error("A batch executor was provided, but the configuration is in live mode.")]
+    BatchExecutorInLiveMode,
+
+    #[error("A seed was provided without a backend to use it.")]
+    SeedWithoutBackend,
+
+    #[error("The execution timeout must be a positive number of seconds, got {0}.")]
+    InvalidTimeout(f64),
+
+    #[error("The execution timeout only applies to batch execution.")]
+    TimeoutInLiveMode,
+
+    #[error("The backend failed to create the executor: {0}")]
+    Backend(String),
+
+    #[error("The chunk size must be greater than zero.")]
+    InvalidChunkSize,
+
+    #[error("Chunked execution only applies to batch execution.")]
+    ChunksInLiveMode,
+}
+
+impl From<ConfigurationError> for crate::error::KetError {
+    fn from(_: ConfigurationError) -> Self {
+        crate::error::KetError::InvalidConfiguration
+    }
+}
+
+/// Builder for [`Configuration`], created with [`Configuration::builder`].
+///
+/// All features are allowed by default, as in [`Configuration::new`], and the process
+/// runs in batch mode.
+pub struct ConfigurationBuilder {
+    configuration: Configuration,
+    qubits: Option<usize>,
+    live: bool,
+    seed: Option<u64>,
+    backend: Option<Box<dyn ExecutionFactory>>,
+}
+
+impl Default for ConfigurationBuilder {
+    fn default() -> Self {
+        Self {
+            configuration: Configuration::new(0),
+            qubits: None,
+            live: false,
+            seed: None,
+            backend: None,
+        }
+    }
+}
+
+impl ConfigurationBuilder {
+    /// Maximum number of qubits in the process. Required.
+    pub fn qubits(mut self, num_qubits: usize) -> Self {
+        self.qubits = Some(num_qubits);
+        self
+    }
+
+    /// Runs each instruction as it is added to the process, instead of running the
+    /// whole process at once.
+    pub fn live(mut self, live: bool) -> Self {
+        self.live = live;
+        self
+    }
+
+    /// Backend that creates the executor for the selected mode.
+    pub fn backend(mut self, backend: impl ExecutionFactory + 'static) -> Self {
+        self.backend = Some(Box::new(backend));
+        self
+    }
+
+    /// Seed passed to the backend.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Executor used in live mode, instead of a backend.
+    pub fn live_execution(mut self, execution: Box<dyn LiveExecution>) -> Self {
+        self.configuration.live_quantum_execution = Some(execution);
+        self
+    }
+
+    /// Executor used in batch mode, instead of a backend.
+    pub fn batch_execution(mut self, execution: Box<dyn BatchExecution>) -> Self {
+        self.configuration.batch_execution = Some(execution);
+        self
+    }
+
+    /// Allows measurements.
+    pub fn allow_measure(mut self, allow: bool) -> Self {
+        self.configuration.allow_measure = allow;
+        self
+    }
+
+    /// Allows sampling.
+    pub fn allow_sample(mut self, allow: bool) -> Self {
+        self.configuration.allow_sample = allow;
+        self
+    }
+
+    /// Allows expected value calculations.
+    pub fn allow_exp_value(mut self, allow: bool) -> Self {
+        self.configuration.allow_exp_value = allow;
+        self
+    }
+
+    /// Allows state dumps.
+    pub fn allow_dump(mut self, allow: bool) -> Self {
+        self.configuration.allow_dump = allow;
+        self
+    }
+
+    /// Keeps the process valid after a measurement.
+    pub fn valid_after_measure(mut self, valid: bool) -> Self {
+        self.configuration.valid_after_measure = valid;
+        self
+    }
+
+    /// Keeps the process open after sampling.
+    pub fn continue_after_sample(mut self, continue_: bool) -> Self {
+        self.configuration.continue_after_sample = continue_;
+        self
+    }
+
+    /// Keeps the process open after an expected value calculation.
+    pub fn continue_after_exp_value(mut self, continue_: bool) -> Self {
+        self.configuration.continue_after_exp_value = continue_;
+        self
+    }
+
+    /// Keeps the process open after a state dump.
+    pub fn continue_after_dump(mut self, continue_: bool) -> Self {
+        self.configuration.continue_after_dump = continue_;
+        self
+    }
+
+    /// Decomposes all gates into X, H, and phase gates.
+    pub fn decompose(mut self, decompose: bool) -> Self {
+        self.configuration.decompose = decompose;
+        self
+    }
+
+    /// Optimizes the circuit before a batch execution.
+    pub fn optimize(mut self, optimize: bool) -> Self {
+        self.configuration.optimize = optimize;
+        self
+    }
+
+    /// Reuses the optimized circuits stored in `directory`, see
+    /// [`OptimizationCache`].
+    pub fn optimization_cache(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.configuration.optimization_cache = Some(OptimizationCache::new(directory));
+        self
+    }
+
+    /// Order of the qubits in the results, see [`BitOrder`].
+    pub fn bit_order(mut self, order: BitOrder) -> Self {
+        self.configuration.bit_order = order;
+        self
+    }
+
+    /// Executes the process in chunks of at most `instructions` instructions, see
+    /// [`Configuration::chunk_size`].
+    pub fn chunk_size(mut self, instructions: usize) -> Self {
+        self.configuration.chunk_size = Some(instructions);
+        self
+    }
+
+    /// Limits of the OpenQASM imports, see [`ImportLimits`].
+    pub fn import_limits(mut self, limits: ImportLimits) -> Self {
+        self.configuration.import_limits = limits;
+        self
+    }
+
+    /// Timeout of a batch execution, in seconds.
+    pub fn timeout(mut self, seconds: f64) -> Self {
+        self.configuration.execution_timeout = Some(seconds);
+        self
+    }
+
+    /// Validates the options and creates the configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigurationError`] describing the first inconsistent option.
+    pub fn build(self) -> std::result::Result<Configuration, ConfigurationError> {
+        let mut configuration = self.configuration;
+
+        configuration.num_qubits = match self.qubits {
+            Some(num_qubits) if num_qubits > 0 => num_qubits,
+            _ => return Err(ConfigurationError::MissingQubits),
+        };
+
+        let has_executor = configuration.live_quantum_execution.is_some()
+            || configuration.batch_execution.is_some();
+        if self.backend.is_some() && has_executor {
+            return Err(ConfigurationError::ConflictingExecutors);
+        }
+        if self.live && configuration.batch_execution.is_some() {
+            return Err(ConfigurationError::BatchExecutorInLiveMode);
+        }
+        if !self.live && configuration.live_quantum_execution.is_some() {
+            return Err(ConfigurationError::LiveExecutorInBatchMode);
+        }
+        if self.seed.is_some() && self.backend.is_none() {
+            return Err(ConfigurationError::SeedWithoutBackend);
+        }
+
+        if let Some(timeout) = configuration.execution_timeout {
+            if !(timeout.is_finite() && timeout > 0.0) {
+                return Err(ConfigurationError::InvalidTimeout(timeout));
+            }
+            if self.live {
+                return Err(ConfigurationError::TimeoutInLiveMode);
+            }
+        }
+
+        if let Some(chunk_size) = configuration.chunk_size {
+            if chunk_size == 0 {
+                return Err(ConfigurationError::InvalidChunkSize);
+            }
+            if self.live {
+                return Err(ConfigurationError::ChunksInLiveMode);
+            }
+        }
+
+        if let Some(backend) = self.backend {
+            let backend_error =
+                |error: Box<dyn std::error::Error>| ConfigurationError::Backend(error.to_string());
+            if self.live {
+                configuration.live_quantum_execution = Some(
+                    backend
+                        .live(configuration.num_qubits, self.seed)
+                        .map_err(backend_error)?,
+                );
+            } else {
+                configuration.batch_execution = Some(
+                    backend
+                        .batch(configuration.num_qubits, self.seed)
+                        .map_err(backend_error)?,
+                );
+            }
+        }
+
+        Ok(configuration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Configuration, ConfigurationError};
+
+    #[test]
+    fn builder_validation() {
+        let configuration = Configuration::builder()
+            .qubits(4)
+            .allow_dump(false)
+            .timeout(1.5)
+            .build()
+            .unwrap();
+        assert_eq!(configuration.num_qubits, 4);
+        assert!(!configuration.allow_dump);
+        assert_eq!(configuration.execution_timeout, Some(1.5));
+
+        let error = |builder: super::ConfigurationBuilder| builder.build().err().unwrap();
+        assert_eq!(
+            error(Configuration::builder().qubits(0)),
+            ConfigurationError::MissingQubits
+        );
+        assert_eq!(
+            error(Configuration::builder().qubits(2).seed(7)),
+            ConfigurationError::SeedWithoutBackend
+        );
+        assert_eq!(
+            error(Configuration::builder().qubits(2).timeout(-1.0)),
+            ConfigurationError::InvalidTimeout(-1.0)
+        );
+        assert_eq!(
+            error(Configuration::builder().qubits(2).live(true).timeout(1.0)),
+            ConfigurationError::TimeoutInLiveMode
+        );
+        assert_eq!(
+            error(Configuration::builder().qubits(2).chunk_size(0)),
+            ConfigurationError::InvalidChunkSize
+        );
+        assert_eq!(
+            error(Configuration::builder().qubits(2).live(true).chunk_size(8)),
+            ConfigurationError::ChunksInLiveMode
+        );
+    }
+}
@@ -0,0 +1,353 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Experiment tracking with a local SQLite database.
+//!
+//! This module is available with the `experiments` feature. Each executed [`Process`]
+//! is recorded with a name, a hash of its instructions, its OpenQASM 2.0 code (when it
+//! can be exported), user provided parameters, its results, and its metadata. The
+//! query helpers allow retrieving the records of a parameter sweep long after the
+//! simulations finished.
+//!
+//! ```rust,no_run
+//! # use ket::error::KetError;
+//! # use ket::experiments::ExperimentDatabase;
+//! # use ket::{Configuration, Process};
+//! # fn main() -> Result<(), KetError> {
+//! # let process = Process::new(Configuration::new(2));
+//! let database = ExperimentDatabase::open("experiments.db")?;
+//! database.record("vqe", &process, &serde_json::json!({ "theta": 0.5 }))?;
+//! let records = database.find_by_parameter("vqe", "theta", 0.5)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{KetError, Result},
+    ir::DumpData,
+    qasmv2::instruction_set::InstructionSet,
+    Process,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS experiments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    qasm TEXT,
+    parameters TEXT NOT NULL,
+    results TEXT NOT NULL,
+    metadata TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX IF NOT EXISTS experiments_name ON experiments (name);
+CREATE INDEX IF NOT EXISTS experiments_hash ON experiments (hash);
+";
+
+const SELECT: &str =
+    "SELECT id, name, hash, qasm, parameters, results, metadata, created_at FROM experiments";
+
+/// Results of a recorded process. Results that were not available are `None`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentResults {
+    /// Measurement results.
+    pub measurements: Vec<Option<u64>>,
+
+    /// Expected values.
+    pub exp_values: Vec<Option<f64>>,
+
+    /// Sampled results.
+    pub samples: Vec<Option<(Vec<u64>, Vec<u64>)>>,
+
+    /// Dumped quantum state data.
+    pub dumps: Vec<Option<DumpData>>,
+}
+
+/// A recorded experiment.
+#[derive(Debug, Clone)]
+pub struct ExperimentRecord {
+    /// Row identifier.
+    pub id: i64,
+
+    /// Experiment name.
+    pub name: String,
+
+    /// Hash of the process instructions, see [`process_hash`].
+    pub hash: String,
+
+    /// OpenQASM 2.0 code of the process, if it could be exported.
+    pub qasm: Option<String>,
+
+    /// User provided parameters.
+    pub parameters: serde_json::Value,
+
+    /// Process results.
+    pub results: ExperimentResults,
+
+    /// Process metadata.
+    pub metadata: serde_json::Value,
+
+    /// Creation time in UTC, as formatted by SQLite.
+    pub created_at: String,
+}
+
+/// Returns the FNV-1a hash of the process instructions as a hexadecimal string.
+///
+/// Processes with the same instruction list have the same hash, regardless of their
+/// results.
+pub fn process_hash(process: &Process) -> String {
+    let hash = process
+        .instructions_json()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+fn database_error(error: rusqlite::Error) -> KetError {
+    tracing::error!(%error, "experiment database");
+    KetError::ExperimentDatabaseError
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<(ExperimentRecord, String, String, String)> {
+    Ok((
+        ExperimentRecord {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            hash: row.get(2)?,
+            qasm: row.get(3)?,
+            parameters: serde_json::Value::Null,
+            results: ExperimentResults {
+                measurements: Vec::new(),
+                exp_values: Vec::new(),
+                samples: Vec::new(),
+                dumps: Vec::new(),
+            },
+            metadata: serde_json::Value::Null,
+            created_at: row.get(7)?,
+        },
+        row.get(4)?,
+        row.get(5)?,
+        row.get(6)?,
+    ))
+}
+
+/// Connection to an experiment database.
+pub struct ExperimentDatabase {
+    connection: Connection,
+}
+
+impl ExperimentDatabase {
+    /// Opens the database at `path`, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the file cannot be opened or
+    /// initialized.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::init(Connection::open(path).map_err(database_error)?)
+    }
+
+    /// Opens a database that lives in memory and is discarded when dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the database cannot be initialized.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory().map_err(database_error)?)
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA).map_err(database_error)?;
+        Ok(Self { connection })
+    }
+
+    /// Records a process, its results, and the parameters used to build it.
+    ///
+    /// Returns the identifier of the new record.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the record cannot be written.
+    pub fn record(
+        &self,
+        name: &str,
+        process: &Process,
+        parameters: &serde_json::Value,
+    ) -> Result<i64> {
+        let qasm = process.to_qasmv2(true, InstructionSet::QELIB).ok();
+
+        let results = ExperimentResults {
+            measurements: process.measurements.iter().map(|m| m.result).collect(),
+            exp_values: process.exp_values.iter().map(|e| e.result).collect(),
+            samples: process.samples.iter().map(|s| s.result.clone()).collect(),
+            dumps: (0..process.dumps.len())
+                .map(|index| process.get_dump_data(index))
+                .collect(),
+        };
+
+        self.connection
+            .execute(
+                "INSERT INTO experiments (name, hash, qasm, parameters, results, metadata) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    name,
+                    process_hash(process),
+                    qasm,
+                    parameters.to_string(),
+                    serde_json::to_string(&results).unwrap(),
+                    process.metadata_json(),
+                ],
+            )
+            .map_err(database_error)?;
+
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    fn query<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<Vec<ExperimentRecord>> {
+        let mut statement = self.connection.prepare(sql).map_err(database_error)?;
+        let rows = statement
+            .query_map(params, from_row)
+            .map_err(database_error)?;
+
+        rows.map(|row| {
+            let (mut record, parameters, results, metadata) = row.map_err(database_error)?;
+            record.parameters =
+                serde_json::from_str(&parameters).map_err(|_| KetError::ExperimentDatabaseError)?;
+            record.results =
+                serde_json::from_str(&results).map_err(|_| KetError::ExperimentDatabaseError)?;
+            record.metadata =
+                serde_json::from_str(&metadata).map_err(|_| KetError::ExperimentDatabaseError)?;
+            Ok(record)
+        })
+        .collect()
+    }
+
+    /// Returns the record with the given identifier, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the query fails.
+    pub fn get(&self, id: i64) -> Result<Option<ExperimentRecord>> {
+        Ok(self
+            .query(&format!("{SELECT} WHERE id = ?1"), params![id])?
+            .pop())
+    }
+
+    /// Returns all records, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the query fails.
+    pub fn list(&self) -> Result<Vec<ExperimentRecord>> {
+        self.query(&format!("{SELECT} ORDER BY id"), [])
+    }
+
+    /// Returns the records with the given experiment name, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the query fails.
+    pub fn find_by_name(&self, name: &str) -> Result<Vec<ExperimentRecord>> {
+        self.query(
+            &format!("{SELECT} WHERE name = ?1 ORDER BY id"),
+            params![name],
+        )
+    }
+
+    /// Returns the records of processes with the given hash, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the query fails.
+    pub fn find_by_hash(&self, hash: &str) -> Result<Vec<ExperimentRecord>> {
+        self.query(
+            &format!("{SELECT} WHERE hash = ?1 ORDER BY id"),
+            params![hash],
+        )
+    }
+
+    /// Returns the records of an experiment whose numeric parameter `key` equals `value`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the query fails.
+    pub fn find_by_parameter(
+        &self,
+        name: &str,
+        key: &str,
+        value: f64,
+    ) -> Result<Vec<ExperimentRecord>> {
+        self.query(
+            &format!("{SELECT} WHERE name = ?1 AND json_extract(parameters, ?2) = ?3 ORDER BY id"),
+            params![name, format!("$.{}", key), value],
+        )
+    }
+
+    /// Deletes the record with the given identifier.
+    ///
+    /// Returns `true` if a record was deleted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::ExperimentDatabaseError`] if the statement fails.
+    pub fn delete(&self, id: i64) -> Result<bool> {
+        let deleted = self
+            .connection
+            .execute("DELETE FROM experiments WHERE id = ?1", params![id])
+            .map_err(database_error)?;
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{process_hash, ExperimentDatabase};
+    use crate::error::KetError;
+    use crate::{Configuration, Process, QuantumGate, ResultData};
+
+    #[test]
+    fn record_and_query() -> Result<(), KetError> {
+        let database = ExperimentDatabase::open_in_memory()?;
+
+        for theta in [0.25, 0.5] {
+            let mut process = Process::new(Configuration::new(1));
+            process.config.optimize = false;
+            let qubit = process.allocate_qubit()?;
+            process.apply_gate(QuantumGate::Hadamard, qubit)?;
+            process.measure(&[qubit])?;
+            process.prepare_for_execution()?;
+            process.set_result(ResultData {
+                measurements: vec![1],
+                ..Default::default()
+            })?;
+
+            database.record("sweep", &process, &serde_json::json!({ "theta": theta }))?;
+        }
+
+        assert_eq!(database.list()?.len(), 2);
+
+        let records = database.find_by_parameter("sweep", "theta", 0.5)?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].results.measurements, vec![Some(1)]);
+
+        let by_hash = database.find_by_hash(&records[0].hash)?;
+        assert_eq!(by_hash.len(), 2);
+
+        assert!(database.delete(records[0].id)?);
+        assert!(database.get(records[0].id)?.is_none());
+
+        let process = Process::new(Configuration::new(1));
+        assert_eq!(process_hash(&process).len(), 16);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,463 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Measurement grouping for Hamiltonian estimation.
+//!
+//! Terms of a [`PauliHamiltonian`] that commute qubit-wise (every shared qubit is acted
+//! on by the same Pauli operator) can be estimated from the same measurement circuit.
+//! This module groups the terms, distributes a shot budget among the groups, schedules
+//! one basis-rotated sample per group, and combines the sampled counts into an
+//! expected value with its variance.
+//!
+//! An [`Estimate`] gives the standard error and confidence interval of a value
+//! estimated from counts, as those of [`Sample::estimate`](crate::Sample::estimate), and
+//! the number of shots needed for a target precision.
+
+use crate::{
+    error::{KetError, Result},
+    ir::{Pauli, PauliHamiltonian, PauliTerm, QuantumGate},
+    Angle, Process,
+};
+
+/// A set of qubit-wise commuting Hamiltonian terms measured with the same basis.
+#[derive(Debug, Clone)]
+pub struct MeasurementGroup {
+    /// Indices of the Hamiltonian terms in the group.
+    pub terms: Vec<usize>,
+
+    /// Measurement basis for each qubit in the group, sorted by qubit index.
+    pub basis: Vec<PauliTerm>,
+
+    /// Number of shots allocated to the group.
+    pub shots: u64,
+}
+
+/// Normal quantile of a two-sided 95% confidence interval.
+pub const Z_95: f64 = 1.959_963_984_540_054;
+
+/// Estimated expected value, from sampled counts.
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    /// Expected value.
+    pub value: f64,
+
+    /// Variance of the estimated expected value.
+    pub variance: f64,
+
+    /// Number of shots of the estimate.
+    pub shots: u64,
+}
+
+impl Estimate {
+    /// Estimates the mean of a quantity from its value on each sampled outcome, given
+    /// as `(value, count)` pairs.
+    ///
+    /// Returns `None` if there is no shot.
+    pub fn from_counts(values: impl IntoIterator<Item = (f64, u64)>) -> Option<Self> {
+        let (sum, sum_sq, shots) =
+            values
+                .into_iter()
+                .fold((0.0, 0.0, 0), |(sum, sum_sq, shots), (value, count)| {
+                    let weight = count as f64;
+                    (
+                        sum + value * weight,
+                        sum_sq + value * value * weight,
+                        shots + count,
+                    )
+                });
+        if shots == 0 {
+            return None;
+        }
+
+        let n = shots as f64;
+        let mean = sum / n;
+        let variance = if shots > 1 {
+            (sum_sq - n * mean * mean).max(0.0) / (n - 1.0) / n
+        } else {
+            0.0
+        };
+        Some(Self {
+            value: mean,
+            variance,
+            shots,
+        })
+    }
+
+    /// Returns the standard error of the estimate.
+    pub fn std_error(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    /// Returns the confidence interval of the estimate for the normal quantile `z`, as
+    /// [`Z_95`] for a 95% interval.
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        let margin = z * self.std_error();
+        (self.value - margin, self.value + margin)
+    }
+
+    /// Returns the number of shots needed to reach the standard error `std_error`,
+    /// assuming the variance of a single shot stays the same.
+    pub fn shots_for_std_error(&self, std_error: f64) -> u64 {
+        required_shots(self.variance * self.shots as f64, std_error)
+    }
+}
+
+/// Returns the number of shots needed to estimate a mean with the standard error
+/// `std_error`, for a quantity whose single-shot variance is `variance`.
+///
+/// For the expected value of a Hamiltonian, the single-shot variance is bounded by the
+/// square of the sum of the absolute value of its coefficients.
+pub fn required_shots(variance: f64, std_error: f64) -> u64 {
+    if variance <= 0.0 {
+        return 1;
+    }
+    (variance / (std_error * std_error)).ceil().max(1.0) as u64
+}
+
+/// Handle for a Hamiltonian estimation scheduled with [`Process::sample_hamiltonian`].
+#[derive(Debug, Clone)]
+pub struct GroupedSample {
+    /// Hamiltonian being estimated.
+    pub hamiltonian: PauliHamiltonian,
+
+    /// Measurement groups.
+    pub groups: Vec<MeasurementGroup>,
+
+    /// Sample index of each group in the process.
+    pub samples: Vec<usize>,
+}
+
+/// Returns `true` if the Pauli product is compatible with the measurement basis.
+fn qubit_wise_commute(basis: &[PauliTerm], product: &[PauliTerm]) -> bool {
+    product.iter().all(|term| {
+        basis
+            .iter()
+            .filter(|basis| basis.qubit == term.qubit)
+            .all(|basis| basis.pauli == term.pauli)
+    })
+}
+
+/// Groups the Hamiltonian terms into qubit-wise commuting sets.
+///
+/// Terms are inserted greedily in decreasing order of coefficient magnitude, which tends
+/// to place the dominant terms together. Identity terms are not assigned to any group,
+/// as they do not require measurement. The shot count of every group is zero; use
+/// [`allocate_shots`] to distribute a budget.
+pub fn group_qubit_wise_commuting(hamiltonian: &PauliHamiltonian) -> Vec<MeasurementGroup> {
+    let mut order: Vec<usize> = (0..hamiltonian.products.len())
+        .filter(|index| !hamiltonian.products[*index].is_empty())
+        .collect();
+    order.sort_by(|a, b| {
+        hamiltonian.coefficients[*b]
+            .abs()
+            .total_cmp(&hamiltonian.coefficients[*a].abs())
+    });
+
+    let mut groups: Vec<MeasurementGroup> = Vec::new();
+
+    for index in order {
+        let product = &hamiltonian.products[index];
+        let group = match groups
+            .iter_mut()
+            .find(|group| qubit_wise_commute(&group.basis, product))
+        {
+            Some(group) => group,
+            None => {
+                groups.push(MeasurementGroup {
+                    terms: Vec::new(),
+                    basis: Vec::new(),
+                    shots: 0,
+                });
+                groups.last_mut().unwrap()
+            }
+        };
+
+        group.terms.push(index);
+        for term in product {
+            if !group.basis.iter().any(|basis| basis.qubit == term.qubit) {
+                group.basis.push(term.clone());
+            }
+        }
+    }
+
+    for group in groups.iter_mut() {
+        group.basis.sort_by_key(|term| term.qubit);
+    }
+
+    groups
+}
+
+/// Distributes `shots` among the groups proportionally to the sum of the absolute
+/// coefficients of their terms, giving every group at least one shot.
+pub fn allocate_shots(hamiltonian: &PauliHamiltonian, groups: &mut [MeasurementGroup], shots: u64) {
+    let weights: Vec<f64> = groups
+        .iter()
+        .map(|group| {
+            group
+                .terms
+                .iter()
+                .map(|index| hamiltonian.coefficients[*index].abs())
+                .sum()
+        })
+        .collect();
+    let total: f64 = weights.iter().sum();
+    let num_groups = groups.len() as f64;
+
+    for (group, weight) in groups.iter_mut().zip(weights) {
+        let share = if total > 0.0 {
+            weight / total
+        } else {
+            1.0 / num_groups
+        };
+        group.shots = ((shots as f64 * share).round() as u64).max(1);
+    }
+}
+
+/// Schedules the estimation of a Hamiltonian using qubit-wise commuting groups.
+///
+/// See [`Process::sample_hamiltonian`].
+pub(crate) fn sample_hamiltonian(
+    process: &mut Process,
+    hamiltonian: &PauliHamiltonian,
+    shots: u64,
+) -> Result<GroupedSample> {
+    let mut groups = group_qubit_wise_commuting(hamiltonian);
+    allocate_shots(hamiltonian, &mut groups, shots);
+
+    if groups.iter().any(|group| group.basis.len() > 64) {
+        return Err(KetError::NumberOfQubitsExceeded);
+    }
+
+    let samples = sample_in_bases(
+        process,
+        groups.iter().map(|group| {
+            let basis = group
+                .basis
+                .iter()
+                .map(|term| (term.qubit, term.pauli))
+                .collect();
+            (basis, group.shots)
+        }),
+    )?;
+
+    Ok(GroupedSample {
+        hamiltonian: hamiltonian.clone(),
+        groups,
+        samples,
+    })
+}
+
+/// Schedules one sample for each `(basis, shots)` setting, where the basis is a list
+/// of `(qubit, pauli)` pairs.
+///
+/// Every qubit is rotated into its Pauli basis before the sample and rotated back
+/// after it. Returns the sample indexes in the order of the settings.
+///
+/// Shared by the grouped Hamiltonian estimation and the classical shadows.
+pub(crate) fn sample_in_bases(
+    process: &mut Process,
+    settings: impl IntoIterator<Item = (Vec<(usize, Pauli)>, u64)>,
+) -> Result<Vec<usize>> {
+    // Basis changes must not be affected by an open control scope.
+    let ctrl_stack = std::mem::take(&mut process.ctrl_stack);
+    process.ctrl_list_is_up_to_date = false;
+
+    let result = settings
+        .into_iter()
+        .map(|(basis, shots)| sample_in_basis(process, &basis, shots))
+        .collect();
+
+    process.ctrl_stack = ctrl_stack;
+    process.ctrl_list_is_up_to_date = false;
+
+    result
+}
+
+fn sample_in_basis(process: &mut Process, basis: &[(usize, Pauli)], shots: u64) -> Result<usize> {
+    for &(qubit, pauli) in basis {
+        match pauli {
+            Pauli::PauliX => process.apply_gate(QuantumGate::Hadamard, qubit)?,
+            Pauli::PauliY => {
+                process.apply_gate(
+                    QuantumGate::Phase(Angle::PiFraction { top: -1, bottom: 2 }),
+                    qubit,
+                )?;
+                process.apply_gate(QuantumGate::Hadamard, qubit)?;
+            }
+            Pauli::PauliZ => {}
+        }
+    }
+
+    let qubits: Vec<usize> = basis.iter().map(|&(qubit, _)| qubit).collect();
+    let sample = process.sample_arranged(&qubits, shots)?;
+
+    for &(qubit, pauli) in basis {
+        match pauli {
+            Pauli::PauliX => process.apply_gate(QuantumGate::Hadamard, qubit)?,
+            Pauli::PauliY => {
+                process.apply_gate(QuantumGate::Hadamard, qubit)?;
+                process.apply_gate(
+                    QuantumGate::Phase(Angle::PiFraction { top: 1, bottom: 2 }),
+                    qubit,
+                )?;
+            }
+            Pauli::PauliZ => {}
+        }
+    }
+
+    Ok(sample)
+}
+
+impl GroupedSample {
+    /// Combines the sampled counts into the expected value and its variance.
+    ///
+    /// Returns `None` if any of the samples is not yet available.
+    pub fn estimate(&self, process: &Process) -> Option<Estimate> {
+        let mut value: f64 = self
+            .hamiltonian
+            .products
+            .iter()
+            .zip(self.hamiltonian.coefficients.iter())
+            .filter(|(product, _)| product.is_empty())
+            .map(|(_, coefficient)| coefficient)
+            .sum();
+        let mut variance = 0.0;
+        let mut total_shots = 0;
+
+        for (group, sample) in self.groups.iter().zip(self.samples.iter()) {
+            let (states, counts) = process.get_sample(*sample).result.as_ref()?;
+            let size = group.basis.len();
+
+            // Bit mask of each term over the sampled state. The first sampled qubit is
+            // the most significant bit.
+            let masks: Vec<(u64, f64)> = group
+                .terms
+                .iter()
+                .map(|index| {
+                    let mask = self.hamiltonian.products[*index]
+                        .iter()
+                        .map(|term| {
+                            let position = group
+                                .basis
+                                .iter()
+                                .position(|basis| basis.qubit == term.qubit)
+                                .unwrap();
+                            1u64 << (size - 1 - position)
+                        })
+                        .fold(0, |acc, bit| acc | bit);
+                    (mask, self.hamiltonian.coefficients[*index])
+                })
+                .collect();
+
+            let estimate =
+                Estimate::from_counts(states.iter().zip(counts.iter()).map(|(state, count)| {
+                    let shot_value: f64 = masks
+                        .iter()
+                        .map(|(mask, coefficient)| {
+                            if (state & mask).count_ones() % 2 == 0 {
+                                *coefficient
+                            } else {
+                                -*coefficient
+                            }
+                        })
+                        .sum();
+                    (shot_value, *count)
+                }))?;
+
+            value += estimate.value;
+            variance += estimate.variance;
+            total_shots += estimate.shots;
+        }
+
+        Some(Estimate {
+            value,
+            variance,
+            shots: total_shots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group_qubit_wise_commuting, required_shots, Estimate, Z_95};
+    use crate::error::KetError;
+    use crate::{Configuration, PauliHamiltonian, Process, ResultData, Sample};
+
+    #[test]
+    fn groups_commuting_terms() -> Result<(), KetError> {
+        let hamiltonian = PauliHamiltonian::from_openfermion(
+            "1.0 [Z0 Z1] + 0.5 [Z0] + 0.25 [X0] + 0.1 [X0 Z1] + 2.0 []",
+        )?;
+
+        let groups = group_qubit_wise_commuting(&hamiltonian);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].terms, vec![0, 1]);
+        assert_eq!(groups[1].terms, vec![2, 3]);
+        assert_eq!(groups[1].basis.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn estimate_from_counts() -> Result<(), KetError> {
+        let hamiltonian =
+            PauliHamiltonian::from_openfermion("1.0 [Z0] + 1.0 [Z1] + 0.5 [X0] + 3.0 []")?;
+
+        let mut process = Process::new(Configuration::new(2));
+        process.config.optimize = false;
+        let qubit_a = process.allocate_qubit()?;
+        let qubit_b = process.allocate_qubit()?;
+        assert_eq!((qubit_a, qubit_b), (0, 1));
+
+        let estimation = process.sample_hamiltonian(&hamiltonian, 100)?;
+        assert_eq!(estimation.groups.len(), 2);
+
+        process.prepare_for_execution()?;
+        process.set_result(ResultData {
+            // |00> in the Z basis; an even split of |+>/|-> in the X basis.
+            samples: vec![(vec![0b00], vec![80]), (vec![0, 1], vec![10, 10])],
+            ..Default::default()
+        })?;
+
+        let estimate = estimation.estimate(&process).unwrap();
+        assert!((estimate.value - 5.0).abs() < 1e-12);
+        assert!(estimate.variance > 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn standard_errors() {
+        // Half of the shots of a ±1 observable are -1.
+        let estimate = Estimate::from_counts([(1.0, 50), (-1.0, 50)]).unwrap();
+        assert_eq!(estimate.shots, 100);
+        assert!(estimate.value.abs() < 1e-12);
+        assert!((estimate.std_error() - (1.0 / 99.0f64).sqrt()).abs() < 1e-12);
+        let (low, high) = estimate.confidence_interval(Z_95);
+        assert!((high - low - 2.0 * Z_95 * estimate.std_error()).abs() < 1e-12);
+        assert!(Estimate::from_counts([]).is_none());
+
+        // Halving the standard error takes four times the shots.
+        let target = estimate.std_error() / 2.0;
+        assert_eq!(estimate.shots_for_std_error(target), 400);
+        assert_eq!(required_shots(1.0, 0.01), 10000);
+
+        let sample = Sample {
+            qubits: vec![0, 1],
+            shots: 10,
+            result: Some((vec![0b00, 0b11], vec![8, 2])),
+        };
+        let probability = sample.probability(0b11).unwrap();
+        assert!((probability.value - 0.2).abs() < 1e-12);
+        assert!((probability.variance - 0.2 * 0.8 / 9.0).abs() < 1e-12);
+        assert!(Sample {
+            result: None,
+            ..sample
+        }
+        .probability(0)
+        .is_none());
+    }
+}
@@ -0,0 +1,285 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sharing a [`Process`] between threads.
+//!
+//! A [`Process`] is neither `Send` nor `Sync`, since its executor may be bound to the
+//! thread that created it, like the callbacks of the C API. A [`ProcessHandle`] runs
+//! the process in a worker thread that owns it, and sends each call to the worker
+//! through a channel. The handle is `Send`, `Sync`, and `Clone`, and the calls of
+//! every clone are applied in the order they are received, so a GUI thread can append
+//! gates while the worker executes the previous ones:
+//!
+//! ```
+//! use ket::{handle::ProcessHandle, Configuration, QuantumGate};
+//!
+//! # fn main() -> Result<(), ket::error::KetError> {
+//! let handle = ProcessHandle::spawn(|| Configuration::new(2));
+//! let qubit = handle.allocate_qubit().wait()?;
+//!
+//! let gui = handle.clone();
+//! std::thread::spawn(move || gui.apply_gate(QuantumGate::Hadamard, qubit))
+//!     .join()
+//!     .unwrap()
+//!     .wait()?;
+//!
+//! let gates = handle.call(|process| process.instructions().len()).wait()?;
+//! assert_eq!(gates, 2);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `async` feature, [`Pending::wait_async`] and
+//! [`ProcessHandle::execute_async`] wait for the worker from a tokio task, so a web
+//! service can run a simulation that takes seconds without blocking its runtime.
+//!
+//! The worker stops when every handle is dropped. If a call panics, the worker stops,
+//! and the later calls return [`KetError::ProcessHandleClosed`].
+
+use std::{
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+use crate::{
+    error::{KetError, Result},
+    Configuration, Process, QuantumGate,
+};
+
+/// Call sent to the worker thread.
+type Job = Box<dyn FnOnce(&mut Process) + Send>;
+
+/// Thread-safe handle to a [`Process`] owned by a worker thread.
+#[derive(Debug, Clone)]
+pub struct ProcessHandle {
+    jobs: Sender<Job>,
+}
+
+/// Result of a call sent to a [`ProcessHandle`], available once the worker runs it.
+#[derive(Debug)]
+#[must_use = "the call runs anyway, but its result and errors are only known by waiting"]
+pub struct Pending<T> {
+    result: Receiver<Result<T>>,
+}
+
+impl<T> Pending<T> {
+    /// Blocks until the call is run and returns its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the call, or [`KetError::ProcessHandleClosed`] if the worker
+    /// stopped before running it.
+    pub fn wait(self) -> Result<T> {
+        self.result
+            .recv()
+            .unwrap_or(Err(KetError::ProcessHandleClosed))
+    }
+
+    /// Returns the result of the call if it was already run, without blocking.
+    pub fn try_wait(&self) -> Option<Result<T>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(KetError::ProcessHandleClosed)),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: Send + 'static> Pending<T> {
+    /// Waits for the call without blocking the threads of the tokio runtime.
+    ///
+    /// The wait is moved to the blocking thread pool of the runtime with
+    /// [`tokio::task::spawn_blocking`], so it must be called in a runtime.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Pending::wait`].
+    pub async fn wait_async(self) -> Result<T> {
+        tokio::task::spawn_blocking(move || self.wait())
+            .await
+            .unwrap_or(Err(KetError::ProcessHandleClosed))
+    }
+}
+
+impl ProcessHandle {
+    /// Starts a worker thread with a process created with the configuration.
+    ///
+    /// The configuration is created in the worker thread, so its executor does not need
+    /// to be `Send`.
+    pub fn spawn<F>(configuration: F) -> Self
+    where
+        F: FnOnce() -> Configuration + Send + 'static,
+    {
+        let (jobs, received) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let mut process = Process::new(configuration());
+            for job in received {
+                job(&mut process);
+            }
+        });
+        Self { jobs }
+    }
+
+    /// Sends a fallible call to the worker.
+    fn try_call<T, F>(&self, call: F) -> Pending<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Process) -> Result<T> + Send + 'static,
+    {
+        let (sender, result) = mpsc::channel();
+        let job: Job = Box::new(move |process| {
+            let _ = sender.send(call(process));
+        });
+        // If the worker stopped, the result sender is dropped with the job, and
+        // `Pending::wait` returns an error.
+        let _ = self.jobs.send(job);
+        Pending { result }
+    }
+
+    /// Runs `call` with the process in the worker thread.
+    pub fn call<T, F>(&self, call: F) -> Pending<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Process) -> T + Send + 'static,
+    {
+        self.try_call(move |process| Ok(call(process)))
+    }
+
+    /// Allocates a qubit, see [`Process::allocate_qubit`].
+    pub fn allocate_qubit(&self) -> Pending<usize> {
+        self.try_call(Process::allocate_qubit)
+    }
+
+    /// Frees a qubit, see [`Process::free_qubit`].
+    pub fn free_qubit(&self, qubit: usize) -> Pending<()> {
+        self.try_call(move |process| process.free_qubit(qubit))
+    }
+
+    /// Applies a gate, see [`Process::apply_gate`].
+    pub fn apply_gate(&self, gate: QuantumGate, target: usize) -> Pending<()> {
+        self.try_call(move |process| process.apply_gate(gate, target))
+    }
+
+    /// Adds control qubits, see [`Process::ctrl_push`].
+    pub fn ctrl_push(&self, qubits: &[usize]) -> Pending<()> {
+        let qubits = qubits.to_vec();
+        self.try_call(move |process| process.ctrl_push(&qubits))
+    }
+
+    /// Removes the last control qubits, see [`Process::ctrl_pop`].
+    pub fn ctrl_pop(&self) -> Pending<()> {
+        self.try_call(Process::ctrl_pop)
+    }
+
+    /// Measures qubits, see [`Process::measure`].
+    pub fn measure(&self, qubits: &[usize]) -> Pending<usize> {
+        let qubits = qubits.to_vec();
+        self.try_call(move |process| process.measure(&qubits))
+    }
+
+    /// Executes the process, see [`Process::prepare_for_execution`].
+    pub fn prepare_for_execution(&self) -> Pending<()> {
+        self.try_call(Process::prepare_for_execution)
+    }
+
+    /// Executes the process without blocking the threads of the tokio runtime, see
+    /// [`Process::prepare_for_execution`] and [`Pending::wait_async`].
+    ///
+    /// # Errors
+    ///
+    /// The same as [`Pending::wait`].
+    #[cfg(feature = "async")]
+    pub async fn execute_async(&self) -> Result<()> {
+        self.prepare_for_execution().wait_async().await
+    }
+
+    /// Returns the result of a measurement, see [`Process::get_measurement`].
+    pub fn get_measurement(&self, index: usize) -> Pending<Option<u64>> {
+        self.call(move |process| process.get_measurement(index).result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::ProcessHandle;
+    use crate::{
+        error::KetError,
+        ir::{DumpData, PauliHamiltonian, QuantumGate},
+        Configuration, LiveExecution,
+    };
+
+    /// Measures the number of applied gates.
+    struct Counter(u64);
+
+    impl LiveExecution for Counter {
+        fn alloc(&mut self, _: usize) {}
+        fn free(&mut self, _: usize) {}
+        fn gate(&mut self, _: &QuantumGate, _: usize, _: &[usize]) {
+            self.0 += 1;
+        }
+        fn measure(&mut self, _: &[usize]) -> u64 {
+            self.0
+        }
+        fn exp_value(&mut self, _: &PauliHamiltonian) -> f64 {
+            0.0
+        }
+        fn sample(&mut self, _: &[usize], _: u64) -> (Vec<u64>, Vec<u64>) {
+            (vec![self.0], vec![1])
+        }
+        fn dump(&mut self, _: &[usize]) -> DumpData {
+            DumpData {
+                basis_states: vec![vec![self.0]],
+                amplitudes_real: vec![1.0],
+                amplitudes_imag: vec![0.0],
+            }
+        }
+    }
+
+    #[test]
+    fn calls_from_threads() -> Result<(), KetError> {
+        fn shared<T: Send + Sync + Clone>(_: &T) {}
+
+        let handle = ProcessHandle::spawn(|| Configuration {
+            live_quantum_execution: Some(Box::new(Counter(0))),
+            ..Configuration::new(4)
+        });
+        shared(&handle);
+
+        let qubits: Vec<usize> = (0..4)
+            .map(|_| handle.allocate_qubit().wait())
+            .collect::<Result<_, _>>()?;
+        let workers: Vec<_> = qubits
+            .iter()
+            .map(|&qubit| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    (0..10)
+                        .map(|_| handle.apply_gate(QuantumGate::PauliX, qubit))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for worker in workers {
+            for pending in worker.join().unwrap() {
+                pending.wait()?;
+            }
+        }
+
+        let measurement = handle.measure(&qubits).wait()?;
+        assert_eq!(handle.get_measurement(measurement).wait()?, Some(40));
+        assert!(matches!(
+            handle.apply_gate(QuantumGate::PauliX, 7).wait(),
+            Err(KetError::QubitIndexOutOfBounds)
+        ));
+
+        let pending = handle.call(|process| process.instructions().len());
+        assert_eq!(pending.wait()?, 45);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Content-addressed hashing and structural equality of circuits.
+//!
+//! Two instruction lists are structurally equal if they only differ in the order of
+//! instructions on disjoint qubits, which never changes the result of the circuit. Both
+//! are compared in the [canonical order](canonical_order): every instruction is placed
+//! in the earliest layer after the instructions it shares a qubit with, and the
+//! instructions of a layer are sorted by their qubits.
+//!
+//! [`CircuitHash`] is computed from the canonical order with FNV-1a, so it does not
+//! depend on the platform or on the Rust version and can be used as a key of persistent
+//! caches.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::{Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut a = Process::new(Configuration::new(2));
+//! let mut b = Process::new(Configuration::new(2));
+//! for process in [&mut a, &mut b] {
+//!     process.allocate_qubit()?;
+//!     process.allocate_qubit()?;
+//! }
+//!
+//! a.apply_gate(QuantumGate::Hadamard, 0)?;
+//! a.apply_gate(QuantumGate::PauliX, 1)?;
+//! b.apply_gate(QuantumGate::PauliX, 1)?;
+//! b.apply_gate(QuantumGate::Hadamard, 0)?;
+//!
+//! assert!(a == b);
+//! assert_eq!(a.circuit_hash(), b.circuit_hash());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ir::Instruction, passes::peephole::qubits};
+
+/// Returns the indices of the instructions in the canonical order.
+pub fn canonical_order(instructions: &[Instruction]) -> Vec<usize> {
+    let mut layer_of_qubit: Vec<usize> = Vec::new();
+    let mut keys: Vec<(usize, Vec<usize>, String, usize)> = instructions
+        .iter()
+        .enumerate()
+        .map(|(index, instruction)| {
+            let qubits = qubits(instruction);
+            let layer = qubits
+                .iter()
+                .map(|&qubit| layer_of_qubit.get(qubit).copied().unwrap_or_default())
+                .max()
+                .unwrap_or_default();
+            for &qubit in &qubits {
+                if qubit >= layer_of_qubit.len() {
+                    layer_of_qubit.resize(qubit + 1, 0);
+                }
+                layer_of_qubit[qubit] = layer + 1;
+            }
+            (layer, qubits, serialize(instruction), index)
+        })
+        .collect();
+
+    keys.sort();
+    keys.into_iter().map(|(.., index)| index).collect()
+}
+
+fn serialize(instruction: &Instruction) -> String {
+    serde_json::to_string(instruction).expect("instructions are serializable")
+}
+
+/// Instructions serialized in the canonical order.
+fn canonical_form(instructions: &[Instruction]) -> Vec<String> {
+    canonical_order(instructions)
+        .into_iter()
+        .map(|index| serialize(&instructions[index]))
+        .collect()
+}
+
+/// Returns `true` if the instruction lists only differ in the order of instructions on
+/// disjoint qubits.
+pub fn structurally_equal(a: &[Instruction], b: &[Instruction]) -> bool {
+    a.len() == b.len() && canonical_form(a) == canonical_form(b)
+}
+
+/// Stable 64-bit hash of a circuit, equal for structurally equal circuits.
+///
+/// It is written as 16 hexadecimal digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CircuitHash(pub u64);
+
+impl CircuitHash {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    /// Computes the hash of the instructions.
+    pub fn new(instructions: &[Instruction]) -> Self {
+        let mut hash = Self::FNV_OFFSET;
+        for instruction in canonical_form(instructions) {
+            for byte in instruction.bytes().chain([b'\n']) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(Self::FNV_PRIME);
+            }
+        }
+        Self(hash)
+    }
+}
+
+impl fmt::Display for CircuitHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{error::KetError, Angle, Configuration, Process, QuantumGate};
+
+    fn circuit(gates: &[(QuantumGate, usize)]) -> Result<Process, KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        for _ in 0..3 {
+            process.allocate_qubit()?;
+        }
+        for (gate, qubit) in gates {
+            process.apply_gate(gate.clone(), *qubit)?;
+        }
+        process.ctrl_push(&[0])?;
+        process.apply_gate(QuantumGate::PauliX, 2)?;
+        process.ctrl_pop()?;
+        Ok(process)
+    }
+
+    #[test]
+    fn reordering_disjoint_gates() -> Result<(), KetError> {
+        let h = QuantumGate::Hadamard;
+        let x = QuantumGate::PauliX;
+        let rz = |angle| QuantumGate::RotationZ(Angle::Scalar(angle));
+
+        let a = circuit(&[(h.clone(), 0), (x.clone(), 1), (rz(0.5), 2)])?;
+        let b = circuit(&[(rz(0.5), 2), (x.clone(), 1), (h.clone(), 0)])?;
+        assert!(a == b);
+        assert_eq!(a.circuit_hash(), b.circuit_hash());
+
+        // Gates on the same qubit keep their order.
+        let c = circuit(&[(h.clone(), 0), (x.clone(), 0), (rz(0.5), 2)])?;
+        let d = circuit(&[(x.clone(), 0), (h.clone(), 0), (rz(0.5), 2)])?;
+        assert!(c != d);
+        assert_ne!(c.circuit_hash(), d.circuit_hash());
+
+        let e = circuit(&[(h, 0), (x, 1), (rz(0.25), 2)])?;
+        assert!(a != e);
+
+        let unique: HashSet<_> = [&a, &b, &c, &d, &e].into_iter().collect();
+        assert_eq!(unique.len(), 4);
+        assert_eq!(a.circuit_hash().to_string().len(), 16);
+
+        Ok(())
+    }
+}
@@ -4,10 +4,16 @@
 // SPDX-License-Identifier: Apache-2.0
 //! This module contains the IR (Intermediate Representation) of the quantum circuit.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Deserialize, Serialize};
 
+pub use crate::circuit::{
+    Angle, Condition, Instruction, KrausOperator, Pauli, PauliHamiltonian, PauliProduct, PauliTerm,
+    QuantumGate,
+};
+use crate::{passes::CompilationReport, zx::OptimizationReport};
+
 /// Structure representing the data dumped from a quantum state.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DumpData {
@@ -21,203 +27,6 @@ pub struct DumpData {
     pub amplitudes_imag: Vec<f64>,
 }
 
-/// Enum representing different angle representations for quantum gates.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Angle {
-    /// Scalar angle.
-    Scalar(f64),
-    /// Pi fraction angle.
-    PiFraction {
-        /// Top part of the fraction.
-        top: i32,
-        /// Bottom part of the fraction.
-        bottom: u32,
-    },
-}
-
-impl Angle {
-    /// Returns the inverse of the angle.
-    pub fn inverse(&self) -> Angle {
-        match self {
-            Angle::Scalar(angle) => Angle::Scalar(-angle),
-            Angle::PiFraction { top, bottom } => Angle::PiFraction {
-                top: -top,
-                bottom: *bottom,
-            },
-        }
-    }
-
-    /// Returns an angle representing π.
-    pub fn pi() -> Angle {
-        Angle::PiFraction { top: 1, bottom: 1 }
-    }
-
-    /// Return the angle as a scalar value.
-    pub fn scalar(&self) -> f64 {
-        match self {
-            Angle::Scalar(angle) => *angle,
-            Angle::PiFraction { top, bottom } => {
-                let top = *top as f64;
-                let bottom = *bottom as f64;
-                top / bottom
-            }
-        }
-    }
-}
-
-impl std::fmt::Display for Angle {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Angle::Scalar(angle) => write!(f, "{}", angle),
-            Angle::PiFraction { top, bottom } => {
-                if *top == 1 {
-                    write!(f, "pi/{}", bottom)
-                } else {
-                    write!(f, "({} * pi)/{}", top, bottom)
-                }
-            }
-        }
-    }
-}
-
-/// Enum representing various quantum gates.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum QuantumGate {
-    /// Pauli X gate.
-    PauliX,
-    /// Pauli Y gate.
-    PauliY,
-    /// Pauli Z gate.
-    PauliZ,
-    /// X-axis rotation gate.
-    RotationX(Angle),
-    /// Y-axis rotation gate.
-    RotationY(Angle),
-    /// Z-axis rotation gate.
-    RotationZ(Angle),
-    /// Phase shift gate.
-    Phase(Angle),
-    /// Hadamard gate.
-    Hadamard,
-}
-
-impl QuantumGate {
-    /// Returns the inverse of the quantum gate.
-    pub fn inverse(&self) -> QuantumGate {
-        match self {
-            QuantumGate::RotationX(angle) => QuantumGate::RotationX(angle.inverse()),
-            QuantumGate::RotationY(angle) => QuantumGate::RotationY(angle.inverse()),
-            QuantumGate::RotationZ(angle) => QuantumGate::RotationZ(angle.inverse()),
-            QuantumGate::Phase(angle) => QuantumGate::Phase(angle.inverse()),
-            QuantumGate::Hadamard => QuantumGate::Hadamard,
-            QuantumGate::PauliX => QuantumGate::PauliX,
-            QuantumGate::PauliY => QuantumGate::PauliY,
-            QuantumGate::PauliZ => QuantumGate::PauliZ,
-        }
-    }
-}
-
-/// Enum representing Pauli operators.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Pauli {
-    /// Pauli X operator.
-    PauliX,
-    /// Pauli Y operator.
-    PauliY,
-    /// Pauli Z operator.
-    PauliZ,
-}
-
-/// Structure representing a term in a Pauli product.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PauliTerm {
-    /// Pauli operator.
-    pub pauli: Pauli,
-
-    /// Qubit index.
-    pub qubit: usize,
-}
-
-/// Type representing a product of Pauli terms.
-pub type PauliProduct = Vec<PauliTerm>;
-
-/// Structure representing a Hamiltonian in terms of Pauli products.
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct PauliHamiltonian {
-    /// List of Pauli products.
-    pub products: Vec<PauliProduct>,
-
-    /// Coefficients associated with each Pauli product.
-    pub coefficients: Vec<f64>,
-}
-
-/// Enum representing different quantum instructions.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Instruction {
-    /// Alloc instruction, allocating a qubit.
-    Alloc {
-        /// The target index of the allocated qubit.
-        target: usize,
-    },
-
-    /// Free instruction, freeing a previously allocated qubit.
-    Free {
-        /// The target index of the qubit to be freed.
-        target: usize,
-    },
-
-    /// Gate instruction, applying a quantum gate to a target qubit with optional control qubits.
-    Gate {
-        /// The quantum gate to be applied.
-        gate: QuantumGate,
-
-        /// The target index of the qubit on which the gate is applied.
-        target: usize,
-
-        /// The list of control qubits influencing the gate operation.
-        control: Vec<usize>,
-    },
-
-    /// Measure instruction, measuring specified qubits and recording the result.
-    Measure {
-        /// The list of qubits to be measured.
-        qubits: Vec<usize>,
-
-        /// The index to store the measurement result.
-        output: usize,
-    },
-
-    /// ExpValue instruction, calculating the expected value of a Hamiltonian.
-    ExpValue {
-        /// The Hamiltonian for which the expected value is calculated.
-        hamiltonian: PauliHamiltonian,
-
-        /// The index to store the calculated expected value.
-        output: usize,
-    },
-
-    /// Sample instruction, sampling specified qubits a certain number of times.
-    Sample {
-        /// The list of qubits to be sampled.
-        qubits: Vec<usize>,
-
-        /// The number of shots for the sampling process.
-        shots: u64,
-
-        /// The index to store the sampling results.
-        output: usize,
-    },
-
-    /// Dump instruction, capturing the state of specified qubits.
-    Dump {
-        /// The list of qubits to be dumped.
-        qubits: Vec<usize>,
-
-        /// The index to store the dump data.
-        output: usize,
-    },
-}
-
 /// Enum representing the status of a quantum process.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ProcessStatus {
@@ -257,6 +66,24 @@ pub struct Metadata {
 
     /// Depth of the quantum circuit.
     pub depth: usize,
+
+    /// Physical qubits of the results, if a pass remapped the qubits.
+    #[serde(default)]
+    pub mapping: Option<MappingReport>,
+
+    /// Outcome of the last ZX optimization, see
+    /// [`Process::optimize`](crate::Process::optimize).
+    #[serde(default)]
+    pub optimization: Option<OptimizationReport>,
+
+    /// Report of the last [`PassManager::compile`](crate::passes::PassManager::compile).
+    #[serde(default)]
+    pub compilation: Option<CompilationReport>,
+
+    /// Custom data attached by other tools, see
+    /// [`Process::set_metadata_extension`](crate::Process::set_metadata_extension).
+    #[serde(default)]
+    pub extensions: BTreeMap<String, serde_json::Value>,
 }
 
 impl Metadata {
@@ -273,6 +100,82 @@ impl Metadata {
             execution_time: None,
             gate_count: HashMap::new(),
             depth: 0,
+            mapping: None,
+            optimization: None,
+            compilation: None,
+            extensions: BTreeMap::new(),
+        }
+    }
+}
+
+/// Qubits of a result before and after the passes that remap qubits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QubitMapping {
+    /// Qubits given by the program.
+    pub logical: Vec<usize>,
+
+    /// Physical qubit on which each logical qubit ended.
+    pub physical: Vec<usize>,
+}
+
+impl QubitMapping {
+    /// Returns the physical qubit on which a logical qubit of the result ended.
+    pub fn physical_of(&self, logical: usize) -> Option<usize> {
+        self.logical
+            .iter()
+            .position(|qubit| *qubit == logical)
+            .map(|index| self.physical[index])
+    }
+}
+
+/// Physical qubits of the measurements, samples, and dumps after routing or compaction.
+///
+/// The passes that remap qubits, like [`Routing`](crate::passes::Routing) and
+/// [`QubitCompaction`](crate::passes::QubitCompaction), change the qubits of the
+/// results. The report keeps the qubits given by the program, so a result can be
+/// attributed to its logical qubits after any number of passes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingReport {
+    /// Mapping of each measurement.
+    pub measurements: Vec<QubitMapping>,
+
+    /// Mapping of each sample.
+    pub samples: Vec<QubitMapping>,
+
+    /// Mapping of each dump.
+    pub dumps: Vec<QubitMapping>,
+}
+
+impl MappingReport {
+    /// Creates a report with the logical qubits of the results, not yet remapped.
+    pub(crate) fn new(qubits: [Vec<Vec<usize>>; 3]) -> Self {
+        let [measurements, samples, dumps] = qubits.map(|results| {
+            results
+                .into_iter()
+                .map(|qubits| QubitMapping {
+                    logical: qubits.clone(),
+                    physical: qubits,
+                })
+                .collect()
+        });
+        Self {
+            measurements,
+            samples,
+            dumps,
+        }
+    }
+
+    /// Sets the physical qubits of the results after a pass.
+    pub(crate) fn update(&mut self, qubits: [Vec<Vec<usize>>; 3]) {
+        let [measurements, samples, dumps] = qubits;
+        for (mappings, results) in [
+            (&mut self.measurements, measurements),
+            (&mut self.samples, samples),
+            (&mut self.dumps, dumps),
+        ] {
+            for (mapping, qubits) in mappings.iter_mut().zip(results) {
+                mapping.physical = qubits;
+            }
         }
     }
 }
@@ -295,3 +198,30 @@ pub struct ResultData {
     /// Total execution time of the quantum process.
     pub execution_time: Option<f64>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Metadata;
+    use crate::{Configuration, Process};
+
+    #[test]
+    fn metadata_extensions() {
+        let mut process = Process::new(Configuration::new(1));
+        let value = serde_json::json!({"queue": "short", "priority": 2});
+        assert!(process
+            .set_metadata_extension("scheduler", value.clone())
+            .is_none());
+
+        let metadata: Metadata = serde_json::from_str(&process.metadata_json()).unwrap();
+        assert_eq!(metadata.extensions["scheduler"], value);
+        assert_eq!(process.get_metadata_extension("scheduler"), Some(&value));
+        assert_eq!(process.remove_metadata_extension("scheduler"), Some(value));
+        assert!(process.get_metadata_extension("scheduler").is_none());
+
+        // Metadata serialized before the extensions were added is still read.
+        let mut json: serde_json::Value = serde_json::from_str(&process.metadata_json()).unwrap();
+        json.as_object_mut().unwrap().remove("extensions");
+        let metadata: Metadata = serde_json::from_value(json).unwrap();
+        assert!(metadata.extensions.is_empty());
+    }
+}
@@ -0,0 +1,561 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Two-qubit gate synthesis with the KAK decomposition.
+//!
+//! Every two-qubit unitary is `(A0 ⊗ A1) exp(i(a XX + b YY + c ZZ)) (B0 ⊗ B1)` up to a
+//! global phase, where `A0`, `A1`, `B0`, and `B1` are single-qubit gates. The
+//! interaction coefficients `(a, b, c)` are defined up to shifts of π/2, which are
+//! Pauli gates, and they give the minimal number of CNOTs of the unitary (Shende,
+//! Bullock, and Markov, 2004):
+//!
+//! - 0 if all the coefficients are zero, as the unitary is a product of single-qubit
+//!   gates;
+//! - 1 if one coefficient is π/4 and the others are zero, as for the CNOT;
+//! - 2 if a coefficient is zero;
+//! - 3 otherwise.
+//!
+//! [`decompose`] synthesizes a unitary with its minimal number of CNOTs, and Z and Y
+//! rotations before and after each of them. The single-qubit gates are found by
+//! diagonalizing the unitary in the magic basis, where the product of two single-qubit
+//! gates is a real orthogonal matrix.
+//!
+//! ```
+//! use ket::{kak, Instruction};
+//!
+//! // SWAP takes three CNOTs.
+//! let swap = kak::permutation([0, 2, 1, 3]);
+//! assert_eq!(kak::num_cnots(&swap), 3);
+//!
+//! let (gates, _) = kak::decompose(&swap, [0, 1]);
+//! let cnots = gates
+//!     .iter()
+//!     .filter(|gate| matches!(gate, Instruction::Gate { control, .. } if !control.is_empty()))
+//!     .count();
+//! assert_eq!(cnots, 3);
+//! ```
+
+use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4, PI};
+
+use num::complex::Complex64;
+
+use crate::{passes::canonicalize::gate_matrix, Angle, Instruction, QuantumGate};
+
+/// Matrix of a two-qubit gate, the first qubit being the most significant bit of the
+/// row and column indices.
+pub type Matrix4 = [[Complex64; 4]; 4];
+
+type Matrix2 = [[Complex64; 2]; 2];
+
+/// Tolerance on the interaction coefficients and on the rotation angles.
+const EPSILON: f64 = 1e-9;
+
+/// Tolerance of the match of the eigenvalues of two locally equivalent unitaries.
+const TOLERANCE: f64 = 1e-6;
+
+fn zero() -> Complex64 {
+    Complex64::new(0.0, 0.0)
+}
+
+fn identity() -> Matrix4 {
+    let mut matrix = [[zero(); 4]; 4];
+    for (index, row) in matrix.iter_mut().enumerate() {
+        row[index] = Complex64::new(1.0, 0.0);
+    }
+    matrix
+}
+
+fn mul(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+    let mut result = [[zero(); 4]; 4];
+    for (row, result) in result.iter_mut().enumerate() {
+        for (column, entry) in result.iter_mut().enumerate() {
+            *entry = (0..4).map(|k| a[row][k] * b[k][column]).sum();
+        }
+    }
+    result
+}
+
+fn dagger(a: &Matrix4) -> Matrix4 {
+    let mut result = [[zero(); 4]; 4];
+    for (row, result) in result.iter_mut().enumerate() {
+        for (column, entry) in result.iter_mut().enumerate() {
+            *entry = a[column][row].conj();
+        }
+    }
+    result
+}
+
+fn transpose(a: &Matrix4) -> Matrix4 {
+    let mut result = [[zero(); 4]; 4];
+    for (row, result) in result.iter_mut().enumerate() {
+        for (column, entry) in result.iter_mut().enumerate() {
+            *entry = a[column][row];
+        }
+    }
+    result
+}
+
+fn real(a: &[[f64; 4]; 4]) -> Matrix4 {
+    a.map(|row| row.map(|x| Complex64::new(x, 0.0)))
+}
+
+fn kron(a: &Matrix2, b: &Matrix2) -> Matrix4 {
+    let mut result = [[zero(); 4]; 4];
+    for (row, result) in result.iter_mut().enumerate() {
+        for (column, entry) in result.iter_mut().enumerate() {
+            *entry = a[row >> 1][column >> 1] * b[row & 1][column & 1];
+        }
+    }
+    result
+}
+
+fn det(a: &Matrix4) -> Complex64 {
+    // Laplace expansion along the first row.
+    let minor = |skip: usize| {
+        let columns: Vec<usize> = (0..4).filter(|column| *column != skip).collect();
+        let m = |row: usize, column: usize| a[row][columns[column]];
+        m(1, 0) * (m(2, 1) * m(3, 2) - m(2, 2) * m(3, 1))
+            - m(1, 1) * (m(2, 0) * m(3, 2) - m(2, 2) * m(3, 0))
+            + m(1, 2) * (m(2, 0) * m(3, 1) - m(2, 1) * m(3, 0))
+    };
+    (0..4)
+        .map(|column| {
+            let sign = if column % 2 == 0 { 1.0 } else { -1.0 };
+            a[0][column] * minor(column) * sign
+        })
+        .sum()
+}
+
+fn distance(a: &Matrix4, b: &Matrix4) -> f64 {
+    a.iter()
+        .flatten()
+        .zip(b.iter().flatten())
+        .map(|(a, b)| (a - b).norm())
+        .fold(0.0, f64::max)
+}
+
+/// Magic basis, where the product of two single-qubit gates of determinant 1 is a real
+/// orthogonal matrix.
+fn magic() -> Matrix4 {
+    let (o, h, i) = (
+        zero(),
+        Complex64::new(FRAC_1_SQRT_2, 0.0),
+        Complex64::new(0.0, FRAC_1_SQRT_2),
+    );
+    [[h, i, o, o], [o, o, i, h], [o, o, i, -h], [h, -i, o, o]]
+}
+
+/// Returns the unitary divided by a fourth root of its determinant.
+fn special(matrix: &Matrix4) -> Matrix4 {
+    let root = Complex64::from_polar(1.0, det(matrix).arg() / 4.0);
+    matrix.map(|row| row.map(|x| x / root))
+}
+
+/// Returns the eigenvectors, by column, of a real symmetric matrix with the Jacobi
+/// method.
+fn jacobi(mut a: [[f64; 4]; 4]) -> [[f64; 4]; 4] {
+    let mut vectors = [[0.0; 4]; 4];
+    for (index, row) in vectors.iter_mut().enumerate() {
+        row[index] = 1.0;
+    }
+    for _ in 0..64 {
+        let off: f64 = (0..4)
+            .flat_map(|p| (0..4).filter(move |q| *q != p).map(move |q| (p, q)))
+            .map(|(p, q)| a[p][q] * a[p][q])
+            .sum();
+        if off < 1e-30 {
+            break;
+        }
+        for p in 0..4 {
+            for q in p + 1..4 {
+                if a[p][q].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let t = if theta == 0.0 { 1.0 } else { t };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (akp, akq) = (row[p], row[q]);
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let (above, below) = a.split_at_mut(q);
+                for (apk, aqk) in above[p].iter_mut().zip(below[0].iter_mut()) {
+                    (*apk, *aqk) = (c * *apk - s * *aqk, s * *apk + c * *aqk);
+                }
+                for row in vectors.iter_mut() {
+                    let (vp, vq) = (row[p], row[q]);
+                    row[p] = c * vp - s * vq;
+                    row[q] = s * vp + c * vq;
+                }
+            }
+        }
+    }
+    vectors
+}
+
+/// Weights of the imaginary part in the combinations tried by [`diagonalize`]. They are
+/// arbitrary, so a combination with degenerate eigenvalues is unlikely to repeat.
+const WEIGHTS: [f64; 5] = [0.5317, 1.4629, -0.6841, 2.3917, 0.2753];
+
+/// Diagonalizes a symmetric unitary with a real orthogonal matrix of determinant 1,
+/// returned with the eigenvalues.
+///
+/// The real and imaginary parts of the matrix are real symmetric matrices that commute,
+/// so the eigenvectors of a generic combination of them diagonalize both.
+fn diagonalize(matrix: &Matrix4) -> ([[f64; 4]; 4], [Complex64; 4]) {
+    let mut best: Option<(f64, [[f64; 4]; 4], [Complex64; 4])> = None;
+    for weight in WEIGHTS {
+        let combination = [0, 1, 2, 3].map(|row| {
+            [0, 1, 2, 3].map(|column| matrix[row][column].re + weight * matrix[row][column].im)
+        });
+        let mut vectors = jacobi(combination);
+        if det(&real(&vectors)).re < 0.0 {
+            vectors.iter_mut().for_each(|row| row[0] = -row[0]);
+        }
+
+        let diagonal = mul(&mul(&transpose(&real(&vectors)), matrix), &real(&vectors));
+        let error = (0..16)
+            .filter(|index| index / 4 != index % 4)
+            .map(|index| diagonal[index / 4][index % 4].norm())
+            .fold(0.0, f64::max);
+        if best.as_ref().is_none_or(|(best, ..)| error < *best) {
+            best = Some((
+                error,
+                vectors,
+                [0, 1, 2, 3].map(|index| diagonal[index][index]),
+            ));
+        }
+        if error < EPSILON {
+            break;
+        }
+    }
+    let (_, vectors, values) = best.unwrap();
+    (vectors, values)
+}
+
+/// Returns the single-qubit gates of a product of two single-qubit gates.
+fn factor(matrix: &Matrix4) -> (Matrix2, Matrix2) {
+    // The block of the largest entry is proportional to the first gate.
+    let (row, column) = (0..16)
+        .map(|index| (index / 4, index % 4))
+        .max_by(|a, b| matrix[a.0][a.1].norm().total_cmp(&matrix[b.0][b.1].norm()))
+        .unwrap();
+    let (k, l) = (row & 1, column & 1);
+    let mut first = [[zero(); 2]; 2];
+    for (i, first) in first.iter_mut().enumerate() {
+        for (j, entry) in first.iter_mut().enumerate() {
+            *entry = matrix[2 * i + k][2 * j + l];
+        }
+    }
+    let root = (first[0][0] * first[1][1] - first[0][1] * first[1][0]).sqrt();
+    let first = first.map(|row| row.map(|x| x / root));
+
+    let mut second = [[zero(); 2]; 2];
+    for (k, second) in second.iter_mut().enumerate() {
+        for (l, entry) in second.iter_mut().enumerate() {
+            *entry = (0..4)
+                .map(|index| {
+                    let (i, j) = (index / 2, index % 2);
+                    first[i][j].conj() * matrix[2 * i + k][2 * j + l]
+                })
+                .sum::<Complex64>()
+                / 2.0;
+        }
+    }
+    (first, second)
+}
+
+/// Global phase, and the single-qubit gates applied after and before the template, see
+/// [`local_equivalence`].
+type LocalEquivalence = (f64, (Matrix2, Matrix2), (Matrix2, Matrix2));
+
+/// Returns the local gates and the global phase that make `template` equal to
+/// `matrix`, that is, `matrix = exp(i phase) (after) template (before)`, or `None` if
+/// the matrices are not locally equivalent.
+fn local_equivalence(matrix: &Matrix4, template: &Matrix4) -> Option<LocalEquivalence> {
+    let magic = magic();
+    let to_magic = |matrix: &Matrix4| mul(&mul(&dagger(&magic), matrix), &magic);
+    let from_magic = |matrix: &Matrix4| mul(&mul(&magic, matrix), &dagger(&magic));
+    let gamma = |matrix: &Matrix4| mul(&transpose(matrix), matrix);
+
+    let template_magic = to_magic(&special(template));
+    let (template_vectors, template_values) = diagonalize(&gamma(&template_magic));
+
+    // Multiplying the matrix by i keeps its determinant and negates the eigenvalues.
+    for rotation in [Complex64::new(1.0, 0.0), Complex64::new(0.0, 1.0)] {
+        let matrix_magic = to_magic(&special(matrix).map(|row| row.map(|x| x * rotation)));
+        let (vectors, values) = diagonalize(&gamma(&matrix_magic));
+
+        // The eigenvectors of the matrix in the order of the template eigenvalues.
+        let mut order = Vec::with_capacity(4);
+        for value in template_values {
+            let closest = (0..4)
+                .filter(|index| !order.contains(index))
+                .min_by(|a, b| {
+                    (values[*a] - value)
+                        .norm()
+                        .total_cmp(&(values[*b] - value).norm())
+                })
+                .unwrap();
+            if (values[closest] - value).norm() > TOLERANCE {
+                break;
+            }
+            order.push(closest);
+        }
+        if order.len() < 4 {
+            continue;
+        }
+        let mut sorted =
+            [0, 1, 2, 3].map(|row| [0, 1, 2, 3].map(|column| vectors[row][order[column]]));
+        if det(&real(&sorted)).re < 0.0 {
+            sorted.iter_mut().for_each(|row| row[0] = -row[0]);
+        }
+
+        // matrix = K1 D P^T and template = L1 D Q^T in the magic basis, with the same
+        // diagonal D, so matrix = (K1 L1^T) template (Q P^T).
+        let roots = template_values.map(|value| value.sqrt());
+        let inverse_roots = |vectors: &[[f64; 4]; 4]| {
+            let mut matrix = real(vectors);
+            for row in matrix.iter_mut() {
+                for (column, entry) in row.iter_mut().enumerate() {
+                    *entry /= roots[column];
+                }
+            }
+            matrix
+        };
+        let k1 = mul(&matrix_magic, &inverse_roots(&sorted));
+        let l1 = mul(&template_magic, &inverse_roots(&template_vectors));
+        let after = from_magic(&mul(&k1, &transpose(&l1)));
+        let before = from_magic(&mul(&real(&template_vectors), &transpose(&real(&sorted))));
+
+        let after = factor(&after);
+        let before = factor(&before);
+        let synthesized = mul(
+            &mul(&kron(&after.0, &after.1), template),
+            &kron(&before.0, &before.1),
+        );
+        let (row, column) = (0..16)
+            .map(|index| (index / 4, index % 4))
+            .max_by(|a, b| matrix[a.0][a.1].norm().total_cmp(&matrix[b.0][b.1].norm()))
+            .unwrap();
+        let phase = (matrix[row][column] / synthesized[row][column]).arg();
+        let rotated = synthesized.map(|row| row.map(|x| x * Complex64::from_polar(1.0, phase)));
+        if distance(&rotated, matrix) < TOLERANCE {
+            return Some((phase, after, before));
+        }
+    }
+    None
+}
+
+/// Returns the interaction coefficients of a two-qubit unitary, each between -π/4 and
+/// π/4.
+pub fn coefficients(matrix: &Matrix4) -> [f64; 3] {
+    let magic = magic();
+    let matrix = mul(&mul(&dagger(&magic), &special(matrix)), &magic);
+    let (_, values) = diagonalize(&mul(&transpose(&matrix), &matrix));
+
+    // The square roots of the eigenvalues are the phases of the interaction in the
+    // magic basis, a - b + c, -a + b + c, a + b - c, and -a - b - c, which add up to 0.
+    let mut phases = values.map(|value| value.arg() / 2.0);
+    if ((phases.iter().sum::<f64>() / PI).round() as i64).rem_euclid(2) == 1 {
+        phases[0] += PI;
+    }
+    let reduce = |x: f64| {
+        let x = x - FRAC_PI_2 * (x / FRAC_PI_2).round();
+        if x < -FRAC_PI_4 + EPSILON {
+            x + FRAC_PI_2
+        } else {
+            x
+        }
+    };
+    [
+        reduce((phases[0] + phases[2]) / 2.0),
+        reduce((phases[1] + phases[2]) / 2.0),
+        reduce((phases[0] + phases[1]) / 2.0),
+    ]
+}
+
+/// Returns the minimal number of CNOTs of a two-qubit unitary, see the
+/// [module documentation](self).
+pub fn num_cnots(matrix: &Matrix4) -> usize {
+    num_cnots_of(coefficients(matrix))
+}
+
+fn gate(gate: QuantumGate, target: usize, control: &[usize]) -> Instruction {
+    Instruction::Gate {
+        gate,
+        target,
+        control: control.to_vec(),
+    }
+}
+
+/// Returns the Z, Y, and Z rotations of a single-qubit gate, in the order they are
+/// applied, and the global phase they leave out.
+fn euler(matrix: &Matrix2, target: usize) -> (Vec<Instruction>, f64) {
+    let det = matrix[0][0] * matrix[1][1] - matrix[0][1] * matrix[1][0];
+    let phase = det.arg() / 2.0;
+    let special = matrix.map(|row| row.map(|x| x * Complex64::from_polar(1.0, -phase)));
+
+    // special = Rz(beta) Ry(gamma) Rz(delta)
+    let gamma = 2.0 * special[1][0].norm().atan2(special[0][0].norm());
+    let sum = if special[1][1].norm() > EPSILON {
+        2.0 * special[1][1].arg()
+    } else {
+        0.0
+    };
+    let difference = if special[1][0].norm() > EPSILON {
+        2.0 * special[1][0].arg()
+    } else {
+        0.0
+    };
+    let (beta, delta) = ((sum + difference) / 2.0, (sum - difference) / 2.0);
+
+    let gates = [
+        (delta, QuantumGate::RotationZ(Angle::Scalar(delta))),
+        (gamma, QuantumGate::RotationY(Angle::Scalar(gamma))),
+        (beta, QuantumGate::RotationZ(Angle::Scalar(beta))),
+    ]
+    .into_iter()
+    .filter(|(angle, _)| angle.abs() > EPSILON)
+    .map(|(_, rotation)| gate(rotation, target, &[]))
+    .collect();
+    (gates, phase)
+}
+
+/// Returns the CNOTs and rotations of a template with the given interaction
+/// coefficients.
+fn template(coefficients: [f64; 3], qubits: [usize; 2]) -> Vec<Instruction> {
+    let [a, b, c] = coefficients;
+    let [first, second] = qubits;
+    let cnot = |control: usize, target: usize| gate(QuantumGate::PauliX, target, &[control]);
+    let rotation = |gate_of: fn(Angle) -> QuantumGate, angle: f64, target: usize| {
+        gate(gate_of(Angle::Scalar(angle)), target, &[])
+    };
+
+    match num_cnots_of(coefficients) {
+        0 => Vec::new(),
+        1 => vec![cnot(first, second)],
+        2 => {
+            // CNOT (Rx(-2x) ⊗ Rz(-2z)) CNOT = exp(i(x XX + z ZZ)), with the coefficients
+            // that are not zero.
+            let mut nonzero = coefficients.into_iter().filter(|x| x.abs() >= EPSILON);
+            let x = nonzero.next().unwrap_or(0.0);
+            let z = nonzero.next().unwrap_or(0.0);
+            vec![
+                cnot(first, second),
+                rotation(QuantumGate::RotationX, -2.0 * x, first),
+                rotation(QuantumGate::RotationZ, -2.0 * z, second),
+                cnot(first, second),
+            ]
+        }
+        // Vatan and Williams, 2004.
+        _ => vec![
+            cnot(second, first),
+            rotation(QuantumGate::RotationZ, FRAC_PI_2 - 2.0 * c, first),
+            rotation(QuantumGate::RotationY, -FRAC_PI_2 - 2.0 * a, second),
+            cnot(first, second),
+            rotation(QuantumGate::RotationY, FRAC_PI_2 + 2.0 * b, second),
+            cnot(second, first),
+        ],
+    }
+}
+
+fn num_cnots_of(coefficients: [f64; 3]) -> usize {
+    let zeros = coefficients.iter().filter(|x| x.abs() < EPSILON).count();
+    let quarters = coefficients
+        .iter()
+        .filter(|x| (x.abs() - FRAC_PI_4).abs() < EPSILON)
+        .count();
+    match (zeros, quarters) {
+        (3, _) => 0,
+        (2, 1) => 1,
+        (1.., _) => 2,
+        _ => 3,
+    }
+}
+
+/// Returns the matrix of the gates on two qubits, or `None` if an instruction is not a
+/// gate on them with at most one control qubit.
+pub fn matrix(instructions: &[Instruction], qubits: [usize; 2]) -> Option<Matrix4> {
+    let (one, o) = (Complex64::new(1.0, 0.0), zero());
+    let identity2 = [[one, o], [o, one]];
+    let mut result = identity();
+    for instruction in instructions {
+        let Instruction::Gate {
+            gate,
+            target,
+            control,
+        } = instruction
+        else {
+            return None;
+        };
+        let position = |qubit: &usize| qubits.iter().position(|other| other == qubit);
+        let target_position = position(target)?;
+        let single = gate_matrix(gate);
+        let matrix = match control.as_slice() {
+            [] if target_position == 0 => kron(&single, &identity2),
+            [] => kron(&identity2, &single),
+            [control] if position(control)? != target_position => {
+                let mut matrix = identity();
+                let control_bit = 1 - position(control)?;
+                let target_bit = 1 - target_position;
+                for column in (0..4).filter(|column| column >> control_bit & 1 == 1) {
+                    for row in (0..4).filter(|row| row >> control_bit & 1 == 1) {
+                        if (row ^ column) & !(1 << target_bit) == 0 {
+                            matrix[row][column] =
+                                single[row >> target_bit & 1][column >> target_bit & 1];
+                        }
+                    }
+                }
+                matrix
+            }
+            _ => return None,
+        };
+        result = mul(&matrix, &result);
+    }
+    Some(result)
+}
+
+/// Returns the matrix that maps the basis state `i` to `permutation[i]`.
+pub fn permutation(permutation: [usize; 4]) -> Matrix4 {
+    let mut matrix = [[zero(); 4]; 4];
+    for (column, row) in permutation.into_iter().enumerate() {
+        matrix[row][column] = Complex64::new(1.0, 0.0);
+    }
+    matrix
+}
+
+/// Returns the gates of a two-qubit unitary, with its minimal number of CNOTs, and the
+/// global phase they leave out, see the [module documentation](self).
+///
+/// The first qubit is the most significant bit of the row and column indices of the
+/// matrix.
+///
+/// # Panics
+///
+/// Panics if the matrix is not unitary.
+pub fn decompose(matrix: &Matrix4, qubits: [usize; 2]) -> (Vec<Instruction>, f64) {
+    let gates = template(coefficients(matrix), qubits);
+    let template_matrix = self::matrix(&gates, qubits).unwrap();
+    let (phase, after, before) =
+        local_equivalence(matrix, &template_matrix).expect("the matrix must be unitary");
+
+    let (before_first, phase_a) = euler(&before.0, qubits[0]);
+    let (before_second, phase_b) = euler(&before.1, qubits[1]);
+    let (after_first, phase_c) = euler(&after.0, qubits[0]);
+    let (after_second, phase_d) = euler(&after.1, qubits[1]);
+
+    let instructions = before_first
+        .into_iter()
+        .chain(before_second)
+        .chain(gates)
+        .chain(after_first)
+        .chain(after_second)
+        .collect();
+    (instructions, phase + phase_a + phase_b + phase_c + phase_d)
+}
@@ -31,16 +31,59 @@
 //! serde_json = "1.0"
 //! kbw = "0.2.0"
 //! ```
+//!
+//! ## Telemetry
+//!
+//! Libket reports its work with [`tracing`](https://docs.rs/tracing). The optimization
+//! phases (`optimize`, `optimize_with` and its `section`s, `optimize_around_opaque`,
+//! `warm_start`) and the executions (`execute`, `submit_chunk`) run in spans with the
+//! number of instructions and qubits of the circuit as fields, so a subscriber can
+//! filter and time them. Without a subscriber, the events go to the `log` crate.
 
+pub mod amplification;
+pub mod basis;
+pub mod bloch;
 pub mod c_api;
-pub mod error;
+pub mod cloud;
+pub mod compression;
+pub mod debugger;
+pub mod distribution;
+pub mod dsl;
+pub mod encoders;
+pub mod equivalence;
+pub mod events;
 pub mod execution;
+#[cfg(feature = "experiments")]
+pub mod experiments;
+pub mod grouping;
+pub mod handle;
+pub mod hashing;
 pub mod ir;
+pub mod kak;
+pub mod multiplexor;
+pub mod npy;
 pub mod objects;
+pub mod passes;
+pub mod phase_estimation;
 pub mod process;
+pub mod provenance;
 pub mod qasmv2;
+pub mod qasmv3;
+pub mod remote;
+pub mod shadows;
+pub mod state_format;
+pub mod state_preparation;
+pub mod structure;
+pub mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod trace;
 pub mod zx;
 
+pub use ket_core::{circuit, error, hamiltonian};
+
+pub use circuit::{instruction::*, CircuitBuilder, InstructionSink};
+pub use events::ExecutionEvent;
 pub use execution::*;
 pub use ir::*;
 pub use objects::*;
@@ -0,0 +1,220 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Diagonal gates and multiplexed rotations.
+//!
+//! A diagonal gate multiplies each basis state of its qubits by a phase, and a
+//! multiplexed rotation, or uniformly controlled rotation, rotates its target by a
+//! different angle for each value of its control qubits. With the control stack, both
+//! take a multi-controlled gate per basis state; they are the building blocks of state
+//! preparation and of the phase arithmetic of QFT-based circuits.
+//!
+//! [`Process::apply_diagonal`](crate::Process::apply_diagonal) and
+//! [`Process::apply_multiplexor`](crate::Process::apply_multiplexor) append them as
+//! single instructions, which simulators can apply in one pass over the state. For the
+//! exports and the executors without a native kernel, [`lower`] synthesizes them with
+//! CNOTs and rotations:
+//!
+//! - A multiplexed rotation with `k` controls takes `2^k` rotations on the target and
+//!   `2^k` CNOTs, with the controls in Gray code order (Möttönen et al., 2004). X
+//!   rotations are Z rotations in the Hadamard basis.
+//! - A diagonal gate on `n` qubits is a multiplexed Z rotation on each qubit, controlled
+//!   by the qubits before it, up to a global phase.
+//!
+//! ```
+//! use ket::{multiplexor::diagonal_gates, Instruction, QuantumGate};
+//!
+//! // CZ, up to a global phase.
+//! let pi = std::f64::consts::PI;
+//! let (gates, phase) = diagonal_gates(&[0.0, 0.0, 0.0, pi], &[0, 1]);
+//! assert!((phase - pi / 4.0).abs() < 1e-12);
+//! let cnots = gates
+//!     .iter()
+//!     .filter(|gate| matches!(gate, Instruction::Gate { gate: QuantumGate::PauliX, .. }))
+//!     .count();
+//! assert_eq!(cnots, 2);
+//! ```
+
+use std::borrow::Cow;
+
+use crate::{Angle, Instruction, Pauli, QuantumGate};
+
+/// Rotations with a smaller angle are left out of the synthesis.
+const EPSILON: f64 = 1e-12;
+
+fn gate(gate: QuantumGate, target: usize, control: &[usize]) -> Instruction {
+    Instruction::Gate {
+        gate,
+        target,
+        control: control.to_vec(),
+    }
+}
+
+/// Returns the rotation around `axis` by `angle`.
+pub fn rotation(axis: Pauli, angle: f64) -> QuantumGate {
+    match axis {
+        Pauli::PauliX => QuantumGate::RotationX(Angle::Scalar(angle)),
+        Pauli::PauliY => QuantumGate::RotationY(Angle::Scalar(angle)),
+        Pauli::PauliZ => QuantumGate::RotationZ(Angle::Scalar(angle)),
+    }
+}
+
+/// Returns the angles of the rotations of the Gray code synthesis.
+///
+/// Before the rotation `i`, the target was flipped by the controls set in the Gray code
+/// of `i`, which inverts the rotation, so the angle of the control value `j` is the sum
+/// of the rotations with a sign for the parity of `j & gray(i)`.
+fn gray_code_angles(angles: &[f64]) -> Vec<f64> {
+    let size = angles.len();
+    (0..size)
+        .map(|i| {
+            let gray = i ^ (i >> 1);
+            angles
+                .iter()
+                .enumerate()
+                .map(|(j, angle)| {
+                    if (j & gray).count_ones() % 2 == 0 {
+                        *angle
+                    } else {
+                        -*angle
+                    }
+                })
+                .sum::<f64>()
+                / size as f64
+        })
+        .collect()
+}
+
+/// Returns the gates of a multiplexed rotation, see the [module documentation](self).
+///
+/// The rotation is not controlled if its angles are the same for all values of the
+/// controls, and no gate is returned if they are all zero.
+///
+/// # Panics
+///
+/// Panics if the number of angles is not two to the number of controls.
+pub fn multiplexor_gates(
+    axis: Pauli,
+    angles: &[f64],
+    control: &[usize],
+    target: usize,
+) -> Vec<Instruction> {
+    assert_eq!(angles.len(), 1 << control.len());
+
+    if angles
+        .iter()
+        .all(|angle| (angle - angles[0]).abs() < EPSILON)
+    {
+        return if angles[0].abs() < EPSILON {
+            Vec::new()
+        } else {
+            vec![gate(rotation(axis, angles[0]), target, &[])]
+        };
+    }
+
+    // Y and Z rotations are inverted by X, but X rotations commute with it.
+    let basis = if axis == Pauli::PauliX {
+        vec![gate(QuantumGate::Hadamard, target, &[])]
+    } else {
+        Vec::new()
+    };
+    let axis = if axis == Pauli::PauliX {
+        Pauli::PauliZ
+    } else {
+        axis
+    };
+
+    let size = angles.len();
+    let gray = |i: usize| i ^ (i >> 1);
+    let mut gates = basis.clone();
+    for (i, angle) in gray_code_angles(angles).into_iter().enumerate() {
+        if angle.abs() >= EPSILON {
+            gates.push(gate(rotation(axis, angle), target, &[]));
+        }
+        // Bit that changes in the next Gray code, the last control being the first bit.
+        let bit = (gray(i) ^ gray((i + 1) % size)).trailing_zeros() as usize;
+        let qubit = control[control.len() - 1 - bit];
+        gates.push(gate(QuantumGate::PauliX, target, &[qubit]));
+    }
+    gates.extend(basis);
+    gates
+}
+
+/// Returns the gates of a diagonal gate and the global phase they leave out, see the
+/// [module documentation](self).
+///
+/// # Panics
+///
+/// Panics if the number of phases is not two to the number of qubits.
+pub fn diagonal_gates(phases: &[f64], qubits: &[usize]) -> (Vec<Instruction>, f64) {
+    assert_eq!(phases.len(), 1 << qubits.len());
+
+    // Each pair of phases of the last qubit is a Z rotation by their difference, and
+    // their mean is a phase of the qubits before it.
+    let mut phases = phases.to_vec();
+    let mut gates = Vec::new();
+    for level in (0..qubits.len()).rev() {
+        let angles: Vec<f64> = phases.chunks(2).map(|pair| pair[1] - pair[0]).collect();
+        gates.extend(multiplexor_gates(
+            Pauli::PauliZ,
+            &angles,
+            &qubits[..level],
+            qubits[level],
+        ));
+        phases = phases
+            .chunks(2)
+            .map(|pair| (pair[0] + pair[1]) / 2.0)
+            .collect();
+    }
+    (gates, phases[0])
+}
+
+/// Returns the gates of a diagonal gate or multiplexed rotation and the global phase
+/// they leave out, or `None` for the other instructions.
+pub fn synthesize(instruction: &Instruction) -> Option<(Vec<Instruction>, f64)> {
+    match instruction {
+        Instruction::Diagonal { phases, qubits } => Some(diagonal_gates(phases, qubits)),
+        Instruction::Multiplexor {
+            axis,
+            angles,
+            control,
+            target,
+        } => Some((multiplexor_gates(*axis, angles, control, *target), 0.0)),
+        _ => None,
+    }
+}
+
+/// Replaces the diagonal gates and multiplexed rotations with their gates, dropping
+/// their global phases.
+///
+/// The instructions are borrowed if there is nothing to replace.
+pub fn lower(instructions: &[Instruction]) -> Cow<'_, [Instruction]> {
+    if instructions.iter().all(|instruction| {
+        !matches!(
+            instruction,
+            Instruction::Diagonal { .. } | Instruction::Multiplexor { .. }
+        )
+    }) {
+        return Cow::Borrowed(instructions);
+    }
+
+    Cow::Owned(
+        instructions
+            .iter()
+            .flat_map(|instruction| match synthesize(instruction) {
+                Some((gates, _)) => gates,
+                None => vec![instruction.clone()],
+            })
+            .collect(),
+    )
+}
+
+/// Returns the index of a basis state in the phases of a diagonal gate or the angles of
+/// a multiplexed rotation, the first qubit being the most significant bit.
+pub fn index(qubits: &[usize], is_one: impl Fn(usize) -> bool) -> usize {
+    qubits
+        .iter()
+        .fold(0, |index, qubit| index << 1 | is_one(*qubit) as usize)
+}
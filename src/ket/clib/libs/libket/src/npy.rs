@@ -0,0 +1,250 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! State vectors in the NumPy `.npy` format.
+//!
+//! [`DumpData::to_npy`] writes a dump as a one-dimensional `complex128` array, and
+//! [`Configuration::initial_state_from_npy`] loads an array as the initial state of the
+//! first qubits of a process, so states move between NumPy workflows and KBW.
+//!
+//! The index of an amplitude is the basis state of the dump, whose bits follow
+//! [`Configuration::bit_order`]. With [`BitOrder::MsbFirst`], the default, the first
+//! qubit is the most significant bit of the index, as in `np.kron(q0, q1, ...)`. With
+//! [`BitOrder::LsbFirst`], the first qubit is the least significant bit, as in the
+//! `Statevector` of Qiskit, whose `data` array is read and written without reshuffling.
+//!
+//! ```no_run
+//! # use ket::error::KetError;
+//! use ket::{BitOrder, Configuration};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! // Statevector.data saved from Qiskit with np.save("state.npy", state.data).
+//! let mut configuration = Configuration::new(4);
+//! configuration.bit_order = BitOrder::LsbFirst;
+//! configuration.initial_state_from_npy("state.npy")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fs, path::Path};
+
+use num::complex::Complex64;
+
+use crate::{
+    basis,
+    error::{KetError, Result},
+    execution::Configuration,
+    ir::DumpData,
+};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Largest number of qubits of a state vector file.
+pub const MAX_NPY_QUBITS: usize = 32;
+
+/// Writes the amplitudes as a one-dimensional `complex128` array.
+///
+/// # Errors
+///
+/// Returns [`KetError::InvalidNpyFile`] if the file cannot be written.
+pub fn write(path: impl AsRef<Path>, amplitudes: &[Complex64]) -> Result<()> {
+    let mut header = format!(
+        "{{'descr': '<c16', 'fortran_order': False, 'shape': ({},), }}",
+        amplitudes.len()
+    );
+    // The data starts at a multiple of 64 bytes, after the magic string, the version,
+    // the header length, and the header ending with a newline.
+    let unpadded = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(unpadded + 64 + 16 * amplitudes.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for amplitude in amplitudes {
+        bytes.extend_from_slice(&amplitude.re.to_le_bytes());
+        bytes.extend_from_slice(&amplitude.im.to_le_bytes());
+    }
+    fs::write(path, bytes).map_err(|_| KetError::InvalidNpyFile)
+}
+
+/// Reads a little-endian `complex128`, `complex64`, `float64`, or `float32` array in C
+/// order, of any shape.
+///
+/// # Errors
+///
+/// Returns [`KetError::InvalidNpyFile`] if the file cannot be read, or is not an array
+/// of one of these types.
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<Complex64>> {
+    let bytes = fs::read(path).map_err(|_| KetError::InvalidNpyFile)?;
+    parse(&bytes).ok_or(KetError::InvalidNpyFile)
+}
+
+fn parse(bytes: &[u8]) -> Option<Vec<Complex64>> {
+    let version = bytes.strip_prefix(MAGIC)?;
+    let (header, data) = match version.first()? {
+        1 => {
+            let len = u16::from_le_bytes(version.get(2..4)?.try_into().ok()?) as usize;
+            version.get(4..)?.split_at_checked(len)?
+        }
+        2 | 3 => {
+            let len = u32::from_le_bytes(version.get(2..6)?.try_into().ok()?) as usize;
+            version.get(6..)?.split_at_checked(len)?
+        }
+        _ => return None,
+    };
+    let header = std::str::from_utf8(header).ok()?;
+
+    let value = |key: &str| {
+        let start = header.find(&format!("'{key}'"))? + key.len() + 2;
+        let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+        Some(rest)
+    };
+    if !value("fortran_order")?.starts_with("False") {
+        return None;
+    }
+    let shape = value("shape")?.strip_prefix('(')?;
+    let len = shape[..shape.find(')')?]
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .try_fold(1usize, |len, dim| len.checked_mul(dim.parse().ok()?))?;
+
+    let descr = value("descr")?;
+    let descr = &descr[1..descr[1..].find(['\'', '"'])? + 1];
+    let (size, convert): (usize, fn(&[u8]) -> Complex64) = match descr {
+        "<c16" => (16, |x| Complex64::new(f64_at(&x[..8]), f64_at(&x[8..]))),
+        "<c8" => (8, |x| Complex64::new(f32_at(&x[..4]), f32_at(&x[4..]))),
+        "<f8" => (8, |x| Complex64::new(f64_at(x), 0.0)),
+        "<f4" => (4, |x| Complex64::new(f32_at(x), 0.0)),
+        _ => return None,
+    };
+    let data = data.get(..len.checked_mul(size)?)?;
+    Some(data.chunks_exact(size).map(convert).collect())
+}
+
+fn f64_at(bytes: &[u8]) -> f64 {
+    f64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+fn f32_at(bytes: &[u8]) -> f64 {
+    f32::from_le_bytes(bytes.try_into().unwrap()) as f64
+}
+
+impl DumpData {
+    /// Writes the state of the `num_qubits` dumped qubits as a `complex128` array of
+    /// `2^num_qubits` amplitudes, see [`npy`](crate::npy).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidNpyFile`] if `num_qubits` exceeds [`MAX_NPY_QUBITS`],
+    /// if a basis state does not fit in `num_qubits` bits, or if the file cannot be
+    /// written.
+    pub fn to_npy(&self, path: impl AsRef<Path>, num_qubits: usize) -> Result<()> {
+        if num_qubits > MAX_NPY_QUBITS {
+            return Err(KetError::InvalidNpyFile);
+        }
+        let mut amplitudes = vec![Complex64::default(); 1 << num_qubits];
+        for ((state, real), imag) in self
+            .basis_states
+            .iter()
+            .zip(&self.amplitudes_real)
+            .zip(&self.amplitudes_imag)
+        {
+            let index = basis::to_u64(state)
+                .and_then(|index| amplitudes.get_mut(index as usize))
+                .ok_or(KetError::InvalidNpyFile)?;
+            *index = Complex64::new(*real, *imag);
+        }
+        write(path, &amplitudes)
+    }
+}
+
+impl Configuration {
+    /// Reads the initial state of the first qubits of the process from a `.npy` file,
+    /// see [`Configuration::initial_state`] and [`npy`](crate::npy).
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`read`], and [`KetError::InvalidInitialState`] if the
+    /// array is not a normalized state of at most `num_qubits` qubits.
+    pub fn initial_state_from_npy(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let state = read(path)?;
+        let len = state.len();
+        let norm: f64 = state.iter().map(Complex64::norm_sqr).sum();
+        if len < 2
+            || !len.is_power_of_two()
+            || len.trailing_zeros() as usize > self.num_qubits
+            || (norm - 1.0).abs() > 1e-6
+        {
+            return Err(KetError::InvalidInitialState);
+        }
+        self.initial_state = Some(state);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::complex::Complex64;
+
+    use super::{parse, read, write};
+    use crate::{error::KetError, BitOrder, Configuration, DumpData};
+
+    #[test]
+    fn round_trip() -> Result<(), KetError> {
+        let path = std::env::temp_dir().join(format!("ket-npy-{}.npy", std::process::id()));
+        let dump = DumpData {
+            basis_states: vec![vec![0], vec![3]],
+            amplitudes_real: vec![0.6, 0.0],
+            amplitudes_imag: vec![0.0, -0.8],
+        };
+        dump.to_npy(&path, 2)?;
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), 128 + 4 * 16);
+        assert!(std::str::from_utf8(&bytes[10..128])
+            .unwrap()
+            .contains("'shape': (4,)"));
+        let state = read(&path)?;
+        assert_eq!(
+            state,
+            [
+                Complex64::new(0.6, 0.0),
+                Complex64::default(),
+                Complex64::default(),
+                Complex64::new(0.0, -0.8),
+            ]
+        );
+
+        let mut configuration = Configuration::new(2);
+        configuration.bit_order = BitOrder::LsbFirst;
+        configuration.initial_state_from_npy(&path)?;
+        assert_eq!(configuration.initial_state.as_deref(), Some(&state[..]));
+        assert!(Configuration::new(1).initial_state_from_npy(&path).is_err());
+
+        write(&path, &[Complex64::from(1.0); 2])?;
+        assert!(matches!(
+            Configuration::new(1).initial_state_from_npy(&path),
+            Err(KetError::InvalidInitialState)
+        ));
+        std::fs::remove_file(&path).unwrap();
+
+        // A float32 array of shape (2, 1), as written by NumPy.
+        let mut bytes = b"\x93NUMPY\x01\x00\x76\x00".to_vec();
+        let header = "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 1), }";
+        bytes.extend_from_slice(format!("{header:<117}\n").as_bytes());
+        bytes.extend_from_slice(&0.5f32.to_le_bytes());
+        bytes.extend_from_slice(&(-0.25f32).to_le_bytes());
+        assert_eq!(
+            parse(&bytes),
+            Some(vec![Complex64::from(0.5), Complex64::from(-0.25)])
+        );
+        assert_eq!(parse(&bytes[..bytes.len() - 1]), None);
+        Ok(())
+    }
+}
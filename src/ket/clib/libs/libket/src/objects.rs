@@ -5,6 +5,8 @@
 
 //! This module provides structures that hold the status of qubits and measurements.
 
+use crate::compression::CompressedDump;
+use crate::grouping::Estimate;
 use crate::ir::{DumpData, PauliHamiltonian};
 
 /// Represents the status of a qubit.
@@ -54,11 +56,38 @@ pub struct Sample {
     pub result: Option<(Vec<u64>, Vec<u64>)>,
 }
 
+impl Sample {
+    /// Estimates the mean of `observable` over the sampled outcomes, with its variance.
+    ///
+    /// Returns `None` if the result is not yet available.
+    pub fn estimate(&self, observable: impl Fn(u64) -> f64) -> Option<Estimate> {
+        let (states, counts) = self.result.as_ref()?;
+        Estimate::from_counts(
+            states
+                .iter()
+                .zip(counts)
+                .map(|(state, count)| (observable(*state), *count)),
+        )
+    }
+
+    /// Estimates the probability of an outcome, with its binomial variance.
+    ///
+    /// Returns `None` if the result is not yet available.
+    pub fn probability(&self, outcome: u64) -> Option<Estimate> {
+        self.estimate(|state| if state == outcome { 1.0 } else { 0.0 })
+    }
+}
+
 /// Represents the result of a quantum state dump.
 #[derive(Debug, Clone)]
 pub struct Dump {
     /// Qubits used for the dump.
     pub qubits: Vec<usize>,
-    /// State dump result.
+    /// State dump result. `None` if the result is stored compressed.
     pub result: Option<DumpData>,
+    /// Compressed state dump result, see [`Process::get_dump_data`](crate::Process::get_dump_data).
+    pub compressed: Option<CompressedDump>,
+    /// Number of basis states kept, see [`Process::dump_top_k`](crate::Process::dump_top_k).
+    /// `None` keeps every basis state.
+    pub top_k: Option<usize>,
 }
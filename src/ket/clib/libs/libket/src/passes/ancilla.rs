@@ -0,0 +1,362 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verification that the ancilla qubits are returned to `|0>`.
+//!
+//! A freed qubit must be in the `|0>` state, and so must an ancilla that is measured
+//! only to be discarded, the garbage qubits. An ancilla that is left entangled with the
+//! data, because an uncomputation is missing or does not match its computation, gives
+//! wrong answers without any error: freeing it or measuring it collapses the data.
+//!
+//! [`AncillaReport`] simulates the circuit from the `|0>` state of every qubit, on at
+//! most [`MAX_VERIFIED_QUBITS`] qubits, and checks each ancilla when it is freed, when a
+//! garbage qubit is first measured, or at the end of the circuit for a garbage qubit that
+//! is neither freed nor measured. The other measurements are deferred to the end, so they
+//! do not change the check. A dirty ancilla is reported with the gates on it since the
+//! last point where it was clean, which are the computation left without its
+//! uncomputation, and the uncomputation that does not match it.
+//!
+//! The [`AncillaCheck`] pass runs the verification in a [`PassManager`](super::PassManager)
+//! pipeline and fails with [`KetError::DirtyAncilla`] if an ancilla is dirty, without
+//! changing the circuit.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::{passes::ancilla::AncillaReport, Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut process = Process::new(Configuration::new(3));
+//! let a = process.allocate_qubit()?;
+//! let b = process.allocate_qubit()?;
+//! let ancilla = process.allocate_qubit()?;
+//! process.apply_gate(QuantumGate::Hadamard, a)?;
+//! process.apply_gate(QuantumGate::Hadamard, b)?;
+//! process.ctrl_push(&[a, b])?;
+//! process.apply_gate(QuantumGate::PauliX, ancilla)?;
+//! process.ctrl_pop()?;
+//! process.free_qubit(ancilla)?;
+//!
+//! // The Toffoli gate at index 5 is not uncomputed.
+//! let report = AncillaReport::new(&process, &[])?;
+//! assert_eq!(report.dirty[0].qubit, ancilla);
+//! assert_eq!(report.dirty[0].gates, [5]);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use num::complex::Complex64;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{KetError, Result},
+    ir::Instruction,
+    multiplexor, Process,
+};
+
+use super::{canonicalize::gate_matrix, peephole::qubits, Pass};
+
+/// Largest number of qubits of a verified circuit.
+pub const MAX_VERIFIED_QUBITS: usize = 20;
+
+/// Smallest probability of measuring `|1>` on a dirty ancilla.
+const TOLERANCE: f64 = 1e-9;
+
+/// Ancilla that is not in the `|0>` state when it is checked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirtyAncilla {
+    /// Qubit index.
+    pub qubit: usize,
+
+    /// Index of the instruction that frees or measures the qubit, or the number of
+    /// instructions for a garbage qubit checked at the end of the circuit.
+    pub checked_at: usize,
+
+    /// Probability of measuring `|1>` on the qubit.
+    pub probability: f64,
+
+    /// Indices of the instructions on the qubit since it was last in the `|0>` state.
+    pub gates: Vec<usize>,
+}
+
+/// Result of the ancilla verification, see the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AncillaReport {
+    /// Number of ancillas checked.
+    pub checked: usize,
+
+    /// Ancillas that are not in the `|0>` state, in the order they were checked.
+    pub dirty: Vec<DirtyAncilla>,
+}
+
+impl AncillaReport {
+    /// Verifies the freed qubits and the `garbage` qubits of the process instructions.
+    ///
+    /// # Errors
+    ///
+    /// See [`AncillaReport::from_instructions`].
+    pub fn new(process: &Process, garbage: &[usize]) -> Result<Self> {
+        Self::from_instructions(&process.instructions, garbage)
+    }
+
+    /// Verifies the freed qubits and the `garbage` qubits of a list of instructions.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::NumberOfQubitsExceeded`] if the instructions act on more than
+    /// [`MAX_VERIFIED_QUBITS`] qubits, and [`KetError::GateNotSupported`] if they have a
+    /// conditional gate or a Kraus channel, whose effect on the ancillas depends on the
+    /// measurement results.
+    pub fn from_instructions(instructions: &[Instruction], garbage: &[usize]) -> Result<Self> {
+        let mut bits = HashMap::new();
+        for qubit in instructions
+            .iter()
+            .flat_map(qubits)
+            .chain(garbage.iter().copied())
+        {
+            let len = bits.len();
+            bits.entry(qubit).or_insert(len);
+        }
+        if bits.len() > MAX_VERIFIED_QUBITS {
+            return Err(KetError::NumberOfQubitsExceeded);
+        }
+        let mut state = State::new(bits);
+
+        let mut report = Self::default();
+        // Instructions on each ancilla since it was last in the `|0>` state.
+        let mut touched: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut pending: Vec<usize> = garbage.to_vec();
+        let mut check = |state: &State,
+                         qubit: usize,
+                         index: usize,
+                         touched: &mut BTreeMap<usize, Vec<usize>>| {
+            report.checked += 1;
+            let probability = state.probability_one(qubit);
+            if probability > TOLERANCE {
+                report.dirty.push(DirtyAncilla {
+                    qubit,
+                    checked_at: index,
+                    probability,
+                    gates: touched.remove(&qubit).unwrap_or_default(),
+                });
+            }
+        };
+
+        for (index, instruction) in instructions.iter().enumerate() {
+            let target = match instruction {
+                Instruction::Gate {
+                    gate,
+                    target,
+                    control,
+                } => {
+                    state.apply(&gate_matrix(gate), *target, control);
+                    Some(*target)
+                }
+                Instruction::Diagonal { .. } | Instruction::Multiplexor { .. } => {
+                    let (gates, _) = multiplexor::synthesize(instruction)
+                        .expect("diagonal gates and multiplexors are synthesized");
+                    for gate in &gates {
+                        if let Instruction::Gate {
+                            gate,
+                            target,
+                            control,
+                        } = gate
+                        {
+                            state.apply(&gate_matrix(gate), *target, control);
+                        }
+                    }
+                    match instruction {
+                        Instruction::Multiplexor { target, .. } => Some(*target),
+                        _ => None,
+                    }
+                }
+                Instruction::Free { target } => {
+                    pending.retain(|qubit| qubit != target);
+                    check(&state, *target, index, &mut touched);
+                    None
+                }
+                Instruction::Measure { qubits, .. } => {
+                    for qubit in qubits {
+                        if let Some(position) = pending.iter().position(|other| other == qubit) {
+                            pending.remove(position);
+                            check(&state, *qubit, index, &mut touched);
+                        }
+                    }
+                    None
+                }
+                Instruction::ConditionalGate { .. } | Instruction::KrausChannel { .. } => {
+                    return Err(KetError::GateNotSupported);
+                }
+                Instruction::Alloc { .. }
+                | Instruction::ExpValue { .. }
+                | Instruction::Sample { .. }
+                | Instruction::Dump { .. } => None,
+            };
+
+            if let Some(target) = target {
+                if state.probability_one(target) > TOLERANCE {
+                    touched.entry(target).or_default().push(index);
+                } else {
+                    touched.remove(&target);
+                }
+            }
+        }
+        for qubit in pending {
+            check(&state, qubit, instructions.len(), &mut touched);
+        }
+
+        Ok(report)
+    }
+
+    /// Returns whether every ancilla was in the `|0>` state.
+    pub fn passed(&self) -> bool {
+        self.dirty.is_empty()
+    }
+}
+
+impl fmt::Display for AncillaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "ancilla check: {} of {} ancillas not returned to |0>",
+            self.dirty.len(),
+            self.checked
+        )?;
+        for ancilla in &self.dirty {
+            let gates: Vec<String> = ancilla.gates.iter().map(usize::to_string).collect();
+            writeln!(
+                f,
+                "  qubit {} at instruction {}: P(1) = {:.6}, left by instructions {}",
+                ancilla.qubit,
+                ancilla.checked_at,
+                ancilla.probability,
+                gates.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// State vector of the verified qubits.
+struct State {
+    /// Bit of each qubit in the amplitude index.
+    bits: HashMap<usize, usize>,
+    amplitudes: Vec<Complex64>,
+}
+
+impl State {
+    fn new(bits: HashMap<usize, usize>) -> Self {
+        let mut amplitudes = vec![Complex64::default(); 1 << bits.len()];
+        amplitudes[0] = Complex64::new(1.0, 0.0);
+        Self { bits, amplitudes }
+    }
+
+    fn mask(&self, qubit: usize) -> usize {
+        1 << self.bits[&qubit]
+    }
+
+    fn apply(&mut self, matrix: &[[Complex64; 2]; 2], target: usize, control: &[usize]) {
+        let target = self.mask(target);
+        let control: usize = control.iter().map(|qubit| self.mask(*qubit)).sum();
+        for index in 0..self.amplitudes.len() {
+            if index & target == 0 && index & control == control {
+                let (zero, one) = (self.amplitudes[index], self.amplitudes[index | target]);
+                self.amplitudes[index] = matrix[0][0] * zero + matrix[0][1] * one;
+                self.amplitudes[index | target] = matrix[1][0] * zero + matrix[1][1] * one;
+            }
+        }
+    }
+
+    fn probability_one(&self, qubit: usize) -> f64 {
+        let mask = self.mask(qubit);
+        self.amplitudes
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| index & mask != 0)
+            .map(|(_, amplitude)| amplitude.norm_sqr())
+            .sum()
+    }
+}
+
+/// Fails with [`KetError::DirtyAncilla`] if an ancilla is not returned to `|0>`, see
+/// [`ancilla`](self).
+///
+/// The circuit is not changed.
+#[derive(Debug, Clone, Default)]
+pub struct AncillaCheck {
+    /// Qubits measured only to be discarded, checked besides the freed qubits.
+    pub garbage: Vec<usize>,
+}
+
+impl Pass for AncillaCheck {
+    fn name(&self) -> String {
+        "ancilla_check".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        let report = AncillaReport::new(process, &self.garbage)?;
+        if report.passed() {
+            Ok(())
+        } else {
+            tracing::warn!("{}", report);
+            Err(KetError::DirtyAncilla)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AncillaCheck, AncillaReport};
+    use crate::{error::KetError, passes::PassManager, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn mismatched_uncomputation() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(5));
+        let data: Vec<usize> = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        let ancilla = process.allocate_qubit()?;
+        let garbage = process.allocate_qubit()?;
+        for qubit in &data {
+            process.apply_gate(QuantumGate::Hadamard, *qubit)?;
+        }
+
+        // Computed and uncomputed with the same controls, then with different ones.
+        let toffoli = |process: &mut Process, control: &[usize], target| {
+            process.ctrl_push(control)?;
+            process.apply_gate(QuantumGate::PauliX, target)?;
+            process.ctrl_pop()
+        };
+        toffoli(&mut process, &data[..2], ancilla)?;
+        process.apply_gate(QuantumGate::PauliZ, ancilla)?;
+        toffoli(&mut process, &data[..2], ancilla)?;
+        let first = process.instructions().len();
+        toffoli(&mut process, &data[..2], garbage)?;
+        toffoli(&mut process, &data[1..], garbage)?;
+        process.measure(&[garbage])?;
+
+        let report = AncillaReport::new(&process, &[])?;
+        assert_eq!((report.checked, report.passed()), (0, true));
+        let report = AncillaReport::new(&process, &[garbage])?;
+        assert_eq!(report.checked, 1);
+        assert_eq!(report.dirty[0].gates, [first, first + 1]);
+        assert!((report.dirty[0].probability - 0.25).abs() < 1e-9);
+
+        process.free_qubit(ancilla)?;
+        let pass = AncillaCheck {
+            garbage: vec![garbage],
+        };
+        assert!(matches!(
+            PassManager::new().add_pass(pass).run(&mut process),
+            Err(KetError::DirtyAncilla)
+        ));
+        let report = AncillaReport::new(&process, &[garbage])?;
+        assert_eq!((report.checked, report.dirty.len()), (2, 1));
+        assert_eq!(report.dirty[0].qubit, garbage);
+        Ok(())
+    }
+}
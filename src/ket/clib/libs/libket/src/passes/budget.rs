@@ -0,0 +1,238 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Error budgets and compilation reports.
+//!
+//! A section of the circuit between [`Process::budget_begin`] and
+//! [`Process::budget_end`] has a fidelity target `F`, which allows its approximations
+//! an error of `1 - F`. The error of a section is the sum of the errors, in operator
+//! norm, of the approximations made in it, an upper bound of its distance to the exact
+//! circuit. Passes that approximate, as [`CliffordT`](super::CliffordT), choose their
+//! precision to stay within the remaining budget of each section, and record the
+//! error they spend. Exact passes spend nothing.
+//!
+//! Passes rewrite each section separately, so no instruction moves in or out of a
+//! section. The passes that relabel qubits cannot run on a process with sections.
+//!
+//! [`PassManager::compile`](super::PassManager::compile) runs a pipeline and returns a
+//! [`CompilationReport`] with the resources and the error of each pass, and the error
+//! spent in each section against its budget.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::passes::{CliffordT, PassManager};
+//! use ket::{Angle, Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut process = Process::new(Configuration::new(1));
+//! let qubit = process.allocate_qubit()?;
+//! process.budget_begin("rotation", 0.999)?;
+//! process.apply_gate(QuantumGate::RotationZ(Angle::Scalar(0.3)), qubit)?;
+//! process.budget_end()?;
+//!
+//! let report = PassManager::new()
+//!     .add_pass(CliffordT { epsilon: 1e-2 })
+//!     .compile(&mut process)?;
+//! assert!(report.budgets[0].met);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::ResourceReport;
+
+/// Section of the circuit with a fidelity target, see [`Process::budget_begin`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBudget {
+    /// Name of the section, used in reports.
+    pub name: String,
+
+    /// Fidelity target of the section.
+    pub fidelity: f64,
+
+    /// Error spent by the approximations of the section.
+    pub spent: f64,
+
+    /// Instructions of the section, empty after they are executed as a chunk.
+    pub(crate) range: Range<usize>,
+}
+
+impl ErrorBudget {
+    /// Returns the error allowed in the section, `1 - fidelity`.
+    pub fn allowed(&self) -> f64 {
+        1.0 - self.fidelity
+    }
+
+    /// Returns the error that the section can still spend.
+    pub fn remaining(&self) -> f64 {
+        (self.allowed() - self.spent).max(0.0)
+    }
+
+    /// Returns the range of the instructions of the section.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+/// Error spent in a section against its budget.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetReport {
+    /// Name of the section.
+    pub name: String,
+
+    /// Fidelity target of the section.
+    pub target_fidelity: f64,
+
+    /// Error allowed in the section.
+    pub allowed_error: f64,
+
+    /// Error spent by the approximations of the section.
+    pub spent_error: f64,
+
+    /// Whether the spent error is within the allowed error.
+    pub met: bool,
+}
+
+impl From<&ErrorBudget> for BudgetReport {
+    fn from(budget: &ErrorBudget) -> Self {
+        Self {
+            name: budget.name.clone(),
+            target_fidelity: budget.fidelity,
+            allowed_error: budget.allowed(),
+            spent_error: budget.spent,
+            // Tolerates the rounding of the sum of the errors.
+            met: budget.spent <= budget.allowed() * (1.0 + 1e-9),
+        }
+    }
+}
+
+/// Effect of a pass in a [`CompilationReport`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompilationStep {
+    /// Name of the pass.
+    pub pass: String,
+
+    /// Resources of the circuit after the pass.
+    pub resources: ResourceReport,
+
+    /// Error of the approximations made by the pass, in and out of the sections.
+    pub error: f64,
+}
+
+/// Summary of the passes run on a process and of the error they spent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompilationReport {
+    /// Resources of the circuit before the passes.
+    pub input: ResourceReport,
+
+    /// Effect of each pass, in order.
+    pub steps: Vec<CompilationStep>,
+
+    /// Error spent in each section, in the order they begin.
+    pub budgets: Vec<BudgetReport>,
+
+    /// Error of the approximations made out of the sections.
+    pub unbudgeted_error: f64,
+}
+
+impl CompilationReport {
+    /// Returns the error of all approximations, an upper bound of the distance in
+    /// operator norm between the compiled and the input circuit.
+    pub fn total_error(&self) -> f64 {
+        self.steps.iter().map(|step| step.error).sum()
+    }
+
+    /// Returns `true` if every section is within its budget.
+    pub fn budgets_met(&self) -> bool {
+        self.budgets.iter().all(|budget| budget.met)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::KetError,
+        passes::{CliffordT, CouplingMap, Pass, PassManager, Peephole, Routing},
+        Angle, Configuration, Instruction, Process, QuantumGate,
+    };
+
+    #[test]
+    fn spend_budgets() -> Result<(), KetError> {
+        let rz = |angle| QuantumGate::RotationZ(Angle::Scalar(angle));
+        let mut process = Process::new(Configuration::new(2));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(rz(0.1), qubits[0])?;
+        process.budget_begin("rotations", 0.997)?;
+        process.apply_gate(rz(0.2), qubits[0])?;
+        process.apply_gate(rz(0.3), qubits[1])?;
+        process.apply_gate(QuantumGate::RotationX(Angle::Scalar(0.4)), qubits[1])?;
+        process.apply_gate(QuantumGate::Phase(Angle::pi_fraction(1, 4)), qubits[1])?;
+        process.budget_end()?;
+
+        assert!(matches!(
+            process.budget_begin("invalid", 0.0),
+            Err(KetError::InvalidErrorBudget)
+        ));
+        assert!(matches!(
+            process.budget_end(),
+            Err(KetError::InvalidErrorBudget)
+        ));
+        process.budget_begin("open", 1.0)?;
+        assert!(matches!(
+            process.budget_begin("nested", 0.5),
+            Err(KetError::InvalidErrorBudget)
+        ));
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+
+        // The peephole pass does not merge the rotations on the first qubit across the
+        // bound of the section.
+        let report = PassManager::new()
+            .add_pass(Peephole)
+            .add_pass(CliffordT { epsilon: 1e-2 })
+            .compile(&mut process)?;
+        process.budget_end()?;
+
+        assert_eq!(report.budgets.len(), 1);
+        let budget = &report.budgets[0];
+        assert!(budget.met);
+        assert!((budget.spent_error - 0.003).abs() < 1e-12);
+        assert!((report.unbudgeted_error - 1e-2).abs() < 1e-12);
+        assert!((report.total_error() - 0.013).abs() < 1e-12);
+        assert_eq!(report.steps[0].error, 0.0);
+        assert!(report.budgets_met());
+
+        let budgets = process.error_budgets();
+        assert_eq!(budgets[1].name, "open");
+        assert_eq!(budgets[1].range.len(), 1);
+        let instructions = process.instructions();
+        assert!(matches!(
+            instructions[budgets[1].range.start],
+            Instruction::Gate {
+                gate: QuantumGate::Hadamard,
+                ..
+            }
+        ));
+        assert_eq!(budgets[0].range.end, budgets[1].range.start);
+        assert!(budgets[0].range.len() > 4);
+        // The approximation of the first rotation is before the section.
+        assert!(budgets[0].range.start > 2);
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains("\"rotations\""));
+        assert!(process.metadata.compilation.is_some());
+
+        let routing = Routing {
+            coupling_map: CouplingMap::line(2),
+        };
+        assert!(matches!(
+            routing.run(&mut process),
+            Err(KetError::InvalidErrorBudget)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Canonical order of commuting gates.
+//!
+//! Two gates commute if, on every qubit they share, both act in the same basis: as a
+//! control or a diagonal gate (Z, RZ, and phase), both in the X basis (X and RX), or
+//! both in the Y basis (Y and RY). The [`Canonicalize`] pass places each instruction
+//! in the earliest layer after the instructions it does not commute with, and sorts
+//! the instructions of a layer by their qubits and content. This is the Foata normal
+//! form of the circuit, so circuits that only differ by swaps of commuting gates are
+//! rewritten into the same instructions, and a [`CircuitHash`](crate::hashing::CircuitHash)
+//! or a diff of canonical circuits ignores the order of commuting gates.
+//!
+//! Instructions other than gates commute with nothing on their qubits.
+//!
+//! In debug builds, the pass simulates every rewritten segment of at most
+//! [`MAX_CHECKED_QUBITS`] qubits and panics if its action on a state changed.
+
+use num::complex::Complex64;
+
+use crate::{
+    error::Result,
+    ir::{Instruction, QuantumGate},
+    Process,
+};
+
+use super::{peephole::qubits, rewrite_editable, Pass};
+
+/// Largest number of qubits of a segment checked by simulation in debug builds.
+pub const MAX_CHECKED_QUBITS: usize = 10;
+
+/// Reorders commuting gates into the canonical order, see [`canonicalize`](self).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Canonicalize;
+
+impl Pass for Canonicalize {
+    fn name(&self) -> String {
+        "canonicalize".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        rewrite_editable(process, |instructions| {
+            let canonical = canonicalize(instructions);
+            debug_assert!(
+                same_action(instructions, &canonical),
+                "canonicalization changed the circuit"
+            );
+            Ok(canonical)
+        })
+    }
+}
+
+/// Basis in which an instruction acts on one of its qubits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Basis {
+    Z,
+    X,
+    Y,
+    Any,
+}
+
+impl Basis {
+    const ALL: [Basis; 4] = [Basis::Z, Basis::X, Basis::Y, Basis::Any];
+
+    fn of(gate: &QuantumGate) -> Self {
+        match gate {
+            QuantumGate::PauliZ | QuantumGate::RotationZ(_) | QuantumGate::Phase(_) => Basis::Z,
+            QuantumGate::PauliX | QuantumGate::RotationX(_) => Basis::X,
+            QuantumGate::PauliY | QuantumGate::RotationY(_) => Basis::Y,
+            QuantumGate::Hadamard => Basis::Any,
+        }
+    }
+
+    fn commutes_with(self, other: Basis) -> bool {
+        self == other && self != Basis::Any
+    }
+}
+
+/// Qubits of the instruction, with the basis in which it acts on each one.
+fn bases(instruction: &Instruction) -> Vec<(usize, Basis)> {
+    match instruction {
+        Instruction::Gate {
+            gate,
+            target,
+            control,
+        } => control
+            .iter()
+            .map(|qubit| (*qubit, Basis::Z))
+            .chain([(*target, Basis::of(gate))])
+            .collect(),
+        instruction => qubits(instruction)
+            .into_iter()
+            .map(|qubit| (qubit, Basis::Any))
+            .collect(),
+    }
+}
+
+/// Returns `true` if the instructions commute by the rule of [`canonicalize`](self).
+pub fn commute(a: &Instruction, b: &Instruction) -> bool {
+    let b = bases(b);
+    bases(a).into_iter().all(|(qubit, basis)| {
+        b.iter()
+            .filter(|(other, _)| *other == qubit)
+            .all(|(_, other)| basis.commutes_with(*other))
+    })
+}
+
+/// Returns the indices of the instructions in the canonical order.
+pub fn canonical_order(instructions: &[Instruction]) -> Vec<usize> {
+    // The layer after the last instruction in each basis on each qubit.
+    let mut next_layer: Vec<[usize; 4]> = Vec::new();
+    let mut keys: Vec<(usize, Vec<usize>, String, usize)> = instructions
+        .iter()
+        .enumerate()
+        .map(|(index, instruction)| {
+            let bases = bases(instruction);
+            let layer = bases
+                .iter()
+                .flat_map(|&(qubit, basis)| {
+                    let next = next_layer.get(qubit).copied().unwrap_or_default();
+                    Basis::ALL
+                        .into_iter()
+                        .zip(next)
+                        .filter(move |(other, _)| !basis.commutes_with(*other))
+                        .map(|(_, layer)| layer)
+                })
+                .max()
+                .unwrap_or_default();
+            for &(qubit, basis) in &bases {
+                if qubit >= next_layer.len() {
+                    next_layer.resize(qubit + 1, [0; 4]);
+                }
+                let next = &mut next_layer[qubit][basis as usize];
+                *next = (*next).max(layer + 1);
+            }
+            let json = serde_json::to_string(instruction).expect("instructions are serializable");
+            (layer, qubits(instruction), json, index)
+        })
+        .collect();
+
+    keys.sort();
+    keys.into_iter().map(|(.., index)| index).collect()
+}
+
+/// Returns the instructions in the canonical order.
+pub fn canonicalize(instructions: &[Instruction]) -> Vec<Instruction> {
+    canonical_order(instructions)
+        .into_iter()
+        .map(|index| instructions[index].clone())
+        .collect()
+}
+
+/// Returns the matrix of a gate.
+pub(crate) fn gate_matrix(gate: &QuantumGate) -> [[Complex64; 2]; 2] {
+    let (zero, one, i) = (Complex64::from(0.0), Complex64::from(1.0), Complex64::i());
+    let rotation = |angle: f64| {
+        let half = angle / 2.0;
+        (Complex64::from(half.cos()), Complex64::from(half.sin()))
+    };
+    match gate {
+        QuantumGate::PauliX => [[zero, one], [one, zero]],
+        QuantumGate::PauliY => [[zero, -i], [i, zero]],
+        QuantumGate::PauliZ => [[one, zero], [zero, -one]],
+        QuantumGate::Hadamard => {
+            let h = Complex64::from(std::f64::consts::FRAC_1_SQRT_2);
+            [[h, h], [h, -h]]
+        }
+        QuantumGate::Phase(angle) => [[one, zero], [zero, (i * angle.radians()).exp()]],
+        QuantumGate::RotationZ(angle) => {
+            let half = i * angle.radians() / 2.0;
+            [[(-half).exp(), zero], [zero, half.exp()]]
+        }
+        QuantumGate::RotationX(angle) => {
+            let (cos, sin) = rotation(angle.radians());
+            [[cos, -i * sin], [-i * sin, cos]]
+        }
+        QuantumGate::RotationY(angle) => {
+            let (cos, sin) = rotation(angle.radians());
+            [[cos, -sin], [sin, cos]]
+        }
+    }
+}
+
+/// Returns `false` if the gates of `a` and `b` act differently on a state, checked by
+/// simulation if they only have gates, allocations, and frees on at most
+/// [`MAX_CHECKED_QUBITS`] qubits.
+fn same_action(a: &[Instruction], b: &[Instruction]) -> bool {
+    let mut qubits: Vec<usize> = a.iter().flat_map(qubits).collect();
+    qubits.sort_unstable();
+    qubits.dedup();
+
+    match (action(a, &qubits), action(b, &qubits)) {
+        (Some(a), Some(b)) => a.iter().zip(b).all(|(a, b)| (a - b).norm() < 1e-9),
+        _ => true,
+    }
+}
+
+/// Returns the state after the gates act on a state with distinct amplitudes, where
+/// each qubit is the bit of its position in `qubits`, or `None` if there is an
+/// instruction other than gates, allocations, and frees, a qubit not in `qubits`, or
+/// more than [`MAX_CHECKED_QUBITS`] qubits.
+pub(crate) fn action(instructions: &[Instruction], qubits: &[usize]) -> Option<Vec<Complex64>> {
+    if qubits.len() > MAX_CHECKED_QUBITS {
+        return None;
+    }
+    let bit = |qubit: &usize| Some(1usize << qubits.iter().position(|other| other == qubit)?);
+
+    // A state with distinct amplitudes, so no reordering goes unnoticed.
+    let mut state: Vec<Complex64> = (0..1usize << qubits.len())
+        .map(|index| Complex64::new((index as f64 + 1.0).sin(), (index as f64 * 0.7).cos()))
+        .collect();
+    for instruction in instructions {
+        let (gate, target, control) = match instruction {
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } => (gate, target, control),
+            Instruction::Alloc { .. } | Instruction::Free { .. } => continue,
+            _ => return None,
+        };
+        let matrix = gate_matrix(gate);
+        let target = bit(target)?;
+        let control: usize = control.iter().map(bit).sum::<Option<usize>>()?;
+        for index in 0..state.len() {
+            if index & target == 0 && index & control == control {
+                let (zero, one) = (state[index], state[index | target]);
+                state[index] = matrix[0][0] * zero + matrix[0][1] * one;
+                state[index | target] = matrix[1][0] * zero + matrix[1][1] * one;
+            }
+        }
+    }
+    Some(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonicalize, commute, same_action};
+    use crate::{
+        error::KetError, hashing::structurally_equal, Angle, Configuration, Instruction, Process,
+        QuantumGate,
+    };
+
+    fn gate(gate: QuantumGate, target: usize, control: &[usize]) -> Instruction {
+        Instruction::Gate {
+            gate,
+            target,
+            control: control.to_vec(),
+        }
+    }
+
+    #[test]
+    fn commuting_gates() {
+        let rz = |angle| QuantumGate::RotationZ(Angle::Scalar(angle));
+        let rx = |angle| QuantumGate::RotationX(Angle::Scalar(angle));
+        let cnot = gate(QuantumGate::PauliX, 1, &[0]);
+
+        assert!(commute(&gate(rz(0.5), 0, &[]), &cnot));
+        assert!(commute(&gate(rx(0.5), 1, &[]), &cnot));
+        assert!(!commute(&gate(rz(0.5), 1, &[]), &cnot));
+        assert!(!commute(&gate(QuantumGate::Hadamard, 0, &[]), &cnot));
+        assert!(commute(&cnot, &gate(QuantumGate::PauliX, 2, &[0])));
+        assert!(!commute(&cnot, &gate(QuantumGate::PauliX, 0, &[1])));
+        assert!(commute(
+            &gate(QuantumGate::Phase(Angle::pi()), 2, &[0, 1]),
+            &gate(QuantumGate::PauliZ, 0, &[2])
+        ));
+        assert!(!commute(
+            &Instruction::Measure {
+                qubits: vec![0],
+                output: 0
+            },
+            &gate(QuantumGate::PauliZ, 0, &[])
+        ));
+
+        // Circuits that only differ by swaps of commuting gates have the same canonical
+        // form, which is not the case with the order of disjoint gates.
+        let a = [
+            gate(rz(0.5), 0, &[]),
+            cnot.clone(),
+            gate(rx(0.25), 1, &[]),
+            gate(QuantumGate::PauliZ, 2, &[0]),
+            gate(QuantumGate::Hadamard, 1, &[]),
+        ];
+        let b = [
+            gate(QuantumGate::PauliZ, 2, &[0]),
+            gate(rx(0.25), 1, &[]),
+            cnot,
+            gate(rz(0.5), 0, &[]),
+            gate(QuantumGate::Hadamard, 1, &[]),
+        ];
+        assert!(!structurally_equal(&a, &b));
+        let (a, b) = (canonicalize(&a), canonicalize(&b));
+        assert!(structurally_equal(&a, &b));
+        assert!(same_action(&a, &b));
+        assert!(!same_action(&a, &a[1..]));
+    }
+
+    #[test]
+    fn canonicalize_process() -> Result<(), KetError> {
+        let build = |swap: bool| -> Result<Process, KetError> {
+            let mut process = Process::new(Configuration::new(2));
+            let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+            process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+            process.freeze()?;
+            let mut gates = [
+                (QuantumGate::PauliZ, qubits[0]),
+                (QuantumGate::Phase(Angle::pi_fraction(1, 4)), qubits[0]),
+            ];
+            if swap {
+                gates.reverse();
+            }
+            process.ctrl_push(&qubits[1..])?;
+            for (gate, qubit) in gates {
+                process.apply_gate(gate, qubit)?;
+            }
+            process.ctrl_pop()?;
+            process.canonicalize()?;
+            Ok(process)
+        };
+
+        let (a, b) = (build(false)?, build(true)?);
+        assert_eq!(a.circuit_hash(), b.circuit_hash());
+        assert_eq!(a.frozen_len(), 3);
+        assert!(matches!(
+            a.instructions()[2],
+            Instruction::Gate {
+                gate: QuantumGate::Hadamard,
+                ..
+            }
+        ));
+        Ok(())
+    }
+}
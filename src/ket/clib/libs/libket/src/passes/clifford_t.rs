@@ -0,0 +1,341 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Discretization of rotations into the Clifford+T gate set.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::Result,
+    ir::{Angle, Instruction, QuantumGate},
+    Process,
+};
+
+use super::{
+    gridsynth::{approximate_rz, MIN_EPSILON},
+    rewrite_sections, Pass,
+};
+
+/// Replaces the rotations without control qubits by Clifford+T sequences.
+///
+/// `RZ` and phase gates are approximated with [`approximate_rz`], and `RX` and `RY`
+/// gates are approximated in the `H` and `S H` rotated bases. Rotations by a multiple of
+/// `π/4` are replaced by an exact phase gate, and the other rotations by a sequence
+/// within `epsilon` of the rotation in operator norm, up to a global phase. The error
+/// of the circuit is at most `epsilon` times the number of approximated rotations.
+///
+/// In an error budget section, see [`budget`](super::budget), the rotations share the
+/// remaining error of the section, so `epsilon` is only an upper bound, and the error
+/// spent is recorded in the section.
+///
+/// Gates with control qubits are not changed and must be decomposed beforehand to
+/// obtain a Clifford+T circuit. The T-count of the result is reported by
+/// [`ResourceReport`](super::ResourceReport).
+#[derive(Debug, Clone, Copy)]
+pub struct CliffordT {
+    /// Approximation error of each rotation, at least
+    /// [`MIN_EPSILON`](super::gridsynth::MIN_EPSILON).
+    pub epsilon: f64,
+}
+
+impl Pass for CliffordT {
+    fn name(&self) -> String {
+        "clifford_t".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        let mut unbudgeted = 0.0;
+        rewrite_sections(process, |instructions, section| {
+            let rotations = instructions.iter().filter(|i| is_approximated(i)).count() as f64;
+            let epsilon = match &section {
+                Some(budget) if rotations > 0.0 => (budget.remaining() / rotations)
+                    .max(MIN_EPSILON)
+                    .min(self.epsilon),
+                _ => self.epsilon,
+            };
+            let result = clifford_t(instructions, epsilon)?;
+            match section {
+                Some(budget) => budget.spent += rotations * epsilon,
+                None => unbudgeted += rotations * epsilon,
+            }
+            Ok(result)
+        })?;
+        process.unbudgeted_error += unbudgeted;
+        Ok(())
+    }
+}
+
+/// Returns `true` for a T or T† gate, a phase or `RZ` rotation without control qubits
+/// by an odd multiple of `π/4`.
+pub fn is_t_gate(gate: &QuantumGate, control: &[usize]) -> bool {
+    match gate {
+        QuantumGate::Phase(angle) | QuantumGate::RotationZ(angle) => {
+            control.is_empty() && matches!(angle.as_pi_fraction(), Some((_, 4)))
+        }
+        _ => false,
+    }
+}
+
+/// Returns the rotation as a multiple of `π/4`, if it is one.
+///
+/// A multiple that does not fit an `i32` is not recognized, and the rotation is
+/// approximated instead.
+fn exact_eighth(angle: &Angle) -> Option<i32> {
+    match angle.as_pi_fraction() {
+        Some((top, bottom)) if 4 % bottom == 0 => top.checked_mul(4 / bottom as i32),
+        Some(_) => None,
+        None => {
+            let eighths = angle.radians() / std::f64::consts::FRAC_PI_4;
+            ((eighths - eighths.round()).abs() < 1e-12 && eighths.abs() <= i32::MAX as f64)
+                .then_some(eighths.round() as i32)
+        }
+    }
+}
+
+/// Returns `true` for a rotation without control qubits that is not a multiple of `π/4`.
+fn is_approximated(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Gate {
+            gate:
+                QuantumGate::RotationX(angle)
+                | QuantumGate::RotationY(angle)
+                | QuantumGate::RotationZ(angle)
+                | QuantumGate::Phase(angle),
+            control,
+            ..
+        } => control.is_empty() && exact_eighth(angle).is_none(),
+        _ => false,
+    }
+}
+
+/// Returns the Clifford+T gates of `RZ(angle)`, up to a global phase.
+fn rz_gates(
+    angle: &Angle,
+    epsilon: f64,
+    cache: &mut HashMap<u64, Vec<QuantumGate>>,
+) -> Result<Vec<QuantumGate>> {
+    if let Some(eighths) = exact_eighth(angle) {
+        let eighths = (eighths % 8 + 8) % 8;
+        return Ok(if eighths == 0 {
+            Vec::new()
+        } else {
+            let eighths = if eighths > 4 { eighths - 8 } else { eighths };
+            vec![QuantumGate::Phase(Angle::pi_fraction(eighths, 4))]
+        });
+    }
+
+    let theta = angle.radians();
+    if let Some(gates) = cache.get(&theta.to_bits()) {
+        return Ok(gates.clone());
+    }
+    let gates = approximate_rz(theta, epsilon)?;
+    cache.insert(theta.to_bits(), gates.clone());
+    Ok(gates)
+}
+
+/// Approximates the rotations without control qubits by Clifford+T sequences, see
+/// [`CliffordT`].
+///
+/// # Errors
+///
+/// Returns [`KetError::ApproximationFailed`](crate::error::KetError::ApproximationFailed)
+/// if `epsilon` is out of range.
+pub fn clifford_t(instructions: &[Instruction], epsilon: f64) -> Result<Vec<Instruction>> {
+    let mut cache = HashMap::new();
+    let mut result = Vec::with_capacity(instructions.len());
+
+    for instruction in instructions {
+        let (gate, target) = match instruction {
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } if control.is_empty() => (gate, *target),
+            _ => {
+                result.push(instruction.clone());
+                continue;
+            }
+        };
+
+        let s = |top| QuantumGate::Phase(Angle::pi_fraction(top, 2));
+        let gates = match gate {
+            QuantumGate::RotationZ(angle) | QuantumGate::Phase(angle) => {
+                rz_gates(angle, epsilon, &mut cache)?
+            }
+            // RX = H RZ H
+            QuantumGate::RotationX(angle) => [QuantumGate::Hadamard]
+                .into_iter()
+                .chain(rz_gates(angle, epsilon, &mut cache)?)
+                .chain([QuantumGate::Hadamard])
+                .collect(),
+            // RY = S H RZ H S†
+            QuantumGate::RotationY(angle) => [s(-1), QuantumGate::Hadamard]
+                .into_iter()
+                .chain(rz_gates(angle, epsilon, &mut cache)?)
+                .chain([QuantumGate::Hadamard, s(1)])
+                .collect(),
+            _ => vec![gate.clone()],
+        };
+
+        result.extend(gates.into_iter().map(|gate| Instruction::Gate {
+            gate,
+            target,
+            control: Vec::new(),
+        }));
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use num::{complex::Complex64, One, Zero};
+
+    use super::{clifford_t, exact_eighth, is_t_gate};
+    use crate::{
+        ir::{Angle, Instruction, QuantumGate},
+        passes::{gridsynth::approximate_rz, ResourceReport},
+    };
+
+    type Matrix = [[Complex64; 2]; 2];
+
+    fn matrix(gate: &QuantumGate) -> Matrix {
+        let (zero, one, i) = (Complex64::zero(), Complex64::one(), Complex64::i());
+        let h = Complex64::from(FRAC_1_SQRT_2);
+        match gate {
+            QuantumGate::Hadamard => [[h, h], [h, -h]],
+            QuantumGate::Phase(angle) => [[one, zero], [zero, (i * angle.radians()).exp()]],
+            QuantumGate::RotationZ(angle) => {
+                let half = i * angle.radians() / 2.0;
+                [[(-half).exp(), zero], [zero, half.exp()]]
+            }
+            QuantumGate::RotationX(angle) => {
+                let cos = Complex64::from(f64::cos(angle.radians() / 2.0));
+                let sin = -i * f64::sin(angle.radians() / 2.0);
+                [[cos, sin], [sin, cos]]
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Matrix of the gates in circuit order.
+    fn product(gates: &[QuantumGate]) -> Matrix {
+        let identity = [
+            [Complex64::one(), Complex64::zero()],
+            [Complex64::zero(), Complex64::one()],
+        ];
+        gates.iter().fold(identity, |acc, gate| {
+            let gate = matrix(gate);
+            [0, 1].map(|row| {
+                [0, 1].map(|column| gate[row][0] * acc[0][column] + gate[row][1] * acc[1][column])
+            })
+        })
+    }
+
+    /// Operator norm distance up to a global phase.
+    fn distance(a: &Matrix, b: &Matrix) -> f64 {
+        // For 2x2 unitaries, |tr(a† b)| = 2 cos(d) where e^(±id) are the eigenvalues of
+        // a† b up to phase, and the distance is 2 sin(d/2).
+        let trace: Complex64 = (0..2)
+            .flat_map(|i| (0..2).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j].conj() * b[i][j])
+            .sum();
+        let cos = (trace.norm() / 2.0).min(1.0);
+        2.0 * (cos.acos() / 2.0).sin()
+    }
+
+    #[test]
+    fn approximate_rotations() {
+        for (theta, epsilon) in [(0.1, 1e-2), (1.0, 1e-3), (-2.5, 1e-4), (3.0, 1e-3)] {
+            let gates = approximate_rz(theta, epsilon).unwrap();
+            let target = product(&[QuantumGate::RotationZ(Angle::Scalar(theta))]);
+            assert!(distance(&target, &product(&gates)) <= epsilon * (1.0 + 1e-6));
+            assert!(gates.iter().all(|gate| matches!(
+                gate,
+                QuantumGate::Hadamard
+                    | QuantumGate::Phase(Angle::PiFraction {
+                        bottom: 1 | 2 | 4,
+                        ..
+                    })
+            )));
+        }
+        assert!(approximate_rz(0.1, 1e-9).is_err());
+    }
+
+    #[test]
+    fn exact_eighths() {
+        assert_eq!(exact_eighth(&Angle::pi_fraction(3, 4)), Some(3));
+        assert_eq!(exact_eighth(&Angle::pi_fraction(-1, 2)), Some(-2));
+        assert_eq!(exact_eighth(&Angle::pi_fraction(1, 8)), None);
+        assert_eq!(
+            exact_eighth(&Angle::Scalar(-std::f64::consts::FRAC_PI_4)),
+            Some(-1)
+        );
+        assert_eq!(exact_eighth(&Angle::Scalar(0.3)), None);
+
+        // Multiples beyond i32 are not exact eighths, instead of overflowing.
+        assert_eq!(exact_eighth(&Angle::pi_fraction(i32::MAX, 2)), None);
+        assert_eq!(exact_eighth(&Angle::pi_fraction(i32::MIN + 1, 1)), None);
+        assert_eq!(
+            exact_eighth(&Angle::Scalar(2f64.powi(40) * std::f64::consts::FRAC_PI_4)),
+            None
+        );
+    }
+
+    #[test]
+    fn discretize_circuit() {
+        let gate = |gate| Instruction::Gate {
+            gate,
+            target: 0,
+            control: Vec::new(),
+        };
+        let instructions = [
+            gate(QuantumGate::RotationZ(Angle::pi_fraction(3, 4))),
+            gate(QuantumGate::RotationX(Angle::Scalar(0.3))),
+            gate(QuantumGate::RotationZ(Angle::Scalar(
+                std::f64::consts::FRAC_PI_2,
+            ))),
+            Instruction::Gate {
+                gate: QuantumGate::RotationZ(Angle::Scalar(0.3)),
+                target: 0,
+                control: vec![1],
+            },
+        ];
+        let result = clifford_t(&instructions, 1e-3).unwrap();
+
+        assert!(matches!(
+            &result[0],
+            Instruction::Gate { gate: QuantumGate::Phase(angle), .. } if angle.as_pi_fraction() == Some((3, 4))
+        ));
+        let Instruction::Gate { gate: last, .. } = &result[result.len() - 1] else {
+            panic!()
+        };
+        assert!(matches!(last, QuantumGate::RotationZ(Angle::Scalar(_))));
+
+        let gates: Vec<QuantumGate> = result[..result.len() - 1]
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Gate { gate, .. } => gate.clone(),
+                _ => panic!(),
+            })
+            .collect();
+        let expected = product(&[
+            QuantumGate::RotationZ(Angle::pi_fraction(3, 4)),
+            QuantumGate::RotationX(Angle::Scalar(0.3)),
+            QuantumGate::RotationZ(Angle::Scalar(std::f64::consts::FRAC_PI_2)),
+        ]);
+        assert!(distance(&expected, &product(&gates)) <= 1e-3);
+
+        let report = ResourceReport::from_instructions(&result);
+        let t_count = result
+            .iter()
+            .filter(|instruction| matches!(instruction, Instruction::Gate { gate, control, .. } if is_t_gate(gate, control)))
+            .count();
+        assert_eq!(report.t_count, t_count);
+        assert!(t_count > 1);
+    }
+}
@@ -0,0 +1,295 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Solution of the norm equation `t†t = ξ` for `ξ` in `Z[√2]` and `t` in `Z[ω]`.
+//!
+//! The equation is solved prime by prime after factoring the integer norm of `ξ`. The
+//! factoring gives up after a fixed number of steps, in which case the candidate is
+//! rejected and the search moves to the next one, as in the Ross–Selinger algorithm.
+
+use std::collections::BTreeSet;
+
+use num::{BigInt, Integer, One, Signed, ToPrimitive, Zero};
+
+use super::ring::{ZOmega, ZRoot2};
+
+/// Primes removed by trial division.
+const SMALL_PRIMES: [u32; 25] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+];
+
+/// Largest number of steps of each attempt of the Pollard rho method.
+const MAX_RHO_STEPS: usize = 1 << 14;
+
+/// Number of attempts of the Pollard rho method, with different polynomials.
+const RHO_ATTEMPTS: u32 = 4;
+
+/// Largest exponent searched when adjusting the unit of a solution.
+const MAX_UNIT_EXPONENT: i32 = 256;
+
+/// Miller–Rabin probable prime test.
+fn is_probable_prime(n: &BigInt) -> bool {
+    if n < &BigInt::from(2) {
+        return false;
+    }
+    for p in SMALL_PRIMES {
+        if n == &BigInt::from(p) {
+            return true;
+        }
+        if n.is_multiple_of(&BigInt::from(p)) {
+            return false;
+        }
+    }
+
+    let n_minus_one: BigInt = n - 1;
+    let twos = n_minus_one.trailing_zeros().unwrap_or_default();
+    let odd = &n_minus_one >> twos;
+    SMALL_PRIMES[..12].iter().all(|base| {
+        let mut x = BigInt::from(*base).modpow(&odd, n);
+        if x.is_one() || x == n_minus_one {
+            return true;
+        }
+        for _ in 1..twos {
+            x = &x * &x % n;
+            if x == n_minus_one {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// Finds a nontrivial factor of the odd composite `n` with Brent's variant of the
+/// Pollard rho method.
+fn pollard_rho(n: &BigInt) -> Option<BigInt> {
+    for c in 1..=RHO_ATTEMPTS {
+        let step = |x: &BigInt| (x * x + c) % n;
+        let (mut x, mut y) = (BigInt::from(2), BigInt::from(2));
+        let mut power = 1;
+        let mut length = 0;
+        for _ in 0..MAX_RHO_STEPS {
+            if length == power {
+                x = y.clone();
+                power *= 2;
+                length = 0;
+            }
+            y = step(&y);
+            length += 1;
+            let divisor = (&x - &y).abs().gcd(n);
+            if &divisor == n {
+                break;
+            }
+            if !divisor.is_one() {
+                return Some(divisor);
+            }
+        }
+    }
+    None
+}
+
+/// Returns the distinct prime factors of `n > 0`, or `None` if the factoring gives up.
+fn prime_factors(n: &BigInt) -> Option<BTreeSet<BigInt>> {
+    let mut factors = BTreeSet::new();
+    let mut n = n.clone();
+    for p in SMALL_PRIMES.map(BigInt::from) {
+        while n.is_multiple_of(&p) {
+            n /= &p;
+            factors.insert(p.clone());
+        }
+    }
+
+    let mut pending = vec![n];
+    while let Some(n) = pending.pop() {
+        if n.is_one() {
+            continue;
+        }
+        if is_probable_prime(&n) {
+            factors.insert(n);
+            continue;
+        }
+        let factor = pollard_rho(&n)?;
+        pending.push(&n / &factor);
+        pending.push(factor);
+    }
+    Some(factors)
+}
+
+/// Square root of `a` modulo the odd prime `p`, with the Tonelli–Shanks algorithm.
+fn sqrt_mod(a: &BigInt, p: &BigInt) -> Option<BigInt> {
+    let a = a.mod_floor(p);
+    let legendre = |x: &BigInt| x.modpow(&((p - 1) >> 1), p);
+    if a.is_zero() {
+        return Some(a);
+    }
+    if !legendre(&a).is_one() {
+        return None;
+    }
+
+    let p_minus_one: BigInt = p - 1;
+    let twos = p_minus_one.trailing_zeros().unwrap_or_default();
+    let odd = &p_minus_one >> twos;
+    let mut non_residue = BigInt::from(2);
+    while legendre(&non_residue) != p_minus_one {
+        non_residue += 1;
+    }
+
+    let mut m = twos;
+    let mut c = non_residue.modpow(&odd, p);
+    let mut t = a.modpow(&odd, p);
+    let mut root = a.modpow(&((&odd + 1) >> 1), p);
+    while !t.is_one() {
+        let mut i = 0;
+        let mut t_power = t.clone();
+        while !t_power.is_one() {
+            t_power = &t_power * &t_power % p;
+            i += 1;
+            if i == m {
+                return None;
+            }
+        }
+        let b = c.modpow(&(BigInt::one() << (m - i - 1)), p);
+        root = root * &b % p;
+        c = &b * &b % p;
+        t = t * &c % p;
+        m = i;
+    }
+    Some(root)
+}
+
+/// Returns `t` with `t†t = p` times a unit, for a prime `p ≡ 3, 5 (mod 8)`.
+fn split_inert(p: &BigInt) -> Option<ZOmega> {
+    let prime = ZOmega::from(&ZRoot2::from_int(p.clone()));
+    let (root, unit) = if p.mod_floor(&BigInt::from(8)) == BigInt::from(5) {
+        // p = x² + y² = |x + iy|²
+        (sqrt_mod(&BigInt::from(-1), p)?, ZOmega::new([0, 0, 1, 0]))
+    } else {
+        // p = x² + 2y² = |x + i√2y|²
+        (sqrt_mod(&BigInt::from(-2), p)?, ZOmega::new([0, 1, 0, 1]))
+    };
+    let root = ZOmega::from(&ZRoot2::from_int(root));
+    ZOmega::gcd(&prime, &(&root + &unit))
+}
+
+/// Returns `t` with `t†t = η` times a unit, for a prime `η` of `Z[√2]` whose norm is a
+/// prime `p ≡ 1 (mod 8)`.
+fn split_real(eta: &ZRoot2, p: &BigInt) -> Option<ZOmega> {
+    let root = ZOmega::from(&ZRoot2::from_int(sqrt_mod(&BigInt::from(-1), p)?));
+    ZOmega::gcd(&ZOmega::from(eta), &(&root + &ZOmega::new([0, 0, 1, 0])))
+}
+
+/// Divides `rest` by `factor` as many times as possible, returning the count.
+fn remove_factor(rest: &mut ZRoot2, factor: &ZRoot2) -> u32 {
+    let mut count = 0;
+    while let Some(quotient) = rest.div_exact(factor) {
+        *rest = quotient;
+        count += 1;
+    }
+    count
+}
+
+/// Returns `t` in `Z[ω]` with `t†t = ξ`, or `None` if there is no solution or the
+/// factoring of the norm of `ξ` gives up.
+pub(crate) fn solve(xi: &ZRoot2) -> Option<ZOmega> {
+    if xi.is_zero() {
+        return Some(ZOmega::zero());
+    }
+    if !xi.is_nonnegative() || !xi.conj().is_nonnegative() {
+        return None;
+    }
+
+    let eight = BigInt::from(8);
+    let mut rest = xi.clone();
+    let mut t = ZOmega::one();
+    for p in prime_factors(&xi.norm())? {
+        if p == BigInt::from(2) {
+            // √2 = (1 + ω)†(1 + ω) times a unit
+            while let Some(quotient) = rest.div_sqrt2() {
+                rest = quotient;
+                t = t * ZOmega::new([1, 1, 0, 0]);
+            }
+            continue;
+        }
+
+        match p.mod_floor(&eight).to_u8()? {
+            3 | 5 => {
+                let count = remove_factor(&mut rest, &ZRoot2::from_int(p.clone()));
+                t = t * split_inert(&p)?.pow(count);
+            }
+            residue => {
+                let root = sqrt_mod(&BigInt::from(2), &p)?;
+                let eta = ZRoot2::gcd(&ZRoot2::from_int(p.clone()), &ZRoot2::new(root, 1))?;
+                for eta in [eta.conj(), eta] {
+                    let count = remove_factor(&mut rest, &eta);
+                    if residue == 7 {
+                        // η is prime in Z[ω], so it must appear an even number of times
+                        if count % 2 == 1 {
+                            return None;
+                        }
+                        t = t * ZOmega::from(&eta.pow(count / 2));
+                    } else if count > 0 {
+                        t = t * split_real(&eta, &p)?.pow(count);
+                    }
+                }
+            }
+        }
+    }
+
+    if rest.norm().abs() != BigInt::one() {
+        return None;
+    }
+
+    // The remaining unit is a totally positive unit λ^2j, which is the squared
+    // absolute value of λ^j.
+    let mut unit = xi.div_exact(&t.abs_squared())?;
+    let mut exponent = 0;
+    let lambda_squared = ZRoot2::lambda().pow(2);
+    while unit != ZRoot2::from_int(1) {
+        if exponent.abs() > MAX_UNIT_EXPONENT {
+            return None;
+        }
+        if unit.value() > 1.0 {
+            unit = unit.div_exact(&lambda_squared)?;
+            exponent += 1;
+        } else {
+            unit = &unit * &lambda_squared;
+            exponent -= 1;
+        }
+    }
+    let t = t * ZOmega::from(&ZRoot2::lambda_pow(exponent));
+    (t.abs_squared() == *xi).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use num::BigInt;
+
+    use super::{prime_factors, solve, sqrt_mod};
+    use crate::passes::gridsynth::ring::{ZOmega, ZRoot2};
+
+    #[test]
+    fn norm_equation() {
+        assert_eq!(
+            sqrt_mod(&BigInt::from(2), &BigInt::from(17)).map(|r| r.modpow(&2.into(), &17.into())),
+            Some(BigInt::from(2))
+        );
+        let factors: Vec<_> = prime_factors(&BigInt::from(1_000_003u64 * 999_983 * 12))
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(factors, [2, 3, 999_983, 1_000_003].map(BigInt::from));
+
+        for xi in [ZRoot2::new(2, 0), ZRoot2::new(3, 0), ZRoot2::new(17, 0)] {
+            assert_eq!(solve(&xi).unwrap().abs_squared(), xi);
+        }
+        for c in [[1, 2, 3, 4], [-7, 0, 5, 11], [120, -33, 17, 2]] {
+            let xi = ZOmega::new(c).abs_squared();
+            assert_eq!(solve(&xi).unwrap().abs_squared(), xi);
+        }
+        // Not totally positive.
+        assert!(solve(&ZRoot2::new(1, 1)).is_none());
+        // 7 = (3 + √2)(3 - √2), where each factor is prime in Z[ω].
+        assert!(solve(&ZRoot2::new(3, 1)).is_none());
+    }
+}
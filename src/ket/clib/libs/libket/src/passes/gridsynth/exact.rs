@@ -0,0 +1,230 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Exact synthesis of single-qubit Clifford+T unitaries.
+//!
+//! A unitary with entries in `Z[ω]/√2^k` is reduced to a diagonal matrix of powers of
+//! `ω` by multiplying it on the left by `HT^j`, choosing `j` so that the smallest
+//! denominator exponent of `|u|²`, where `u` is the top-left entry, decreases by one
+//! (Kliuchnikov, Maslov, and Mosca). Once the exponent is small, a breadth-first search
+//! over `H` and `T` finishes the reduction.
+
+use std::collections::{HashSet, VecDeque};
+
+use super::ring::ZOmega;
+
+/// Smallest denominator exponent handled by the breadth-first search.
+const SEARCH_EXPONENT: u32 = 4;
+
+/// Largest number of states visited by the breadth-first search.
+const MAX_SEARCH_STATES: usize = 1 << 14;
+
+/// Gate of a Clifford+T circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Gate {
+    H,
+    /// `T^j`, for `j` in `0..8`.
+    T(u8),
+}
+
+/// Matrix `entries / √2^exponent` with entries in `Z[ω]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Unitary {
+    entries: [[ZOmega; 2]; 2],
+    exponent: u32,
+}
+
+impl Unitary {
+    /// Creates `[[u, -t†], [t, u†]] / √2^exponent`.
+    pub(crate) fn new(u: ZOmega, t: ZOmega, exponent: u32) -> Self {
+        let mut unitary = Self {
+            entries: [[u.clone(), -&t.conj()], [t, u.conj()]],
+            exponent,
+        };
+        unitary.reduce();
+        unitary
+    }
+
+    /// Divides the entries by `√2` while possible.
+    fn reduce(&mut self) {
+        while self.exponent > 0 {
+            let entries: Option<Vec<ZOmega>> = self
+                .entries
+                .iter()
+                .flatten()
+                .map(ZOmega::div_sqrt2)
+                .collect();
+            match entries {
+                Some(entries) => {
+                    let [a, b, c, d]: [ZOmega; 4] = entries.try_into().unwrap();
+                    self.entries = [[a, b], [c, d]];
+                    self.exponent -= 1;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Multiplies the matrix by a gate on the left.
+    fn apply(&mut self, gate: Gate) {
+        match gate {
+            Gate::H => {
+                let [row_0, row_1] = &self.entries;
+                self.entries = [
+                    [&row_0[0] + &row_1[0], &row_0[1] + &row_1[1]],
+                    [&row_0[0] - &row_1[0], &row_0[1] - &row_1[1]],
+                ];
+                self.exponent += 1;
+                self.reduce();
+            }
+            Gate::T(j) => {
+                let omega = ZOmega::omega_pow(j as i32);
+                for entry in self.entries[1].iter_mut() {
+                    *entry = &omega * entry;
+                }
+            }
+        }
+    }
+
+    /// Smallest denominator exponent of `|u|²`, as an element of `Z[√2]/√2^k`.
+    fn sde(&self) -> u32 {
+        let mut abs_squared = self.entries[0][0].abs_squared();
+        if abs_squared.is_zero() {
+            return 0;
+        }
+        let mut exponent = 2 * self.exponent;
+        while exponent > 0 {
+            match abs_squared.div_sqrt2() {
+                Some(quotient) => {
+                    abs_squared = quotient;
+                    exponent -= 1;
+                }
+                None => break,
+            }
+        }
+        exponent
+    }
+
+    /// Returns `j` if the matrix is `ω^m diag(1, ω^j)`.
+    fn diagonal_phase(&self) -> Option<u8> {
+        let [[a, b], [c, d]] = &self.entries;
+        if self.exponent != 0 || !b.is_zero() || !c.is_zero() {
+            return None;
+        }
+        (0..8)
+            .find(|j| &(a * &ZOmega::omega_pow(*j)) == d)
+            .map(|j| j as u8)
+    }
+
+    /// The matrix normalized up to a global phase.
+    fn key(&self) -> (u32, ZOmega, ZOmega, ZOmega, ZOmega) {
+        (0..8)
+            .map(|m| {
+                let phase = ZOmega::omega_pow(m);
+                let [[a, b], [c, d]] = &self.entries;
+                (
+                    self.exponent,
+                    &phase * a,
+                    &phase * b,
+                    &phase * c,
+                    &phase * d,
+                )
+            })
+            .min()
+            .unwrap()
+    }
+}
+
+/// Finds gates `W` such that `W U` is diagonal, with a breadth-first search over the
+/// products `HT^j`.
+fn search(unitary: Unitary) -> Option<(Vec<Gate>, u8)> {
+    let mut visited = HashSet::from([unitary.key()]);
+    let mut states = vec![(unitary, None)];
+    let mut queue = VecDeque::from([0]);
+
+    while let Some(index) = queue.pop_front() {
+        let state = states[index].0.clone();
+        if let Some(phase) = state.diagonal_phase() {
+            let mut gates = Vec::new();
+            let mut current = index;
+            while let Some((parent, j)) = states[current].1 {
+                gates.extend([Gate::H, Gate::T(j)]);
+                current = parent;
+            }
+            gates.reverse();
+            return Some((gates, phase));
+        }
+
+        for j in 0..8 {
+            let mut next = state.clone();
+            next.apply(Gate::T(j));
+            next.apply(Gate::H);
+            if !visited.insert(next.key()) {
+                continue;
+            }
+            if states.len() == MAX_SEARCH_STATES {
+                return None;
+            }
+            queue.push_back(states.len());
+            states.push((next, Some((index, j))));
+        }
+    }
+    None
+}
+
+/// Decomposes the unitary into `H` and `T^j` gates, up to a global phase.
+///
+/// The gates are returned in circuit order, so the first gate is applied first.
+pub(crate) fn synthesize(mut unitary: Unitary) -> Option<Vec<Gate>> {
+    let mut reduction = Vec::new();
+    while unitary.sde() >= SEARCH_EXPONENT {
+        let sde = unitary.sde();
+        let (next, j) = (0..4)
+            .map(|j| {
+                let mut next = unitary.clone();
+                next.apply(Gate::T(j));
+                next.apply(Gate::H);
+                (next, j)
+            })
+            .min_by_key(|(next, _)| next.sde())?;
+        if next.sde() >= sde {
+            break;
+        }
+        reduction.extend([Gate::T(j), Gate::H]);
+        unitary = next;
+    }
+
+    let (tail, phase) = search(unitary)?;
+    reduction.extend(tail);
+
+    // W U = ω^m diag(1, ω^j), so U = W^-1 T^j up to a global phase.
+    let mut gates = vec![Gate::T(phase)];
+    gates.extend(reduction.iter().rev().map(|gate| match gate {
+        Gate::H => Gate::H,
+        Gate::T(j) => Gate::T((8 - j) % 8),
+    }));
+    Some(simplify(&gates))
+}
+
+/// Merges adjacent `T` gates and cancels adjacent `H` gates.
+fn simplify(gates: &[Gate]) -> Vec<Gate> {
+    let mut result: Vec<Gate> = Vec::new();
+    for gate in gates {
+        match (result.last().copied(), gate) {
+            (Some(Gate::H), Gate::H) => {
+                result.pop();
+            }
+            (Some(Gate::T(a)), Gate::T(b)) => {
+                result.pop();
+                if (a + b) % 8 != 0 {
+                    result.push(Gate::T((a + b) % 8));
+                }
+            }
+            (_, Gate::T(0)) => {}
+            _ => result.push(*gate),
+        }
+    }
+    result
+}
@@ -0,0 +1,199 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Clifford+T approximation of `RZ` rotations, following Ross and Selinger,
+//! "Optimal ancilla-free Clifford+T approximation of z-rotations" (2016).
+//!
+//! A Clifford+T unitary `[[u, -t†], [t, u†]]` has entries `u = α/√2^k` and `t = β/√2^k`
+//! with `α` and `β` in `Z[ω]`. It is within `ε` of `RZ(θ)` in operator norm if `u` lies
+//! in the ε-region, the part of the unit disk whose projection on `e^(-iθ/2)` is at
+//! least `1 - ε²/2`, and `|u|² + |t|² = 1`. For increasing `k`, the candidates `α` are
+//! the solutions of a grid problem, and `β` is found by solving the norm equation
+//! `β†β = 2^k - α†α`. The first solution is decomposed into `H` and `T` gates by exact
+//! synthesis, with a T-count of about `3 log2(1/ε)`.
+//!
+//! Unlike the reference algorithm, the two-dimensional grid problem is solved by
+//! scanning the real part of `α` and solving a one-dimensional grid problem for the
+//! imaginary part, which takes `O(ε^-1/2)` steps for each `k` instead of a constant
+//! number. The search is carried out in double precision, which limits `ε` to
+//! [`MIN_EPSILON`].
+
+mod diophantine;
+mod exact;
+mod ring;
+
+use std::f64::consts::{PI, SQRT_2};
+
+use num::{complex::Complex64, BigInt, Integer};
+
+use crate::{
+    error::{KetError, Result},
+    ir::{Angle, QuantumGate},
+};
+
+use exact::{Gate, Unitary};
+use ring::{ZOmega, ZRoot2};
+
+/// Smallest supported approximation error.
+pub const MIN_EPSILON: f64 = 1e-7;
+
+/// Largest denominator exponent searched.
+const MAX_EXPONENT: u32 = 80;
+
+/// Largest scaling exponent of a one-dimensional grid problem.
+const MAX_SCALING: i32 = 40;
+
+/// Returns all `x` in `Z[√2]` with `x` in `[x0, x1]` and its `√2`-conjugate in
+/// `[y0, y1]`.
+///
+/// The problem is first scaled by a power of `λ = 1 + √2` so that both intervals have
+/// about the same width, which bounds the number of steps by the number of solutions
+/// plus a constant.
+fn grid_problem(x0: f64, x1: f64, y0: f64, y1: f64) -> Vec<ZRoot2> {
+    if x1 < x0 || y1 < y0 {
+        return Vec::new();
+    }
+
+    let lambda = 1.0 + SQRT_2;
+    let ratio = (y1 - y0).max(f64::MIN_POSITIVE) / (x1 - x0).max(f64::MIN_POSITIVE);
+    let scaling =
+        ((ratio.ln() / (2.0 * lambda.ln())).round() as i32).clamp(-MAX_SCALING, MAX_SCALING);
+
+    // λ• = -1/λ, so the conjugate interval is scaled by (-λ)^-scaling.
+    let factor = lambda.powi(scaling);
+    let (x0, x1) = (x0 * factor, x1 * factor);
+    let (y0, y1) = if scaling % 2 == 0 {
+        (y0 / factor, y1 / factor)
+    } else {
+        (-y1 / factor, -y0 / factor)
+    };
+
+    let unscale = ZRoot2::lambda_pow(-scaling);
+    let mut solutions = Vec::new();
+    let b_min = ((x0 - y1) / (2.0 * SQRT_2)).ceil() as i64;
+    let b_max = ((x1 - y0) / (2.0 * SQRT_2)).floor() as i64;
+    for b in b_min..=b_max {
+        let offset = b as f64 * SQRT_2;
+        let a_min = (x0 - offset).max(y0 + offset).ceil() as i64;
+        let a_max = (x1 - offset).min(y1 + offset).floor() as i64;
+        for a in a_min..=a_max {
+            solutions.push(&ZRoot2::new(a, b) * &unscale);
+        }
+    }
+    solutions
+}
+
+/// Intersection of the ε-region with the vertical line at `x`, where `z` is the
+/// direction of the region and `cos` is the bound of the projection.
+fn region_slice(x: f64, z: Complex64, cos: f64) -> Option<(f64, f64)> {
+    let radius = (1.0 - x * x).max(0.0).sqrt();
+    let (mut y0, mut y1) = (-radius, radius);
+    let bound = cos - x * z.re;
+    if z.im > 0.0 {
+        y0 = y0.max(bound / z.im);
+    } else if z.im < 0.0 {
+        y1 = y1.min(bound / z.im);
+    } else if bound > 0.0 {
+        return None;
+    }
+    (y0 <= y1).then_some((y0, y1))
+}
+
+/// Horizontal extent of the ε-region, the arc of angles `phi ± delta`.
+fn region_width(phi: f64, delta: f64) -> (f64, f64) {
+    let contains = |angle: f64| ((phi - angle + PI).rem_euclid(2.0 * PI) - PI).abs() <= delta;
+    let ends = [f64::cos(phi - delta), f64::cos(phi + delta)];
+    let x0 = if contains(PI) {
+        -1.0
+    } else {
+        ends[0].min(ends[1])
+    };
+    let x1 = if contains(0.0) {
+        1.0
+    } else {
+        ends[0].max(ends[1])
+    };
+    (x0, x1)
+}
+
+/// Error of the approximation of `diag(z, z†)` by `[[u, -t†], [t, u†]]`.
+fn distance(z: Complex64, u: Complex64, t: Complex64) -> f64 {
+    ((u - z).norm_sqr() + t.norm_sqr()).sqrt()
+}
+
+/// Returns `2^k` as an element of `Z[√2]`.
+fn power_of_two(k: u32) -> ZRoot2 {
+    ZRoot2::from_int(BigInt::from(1) << k)
+}
+
+/// Approximates `RZ(theta)` by a sequence of `H` and `T^j` gates, up to a global
+/// phase, with an error of at most `epsilon` in operator norm.
+///
+/// The gates are returned in circuit order, with `T^j` as a
+/// [`Phase`](QuantumGate::Phase) gate of `jπ/4`.
+///
+/// # Errors
+///
+/// Returns [`KetError::ApproximationFailed`] if `epsilon` is not in `[MIN_EPSILON, 1)`.
+pub fn approximate_rz(theta: f64, epsilon: f64) -> Result<Vec<QuantumGate>> {
+    if !(MIN_EPSILON..1.0).contains(&epsilon) || !theta.is_finite() {
+        return Err(KetError::ApproximationFailed);
+    }
+
+    let phi = -theta.rem_euclid(4.0 * PI) / 2.0;
+    let z = Complex64::from_polar(1.0, phi);
+    let cos = 1.0 - epsilon * epsilon / 2.0;
+    let (x0, x1) = region_width(phi, 2.0 * (epsilon / 2.0).asin());
+
+    for k in 0..=MAX_EXPONENT {
+        // X = √2 Re(α) and Y = √2 Im(α) are in Z[√2], and their conjugates are bounded
+        // by the unit disk.
+        let scale = SQRT_2.powi(k as i32 + 1);
+        let denominator = SQRT_2.powi(k as i32);
+        for x in grid_problem(scale * x0, scale * x1, -scale, scale) {
+            let Some((y0, y1)) = region_slice(x.value() / scale, z, cos) else {
+                continue;
+            };
+            let bound = (scale * scale - x.conj().value().powi(2)).max(0.0).sqrt();
+            for y in grid_problem(scale * y0, scale * y1, -bound, bound) {
+                if !(&x.a - &y.a).is_even() {
+                    continue;
+                }
+                let alpha = ZOmega([
+                    x.b.clone(),
+                    (&x.a + &y.a) / 2,
+                    y.b.clone(),
+                    (&y.a - &x.a) / 2,
+                ]);
+                let u = alpha.value() / denominator;
+                if distance(z, u, Complex64::default()) > epsilon {
+                    continue;
+                }
+
+                let xi = &power_of_two(k) - &alpha.abs_squared();
+                let Some(beta) = diophantine::solve(&xi) else {
+                    continue;
+                };
+                if distance(z, u, beta.value() / denominator) > epsilon {
+                    continue;
+                }
+                if let Some(gates) = exact::synthesize(Unitary::new(alpha, beta, k)) {
+                    return Ok(gates
+                        .into_iter()
+                        .map(|gate| match gate {
+                            Gate::H => QuantumGate::Hadamard,
+                            Gate::T(j) => {
+                                let j = if j > 4 { j as i32 - 8 } else { j as i32 };
+                                QuantumGate::Phase(Angle::pi_fraction(j, 4))
+                            }
+                        })
+                        .collect());
+                }
+            }
+        }
+    }
+
+    Err(KetError::ApproximationFailed)
+}
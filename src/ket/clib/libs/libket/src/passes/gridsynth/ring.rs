@@ -0,0 +1,314 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The rings `Z[√2]` and `Z[ω]`, with `ω = e^(iπ/4)`.
+//!
+//! Both rings are Euclidean, so greatest common divisors are computed with the usual
+//! Euclidean algorithm, rounding the exact quotient coefficient by coefficient.
+
+use std::f64::consts::SQRT_2;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use num::{complex::Complex64, BigInt, Integer, Signed, ToPrimitive, Zero};
+
+/// Largest number of steps of the Euclidean algorithm.
+const MAX_GCD_STEPS: usize = 1000;
+
+/// Returns `x / n` rounded to the nearest integer, for `n > 0`.
+fn round_div(x: &BigInt, n: &BigInt) -> BigInt {
+    let numerator: BigInt = 2 * x + n;
+    numerator.div_floor(&(2 * n))
+}
+
+/// Element `a + b√2` of `Z[√2]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ZRoot2 {
+    pub(crate) a: BigInt,
+    pub(crate) b: BigInt,
+}
+
+impl ZRoot2 {
+    pub(crate) fn new(a: impl Into<BigInt>, b: impl Into<BigInt>) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+        }
+    }
+
+    pub(crate) fn from_int(a: impl Into<BigInt>) -> Self {
+        Self::new(a, 0)
+    }
+
+    /// The fundamental unit `λ = 1 + √2`.
+    pub(crate) fn lambda() -> Self {
+        Self::new(1, 1)
+    }
+
+    /// Returns `λ^exponent`, where `λ^-1 = √2 - 1`.
+    pub(crate) fn lambda_pow(exponent: i32) -> Self {
+        let base = if exponent < 0 {
+            Self::new(-1, 1)
+        } else {
+            Self::lambda()
+        };
+        base.pow(exponent.unsigned_abs())
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.a.is_zero() && self.b.is_zero()
+    }
+
+    pub(crate) fn pow(&self, exponent: u32) -> Self {
+        (0..exponent).fold(Self::from_int(1), |result, _| &result * self)
+    }
+
+    /// The `√2`-conjugate `a - b√2`.
+    pub(crate) fn conj(&self) -> Self {
+        Self::new(self.a.clone(), -&self.b)
+    }
+
+    /// The norm `a² - 2b²`.
+    pub(crate) fn norm(&self) -> BigInt {
+        &self.a * &self.a - 2 * &self.b * &self.b
+    }
+
+    pub(crate) fn value(&self) -> f64 {
+        self.a.to_f64().unwrap_or(f64::NAN) + self.b.to_f64().unwrap_or(f64::NAN) * SQRT_2
+    }
+
+    /// Returns `true` if `a + b√2 >= 0`, computed exactly.
+    pub(crate) fn is_nonnegative(&self) -> bool {
+        let (a_square, b_square) = (&self.a * &self.a, 2 * &self.b * &self.b);
+        match (self.a.is_negative(), self.b.is_negative()) {
+            (false, false) => true,
+            (true, true) => false,
+            (false, true) => a_square >= b_square,
+            (true, false) => b_square >= a_square,
+        }
+    }
+
+    /// Returns `self / √2`, if it is in the ring.
+    pub(crate) fn div_sqrt2(&self) -> Option<Self> {
+        self.a
+            .is_even()
+            .then(|| Self::new(self.b.clone(), &self.a / 2))
+    }
+
+    /// Returns `self / other`, if it is in the ring.
+    pub(crate) fn div_exact(&self, other: &Self) -> Option<Self> {
+        let norm = other.norm();
+        if norm.is_zero() {
+            return None;
+        }
+        let numerator = self * &other.conj();
+        (numerator.a.is_multiple_of(&norm) && numerator.b.is_multiple_of(&norm))
+            .then(|| Self::new(&numerator.a / &norm, &numerator.b / &norm))
+    }
+
+    /// Quotient of the Euclidean division.
+    fn div_round(&self, other: &Self) -> Self {
+        let (mut numerator, mut norm) = (self * &other.conj(), other.norm());
+        if norm.is_negative() {
+            numerator = -numerator;
+            norm = -norm;
+        }
+        Self::new(
+            round_div(&numerator.a, &norm),
+            round_div(&numerator.b, &norm),
+        )
+    }
+
+    /// Greatest common divisor, up to a unit.
+    pub(crate) fn gcd(a: &Self, b: &Self) -> Option<Self> {
+        let (mut a, mut b) = (a.clone(), b.clone());
+        for _ in 0..MAX_GCD_STEPS {
+            if b.is_zero() {
+                return Some(a);
+            }
+            let remainder = &a - &(&a.div_round(&b) * &b);
+            (a, b) = (b, remainder);
+        }
+        None
+    }
+}
+
+impl Add for &ZRoot2 {
+    type Output = ZRoot2;
+
+    fn add(self, other: &ZRoot2) -> ZRoot2 {
+        ZRoot2::new(&self.a + &other.a, &self.b + &other.b)
+    }
+}
+
+impl Sub for &ZRoot2 {
+    type Output = ZRoot2;
+
+    fn sub(self, other: &ZRoot2) -> ZRoot2 {
+        ZRoot2::new(&self.a - &other.a, &self.b - &other.b)
+    }
+}
+
+impl Mul for &ZRoot2 {
+    type Output = ZRoot2;
+
+    fn mul(self, other: &ZRoot2) -> ZRoot2 {
+        ZRoot2::new(
+            &self.a * &other.a + 2 * &self.b * &other.b,
+            &self.a * &other.b + &self.b * &other.a,
+        )
+    }
+}
+
+impl Neg for ZRoot2 {
+    type Output = ZRoot2;
+
+    fn neg(self) -> ZRoot2 {
+        ZRoot2::new(-self.a, -self.b)
+    }
+}
+
+/// Element `c[0] + c[1]ω + c[2]ω² + c[3]ω³` of `Z[ω]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) struct ZOmega(pub(crate) [BigInt; 4]);
+
+impl ZOmega {
+    pub(crate) fn new(c: [i64; 4]) -> Self {
+        Self(c.map(BigInt::from))
+    }
+
+    pub(crate) fn zero() -> Self {
+        Self::new([0; 4])
+    }
+
+    pub(crate) fn one() -> Self {
+        Self::new([1, 0, 0, 0])
+    }
+
+    /// Returns `ω^exponent`.
+    pub(crate) fn omega_pow(exponent: i32) -> Self {
+        let exponent = exponent.rem_euclid(8) as usize;
+        let mut c = [0; 4];
+        c[exponent % 4] = if exponent < 4 { 1 } else { -1 };
+        Self::new(c)
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.0.iter().all(Zero::is_zero)
+    }
+
+    pub(crate) fn pow(&self, exponent: u32) -> Self {
+        (0..exponent).fold(Self::one(), |result, _| &result * self)
+    }
+
+    /// The complex conjugate.
+    pub(crate) fn conj(&self) -> Self {
+        let [c0, c1, c2, c3] = &self.0;
+        Self([c0.clone(), -c3, -c2, -c1])
+    }
+
+    /// The squared absolute value, an element of `Z[√2]`.
+    pub(crate) fn abs_squared(&self) -> ZRoot2 {
+        let [c0, c1, ..] = (self * &self.conj()).0;
+        ZRoot2::new(c0, c1)
+    }
+
+    /// The norm, a nonnegative integer.
+    pub(crate) fn norm(&self) -> BigInt {
+        self.abs_squared().norm()
+    }
+
+    pub(crate) fn value(&self) -> Complex64 {
+        let [c0, c1, c2, c3] = self.0.each_ref().map(|c| c.to_f64().unwrap_or(f64::NAN));
+        Complex64::new(c0 + (c1 - c3) / SQRT_2, c2 + (c1 + c3) / SQRT_2)
+    }
+
+    /// Returns `self / √2`, if it is in the ring.
+    pub(crate) fn div_sqrt2(&self) -> Option<Self> {
+        let product = self * &ZOmega::from(&ZRoot2::new(0, 1));
+        product
+            .0
+            .iter()
+            .all(Integer::is_even)
+            .then(|| Self(product.0.map(|c| c / 2)))
+    }
+
+    /// Quotient of the Euclidean division.
+    fn div_round(&self, other: &Self) -> Self {
+        let norm = other.norm();
+        let numerator = self * &other.conj() * ZOmega::from(&other.abs_squared().conj());
+        Self(numerator.0.map(|c| round_div(&c, &norm)))
+    }
+
+    /// Greatest common divisor, up to a unit.
+    pub(crate) fn gcd(a: &Self, b: &Self) -> Option<Self> {
+        let (mut a, mut b) = (a.clone(), b.clone());
+        for _ in 0..MAX_GCD_STEPS {
+            if b.is_zero() {
+                return Some(a);
+            }
+            let remainder = &a - &(&a.div_round(&b) * &b);
+            (a, b) = (b, remainder);
+        }
+        None
+    }
+}
+
+impl From<&ZRoot2> for ZOmega {
+    /// Embeds `a + b√2` as `a + bω - bω³`.
+    fn from(x: &ZRoot2) -> Self {
+        Self([x.a.clone(), x.b.clone(), BigInt::zero(), -&x.b])
+    }
+}
+
+impl Add for &ZOmega {
+    type Output = ZOmega;
+
+    fn add(self, other: &ZOmega) -> ZOmega {
+        ZOmega([0, 1, 2, 3].map(|i| &self.0[i] + &other.0[i]))
+    }
+}
+
+impl Sub for &ZOmega {
+    type Output = ZOmega;
+
+    fn sub(self, other: &ZOmega) -> ZOmega {
+        ZOmega([0, 1, 2, 3].map(|i| &self.0[i] - &other.0[i]))
+    }
+}
+
+impl Mul for &ZOmega {
+    type Output = ZOmega;
+
+    fn mul(self, other: &ZOmega) -> ZOmega {
+        let mut c: [BigInt; 4] = Default::default();
+        for (i, x) in self.0.iter().enumerate() {
+            for (j, y) in other.0.iter().enumerate() {
+                // ω^4 = -1
+                if i + j < 4 {
+                    c[i + j] += x * y;
+                } else {
+                    c[i + j - 4] -= x * y;
+                }
+            }
+        }
+        ZOmega(c)
+    }
+}
+
+impl Mul<ZOmega> for ZOmega {
+    type Output = ZOmega;
+
+    fn mul(self, other: ZOmega) -> ZOmega {
+        &self * &other
+    }
+}
+
+impl Neg for &ZOmega {
+    type Output = ZOmega;
+
+    fn neg(self) -> ZOmega {
+        ZOmega(self.0.clone().map(|c| -c))
+    }
+}
@@ -0,0 +1,386 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Qubit lifetimes and register compaction.
+//!
+//! Every allocation gets a new qubit index, so the declared register of a process has
+//! one qubit per allocation. A freed qubit is in the `|0>` state by contract, so a later
+//! allocation can take its place; a qubit that is never freed can only be reused after
+//! a reset is added after its last use. The OpenQASM export declares the compacted
+//! register, with a reset where an allocation reuses a qubit.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    ir::{Condition, Instruction},
+    objects::QubitStatus,
+    Process,
+};
+
+use super::{assert_all_editable, peephole::qubits, relabel_results, Pass};
+
+/// Lifetime of a qubit, in instruction indices.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QubitLifetime {
+    /// Qubit index.
+    pub qubit: usize,
+
+    /// Index of the allocation.
+    pub allocated: usize,
+
+    /// Index of the first instruction that uses the qubit.
+    pub first_use: Option<usize>,
+
+    /// Index of the last instruction that uses the qubit.
+    pub last_use: Option<usize>,
+
+    /// Index of the instruction that frees the qubit.
+    pub freed: Option<usize>,
+
+    /// Ranges `[start, end)` of instructions between two uses of the qubit.
+    pub idle_spans: Vec<(usize, usize)>,
+
+    /// First qubit allocated after the last use of this one, which could take its
+    /// place. Without a [`freed`](Self::freed) index, a reset must be added first.
+    pub reusable_by: Option<usize>,
+}
+
+impl QubitLifetime {
+    /// Returns `true` if the qubit must be reset before another allocation reuses it.
+    pub fn needs_reset(&self) -> bool {
+        self.freed.is_none()
+    }
+
+    /// Index from which the qubit is no longer used.
+    fn end(&self) -> usize {
+        self.last_use.unwrap_or(self.allocated) + 1
+    }
+}
+
+/// Lifetime analysis of the qubits of a process.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LifetimeReport {
+    /// Lifetime of each qubit, in order of allocation.
+    pub qubits: Vec<QubitLifetime>,
+
+    /// Number of declared qubits, the distinct qubit indices.
+    pub declared: usize,
+
+    /// Number of qubits after [`QubitCompaction`], which reuses freed qubits.
+    pub width: usize,
+
+    /// Number of qubits if every qubit were reset after its last use.
+    pub width_with_resets: usize,
+}
+
+impl LifetimeReport {
+    /// Analyzes the qubits of the process instructions.
+    pub fn new(process: &Process) -> Self {
+        Self::from_instructions(&process.instructions)
+    }
+
+    /// Analyzes the qubits of a list of instructions.
+    pub fn from_instructions(instructions: &[Instruction]) -> Self {
+        let mut lifetimes: Vec<QubitLifetime> = Vec::new();
+        let mut index_of = HashMap::new();
+        let (mut live, mut width) = (0usize, 0usize);
+
+        for (index, instruction) in instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Alloc { target } => {
+                    index_of.insert(*target, lifetimes.len());
+                    lifetimes.push(QubitLifetime {
+                        qubit: *target,
+                        allocated: index,
+                        ..Default::default()
+                    });
+                    live += 1;
+                    width = width.max(live);
+                }
+                Instruction::Free { target } => {
+                    if let Some(lifetime) = index_of.get(target).map(|i| &mut lifetimes[*i]) {
+                        lifetime.freed = Some(index);
+                        live -= 1;
+                    }
+                }
+                _ => {
+                    for qubit in qubits(instruction) {
+                        let Some(lifetime) = index_of.get(&qubit).map(|i| &mut lifetimes[*i])
+                        else {
+                            continue;
+                        };
+                        match lifetime.last_use {
+                            Some(last) if last == index => continue,
+                            Some(last) if index > last + 1 => {
+                                lifetime.idle_spans.push((last + 1, index))
+                            }
+                            _ => {}
+                        }
+                        lifetime.first_use.get_or_insert(index);
+                        lifetime.last_use = Some(index);
+                    }
+                }
+            }
+        }
+
+        for i in 0..lifetimes.len() {
+            let end = lifetimes[i].end();
+            lifetimes[i].reusable_by = lifetimes[i + 1..]
+                .iter()
+                .find(|other| other.allocated >= end)
+                .map(|other| other.qubit);
+        }
+
+        // Largest number of overlapping [first_use, last_use] intervals.
+        let mut events: Vec<(usize, bool)> = lifetimes
+            .iter()
+            .filter_map(|lifetime| Some((lifetime.first_use?, lifetime.end())))
+            .flat_map(|(start, end)| [(start, true), (end, false)])
+            .collect();
+        events.sort_by_key(|(index, start)| (*index, *start));
+        let (mut overlapping, mut width_with_resets) = (0usize, 0usize);
+        for (_, start) in events {
+            if start {
+                overlapping += 1;
+                width_with_resets = width_with_resets.max(overlapping);
+            } else {
+                overlapping -= 1;
+            }
+        }
+
+        Self {
+            declared: index_of.len(),
+            qubits: lifetimes,
+            width,
+            width_with_resets,
+        }
+    }
+}
+
+/// Reduces the declared register by giving each allocation the index of a freed
+/// qubit, if there is one.
+///
+/// The qubit indices returned by [`Process::allocate_qubit`] are no longer valid after
+/// the pass, so it must run on a complete circuit, without a frozen prefix or opaque
+/// blocks. Qubits that are never freed keep their own index, see [`LifetimeReport`].
+/// The new qubits of the results are recorded in the
+/// [`MappingReport`](crate::MappingReport) of the metadata.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QubitCompaction;
+
+impl Pass for QubitCompaction {
+    fn name(&self) -> String {
+        "qubit_compaction".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        assert_all_editable(process)?;
+        let (instructions, slot_of) = compact(&process.instructions);
+        let map = |qubits: &[usize]| -> Vec<usize> {
+            qubits.iter().map(|qubit| slot_of[*qubit]).collect()
+        };
+
+        relabel_results(process, |process| {
+            for measurement in &mut process.measurements {
+                measurement.qubits = map(&measurement.qubits);
+            }
+            for sample in &mut process.samples {
+                sample.qubits = map(&sample.qubits);
+            }
+            for dump in &mut process.dumps {
+                dump.qubits = map(&dump.qubits);
+            }
+            Ok(())
+        })?;
+        for exp_value in &mut process.exp_values {
+            for term in exp_value.hamiltonian.products.iter_mut().flatten() {
+                term.qubit = slot_of[term.qubit];
+            }
+        }
+
+        let width = slot_of.iter().map(|slot| slot + 1).max().unwrap_or(0);
+        let mut qubits = vec![QubitStatus::default(); width];
+        for (qubit, status) in process.qubits.iter().enumerate() {
+            qubits[slot_of[qubit]] = status.clone();
+        }
+        process.qubit_allocated = qubits.iter().filter(|status| status.allocated).count();
+        process.qubits = qubits;
+        process.instructions = instructions;
+
+        Ok(())
+    }
+}
+
+/// Returns the instructions with the compacted qubit indices, and the new index of each
+/// qubit.
+pub fn compact(instructions: &[Instruction]) -> (Vec<Instruction>, Vec<usize>) {
+    let num_qubits = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Alloc { target } => Some(target + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut slot_of: Vec<usize> = (0..num_qubits).collect();
+    let mut free_slots = Vec::new();
+    let mut width = 0;
+
+    let instructions = instructions
+        .iter()
+        .map(|instruction| match instruction {
+            Instruction::Alloc { target } => {
+                let slot = free_slots.pop().unwrap_or_else(|| {
+                    width += 1;
+                    width - 1
+                });
+                slot_of[*target] = slot;
+                Instruction::Alloc { target: slot }
+            }
+            Instruction::Free { target } => {
+                free_slots.push(slot_of[*target]);
+                Instruction::Free {
+                    target: slot_of[*target],
+                }
+            }
+            instruction => remap(instruction, &slot_of),
+        })
+        .collect();
+
+    (instructions, slot_of)
+}
+
+/// Returns the instruction with each qubit `q` replaced by `slot_of[q]`.
+pub(crate) fn remap(instruction: &Instruction, slot_of: &[usize]) -> Instruction {
+    let map = |qubits: &[usize]| qubits.iter().map(|qubit| slot_of[*qubit]).collect();
+    match instruction {
+        Instruction::Alloc { target } => Instruction::Alloc {
+            target: slot_of[*target],
+        },
+        Instruction::Free { target } => Instruction::Free {
+            target: slot_of[*target],
+        },
+        Instruction::Gate {
+            gate,
+            target,
+            control,
+        } => Instruction::Gate {
+            gate: gate.clone(),
+            target: slot_of[*target],
+            control: map(control),
+        },
+        Instruction::Measure { qubits, output } => Instruction::Measure {
+            qubits: map(qubits),
+            output: *output,
+        },
+        Instruction::Sample {
+            qubits,
+            shots,
+            output,
+        } => Instruction::Sample {
+            qubits: map(qubits),
+            shots: *shots,
+            output: *output,
+        },
+        Instruction::Dump { qubits, output } => Instruction::Dump {
+            qubits: map(qubits),
+            output: *output,
+        },
+        Instruction::ExpValue {
+            hamiltonian,
+            output,
+        } => {
+            let mut hamiltonian = hamiltonian.clone();
+            for term in hamiltonian.products.iter_mut().flatten() {
+                term.qubit = slot_of[term.qubit];
+            }
+            Instruction::ExpValue {
+                hamiltonian,
+                output: *output,
+            }
+        }
+        Instruction::KrausChannel { operators, target } => Instruction::KrausChannel {
+            operators: operators.clone(),
+            target: slot_of[*target],
+        },
+        Instruction::ConditionalGate {
+            gate,
+            target,
+            control,
+            condition,
+        } => Instruction::ConditionalGate {
+            gate: gate.clone(),
+            target: slot_of[*target],
+            control: map(control),
+            condition: Condition {
+                expected: condition.expected.clone(),
+                qubits: map(&condition.qubits),
+            },
+        },
+        Instruction::Diagonal { phases, qubits } => Instruction::Diagonal {
+            phases: phases.clone(),
+            qubits: map(qubits),
+        },
+        Instruction::Multiplexor {
+            axis,
+            angles,
+            control,
+            target,
+        } => Instruction::Multiplexor {
+            axis: *axis,
+            angles: angles.clone(),
+            control: map(control),
+            target: slot_of[*target],
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LifetimeReport, QubitCompaction};
+    use crate::{error::KetError, passes::Pass, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn compact_freed_qubits() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(4));
+        let a = process.allocate_qubit()?;
+        let b = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::Hadamard, a)?;
+        process.apply_gate(QuantumGate::Hadamard, b)?;
+        process.apply_gate(QuantumGate::Hadamard, a)?;
+        process.free_qubit(a)?;
+        let c = process.allocate_qubit()?;
+        process.ctrl_push(&[b])?;
+        process.apply_gate(QuantumGate::PauliX, c)?;
+        process.ctrl_pop()?;
+        let d = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::PauliX, d)?;
+        process.measure(&[c, d])?;
+
+        let report = LifetimeReport::new(&process);
+        assert_eq!(report.declared, 4);
+        assert_eq!(report.width, 3);
+        assert_eq!(report.width_with_resets, 2);
+
+        let lifetime = &report.qubits[b];
+        assert_eq!((lifetime.first_use, lifetime.last_use), (Some(3), Some(7)));
+        assert_eq!(lifetime.idle_spans, [(4, 7)]);
+        assert!(lifetime.needs_reset());
+        assert_eq!(report.qubits[a].reusable_by, Some(c));
+        assert!(!report.qubits[a].needs_reset());
+        assert_eq!(report.qubits[b].reusable_by, Some(d));
+
+        QubitCompaction.run(&mut process)?;
+        assert_eq!(process.qubits.len(), 3);
+        assert_eq!(process.get_measurement(0).qubits, [0, 2]);
+        assert_eq!(LifetimeReport::new(&process).declared, 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Light-cone reduction of the circuit.
+//!
+//! A measurement, sample, or expected value only depends on the gates of its backward
+//! light cone: the gates on its qubits before it, the gates on the other qubits of those
+//! gates before them, and so on. The gates outside the light cone of every result
+//! cannot change them, so [`LightCone`] removes them, which shrinks deep circuits when
+//! only a few of their qubits are measured.
+
+use std::collections::HashSet;
+
+use crate::{error::Result, ir::Instruction, Process};
+
+use super::{peephole::qubits, rewrite_ranges, Pass};
+
+/// Removes the instructions outside the backward light cone of the results and of the
+/// observed qubits, see [`light_cone`].
+///
+/// The results, and the allocations and frees of the qubits, are kept, so the result
+/// indices do not change. The observed qubits are the ones of the observable that will
+/// be measured after the circuit, as the qubits of a later expected value.
+#[derive(Debug, Clone, Default)]
+pub struct LightCone {
+    /// Qubits observed after the circuit, in addition to the qubits of its results.
+    pub qubits: Vec<usize>,
+}
+
+impl Pass for LightCone {
+    fn name(&self) -> String {
+        "light_cone".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        let keep = light_cone(&process.instructions, &self.qubits);
+        rewrite_ranges(process, |range, instructions, _| {
+            Ok(instructions
+                .iter()
+                .zip(&keep[range])
+                .filter(|(_, keep)| **keep)
+                .map(|(instruction, _)| instruction.clone())
+                .collect())
+        })
+    }
+}
+
+/// Returns whether each instruction is in the backward light cone of the results and of
+/// the `observed` qubits after the instructions.
+///
+/// A dump holds the amplitudes of the whole state, not only of its qubits, so every
+/// instruction before a dump is in the light cone.
+pub fn light_cone(instructions: &[Instruction], observed: &[usize]) -> Vec<bool> {
+    let mut cone: HashSet<usize> = observed.iter().copied().collect();
+    let mut keep = vec![true; instructions.len()];
+    for (index, instruction) in instructions.iter().enumerate().rev() {
+        match instruction {
+            Instruction::Dump { .. } => break,
+            Instruction::Alloc { .. } | Instruction::Free { .. } => {}
+            Instruction::Measure { .. }
+            | Instruction::Sample { .. }
+            | Instruction::ExpValue { .. } => cone.extend(qubits(instruction)),
+            _ => {
+                let qubits = qubits(instruction);
+                if qubits.iter().any(|qubit| cone.contains(qubit)) {
+                    cone.extend(qubits);
+                } else {
+                    keep[index] = false;
+                }
+            }
+        }
+    }
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LightCone;
+    use crate::error::KetError;
+    use crate::passes::{Pass, ResourceReport};
+    use crate::{Configuration, Instruction, Process, QuantumGate};
+
+    fn circuit(process: &mut Process) -> Result<Vec<usize>, KetError> {
+        let qubits = (0..4)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>, _>>()?;
+        let (a, b, c, d) = (qubits[0], qubits[1], qubits[2], qubits[3]);
+        process.apply_gate(QuantumGate::Hadamard, d)?;
+        process.ctrl_push(&[d])?;
+        process.apply_gate(QuantumGate::PauliX, c)?;
+        process.ctrl_pop()?;
+        process.apply_gate(QuantumGate::Hadamard, a)?;
+        process.ctrl_push(&[a])?;
+        process.apply_gate(QuantumGate::PauliX, b)?;
+        process.ctrl_pop()?;
+        process.apply_gate(QuantumGate::PauliX, c)?;
+        Ok(qubits)
+    }
+
+    #[test]
+    fn measured_subset() -> Result<(), KetError> {
+        // Only the Bell pair of the measured qubit is kept.
+        let mut process = Process::new(Configuration::new(4));
+        let qubits = circuit(&mut process)?;
+        process.measure(&[qubits[1]])?;
+        LightCone::default().run(&mut process)?;
+        let report = ResourceReport::from_instructions(&process.instructions);
+        assert_eq!((report.gates, report.gate_counts["cx"]), (2, 1));
+        assert!(matches!(
+            process.instructions.last(),
+            Some(Instruction::Measure { .. })
+        ));
+
+        // An observed qubit keeps its own light cone.
+        let mut process = Process::new(Configuration::new(4));
+        let qubits = circuit(&mut process)?;
+        process.measure(&[qubits[1]])?;
+        LightCone {
+            qubits: vec![qubits[2]],
+        }
+        .run(&mut process)?;
+        assert_eq!(ResourceReport::new(&process).gates, 5);
+
+        // A dump depends on the whole state.
+        let mut process = Process::new(Configuration::new(4));
+        let qubits = circuit(&mut process)?;
+        process.dump(&[qubits[1]])?;
+        LightCone::default().run(&mut process)?;
+        assert_eq!(ResourceReport::new(&process).gates, 5);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Circuit transformation passes.
+//!
+//! A [`Pass`] rewrites the instructions of a [`Process`] that is still being built. The
+//! [`PassManager`] runs a sequence of passes and records a [`ResourceReport`] after each
+//! one, so the effect of every pass on the circuit can be inspected.
+//!
+//! Passes only rewrite the instructions after the prefix frozen with
+//! [`Process::freeze`], and never rewrite nor reorder across the opaque blocks of
+//! [`Process::opaque_begin`] or the error budget sections of [`Process::budget_begin`].
+//! Passes that relabel qubits, as [`Routing`] and [`QubitCompaction`], cannot run on a
+//! process with a frozen prefix, opaque blocks, or sections.
+//!
+//! [`PassManager::compile`] also reports the error that the approximating passes spent
+//! against the budgets of the sections, see [`budget`].
+
+pub mod ancilla;
+pub mod budget;
+pub mod canonicalize;
+pub mod clifford_t;
+pub mod gridsynth;
+pub mod lifetime;
+pub mod light_cone;
+pub mod peephole;
+pub mod report;
+pub mod resynthesis;
+pub mod routing;
+pub mod schedule;
+
+pub use ancilla::{AncillaCheck, AncillaReport};
+pub use budget::{CompilationReport, ErrorBudget};
+pub use canonicalize::Canonicalize;
+pub use clifford_t::CliffordT;
+pub use lifetime::{LifetimeReport, QubitCompaction};
+pub use light_cone::LightCone;
+pub use peephole::Peephole;
+pub use report::ResourceReport;
+pub use resynthesis::TwoQubitResynthesis;
+pub use routing::{CouplingMap, Routing};
+pub use schedule::Scheduling;
+
+use std::ops::Range;
+
+#[cfg(feature = "optimizer")]
+use crate::zx::{
+    optimize::{optimize_incremental, optimize_with},
+    ZXStrategy,
+};
+use crate::{
+    error::{KetError, Result},
+    Instruction, MappingReport, Process,
+};
+
+/// A circuit transformation.
+pub trait Pass {
+    /// Name of the pass, used in reports.
+    fn name(&self) -> String;
+
+    /// Rewrites the process instructions after its frozen prefix.
+    fn run(&self, process: &mut Process) -> Result<()>;
+}
+
+/// Replaces the instructions after the frozen prefix of the process with the result of
+/// `rewrite`.
+///
+/// The opaque blocks, see [`Process::opaque_begin`], are kept in place, and `rewrite`
+/// is called on each segment of instructions between them and the bounds of the error
+/// budget sections, see [`Process::budget_begin`], so no instruction is moved across a
+/// block nor in or out of a section.
+pub(crate) fn rewrite_editable(
+    process: &mut Process,
+    mut rewrite: impl FnMut(&[Instruction]) -> Result<Vec<Instruction>>,
+) -> Result<()> {
+    rewrite_sections(process, |segment, _| rewrite(segment))
+}
+
+/// Same as [`rewrite_editable`], also passing to `rewrite` the error budget section of
+/// the segment, if any.
+pub(crate) fn rewrite_sections(
+    process: &mut Process,
+    mut rewrite: impl FnMut(&[Instruction], Option<&mut ErrorBudget>) -> Result<Vec<Instruction>>,
+) -> Result<()> {
+    rewrite_ranges(process, |_, segment, section| rewrite(segment, section))
+}
+
+/// Same as [`rewrite_sections`], also passing to `rewrite` the range of the segment in
+/// the process instructions.
+pub(crate) fn rewrite_ranges(
+    process: &mut Process,
+    mut rewrite: impl FnMut(
+        Range<usize>,
+        &[Instruction],
+        Option<&mut ErrorBudget>,
+    ) -> Result<Vec<Instruction>>,
+) -> Result<()> {
+    let len = process.instructions.len();
+    let frozen = process.frozen_len.min(len);
+    let mut blocks = process.opaque.clone();
+    if let Some((start, _)) = process.opaque_scope {
+        blocks.push(start..len);
+    }
+
+    // The open section ends at the last instruction while rewriting.
+    let mut budgets = process.budgets.clone();
+    let open = process.budget_scope.clone();
+    if let Some(mut budget) = open.clone() {
+        budget.range.end = len;
+        budgets.push(budget);
+    }
+    let mut cuts: Vec<usize> = budgets
+        .iter()
+        .flat_map(|budget| [budget.range.start, budget.range.end])
+        .filter(|cut| (frozen..len).contains(cut))
+        .collect();
+    cuts.sort_unstable();
+    cuts.dedup();
+
+    // New position of each bound of a segment after the frozen prefix.
+    let mut positions = std::collections::HashMap::new();
+    let mut instructions = process.instructions[..frozen].to_vec();
+    let mut rewrite_segment = |range: Range<usize>, instructions: &mut Vec<Instruction>| {
+        let mut start = range.start;
+        for end in cuts
+            .iter()
+            .copied()
+            .filter(|cut| range.contains(cut) && *cut > range.start)
+            .chain([range.end])
+        {
+            positions.insert(start, instructions.len());
+            if start < end {
+                let section = budgets
+                    .iter_mut()
+                    .find(|budget| budget.range.start <= start && end <= budget.range.end);
+                instructions.extend(rewrite(
+                    start..end,
+                    &process.instructions[start..end],
+                    section,
+                )?);
+            }
+            start = end;
+        }
+        positions.insert(range.end, instructions.len());
+        Ok::<_, KetError>(())
+    };
+
+    let mut opaque = Vec::with_capacity(blocks.len());
+    let mut start = frozen;
+    for block in blocks {
+        if block.end <= frozen {
+            opaque.push(block);
+            continue;
+        }
+        let block_start = block.start.max(frozen);
+        rewrite_segment(start..block_start, &mut instructions)?;
+        let new_start = if block.start < frozen {
+            block.start
+        } else {
+            instructions.len()
+        };
+        instructions.extend_from_slice(&process.instructions[block_start..block.end]);
+        opaque.push(new_start..instructions.len());
+        start = block.end;
+    }
+    rewrite_segment(start..len, &mut instructions)?;
+
+    let position = |index: usize| {
+        if index <= frozen {
+            index
+        } else {
+            positions[&index]
+        }
+    };
+    for budget in &mut budgets {
+        budget.range = position(budget.range.start)..position(budget.range.end);
+    }
+    if open.is_some() {
+        process.budget_scope = budgets.pop();
+    }
+    if let Some((start, _)) = process.opaque_scope.as_mut() {
+        *start = opaque.pop().map_or(instructions.len(), |block| block.start);
+    }
+    process.instructions = instructions;
+    process.opaque = opaque;
+    process.budgets = budgets;
+    Ok(())
+}
+
+/// Returns an error if the process has a frozen prefix or opaque blocks, for the passes
+/// that relabel qubits.
+pub(crate) fn assert_all_editable(process: &Process) -> Result<()> {
+    if process.frozen_len > 0 {
+        Err(KetError::FrozenInstructions)
+    } else if !process.opaque.is_empty() || process.opaque_scope.is_some() {
+        Err(KetError::InvalidOpaqueBlock)
+    } else if !process.budgets.is_empty() || process.budget_scope.is_some() {
+        Err(KetError::InvalidErrorBudget)
+    } else {
+        Ok(())
+    }
+}
+
+/// Runs `relabel` on the process, recording the new qubits of its results in the
+/// [`MappingReport`] of the process metadata.
+pub(crate) fn relabel_results(
+    process: &mut Process,
+    relabel: impl FnOnce(&mut Process) -> Result<()>,
+) -> Result<()> {
+    let result_qubits = |process: &Process| {
+        [
+            process
+                .measurements
+                .iter()
+                .map(|measurement| measurement.qubits.clone())
+                .collect(),
+            process
+                .samples
+                .iter()
+                .map(|sample| sample.qubits.clone())
+                .collect(),
+            process
+                .dumps
+                .iter()
+                .map(|dump| dump.qubits.clone())
+                .collect(),
+        ]
+    };
+
+    let mut report = process
+        .metadata
+        .mapping
+        .take()
+        .unwrap_or_else(|| MappingReport::new(result_qubits(process)));
+    let result = relabel(process);
+    report.update(result_qubits(process));
+    process.metadata.mapping = Some(report);
+    result
+}
+
+/// ZX-calculus simplification, see [`Process::optimize`].
+#[cfg(feature = "optimizer")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZXSimplify(pub ZXStrategy);
+
+#[cfg(feature = "optimizer")]
+impl Pass for ZXSimplify {
+    fn name(&self) -> String {
+        match self.0 {
+            ZXStrategy::Clifford => "clifford_simp".to_string(),
+            ZXStrategy::Full => "full_simp".to_string(),
+        }
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        if process.frozen_len > 0 {
+            process.optimized_len = process.frozen_len;
+            optimize_incremental(process, self.0)
+        } else {
+            optimize_with(process, self.0)
+        }
+    }
+}
+
+/// Runs a sequence of passes.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    /// Creates an empty pass manager.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a pass to the pipeline.
+    pub fn add_pass<P: Pass + 'static>(mut self, pass: P) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Returns the names of the passes in the pipeline.
+    pub fn names(&self) -> Vec<String> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Runs the pipeline on the process.
+    ///
+    /// Returns the resource report of the input circuit, followed by the report after
+    /// each pass, labeled by the pass name.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first pass that fails. The process may be partially
+    /// transformed in this case.
+    pub fn run(&self, process: &mut Process) -> Result<Vec<(String, ResourceReport)>> {
+        let mut reports = vec![("input".to_string(), ResourceReport::new(process))];
+        for pass in &self.passes {
+            pass.run(process)?;
+            process.rewritten();
+            reports.push((pass.name(), ResourceReport::new(process)));
+        }
+        Ok(reports)
+    }
+
+    /// Runs the pipeline on the process, reporting the resources and the error of each
+    /// pass, and the error spent in each section, see [`budget`].
+    ///
+    /// The report is also kept in the process metadata.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first pass that fails. The process may be partially
+    /// transformed in this case.
+    pub fn compile(&self, process: &mut Process) -> Result<CompilationReport> {
+        let spent = |process: &Process| {
+            process.unbudgeted_error
+                + process
+                    .budgets
+                    .iter()
+                    .map(|budget| budget.spent)
+                    .sum::<f64>()
+        };
+
+        let input = ResourceReport::new(process);
+        let mut steps = Vec::with_capacity(self.passes.len());
+        for pass in &self.passes {
+            let before = spent(process);
+            pass.run(process)?;
+            process.rewritten();
+            steps.push(budget::CompilationStep {
+                pass: pass.name(),
+                resources: ResourceReport::new(process),
+                error: spent(process) - before,
+            });
+        }
+
+        let report = CompilationReport {
+            input,
+            steps,
+            budgets: process.budgets.iter().map(Into::into).collect(),
+            unbudgeted_error: process.unbudgeted_error,
+        };
+        process.metadata.compilation = Some(report.clone());
+        Ok(report)
+    }
+}
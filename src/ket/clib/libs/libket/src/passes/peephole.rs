@@ -0,0 +1,330 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Peephole optimization of adjacent gates.
+
+use num::Integer;
+
+use crate::{
+    error::Result,
+    ir::{Angle, Instruction, QuantumGate},
+    Process,
+};
+
+use super::{rewrite_editable, Pass};
+
+/// Cancels adjacent self-inverse gates and merges adjacent rotations.
+///
+/// Two gates are adjacent if no instruction between them acts on any of their qubits.
+/// Only gates with the same target and the same set of control qubits are combined.
+/// Measurements, samples, dumps, and expected values act as barriers on their qubits.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Peephole;
+
+impl Pass for Peephole {
+    fn name(&self) -> String {
+        "peephole".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        rewrite_editable(process, |instructions| Ok(peephole(instructions.to_vec())))
+    }
+}
+
+/// Qubits used by an instruction.
+pub(crate) fn qubits(instruction: &Instruction) -> Vec<usize> {
+    match instruction {
+        Instruction::Alloc { target }
+        | Instruction::Free { target }
+        | Instruction::KrausChannel { target, .. } => vec![*target],
+        Instruction::Gate {
+            target, control, ..
+        }
+        | Instruction::Multiplexor {
+            target, control, ..
+        } => {
+            let mut qubits = control.clone();
+            qubits.push(*target);
+            qubits
+        }
+        Instruction::ConditionalGate {
+            target,
+            control,
+            condition,
+            ..
+        } => {
+            let mut qubits = control.clone();
+            qubits.push(*target);
+            for qubit in &condition.qubits {
+                if !qubits.contains(qubit) {
+                    qubits.push(*qubit);
+                }
+            }
+            qubits
+        }
+        Instruction::Measure { qubits, .. }
+        | Instruction::Sample { qubits, .. }
+        | Instruction::Dump { qubits, .. }
+        | Instruction::Diagonal { qubits, .. } => qubits.clone(),
+        Instruction::ExpValue { hamiltonian, .. } => hamiltonian
+            .products
+            .iter()
+            .flatten()
+            .map(|term| term.qubit)
+            .collect(),
+    }
+}
+
+fn add_angles(a: &Angle, b: &Angle) -> Angle {
+    match (a, b) {
+        (
+            Angle::PiFraction {
+                top: top_a,
+                bottom: bottom_a,
+            },
+            Angle::PiFraction {
+                top: top_b,
+                bottom: bottom_b,
+            },
+        ) => {
+            let bottom = (*bottom_a as i64).lcm(&(*bottom_b as i64));
+            let top = *top_a as i64 * (bottom / *bottom_a as i64)
+                + *top_b as i64 * (bottom / *bottom_b as i64);
+            let gcd = top.gcd(&bottom).max(1);
+            Angle::PiFraction {
+                top: (top / gcd) as i32,
+                bottom: (bottom / gcd) as u32,
+            }
+        }
+        _ => Angle::Scalar(a.radians() + b.radians()),
+    }
+}
+
+/// Returns `true` if the angle is a multiple of `period` times π.
+fn is_identity(angle: &Angle, period: i64) -> bool {
+    match angle {
+        Angle::PiFraction { top, bottom } => *top as i64 % (period * *bottom as i64) == 0,
+        Angle::Scalar(angle) => {
+            let period = period as f64 * std::f64::consts::PI;
+            let remainder = angle.rem_euclid(period);
+            remainder < 1e-12 || period - remainder < 1e-12
+        }
+    }
+}
+
+/// Combines two gates with the same target and controls.
+///
+/// Returns `None` if the gates cannot be combined, `Some(None)` if they cancel out, and
+/// `Some(Some(gate))` if they merge into a single gate.
+fn combine(a: &QuantumGate, b: &QuantumGate) -> Option<Option<QuantumGate>> {
+    let merged = match (a, b) {
+        (QuantumGate::PauliX, QuantumGate::PauliX)
+        | (QuantumGate::PauliY, QuantumGate::PauliY)
+        | (QuantumGate::PauliZ, QuantumGate::PauliZ)
+        | (QuantumGate::Hadamard, QuantumGate::Hadamard) => return Some(None),
+        (QuantumGate::RotationX(a), QuantumGate::RotationX(b)) => {
+            QuantumGate::RotationX(add_angles(a, b))
+        }
+        (QuantumGate::RotationY(a), QuantumGate::RotationY(b)) => {
+            QuantumGate::RotationY(add_angles(a, b))
+        }
+        (QuantumGate::RotationZ(a), QuantumGate::RotationZ(b)) => {
+            QuantumGate::RotationZ(add_angles(a, b))
+        }
+        (QuantumGate::Phase(a), QuantumGate::Phase(b)) => QuantumGate::Phase(add_angles(a, b)),
+        _ => return None,
+    };
+
+    // Rotations have period 4π, as a 2π rotation is a controlled global phase of -1.
+    let identity = match &merged {
+        QuantumGate::RotationX(angle)
+        | QuantumGate::RotationY(angle)
+        | QuantumGate::RotationZ(angle) => is_identity(angle, 4),
+        QuantumGate::Phase(angle) => is_identity(angle, 2),
+        _ => false,
+    };
+
+    Some(if identity { None } else { Some(merged) })
+}
+
+fn same_controls(a: &[usize], b: &[usize]) -> bool {
+    a.len() == b.len() && a.iter().all(|qubit| b.contains(qubit))
+}
+
+/// Applies the peephole optimization to a list of instructions.
+pub fn peephole(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut output: Vec<Instruction> = Vec::with_capacity(instructions.len());
+
+    for instruction in instructions {
+        if let Instruction::Gate {
+            gate,
+            target,
+            control,
+        } = &instruction
+        {
+            let touched = qubits(&instruction);
+            let previous = output
+                .iter()
+                .rposition(|other| qubits(other).iter().any(|qubit| touched.contains(qubit)));
+
+            if let Some(index) = previous {
+                if let Instruction::Gate {
+                    gate: other_gate,
+                    target: other_target,
+                    control: other_control,
+                } = &output[index]
+                {
+                    if other_target == target && same_controls(other_control, control) {
+                        if let Some(merged) = combine(other_gate, gate) {
+                            match merged {
+                                Some(merged) => {
+                                    output[index] = Instruction::Gate {
+                                        gate: merged,
+                                        target: *target,
+                                        control: control.clone(),
+                                    }
+                                }
+                                None => {
+                                    output.remove(index);
+                                }
+                            }
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+
+        output.push(instruction);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{peephole, Peephole};
+    use crate::error::KetError;
+    use crate::passes::{CouplingMap, PassManager, QubitCompaction, ResourceReport, Routing};
+    use crate::{Angle, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn cancel_and_merge() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(2));
+        let qubit_a = process.allocate_qubit()?;
+        let qubit_b = process.allocate_qubit()?;
+
+        // X H H X cancels out completely.
+        process.apply_gate(QuantumGate::PauliX, qubit_a)?;
+        process.apply_gate(QuantumGate::Hadamard, qubit_a)?;
+        process.apply_gate(QuantumGate::Hadamard, qubit_b)?;
+        process.apply_gate(QuantumGate::Hadamard, qubit_a)?;
+        process.apply_gate(QuantumGate::PauliX, qubit_a)?;
+
+        // T T becomes S.
+        for _ in 0..2 {
+            process.apply_gate(
+                QuantumGate::Phase(Angle::PiFraction { top: 1, bottom: 4 }),
+                qubit_a,
+            )?;
+        }
+
+        // A CNOT blocks the cancellation of the Hadamard gates on the control qubit.
+        process.ctrl_push(&[qubit_b])?;
+        process.apply_gate(QuantumGate::PauliX, qubit_a)?;
+        process.ctrl_pop()?;
+        process.apply_gate(QuantumGate::Hadamard, qubit_b)?;
+
+        let instructions = peephole(process.instructions.clone());
+        let report = ResourceReport::from_instructions(&instructions);
+
+        assert_eq!(report.gates, 4);
+        assert_eq!(report.gate_counts["h"], 2);
+        assert_eq!(report.gate_counts["p"], 1);
+        assert_eq!(report.gate_counts["cx"], 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn frozen_prefix() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(1));
+        let qubit = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::Hadamard, qubit)?;
+        process.freeze()?;
+        process.apply_gate(QuantumGate::Hadamard, qubit)?;
+        process.apply_gate(QuantumGate::PauliX, qubit)?;
+        process.apply_gate(QuantumGate::PauliX, qubit)?;
+        assert_eq!(process.frozen_len(), 2);
+
+        // The Hadamard gates only cancel across the frozen boundary.
+        PassManager::new().add_pass(Peephole).run(&mut process)?;
+        let report = ResourceReport::new(&process);
+        assert_eq!(report.gates, 2);
+        assert_eq!(report.gate_counts["h"], 2);
+
+        let routing = Routing {
+            coupling_map: CouplingMap::line(1),
+        };
+        assert!(matches!(
+            PassManager::new().add_pass(routing).run(&mut process),
+            Err(KetError::FrozenInstructions)
+        ));
+        assert!(PassManager::new()
+            .add_pass(QubitCompaction)
+            .run(&mut process)
+            .is_err());
+
+        process.adj_begin()?;
+        assert!(process.freeze().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn opaque_blocks() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration {
+            decompose: true,
+            ..Configuration::new(2)
+        });
+        let qubit = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::PauliX, qubit)?;
+        process.opaque_begin()?;
+        process.apply_gate(QuantumGate::Hadamard, qubit)?;
+        process.apply_gate(QuantumGate::PauliZ, qubit)?;
+        process.apply_gate(QuantumGate::Hadamard, qubit)?;
+        process.opaque_end()?;
+        process.apply_gate(QuantumGate::PauliX, qubit)?;
+        process.apply_gate(QuantumGate::Hadamard, qubit)?;
+        process.apply_gate(QuantumGate::Hadamard, qubit)?;
+        assert_eq!(process.opaque_blocks(), std::slice::from_ref(&(2..5)));
+
+        // The Z gate of the block is not decomposed, the X gates around the block do
+        // not cancel, and only the Hadamard gates after it do.
+        PassManager::new().add_pass(Peephole).run(&mut process)?;
+        let report = ResourceReport::new(&process);
+        assert_eq!(report.gate_counts["x"], 2);
+        assert_eq!(report.gate_counts["z"], 1);
+        assert_eq!(report.gate_counts["h"], 2);
+        assert_eq!(process.opaque_blocks(), std::slice::from_ref(&(2..5)));
+        process.optimize()?;
+        assert_eq!(process.opaque_blocks(), std::slice::from_ref(&(2..5)));
+        assert_eq!(ResourceReport::new(&process).gates, 5);
+
+        let routing = Routing {
+            coupling_map: CouplingMap::line(2),
+        };
+        assert!(matches!(
+            PassManager::new().add_pass(routing).run(&mut process),
+            Err(KetError::InvalidOpaqueBlock)
+        ));
+        assert!(matches!(
+            process.opaque_end(),
+            Err(KetError::InvalidOpaqueBlock)
+        ));
+
+        Ok(())
+    }
+}
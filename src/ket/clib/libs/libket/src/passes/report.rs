@@ -0,0 +1,201 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resource counts of a circuit.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ir::{Instruction, QuantumGate},
+    Process,
+};
+
+use super::clifford_t::is_t_gate;
+
+/// Resource counts of the instructions of a process.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceReport {
+    /// Number of distinct qubits used.
+    pub qubits: usize,
+
+    /// Total number of gates.
+    pub gates: usize,
+
+    /// Number of gates without control qubits.
+    pub single_qubit: usize,
+
+    /// Number of gates with one control qubit.
+    pub two_qubit: usize,
+
+    /// Number of gates with two or more control qubits.
+    pub multi_controlled: usize,
+
+    /// Circuit depth, counting gates only.
+    pub depth: usize,
+
+    /// Number of T and T† gates, see [`is_t_gate`](super::clifford_t::is_t_gate).
+    pub t_count: usize,
+
+    /// Number of gates of each kind, ignoring angles.
+    pub gate_counts: BTreeMap<String, usize>,
+}
+
+/// Name of a gate in OpenQASM, ignoring its angle.
+pub(crate) fn gate_name(gate: &QuantumGate) -> &'static str {
+    match gate {
+        QuantumGate::PauliX => "x",
+        QuantumGate::PauliY => "y",
+        QuantumGate::PauliZ => "z",
+        QuantumGate::RotationX(_) => "rx",
+        QuantumGate::RotationY(_) => "ry",
+        QuantumGate::RotationZ(_) => "rz",
+        QuantumGate::Phase(_) => "p",
+        QuantumGate::Hadamard => "h",
+    }
+}
+
+/// Name of a gate with its number of control qubits, as in `ccx`.
+pub(crate) fn gate_key(gate: &QuantumGate, control: &[usize]) -> String {
+    format!("{}{}", "c".repeat(control.len()), gate_name(gate))
+}
+
+impl ResourceReport {
+    /// Counts the resources of the process instructions.
+    pub fn new(process: &Process) -> Self {
+        Self::from_instructions(&process.instructions)
+    }
+
+    /// Counts the resources of a list of instructions.
+    pub fn from_instructions(instructions: &[Instruction]) -> Self {
+        let mut report = ResourceReport::default();
+        let mut levels: HashMap<usize, usize> = HashMap::new();
+
+        for instruction in instructions {
+            match instruction {
+                Instruction::Alloc { target } => {
+                    levels.entry(*target).or_default();
+                }
+                Instruction::Gate {
+                    gate,
+                    target,
+                    control,
+                } => {
+                    report.gates += 1;
+                    if is_t_gate(gate, control) {
+                        report.t_count += 1;
+                    }
+                    match control.len() {
+                        0 => report.single_qubit += 1,
+                        1 => report.two_qubit += 1,
+                        _ => report.multi_controlled += 1,
+                    }
+                    *report
+                        .gate_counts
+                        .entry(gate_key(gate, control))
+                        .or_default() += 1;
+
+                    let level = control
+                        .iter()
+                        .chain(std::iter::once(target))
+                        .map(|qubit| *levels.entry(*qubit).or_default())
+                        .max()
+                        .unwrap_or_default()
+                        + 1;
+                    for qubit in control.iter().chain(std::iter::once(target)) {
+                        levels.insert(*qubit, level);
+                    }
+                    report.depth = report.depth.max(level);
+                }
+                _ => {}
+            }
+        }
+
+        report.qubits = levels.len();
+        report
+    }
+
+    /// Formats a table comparing this report with `other`.
+    pub fn diff(&self, other: &ResourceReport) -> String {
+        let mut rows = vec![
+            ("qubits".to_string(), self.qubits, other.qubits),
+            ("gates".to_string(), self.gates, other.gates),
+            (
+                "single-qubit".to_string(),
+                self.single_qubit,
+                other.single_qubit,
+            ),
+            ("two-qubit".to_string(), self.two_qubit, other.two_qubit),
+            (
+                "multi-controlled".to_string(),
+                self.multi_controlled,
+                other.multi_controlled,
+            ),
+            ("depth".to_string(), self.depth, other.depth),
+            ("t-count".to_string(), self.t_count, other.t_count),
+        ];
+
+        let mut names: Vec<&String> = self
+            .gate_counts
+            .keys()
+            .chain(other.gate_counts.keys())
+            .collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            rows.push((
+                format!("  {}", name),
+                self.gate_counts.get(name).copied().unwrap_or_default(),
+                other.gate_counts.get(name).copied().unwrap_or_default(),
+            ));
+        }
+
+        let mut table = format!("{:<18}{:>10}{:>10}{:>10}\n", "", "before", "after", "diff");
+        for (name, before, after) in rows {
+            table += &format!(
+                "{:<18}{:>10}{:>10}{:>+10}\n",
+                name,
+                before,
+                after,
+                after as i64 - before as i64
+            );
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResourceReport;
+    use crate::error::KetError;
+    use crate::{Configuration, Process, QuantumGate};
+
+    #[test]
+    fn count_resources() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        let qubits = [
+            process.allocate_qubit()?,
+            process.allocate_qubit()?,
+            process.allocate_qubit()?,
+        ];
+
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+        process.apply_gate(QuantumGate::Hadamard, qubits[2])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.ctrl_pop()?;
+
+        let report = ResourceReport::new(&process);
+        assert_eq!(report.qubits, 3);
+        assert_eq!(report.gates, 3);
+        assert_eq!(report.two_qubit, 1);
+        assert_eq!(report.depth, 2);
+        assert_eq!(report.gate_counts["h"], 2);
+        assert_eq!(report.gate_counts["cx"], 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Re-synthesis of two-qubit blocks with the KAK decomposition.
+
+use crate::{error::Result, kak, Instruction, Process};
+
+use super::{peephole::qubits, rewrite_editable, Pass};
+
+/// Replaces each block of gates on the same two qubits with its minimal CNOT
+/// realization, see [`kak`].
+///
+/// A block starts at a gate with one control qubit and takes the following gates on its
+/// two qubits with at most one control, moving the instructions on other qubits before
+/// it. A block is only replaced if its realization has fewer two-qubit gates, and the
+/// global phase of the replaced blocks is left out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TwoQubitResynthesis;
+
+impl Pass for TwoQubitResynthesis {
+    fn name(&self) -> String {
+        "resynthesis".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        rewrite_editable(process, |instructions| Ok(resynthesize(instructions)))
+    }
+}
+
+struct Block {
+    qubits: [usize; 2],
+    gates: Vec<Instruction>,
+}
+
+impl Block {
+    /// Appends the block, or its realization if it takes fewer two-qubit gates.
+    fn close(self, output: &mut Vec<Instruction>) {
+        let two_qubit =
+            |gates: &[Instruction]| gates.iter().filter(|gate| qubits(gate).len() == 2).count();
+        let matrix = kak::matrix(&self.gates, self.qubits).unwrap();
+        if kak::num_cnots(&matrix) < two_qubit(&self.gates) {
+            output.extend(kak::decompose(&matrix, self.qubits).0);
+        } else {
+            output.extend(self.gates);
+        }
+    }
+}
+
+/// Returns the instructions with the two-qubit blocks re-synthesized.
+pub fn resynthesize(instructions: &[Instruction]) -> Vec<Instruction> {
+    let mut output = Vec::with_capacity(instructions.len());
+    let mut blocks: Vec<Block> = Vec::new();
+
+    for instruction in instructions {
+        let used = qubits(instruction);
+        let is_gate = matches!(
+            instruction,
+            Instruction::Gate { control, .. } if control.len() <= 1
+        );
+        let mut pair = used.clone();
+        pair.sort_unstable();
+        pair.dedup();
+
+        if is_gate {
+            if let Some(block) = blocks
+                .iter_mut()
+                .find(|block| used.iter().all(|qubit| block.qubits.contains(qubit)))
+            {
+                block.gates.push(instruction.clone());
+                continue;
+            }
+        }
+
+        // The blocks on the qubits of the instruction end before it.
+        let (closed, open): (Vec<_>, Vec<_>) = blocks
+            .into_iter()
+            .partition(|block| block.qubits.iter().any(|qubit| used.contains(qubit)));
+        blocks = open;
+        closed
+            .into_iter()
+            .for_each(|block| block.close(&mut output));
+
+        if is_gate && pair.len() == 2 {
+            blocks.push(Block {
+                qubits: [pair[0], pair[1]],
+                gates: vec![instruction.clone()],
+            });
+        } else {
+            output.push(instruction.clone());
+        }
+    }
+    blocks
+        .into_iter()
+        .for_each(|block| block.close(&mut output));
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwoQubitResynthesis;
+    use crate::error::KetError;
+    use crate::passes::{PassManager, ResourceReport};
+    use crate::{Configuration, Process, QuantumGate};
+
+    #[test]
+    fn resynthesize_swap() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration {
+            optimize: false,
+            ..Configuration::new(3)
+        });
+        let [a, b, c] = [
+            process.allocate_qubit()?,
+            process.allocate_qubit()?,
+            process.allocate_qubit()?,
+        ];
+
+        // Two SWAPs as 6 CNOTs cancel out, with a gate on another qubit between them.
+        for index in 0..6 {
+            let (control, target) = if index % 2 == 0 { (a, b) } else { (b, a) };
+            process.ctrl_push(&[control])?;
+            process.apply_gate(QuantumGate::PauliX, target)?;
+            process.ctrl_pop()?;
+            if index == 3 {
+                process.apply_gate(QuantumGate::Hadamard, c)?;
+            }
+        }
+        // A CNOT is already minimal.
+        process.ctrl_push(&[c])?;
+        process.apply_gate(QuantumGate::PauliX, a)?;
+        process.ctrl_pop()?;
+
+        PassManager::new()
+            .add_pass(TwoQubitResynthesis)
+            .run(&mut process)?;
+        let report = ResourceReport::from_instructions(process.instructions());
+
+        assert_eq!(report.gate_counts["cx"], 1);
+        assert_eq!(report.gate_counts["h"], 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Qubit routing for devices with restricted connectivity.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{KetError, Result},
+    ir::{Condition, Instruction, QuantumGate},
+    multiplexor,
+    objects::QubitStatus,
+    Process,
+};
+
+use super::{assert_all_editable, relabel_results, Pass};
+
+/// Connectivity of a quantum device.
+///
+/// The JSON representation is `{"num_qubits": 3, "edges": [[0, 1], [1, 2]]}`. Edges are
+/// undirected. The optional `crosstalk` field lists pairs of qubit sets, as in
+/// `"crosstalk": [[[0, 1], [2, 3]]]`, whose gates cannot run at the same time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CouplingMap {
+    /// Number of physical qubits.
+    pub num_qubits: usize,
+
+    /// Pairs of physical qubits that support two-qubit gates.
+    pub edges: Vec<(usize, usize)>,
+
+    /// Pairs of qubit sets, a qubit or an edge, that cannot operate simultaneously, see
+    /// [`Scheduling`](super::Scheduling).
+    #[serde(default)]
+    pub crosstalk: Vec<(Vec<usize>, Vec<usize>)>,
+}
+
+impl CouplingMap {
+    /// Parses a coupling map from JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidCouplingMap`] if the JSON is malformed, if an edge
+    /// refers to a qubit out of range, or if a crosstalk constraint has an empty set or
+    /// a qubit out of range.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let map: CouplingMap =
+            serde_json::from_str(json).map_err(|_| KetError::InvalidCouplingMap)?;
+        if map
+            .edges
+            .iter()
+            .any(|(a, b)| *a >= map.num_qubits || *b >= map.num_qubits || a == b)
+        {
+            return Err(KetError::InvalidCouplingMap);
+        }
+        if map
+            .crosstalk
+            .iter()
+            .flat_map(|(a, b)| [a, b])
+            .any(|set| set.is_empty() || set.iter().any(|qubit| *qubit >= map.num_qubits))
+        {
+            return Err(KetError::InvalidCouplingMap);
+        }
+        Ok(map)
+    }
+
+    /// Creates a linear coupling map `0 - 1 - ... - (num_qubits - 1)`.
+    pub fn line(num_qubits: usize) -> Self {
+        Self {
+            num_qubits,
+            edges: (1..num_qubits).map(|qubit| (qubit - 1, qubit)).collect(),
+            crosstalk: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if a crosstalk constraint forbids running gates on the qubits `a`
+    /// and `b` at the same time.
+    ///
+    /// A gate operates on a set of a constraint if it acts on all the qubits of the set.
+    pub fn conflicts(&self, a: &[usize], b: &[usize]) -> bool {
+        let on = |gate: &[usize], set: &[usize]| set.iter().all(|qubit| gate.contains(qubit));
+        self.crosstalk
+            .iter()
+            .any(|(x, y)| (on(a, x) && on(b, y)) || (on(a, y) && on(b, x)))
+    }
+
+    fn neighbors(&self) -> Vec<Vec<usize>> {
+        let mut neighbors = vec![Vec::new(); self.num_qubits];
+        for (a, b) in &self.edges {
+            neighbors[*a].push(*b);
+            neighbors[*b].push(*a);
+        }
+        neighbors
+    }
+}
+
+/// Shortest path between two physical qubits, including both ends.
+fn shortest_path(neighbors: &[Vec<usize>], from: usize, to: usize) -> Option<Vec<usize>> {
+    let mut previous = vec![usize::MAX; neighbors.len()];
+    let mut queue = VecDeque::from([from]);
+    previous[from] = from;
+
+    while let Some(qubit) = queue.pop_front() {
+        if qubit == to {
+            let mut path = vec![to];
+            while *path.last().unwrap() != from {
+                path.push(previous[*path.last().unwrap()]);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next in &neighbors[qubit] {
+            if previous[*next] == usize::MAX {
+                previous[*next] = qubit;
+                queue.push_back(*next);
+            }
+        }
+    }
+
+    None
+}
+
+fn gate_instruction(
+    gate: &QuantumGate,
+    target: usize,
+    control: Vec<usize>,
+    condition: Option<Condition>,
+) -> Instruction {
+    let gate = gate.clone();
+    match condition {
+        None => Instruction::Gate {
+            gate,
+            target,
+            control,
+        },
+        Some(condition) => Instruction::ConditionalGate {
+            gate,
+            target,
+            control,
+            condition,
+        },
+    }
+}
+
+/// Inserts SWAP gates so that every two-qubit gate acts on coupled physical qubits.
+///
+/// Logical qubits are placed on the lowest free physical qubit when allocated. Before a
+/// gate on uncoupled qubits, the control qubit is swapped along the shortest path
+/// towards the target. Each SWAP is emitted as three CNOT gates.
+///
+/// After routing, the process instructions refer to physical qubits, all of which are
+/// allocated at the beginning of the circuit, and the physical qubits of the results
+/// are recorded in the [`MappingReport`](crate::MappingReport) of the metadata. Gates
+/// with more than one control qubit must be decomposed beforehand. The process cannot
+/// have a frozen prefix or opaque blocks.
+#[derive(Debug, Clone)]
+pub struct Routing {
+    /// Device connectivity.
+    pub coupling_map: CouplingMap,
+}
+
+impl Pass for Routing {
+    fn name(&self) -> String {
+        "routing".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        assert_all_editable(process)?;
+        let instructions = route(&process.instructions, &self.coupling_map)?;
+
+        relabel_results(process, |process| {
+            for instruction in &instructions {
+                match instruction {
+                    Instruction::Measure { qubits, output } => {
+                        process.measurements[*output].qubits = qubits.clone()
+                    }
+                    Instruction::Sample { qubits, output, .. } => {
+                        process.samples[*output].qubits = qubits.clone()
+                    }
+                    Instruction::Dump { qubits, output } => {
+                        process.dumps[*output].qubits = qubits.clone()
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        })?;
+
+        let num_qubits = self.coupling_map.num_qubits;
+        process.instructions = instructions;
+        process.qubits = vec![QubitStatus::default(); num_qubits];
+        process.qubit_allocated = num_qubits;
+        process.metadata.qubit_simultaneous = num_qubits;
+
+        Ok(())
+    }
+}
+
+fn swap(a: usize, b: usize) -> [Instruction; 3] {
+    let cnot = |control: usize, target: usize| Instruction::Gate {
+        gate: QuantumGate::PauliX,
+        target,
+        control: vec![control],
+    };
+    [cnot(a, b), cnot(b, a), cnot(a, b)]
+}
+
+/// Routes a list of instructions, returning the instructions on physical qubits.
+///
+/// Diagonal gates and multiplexed rotations are routed as their gates, see
+/// [`multiplexor::lower`].
+pub fn route(instructions: &[Instruction], coupling_map: &CouplingMap) -> Result<Vec<Instruction>> {
+    let instructions = multiplexor::lower(instructions);
+    let neighbors = coupling_map.neighbors();
+    let mut physical: HashMap<usize, usize> = HashMap::new();
+    let mut logical: Vec<Option<usize>> = vec![None; coupling_map.num_qubits];
+
+    let mut output: Vec<Instruction> = (0..coupling_map.num_qubits)
+        .map(|target| Instruction::Alloc { target })
+        .collect();
+
+    let map = |physical: &HashMap<usize, usize>, qubits: &[usize]| -> Result<Vec<usize>> {
+        qubits
+            .iter()
+            .map(|qubit| {
+                physical
+                    .get(qubit)
+                    .copied()
+                    .ok_or(KetError::DeallocatedQubit)
+            })
+            .collect()
+    };
+
+    for instruction in instructions.iter() {
+        match instruction {
+            Instruction::Alloc { target } => {
+                let free = logical
+                    .iter()
+                    .position(Option::is_none)
+                    .ok_or(KetError::NumberOfQubitsExceeded)?;
+                logical[free] = Some(*target);
+                physical.insert(*target, free);
+            }
+            Instruction::Free { target } => {
+                if let Some(qubit) = physical.remove(target) {
+                    logical[qubit] = None;
+                }
+            }
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            }
+            | Instruction::ConditionalGate {
+                gate,
+                target,
+                control,
+                ..
+            } => {
+                // The measured qubits only order the gate after the measurements, so
+                // the freed ones are dropped.
+                let condition = match instruction {
+                    Instruction::ConditionalGate { condition, .. } => Some(Condition {
+                        expected: condition.expected.clone(),
+                        qubits: condition
+                            .qubits
+                            .iter()
+                            .filter_map(|qubit| physical.get(qubit).copied())
+                            .collect(),
+                    }),
+                    _ => None,
+                };
+                if control.len() > 1 {
+                    return Err(KetError::GateNotSupported);
+                }
+
+                let physical_target = map(&physical, &[*target])?[0];
+                if let Some(control) = control.first() {
+                    let physical_control = map(&physical, &[*control])?[0];
+                    let path = shortest_path(&neighbors, physical_control, physical_target)
+                        .ok_or(KetError::InvalidCouplingMap)?;
+
+                    for pair in path[..path.len() - 1].windows(2) {
+                        output.extend(swap(pair[0], pair[1]));
+                        logical.swap(pair[0], pair[1]);
+                        for qubit in pair {
+                            if let Some(logical_qubit) = logical[*qubit] {
+                                physical.insert(logical_qubit, *qubit);
+                            }
+                        }
+                    }
+
+                    output.push(gate_instruction(
+                        gate,
+                        physical_target,
+                        vec![physical[control]],
+                        condition,
+                    ));
+                } else {
+                    output.push(gate_instruction(
+                        gate,
+                        physical_target,
+                        Vec::new(),
+                        condition,
+                    ));
+                }
+            }
+            Instruction::Measure {
+                qubits,
+                output: index,
+            } => output.push(Instruction::Measure {
+                qubits: map(&physical, qubits)?,
+                output: *index,
+            }),
+            Instruction::Sample {
+                qubits,
+                shots,
+                output: index,
+            } => output.push(Instruction::Sample {
+                qubits: map(&physical, qubits)?,
+                shots: *shots,
+                output: *index,
+            }),
+            Instruction::Dump {
+                qubits,
+                output: index,
+            } => output.push(Instruction::Dump {
+                qubits: map(&physical, qubits)?,
+                output: *index,
+            }),
+            Instruction::ExpValue {
+                hamiltonian,
+                output: index,
+            } => {
+                let mut hamiltonian = hamiltonian.clone();
+                for term in hamiltonian.products.iter_mut().flatten() {
+                    term.qubit = map(&physical, &[term.qubit])?[0];
+                }
+                output.push(Instruction::ExpValue {
+                    hamiltonian,
+                    output: *index,
+                });
+            }
+            Instruction::KrausChannel { operators, target } => {
+                output.push(Instruction::KrausChannel {
+                    operators: operators.clone(),
+                    target: map(&physical, &[*target])?[0],
+                })
+            }
+            Instruction::Diagonal { .. } | Instruction::Multiplexor { .. } => {
+                unreachable!("lowered to gates")
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{route, CouplingMap, Routing};
+    use crate::error::KetError;
+    use crate::passes::{PassManager, QubitCompaction};
+    use crate::{Configuration, Instruction, Process, QuantumGate};
+
+    #[test]
+    fn route_on_line() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        let qubits = [
+            process.allocate_qubit()?,
+            process.allocate_qubit()?,
+            process.allocate_qubit()?,
+        ];
+
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[2])?;
+        process.ctrl_pop()?;
+
+        let coupling_map =
+            CouplingMap::from_json(r#"{"num_qubits": 3, "edges": [[0, 1], [1, 2]]}"#)?;
+        let instructions = route(&process.instructions, &coupling_map)?;
+
+        let gates: Vec<(usize, Vec<usize>)> = instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Gate {
+                    target, control, ..
+                } => Some((*target, control.clone())),
+                _ => None,
+            })
+            .collect();
+
+        // One SWAP between 0 and 1, then the CNOT from 1 to 2.
+        assert_eq!(gates.len(), 4);
+        assert_eq!(gates[3], (2, vec![1]));
+
+        assert!(CouplingMap::from_json(r#"{"num_qubits": 2, "edges": [[0, 2]]}"#).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_mapping() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        let qubits = [
+            process.allocate_qubit()?,
+            process.allocate_qubit()?,
+            process.allocate_qubit()?,
+        ];
+
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[2])?;
+        process.ctrl_pop()?;
+        process.measure(&[qubits[0], qubits[2]])?;
+        process.dump(&qubits)?;
+        assert!(process.get_metadata().mapping.is_none());
+
+        let routing = Routing {
+            coupling_map: CouplingMap::line(3),
+        };
+        PassManager::new()
+            .add_pass(routing)
+            .add_pass(QubitCompaction)
+            .run(&mut process)?;
+
+        // The SWAP moved the logical qubit 0 to the physical qubit 1.
+        let mapping = process.get_metadata().mapping.clone().unwrap();
+        assert_eq!(mapping.measurements[0].logical, [0, 2]);
+        assert_eq!(mapping.measurements[0].physical, [1, 2]);
+        assert_eq!(mapping.measurements[0].physical_of(0), Some(1));
+        assert_eq!(mapping.dumps[0].physical, [1, 0, 2]);
+        assert!(mapping.samples.is_empty());
+        assert_eq!(process.get_measurement(0).qubits, [1, 2]);
+
+        Ok(())
+    }
+}
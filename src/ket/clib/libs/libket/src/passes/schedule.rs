@@ -0,0 +1,266 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Scheduling of instructions in layers of simultaneous operations, and in time with
+//! the gate durations of a device.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, ir::Instruction, Process};
+
+use super::{peephole::qubits, report::gate_key, rewrite_editable, CouplingMap, Pass};
+
+/// Reorders the instructions layer by layer, see [`layers`].
+///
+/// Instructions on disjoint qubits commute, so the reordered circuit is equivalent. Its
+/// number of layers is the depth of the circuit on the device, with the gates that
+/// conflict in the crosstalk constraints of the coupling map in different layers.
+#[derive(Debug, Clone)]
+pub struct Scheduling {
+    /// Device connectivity and crosstalk constraints.
+    pub coupling_map: CouplingMap,
+}
+
+impl Pass for Scheduling {
+    fn name(&self) -> String {
+        "scheduling".to_string()
+    }
+
+    fn run(&self, process: &mut Process) -> Result<()> {
+        rewrite_editable(process, |instructions| {
+            Ok(layers(instructions, &self.coupling_map)
+                .into_iter()
+                .flatten()
+                .map(|index| instructions[index].clone())
+                .collect())
+        })
+    }
+}
+
+/// Splits the instructions in layers of operations that run at the same time.
+///
+/// Each instruction is placed as soon as possible, in the first layer after the previous
+/// instructions on its qubits. A gate is then moved to a later layer while its layer has
+/// a gate it [`conflicts`](CouplingMap::conflicts) with. Returns the instruction indices
+/// of each layer, in increasing order.
+pub fn layers(instructions: &[Instruction], coupling_map: &CouplingMap) -> Vec<Vec<usize>> {
+    let mut layers: Vec<Vec<usize>> = Vec::new();
+    // Gates of each layer, with their qubits.
+    let mut gates: Vec<Vec<Vec<usize>>> = Vec::new();
+    let mut next_layer: HashMap<usize, usize> = HashMap::new();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        let qubits = qubits(instruction);
+        let mut layer = qubits
+            .iter()
+            .map(|qubit| next_layer.get(qubit).copied().unwrap_or_default())
+            .max()
+            .unwrap_or_default();
+
+        let is_gate = matches!(instruction, Instruction::Gate { .. });
+        if is_gate {
+            while gates.get(layer).is_some_and(|gates| {
+                gates
+                    .iter()
+                    .any(|other| coupling_map.conflicts(&qubits, other))
+            }) {
+                layer += 1;
+            }
+        }
+
+        if layer >= layers.len() {
+            layers.resize_with(layer + 1, Vec::new);
+            gates.resize_with(layer + 1, Vec::new);
+        }
+        layers[layer].push(index);
+        for qubit in &qubits {
+            next_layer.insert(*qubit, layer + 1);
+        }
+        if is_gate {
+            gates[layer].push(qubits);
+        }
+    }
+
+    layers
+}
+
+/// Durations of the operations of a device, in nanoseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateDurations {
+    /// Duration of a gate without control qubits.
+    pub single_qubit: u64,
+
+    /// Duration of a gate with one control qubit.
+    pub two_qubit: u64,
+
+    /// Duration of a gate with two or more control qubits.
+    pub multi_controlled: u64,
+
+    /// Duration of the measurement of a qubit.
+    pub measure: u64,
+
+    /// Duration of specific gates, by the names of
+    /// [`ResourceReport::gate_counts`](super::ResourceReport::gate_counts), as in `cz`.
+    #[serde(default)]
+    pub gates: BTreeMap<String, u64>,
+}
+
+impl GateDurations {
+    /// Duration of an instruction. Instructions other than gates and measurements take
+    /// no time.
+    pub fn duration(&self, instruction: &Instruction) -> u64 {
+        match instruction {
+            Instruction::Gate { gate, control, .. } => self
+                .gates
+                .get(&gate_key(gate, control))
+                .copied()
+                .unwrap_or(match control.len() {
+                    0 => self.single_qubit,
+                    1 => self.two_qubit,
+                    _ => self.multi_controlled,
+                }),
+            Instruction::Measure { .. } => self.measure,
+            _ => 0,
+        }
+    }
+}
+
+/// Instruction with its start time and duration, in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimedInstruction {
+    /// Index of the instruction.
+    pub index: usize,
+
+    /// Start time.
+    pub start: u64,
+
+    /// Duration.
+    pub duration: u64,
+}
+
+impl TimedInstruction {
+    /// End time.
+    pub fn end(&self) -> u64 {
+        self.start + self.duration
+    }
+}
+
+/// Schedules the instructions in time, as soon as possible.
+///
+/// Each instruction starts when the previous instructions on its qubits end, and a gate
+/// is delayed while it overlaps in time a gate it
+/// [`conflicts`](CouplingMap::conflicts) with. Returns one entry per instruction, in the
+/// order of the instructions.
+pub fn timed_schedule(
+    instructions: &[Instruction],
+    coupling_map: &CouplingMap,
+    durations: &GateDurations,
+) -> Vec<TimedInstruction> {
+    let mut schedule = Vec::with_capacity(instructions.len());
+    // Scheduled gates, with their qubits.
+    let mut gates: Vec<(Vec<usize>, TimedInstruction)> = Vec::new();
+    let mut free_at: HashMap<usize, u64> = HashMap::new();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        let qubits = qubits(instruction);
+        let mut timed = TimedInstruction {
+            index,
+            start: qubits
+                .iter()
+                .map(|qubit| free_at.get(qubit).copied().unwrap_or_default())
+                .max()
+                .unwrap_or_default(),
+            duration: durations.duration(instruction),
+        };
+
+        if matches!(instruction, Instruction::Gate { .. }) {
+            while let Some(end) = gates
+                .iter()
+                .filter(|(other, other_timed)| {
+                    other_timed.start < timed.end()
+                        && timed.start < other_timed.end()
+                        && coupling_map.conflicts(&qubits, other)
+                })
+                .map(|(_, other_timed)| other_timed.end())
+                .max()
+            {
+                timed.start = end;
+            }
+        }
+
+        for qubit in &qubits {
+            free_at.insert(*qubit, timed.end());
+        }
+        if matches!(instruction, Instruction::Gate { .. }) {
+            gates.push((qubits, timed));
+        }
+        schedule.push(timed);
+    }
+
+    schedule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layers, Scheduling};
+    use crate::{
+        error::KetError,
+        passes::{CouplingMap, Pass},
+        Configuration, Instruction, Process, QuantumGate,
+    };
+
+    #[test]
+    fn serialize_crosstalk() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(4));
+        let qubits: Vec<usize> = (0..4)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        for qubit in &qubits {
+            process.apply_gate(QuantumGate::Hadamard, *qubit)?;
+        }
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.ctrl_pop()?;
+        process.apply_gate(QuantumGate::PauliZ, qubits[0])?;
+        process.ctrl_push(&[qubits[2]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[3])?;
+        process.ctrl_pop()?;
+
+        // Allocations, Hadamard gates, then the CNOT gates 8 and 10 and the Z gate 9.
+        let line = CouplingMap::line(4);
+        assert_eq!(
+            layers(&process.instructions, &line)[2..],
+            [vec![8, 10], vec![9]]
+        );
+
+        let coupling_map = CouplingMap::from_json(
+            r#"{"num_qubits": 4, "edges": [[0, 1], [1, 2], [2, 3]], "crosstalk": [[[0, 1], [2, 3]]]}"#,
+        )?;
+        assert!(coupling_map.conflicts(&[1, 0], &[2, 3]));
+        assert!(!coupling_map.conflicts(&[0, 1], &[2]));
+        assert_eq!(
+            layers(&process.instructions, &coupling_map)[2..],
+            [vec![8], vec![9, 10]]
+        );
+
+        Scheduling { coupling_map: line }.run(&mut process)?;
+        assert!(matches!(
+            process.instructions[10],
+            Instruction::Gate {
+                gate: QuantumGate::PauliZ,
+                ..
+            }
+        ));
+
+        assert!(CouplingMap::from_json(
+            r#"{"num_qubits": 2, "edges": [[0, 1]], "crosstalk": [[[0], []]]}"#
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
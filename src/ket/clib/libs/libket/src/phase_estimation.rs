@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Iterative phase estimation.
+//!
+//! The eigenphase `φ = 0.φ1φ2...φm` of a unitary `U` is measured one bit at a time with a
+//! single ancilla qubit, starting from the least significant bit. The iteration `k`
+//! applies `U^(2^(k-1))` controlled by the ancilla in the `|+>` state, and rotates the
+//! ancilla by the phase of the bits already measured, so that the remaining phase is
+//! exactly `0.φk`. The rotation is computed from the previous outcomes with
+//! [`Process::classical_step`], so the routine requires a live quantum execution.
+//!
+//! Repeating the estimation on the same eigenstate gives error bars for phases that
+//! are not exactly represented with `m` bits.
+
+use std::f64::consts::PI;
+
+use crate::{
+    error::{KetError, Result},
+    ir::QuantumGate,
+    Angle, Process,
+};
+
+/// Result of [`iterative_phase_estimation`].
+#[derive(Debug, Clone)]
+pub struct PhaseEstimate {
+    /// Estimated eigenphase, as a fraction of a turn in `[0, 1)`.
+    pub phase: f64,
+
+    /// Standard error of the estimated phase across the repetitions.
+    pub std_error: f64,
+
+    /// Phase measured in each repetition.
+    pub samples: Vec<f64>,
+}
+
+/// Estimates the eigenphase of a unitary with `bits` bits of precision.
+///
+/// `apply_power(process, power)` must apply `U^power` on the eigenstate qubits. It is
+/// called within a control scope of the ancilla, so the gates it applies are
+/// controlled. The eigenstate must be prepared beforehand, and the estimation is
+/// repeated `repetitions` times on it.
+///
+/// # Errors
+///
+/// Returns [`KetError::InvalidPhaseEstimation`] if `bits` is not between 1 and 63 or
+/// `repetitions` is zero, [`KetError::LiveExecutionRequired`] if the process is not in
+/// live mode, and the errors of `apply_power`.
+pub fn iterative_phase_estimation<U>(
+    process: &mut Process,
+    bits: usize,
+    repetitions: usize,
+    mut apply_power: U,
+) -> Result<PhaseEstimate>
+where
+    U: FnMut(&mut Process, u64) -> Result<()>,
+{
+    if bits == 0 || bits >= u64::BITS as usize || repetitions == 0 {
+        return Err(KetError::InvalidPhaseEstimation);
+    }
+    if process.config.live_quantum_execution.is_none() {
+        return Err(KetError::LiveExecutionRequired);
+    }
+
+    let ancilla = process.allocate_qubit()?;
+    let samples = (0..repetitions)
+        .map(|_| estimate_once(process, ancilla, bits, &mut apply_power))
+        .collect::<Result<Vec<_>>>()?;
+    process.free_qubit(ancilla)?;
+
+    let (phase, std_error) = circular_statistics(&samples);
+    Ok(PhaseEstimate {
+        phase,
+        std_error,
+        samples,
+    })
+}
+
+/// Measures the phase bits from the least to the most significant, returning the phase.
+fn estimate_once<U>(
+    process: &mut Process,
+    ancilla: usize,
+    bits: usize,
+    apply_power: &mut U,
+) -> Result<f64>
+where
+    U: FnMut(&mut Process, u64) -> Result<()>,
+{
+    // Measurement of each bit, from the least significant.
+    let mut measurements = Vec::with_capacity(bits);
+
+    for k in (1..=bits).rev() {
+        process.apply_gate(QuantumGate::Hadamard, ancilla)?;
+        process.ctrl_push(&[ancilla])?;
+        let result = apply_power(process, 1 << (k - 1));
+        process.ctrl_pop()?;
+        result?;
+
+        // The bit j of the phase, measured at index bits - j, adds 2^(k-j-1) turns.
+        process.classical_step(&measurements, |results| {
+            let turns: f64 = results
+                .iter()
+                .enumerate()
+                .map(|(index, bit)| *bit as f64 / (1u64 << (bits - index - k + 1)) as f64)
+                .sum();
+            vec![(
+                QuantumGate::Phase(Angle::Scalar(-2.0 * PI * turns)),
+                ancilla,
+            )]
+        })?;
+        process.apply_gate(QuantumGate::Hadamard, ancilla)?;
+
+        let measurement = process.measure(&[ancilla])?;
+        process.classical_step(&[measurement], |results| match results[0] {
+            1 => vec![(QuantumGate::PauliX, ancilla)],
+            _ => vec![],
+        })?;
+        measurements.push(measurement);
+    }
+
+    let value = measurements.iter().rev().fold(0, |value, index| {
+        (value << 1) | process.get_measurement(*index).result.unwrap()
+    });
+    Ok(value as f64 / (1u64 << bits) as f64)
+}
+
+/// Returns the circular mean of phases in turns and its standard error.
+fn circular_statistics(phases: &[f64]) -> (f64, f64) {
+    let (sin, cos) = phases.iter().fold((0.0, 0.0), |(sin, cos), phase| {
+        let angle = 2.0 * PI * phase;
+        (sin + angle.sin(), cos + angle.cos())
+    });
+    let mean = (sin.atan2(cos) / (2.0 * PI)).rem_euclid(1.0);
+
+    if phases.len() < 2 {
+        return (mean, 0.0);
+    }
+    // Distance to the mean, wrapped to half a turn.
+    let variance = phases
+        .iter()
+        .map(|phase| (phase - mean + 0.5).rem_euclid(1.0) - 0.5)
+        .map(|distance| distance * distance)
+        .sum::<f64>()
+        / (phases.len() - 1) as f64;
+    (mean, (variance / phases.len() as f64).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::circular_statistics;
+
+    #[test]
+    fn wrapped_phases() {
+        let (phase, std_error) = circular_statistics(&[0.75, 0.75]);
+        assert!((phase - 0.75).abs() < 1e-12 && std_error < 1e-12);
+
+        // The phases around zero average to zero, not to one half.
+        let (phase, std_error) = circular_statistics(&[0.98, 0.02]);
+        assert!(phase < 1e-12 || 1.0 - phase < 1e-12);
+        assert!((std_error - 0.02).abs() < 1e-12);
+    }
+}
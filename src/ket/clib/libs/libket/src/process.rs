@@ -6,19 +6,52 @@
 //! This module contains the `Process` struct, which encapsulates the necessary information for
 //! handling qubit allocations and creating quantum circuits.
 
-use log::info;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use tracing::{info, info_span};
 
 use crate::{
     error::{KetError, Result},
-    ir::{Instruction, Metadata, PauliHamiltonian, ProcessStatus, QuantumGate, ResultData},
+    ir::{
+        Condition, DumpData, Instruction, KrausOperator, Metadata, Pauli, PauliHamiltonian,
+        ProcessStatus, QuantumGate, ResultData,
+    },
     objects::{Dump, ExpValue, Measurement, QubitStatus, Sample},
-    Angle, Configuration,
+    Angle, BitOrder, Configuration,
 };
 
-use crate::qasmv2::exporter::to_qasmv2;
-use crate::qasmv2::importer::from_qasmv2;
+use crate::bloch::{BlochHandle, BlochTrajectories, BlochVector};
+use crate::compression::{CompressedDump, DumpCompression};
+use crate::events::{ExecutionEvent, Subscribers};
+use crate::grouping::{sample_hamiltonian, GroupedSample};
+use crate::hashing::{structurally_equal, CircuitHash};
+use crate::multiplexor;
+use crate::passes::budget::ErrorBudget;
+use crate::passes::schedule::{timed_schedule, GateDurations};
+use crate::passes::{Canonicalize, CouplingMap, Pass, ResourceReport};
+use crate::provenance::{Provenance, SourceLocation};
+#[cfg(feature = "qasm")]
+use crate::qasmv2::creg::CregMap;
+#[cfg(feature = "qasm")]
+use crate::qasmv2::exporter::{to_qasmv2, to_qasmv2_formatted};
+#[cfg(feature = "qasm")]
+use crate::qasmv2::formatter::QasmFormat;
+#[cfg(feature = "qasm")]
+use crate::qasmv2::gate_table::GateTable;
+#[cfg(feature = "qasm")]
+use crate::qasmv2::importer::{from_qasmv2, from_qasmv2_with_gates};
+#[cfg(feature = "qasm")]
 use crate::qasmv2::instruction_set::InstructionSet;
-use crate::zx::optimize::optimize;
+use crate::qasmv3::to_qasmv3_scheduled;
+use crate::shadows::{classical_shadow, ClassicalShadow};
+use crate::state_preparation::GroverRudolph;
+use crate::structure::{Node, StructureRecorder};
+use crate::template::{apply_power, apply_template, Template};
+use crate::trace::{Trace, TraceCall};
+use crate::zx;
 
 /// Quantum Process for managing qubit allocation and circuit creation.
 ///
@@ -82,9 +115,15 @@ pub struct Process {
     /// Instructions stack fo handling nested inverse scopes
     pub(crate) adj_stack: Vec<Vec<Instruction>>,
 
+    /// Conditions of the nested conditional scopes
+    pub(crate) cond_stack: Vec<Condition>,
+
     /// List of measurement results
     pub(crate) measurements: Vec<Measurement>,
 
+    /// Outcomes forced by measurement index
+    pub(crate) forced_measurements: BTreeMap<usize, u64>,
+
     /// List of expected values
     pub(crate) exp_values: Vec<ExpValue>,
 
@@ -94,6 +133,53 @@ pub struct Process {
     /// List of quantum state dump results
     pub(crate) dumps: Vec<Dump>,
 
+    /// Compression mode for new dump results
+    pub(crate) dump_compression: DumpCompression,
+
+    /// Receivers of the live execution events
+    pub(crate) subscribers: Subscribers,
+
+    /// Bloch vectors of the tracked qubits after each live gate
+    pub(crate) bloch_trajectories: BlochTrajectories,
+
+    /// Number of instructions already optimized, see [`Process::optimize`]
+    pub(crate) optimized_len: usize,
+
+    /// Number of instructions that passes cannot rewrite, see [`Process::freeze`]
+    pub(crate) frozen_len: usize,
+
+    /// Instructions that passes cannot rewrite nor reorder, see
+    /// [`Process::opaque_begin`]
+    pub(crate) opaque: Vec<Range<usize>>,
+
+    /// Start and nesting depth of the opaque block being recorded
+    pub(crate) opaque_scope: Option<(usize, usize)>,
+
+    /// Sections with a fidelity target, see [`Process::budget_begin`]
+    pub(crate) budgets: Vec<ErrorBudget>,
+
+    /// Section being recorded, whose range ends at the last instruction
+    pub(crate) budget_scope: Option<ErrorBudget>,
+
+    /// Error of the approximations made out of the sections
+    pub(crate) unbudgeted_error: f64,
+
+    /// Number of instructions executed as chunks and removed, see
+    /// [`Configuration::chunk_size`]
+    pub(crate) streamed_len: usize,
+
+    /// Hierarchical view of the control and inverse scopes
+    pub(crate) structure: StructureRecorder,
+
+    /// Source location of each instruction, see [`Process::set_source_location`]
+    pub(crate) provenance: Provenance,
+
+    /// Calls recorded since [`Process::start_trace`]
+    pub(crate) trace: Option<Trace>,
+
+    /// Number of checkpoints taken, see [`Process::checkpoint`]
+    pub(crate) checkpoints: usize,
+
     /// Number of qubits allocated
     pub qubit_allocated: usize,
     pub qubits: Vec<QubitStatus>,
@@ -110,15 +196,47 @@ impl Process {
             ctrl_list: Default::default(),
             ctrl_list_is_up_to_date: Default::default(),
             adj_stack: Default::default(),
+            cond_stack: Default::default(),
             measurements: Default::default(),
+            forced_measurements: Default::default(),
             exp_values: Default::default(),
             samples: Default::default(),
             dumps: Default::default(),
+            dump_compression: Default::default(),
+            subscribers: Default::default(),
+            bloch_trajectories: Default::default(),
+            optimized_len: Default::default(),
+            frozen_len: Default::default(),
+            opaque: Default::default(),
+            opaque_scope: Default::default(),
+            budgets: Default::default(),
+            budget_scope: Default::default(),
+            unbudgeted_error: Default::default(),
+            streamed_len: Default::default(),
+            structure: StructureRecorder::new(),
+            provenance: Default::default(),
+            trace: None,
+            checkpoints: Default::default(),
             qubit_allocated: Default::default(),
             qubits: Default::default(),
         }
     }
 
+    /// Records a call if the process is being traced
+    fn record(&mut self, call: impl FnOnce() -> TraceCall) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(call());
+        }
+    }
+
+    /// Runs `f` without recording the calls it makes
+    fn untraced<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let trace = self.trace.take();
+        let result = f(self);
+        self.trace = trace;
+        result
+    }
+
     /// Returns a list of control qubits
     ///
     /// Update the control qubits list if necessary and return it.
@@ -133,6 +251,80 @@ impl Process {
         &self.ctrl_list
     }
 
+    /// Appends a non-gate instruction to the circuit and to its hierarchical view
+    fn push_instruction(&mut self, instruction: Instruction) {
+        self.structure.push(Node::Instruction(instruction.clone()));
+        self.append(instruction);
+    }
+
+    /// Appends an instruction to the circuit with the current source location
+    fn append(&mut self, instruction: Instruction) {
+        self.provenance.push(&instruction);
+        self.instructions.push(instruction);
+    }
+
+    /// Restarts the hierarchical view and carries the source locations, after the
+    /// instructions were rewritten
+    pub(crate) fn rewritten(&mut self) {
+        self.structure.reset(&self.instructions);
+        self.provenance.carry(&self.instructions);
+    }
+
+    /// Returns a process without instructions or qubits that checks the instructions
+    /// added to it as this one does.
+    ///
+    /// The scratch process takes the limits, decomposition, and bit order of the
+    /// process, but not its executor nor its initial state, and it is never optimized.
+    #[cfg(feature = "qasm")]
+    pub(crate) fn scratch(&self) -> Process {
+        let config = &self.config;
+        Process::new(Configuration {
+            allow_measure: config.allow_measure,
+            allow_sample: config.allow_sample,
+            allow_exp_value: config.allow_exp_value,
+            allow_dump: config.allow_dump,
+            valid_after_measure: config.valid_after_measure,
+            continue_after_sample: config.continue_after_sample,
+            continue_after_exp_value: config.continue_after_exp_value,
+            continue_after_dump: config.continue_after_dump,
+            decompose: config.decompose,
+            optimize: false,
+            bit_order: config.bit_order,
+            import_limits: config.import_limits,
+            ..Configuration::new(config.num_qubits)
+        })
+    }
+
+    /// Returns the condition of the open conditional scopes, or `None` if the gates
+    /// are applied unconditionally
+    ///
+    /// In live mode, the measurement results are known, so the condition is evaluated
+    /// here and the gate is skipped if `Err` is returned.
+    fn gate_condition(&self) -> std::result::Result<Option<Condition>, ()> {
+        if self.cond_stack.is_empty() {
+            return Ok(None);
+        }
+        let condition = Condition {
+            expected: self
+                .cond_stack
+                .iter()
+                .flat_map(|condition| condition.expected.iter().copied())
+                .collect(),
+            qubits: self
+                .cond_stack
+                .iter()
+                .flat_map(|condition| condition.qubits.iter().copied())
+                .collect(),
+        };
+        if self.config.live_quantum_execution.is_none() {
+            Ok(Some(condition))
+        } else if condition.holds(|index| self.measurements.get(index)?.result) {
+            Ok(None)
+        } else {
+            Err(())
+        }
+    }
+
     /// Return an error if the given qubit index is in the control qubit list
     fn assert_target_not_in_control(&mut self, target: usize) -> Result<()> {
         if self.get_control_qubits().contains(&target) {
@@ -197,6 +389,7 @@ impl Process {
     /// Returns an error if the process is in an inverse scope, if it is ready for
     /// execution, or if the number of allocated qubits exceeds the configured limit.
     pub fn allocate_qubit(&mut self) -> Result<usize> {
+        self.record(|| TraceCall::AllocateQubit);
         self.assert_not_adj()?;
         self.assert_not_ready_for_execution()?;
         if self.qubit_allocated >= self.config.num_qubits {
@@ -212,15 +405,41 @@ impl Process {
             self.metadata.qubit_simultaneous = self.qubit_allocated;
         }
 
-        self.instructions.push(Instruction::Alloc { target: index });
+        self.push_instruction(Instruction::Alloc { target: index });
 
         if let Some(processor) = self.config.live_quantum_execution.as_mut() {
             processor.alloc(index);
+            self.subscribers
+                .emit(|| ExecutionEvent::Allocated { qubit: index });
         }
 
+        self.prepare_initial_state(index)?;
+        self.stream_chunk()?;
         Ok(index)
     }
 
+    /// Prepares [`Configuration::initial_state`] once its last qubit is allocated
+    fn prepare_initial_state(&mut self, index: usize) -> Result<()> {
+        let Some(state) = &self.config.initial_state else {
+            return Ok(());
+        };
+        let num_qubits = state.len().trailing_zeros() as usize;
+        if index + 1 != num_qubits {
+            return Ok(());
+        }
+        if self
+            .instructions
+            .iter()
+            .any(|instruction| !matches!(instruction, Instruction::Alloc { .. }))
+        {
+            return Err(KetError::InvalidInitialState);
+        }
+        let preparation =
+            GroverRudolph::from_amplitudes(state).map_err(|_| KetError::InvalidInitialState)?;
+        let qubits: Vec<usize> = (0..num_qubits).collect();
+        self.untraced(|process| preparation.apply(process, &qubits))
+    }
+
     /// Frees a previously allocated qubit
     ///
     /// # Examples
@@ -243,18 +462,21 @@ impl Process {
     /// Returns an error if the process is in an inverse scope, if it is ready for
     /// execution, or if the specified qubit has not been allocated.
     pub fn free_qubit(&mut self, qubit: usize) -> Result<()> {
+        self.record(|| TraceCall::FreeQubit { qubit });
         self.assert_not_adj()?;
         self.assert_not_ready_for_execution()?;
         self.assert_qubit_allocated(qubit)?;
 
-        self.instructions.push(Instruction::Free { target: qubit });
+        self.push_instruction(Instruction::Free { target: qubit });
 
         self.qubits[qubit].allocated = false;
 
         if let Some(processor) = self.config.live_quantum_execution.as_mut() {
             processor.free(qubit);
+            self.subscribers.emit(|| ExecutionEvent::Freed { qubit });
         }
 
+        self.stream_chunk()?;
         Ok(())
     }
 
@@ -287,15 +509,42 @@ impl Process {
     /// qubit has not been allocated, or if the target qubit is part of the control
     ///  qubits.
     pub fn apply_gate(&mut self, gate: QuantumGate, target: usize) -> Result<()> {
+        self.record(|| TraceCall::ApplyGate {
+            gate: gate.clone(),
+            target,
+        });
         self.assert_not_ready_for_execution()?;
         self.assert_qubit_allocated(target)?;
         self.assert_target_not_in_control(target)?;
         let control = self.get_control_qubits().to_vec();
 
-        if self.config.decompose {
-            todo!();
+        if self.config.decompose && !gate.is_minimal() && self.opaque_scope.is_none() {
+            return self.untraced(|process| {
+                for gate in gate.decompose_minimal() {
+                    process.apply_gate(gate, target)?;
+                }
+                Ok(())
+            });
         }
 
+        let Ok(condition) = self.gate_condition() else {
+            return Ok(());
+        };
+
+        self.structure.push(Node::Instruction(match &condition {
+            None => Instruction::Gate {
+                gate: gate.clone(),
+                target,
+                control: Vec::new(),
+            },
+            Some(condition) => Instruction::ConditionalGate {
+                gate: gate.clone(),
+                target,
+                control: Vec::new(),
+                condition: condition.clone(),
+            },
+        }));
+
         let add_adj_gate = self.adj_stack.len() % 2 == 1;
 
         let gate = if add_adj_gate { gate.inverse() } else { gate };
@@ -315,22 +564,33 @@ impl Process {
             });
         } else {
             if let Some(processor) = self.config.live_quantum_execution.as_mut() {
-                info!(
-                    "live execution: gate={:?}, target={}, control={:?}",
-                    gate, target, control
-                );
-
-                processor.gate(&gate, target, &control)
+                info!(?gate, target, ?control, "live execution");
+
+                processor.gate(&gate, target, &control);
+                self.subscribers.emit(|| ExecutionEvent::GateApplied {
+                    gate: gate.clone(),
+                    target,
+                    control: control.clone(),
+                });
+                self.record_bloch_trajectories();
             }
 
-            self.instructions.push(Instruction::Gate {
-                gate,
-                target,
-                control,
+            self.append(match condition {
+                None => Instruction::Gate {
+                    gate,
+                    target,
+                    control,
+                },
+                Some(condition) => Instruction::ConditionalGate {
+                    gate,
+                    target,
+                    control,
+                    condition,
+                },
             });
         }
 
-        Ok(())
+        self.stream_chunk()
     }
 
     /// Applies a global phase
@@ -339,6 +599,9 @@ impl Process {
     /// If the process has an opened controlled scope, a controlled-phase gate
     /// is applied on the control qubits. Otherwise, the global-phase is ignored.
     pub fn apply_global_phase(&mut self, phase: Angle) -> Result<()> {
+        self.record(|| TraceCall::ApplyGlobalPhase {
+            phase: phase.clone(),
+        });
         self.assert_not_ready_for_execution()?;
 
         let control = self.get_control_qubits().to_vec();
@@ -346,6 +609,17 @@ impl Process {
         if control.is_empty() {
             return Ok(());
         }
+        let Ok(condition) = self.gate_condition() else {
+            return Ok(());
+        };
+
+        self.structure.push(Node::GlobalPhase(phase.clone()));
+
+        let phase = if self.adj_stack.len() % 2 == 1 {
+            phase.inverse()
+        } else {
+            phase
+        };
 
         self.metadata.depth += 1;
         self.metadata
@@ -354,30 +628,289 @@ impl Process {
             .and_modify(|count| *count += 1)
             .or_insert(1);
 
-        let phase_gate = Instruction::Gate {
-            gate: QuantumGate::Phase(phase.clone()),
-            target: control[0],
-            control: control[1..].to_vec(),
+        let phase_gate = match condition {
+            None => Instruction::Gate {
+                gate: QuantumGate::Phase(phase.clone()),
+                target: control[0],
+                control: control[1..].to_vec(),
+            },
+            Some(condition) => Instruction::ConditionalGate {
+                gate: QuantumGate::Phase(phase.clone()),
+                target: control[0],
+                control: control[1..].to_vec(),
+                condition,
+            },
         };
 
         if !self.adj_stack.is_empty() {
             self.adj_stack.last_mut().unwrap().push(phase_gate);
         } else {
             if let Some(processor) = self.config.live_quantum_execution.as_mut() {
-                processor.gate(&QuantumGate::Phase(phase), control[0], &control[1..])
+                processor.gate(
+                    &QuantumGate::Phase(phase.clone()),
+                    control[0],
+                    &control[1..],
+                );
+                self.subscribers.emit(|| ExecutionEvent::GateApplied {
+                    gate: QuantumGate::Phase(phase),
+                    target: control[0],
+                    control: control[1..].to_vec(),
+                });
+                self.record_bloch_trajectories();
+            }
+
+            self.append(phase_gate);
+        }
+
+        self.stream_chunk()
+    }
+
+    /// Applies a Kraus channel to a qubit
+    ///
+    /// The channel maps the state `rho` of the target qubit to `sum K rho K^dagger` over
+    /// its operators `K`, inserting custom noise at this point of the circuit,
+    /// independently of the noise model of the executor. Simulators apply it exactly on
+    /// a density matrix, or pick one operator at random on a state vector, so the
+    /// average over many runs is the noisy result.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use ket::error::KetError;
+    /// # use ket::{Configuration, KrausOperator, Process};
+    /// #
+    /// # fn main() -> Result<(), KetError> {
+    /// # let mut process = Process::new(Configuration::new(1));
+    /// let qubit = process.allocate_qubit()?;
+    /// // Bit flip with probability 0.1.
+    /// let p: f64 = 0.1;
+    /// let identity = KrausOperator {
+    ///     real: [[(1.0 - p).sqrt(), 0.0], [0.0, (1.0 - p).sqrt()]],
+    ///     imag: [[0.0; 2]; 2],
+    /// };
+    /// let flip = KrausOperator {
+    ///     real: [[0.0, p.sqrt()], [p.sqrt(), 0.0]],
+    ///     imag: [[0.0; 2]; 2],
+    /// };
+    /// process.apply_kraus_channel(&[identity, flip], qubit)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidKrausChannel`] if `sum K^dagger K` is not the identity
+    /// or if a control or inverse scope is open, and
+    /// [`KetError::KrausChannelNotSupported`] if the live executor does not support
+    /// channels. Also returns an error if the process is ready for execution or if the
+    /// qubit is not allocated.
+    pub fn apply_kraus_channel(
+        &mut self,
+        operators: &[KrausOperator],
+        target: usize,
+    ) -> Result<()> {
+        self.record(|| TraceCall::ApplyKrausChannel {
+            operators: operators.to_vec(),
+            target,
+        });
+        self.assert_not_ready_for_execution()?;
+        self.assert_qubit_allocated(target)?;
+        if !self.ctrl_stack.is_empty()
+            || !self.adj_stack.is_empty()
+            || !KrausOperator::is_channel(operators)
+        {
+            return Err(KetError::InvalidKrausChannel);
+        }
+
+        if let Some(processor) = self.config.live_quantum_execution.as_mut() {
+            if !processor.kraus_channel(operators, target) {
+                return Err(KetError::KrausChannelNotSupported);
             }
+            self.record_bloch_trajectories();
+        }
+
+        self.push_instruction(Instruction::KrausChannel {
+            operators: operators.to_vec(),
+            target,
+        });
+
+        self.stream_chunk()
+    }
+
+    /// Applies a diagonal gate
+    ///
+    /// Each basis state `|i>` of the qubits is multiplied by `exp(i phases[i])`, the
+    /// first qubit being the most significant bit of `i`. The gate is appended as a
+    /// single instruction, see [`multiplexor`](crate::multiplexor), or as its CNOTs and
+    /// rotations in a control, inverse, or conditional scope, or if the configuration
+    /// decomposes the gates.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use ket::error::KetError;
+    /// # use ket::{Configuration, Process};
+    /// #
+    /// # fn main() -> Result<(), KetError> {
+    /// # let mut process = Process::new(Configuration::new(2));
+    /// let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+    /// // CZ
+    /// process.apply_diagonal(&[0.0, 0.0, 0.0, std::f64::consts::PI], &qubits)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidMultiplexor`] if the number of phases is not two to the
+    /// number of qubits or if a qubit is repeated. Also returns an error if the process
+    /// is ready for execution, if a qubit is not allocated, or if it is a control qubit.
+    pub fn apply_diagonal(&mut self, phases: &[f64], qubits: &[usize]) -> Result<()> {
+        self.record(|| TraceCall::ApplyDiagonal {
+            phases: phases.to_vec(),
+            qubits: qubits.to_vec(),
+        });
+        self.assert_not_ready_for_execution()?;
+        if phases.len() != 1 << qubits.len() {
+            return Err(KetError::InvalidMultiplexor);
+        }
+        self.assert_multiplexor_qubits(qubits)?;
+
+        self.apply_multiplexed(Instruction::Diagonal {
+            phases: phases.to_vec(),
+            qubits: qubits.to_vec(),
+        })
+    }
 
-            self.instructions.push(phase_gate);
+    /// Applies a multiplexed rotation
+    ///
+    /// The target qubit is rotated around `axis` by `angles[i]` when the control qubits
+    /// are in the state `|i>`, the first control being the most significant bit of `i`.
+    /// The rotation is appended as a single instruction, see
+    /// [`multiplexor`](crate::multiplexor), or as its CNOTs and rotations in a control,
+    /// inverse, or conditional scope, or if the configuration decomposes the gates.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidMultiplexor`] if the number of angles is not two to the
+    /// number of controls or if a qubit is repeated. Also returns an error if the
+    /// process is ready for execution, if a qubit is not allocated, or if it is a
+    /// control qubit of the control stack.
+    pub fn apply_multiplexor(
+        &mut self,
+        axis: Pauli,
+        angles: &[f64],
+        control: &[usize],
+        target: usize,
+    ) -> Result<()> {
+        self.record(|| TraceCall::ApplyMultiplexor {
+            axis,
+            angles: angles.to_vec(),
+            control: control.to_vec(),
+            target,
+        });
+        self.assert_not_ready_for_execution()?;
+        if angles.len() != 1 << control.len() {
+            return Err(KetError::InvalidMultiplexor);
         }
+        self.assert_multiplexor_qubits(&[control, &[target]].concat())?;
+
+        self.apply_multiplexed(Instruction::Multiplexor {
+            axis,
+            angles: angles.to_vec(),
+            control: control.to_vec(),
+            target,
+        })
+    }
 
+    /// Return an error if the qubits of a diagonal gate or multiplexed rotation are not
+    /// distinct, allocated, and out of the control qubit list
+    fn assert_multiplexor_qubits(&mut self, qubits: &[usize]) -> Result<()> {
+        if qubits
+            .iter()
+            .enumerate()
+            .any(|(index, qubit)| qubits[..index].contains(qubit))
+        {
+            return Err(KetError::InvalidMultiplexor);
+        }
+        for qubit in qubits {
+            self.assert_qubit_allocated(*qubit)?;
+            self.assert_target_not_in_control(*qubit)?;
+        }
         Ok(())
     }
 
+    /// Appends a diagonal gate or multiplexed rotation, or its gates if it cannot be a
+    /// single instruction here
+    fn apply_multiplexed(&mut self, instruction: Instruction) -> Result<()> {
+        if !self.ctrl_stack.is_empty()
+            || !self.adj_stack.is_empty()
+            || !self.cond_stack.is_empty()
+            || self.config.decompose
+        {
+            let (gates, phase) = multiplexor::synthesize(&instruction).unwrap();
+            return self.untraced(|process| {
+                for gate in gates {
+                    let Instruction::Gate {
+                        gate,
+                        target,
+                        control,
+                    } = gate
+                    else {
+                        unreachable!("synthesized instructions are gates")
+                    };
+                    if control.is_empty() {
+                        process.apply_gate(gate, target)?;
+                    } else {
+                        process.ctrl_push(&control)?;
+                        process.apply_gate(gate, target)?;
+                        process.ctrl_pop()?;
+                    }
+                }
+                if phase != 0.0 {
+                    process.apply_global_phase(Angle::Scalar(phase))?;
+                }
+                Ok(())
+            });
+        }
+
+        let num_qubits = match &instruction {
+            Instruction::Diagonal { qubits, .. } => qubits.len(),
+            Instruction::Multiplexor { control, .. } => control.len() + 1,
+            _ => unreachable!(),
+        };
+        self.metadata.depth += 1;
+        self.metadata
+            .gate_count
+            .entry(num_qubits)
+            .and_modify(|count| *count += 1)
+            .or_insert(1);
+
+        if let Some(processor) = self.config.live_quantum_execution.as_mut() {
+            match &instruction {
+                Instruction::Diagonal { phases, qubits } => processor.diagonal(phases, qubits),
+                Instruction::Multiplexor {
+                    axis,
+                    angles,
+                    control,
+                    target,
+                } => processor.multiplexor(*axis, angles, control, *target),
+                _ => unreachable!(),
+            }
+            self.record_bloch_trajectories();
+        }
+
+        self.push_instruction(instruction);
+        self.stream_chunk()
+    }
+
     /// Measures the specified qubits
     ///
     /// This function performs measurements on the specified qubits.
     /// It updates the internal state of the process, records measurement instructions, and
-    /// returns the index of the measurement result.
+    /// returns the index of the measurement result. The qubits are stored in the
+    /// measurement from the most to the least significant bit of the result, following
+    /// [`Configuration::bit_order`].
     ///
     /// # Examples
     ///
@@ -400,6 +933,16 @@ impl Process {
     /// is ready for execution, or if measurements are not allowed based on the
     /// process configuration.
     pub fn measure(&mut self, qubits: &[usize]) -> Result<usize> {
+        let qubits = self.config.bit_order.arrange(qubits);
+        self.measure_arranged(&qubits)
+    }
+
+    /// Measures the qubits, given from the most to the least significant bit
+    pub(crate) fn measure_arranged(&mut self, qubits: &[usize]) -> Result<usize> {
+        self.record(|| TraceCall::Measure {
+            qubits: qubits.to_vec(),
+            result: None,
+        });
         self.assert_not_adj()?;
         self.assert_not_ready_for_execution()?;
         if !self.config.allow_measure {
@@ -423,21 +966,118 @@ impl Process {
             .config
             .live_quantum_execution
             .as_mut()
-            .map(|processor| processor.measure(qubits));
+            .map(
+                |processor| match self.forced_measurements.get(&measure_index) {
+                    Some(outcome) => processor.measure_forced(qubits, *outcome),
+                    None => processor.measure(qubits),
+                },
+            );
+
+        if let Some(result) = result {
+            if let Some(trace) = self.trace.as_mut() {
+                trace.set_measurement_result(result);
+            }
+            self.subscribers.emit(|| ExecutionEvent::Measured {
+                index: measure_index,
+                qubits: qubits.to_vec(),
+                result,
+            });
+        }
 
         self.measurements.push(Measurement {
             qubits: qubits.to_vec(),
             result,
         });
 
-        self.instructions.push(Instruction::Measure {
+        self.push_instruction(Instruction::Measure {
             qubits: qubits.to_vec(),
             output: measure_index,
         });
 
+        self.stream_chunk()?;
+        self.assert_forced_measurements()?;
+
         Ok(measure_index)
     }
 
+    /// Forces the outcome of a measurement
+    ///
+    /// The measurement with the given index, the value returned by [`Process::measure`],
+    /// collapses to `outcome` instead of a random result, so a run can be replayed
+    /// exactly. The index may refer to a measurement that is not added yet. The bits of
+    /// `outcome` follow [`Configuration::bit_order`], as the result of the measurement.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use ket::error::KetError;
+    /// # use ket::{Configuration, Process};
+    /// #
+    /// # fn main() -> Result<(), KetError> {
+    /// # let mut process = Process::new(Configuration::new(2));
+    /// let qubit = process.allocate_qubit()?;
+    /// process.force_measurement(0, 1)?;
+    /// let measurement = process.measure(&[qubit])?;
+    /// # assert_eq!(measurement, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution or if the measurement was
+    /// already performed by a live execution. The measurement fails with
+    /// [`KetError::ForcedMeasurementFailed`] if the executor does not return the forced
+    /// outcome, either because it cannot force outcomes or because the outcome has zero
+    /// probability.
+    pub fn force_measurement(&mut self, index: usize, outcome: u64) -> Result<()> {
+        self.record(|| TraceCall::ForceMeasurement { index, outcome });
+        self.assert_not_ready_for_execution()?;
+        if self
+            .measurements
+            .get(index)
+            .is_some_and(|measurement| measurement.result.is_some())
+        {
+            return Err(KetError::ForcedMeasurementFailed);
+        }
+        self.forced_measurements.insert(index, outcome);
+        Ok(())
+    }
+
+    /// Forces the outcomes of the first measurements from a recorded tape
+    ///
+    /// Use [`Process::measurement_tape`] to record the outcomes of a run, and replay
+    /// them in a new process with this function, see [`Process::force_measurement`].
+    pub fn replay_measurements(&mut self, tape: &[u64]) -> Result<()> {
+        for (index, outcome) in tape.iter().enumerate() {
+            self.force_measurement(index, *outcome)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the outcomes of all measurements, or `None` if some are not available
+    pub fn measurement_tape(&self) -> Option<Vec<u64>> {
+        self.measurements
+            .iter()
+            .map(|measurement| measurement.result)
+            .collect()
+    }
+
+    /// Returns an error if a measurement result differs from its forced outcome
+    fn assert_forced_measurements(&self) -> Result<()> {
+        let mismatch = self.forced_measurements.iter().any(|(index, outcome)| {
+            self.measurements
+                .get(*index)
+                .and_then(|measurement| measurement.result)
+                .is_some_and(|result| result != *outcome)
+        });
+        if mismatch {
+            Err(KetError::ForcedMeasurementFailed)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Calculates the expected values of a Pauli Hamiltonian
     ///
     /// This function calculates the expected values of a Pauli Hamiltonian. It updates
@@ -480,6 +1120,9 @@ impl Process {
     /// process configuration. Additionally, it verifies whether the qubits involved in the
     /// Hamiltonian are allocated.
     pub fn exp_values(&mut self, hamiltonian: PauliHamiltonian) -> Result<usize> {
+        self.record(|| TraceCall::ExpValue {
+            hamiltonian: hamiltonian.clone(),
+        });
         self.assert_not_adj()?;
         self.assert_not_ready_for_execution()?;
 
@@ -499,18 +1142,24 @@ impl Process {
             .as_mut()
             .map(|processor| processor.exp_value(&hamiltonian));
 
+        if let Some(result) = result {
+            self.subscribers
+                .emit(|| ExecutionEvent::ExpValueCalculated { index, result });
+        }
+
         self.exp_values.push(ExpValue {
             hamiltonian: hamiltonian.clone(),
             result,
         });
 
-        self.instructions.push(Instruction::ExpValue {
+        self.push_instruction(Instruction::ExpValue {
             hamiltonian,
             output: index,
         });
 
+        self.stream_chunk()?;
         if !self.config.continue_after_exp_value {
-            self.prepare_for_execution()?;
+            self.untraced(Self::prepare_for_execution)?;
         }
 
         Ok(index)
@@ -520,7 +1169,8 @@ impl Process {
     ///
     /// This function performs sampling on the specified qubits with a specified number of shots.
     /// It updates the internal state of the process, records sampling instructions, and
-    /// returns the index of the sample result.
+    /// returns the index of the sample result. The bits of the outcomes follow
+    /// [`Configuration::bit_order`].
     ///
     /// # Examples
     ///
@@ -546,6 +1196,16 @@ impl Process {
     /// process configuration. Additionally, it verifies whether the qubits involved in the
     /// sampling are allocated.
     pub fn sample(&mut self, qubits: &[usize], shots: u64) -> Result<usize> {
+        let qubits = self.config.bit_order.arrange(qubits);
+        self.sample_arranged(&qubits, shots)
+    }
+
+    /// Samples the qubits, given from the most to the least significant bit
+    pub(crate) fn sample_arranged(&mut self, qubits: &[usize], shots: u64) -> Result<usize> {
+        self.record(|| TraceCall::Sample {
+            qubits: qubits.to_vec(),
+            shots,
+        });
         self.assert_not_adj()?;
         self.assert_not_ready_for_execution()?;
 
@@ -565,29 +1225,113 @@ impl Process {
             .as_mut()
             .map(|processor| processor.sample(qubits, shots));
 
+        if result.is_some() {
+            self.subscribers.emit(|| ExecutionEvent::Sampled { index });
+        }
+
         self.samples.push(Sample {
             qubits: qubits.to_vec(),
             shots,
             result,
         });
 
-        self.instructions.push(Instruction::Sample {
+        self.push_instruction(Instruction::Sample {
             qubits: qubits.to_vec(),
             shots,
             output: index,
         });
 
+        self.stream_chunk()?;
         if !self.config.continue_after_exp_value {
-            self.prepare_for_execution()?;
+            self.untraced(Self::prepare_for_execution)?;
         }
 
         Ok(index)
     }
 
+    /// Samples qubits from the current state without collapsing it
+    ///
+    /// Draws `shots` bitstrings of the qubits from the live quantum state and returns
+    /// them as `(states, counts)`, as in [`Process::sample`], but the state is left
+    /// untouched and nothing is added to the circuit. This lets a variational loop
+    /// monitor its convergence mid-circuit without perturbing the following gates.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is not in live mode, if it is in an inverse
+    /// scope, if sampling is not allowed, if a qubit is not allocated, or if the live
+    /// executor cannot sample without collapsing the state.
+    pub fn sample_without_collapse(
+        &mut self,
+        qubits: &[usize],
+        shots: u64,
+    ) -> Result<(Vec<u64>, Vec<u64>)> {
+        self.assert_not_adj()?;
+        self.assert_not_ready_for_execution()?;
+
+        if !self.config.allow_sample {
+            return Err(KetError::SampleNotAllowed);
+        }
+
+        for qubit in qubits {
+            self.assert_qubit_allocated(*qubit)?;
+        }
+
+        let qubits = self.config.bit_order.arrange(qubits);
+        self.config
+            .live_quantum_execution
+            .as_mut()
+            .ok_or(KetError::LiveExecutionRequired)?
+            .sample_without_collapse(&qubits, shots)
+            .ok_or(KetError::SampleWithoutCollapseNotSupported)
+    }
+
+    /// Samples a Pauli Hamiltonian using qubit-wise commuting measurement groups
+    ///
+    /// The Hamiltonian terms are grouped so that every group can be measured with a single
+    /// basis rotation, and the `shots` budget is distributed among the groups. For each
+    /// group, this function applies the basis rotation, samples the group qubits, and
+    /// reverts the rotation. Use [`GroupedSample::estimate`] after the execution to
+    /// obtain the expected value and its variance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as [`Process::sample`] and
+    /// [`Process::apply_gate`].
+    pub fn sample_hamiltonian(
+        &mut self,
+        hamiltonian: &PauliHamiltonian,
+        shots: u64,
+    ) -> Result<GroupedSample> {
+        sample_hamiltonian(self, hamiltonian, shots)
+    }
+
+    /// Schedules a classical shadow of the specified qubits
+    ///
+    /// For each of the `snapshots`, every qubit is rotated by a random single-qubit
+    /// Clifford gate before measurement. Snapshots that share the same rotations are
+    /// sampled together. The `seed` makes the choice of rotations reproducible. Use
+    /// [`ClassicalShadow::expectation`] after the execution to reconstruct the expected
+    /// value of local observables.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if more than 64 qubits are given, and under the same conditions
+    /// as [`Process::sample`] and [`Process::apply_gate`].
+    pub fn classical_shadow(
+        &mut self,
+        qubits: &[usize],
+        snapshots: u64,
+        seed: u64,
+    ) -> Result<ClassicalShadow> {
+        classical_shadow(self, qubits, snapshots, seed)
+    }
+
     /// Dumps the state of specified qubits
     ///
     /// This function dumps the state of the specified qubits. It updates the internal state
     /// of the process, records dump instructions, and returns the index of the dump result.
+    /// The bits of the basis states follow [`Configuration::bit_order`].
     ///
     /// # Examples
     ///
@@ -612,42 +1356,189 @@ impl Process {
     /// process configuration. Additionally, it verifies whether the qubits involved in the
     /// dump operation are allocated.
     pub fn dump(&mut self, qubits: &[usize]) -> Result<usize> {
-        self.assert_not_adj()?;
-        self.assert_not_ready_for_execution()?;
-
-        if !self.config.allow_dump {
-            return Err(KetError::DumpNotAllowed);
-        }
-
-        for qubit in qubits {
-            self.assert_qubit_allocated(*qubit)?;
-        }
+        let qubits = self.config.bit_order.arrange(qubits);
+        self.dump_arranged(&qubits)
+    }
 
-        let dump_index = self.dumps.len();
+    /// Dumps the `k` basis states of largest probability of the specified qubits
+    ///
+    /// This function works like [`Process::dump`], but the result keeps only the `k`
+    /// basis states of largest probability, from the most to the least probable, so the
+    /// dominant outcomes of a large state can be inspected without transferring every
+    /// amplitude. In live mode, the simulator chooses the states with a parallel partial
+    /// selection.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use ket::error::KetError;
+    /// # use ket::{Configuration, Process};
+    /// #
+    /// # fn main() -> Result<(), KetError> {
+    /// # let mut process = Process::new(Configuration::new(2));
+    /// let qubit_a = process.allocate_qubit()?;
+    /// let qubit_b = process.allocate_qubit()?;
+    /// let dump_index = process.dump_top_k(&[qubit_a, qubit_b], 1)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as [`Process::dump`].
+    pub fn dump_top_k(&mut self, qubits: &[usize], k: usize) -> Result<usize> {
+        let qubits = self.config.bit_order.arrange(qubits);
+        self.dump_truncated(&qubits, Some(k))
+    }
+
+    /// Dumps the state of the qubits, given from the most to the least significant bit
+    pub(crate) fn dump_arranged(&mut self, qubits: &[usize]) -> Result<usize> {
+        self.dump_truncated(qubits, None)
+    }
+
+    /// Dumps the state of the qubits, given from the most to the least significant bit,
+    /// keeping the `top_k` basis states of largest probability if given
+    pub(crate) fn dump_truncated(
+        &mut self,
+        qubits: &[usize],
+        top_k: Option<usize>,
+    ) -> Result<usize> {
+        self.record(|| match top_k {
+            Some(k) => TraceCall::DumpTopK {
+                qubits: qubits.to_vec(),
+                k,
+            },
+            None => TraceCall::Dump {
+                qubits: qubits.to_vec(),
+            },
+        });
+        self.assert_not_adj()?;
+        self.assert_not_ready_for_execution()?;
+
+        if !self.config.allow_dump {
+            return Err(KetError::DumpNotAllowed);
+        }
+
+        for qubit in qubits {
+            self.assert_qubit_allocated(*qubit)?;
+        }
+
+        let dump_index = self.dumps.len();
 
         let result = self
             .config
             .live_quantum_execution
             .as_mut()
-            .map(|processor| processor.dump(qubits));
+            .map(|processor| match top_k {
+                Some(k) => processor.dump_top_k(qubits, k),
+                None => processor.dump(qubits),
+            });
 
         self.dumps.push(Dump {
             qubits: qubits.to_vec(),
-            result,
+            result: None,
+            compressed: None,
+            top_k,
         });
 
-        self.instructions.push(Instruction::Dump {
+        if let Some(result) = result {
+            self.store_dump(dump_index, result);
+            self.subscribers
+                .emit(|| ExecutionEvent::Dumped { index: dump_index });
+        }
+
+        self.push_instruction(Instruction::Dump {
             qubits: qubits.to_vec(),
             output: dump_index,
         });
 
+        self.stream_chunk()?;
         if !self.config.continue_after_dump {
-            self.prepare_for_execution()?;
+            self.untraced(Self::prepare_for_execution)?;
         }
 
         Ok(dump_index)
     }
 
+    /// Schedules the Bloch vector of a qubit
+    ///
+    /// The Bloch vector is computed from the reduced density matrix of the qubit, so this
+    /// function dumps every allocated qubit. In live mode, the result is available
+    /// immediately; otherwise, use [`BlochHandle::get`] after the execution.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error under the same conditions as [`Process::dump`].
+    pub fn bloch_vector(&mut self, qubit: usize) -> Result<BlochHandle> {
+        self.assert_qubit_allocated(qubit)?;
+        let qubits = self.allocated_qubits();
+        let position = qubits
+            .iter()
+            .position(|allocated| *allocated == qubit)
+            .unwrap();
+        let dump = self.dump_arranged(&qubits)?;
+        Ok(BlochHandle { dump, position })
+    }
+
+    /// Records the Bloch vector of the qubits after each gate
+    ///
+    /// The trajectory of each qubit starts with its current Bloch vector, and a new
+    /// point is added after every gate run by the live executor until the qubit is no
+    /// longer allocated. Use [`Process::get_bloch_trajectory`] to read it. Each point
+    /// requires a dump of all allocated qubits, so this is meant for teaching and
+    /// debugging small programs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is not in live mode, if dumping is not allowed,
+    /// or if a qubit is not allocated.
+    pub fn track_bloch_vector(&mut self, qubits: &[usize]) -> Result<()> {
+        if self.config.live_quantum_execution.is_none() {
+            return Err(KetError::LiveExecutionRequired);
+        }
+        if !self.config.allow_dump {
+            return Err(KetError::DumpNotAllowed);
+        }
+        for qubit in qubits {
+            self.assert_qubit_allocated(*qubit)?;
+        }
+
+        for qubit in qubits {
+            if self.bloch_trajectories.get(*qubit).is_none() {
+                self.bloch_trajectories
+                    .trajectories
+                    .push((*qubit, Vec::new()));
+            }
+        }
+        self.record_bloch_trajectories();
+
+        Ok(())
+    }
+
+    /// Returns the Bloch vectors recorded for a tracked qubit
+    pub fn get_bloch_trajectory(&self, qubit: usize) -> Option<&[BlochVector]> {
+        self.bloch_trajectories.get(qubit)
+    }
+
+    /// Returns the allocated qubits in order of allocation
+    pub(crate) fn allocated_qubits(&self) -> Vec<usize> {
+        (0..self.qubits.len())
+            .filter(|qubit| self.qubits[*qubit].allocated)
+            .collect()
+    }
+
+    /// Appends the current Bloch vector of the tracked qubits to their trajectories
+    fn record_bloch_trajectories(&mut self) {
+        if self.bloch_trajectories.is_empty() {
+            return;
+        }
+        let qubits = self.allocated_qubits();
+        if let Some(processor) = self.config.live_quantum_execution.as_mut() {
+            let data = processor.dump(&qubits);
+            self.bloch_trajectories.record(&qubits, &data);
+        }
+    }
+
     /// Pushes control qubits onto the control stack
     ///
     /// This function pushes control qubits onto the control stack. It updates the internal state
@@ -674,6 +1565,9 @@ impl Process {
     /// Returns an error if the process is ready for execution or if the control qubits
     /// are allocated more than once during a control operation.
     pub fn ctrl_push(&mut self, qubits: &[usize]) -> Result<()> {
+        self.record(|| TraceCall::CtrlPush {
+            qubits: qubits.to_vec(),
+        });
         self.assert_not_ready_for_execution()?;
         let qubits = qubits.to_vec();
         for ctrl_list in self.ctrl_stack.iter() {
@@ -685,6 +1579,7 @@ impl Process {
             }
         }
 
+        self.structure.begin_controlled(&qubits);
         self.ctrl_stack.push(qubits);
 
         self.ctrl_list_is_up_to_date = false;
@@ -699,24 +1594,151 @@ impl Process {
     /// Returns an error if the process is ready for execution or if there are no control
     /// configurations on the control stack to pop.
     pub fn ctrl_pop(&mut self) -> Result<()> {
+        self.record(|| TraceCall::CtrlPop);
         self.assert_not_ready_for_execution()?;
         self.ctrl_list_is_up_to_date = false;
 
         match self.ctrl_stack.pop() {
-            Some(_) => Ok(()),
+            Some(_) => {
+                self.structure.end_controlled();
+                Ok(())
+            }
             None => Err(KetError::NoCtrl),
         }
     }
 
+    /// Pushes a condition on measurement results to the condition stack
+    ///
+    /// Gates applied until the matching [`Process::cond_pop`] only take effect if every
+    /// measurement in `expected`, given by its index, has the expected result. In live
+    /// mode, the results are known and the gates are skipped if the condition does not
+    /// hold. Otherwise, the gates are added as
+    /// [`Instruction::ConditionalGate`] and the executor evaluates the condition.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use ket::error::KetError;
+    /// # use ket::{Configuration, Instruction, Process, QuantumGate};
+    /// #
+    /// # fn main() -> Result<(), KetError> {
+    /// # let mut process = Process::new(Configuration::new(2));
+    /// let qubit_a = process.allocate_qubit()?;
+    /// let qubit_b = process.allocate_qubit()?;
+    /// process.apply_gate(QuantumGate::Hadamard, qubit_a)?;
+    /// let measurement = process.measure(&[qubit_a])?;
+    ///
+    /// process.cond_push(&[(measurement, 1)])?;
+    /// process.apply_gate(QuantumGate::PauliX, qubit_b)?;
+    /// process.cond_pop()?;
+    ///
+    /// assert!(matches!(
+    ///     process.instructions().last(),
+    ///     Some(Instruction::ConditionalGate { .. })
+    /// ));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution, or
+    /// [`KetError::InvalidCondition`] if a measurement does not exist or within an
+    /// inverse scope.
+    pub fn cond_push(&mut self, expected: &[(usize, u64)]) -> Result<()> {
+        self.record(|| TraceCall::CondPush {
+            expected: expected.to_vec(),
+        });
+        self.assert_not_ready_for_execution()?;
+        if !self.adj_stack.is_empty() {
+            return Err(KetError::InvalidCondition);
+        }
+
+        let mut qubits = Vec::new();
+        for (measurement, _) in expected {
+            match self.measurements.get(*measurement) {
+                Some(measurement) => qubits.extend(measurement.qubits.iter().copied()),
+                None => return Err(KetError::InvalidCondition),
+            }
+        }
+        self.cond_stack.push(Condition {
+            expected: expected.to_vec(),
+            qubits,
+        });
+        Ok(())
+    }
+
+    /// Pops the last added condition from the condition stack
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution or if there are no
+    /// conditions on the condition stack to pop.
+    pub fn cond_pop(&mut self) -> Result<()> {
+        self.record(|| TraceCall::CondPop);
+        self.assert_not_ready_for_execution()?;
+        match self.cond_stack.pop() {
+            Some(_) => Ok(()),
+            None => Err(KetError::NoCond),
+        }
+    }
+
+    /// Runs a classical computation on measurement results and applies the gates it
+    /// returns
+    ///
+    /// In live mode, the measurements are executed immediately, so `step` receives
+    /// their results, in the order of `measurements`, and the gates it returns are
+    /// applied as with [`Process::apply_gate`], within the current control scope. This
+    /// expresses adaptive algorithms, like iterative phase estimation, where the next
+    /// rotation depends on the previous outcomes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::LiveExecutionRequired`] if the process is not in live mode,
+    /// [`KetError::InvalidCondition`] if a measurement does not exist or within an
+    /// inverse scope, and the errors of [`Process::apply_gate`].
+    pub fn classical_step<F>(&mut self, measurements: &[usize], step: F) -> Result<()>
+    where
+        F: FnOnce(&[u64]) -> Vec<(QuantumGate, usize)>,
+    {
+        if self.config.live_quantum_execution.is_none() {
+            return Err(KetError::LiveExecutionRequired);
+        }
+        if !self.adj_stack.is_empty() {
+            return Err(KetError::InvalidCondition);
+        }
+
+        let results = measurements
+            .iter()
+            .map(|index| {
+                self.measurements
+                    .get(*index)
+                    .and_then(|measurement| measurement.result)
+                    .ok_or(KetError::InvalidCondition)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for (gate, target) in step(&results) {
+            self.apply_gate(gate, target)?;
+        }
+        Ok(())
+    }
+
     /// Begins an adjoint block, where gates are inverted upon insertion
     ///
     /// # Errors
     ///
-    /// Returns an error if the process is ready for execution.
+    /// Returns an error if the process is ready for execution, or
+    /// [`KetError::InvalidCondition`] within a conditional scope.
     pub fn adj_begin(&mut self) -> Result<()> {
+        self.record(|| TraceCall::AdjBegin);
         self.assert_not_ready_for_execution()?;
+        if !self.cond_stack.is_empty() {
+            return Err(KetError::InvalidCondition);
+        }
 
         self.adj_stack.push(Vec::new());
+        self.structure.begin_adjoint();
         Ok(())
     }
 
@@ -726,11 +1748,13 @@ impl Process {
     ///
     /// Returns an error if the process is ready for execution or if there is no adjoint block to end.
     pub fn adj_end(&mut self) -> Result<()> {
+        self.record(|| TraceCall::AdjEnd);
         self.assert_not_ready_for_execution()?;
 
         if self.adj_stack.is_empty() {
             return Err(KetError::NoAdj);
         }
+        self.structure.end_adjoint();
 
         if self.adj_stack.len() == 1 {
             while let Some(instruction) = self.adj_stack.last_mut().unwrap().pop() {
@@ -741,16 +1765,19 @@ impl Process {
                             target,
                             control,
                         } => {
-                            info!(
-                                "live execution: gate={:?}, target={}, control={:?}",
-                                gate, target, control
-                            );
-                            processor.gate(gate, *target, control)
+                            info!(?gate, target, ?control, "live execution");
+                            processor.gate(gate, *target, control);
+                            self.subscribers.emit(|| ExecutionEvent::GateApplied {
+                                gate: gate.clone(),
+                                target: *target,
+                                control: control.clone(),
+                            });
                         }
                         _ => panic!(),
                     }
+                    self.record_bloch_trajectories();
                 }
-                self.instructions.push(instruction);
+                self.append(instruction);
             }
             self.adj_stack.pop();
         } else {
@@ -760,18 +1787,40 @@ impl Process {
             }
         }
 
-        Ok(())
+        self.stream_chunk()
     }
 
     /// Prepares the process for quantum execution
     pub fn prepare_for_execution(&mut self) -> Result<()> {
+        self.record(|| TraceCall::PrepareForExecution);
+        self.untraced(Self::execute)
+    }
 
+    /// Executes the process in batch mode, or marks it as ready in live mode
+    fn execute(&mut self) -> Result<()> {
         if let ProcessStatus::Building = self.metadata.status {
+            let _span = info_span!(
+                "execute",
+                instructions = self.instructions.len(),
+                qubits = self.qubits.len(),
+                measurements = self.measurements.len(),
+                streamed = self.streamed_len
+            )
+            .entered();
+            if self.streamed_len > 0 {
+                // The remaining instructions are the last chunk.
+                self.submit_chunk()?;
+                self.metadata.status = ProcessStatus::Terminated;
+                return Ok(());
+            }
             if self.config.optimize {
-                    self.optimize().unwrap();
+                self.optimize()?;
             }
             let mut result = None;
             if let Some(processor) = self.config.batch_execution.as_mut() {
+                if !self.forced_measurements.is_empty() {
+                    processor.force_measurements(&self.forced_measurements);
+                }
                 processor.submit_execution(&self.instructions);
                 self.metadata.status = ProcessStatus::Running;
                 result = Some(processor.get_result());
@@ -787,6 +1836,103 @@ impl Process {
         Ok(())
     }
 
+    /// Executes the instructions as a chunk if they reached
+    /// [`Configuration::chunk_size`]
+    fn stream_chunk(&mut self) -> Result<()> {
+        let full = self
+            .config
+            .chunk_size
+            .is_some_and(|chunk_size| self.instructions.len() >= chunk_size);
+        if full && self.adj_stack.is_empty() && self.config.batch_execution.is_some() {
+            self.submit_chunk()?;
+        }
+        Ok(())
+    }
+
+    /// Executes the instructions as the next chunk of a streamed execution and removes
+    /// them, see [`BatchExecution::submit_chunk`](crate::BatchExecution::submit_chunk)
+    ///
+    /// Chunks are disabled if the executor does not support them.
+    fn submit_chunk(&mut self) -> Result<()> {
+        let _span = info_span!(
+            "submit_chunk",
+            instructions = self.instructions.len(),
+            qubits = self.qubits.len(),
+            streamed = self.streamed_len
+        )
+        .entered();
+        let processor = self.config.batch_execution.as_mut().unwrap();
+        if !self.forced_measurements.is_empty() {
+            processor.force_measurements(&self.forced_measurements);
+        }
+        let Some(results) = processor.submit_chunk(&self.instructions) else {
+            info!("batch execution does not support chunks, executing the whole process");
+            self.config.chunk_size = None;
+            return Ok(());
+        };
+
+        let chunk = std::mem::take(&mut self.instructions);
+        self.streamed_len += chunk.len();
+        self.optimized_len = 0;
+        self.frozen_len = 0;
+        self.opaque.clear();
+        if let Some((start, _)) = self.opaque_scope.as_mut() {
+            *start = 0;
+        }
+        for budget in &mut self.budgets {
+            budget.range = 0..0;
+        }
+        if let Some(budget) = self.budget_scope.as_mut() {
+            budget.range = 0..0;
+        }
+        self.structure.reset(&[]);
+        self.provenance.clear();
+
+        let mut measurements = results.measurements.into_iter();
+        let mut exp_values = results.exp_values.into_iter();
+        let mut samples = results.samples.into_iter();
+        let mut dumps = results.dumps.into_iter();
+        for instruction in &chunk {
+            match instruction {
+                Instruction::Measure { output, .. } => {
+                    let result = measurements.next().ok_or(KetError::UnexpectedResultData)?;
+                    self.measurements[*output].result = Some(result);
+                }
+                Instruction::ExpValue { output, .. } => {
+                    let result = exp_values.next().ok_or(KetError::UnexpectedResultData)?;
+                    self.exp_values[*output].result = Some(result);
+                }
+                Instruction::Sample { output, .. } => {
+                    let result = samples.next().ok_or(KetError::UnexpectedResultData)?;
+                    self.samples[*output].result = Some(result);
+                }
+                Instruction::Dump { output, .. } => {
+                    let result = dumps.next().ok_or(KetError::UnexpectedResultData)?;
+                    self.store_dump(*output, result);
+                }
+                _ => {}
+            }
+        }
+        if measurements.next().is_some()
+            || exp_values.next().is_some()
+            || samples.next().is_some()
+            || dumps.next().is_some()
+        {
+            return Err(KetError::UnexpectedResultData);
+        }
+
+        if let Some(time) = results.execution_time {
+            *self.metadata.execution_time.get_or_insert(0.0) += time;
+        }
+        self.assert_forced_measurements()
+    }
+
+    /// Returns the number of instructions already executed and removed from the
+    /// process, see [`Configuration::chunk_size`]
+    pub fn streamed_len(&self) -> usize {
+        self.streamed_len
+    }
+
     /// Returns the status of the specified qubit
     pub fn get_qubit_status(&self, qubit: usize) -> &QubitStatus {
         &self.qubits[qubit]
@@ -797,6 +1943,53 @@ impl Process {
         &self.measurements[index]
     }
 
+    /// Returns the result of a measurement as an unsigned integer.
+    ///
+    /// The first qubit given to [`Process::measure`] is the most significant bit of the
+    /// integer if `order` is [`BitOrder::MsbFirst`], or the least significant if it is
+    /// [`BitOrder::LsbFirst`], regardless of [`Configuration::bit_order`]. Returns `None`
+    /// if the result is not yet available.
+    pub fn get_measurement_as_u64(&self, index: usize, order: BitOrder) -> Option<u64> {
+        let measurement = &self.measurements[index];
+        let result = measurement.result?;
+        let len = measurement.qubits.len();
+        Some(if order == self.config.bit_order || len == 0 {
+            result
+        } else {
+            result.reverse_bits() >> (64 - len)
+        })
+    }
+
+    /// Returns the result of a measurement as a two's complement signed integer, with
+    /// one bit per measured qubit, ordered as in [`Process::get_measurement_as_u64`].
+    pub fn get_measurement_as_i64(&self, index: usize, order: BitOrder) -> Option<i64> {
+        let value = self.get_measurement_as_u64(index, order)?;
+        let len = self.measurements[index].qubits.len();
+        Some(if len == 0 {
+            0
+        } else {
+            ((value << (64 - len)) as i64) >> (64 - len)
+        })
+    }
+
+    /// Returns the result of a measurement as a fixed-point number with
+    /// `fraction_bits` bits after the binary point, ordered as in
+    /// [`Process::get_measurement_as_u64`], and in two's complement if `signed`.
+    pub fn get_measurement_as_fixed(
+        &self,
+        index: usize,
+        order: BitOrder,
+        fraction_bits: u32,
+        signed: bool,
+    ) -> Option<f64> {
+        let value = if signed {
+            self.get_measurement_as_i64(index, order)? as f64
+        } else {
+            self.get_measurement_as_u64(index, order)? as f64
+        };
+        Some(value / 2f64.powi(fraction_bits as i32))
+    }
+
     /// Returns the expected value result at the specified index
     pub fn get_exp_value(&self, index: usize) -> &ExpValue {
         &self.exp_values[index]
@@ -812,11 +2005,115 @@ impl Process {
         &self.dumps[index]
     }
 
+    /// Returns the decompressed dump result at the specified index
+    ///
+    /// Unlike [`Process::get_dump`], this function also recovers dumps stored with
+    /// [`DumpCompression::Delta`] or [`DumpCompression::Quantized`]. Returns `None` if
+    /// the result is not yet available.
+    pub fn get_dump_data(&self, index: usize) -> Option<DumpData> {
+        let dump = &self.dumps[index];
+        if let Some(result) = &dump.result {
+            return Some(result.clone());
+        }
+
+        let compressed = dump.compressed.as_ref()?;
+        let reference = match compressed.reference() {
+            Some(reference) => Some(self.get_dump_data(reference)?),
+            None => None,
+        };
+        Some(compressed.decompress(reference.as_ref()))
+    }
+
+    /// Returns the dump result at the specified index, decompressing it into
+    /// [`Dump::result`] if it is stored compressed, see [`Process::get_dump_data`]
+    ///
+    /// Returns `None` if the result is not yet available.
+    pub fn decompress_dump(&mut self, index: usize) -> Option<&DumpData> {
+        if self.dumps[index].result.is_none() {
+            self.dumps[index].result = Some(self.get_dump_data(index)?);
+        }
+        self.dumps[index].result.as_ref()
+    }
+
+    /// Sets the compression mode for the next dump results
+    ///
+    /// Dumps that already hold a result are not affected. Use [`Process::get_dump_data`]
+    /// to read the compressed dumps, as [`Dump::result`] is left empty for them. The C API
+    /// dump accessors decompress them, see [`Process::decompress_dump`].
+    pub fn set_dump_compression(&mut self, compression: DumpCompression) {
+        self.dump_compression = compression;
+    }
+
+    /// Subscribes to the live execution events
+    ///
+    /// The returned receiver gets an [`ExecutionEvent`] for every instruction run by
+    /// the live executor from now on. No events are emitted in batch mode.
+    pub fn subscribe(&mut self) -> std::sync::mpsc::Receiver<ExecutionEvent> {
+        self.subscribers.subscribe()
+    }
+
+    /// Stores a dump result, compressing it if required
+    fn store_dump(&mut self, index: usize, data: DumpData) {
+        let data = match self.dumps[index].top_k {
+            Some(k) => data.top_k(k),
+            None => data,
+        };
+        let previous = (0..index).rev().find(|previous| {
+            self.dumps[*previous].qubits == self.dumps[index].qubits
+                && self.dumps[*previous].compressed.is_some()
+        });
+        let previous_data = previous.and_then(|previous| {
+            self.get_dump_data(previous)
+                .map(|previous_data| (previous, previous_data))
+        });
+
+        let compressed = CompressedDump::compress(
+            &data,
+            self.dump_compression,
+            previous_data.as_ref().map(|(previous, previous_data)| {
+                (
+                    *previous,
+                    self.dumps[*previous].compressed.as_ref().unwrap(),
+                    previous_data,
+                )
+            }),
+        );
+
+        let dump = &mut self.dumps[index];
+        match compressed {
+            Some(compressed) => dump.compressed = Some(compressed),
+            None => dump.result = Some(data),
+        }
+    }
+
     /// Return process metadata
     pub fn get_metadata(&self) -> &Metadata {
         &self.metadata
     }
 
+    /// Attaches custom data to the process metadata under `key`, returning the previous
+    /// value.
+    ///
+    /// Extensions let other tools, like schedulers or provenance trackers, carry their
+    /// own data with the metadata, which keeps them through its JSON serialization.
+    pub fn set_metadata_extension(
+        &mut self,
+        key: impl Into<String>,
+        value: serde_json::Value,
+    ) -> Option<serde_json::Value> {
+        self.metadata.extensions.insert(key.into(), value)
+    }
+
+    /// Returns the custom data attached to the process metadata under `key`.
+    pub fn get_metadata_extension(&self, key: &str) -> Option<&serde_json::Value> {
+        self.metadata.extensions.get(key)
+    }
+
+    /// Removes the custom data attached to the process metadata under `key`.
+    pub fn remove_metadata_extension(&mut self, key: &str) -> Option<serde_json::Value> {
+        self.metadata.extensions.remove(key)
+    }
+
     /// Set the quantum execution result
     ///
     /// This function allow to manually set the quantum execution result for the process.
@@ -858,15 +2155,131 @@ impl Process {
         results
             .dumps
             .drain(..)
-            .zip(self.dumps.iter_mut())
-            .for_each(|(result, dump)| {
-                dump.result = Some(result);
-            });
+            .enumerate()
+            .for_each(|(index, result)| self.store_dump(index, result));
 
         self.metadata.execution_time = results.execution_time;
 
         self.metadata.status = ProcessStatus::Terminated;
-        Ok(())
+
+        self.assert_forced_measurements()
+    }
+
+    /// Return the quantum instructions of the process
+    ///
+    /// Instructions already executed as chunks are not included, see
+    /// [`Configuration::chunk_size`].
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Return a stable hash of the quantum instructions
+    ///
+    /// The hash is the same for processes that are equal, see [`hashing`](crate::hashing).
+    pub fn circuit_hash(&self) -> CircuitHash {
+        CircuitHash::new(&self.instructions)
+    }
+
+    /// Return a map from the qubits of this process to those of `other` under which
+    /// both circuits are equivalent
+    ///
+    /// See [`equivalence`](crate::equivalence).
+    pub fn find_relabeling(&self, other: &Process) -> Option<BTreeMap<usize, usize>> {
+        crate::equivalence::find_relabeling(&self.instructions, &other.instructions)
+    }
+
+    /// Set the source location of the instructions added afterwards
+    ///
+    /// The front end calls it with the file, line, and function of the code that builds
+    /// the circuit, or with `None` for instructions without location, see
+    /// [`provenance`](crate::provenance).
+    pub fn set_source_location(&mut self, location: Option<SourceLocation>) {
+        self.record(|| TraceCall::SetSourceLocation {
+            location: location.clone(),
+        });
+        self.provenance.set(location, &self.instructions);
+    }
+
+    /// Return the source location of the instruction at `index` of
+    /// [`Process::instructions`]
+    pub fn source_location(&self, index: usize) -> Option<&SourceLocation> {
+        self.provenance.location(index)
+    }
+
+    /// Return the resources of the instructions of each source location, in the order
+    /// the locations first appear in the circuit
+    pub fn source_resources(&self) -> Vec<(SourceLocation, ResourceReport)> {
+        let mut groups: Vec<(&SourceLocation, Vec<Instruction>)> = Vec::new();
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let Some(location) = self.provenance.location(index) else {
+                continue;
+            };
+            match groups.iter_mut().find(|(other, _)| *other == location) {
+                Some((_, instructions)) => instructions.push(instruction.clone()),
+                None => groups.push((location, vec![instruction.clone()])),
+            }
+        }
+        groups
+            .into_iter()
+            .map(|(location, instructions)| {
+                (
+                    location.clone(),
+                    ResourceReport::from_instructions(&instructions),
+                )
+            })
+            .collect()
+    }
+
+    /// Return the circuit as a tree of control and inverse scopes
+    ///
+    /// Each scope opened with [`Process::ctrl_push`] or [`Process::adj_begin`] is a single
+    /// node, see [`structure`](crate::structure). If a scope is still open, or a pass or
+    /// optimization rewrote the instructions, the view is flat, with one node per
+    /// instruction.
+    pub fn structure(&self) -> Vec<Node> {
+        self.structure.nodes(&self.instructions)
+    }
+
+    /// Applies a template with its placeholder `i` bound to `qubits[i]`
+    ///
+    /// The gates of the template receive the control qubits of the opened control
+    /// scopes and are inverted in an inverse scope, as if the recorded circuit were
+    /// written here, see [`template`](crate::template).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidTemplate`] if `qubits` does not have one distinct qubit
+    /// per placeholder, and the errors of the gate application. On error, the gates
+    /// applied so far are not removed.
+    pub fn apply_template(&mut self, template: &Template, qubits: &[usize]) -> Result<()> {
+        apply_template(self, template, qubits)
+    }
+
+    /// Applies `template` raised to the power `2^k`, controlled by `control`
+    ///
+    /// This is the controlled `U^(2^k)` of phase estimation. The template is repeated
+    /// `2^k` times; a simulator that fuses subcircuits, such as KBW, detects the
+    /// repetition and applies the matrix power in a single pass.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidTemplate`] if `k` is 64 or more, if a control qubit is
+    /// one of `qubits`, and the errors of [`Process::apply_template`].
+    pub fn apply_power(
+        &mut self,
+        template: &Template,
+        qubits: &[usize],
+        k: u32,
+        control: &[usize],
+    ) -> Result<()> {
+        apply_power(self, template, qubits, k, control)
+    }
+
+    /// Return the hierarchical view in JSON
+    ///
+    /// This functions is used in the C API for get the structure out of the process.
+    pub(crate) fn structure_json(&self) -> String {
+        serde_json::to_string(&self.structure()).unwrap()
     }
 
     /// Return the instructions in JSON
@@ -883,18 +2296,574 @@ impl Process {
         serde_json::to_string(&self.metadata).unwrap()
     }
 
+    /// Return the source location of each instruction in JSON
+    ///
+    /// This functions is used in the C API for get the provenance out of the process.
+    pub(crate) fn provenance_json(&self) -> String {
+        serde_json::to_string(&self.provenance.locations(self.instructions.len())).unwrap()
+    }
+
     /// Return the quantum circuit in OpenQASM v2 format
     ///
     /// The `measurements` parameter determines if the generated code will include measurement instructions
+    #[cfg(feature = "qasm")]
     pub fn to_qasmv2(&self, measurements: bool, instruction_set: InstructionSet) -> Result<String> {
         to_qasmv2(self, measurements, instruction_set)
     }
 
-    pub fn from_qasmv2(&mut self, qasm: &str, instruction_set: InstructionSet, ignore_qreg: bool) -> Result<()> {
+    /// Return the bits of the `c` register written by each measurement in the OpenQASM
+    /// v2 export
+    ///
+    /// The final measurements of [`to_qasmv2`](Self::to_qasmv2) take the bits after
+    /// [`CregMap::size`].
+    #[cfg(feature = "qasm")]
+    pub fn creg_map(&self) -> CregMap {
+        CregMap::from_instructions(&self.instructions)
+    }
+
+    /// Return the quantum circuit in OpenQASM v3 format with explicit timing
+    ///
+    /// The instructions are scheduled as soon as possible with the gate durations and
+    /// the crosstalk constraints of the device, see [`qasmv3`](crate::qasmv3).
+    pub fn to_qasmv3_scheduled(
+        &self,
+        coupling_map: &CouplingMap,
+        durations: &GateDurations,
+    ) -> Result<String> {
+        let instructions = multiplexor::lower(&self.instructions);
+        let schedule = timed_schedule(&instructions, coupling_map, durations);
+        to_qasmv3_scheduled(&instructions, &schedule)
+    }
+
+    /// Return the quantum circuit in OpenQASM v2 format with the given formatting options
+    ///
+    /// Use [`QasmFormat::canonical`] for an output that is stable across releases.
+    #[cfg(feature = "qasm")]
+    pub fn to_qasmv2_formatted(
+        &self,
+        measurements: bool,
+        instruction_set: InstructionSet,
+        format: &QasmFormat,
+    ) -> Result<String> {
+        to_qasmv2_formatted(self, measurements, instruction_set, format)
+    }
+
+    #[cfg(feature = "qasm")]
+    pub fn from_qasmv2(
+        &mut self,
+        qasm: &str,
+        instruction_set: InstructionSet,
+        ignore_qreg: bool,
+    ) -> Result<()> {
         from_qasmv2(self, qasm, instruction_set, ignore_qreg)
     }
 
+    /// Imports an OpenQASM v2 circuit with the gates of the table, which replace the
+    /// gates of the instruction set with the same name
+    ///
+    /// See [`GateTable`] for an example.
+    #[cfg(feature = "qasm")]
+    pub fn from_qasmv2_with_gates(
+        &mut self,
+        qasm: &str,
+        instruction_set: InstructionSet,
+        ignore_qreg: bool,
+        gates: &GateTable,
+    ) -> Result<()> {
+        from_qasmv2_with_gates(self, qasm, instruction_set, ignore_qreg, gates)
+    }
+
+    /// Optimizes the quantum circuit with the ZX-calculus
+    ///
+    /// The first call optimizes the whole circuit. Later calls only optimize the
+    /// instructions added since the previous call, together with the last optimized
+    /// gates before them, so optimizing after each appended block stays fast. The
+    /// instructions frozen with [`Process::freeze`] are never optimized again, and the
+    /// opaque blocks of [`Process::opaque_begin`] are never optimized.
+    ///
+    /// The gates between two measurements or dumps are kept as they were if the
+    /// simplified circuit cannot be extracted or has more gates, and the reason is
+    /// recorded in the [`OptimizationReport`](crate::zx::OptimizationReport) of the
+    /// metadata.
+    ///
+    /// A process that already executed chunks, see [`Configuration::chunk_size`], is not
+    /// optimized, as its instructions are no longer the whole circuit. Without the
+    /// `optimizer` feature, the instructions are kept as they are.
     pub fn optimize(&mut self) -> Result<()> {
-        optimize(self)
+        self.record(|| TraceCall::Optimize);
+        if self.streamed_len > 0 {
+            return Ok(());
+        }
+        self.untraced(zx::optimize)
+    }
+
+    /// Reorders the commuting gates into a canonical order
+    ///
+    /// Circuits that only differ in the order of commuting gates, as diagonal gates
+    /// sharing qubits, become the same instructions, with the same
+    /// [`Process::circuit_hash`], see [`canonicalize`](crate::passes::canonicalize).
+    /// Like the passes, it keeps the frozen prefix, the opaque blocks, and the error
+    /// budget sections in place. In debug builds, the reordering of small circuits is
+    /// checked by simulation.
+    pub fn canonicalize(&mut self) -> Result<()> {
+        self.record(|| TraceCall::Canonicalize);
+        self.untraced(|process| {
+            Canonicalize.run(process)?;
+            process.rewritten();
+            Ok(())
+        })
+    }
+
+    /// Freezes the current instructions, so only the instructions added afterwards can
+    /// be rewritten
+    ///
+    /// The frozen prefix is kept as is by [`Process::optimize`] and by the
+    /// [passes](crate::passes), which only rewrite the editable suffix. This is the
+    /// model of interactive sessions, where the prefix is already compiled, or executed
+    /// in live mode, and new instructions keep being appended. Call
+    /// [`Process::optimize`] before freezing to freeze the optimized circuit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::FrozenInstructions`] within an inverse scope, whose gates are
+    /// not in the instructions yet.
+    pub fn freeze(&mut self) -> Result<()> {
+        self.record(|| TraceCall::Freeze);
+        if !self.adj_stack.is_empty() {
+            return Err(KetError::FrozenInstructions);
+        }
+        self.frozen_len = self.instructions.len();
+        Ok(())
+    }
+
+    /// Return the number of frozen instructions, see [`Process::freeze`]
+    pub fn frozen_len(&self) -> usize {
+        self.frozen_len
+    }
+
+    /// Saves the state of the process, to go back to it with [`Process::rollback`]
+    ///
+    /// The checkpoint keeps the instructions, the qubits, the open scopes, and the
+    /// number of results, and, in live mode, the state of the simulation. It lets an
+    /// interactive session undo its last steps, and a search try a circuit and
+    /// discard it.
+    ///
+    /// ```
+    /// # use ket::error::KetError;
+    /// use ket::{Configuration, Process, QuantumGate};
+    ///
+    /// # fn main() -> Result<(), KetError> {
+    /// let mut process = Process::new(Configuration::new(2));
+    /// let qubit = process.allocate_qubit()?;
+    /// let checkpoint = process.checkpoint()?;
+    ///
+    /// process.apply_gate(QuantumGate::Hadamard, qubit)?;
+    /// process.measure(&[qubit])?;
+    /// process.rollback(&checkpoint)?;
+    /// assert_eq!(process.instructions().len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution, or
+    /// [`KetError::InvalidCheckpoint`] if the live execution cannot save its state, see
+    /// [`LiveExecution::save_state`](crate::LiveExecution::save_state).
+    pub fn checkpoint(&mut self) -> Result<Checkpoint> {
+        let index = self.checkpoints;
+        self.record(|| TraceCall::Checkpoint { index });
+        self.assert_not_ready_for_execution()?;
+        let live_state = match self.config.live_quantum_execution.as_mut() {
+            Some(processor) => Some(processor.save_state().ok_or(KetError::InvalidCheckpoint)?),
+            None => None,
+        };
+        self.checkpoints += 1;
+
+        Ok(Checkpoint {
+            index,
+            instructions: self.instructions.clone(),
+            ctrl_stack: self.ctrl_stack.clone(),
+            adj_stack: self.adj_stack.clone(),
+            cond_stack: self.cond_stack.clone(),
+            results: [
+                self.measurements.len(),
+                self.exp_values.len(),
+                self.samples.len(),
+                self.dumps.len(),
+            ],
+            forced_measurements: self.forced_measurements.clone(),
+            optimized_len: self.optimized_len,
+            frozen_len: self.frozen_len,
+            opaque: self.opaque.clone(),
+            opaque_scope: self.opaque_scope,
+            budgets: self.budgets.clone(),
+            budget_scope: self.budget_scope.clone(),
+            unbudgeted_error: self.unbudgeted_error,
+            streamed_len: self.streamed_len,
+            structure: self.structure.clone(),
+            provenance: self.provenance.clone(),
+            qubits: self.qubits.clone(),
+            qubit_allocated: self.qubit_allocated,
+            live_state,
+        })
+    }
+
+    /// Goes back to the state saved by [`Process::checkpoint`]
+    ///
+    /// The instructions, qubits, and results added since the checkpoint are discarded,
+    /// even if the circuit was optimized in the meantime. A checkpoint can be rolled
+    /// back to any number of times.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution, or
+    /// [`KetError::InvalidCheckpoint`] if the process executed a chunk since the
+    /// checkpoint, see [`Configuration::chunk_size`], or if the live execution cannot
+    /// restore its state.
+    pub fn rollback(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+        self.record(|| TraceCall::Rollback {
+            checkpoint: checkpoint.index,
+        });
+        self.assert_not_ready_for_execution()?;
+        if checkpoint.streamed_len != self.streamed_len
+            || checkpoint.live_state.is_some() != self.config.live_quantum_execution.is_some()
+        {
+            return Err(KetError::InvalidCheckpoint);
+        }
+        if let (Some(state), Some(processor)) = (
+            checkpoint.live_state.as_ref(),
+            self.config.live_quantum_execution.as_mut(),
+        ) {
+            if !processor.restore_state(state.as_ref()) {
+                return Err(KetError::InvalidCheckpoint);
+            }
+        }
+
+        let [measurements, exp_values, samples, dumps] = checkpoint.results;
+        self.measurements.truncate(measurements);
+        self.exp_values.truncate(exp_values);
+        self.samples.truncate(samples);
+        self.dumps.truncate(dumps);
+
+        self.instructions = checkpoint.instructions.clone();
+        self.ctrl_stack = checkpoint.ctrl_stack.clone();
+        self.ctrl_list_is_up_to_date = false;
+        self.adj_stack = checkpoint.adj_stack.clone();
+        self.cond_stack = checkpoint.cond_stack.clone();
+        self.forced_measurements = checkpoint.forced_measurements.clone();
+        self.optimized_len = checkpoint.optimized_len;
+        self.frozen_len = checkpoint.frozen_len;
+        self.opaque = checkpoint.opaque.clone();
+        self.opaque_scope = checkpoint.opaque_scope;
+        self.budgets = checkpoint.budgets.clone();
+        self.budget_scope = checkpoint.budget_scope.clone();
+        self.unbudgeted_error = checkpoint.unbudgeted_error;
+        self.structure = checkpoint.structure.clone();
+        self.provenance = checkpoint.provenance.clone();
+        self.qubits = checkpoint.qubits.clone();
+        self.qubit_allocated = checkpoint.qubit_allocated;
+        Ok(())
+    }
+
+    /// Begins an opaque block, whose instructions are kept as they are added
+    ///
+    /// The passes and [`Process::optimize`] treat an opaque block as a black box: they
+    /// never rewrite its instructions nor move instructions across it, and its gates are
+    /// not decomposed, see [`Configuration::decompose`]. This is meant for gates that
+    /// stand for calibrated operations of the hardware. Opaque blocks can be nested, and
+    /// the outermost block is the one kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution, or
+    /// [`KetError::InvalidOpaqueBlock`] within an inverse scope, whose gates are not in
+    /// the instructions yet.
+    pub fn opaque_begin(&mut self) -> Result<()> {
+        self.record(|| TraceCall::OpaqueBegin);
+        self.assert_not_ready_for_execution()?;
+        if !self.adj_stack.is_empty() {
+            return Err(KetError::InvalidOpaqueBlock);
+        }
+
+        self.opaque_scope = match self.opaque_scope {
+            Some((start, depth)) => Some((start, depth + 1)),
+            None => Some((self.instructions.len(), 1)),
+        };
+        Ok(())
+    }
+
+    /// Ends the opaque block, see [`Process::opaque_begin`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution, or
+    /// [`KetError::InvalidOpaqueBlock`] if there is no opaque block to end or within an
+    /// inverse scope.
+    pub fn opaque_end(&mut self) -> Result<()> {
+        self.record(|| TraceCall::OpaqueEnd);
+        self.assert_not_ready_for_execution()?;
+        if !self.adj_stack.is_empty() {
+            return Err(KetError::InvalidOpaqueBlock);
+        }
+
+        match self.opaque_scope.take() {
+            None => return Err(KetError::InvalidOpaqueBlock),
+            Some((start, depth)) if depth > 1 => self.opaque_scope = Some((start, depth - 1)),
+            Some((start, _)) => {
+                if start < self.instructions.len() {
+                    self.opaque.push(start..self.instructions.len());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the ranges of the instructions in opaque blocks, see
+    /// [`Process::opaque_begin`]
+    pub fn opaque_blocks(&self) -> &[Range<usize>] {
+        &self.opaque
+    }
+
+    /// Begins a section with a fidelity target, see [`budget`](crate::passes::budget)
+    ///
+    /// The approximations that passes make in the section may spend an error of
+    /// `1 - fidelity`. Sections cannot be nested.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution, or
+    /// [`KetError::InvalidErrorBudget`] if `fidelity` is not in `(0, 1]`, if a section
+    /// is already open, or within an inverse scope or an opaque block.
+    pub fn budget_begin(&mut self, name: &str, fidelity: f64) -> Result<()> {
+        self.record(|| TraceCall::BudgetBegin {
+            name: name.to_string(),
+            fidelity,
+        });
+        self.assert_not_ready_for_execution()?;
+        if !(fidelity > 0.0 && fidelity <= 1.0)
+            || self.budget_scope.is_some()
+            || !self.adj_stack.is_empty()
+            || self.opaque_scope.is_some()
+        {
+            return Err(KetError::InvalidErrorBudget);
+        }
+        self.budget_scope = Some(ErrorBudget {
+            name: name.to_string(),
+            fidelity,
+            spent: 0.0,
+            range: self.instructions.len()..self.instructions.len(),
+        });
+        Ok(())
+    }
+
+    /// Ends the section, see [`Process::budget_begin`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process is ready for execution, or
+    /// [`KetError::InvalidErrorBudget`] if there is no section to end, or within an
+    /// inverse scope or an opaque block.
+    pub fn budget_end(&mut self) -> Result<()> {
+        self.record(|| TraceCall::BudgetEnd);
+        self.assert_not_ready_for_execution()?;
+        if !self.adj_stack.is_empty() || self.opaque_scope.is_some() {
+            return Err(KetError::InvalidErrorBudget);
+        }
+        let mut budget = self
+            .budget_scope
+            .take()
+            .ok_or(KetError::InvalidErrorBudget)?;
+        budget.range.end = self.instructions.len();
+        self.budgets.push(budget);
+        Ok(())
+    }
+
+    /// Returns the sections with a fidelity target and the error they spent, see
+    /// [`Process::budget_begin`]
+    pub fn error_budgets(&self) -> &[ErrorBudget] {
+        &self.budgets
+    }
+
+    /// Starts recording the calls made on the process, see the [`trace`](crate::trace)
+    /// module
+    ///
+    /// Composite calls, such as [`Process::apply_template`] or [`Process::from_qasmv2`],
+    /// are recorded as the calls they make. A trace already being recorded is discarded.
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Trace::new(self.config.num_qubits));
+    }
+
+    /// Returns the calls recorded since [`Process::start_trace`]
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    /// Stops recording the calls made on the process, returning the trace
+    pub fn stop_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+}
+
+/// State of a process saved by [`Process::checkpoint`]
+pub struct Checkpoint {
+    /// Number of checkpoints taken before this one, which identifies it in a trace
+    index: usize,
+    instructions: Vec<Instruction>,
+    ctrl_stack: Vec<Vec<usize>>,
+    adj_stack: Vec<Vec<Instruction>>,
+    cond_stack: Vec<Condition>,
+    /// Number of measurements, expected values, samples, and dumps
+    results: [usize; 4],
+    forced_measurements: BTreeMap<usize, u64>,
+    optimized_len: usize,
+    frozen_len: usize,
+    opaque: Vec<Range<usize>>,
+    opaque_scope: Option<(usize, usize)>,
+    budgets: Vec<ErrorBudget>,
+    budget_scope: Option<ErrorBudget>,
+    unbudgeted_error: f64,
+    streamed_len: usize,
+    structure: StructureRecorder,
+    provenance: Provenance,
+    qubits: Vec<QubitStatus>,
+    qubit_allocated: usize,
+    /// State of the live execution, see [`LiveExecution::save_state`](crate::LiveExecution::save_state)
+    live_state: Option<Box<dyn Any + Send>>,
+}
+
+/// Processes are equal if their quantum instructions only differ in the order of
+/// instructions on disjoint qubits.
+impl PartialEq for Process {
+    fn eq(&self, other: &Self) -> bool {
+        structurally_equal(&self.instructions, &other.instructions)
+    }
+}
+
+impl Eq for Process {}
+
+impl Hash for Process {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.circuit_hash().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::KetError, BitOrder, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn typed_measurements() -> Result<(), KetError> {
+        for bit_order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut configuration = Configuration::new(4);
+            configuration.bit_order = bit_order;
+            let mut process = Process::new(configuration);
+            let qubits: Vec<usize> = (0..4)
+                .map(|_| process.allocate_qubit())
+                .collect::<Result<_, _>>()?;
+            let index = process.measure(&qubits)?;
+            assert_eq!(process.get_measurement_as_u64(index, bit_order), None);
+
+            // The first qubit is set, and the last two are set.
+            let arranged = &process.measurements[index].qubits;
+            let result = arranged
+                .iter()
+                .map(|qubit| [0, 2, 3].contains(qubit) as u64)
+                .fold(0, |value, bit| value << 1 | bit);
+            process.measurements[index].result = Some(result);
+
+            let msb = BitOrder::MsbFirst;
+            let lsb = BitOrder::LsbFirst;
+            assert_eq!(process.get_measurement_as_u64(index, msb), Some(0b1011));
+            assert_eq!(process.get_measurement_as_u64(index, lsb), Some(0b1101));
+            assert_eq!(process.get_measurement_as_i64(index, msb), Some(-5));
+            assert_eq!(process.get_measurement_as_i64(index, lsb), Some(-3));
+            assert_eq!(
+                process.get_measurement_as_fixed(index, msb, 2, false),
+                Some(2.75)
+            );
+            assert_eq!(
+                process.get_measurement_as_fixed(index, lsb, 3, true),
+                Some(-0.375)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rollback_after_optimize() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(3));
+        process.start_trace();
+        let a = process.allocate_qubit()?;
+        let b = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::Hadamard, a)?;
+        let checkpoint = process.checkpoint()?;
+        let instructions = process.instructions_json();
+
+        process.allocate_qubit()?;
+        process.ctrl_push(&[a])?;
+        process.apply_gate(QuantumGate::PauliX, b)?;
+        process.ctrl_pop()?;
+        process.measure(&[a, b])?;
+        process.optimize()?;
+        process.rollback(&checkpoint)?;
+        assert_eq!(process.instructions_json(), instructions);
+        assert_eq!(process.qubit_allocated, 2);
+        assert!(process.measurements.is_empty());
+
+        // The checkpoint can be used again, and the trace replays the rollbacks.
+        process.ctrl_push(&[b])?;
+        process.rollback(&checkpoint)?;
+        process.apply_gate(QuantumGate::PauliZ, b)?;
+        let mut replayed = Process::new(Configuration::new(3));
+        process.trace().unwrap().replay(&mut replayed)?;
+        assert!(replayed == process);
+
+        process.prepare_for_execution()?;
+        assert!(matches!(
+            process.rollback(&checkpoint),
+            Err(KetError::ProcessReadyToExecute)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn matches_circuit_builder() {
+        use crate::{Angle, CircuitBuilder};
+
+        let mut builder = CircuitBuilder::new().with_num_qubits(3);
+        let mut process = Process::new(Configuration::new(3));
+        for index in 0..3 {
+            assert_eq!(builder.allocate_qubit().unwrap(), index);
+            process.allocate_qubit().unwrap();
+        }
+
+        builder.apply_gate(QuantumGate::Hadamard, 0).unwrap();
+        process.apply_gate(QuantumGate::Hadamard, 0).unwrap();
+        builder.adj_begin();
+        process.adj_begin().unwrap();
+        builder.ctrl_push(&[0]).unwrap();
+        process.ctrl_push(&[0]).unwrap();
+        for (gate, target) in [
+            (QuantumGate::RotationY(Angle::Scalar(0.3)), 1),
+            (QuantumGate::Phase(Angle::pi_fraction(1, 4)), 2),
+        ] {
+            builder.apply_gate(gate.clone(), target).unwrap();
+            process.apply_gate(gate, target).unwrap();
+        }
+        builder.ctrl_pop().unwrap();
+        process.ctrl_pop().unwrap();
+        builder.adj_end().unwrap();
+        process.adj_end().unwrap();
+        builder.free_qubit(2).unwrap();
+        process.free_qubit(2).unwrap();
+        builder.measure(&[0, 1]).unwrap();
+        process.measure(&[0, 1]).unwrap();
+
+        let built = builder.into_sink();
+        assert_eq!(
+            serde_json::to_string(&built).unwrap(),
+            serde_json::to_string(process.instructions()).unwrap()
+        );
     }
 }
@@ -0,0 +1,219 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Source locations of the instructions.
+//!
+//! The front end sets the location of the code that is building the circuit, as the
+//! file, line, and function of a Python statement, with
+//! [`Process::set_source_location`](crate::Process::set_source_location), and each
+//! instruction added afterwards records it. The gates of an inverse scope are added when
+//! the scope ends, so they record the location set at that point.
+//!
+//! The locations are carried through [`Process::optimize`](crate::Process::optimize),
+//! [`Process::canonicalize`](crate::Process::canonicalize), and the
+//! [`PassManager`](crate::passes::PassManager): an instruction that is kept by a rewrite
+//! keeps its location, and an instruction created by a rewrite takes the location of the
+//! kept instruction before it, or after it for the first ones. The passes that relabel
+//! qubits keep no instruction, so the circuit loses its locations.
+//!
+//! [`Process::source_resources`](crate::Process::source_resources) reports the
+//! resources of each location, so a resource report can point at the lines of the
+//! program that cost the most.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::{provenance::SourceLocation, Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut process = Process::new(Configuration::new(2));
+//! let qubit = process.allocate_qubit()?;
+//! process.set_source_location(Some(SourceLocation::new("main.py", 4, "bell")));
+//! process.apply_gate(QuantumGate::Hadamard, qubit)?;
+//!
+//! let location = process.source_location(1).unwrap();
+//! assert_eq!(location.to_string(), "main.py:4 in bell");
+//! assert!(process.source_location(0).is_none());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ir::Instruction;
+
+/// Location in the source code of the front end.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SourceLocation {
+    /// Path of the source file.
+    pub file: String,
+
+    /// Line in the source file, starting at 1.
+    pub line: u32,
+
+    /// Name of the function, or empty at the top level of a file.
+    pub function: String,
+}
+
+impl SourceLocation {
+    /// Creates a source location.
+    pub fn new(file: impl Into<String>, line: u32, function: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            line,
+            function: function.into(),
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        if !self.function.is_empty() {
+            write!(f, " in {}", self.function)?;
+        }
+        Ok(())
+    }
+}
+
+fn serialize(instruction: &Instruction) -> String {
+    serde_json::to_string(instruction).expect("instructions are serializable")
+}
+
+/// Records the source location of each instruction of a process.
+///
+/// Nothing is recorded until a location is first set, so processes built without
+/// locations pay nothing.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Provenance {
+    /// Distinct locations, indexed by the entries.
+    locations: Vec<SourceLocation>,
+    /// Location of the instructions being added.
+    current: Option<usize>,
+    /// Each instruction, serialized, with its location, once a location was set.
+    recorded: Option<Vec<(String, Option<usize>)>>,
+}
+
+impl Provenance {
+    /// Sets the location of the instructions added afterwards. The `instructions`
+    /// already added have no location.
+    pub(crate) fn set(&mut self, location: Option<SourceLocation>, instructions: &[Instruction]) {
+        self.recorded.get_or_insert_with(|| {
+            instructions
+                .iter()
+                .map(|instruction| (serialize(instruction), None))
+                .collect()
+        });
+        self.current = location.map(|location| {
+            match self.locations.iter().position(|other| *other == location) {
+                Some(index) => index,
+                None => {
+                    self.locations.push(location);
+                    self.locations.len() - 1
+                }
+            }
+        });
+    }
+
+    /// Records an instruction added to the end of the circuit.
+    pub(crate) fn push(&mut self, instruction: &Instruction) {
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.push((serialize(instruction), self.current));
+        }
+    }
+
+    /// Forgets the instructions, after they were executed as a chunk.
+    pub(crate) fn clear(&mut self) {
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.clear();
+        }
+    }
+
+    /// Carries the locations to the rewritten `instructions`, see
+    /// [`provenance`](self).
+    pub(crate) fn carry(&mut self, instructions: &[Instruction]) {
+        let Some(recorded) = self.recorded.as_mut() else {
+            return;
+        };
+        let mut kept: HashMap<&str, VecDeque<Option<usize>>> = HashMap::new();
+        for (key, location) in recorded.iter() {
+            kept.entry(key.as_str()).or_default().push_back(*location);
+        }
+
+        let mut carried: Vec<(String, Option<Option<usize>>)> = instructions
+            .iter()
+            .map(|instruction| {
+                let key = serialize(instruction);
+                let location = kept.get_mut(key.as_str()).and_then(VecDeque::pop_front);
+                (key, location)
+            })
+            .collect();
+        let first = carried.iter().find_map(|(_, location)| *location).flatten();
+        let mut previous = first;
+        for (_, location) in &mut carried {
+            previous = *location.get_or_insert(previous);
+        }
+        *recorded = carried
+            .into_iter()
+            .map(|(key, location)| (key, location.flatten()))
+            .collect();
+    }
+
+    /// Returns the location of the instruction at `index`.
+    pub(crate) fn location(&self, index: usize) -> Option<&SourceLocation> {
+        let (_, location) = self.recorded.as_ref()?.get(index)?;
+        location.map(|location| &self.locations[location])
+    }
+
+    /// Returns the location of each of the `len` instructions.
+    pub(crate) fn locations(&self, len: usize) -> Vec<Option<&SourceLocation>> {
+        (0..len).map(|index| self.location(index)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceLocation;
+    use crate::{
+        error::KetError,
+        passes::{PassManager, Peephole},
+        Configuration, Process, QuantumGate,
+    };
+
+    #[test]
+    fn carried_through_passes() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(2));
+        let a = process.allocate_qubit()?;
+        let b = process.allocate_qubit()?;
+
+        let first = SourceLocation::new("main.py", 3, "main");
+        let second = SourceLocation::new("main.py", 4, "main");
+        process.set_source_location(Some(first.clone()));
+        process.apply_gate(QuantumGate::Hadamard, a)?;
+        process.apply_gate(QuantumGate::Hadamard, a)?;
+        process.set_source_location(Some(second.clone()));
+        process.ctrl_push(&[a])?;
+        process.apply_gate(QuantumGate::PauliX, b)?;
+        process.ctrl_pop()?;
+        process.set_source_location(None);
+        process.measure(&[b])?;
+
+        let resources = process.source_resources();
+        assert_eq!(resources.len(), 2);
+        assert_eq!((&resources[0].0, resources[0].1.gates), (&first, 2));
+        assert_eq!((&resources[1].0, resources[1].1.gates), (&second, 1));
+
+        // The Hadamard gates cancel, and the remaining gates keep their locations.
+        PassManager::new().add_pass(Peephole).run(&mut process)?;
+        let locations: Vec<_> = (0..process.instructions().len())
+            .map(|index| process.source_location(index).cloned())
+            .collect();
+        assert_eq!(locations, [None, None, Some(second), None]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+// SPDX-FileCopyrightText: 2024 Gabriel da Silva Cardoso <cardoso.gabriel@grad.ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Classical bits of the measurements of an exported program.
+//!
+//! [`to_qasmv2`](super::exporter::to_qasmv2) writes each measured qubit to its own bit
+//! of the `c` register. The measurements take consecutive bits in the order of their
+//! index, the first qubit of a measurement in the first of its bits, so the bits of a
+//! measurement only depend on the measurements before it. A [`CregMap`] records this
+//! layout, so the bitstrings returned by a quantum computer can be correlated back to
+//! the [`Process::measure`](crate::Process::measure) calls:
+//!
+//! ```
+//! use ket::{qasmv2::creg::CregMap, Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), ket::error::KetError> {
+//! let mut process = Process::new(Configuration::new(3));
+//! let qubits: Vec<_> = (0..3)
+//!     .map(|_| process.allocate_qubit())
+//!     .collect::<Result<_, _>>()?;
+//! process.apply_gate(QuantumGate::PauliX, qubits[2])?;
+//! let first = process.measure(&qubits[..1])?;
+//! let second = process.measure(&qubits[1..])?;
+//!
+//! let map = process.creg_map();
+//! assert_eq!(map.bits(second), Some(1..3));
+//!
+//! // Bitstring of the hardware, with `c[0]` on the right.
+//! assert_eq!(map.result(first, "100"), Some(0));
+//! assert_eq!(map.result(second, "100"), Some(0b01));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Dumps are not written to the register; the parameter of a `dump` statement is the
+//! index of the dump.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Instruction;
+
+/// Bits of the `c` register written by each measurement.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CregMap {
+    /// First bit of each measurement and of the bit after the last one.
+    offsets: Vec<usize>,
+}
+
+impl CregMap {
+    /// Returns the layout of the measurements of the instructions.
+    pub fn from_instructions(instructions: &[Instruction]) -> Self {
+        let mut sizes = Vec::new();
+        for instruction in instructions {
+            if let Instruction::Measure { qubits, output } = instruction {
+                if sizes.len() <= *output {
+                    sizes.resize(*output + 1, 0);
+                }
+                sizes[*output] = qubits.len();
+            }
+        }
+
+        let mut offsets = vec![0];
+        for size in sizes {
+            offsets.push(offsets.last().unwrap() + size);
+        }
+        Self { offsets }
+    }
+
+    /// Returns the number of bits of the register.
+    pub fn size(&self) -> usize {
+        *self.offsets.last().unwrap_or(&0)
+    }
+
+    /// Returns the number of measurements.
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    /// Returns `true` if there are no measurements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the bits of a measurement, in the order of its qubits.
+    pub fn bits(&self, measurement: usize) -> Option<Range<usize>> {
+        (measurement < self.len()).then(|| self.offsets[measurement]..self.offsets[measurement + 1])
+    }
+
+    /// Returns the measurement that writes a bit.
+    pub fn measurement(&self, bit: usize) -> Option<usize> {
+        (bit < self.size()).then(|| self.offsets.partition_point(|offset| *offset <= bit) - 1)
+    }
+
+    /// Returns the result of a measurement in a bitstring of the register, written with
+    /// `c[0]` as its last character, as most quantum computers return them.
+    ///
+    /// The first qubit of the measurement is the most significant bit of the result, as
+    /// in [`Measurement::result`](crate::objects::Measurement::result). Returns `None`
+    /// if the measurement does not exist or the bitstring is too short.
+    pub fn result(&self, measurement: usize, bitstring: &str) -> Option<u64> {
+        let bitstring = bitstring.as_bytes();
+        self.bits(measurement)?.try_fold(0, |result, bit| {
+            let character = bitstring.len().checked_sub(bit + 1)?;
+            match bitstring[character] {
+                b'0' => Some(result << 1),
+                b'1' => Some(result << 1 | 1),
+                _ => None,
+            }
+        })
+    }
+}
@@ -8,6 +8,10 @@ use std::fmt::format;
 use regex::Regex;
 
 use crate::error::KetError;
+use crate::multiplexor;
+use crate::passes::LifetimeReport;
+use crate::qasmv2::creg::CregMap;
+use crate::qasmv2::formatter::QasmFormat;
 use crate::qasmv2::instruction_set::InstructionSet;
 use crate::{Instruction, Process, QuantumGate};
 
@@ -15,6 +19,20 @@ pub fn to_qasmv2(
     process: &Process,
     measurements: bool,
     instruction_set: InstructionSet,
+) -> Result<String, KetError> {
+    to_qasmv2_formatted(
+        process,
+        measurements,
+        instruction_set,
+        &QasmFormat::default(),
+    )
+}
+
+pub fn to_qasmv2_formatted(
+    process: &Process,
+    measurements: bool,
+    instruction_set: InstructionSet,
+    format: &QasmFormat,
 ) -> Result<String, KetError> {
     if instruction_set == InstructionSet::DEFAULT {
         return Err(KetError::PureQASMGateExportError);
@@ -24,12 +42,26 @@ pub fn to_qasmv2(
                             OPENQASM 2.0;\n\
                             include \"qelib1.inc\";\n";
 
-    let num_qubits = process.metadata.qubit_simultaneous;
+    // Diagonal gates and multiplexed rotations are exported as CNOTs and rotations.
+    let instructions = multiplexor::lower(&process.instructions);
+
+    // The register has one qubit per simultaneously allocated qubit, so an allocation
+    // takes the place of a freed qubit, as after the qubit compaction pass.
+    let num_qubits = LifetimeReport::from_instructions(&instructions).width;
 
     let mut alloc = format!("qreg q[{}];\n", num_qubits);
-    let mut bits = 0;
+    let creg = CregMap::from_instructions(&process.instructions);
     let mut qubit_stack: VecDeque<usize> = (0..num_qubits).collect();
-    let mut qubit_map: VecDeque<usize> = VecDeque::from(vec![0; num_qubits]);
+    let num_indices = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Alloc { target } => Some(target + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let mut qubit_map: VecDeque<usize> = VecDeque::from(vec![0; num_indices]);
+    let mut used = vec![false; num_qubits];
 
     let mut gates = String::new();
     let mut ket_instr = String::new();
@@ -37,10 +69,16 @@ pub fn to_qasmv2(
     let mut has_dump = false;
     let mut has_measure = false;
 
-    for instruction in &process.instructions {
+    for instruction in instructions.iter() {
         match instruction {
             Instruction::Alloc { target } => {
-                qubit_map[*target] = qubit_stack.pop_front().unwrap();
+                let qubit = qubit_stack.pop_front().unwrap();
+                // The |0> state of a freed qubit is not guaranteed on hardware.
+                if used[qubit] {
+                    gates += &format!("reset q[{}];\n", qubit);
+                }
+                used[qubit] = true;
+                qubit_map[*target] = qubit;
             }
             Instruction::Free { target } => {
                 qubit_stack.push_front(qubit_map[*target]);
@@ -56,16 +94,16 @@ pub fn to_qasmv2(
                         QuantumGate::PauliY => format!("y q[{}];\n", qubit_map[*target]),
                         QuantumGate::PauliZ => format!("z q[{}];\n", qubit_map[*target]),
                         QuantumGate::RotationX(angle) => {
-                            format!("rx ({}) q[{}];\n", angle, qubit_map[*target])
+                            format!("rx ({}) q[{}];\n", format.angle(angle), qubit_map[*target])
                         }
                         QuantumGate::RotationY(angle) => {
-                            format!("ry ({}) q[{}];\n", angle, qubit_map[*target])
+                            format!("ry ({}) q[{}];\n", format.angle(angle), qubit_map[*target])
                         }
                         QuantumGate::RotationZ(angle) => {
-                            format!("rz ({}) q[{}];\n", angle, qubit_map[*target])
+                            format!("rz ({}) q[{}];\n", format.angle(angle), qubit_map[*target])
                         }
                         QuantumGate::Phase(angle) => {
-                            format!("u1 ({}) q[{}];\n", angle, qubit_map[*target])
+                            format!("u1 ({}) q[{}];\n", format.angle(angle), qubit_map[*target])
                         }
                         QuantumGate::Hadamard => format!("h q[{}];\n", qubit_map[*target]),
                     },
@@ -100,32 +138,41 @@ pub fn to_qasmv2(
                     _ => return Err(KetError::UnsuportedGateExport),
                 };
             }
-            Instruction::Measure { qubits, .. } => {
-                for qubit in qubits {
-                    gates += &format!("measure q[{}] -> c[{}];\n", qubit_map[*qubit], bits);
+            Instruction::Measure { qubits, output } => {
+                for (qubit, bit) in qubits.iter().zip(creg.bits(*output).unwrap()) {
+                    gates += &format!("measure q[{}] -> c[{}];\n", qubit_map[*qubit], bit);
                 }
-                bits += 1;
             }
-            Instruction::ExpValue { .. } => {println!("expvalue"); return Err(KetError::UnsuportedGateExport); },
-            Instruction::Sample { .. } => return {println!("sample"); Err(KetError::UnsuportedGateExport)},
-            Instruction::Dump { qubits, .. } => {
+            Instruction::ExpValue { .. }
+            | Instruction::Sample { .. }
+            | Instruction::KrausChannel { .. }
+            | Instruction::ConditionalGate { .. } => {
+                tracing::debug!(?instruction, "instruction not supported in QASM");
+                return Err(KetError::UnsuportedGateExport);
+            }
+            Instruction::Dump { qubits, output } => {
                 if !has_dump {
                     ket_instr += &format!("opaque dump(classic) {};\n", "a");
                     has_dump = true;
                 }
                 for qubit in qubits {
-                    gates += &format!("dump({}) q[{}];\n", bits, qubit_map[*qubit]);
+                    gates += &format!("dump({}) q[{}];\n", output, qubit_map[*qubit]);
                 }
-                bits += 1;
-            },
+            }
+            Instruction::Diagonal { .. } | Instruction::Multiplexor { .. } => {
+                unreachable!("lowered to gates")
+            }
         }
     }
+    // The final measurements take the bits after those of the process measurements.
+    let mut bits = creg.size();
     let mut measure_qubits = String::new();
     if measurements {
         measure_qubits = (0..num_qubits)
-            .map(|q| format!("measure q[{}] -> c[{}];", q, q))
+            .map(|q| format!("measure q[{}] -> c[{}];", q, bits + q))
             .collect::<Vec<String>>()
             .join("\n");
+        bits += num_qubits;
     }
     if instruction_set == InstructionSet::QELIB {
         gates = qelib_single_gate_replacer(&mut gates);
@@ -135,7 +182,7 @@ pub fn to_qasmv2(
         alloc += &format!("creg c[{}];\n", bits);
     }
 
-    Ok(header.to_owned() + &alloc + &ket_instr +&gates + &measure_qubits + "\n")
+    Ok(format.layout(header.to_owned() + &alloc + &ket_instr + &gates + &measure_qubits + "\n"))
 }
 
 pub fn qelib_single_gate_replacer(qasm: &mut str) -> String {
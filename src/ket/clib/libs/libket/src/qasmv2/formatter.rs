@@ -0,0 +1,229 @@
+// SPDX-FileCopyrightText: 2024 Gabriel da Silva Cardoso <cardoso.gabriel@grad.ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Formatting options of the OpenQASM v2 exporter.
+//!
+//! The default options reproduce the output of [`to_qasmv2`](super::exporter::to_qasmv2).
+//! [`QasmFormat::canonical`] produces a fixed layout meant for text diffs: a single
+//! statement per line with no indentation or blank lines, a single trailing newline,
+//! angles that are fractions of pi printed as reduced fractions, and other angles
+//! printed with 12 decimal places without trailing zeros. The canonical layout is part
+//! of the public interface and only changes in a major release.
+
+use crate::Angle;
+
+/// Largest denominator detected as a fraction of pi.
+const MAX_PI_DENOMINATOR: u32 = 1024;
+
+/// Tolerance of the detection of fractions of pi, relative to pi.
+const PI_TOLERANCE: f64 = 1e-12;
+
+/// Decimal places of the canonical mode.
+const CANONICAL_PRECISION: usize = 12;
+
+/// Formatting options of the OpenQASM v2 exporter.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QasmFormat {
+    /// Statements longer than this are wrapped after a comma, with the continuation
+    /// lines indented by 4 spaces. `None` disables the wrapping.
+    pub line_width: Option<usize>,
+
+    /// Decimal places of scalar angles, without trailing zeros. `None` prints the
+    /// shortest representation that reads back to the same value.
+    pub precision: Option<usize>,
+
+    /// Prints scalar angles that are fractions of pi, with a denominator up to 1024,
+    /// as fractions of pi, and reduces the fractions of pi.
+    pub exact_pi: bool,
+
+    /// Removes indentation and blank lines, and ends the output with a single newline.
+    pub canonical: bool,
+}
+
+/// Returns the angle as a reduced fraction of pi, if possible.
+fn exact_pi(angle: &Angle) -> Angle {
+    let angle = match angle {
+        Angle::PiFraction { top, bottom } => {
+            Angle::checked_pi_fraction(*top, *bottom).unwrap_or_else(|| angle.clone())
+        }
+        Angle::Scalar(value) => {
+            let turns = value / std::f64::consts::PI;
+            let bottom = (1..=MAX_PI_DENOMINATOR).find(|bottom| {
+                let top = turns * *bottom as f64;
+                (top - top.round()).abs() < PI_TOLERANCE * *bottom as f64
+            });
+            match bottom {
+                Some(bottom) if (turns * bottom as f64).abs() < i32::MAX as f64 => {
+                    Angle::pi_fraction((turns * bottom as f64).round() as i32, bottom)
+                }
+                _ => angle.clone(),
+            }
+        }
+    };
+
+    match angle {
+        Angle::PiFraction { top: 0, .. } => Angle::Scalar(0.0),
+        angle => angle,
+    }
+}
+
+/// Prints the value with `precision` decimal places, without trailing zeros.
+fn format_scalar(value: f64, precision: usize) -> String {
+    let text = format!("{:.*}", precision, value);
+    let text = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        &text
+    };
+    if text == "-0" {
+        "0".to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Wraps a statement after the commas that fit in `width`.
+#[cfg(feature = "qasm")]
+fn wrap(line: &str, width: usize, output: &mut String) {
+    if line.len() <= width || line.trim_start().starts_with("//") {
+        output.push_str(line);
+        output.push('\n');
+        return;
+    }
+
+    let mut current = String::new();
+    for (index, part) in line.split(", ").enumerate() {
+        let part = if index == 0 {
+            part.to_string()
+        } else {
+            format!(", {}", part)
+        };
+        if !current.trim().is_empty() && current.len() + part.len() > width {
+            output.push_str(current.trim_end());
+            output.push_str(",\n");
+            current = format!("    {}", part.trim_start_matches(", "));
+        } else {
+            current += &part;
+        }
+    }
+    output.push_str(&current);
+    output.push('\n');
+}
+
+impl QasmFormat {
+    /// Options of the canonical mode, see the [module documentation](self).
+    pub fn canonical() -> Self {
+        Self {
+            line_width: None,
+            precision: Some(CANONICAL_PRECISION),
+            exact_pi: true,
+            canonical: true,
+        }
+    }
+
+    /// Prints an angle.
+    pub fn angle(&self, angle: &Angle) -> String {
+        let angle = if self.exact_pi {
+            exact_pi(angle)
+        } else {
+            angle.clone()
+        };
+        match (&angle, self.precision) {
+            (Angle::Scalar(value), Some(precision)) => format_scalar(*value, precision),
+            _ => angle.to_string(),
+        }
+    }
+
+    /// Applies the layout options to the generated code.
+    #[cfg(feature = "qasm")]
+    pub(crate) fn layout(&self, qasm: String) -> String {
+        if !self.canonical && self.line_width.is_none() {
+            return qasm;
+        }
+
+        let mut output = String::new();
+        for line in qasm.lines() {
+            let line = if self.canonical { line.trim() } else { line };
+            if self.canonical && line.is_empty() {
+                continue;
+            }
+            match self.line_width {
+                Some(width) => wrap(line, width, &mut output),
+                None => {
+                    output.push_str(line);
+                    output.push('\n');
+                }
+            }
+        }
+        output
+    }
+}
+
+#[cfg(all(test, feature = "qasm"))]
+mod tests {
+    use super::QasmFormat;
+    use crate::qasmv2::instruction_set::InstructionSet;
+    use crate::{Angle, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn angles() {
+        let format = QasmFormat::canonical();
+        assert_eq!(
+            format.angle(&Angle::Scalar(std::f64::consts::FRAC_PI_4)),
+            "pi/4"
+        );
+        assert_eq!(
+            format.angle(&Angle::PiFraction { top: -6, bottom: 8 }),
+            "(-3 * pi)/4"
+        );
+        assert_eq!(format.angle(&Angle::Scalar(0.1)), "0.1");
+        assert_eq!(format.angle(&Angle::Scalar(-1e-20)), "0");
+        assert_eq!(format.angle(&Angle::PiFraction { top: 0, bottom: 3 }), "0");
+        assert_eq!(
+            QasmFormat::default().angle(&Angle::PiFraction { top: 2, bottom: 8 }),
+            "(2 * pi)/8"
+        );
+    }
+
+    #[test]
+    fn canonical_output() {
+        let mut process = Process::new(Configuration::new(3));
+        let qubits: Vec<usize> = (0..3).map(|_| process.allocate_qubit().unwrap()).collect();
+        process
+            .apply_gate(
+                QuantumGate::RotationZ(Angle::Scalar(std::f64::consts::FRAC_PI_4)),
+                qubits[0],
+            )
+            .unwrap();
+        process
+            .apply_gate(QuantumGate::RotationX(Angle::Scalar(0.25)), qubits[1])
+            .unwrap();
+        process.ctrl_push(&qubits[..2]).unwrap();
+        process.apply_gate(QuantumGate::PauliX, qubits[2]).unwrap();
+        process.ctrl_pop().unwrap();
+
+        let qasm = process
+            .to_qasmv2_formatted(false, InstructionSet::QELIB, &QasmFormat::canonical())
+            .unwrap();
+        assert_eq!(
+            qasm,
+            "// Generated from libket\n\
+             OPENQASM 2.0;\n\
+             include \"qelib1.inc\";\n\
+             qreg q[3];\n\
+             t q[0];\n\
+             rx (0.25) q[1];\n\
+             ccx q[0], q[1], q[2];\n"
+        );
+
+        let wrapped = QasmFormat {
+            line_width: Some(16),
+            ..QasmFormat::canonical()
+        };
+        let qasm = process
+            .to_qasmv2_formatted(false, InstructionSet::QELIB, &wrapped)
+            .unwrap();
+        assert!(qasm.contains("ccx q[0], q[1],\n    q[2];\n"));
+    }
+}
@@ -0,0 +1,226 @@
+// SPDX-FileCopyrightText: 2024 Gabriel da Silva Cardoso <cardoso.gabriel@grad.ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! User-defined gates for the OpenQASM v2 importer.
+//!
+//! A [`GateTable`] maps gate names to their meaning in the importer. A gate is either
+//! native, applied by a function on the process, or defined by a QASM `gate`
+//! declaration. Both replace the declaration of a `qelib1.inc` gate with the same name,
+//! so a gate like `cp` can be imported as a single controlled phase instead of its
+//! decomposition.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::qasmv2::{gate_table::GateTable, instruction_set::InstructionSet};
+//! use ket::{Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut gates = GateTable::new();
+//! gates.insert_controlled("cp", 1, 1, |angles| QuantumGate::Phase(angles[0].clone()));
+//! gates.insert_qasm("gate sy a { sdg a; h a; sdg a; }")?;
+//!
+//! let mut process = Process::new(Configuration::new(2));
+//! process.from_qasmv2_with_gates(
+//!     "OPENQASM 2.0; qreg q[2]; cp(pi/2) q[0], q[1]; sy q[1];",
+//!     InstructionSet::QELIB,
+//!     false,
+//!     &gates,
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use regex::Regex;
+
+use crate::{
+    error::{KetError, Result},
+    Angle, Process, QuantumGate,
+};
+
+/// Function that applies a native gate, given its angles and qubits.
+pub type NativeGate = Arc<dyn Fn(&mut Process, &[Angle], &[usize]) -> Result<()> + Send + Sync>;
+
+/// Meaning of a gate name.
+#[derive(Clone)]
+pub enum GateDefinition {
+    /// Gate applied by a function, declared as `opaque` with the given number of angles
+    /// and qubits.
+    Native {
+        angles: usize,
+        qubits: usize,
+        apply: NativeGate,
+    },
+    /// QASM `gate` declaration.
+    Qasm(String),
+}
+
+impl fmt::Debug for GateDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Native { angles, qubits, .. } => f
+                .debug_struct("Native")
+                .field("angles", angles)
+                .field("qubits", qubits)
+                .finish_non_exhaustive(),
+            Self::Qasm(declaration) => f.debug_tuple("Qasm").field(declaration).finish(),
+        }
+    }
+}
+
+/// Gate names known to the importer in addition to the instruction set.
+#[derive(Debug, Clone, Default)]
+pub struct GateTable {
+    gates: BTreeMap<String, GateDefinition>,
+}
+
+impl GateTable {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a gate applied by `apply`, replacing any gate with the same name.
+    pub fn insert_native(
+        &mut self,
+        name: &str,
+        angles: usize,
+        qubits: usize,
+        apply: impl Fn(&mut Process, &[Angle], &[usize]) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.gates.insert(
+            name.to_string(),
+            GateDefinition::Native {
+                angles,
+                qubits,
+                apply: Arc::new(apply),
+            },
+        );
+    }
+
+    /// Adds a gate whose first `controls` qubits control the gate returned by `gate`
+    /// on the last qubit.
+    pub fn insert_controlled(
+        &mut self,
+        name: &str,
+        angles: usize,
+        controls: usize,
+        gate: impl Fn(&[Angle]) -> QuantumGate + Send + Sync + 'static,
+    ) {
+        self.insert_native(
+            name,
+            angles,
+            controls + 1,
+            move |process, angles, qubits| {
+                process.ctrl_push(&qubits[..controls])?;
+                let result = process.apply_gate(gate(angles), qubits[controls]);
+                process.ctrl_pop()?;
+                result
+            },
+        );
+    }
+
+    /// Adds a gate from a QASM declaration, such as `gate sy a { sdg a; h a; sdg a; }`,
+    /// replacing any gate with the same name.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidQASM`] if `declaration` is not a `gate` declaration.
+    pub fn insert_qasm(&mut self, declaration: &str) -> Result<()> {
+        let name = Regex::new(r"^\s*gate\s+(\w+)[^{]*\{[^}]*\}\s*$")
+            .unwrap()
+            .captures(declaration)
+            .ok_or(KetError::InvalidQASM)?[1]
+            .to_string();
+        self.gates
+            .insert(name, GateDefinition::Qasm(declaration.to_string()));
+        Ok(())
+    }
+
+    /// Removes a gate, returning its definition.
+    pub fn remove(&mut self, name: &str) -> Option<GateDefinition> {
+        self.gates.remove(name)
+    }
+
+    /// Returns the definition of a gate.
+    pub fn get(&self, name: &str) -> Option<&GateDefinition> {
+        self.gates.get(name)
+    }
+
+    /// Returns the native function of a gate.
+    pub(crate) fn native(&self, name: &str) -> Option<&NativeGate> {
+        match self.gates.get(name) {
+            Some(GateDefinition::Native { apply, .. }) => Some(apply),
+            _ => None,
+        }
+    }
+
+    /// Returns the QASM declarations of the gates in the table.
+    pub(crate) fn declarations(&self) -> String {
+        let mut declarations = String::new();
+        for (name, definition) in &self.gates {
+            match definition {
+                GateDefinition::Native { angles, qubits, .. } => {
+                    declarations.push_str("opaque ");
+                    declarations.push_str(name);
+                    if *angles > 0 {
+                        let angles: Vec<String> = (0..*angles).map(|i| format!("a{}", i)).collect();
+                        declarations.push_str(&format!("({})", angles.join(",")));
+                    }
+                    let qubits: Vec<String> = (0..*qubits).map(|i| format!("q{}", i)).collect();
+                    declarations.push_str(&format!(" {};\n", qubits.join(",")));
+                }
+                GateDefinition::Qasm(declaration) => {
+                    declarations.push_str(declaration);
+                    declarations.push('\n');
+                }
+            }
+        }
+        declarations
+    }
+
+    /// Removes the declarations of the gates in the table from a QASM header.
+    pub(crate) fn replace_in(&self, header: &str) -> String {
+        let declaration =
+            Regex::new(r"(?m)^[ \t]*(?:gate[ \t]+(\w+)[^{]*\{[^}]*\}|opaque[ \t]+(\w+)[^;]*;)")
+                .unwrap();
+        declaration
+            .replace_all(header, |captures: &regex::Captures| {
+                let name = captures.get(1).or(captures.get(2)).unwrap().as_str();
+                if self.gates.contains_key(name) {
+                    String::new()
+                } else {
+                    captures[0].to_string()
+                }
+            })
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GateTable;
+    use crate::{error::KetError, qasmv2::instruction_set::EDITED_QELIB, QuantumGate};
+
+    #[test]
+    fn replace_qelib_gates() -> Result<(), KetError> {
+        let mut gates = GateTable::new();
+        gates.insert_controlled("cp", 1, 1, |angles| QuantumGate::Phase(angles[0].clone()));
+        gates.insert_qasm("gate rccx a,b,c { ccx a,b,c; }")?;
+        assert!(gates.insert_qasm("opaque rccx a,b,c;").is_err());
+
+        let header = gates.replace_in(EDITED_QELIB);
+        assert!(!header.contains("gate cp(lambda)"));
+        assert!(!header.contains("gate rccx"));
+        assert!(header.contains("gate cu1(lambda)"));
+        assert!(header.contains("gate rc3x a,b,c,d"));
+
+        assert_eq!(
+            gates.declarations(),
+            "opaque cp(a0) q0,q1;\ngate rccx a,b,c { ccx a,b,c; }\n"
+        );
+        Ok(())
+    }
+}
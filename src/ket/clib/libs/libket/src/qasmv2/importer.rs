@@ -2,24 +2,104 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
+
 use crate::error::{KetError, Result};
+use crate::qasmv2::gate_table::GateTable;
 use crate::qasmv2::instruction_set::{InstructionSet, EDITED_QELIB, OPAQUE_QELIB_GATES};
+use crate::qasmv2::ImportLimits;
 use crate::{Angle, Process, QuantumGate};
 use openqasm as oq;
 use openqasm::parser::FilePolicy;
 use openqasm::{GateWriter, GenericError, ProgramVisitor, Symbol, Value};
 
+/// Returns `true` if the value `a + b * π` is zero.
+fn is_zero(value: &Value) -> bool {
+    *value.a.numer() == 0 && *value.b.numer() == 0
+}
+
+/// Converts the value `a + b * π` into an angle.
+///
+/// The angle is an exact fraction of π if `a` is zero and the fraction fits the angle
+/// representation, and a scalar angle otherwise.
+fn to_angle(value: &Value) -> Angle {
+    let (top, bottom) = (*value.b.numer(), *value.b.denom());
+    if *value.a.numer() == 0 {
+        if let (Ok(top), Ok(bottom)) = (i32::try_from(top), u32::try_from(bottom)) {
+            return Angle::pi_fraction(top, bottom);
+        }
+    }
+    Angle::Scalar(
+        *value.a.numer() as f64 / *value.a.denom() as f64
+            + std::f64::consts::PI * top as f64 / bottom as f64,
+    )
+}
+
+/// Checks the register declarations and the includes of the program against the
+/// limits, before it is parsed.
+fn check_declarations(qasm: &str, limits: &ImportLimits) -> Result<()> {
+    let source = qasm
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    for statement in source.split(';') {
+        let statement = statement.trim_start_matches(|c: char| c == '}' || c.is_whitespace());
+        if statement.starts_with("include") && !limits.allow_include {
+            tracing::warn!("the program includes a file");
+            return Err(KetError::ImportLimitExceeded);
+        }
+
+        let Some(declaration) = statement
+            .strip_prefix("qreg")
+            .or_else(|| statement.strip_prefix("creg"))
+        else {
+            continue;
+        };
+        let Some(size) = declaration
+            .split_once('[')
+            .and_then(|(_, size)| size.split_once(']'))
+            .map(|(size, _)| size.trim())
+        else {
+            continue;
+        };
+        // Sizes that are not numbers are left to the parser, and numbers that do not
+        // fit in a `usize` exceed any limit.
+        if !size.is_empty() && size.chars().all(|c| c.is_ascii_digit()) {
+            match size.parse::<usize>() {
+                Ok(size) if size <= limits.max_register_size => {}
+                _ => {
+                    tracing::warn!(size, "the register exceeds the import limit");
+                    return Err(KetError::ImportLimitExceeded);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 pub fn from_qasmv2(
     process: &mut Process,
     qasm: &str,
     instruction_set: InstructionSet,
     ignore_qreg: bool
 ) -> Result<()> {
-    let mut writer = QasmInterpreter {
-        process,
-        instruction_set,
-        ignore_qreg,
-    };
+    from_qasmv2_with_gates(process, qasm, instruction_set, ignore_qreg, &GateTable::new())
+}
+
+/// Imports the circuit with the gates of the table, which replace the gates of the
+/// instruction set with the same name.
+pub fn from_qasmv2_with_gates(
+    process: &mut Process,
+    qasm: &str,
+    instruction_set: InstructionSet,
+    ignore_qreg: bool,
+    gates: &GateTable,
+) -> Result<()> {
+    let limits = process.config.import_limits;
+    check_declarations(qasm, &limits)?;
+
     let mut cache = oq::SourceCache::new();
     let mut parser = oq::Parser::new(&mut cache).with_file_policy(FilePolicy::Ignore);
 
@@ -28,29 +108,92 @@ pub fn from_qasmv2(
     match instruction_set {
         InstructionSet::DEFAULT => {}
         InstructionSet::QELIB => {
-            parser.parse_source::<String>(EDITED_QELIB.parse().unwrap(), None);
-            parser.parse_source::<String>(OPAQUE_QELIB_GATES.parse().unwrap(), None);
+            parser.parse_source::<String>(gates.replace_in(EDITED_QELIB), None);
+            parser.parse_source::<String>(gates.replace_in(OPAQUE_QELIB_GATES), None);
         }
     }
+    parser.parse_source::<String>(gates.declarations(), None);
 
-    let program = parser.done().to_errors().unwrap();
-    program.type_check().to_errors().unwrap();
+    let program = parser
+        .done()
+        .to_errors()
+        .map_err(|_| KetError::InvalidQASM)?;
+    program
+        .type_check()
+        .to_errors()
+        .map_err(|_| KetError::InvalidQASM)?;
 
-    let mut l = oq::translate::Linearize::new(&mut writer, usize::MAX);
-    l.visit_program(&program).to_errors().unwrap();
-    Ok(())
+    let write = |process: &mut Process| {
+        let mut writer = QasmInterpreter {
+            process,
+            instruction_set,
+            ignore_qreg,
+            gates,
+            bits: HashMap::new(),
+            skip: false,
+            conditional: false,
+            limits,
+            statements: 0,
+        };
+        let result = oq::translate::Linearize::new(&mut writer, usize::MAX)
+            .visit_program(&program)
+            .to_errors();
+        if writer.statements > limits.max_instructions {
+            return Err(KetError::ImportLimitExceeded);
+        }
+        result.map_err(|_| KetError::InvalidQASM)
+    };
+
+    // The program is written first on a scratch process with the qubits and the open
+    // scopes of the process, so a program rejected midway, as one that exceeds the
+    // instruction limit, leaves the process unchanged.
+    let mut scratch = process.scratch();
+    scratch.qubits = process.qubits.clone();
+    scratch.qubit_allocated = process.qubit_allocated;
+    scratch.measurements = process.measurements.clone();
+    scratch.ctrl_stack = process.ctrl_stack.clone();
+    scratch.adj_stack = process.adj_stack.clone();
+    scratch.cond_stack = process.cond_stack.clone();
+    write(&mut scratch)?;
+
+    write(process)
 }
 
 pub struct QasmInterpreter<'a> {
     pub process: &'a mut Process,
     pub instruction_set: InstructionSet,
-    pub ignore_qreg: bool
+    pub ignore_qreg: bool,
+    pub gates: &'a GateTable,
+    /// Index of the last measurement written to each classical bit
+    pub bits: HashMap<usize, usize>,
+    /// Whether the open `if` statement can never hold, because it expects a bit that
+    /// was never measured to be 1
+    pub skip: bool,
+    /// Whether an `if` statement is open
+    pub conditional: bool,
+    /// Limits of the import
+    pub limits: ImportLimits,
+    /// Number of statements written
+    pub statements: usize,
 }
 
 impl QasmInterpreter<'_> {
     fn get_process(&mut self) -> Result<&mut Process> {
         Ok(self.process)
     }
+
+    /// Counts a statement, failing once there are more than the limit.
+    fn count_statement(&mut self) -> Result<()> {
+        self.statements += 1;
+        if self.statements > self.limits.max_instructions {
+            tracing::warn!(
+                limit = self.limits.max_instructions,
+                "the program exceeds the instruction limit"
+            );
+            return Err(KetError::ImportLimitExceeded);
+        }
+        Ok(())
+    }
 }
 
 impl GateWriter for &mut QasmInterpreter<'_> {
@@ -73,6 +216,11 @@ impl GateWriter for &mut QasmInterpreter<'_> {
     }
 
     fn write_cx(&mut self, copy: usize, xor: usize) -> Result<()> {
+        self.count_statement()?;
+        if self.skip {
+            return Ok(());
+        }
+
         self.get_process()?.ctrl_push(&[copy])?;
         self.get_process()?.apply_gate(QuantumGate::PauliX, xor)?;
         self.get_process()?.ctrl_pop().unwrap();
@@ -80,6 +228,11 @@ impl GateWriter for &mut QasmInterpreter<'_> {
     }
 
     fn write_u(&mut self, theta: Value, phi: Value, lambda: Value, reg: usize) -> Result<()> {
+        self.count_statement()?;
+        if self.skip {
+            return Ok(());
+        }
+
         let mut gate_angles = String::new();
 
         // will only verify the angles if QELIB mode is enabled
@@ -114,39 +267,21 @@ impl GateWriter for &mut QasmInterpreter<'_> {
             }
             // Rz(theta)Ry(phi)Rz(lambda) =  U(phi, theta, lambda)
             _ => {
-                if *lambda.b.numer() != 0 {
+                if !is_zero(&lambda) {
                     self.get_process()?
-                        .apply_gate(
-                            QuantumGate::RotationZ(Angle::PiFraction {
-                                top: *lambda.b.numer() as i32,
-                                bottom: *lambda.b.denom() as u32,
-                            }),
-                            reg,
-                        )
+                        .apply_gate(QuantumGate::RotationZ(to_angle(&lambda)), reg)
                         .unwrap();
                 }
 
-                if *theta.b.numer() != 0 {
+                if !is_zero(&theta) {
                     self.get_process()?
-                        .apply_gate(
-                            QuantumGate::RotationY(Angle::PiFraction {
-                                top: *theta.b.numer() as i32,
-                                bottom: *theta.b.denom() as u32,
-                            }),
-                            reg,
-                        )
+                        .apply_gate(QuantumGate::RotationY(to_angle(&theta)), reg)
                         .unwrap();
                 }
 
-                if *phi.b.numer() != 0 {
+                if !is_zero(&phi) {
                     self.get_process()?
-                        .apply_gate(
-                            QuantumGate::RotationZ(Angle::PiFraction {
-                                top: *phi.b.numer() as i32,
-                                bottom: *phi.b.denom() as u32,
-                            }),
-                            reg,
-                        )
+                        .apply_gate(QuantumGate::RotationZ(to_angle(&phi)), reg)
                         .unwrap();
                 }
             }
@@ -155,7 +290,17 @@ impl GateWriter for &mut QasmInterpreter<'_> {
         Ok(())
     }
 
-    fn write_opaque(&mut self, name: &Symbol, _params: &[Value], regs: &[usize]) -> Result<()> {
+    fn write_opaque(&mut self, name: &Symbol, params: &[Value], regs: &[usize]) -> Result<()> {
+        self.count_statement()?;
+        if self.skip {
+            return Ok(());
+        }
+
+        if let Some(apply) = self.gates.native(name.as_ref()) {
+            let angles: Vec<Angle> = params.iter().map(to_angle).collect();
+            return apply(self.process, &angles, regs);
+        }
+
         match name.to_string().as_str() {
             "ccx" => {
                 self.get_process()?.ctrl_push(&regs[0..2])?;
@@ -171,7 +316,7 @@ impl GateWriter for &mut QasmInterpreter<'_> {
             }
 
             _ => {
-                println!("undefined gate: {}", name.to_string().as_str());
+                tracing::warn!(gate = name.to_string().as_str(), "undefined gate");
                 Err(KetError::GateNotSupported)
             }
         }
@@ -182,8 +327,15 @@ impl GateWriter for &mut QasmInterpreter<'_> {
         Err(KetError::GateNotSupported)
     }
 
-    fn write_measure(&mut self, from: usize, _to: usize) -> Result<()> {
-        self.get_process()?.measure(&[from]);
+    fn write_measure(&mut self, from: usize, to: usize) -> Result<()> {
+        self.count_statement()?;
+        // The result of a measurement cannot depend on a condition.
+        if self.conditional {
+            return Err(KetError::GateNotSupported);
+        }
+
+        let measurement = self.get_process()?.measure(&[from])?;
+        self.bits.insert(to, measurement);
         Ok(())
     }
 
@@ -191,11 +343,35 @@ impl GateWriter for &mut QasmInterpreter<'_> {
         Err(KetError::GateNotSupported)
     }
 
-    fn start_conditional(&mut self, _reg: usize, _count: usize, _value: u64) -> Result<()> {
-        Err(KetError::GateNotSupported)
+    /// Opens `if (c==value)`, where the register `c` has `count` bits starting at
+    /// `reg`, as a condition on the last measurements written to its bits.
+    ///
+    /// A bit that was never measured is 0, so it is left out of the condition if
+    /// `value` expects it to be 0, and the gates are dropped otherwise.
+    fn start_conditional(&mut self, reg: usize, count: usize, value: u64) -> Result<()> {
+        let mut expected = Vec::new();
+        for i in 0..count {
+            let bit = (value >> i) & 1;
+            match self.bits.get(&(reg + i)) {
+                Some(&measurement) => expected.push((measurement, bit)),
+                None if bit == 1 => self.skip = true,
+                None => {}
+            }
+        }
+
+        self.conditional = true;
+        if !self.skip {
+            self.get_process()?.cond_push(&expected)?;
+        }
+        Ok(())
     }
 
     fn end_conditional(&mut self) -> Result<()> {
-        Err(KetError::GateNotSupported)
+        if !self.skip {
+            self.get_process()?.cond_pop()?;
+        }
+        self.skip = false;
+        self.conditional = false;
+        Ok(())
     }
 }
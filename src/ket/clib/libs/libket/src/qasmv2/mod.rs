@@ -2,14 +2,64 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+//! OpenQASM v2 import and export.
+//!
+//! The importer, the exporter, and the gate tables need the `qasm` feature. The
+//! [`formatter`] and the [`instruction_set`]s are always available, as the OpenQASM 3
+//! and cloud exporters format their angles with [`QasmFormat`](formatter::QasmFormat).
+
+#[cfg(feature = "qasm")]
+pub mod creg;
+#[cfg(feature = "qasm")]
 pub mod exporter;
+pub mod formatter;
+#[cfg(feature = "qasm")]
+pub mod gate_table;
+#[cfg(feature = "qasm")]
 pub mod importer;
 pub mod instruction_set;
+#[cfg(feature = "qasm")]
 pub mod verifier;
 
-#[cfg(test)]
+/// Limits of an OpenQASM import, see [`Configuration::import_limits`].
+///
+/// A file that exceeds a limit is rejected with [`KetError::ImportLimitExceeded`]
+/// before it is parsed, for the registers and includes, or as soon as the limit is
+/// reached, for the instructions, so services that import uploaded files do not
+/// exhaust their memory on adversarial or enormous programs. A rejected program
+/// leaves the process unchanged.
+///
+/// [`Configuration::import_limits`]: crate::Configuration::import_limits
+/// [`KetError::ImportLimitExceeded`]: crate::error::KetError::ImportLimitExceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportLimits {
+    /// Maximum number of gates, measurements, and other statements applied to the
+    /// process, after the gate definitions are expanded.
+    pub max_instructions: usize,
+
+    /// Maximum size of a `qreg` or `creg` declaration.
+    pub max_register_size: usize,
+
+    /// Whether the program may have `include` statements.
+    ///
+    /// The included files are never read, as `qelib1.inc` is built in.
+    pub allow_include: bool,
+}
+
+impl Default for ImportLimits {
+    fn default() -> Self {
+        Self {
+            max_instructions: 1 << 24,
+            max_register_size: 1 << 16,
+            allow_include: true,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "qasm"))]
 mod tests {
     use crate::error::KetError;
+    use crate::passes::{Pass, QubitCompaction};
     use crate::qasmv2::instruction_set::InstructionSet;
     use crate::qasmv2::verifier::verify_qasmv2;
     use crate::*;
@@ -29,18 +79,15 @@ mod tests {
         process.apply_gate(QuantumGate::PauliX, qubit_b)?;
         process.ctrl_pop()?;
 
-        let _m_a = process.measure(&[qubit_a])?;
-        let _m_b = process.measure(&[qubit_b])?;
-        let _m_a = process.dump(&[qubit_a])?;
-
+        process.measure(&[qubit_a])?;
+        process.measure(&[qubit_b])?;
+        process.dump(&[qubit_a])?;
 
         let qasm = process.to_qasmv2(true, InstructionSet::QELIB)?;
-        println!("{}", &qasm);
-        if verify_qasmv2(qasm) {
-            Ok(())
-        } else {
-            Err(KetError::InvalidQASM)
-        }
+        assert!(qasm.contains("h q[0];\ncx q[0], q[1];\n"), "{qasm}");
+        assert!(qasm.contains("measure q[0] -> c[0];\nmeasure q[1] -> c[1];\n"));
+        assert!(verify_qasmv2(qasm));
+        Ok(())
     }
 
     fn test_import_export(
@@ -60,16 +107,14 @@ mod tests {
             process.allocate_qubit()?;
         }
 
-        process.from_qasmv2(qasm, import_set, false).unwrap();
+        process.from_qasmv2(qasm, import_set, false)?;
         let qasm_synthesized = process.to_qasmv2(false, export_set)?;
-        if clean_qasm(expected) == clean_qasm(&*qasm_synthesized) {
-            Ok(())
-        } else {
-            println!("Original qasm: \n{}", qasm);
-            println!("Synthesized qasm: \n{}", qasm_synthesized);
-
-            Err(KetError::InvalidQASM)
-        }
+        assert_eq!(
+            clean_qasm(&qasm_synthesized),
+            clean_qasm(expected),
+            "original qasm:\n{qasm}\nsynthesized qasm:\n{qasm_synthesized}"
+        );
+        Ok(())
     }
 
     /// Tests decompostion of QELIB gates to the default instruction set U (represented as
@@ -170,4 +215,134 @@ mod tests {
         "#;
         test_import_export(qasm, expected, InstructionSet::QELIB, InstructionSet::QELIB)
     }
+
+    #[test]
+    fn test_import_limits() -> Result<(), KetError> {
+        let limited = |limits| {
+            Process::new(Configuration {
+                import_limits: limits,
+                ..Configuration::new(4)
+            })
+        };
+        let limits = super::ImportLimits {
+            max_instructions: 4,
+            max_register_size: 4,
+            allow_include: false,
+        };
+
+        let qasm = "OPENQASM 2.0;\nqreg q[2];\nh q[0];\ncx q[0], q[1];\n";
+        let mut process = limited(limits);
+        process.from_qasmv2(qasm, InstructionSet::QELIB, false)?;
+        let instructions = process.instructions_json();
+
+        for qasm in [
+            "OPENQASM 2.0;\nqreg q[2];\nh q;\nh q;\nh q;\n",
+            "OPENQASM 2.0;\nqreg q[1];\ncreg c[99999999999999999999999];\n",
+            "OPENQASM 2.0;\nqreg q[5];\n",
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[1];\n",
+        ] {
+            assert!(matches!(
+                limited(limits).from_qasmv2(qasm, InstructionSet::QELIB, false),
+                Err(KetError::ImportLimitExceeded)
+            ));
+        }
+
+        assert!(matches!(
+            limited(limits).from_qasmv2("qreg q[1]; h q[0]", InstructionSet::QELIB, false),
+            Err(KetError::InvalidQASM)
+        ));
+
+        // A program rejected midway leaves the process unchanged.
+        let qasm = "OPENQASM 2.0;\nqreg q[2];\nx q;\nh q;\nz q;\n";
+        assert!(matches!(
+            process.from_qasmv2(qasm, InstructionSet::QELIB, false),
+            Err(KetError::ImportLimitExceeded)
+        ));
+        assert_eq!(process.instructions_json(), instructions);
+        assert_eq!(process.allocate_qubit()?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_creg_map() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration {
+            optimize: false,
+            ..Configuration::new(2)
+        });
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        let both = process.measure(&qubits)?;
+        process.dump(&qubits)?;
+        let second = process.measure(&qubits[1..])?;
+
+        let map = process.creg_map();
+        assert_eq!(map.bits(both), Some(0..2));
+        assert_eq!(map.bits(second), Some(2..3));
+        assert_eq!(map.measurement(1), Some(both));
+        assert_eq!(map.measurement(3), None);
+        assert_eq!(map.result(both, "110"), Some(0b01));
+
+        let qasm = process.to_qasmv2(true, InstructionSet::QELIB)?;
+        assert!(qasm.contains("creg c[5];"));
+        assert!(qasm.contains("measure q[1] -> c[1];"));
+        assert!(qasm.contains("measure q[1] -> c[2];"));
+        assert!(qasm.contains("measure q[1] -> c[4];"));
+        assert!(qasm.contains("dump(0) q[0];"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_qubit_reuse() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(4));
+        let a = process.allocate_qubit()?;
+        let b = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::Hadamard, a)?;
+        process.ctrl_push(&[a])?;
+        process.apply_gate(QuantumGate::PauliX, b)?;
+        process.ctrl_pop()?;
+        process.free_qubit(a)?;
+        let c = process.allocate_qubit()?;
+        process.apply_gate(QuantumGate::Hadamard, c)?;
+        process.measure(&[b, c])?;
+
+        QubitCompaction.run(&mut process)?;
+        let qasm = process.to_qasmv2(false, InstructionSet::QELIB)?;
+        assert!(qasm.contains("qreg q[2];"));
+        assert!(qasm.contains("reset q[0];"));
+        assert!(qasm.contains("measure q[0] -> c[1];"));
+        assert!(verify_qasmv2(qasm));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gate_table() -> Result<(), KetError> {
+        let mut gates = crate::qasmv2::gate_table::GateTable::new();
+        gates.insert_controlled("crz", 1, 1, |angles| {
+            QuantumGate::RotationZ(angles[0].clone())
+        });
+
+        let mut process = Process::new(Configuration::new(2));
+        process.from_qasmv2_with_gates(
+            r#"OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[2];
+            crz(pi/4) q[1], q[0];
+            "#,
+            InstructionSet::QELIB,
+            false,
+            &gates,
+        )?;
+
+        match process.instructions().last() {
+            Some(Instruction::Gate {
+                gate: QuantumGate::RotationZ(_),
+                target: 0,
+                control,
+            }) => assert_eq!(control, &[1]),
+            instruction => panic!("unexpected instruction {:?}", instruction),
+        }
+        Ok(())
+    }
 }
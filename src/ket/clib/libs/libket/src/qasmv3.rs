@@ -0,0 +1,200 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! OpenQASM 3.0 export of scheduled circuits.
+//!
+//! [`to_qasmv3_scheduled`] writes a circuit with explicit timing: every idle interval
+//! of a qubit is a `delay` statement, so the statements on each qubit run back to back,
+//! and every operation is followed by a comment with its start time and duration. The
+//! schedule is produced by [`timed_schedule`](crate::passes::schedule::timed_schedule).
+//!
+//! ```text
+//! OPENQASM 3.0;
+//! include "stdgates.inc";
+//! qubit[2] q;
+//! bit[1] c;
+//! h q[0]; // start 0ns, duration 50ns
+//! delay[50ns] q[1];
+//! ctrl @ x q[0], q[1]; // start 50ns, duration 300ns
+//! ```
+//!
+//! Qubit indices are written as they are in the instructions, so the circuit should be
+//! routed, or compacted with [`QubitCompaction`](crate::passes::QubitCompaction), first.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::{KetError, Result},
+    ir::{Instruction, QuantumGate},
+    passes::{peephole::qubits, schedule::TimedInstruction},
+    qasmv2::formatter::QasmFormat,
+};
+
+fn gate_statement(gate: &QuantumGate, target: usize, control: &[usize]) -> String {
+    let format = QasmFormat::canonical();
+    let gate = match gate {
+        QuantumGate::PauliX => "x".to_string(),
+        QuantumGate::PauliY => "y".to_string(),
+        QuantumGate::PauliZ => "z".to_string(),
+        QuantumGate::Hadamard => "h".to_string(),
+        QuantumGate::RotationX(angle) => format!("rx({})", format.angle(angle)),
+        QuantumGate::RotationY(angle) => format!("ry({})", format.angle(angle)),
+        QuantumGate::RotationZ(angle) => format!("rz({})", format.angle(angle)),
+        QuantumGate::Phase(angle) => format!("p({})", format.angle(angle)),
+    };
+    let modifier = match control.len() {
+        0 => String::new(),
+        1 => "ctrl @ ".to_string(),
+        n => format!("ctrl({}) @ ", n),
+    };
+    let operands: Vec<String> = control
+        .iter()
+        .chain([&target])
+        .map(|qubit| format!("q[{}]", qubit))
+        .collect();
+    format!("{}{} {};", modifier, gate, operands.join(", "))
+}
+
+/// Returns the instructions in OpenQASM 3.0, in the order and with the timing of the
+/// schedule.
+///
+/// # Errors
+///
+/// Returns [`KetError::UnsuportedGateExport`] if there is a sample, dump, expected
+/// value, Kraus channel, or conditional gate instruction, which are not exported, or a
+/// diagonal gate or multiplexed rotation, which must be
+/// [lowered](crate::multiplexor::lower) before scheduling.
+pub fn to_qasmv3_scheduled(
+    instructions: &[Instruction],
+    schedule: &[TimedInstruction],
+) -> Result<String> {
+    let num_qubits = instructions
+        .iter()
+        .flat_map(qubits)
+        .map(|qubit| qubit + 1)
+        .max()
+        .unwrap_or(0);
+
+    let mut order = schedule.to_vec();
+    order.sort_by_key(|timed| (timed.start, timed.index));
+
+    let mut body = String::new();
+    let mut bits = 0;
+    let mut free_at: HashMap<usize, u64> = HashMap::new();
+
+    for timed in order {
+        let instruction = &instructions[timed.index];
+        let statements = match instruction {
+            Instruction::Alloc { .. } | Instruction::Free { .. } => continue,
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } => vec![gate_statement(gate, *target, control)],
+            Instruction::Measure { qubits, .. } => qubits
+                .iter()
+                .map(|qubit| {
+                    bits += 1;
+                    format!("c[{}] = measure q[{}];", bits - 1, qubit)
+                })
+                .collect(),
+            Instruction::Sample { .. }
+            | Instruction::Dump { .. }
+            | Instruction::ExpValue { .. }
+            | Instruction::KrausChannel { .. }
+            | Instruction::ConditionalGate { .. }
+            | Instruction::Diagonal { .. }
+            | Instruction::Multiplexor { .. } => return Err(KetError::UnsuportedGateExport),
+        };
+
+        for qubit in qubits(instruction) {
+            let idle = timed.start - free_at.get(&qubit).copied().unwrap_or_default();
+            if idle > 0 {
+                body += &format!("delay[{}ns] q[{}];\n", idle, qubit);
+            }
+            free_at.insert(qubit, timed.end());
+        }
+        for statement in statements {
+            body += &format!(
+                "{} // start {}ns, duration {}ns\n",
+                statement, timed.start, timed.duration
+            );
+        }
+    }
+
+    let mut header = format!(
+        "OPENQASM 3.0;\ninclude \"stdgates.inc\";\nqubit[{}] q;\n",
+        num_qubits
+    );
+    if bits > 0 {
+        header += &format!("bit[{}] c;\n", bits);
+    }
+
+    Ok(header + &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_qasmv3_scheduled;
+    use crate::{
+        error::KetError,
+        passes::{
+            schedule::{timed_schedule, GateDurations},
+            CouplingMap,
+        },
+        Configuration, Process, QuantumGate,
+    };
+
+    #[test]
+    fn scheduled_bell_pairs() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(4));
+        let qubits: Vec<usize> = (0..4)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        for pair in qubits.chunks(2) {
+            process.apply_gate(QuantumGate::Hadamard, pair[0])?;
+            process.ctrl_push(&[pair[0]])?;
+            process.apply_gate(QuantumGate::PauliX, pair[1])?;
+            process.ctrl_pop()?;
+        }
+        process.measure(&qubits[..2])?;
+
+        let coupling_map = CouplingMap::from_json(
+            r#"{"num_qubits": 4, "edges": [[0, 1], [1, 2], [2, 3]], "crosstalk": [[[0, 1], [2, 3]]]}"#,
+        )?;
+        let durations = GateDurations {
+            single_qubit: 50,
+            two_qubit: 300,
+            measure: 1000,
+            ..Default::default()
+        };
+        let schedule = timed_schedule(&process.instructions, &coupling_map, &durations);
+        let starts: Vec<u64> = schedule[4..].iter().map(|timed| timed.start).collect();
+        // The second CNOT waits for the first one, and the measurement for the CNOT.
+        assert_eq!(starts, [0, 50, 0, 350, 350]);
+
+        let qasm = to_qasmv3_scheduled(&process.instructions, &schedule)?;
+        let expected = [
+            "qubit[4] q;",
+            "bit[2] c;",
+            "h q[0]; // start 0ns, duration 50ns",
+            "h q[2]; // start 0ns, duration 50ns",
+            "delay[50ns] q[1];",
+            "ctrl @ x q[0], q[1]; // start 50ns, duration 300ns",
+            "delay[300ns] q[2];",
+            "delay[350ns] q[3];",
+            "ctrl @ x q[2], q[3]; // start 350ns, duration 300ns",
+            "c[0] = measure q[0]; // start 350ns, duration 1000ns",
+            "c[1] = measure q[1]; // start 350ns, duration 1000ns",
+        ];
+        assert_eq!(qasm.lines().skip(2).collect::<Vec<_>>(), expected);
+
+        process.sample(&qubits, 10)?;
+        let schedule = timed_schedule(&process.instructions, &coupling_map, &durations);
+        assert!(to_qasmv3_scheduled(&process.instructions, &schedule).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Execution on remote backends from a tokio runtime.
+//!
+//! The process runs in a [`ProcessHandle`], since a [`Process`](crate::Process) is not
+//! `Send`, and the requests are sent through an [`AsyncTransport`], implemented with
+//! the async HTTP client of the application.
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use super::{
+    check_status, BackendError, JobId, JobStatus, Protocol, RemoteBackend, Request, Response,
+};
+use crate::{handle::ProcessHandle, ResultData};
+
+/// Sends the requests to a backend without blocking.
+pub trait AsyncTransport {
+    /// Sends a request and returns the response, whatever its status code.
+    ///
+    /// Connection failures should be returned as [`BackendError::Unavailable`], so they
+    /// are retried.
+    fn send(
+        &mut self,
+        request: &Request,
+    ) -> impl Future<Output = Result<Response, BackendError>> + Send;
+}
+
+impl<P: Protocol, T: AsyncTransport> RemoteBackend<P, T> {
+    async fn send_async(&mut self, mut request: Request) -> Result<Response, BackendError> {
+        request.headers.push((
+            "Authorization".to_string(),
+            self.credentials.authorization(),
+        ));
+        let mut attempt = 0;
+        loop {
+            match self.transport.send(&request).await.and_then(check_status) {
+                Err(error) if error.is_transient() && attempt + 1 < self.retry.max_attempts => {
+                    tokio::time::sleep(self.retry.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Submits the instructions, see [`ExecutionBackend::submit`](super::ExecutionBackend::submit).
+    pub async fn submit_async(
+        &mut self,
+        instructions: &[crate::Instruction],
+    ) -> Result<JobId, BackendError> {
+        let request = self.protocol.submit(instructions)?;
+        let response = self.send_async(request).await?;
+        self.protocol.job_id(&response)
+    }
+
+    /// Returns the status of a job, see [`ExecutionBackend::status`](super::ExecutionBackend::status).
+    pub async fn status_async(&mut self, job: &str) -> Result<JobStatus, BackendError> {
+        let response = self.send_async(self.protocol.status(job)).await?;
+        self.protocol.parse_status(&response)
+    }
+
+    /// Returns the result of a completed job, see
+    /// [`ExecutionBackend::results`](super::ExecutionBackend::results).
+    pub async fn results_async(&mut self, job: &str) -> Result<ResultData, BackendError> {
+        let response = self.send_async(self.protocol.results(job)).await?;
+        self.protocol.parse_results(&response)
+    }
+
+    /// Cancels a job, see [`ExecutionBackend::cancel`](super::ExecutionBackend::cancel).
+    pub async fn cancel_async(&mut self, job: &str) -> Result<(), BackendError> {
+        self.send_async(self.protocol.cancel(job)).await.map(|_| ())
+    }
+}
+
+/// Runs the process of the handle on the backend and stores the result in it.
+///
+/// This is [`execute`](super::execute) for a tokio runtime: the process is prepared in
+/// the worker thread of the handle, and the job status is polled with
+/// [`tokio::time::sleep`], so the runtime must have the time driver enabled.
+///
+/// # Errors
+///
+/// The same as [`execute`](super::execute), and
+/// [`KetError::ProcessHandleClosed`](crate::error::KetError::ProcessHandleClosed) if the
+/// worker of the handle stopped.
+pub async fn execute_async<P: Protocol, T: AsyncTransport>(
+    process: &ProcessHandle,
+    backend: &mut RemoteBackend<P, T>,
+    poll_interval: Duration,
+    timeout: Option<Duration>,
+) -> Result<JobId, BackendError> {
+    let instructions = process
+        .call(|process| {
+            process
+                .prepare_for_execution()
+                .map(|_| process.instructions().to_vec())
+        })
+        .wait_async()
+        .await??;
+    let start = Instant::now();
+    let job = backend.submit_async(&instructions).await?;
+
+    loop {
+        match backend.status_async(&job).await? {
+            JobStatus::Completed => break,
+            JobStatus::Failed(message) => return Err(BackendError::JobFailed(job, message)),
+            JobStatus::Cancelled => return Err(BackendError::JobCancelled(job)),
+            JobStatus::Queued | JobStatus::Running => {}
+        }
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            backend.cancel_async(&job).await?;
+            return Err(BackendError::Timeout(job));
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+
+    let results = backend.results_async(&job).await?;
+    process
+        .call(move |process| process.set_result(results))
+        .wait_async()
+        .await??;
+    Ok(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, future::Future, time::Duration};
+
+    use super::{execute_async, AsyncTransport};
+    use crate::{
+        handle::ProcessHandle,
+        remote::{
+            BackendError, Credentials, JobId, JobStatus, Method, Protocol, RemoteBackend, Request,
+            Response, RetryPolicy,
+        },
+        Configuration, Instruction, QuantumGate, ResultData,
+    };
+
+    /// Protocol of a JSON API at `/jobs`.
+    struct Jobs;
+
+    impl Protocol for Jobs {
+        fn submit(&self, instructions: &[Instruction]) -> Result<Request, BackendError> {
+            Ok(Request::new(Method::Post, "/jobs")
+                .with_body(serde_json::to_string(instructions).unwrap()))
+        }
+
+        fn job_id(&self, response: &Response) -> Result<JobId, BackendError> {
+            Ok(response.body.clone())
+        }
+
+        fn status(&self, job: &str) -> Request {
+            Request::new(Method::Get, format!("/jobs/{}", job))
+        }
+
+        fn parse_status(&self, response: &Response) -> Result<JobStatus, BackendError> {
+            serde_json::from_str(&response.body)
+                .map_err(|error| BackendError::InvalidResponse(error.to_string()))
+        }
+
+        fn results(&self, job: &str) -> Request {
+            Request::new(Method::Get, format!("/jobs/{}/results", job))
+        }
+
+        fn parse_results(&self, response: &Response) -> Result<ResultData, BackendError> {
+            let measurements = serde_json::from_str(&response.body)
+                .map_err(|error| BackendError::InvalidResponse(error.to_string()))?;
+            Ok(ResultData {
+                measurements,
+                ..Default::default()
+            })
+        }
+
+        fn cancel(&self, job: &str) -> Request {
+            Request::new(Method::Delete, format!("/jobs/{}", job))
+        }
+    }
+
+    /// Transport that yields to the runtime and returns the scripted responses in order.
+    struct Scripted {
+        responses: VecDeque<(u16, &'static str)>,
+        requests: Vec<Request>,
+    }
+
+    impl AsyncTransport for Scripted {
+        fn send(
+            &mut self,
+            request: &Request,
+        ) -> impl Future<Output = Result<Response, BackendError>> + Send {
+            self.requests.push(request.clone());
+            let response = self.responses.pop_front();
+            async move {
+                tokio::task::yield_now().await;
+                response
+                    .map(|(status, body)| Response {
+                        status,
+                        body: body.to_string(),
+                    })
+                    .ok_or(BackendError::Unavailable("connection reset".into()))
+            }
+        }
+    }
+
+    #[test]
+    fn execute_on_runtime() -> Result<(), BackendError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let process = ProcessHandle::spawn(|| Configuration {
+                optimize: false,
+                ..Configuration::new(2)
+            });
+            let qubits = [
+                process.allocate_qubit().wait_async().await?,
+                process.allocate_qubit().wait_async().await?,
+            ];
+            process
+                .apply_gate(QuantumGate::PauliX, qubits[0])
+                .wait_async()
+                .await?;
+            process
+                .apply_gate(QuantumGate::PauliX, qubits[1])
+                .wait_async()
+                .await?;
+            let measurement = process.measure(&qubits).wait_async().await?;
+
+            let transport = Scripted {
+                responses: [
+                    (503, "busy"),
+                    (200, "7"),
+                    (200, "\"Running\""),
+                    (200, "\"Completed\""),
+                    (200, "[3]"),
+                ]
+                .into(),
+                requests: Vec::new(),
+            };
+            let retry = RetryPolicy {
+                initial_delay: Duration::ZERO,
+                ..Default::default()
+            };
+            let mut backend =
+                RemoteBackend::new(Jobs, transport, Credentials::new("secret")).with_retry(retry);
+            let job = execute_async(&process, &mut backend, Duration::ZERO, None).await?;
+
+            assert_eq!(job, "7");
+            assert_eq!(
+                process.get_measurement(measurement).wait_async().await?,
+                Some(3)
+            );
+            assert_eq!(backend.transport().requests.len(), 5);
+
+            let process = ProcessHandle::spawn(|| Configuration::new(2));
+            process.execute_async().await?;
+
+            Ok(())
+        })
+    }
+}
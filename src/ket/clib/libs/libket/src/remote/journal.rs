@@ -0,0 +1,364 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Persistent journal of submitted jobs.
+//!
+//! A [`JobJournal`] is an append-only file with one JSON record per line. [`Journaled`]
+//! records the instructions before submitting them, then the job identifier, the result,
+//! and the final status of the job, flushing each record to disk before it returns. A
+//! client that stopped with jobs in the queue of a backend opens the journal again and
+//! calls [`Journaled::recover`] to fetch their results.
+
+use std::{
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Instruction, ResultData};
+
+use super::{BackendError, ExecutionBackend, JobId, JobStatus};
+
+/// Line of the journal file. Records refer to jobs by the position of their entry.
+#[derive(Debug, Serialize, Deserialize)]
+enum Record {
+    Payload { instructions: Vec<Instruction> },
+    Submitted { entry: usize, job: JobId },
+    Result { entry: usize, result: ResultData },
+    Closed { entry: usize, status: JobStatus },
+}
+
+/// Job recorded in a journal.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    /// Submitted instructions.
+    pub instructions: Vec<Instruction>,
+
+    /// Job identifier, or `None` if the backend did not answer the submission, in which
+    /// case the job may or may not be in its queue.
+    pub job: Option<JobId>,
+
+    /// Result of the job, once fetched.
+    pub result: Option<ResultData>,
+
+    /// Final status of the job: [`JobStatus::Completed`] once the result is fetched, or
+    /// [`JobStatus::Failed`] and [`JobStatus::Cancelled`].
+    pub status: Option<JobStatus>,
+}
+
+impl JournalEntry {
+    /// Returns `true` if the job has not reached a final status.
+    pub fn is_pending(&self) -> bool {
+        self.status.is_none()
+    }
+}
+
+fn journal_error(error: impl Display) -> BackendError {
+    BackendError::Journal(error.to_string())
+}
+
+/// Append-only file of the jobs submitted to a backend.
+#[derive(Debug)]
+pub struct JobJournal {
+    file: File,
+    entries: Vec<JournalEntry>,
+}
+
+impl JobJournal {
+    /// Opens the journal file, creating it if it does not exist, and reads its entries.
+    ///
+    /// An incomplete last line, left by a client that stopped while writing it, is
+    /// removed from the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BackendError::Journal`] if the file cannot be read or written, or has
+    /// an invalid record.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BackendError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(journal_error)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content).map_err(journal_error)?;
+
+        let complete = content.rfind('\n').map_or(0, |end| end + 1);
+        if complete < content.len() {
+            file.set_len(complete as u64).map_err(journal_error)?;
+        }
+
+        let mut journal = Self {
+            file,
+            entries: Vec::new(),
+        };
+        for line in content[..complete].lines() {
+            journal.apply(serde_json::from_str(line).map_err(journal_error)?)?;
+        }
+        Ok(journal)
+    }
+
+    /// Returns every job in the journal, in the order of submission.
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Returns the jobs that have not reached a final status.
+    pub fn pending(&self) -> impl Iterator<Item = &JournalEntry> {
+        self.entries.iter().filter(|entry| entry.is_pending())
+    }
+
+    /// Returns the recorded result of a job.
+    pub fn result(&self, job: &str) -> Option<&ResultData> {
+        self.entry(job)
+            .and_then(|entry| self.entries[entry].result.as_ref())
+    }
+
+    fn entry(&self, job: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.job.as_deref() == Some(job))
+    }
+
+    fn entry_mut(&mut self, entry: usize) -> Result<&mut JournalEntry, BackendError> {
+        self.entries
+            .get_mut(entry)
+            .ok_or_else(|| journal_error(format!("unknown entry {}", entry)))
+    }
+
+    fn apply(&mut self, record: Record) -> Result<(), BackendError> {
+        match record {
+            Record::Payload { instructions } => self.entries.push(JournalEntry {
+                instructions,
+                job: None,
+                result: None,
+                status: None,
+            }),
+            Record::Submitted { entry, job } => self.entry_mut(entry)?.job = Some(job),
+            Record::Result { entry, result } => {
+                let entry = self.entry_mut(entry)?;
+                entry.result = Some(result);
+                entry.status = Some(JobStatus::Completed);
+            }
+            Record::Closed { entry, status } => self.entry_mut(entry)?.status = Some(status),
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, record: Record) -> Result<(), BackendError> {
+        let line = serde_json::to_string(&record).map_err(journal_error)?;
+        writeln!(self.file, "{}", line).map_err(journal_error)?;
+        self.file.sync_data().map_err(journal_error)?;
+        self.apply(record)
+    }
+}
+
+/// Backend that records its jobs in a [`JobJournal`].
+///
+/// The result of a job is read from the journal if it was already fetched.
+#[derive(Debug)]
+pub struct Journaled<B> {
+    backend: B,
+    journal: JobJournal,
+}
+
+impl<B: ExecutionBackend> Journaled<B> {
+    /// Records the jobs of `backend` in `journal`.
+    pub fn new(backend: B, journal: JobJournal) -> Self {
+        Self { backend, journal }
+    }
+
+    /// Returns the journal.
+    pub fn journal(&self) -> &JobJournal {
+        &self.journal
+    }
+
+    /// Fetches the results of the pending jobs that completed, and returns their
+    /// identifiers.
+    ///
+    /// Jobs still in the queue stay pending, and jobs without an identifier are left to
+    /// the caller, who may submit their instructions again.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of the backend and of the journal.
+    pub fn recover(&mut self) -> Result<Vec<JobId>, BackendError> {
+        let jobs: Vec<JobId> = self
+            .journal
+            .pending()
+            .filter_map(|entry| entry.job.clone())
+            .collect();
+
+        let mut completed = Vec::new();
+        for job in jobs {
+            if self.status(&job)? == JobStatus::Completed {
+                self.results(&job)?;
+                completed.push(job);
+            }
+        }
+        Ok(completed)
+    }
+}
+
+impl<B: ExecutionBackend> ExecutionBackend for Journaled<B> {
+    /// Records the instructions, submits them, and records the job identifier.
+    ///
+    /// A submission that fails with an error that is not
+    /// [transient](BackendError::is_transient) is recorded as failed, while the others
+    /// stay pending without an identifier.
+    fn submit(&mut self, instructions: &[Instruction]) -> Result<JobId, BackendError> {
+        let entry = self.journal.entries.len();
+        self.journal.append(Record::Payload {
+            instructions: instructions.to_vec(),
+        })?;
+        match self.backend.submit(instructions) {
+            Ok(job) => {
+                self.journal.append(Record::Submitted {
+                    entry,
+                    job: job.clone(),
+                })?;
+                Ok(job)
+            }
+            Err(error) => {
+                if !error.is_transient() {
+                    self.journal.append(Record::Closed {
+                        entry,
+                        status: JobStatus::Failed(error.to_string()),
+                    })?;
+                }
+                Err(error)
+            }
+        }
+    }
+
+    fn status(&mut self, job: &str) -> Result<JobStatus, BackendError> {
+        let status = self.backend.status(job)?;
+        if let (JobStatus::Failed(_) | JobStatus::Cancelled, Some(entry)) =
+            (&status, self.journal.entry(job))
+        {
+            self.journal.append(Record::Closed {
+                entry,
+                status: status.clone(),
+            })?;
+        }
+        Ok(status)
+    }
+
+    fn results(&mut self, job: &str) -> Result<ResultData, BackendError> {
+        if let Some(result) = self.journal.result(job) {
+            return Ok(result.clone());
+        }
+        let result = self.backend.results(job)?;
+        if let Some(entry) = self.journal.entry(job) {
+            self.journal.append(Record::Result {
+                entry,
+                result: result.clone(),
+            })?;
+        }
+        Ok(result)
+    }
+
+    fn cancel(&mut self, job: &str) -> Result<(), BackendError> {
+        self.backend.cancel(job)?;
+        if let Some(entry) = self.journal.entry(job) {
+            self.journal.append(Record::Closed {
+                entry,
+                status: JobStatus::Cancelled,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use super::{JobJournal, Journaled};
+    use crate::{
+        remote::{BackendError, ExecutionBackend, JobId, JobStatus},
+        Instruction, QuantumGate, ResultData,
+    };
+
+    /// Backend whose jobs complete once `done` is set.
+    #[derive(Default)]
+    struct Queue {
+        submitted: usize,
+        done: bool,
+    }
+
+    impl ExecutionBackend for Queue {
+        fn submit(&mut self, _: &[Instruction]) -> Result<JobId, BackendError> {
+            self.submitted += 1;
+            Ok(format!("job-{}", self.submitted))
+        }
+
+        fn status(&mut self, _: &str) -> Result<JobStatus, BackendError> {
+            Ok(if self.done {
+                JobStatus::Completed
+            } else {
+                JobStatus::Running
+            })
+        }
+
+        fn results(&mut self, _: &str) -> Result<ResultData, BackendError> {
+            Ok(ResultData {
+                measurements: vec![1],
+                ..Default::default()
+            })
+        }
+
+        fn cancel(&mut self, _: &str) -> Result<(), BackendError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn recover_after_restart() -> Result<(), BackendError> {
+        let path = std::env::temp_dir().join(format!("ket-journal-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let instructions = [Instruction::Gate {
+            gate: QuantumGate::PauliX,
+            target: 0,
+            control: vec![],
+        }];
+
+        let mut backend = Journaled::new(Queue::default(), JobJournal::open(&path)?);
+        let job = backend.submit(&instructions)?;
+        assert_eq!(backend.status(&job)?, JobStatus::Running);
+        drop(backend);
+
+        // The client stopped while writing a record.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"Result\":{{\"entry\":0,").unwrap();
+
+        let journal = JobJournal::open(&path)?;
+        let pending: Vec<_> = journal.pending().collect();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].job.as_deref(), Some("job-1"));
+        assert_eq!(pending[0].instructions.len(), 1);
+
+        let queue = Queue {
+            done: true,
+            ..Default::default()
+        };
+        let mut backend = Journaled::new(queue, journal);
+        assert_eq!(backend.recover()?, ["job-1"]);
+        assert_eq!(backend.journal().pending().count(), 0);
+        drop(backend);
+
+        let journal = JobJournal::open(&path)?;
+        assert_eq!(journal.entries().len(), 1);
+        assert_eq!(journal.result("job-1").unwrap().measurements, [1]);
+        assert_eq!(journal.pending().count(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,576 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Execution of processes on remote backends.
+//!
+//! An [`ExecutionBackend`] submits the instructions of a process as a job and returns
+//! its result, and [`execute`] waits for the job and stores the result in the process,
+//! as the [`BatchExecution`](crate::BatchExecution) of the configuration would.
+//!
+//! Vendor adapters, in or out of this crate, usually only describe their API with a
+//! [`Protocol`] and reuse [`RemoteBackend`], which adds the [`Credentials`] to every
+//! request and retries the transient failures with the backoff of a [`RetryPolicy`].
+//! Requests are sent through a [`Transport`], implemented with the HTTP client of the
+//! application, so this crate does not depend on one.
+//!
+//! Wrapping a backend with [`Journaled`] records the submitted jobs in a [`JobJournal`],
+//! so the results of jobs submitted before the client stopped can be recovered.
+//!
+//! With the `async` feature, [`execute_async`] does the same through an
+//! [`AsyncTransport`], so the application can use an async HTTP client and the job is
+//! polled without blocking the threads of its runtime.
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod journal;
+
+#[cfg(feature = "async")]
+pub use asynchronous::{execute_async, AsyncTransport};
+pub use journal::{JobJournal, JournalEntry, Journaled};
+
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::KetError, Instruction, Process, ResultData};
+
+/// Identifier of a job in a backend.
+pub type JobId = String;
+
+/// Status of a job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobStatus {
+    /// The job waits in the queue of the backend.
+    Queued,
+
+    /// The job is running.
+    Running,
+
+    /// The result is available.
+    Completed,
+
+    /// The job failed, with the message of the backend.
+    Failed(String),
+
+    /// The job was cancelled.
+    Cancelled,
+}
+
+/// Errors of a remote execution.
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum BackendError {
+    #[error("The credentials are missing: {0}")]
+    MissingCredentials(String),
+
+    #[error("The backend rejected the credentials.")]
+    Unauthorized,
+
+    #[error("The backend is temporarily unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("The request was rejected: {0}")]
+    Rejected(String),
+
+    #[error("The response of the backend is not valid: {0}")]
+    InvalidResponse(String),
+
+    #[error("The job {0} failed: {1}")]
+    JobFailed(JobId, String),
+
+    #[error("The job {0} was cancelled.")]
+    JobCancelled(JobId),
+
+    #[error("The job {0} did not complete before the timeout.")]
+    Timeout(JobId),
+
+    #[error("The job journal could not be used: {0}")]
+    Journal(String),
+
+    #[error(transparent)]
+    Process(#[from] KetError),
+}
+
+impl BackendError {
+    /// Returns `true` for the errors that may not happen again if the request is
+    /// retried.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, BackendError::Unavailable(_))
+    }
+}
+
+impl From<BackendError> for KetError {
+    fn from(error: BackendError) -> Self {
+        match error {
+            BackendError::Process(error) => error,
+            _ => KetError::RemoteExecutionFailed,
+        }
+    }
+}
+
+/// Interface of a backend that runs jobs remotely.
+pub trait ExecutionBackend {
+    /// Submits the instructions of a process and returns the job identifier.
+    fn submit(&mut self, instructions: &[Instruction]) -> Result<JobId, BackendError>;
+
+    /// Returns the status of a job.
+    fn status(&mut self, job: &str) -> Result<JobStatus, BackendError>;
+
+    /// Returns the result of a completed job.
+    fn results(&mut self, job: &str) -> Result<ResultData, BackendError>;
+
+    /// Cancels a job.
+    fn cancel(&mut self, job: &str) -> Result<(), BackendError>;
+}
+
+/// Runs the process on the backend and stores the result in it.
+///
+/// The process must not have a batch executor, so it is ready for execution after
+/// [`Process::prepare_for_execution`]. The job status is checked every `poll_interval`,
+/// and the job is cancelled if it does not complete within `timeout`.
+///
+/// # Errors
+///
+/// Returns the errors of the backend, [`BackendError::JobFailed`] or
+/// [`BackendError::JobCancelled`] if the job does not complete,
+/// [`BackendError::Timeout`], and the errors of [`Process::set_result`].
+pub fn execute(
+    process: &mut Process,
+    backend: &mut dyn ExecutionBackend,
+    poll_interval: Duration,
+    timeout: Option<Duration>,
+) -> Result<JobId, BackendError> {
+    process.prepare_for_execution()?;
+    let start = Instant::now();
+    let job = backend.submit(process.instructions())?;
+
+    loop {
+        match backend.status(&job)? {
+            JobStatus::Completed => break,
+            JobStatus::Failed(message) => return Err(BackendError::JobFailed(job, message)),
+            JobStatus::Cancelled => return Err(BackendError::JobCancelled(job)),
+            JobStatus::Queued | JobStatus::Running => {}
+        }
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            backend.cancel(&job)?;
+            return Err(BackendError::Timeout(job));
+        }
+        thread::sleep(poll_interval);
+    }
+
+    process.set_result(backend.results(&job)?)?;
+    Ok(job)
+}
+
+/// Token used to authenticate the requests.
+///
+/// The token is not shown by the `Debug` implementation, so it does not end up in logs.
+#[derive(Clone)]
+pub struct Credentials {
+    token: String,
+}
+
+impl Credentials {
+    /// Creates the credentials from a token.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+
+    /// Reads the token from an environment variable.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BackendError::MissingCredentials`] if the variable is not set or is
+    /// empty.
+    pub fn from_env(variable: &str) -> Result<Self, BackendError> {
+        match std::env::var(variable) {
+            Ok(token) if !token.is_empty() => Ok(Self::new(token)),
+            _ => Err(BackendError::MissingCredentials(format!(
+                "set the {} environment variable",
+                variable
+            ))),
+        }
+    }
+
+    /// Value of the `Authorization` header.
+    pub fn authorization(&self) -> String {
+        format!("Bearer {}", self.token)
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("token", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Retries of the requests that fail with a transient error, with exponential backoff.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of attempts of a request, including the first one.
+    pub max_attempts: u32,
+
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+
+    /// Factor applied to the delay after each retry.
+    pub multiplier: f64,
+
+    /// Largest delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(500),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the retry that follows the failed attempt `attempt`, counted from
+    /// zero.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(attempt as i32);
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+
+    /// Runs `operation` until it succeeds, fails with an error that is not
+    /// [transient](BackendError::is_transient), or runs out of attempts.
+    pub fn run<T>(
+        &self,
+        mut operation: impl FnMut() -> Result<T, BackendError>,
+    ) -> Result<T, BackendError> {
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Err(error) if error.is_transient() && attempt + 1 < self.max_attempts => {
+                    thread::sleep(self.delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// HTTP method of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// `GET`
+    Get,
+    /// `POST`
+    Post,
+    /// `DELETE`
+    Delete,
+}
+
+/// Request to a backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    /// HTTP method.
+    pub method: Method,
+
+    /// Path relative to the address of the backend.
+    pub path: String,
+
+    /// Headers, including the `Authorization` header added by [`RemoteBackend`].
+    pub headers: Vec<(String, String)>,
+
+    /// Body, usually JSON.
+    pub body: Option<String>,
+}
+
+impl Request {
+    /// Creates a request without headers or body.
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    /// Sets the body of the request.
+    pub fn with_body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+/// Response of a backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// HTTP status code.
+    pub status: u16,
+
+    /// Body.
+    pub body: String,
+}
+
+/// Sends the requests to a backend.
+pub trait Transport {
+    /// Sends a request and returns the response, whatever its status code.
+    ///
+    /// Connection failures should be returned as [`BackendError::Unavailable`], so they
+    /// are retried.
+    fn send(&mut self, request: &Request) -> Result<Response, BackendError>;
+}
+
+/// Requests and responses of the API of a backend.
+pub trait Protocol {
+    /// Request that submits the instructions.
+    fn submit(&self, instructions: &[Instruction]) -> Result<Request, BackendError>;
+
+    /// Job identifier in the response of [`Protocol::submit`].
+    fn job_id(&self, response: &Response) -> Result<JobId, BackendError>;
+
+    /// Request of the status of a job.
+    fn status(&self, job: &str) -> Request;
+
+    /// Job status in the response of [`Protocol::status`].
+    fn parse_status(&self, response: &Response) -> Result<JobStatus, BackendError>;
+
+    /// Request of the result of a job.
+    fn results(&self, job: &str) -> Request;
+
+    /// Result in the response of [`Protocol::results`].
+    fn parse_results(&self, response: &Response) -> Result<ResultData, BackendError>;
+
+    /// Request that cancels a job.
+    fn cancel(&self, job: &str) -> Request;
+}
+
+/// Backend that sends the requests of a [`Protocol`] through a [`Transport`].
+///
+/// Responses with status `401` or `403` are [`BackendError::Unauthorized`], `429` and
+/// `5xx` are [`BackendError::Unavailable`] and retried, and the other error statuses
+/// are [`BackendError::Rejected`].
+#[derive(Debug)]
+pub struct RemoteBackend<P, T> {
+    protocol: P,
+    transport: T,
+    credentials: Credentials,
+    retry: RetryPolicy,
+}
+
+impl<P, T> RemoteBackend<P, T> {
+    /// Creates a backend with the default retry policy.
+    pub fn new(protocol: P, transport: T, credentials: Credentials) -> Self {
+        Self {
+            protocol,
+            transport,
+            credentials,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Replaces the retry policy.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Returns the transport.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
+
+impl<P: Protocol, T: Transport> RemoteBackend<P, T> {
+    fn send(&mut self, mut request: Request) -> Result<Response, BackendError> {
+        request.headers.push((
+            "Authorization".to_string(),
+            self.credentials.authorization(),
+        ));
+        let transport = &mut self.transport;
+        self.retry
+            .run(|| transport.send(&request).and_then(check_status))
+    }
+}
+
+/// Maps the error statuses of a response to a [`BackendError`].
+fn check_status(response: Response) -> Result<Response, BackendError> {
+    match response.status {
+        200..=299 => Ok(response),
+        401 | 403 => Err(BackendError::Unauthorized),
+        429 | 500..=599 => Err(BackendError::Unavailable(response.body)),
+        _ => Err(BackendError::Rejected(response.body)),
+    }
+}
+
+impl<P: Protocol, T: Transport> ExecutionBackend for RemoteBackend<P, T> {
+    fn submit(&mut self, instructions: &[Instruction]) -> Result<JobId, BackendError> {
+        let request = self.protocol.submit(instructions)?;
+        let response = self.send(request)?;
+        self.protocol.job_id(&response)
+    }
+
+    fn status(&mut self, job: &str) -> Result<JobStatus, BackendError> {
+        let response = self.send(self.protocol.status(job))?;
+        self.protocol.parse_status(&response)
+    }
+
+    fn results(&mut self, job: &str) -> Result<ResultData, BackendError> {
+        let response = self.send(self.protocol.results(job))?;
+        self.protocol.parse_results(&response)
+    }
+
+    fn cancel(&mut self, job: &str) -> Result<(), BackendError> {
+        self.send(self.protocol.cancel(job)).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, time::Duration};
+
+    use super::{
+        execute, BackendError, Credentials, JobId, JobStatus, Method, Protocol, RemoteBackend,
+        Request, Response, RetryPolicy, Transport,
+    };
+    use crate::{Configuration, Instruction, Process, QuantumGate, ResultData};
+
+    /// Protocol of a JSON API at `/jobs`.
+    struct Jobs;
+
+    impl Protocol for Jobs {
+        fn submit(&self, instructions: &[Instruction]) -> Result<Request, BackendError> {
+            Ok(Request::new(Method::Post, "/jobs")
+                .with_body(serde_json::to_string(instructions).unwrap()))
+        }
+
+        fn job_id(&self, response: &Response) -> Result<JobId, BackendError> {
+            Ok(response.body.clone())
+        }
+
+        fn status(&self, job: &str) -> Request {
+            Request::new(Method::Get, format!("/jobs/{}", job))
+        }
+
+        fn parse_status(&self, response: &Response) -> Result<JobStatus, BackendError> {
+            serde_json::from_str(&response.body)
+                .map_err(|error| BackendError::InvalidResponse(error.to_string()))
+        }
+
+        fn results(&self, job: &str) -> Request {
+            Request::new(Method::Get, format!("/jobs/{}/results", job))
+        }
+
+        fn parse_results(&self, response: &Response) -> Result<ResultData, BackendError> {
+            let measurements = serde_json::from_str(&response.body)
+                .map_err(|error| BackendError::InvalidResponse(error.to_string()))?;
+            Ok(ResultData {
+                measurements,
+                ..Default::default()
+            })
+        }
+
+        fn cancel(&self, job: &str) -> Request {
+            Request::new(Method::Delete, format!("/jobs/{}", job))
+        }
+    }
+
+    /// Transport that returns the scripted responses in order and records the requests.
+    #[derive(Default)]
+    struct Scripted {
+        responses: VecDeque<Result<Response, BackendError>>,
+        requests: Vec<Request>,
+    }
+
+    impl Scripted {
+        fn new(responses: impl IntoIterator<Item = (u16, &'static str)>) -> Self {
+            Self {
+                responses: responses
+                    .into_iter()
+                    .map(|(status, body)| {
+                        Ok(Response {
+                            status,
+                            body: body.to_string(),
+                        })
+                    })
+                    .collect(),
+                requests: Vec::new(),
+            }
+        }
+    }
+
+    impl Transport for Scripted {
+        fn send(&mut self, request: &Request) -> Result<Response, BackendError> {
+            self.requests.push(request.clone());
+            self.responses
+                .pop_front()
+                .unwrap_or(Err(BackendError::Unavailable("connection reset".into())))
+        }
+    }
+
+    #[test]
+    fn execute_with_retries() -> Result<(), BackendError> {
+        let configuration = || {
+            let mut configuration = Configuration::new(2);
+            configuration.optimize = false;
+            configuration
+        };
+        let mut process = Process::new(configuration());
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(QuantumGate::PauliX, qubits[0])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.measure(&qubits)?;
+
+        let transport = Scripted::new([
+            (503, "busy"),
+            (200, "7"),
+            (200, "\"Running\""),
+            (200, "\"Completed\""),
+            (200, "[3]"),
+        ]);
+        let retry = RetryPolicy {
+            initial_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut backend =
+            RemoteBackend::new(Jobs, transport, Credentials::new("secret")).with_retry(retry);
+        let job = execute(&mut process, &mut backend, Duration::ZERO, None)?;
+
+        assert_eq!(job, "7");
+        assert_eq!(process.get_measurement(0).result, Some(3));
+        let requests = &backend.transport().requests;
+        let paths: Vec<_> = requests
+            .iter()
+            .map(|request| request.path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            ["/jobs", "/jobs", "/jobs/7", "/jobs/7", "/jobs/7/results"]
+        );
+        assert_eq!(
+            requests[0].headers,
+            [("Authorization".to_string(), "Bearer secret".to_string())]
+        );
+        assert!(!format!("{:?}", Credentials::new("secret")).contains("secret"));
+
+        let transport = Scripted::new([(401, "invalid token")]);
+        let mut backend = RemoteBackend::new(Jobs, transport, Credentials::new("expired"));
+        let mut process = Process::new(configuration());
+        assert!(matches!(
+            execute(&mut process, &mut backend, Duration::ZERO, None),
+            Err(BackendError::Unauthorized)
+        ));
+        assert_eq!(backend.transport().requests.len(), 1);
+
+        Ok(())
+    }
+}
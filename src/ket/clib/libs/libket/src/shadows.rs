@@ -0,0 +1,272 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Classical shadow tomography.
+//!
+//! A classical shadow is built by rotating every qubit with a random single-qubit
+//! Clifford gate before a computational basis measurement. For single-qubit Cliffords,
+//! the reconstruction only depends on which Pauli basis (X, Y, or Z) the rotation maps
+//! to Z, so each snapshot draws one of the three bases uniformly at random per qubit.
+//!
+//! Snapshots that share the same basis setting are scheduled as a single sample, so the
+//! number of instructions grows with the number of distinct settings rather than with
+//! the number of snapshots. After the execution, the expected value of any Pauli
+//! observable supported on the shadow qubits can be reconstructed from the stored
+//! records without running the circuit again.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::{KetError, Result},
+    grouping::sample_in_bases,
+    ir::{Pauli, PauliHamiltonian, PauliProduct},
+    Process,
+};
+
+/// Measurement record of a classical shadow snapshot.
+#[derive(Debug, Clone)]
+pub struct ShadowRecord {
+    /// Measurement basis of each shadow qubit.
+    pub basis: Vec<Pauli>,
+
+    /// Measurement outcome. The first shadow qubit is the most significant bit.
+    pub outcome: u64,
+
+    /// Number of snapshots with this basis and outcome.
+    pub count: u64,
+}
+
+/// Handle for a classical shadow scheduled with [`Process::classical_shadow`].
+#[derive(Debug, Clone)]
+pub struct ClassicalShadow {
+    /// Qubits included in the shadow.
+    pub qubits: Vec<usize>,
+
+    /// Distinct measurement basis settings.
+    pub settings: Vec<Vec<Pauli>>,
+
+    /// Sample index of each setting in the process.
+    pub samples: Vec<usize>,
+}
+
+/// SplitMix64 generator used to draw the measurement bases.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_pauli(&mut self) -> Pauli {
+        match self.next() % 3 {
+            0 => Pauli::PauliX,
+            1 => Pauli::PauliY,
+            _ => Pauli::PauliZ,
+        }
+    }
+}
+
+/// Draws `snapshots` random basis settings and returns the distinct settings with
+/// their number of occurrences, in order of first appearance.
+fn random_settings(num_qubits: usize, snapshots: u64, seed: u64) -> Vec<(Vec<Pauli>, u64)> {
+    let mut rng = SplitMix64(seed);
+    let mut index: HashMap<Vec<Pauli>, usize> = HashMap::new();
+    let mut settings: Vec<(Vec<Pauli>, u64)> = Vec::new();
+
+    for _ in 0..snapshots {
+        let basis: Vec<Pauli> = (0..num_qubits).map(|_| rng.next_pauli()).collect();
+        match index.get(&basis) {
+            Some(i) => settings[*i].1 += 1,
+            None => {
+                index.insert(basis.clone(), settings.len());
+                settings.push((basis, 1));
+            }
+        }
+    }
+
+    settings
+}
+
+/// Schedules a classical shadow of `qubits` with `snapshots` random measurements.
+///
+/// See [`Process::classical_shadow`].
+pub(crate) fn classical_shadow(
+    process: &mut Process,
+    qubits: &[usize],
+    snapshots: u64,
+    seed: u64,
+) -> Result<ClassicalShadow> {
+    if qubits.len() > 64 {
+        return Err(KetError::NumberOfQubitsExceeded);
+    }
+
+    let settings = random_settings(qubits.len(), snapshots, seed);
+
+    let samples = sample_in_bases(
+        process,
+        settings.iter().map(|(basis, shots)| {
+            let basis = qubits.iter().copied().zip(basis.iter().copied()).collect();
+            (basis, *shots)
+        }),
+    )?;
+
+    Ok(ClassicalShadow {
+        qubits: qubits.to_vec(),
+        settings: settings.into_iter().map(|(basis, _)| basis).collect(),
+        samples,
+    })
+}
+
+impl ClassicalShadow {
+    /// Returns the measurement records of the shadow.
+    ///
+    /// Returns `None` if any of the samples is not yet available.
+    pub fn records(&self, process: &Process) -> Option<Vec<ShadowRecord>> {
+        let mut records = Vec::new();
+        for (basis, sample) in self.settings.iter().zip(self.samples.iter()) {
+            let (states, counts) = process.get_sample(*sample).result.as_ref()?;
+            for (state, count) in states.iter().zip(counts.iter()) {
+                records.push(ShadowRecord {
+                    basis: basis.clone(),
+                    outcome: *state,
+                    count: *count,
+                });
+            }
+        }
+        Some(records)
+    }
+
+    /// Reconstructs the expected value of a Pauli product from the shadow.
+    ///
+    /// Each snapshot contributes `3^k` times the parity of the measured bits when the
+    /// snapshot basis matches the observable on all of its `k` qubits, and zero
+    /// otherwise. An empty product is the identity and evaluates to `1.0`.
+    ///
+    /// Returns `None` if any of the samples is not yet available, if the shadow has no
+    /// snapshots, or if the observable acts on a qubit outside the shadow.
+    pub fn expectation(&self, process: &Process, observable: &PauliProduct) -> Option<f64> {
+        let size = self.qubits.len();
+        let positions = observable
+            .iter()
+            .map(|term| {
+                self.qubits
+                    .iter()
+                    .position(|qubit| *qubit == term.qubit)
+                    .map(|position| (position, term.pauli))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let weight = 3f64.powi(positions.len() as i32);
+        let mut sum = 0.0;
+        let mut snapshots = 0u64;
+
+        for record in self.records(process)? {
+            snapshots += record.count;
+
+            if !positions
+                .iter()
+                .all(|(position, pauli)| record.basis[*position] == *pauli)
+            {
+                continue;
+            }
+
+            let mask = positions.iter().fold(0u64, |mask, (position, _)| {
+                mask | 1 << (size - 1 - position)
+            });
+            let parity = if (record.outcome & mask).count_ones() % 2 == 0 {
+                1.0
+            } else {
+                -1.0
+            };
+
+            sum += weight * parity * record.count as f64;
+        }
+
+        if snapshots == 0 {
+            None
+        } else {
+            Some(sum / snapshots as f64)
+        }
+    }
+
+    /// Reconstructs the expected value of a Pauli Hamiltonian from the shadow.
+    ///
+    /// Returns `None` under the same conditions as [`ClassicalShadow::expectation`].
+    pub fn expectation_hamiltonian(
+        &self,
+        process: &Process,
+        hamiltonian: &PauliHamiltonian,
+    ) -> Option<f64> {
+        hamiltonian
+            .products
+            .iter()
+            .zip(hamiltonian.coefficients.iter())
+            .map(|(product, coefficient)| {
+                self.expectation(process, product)
+                    .map(|value| coefficient * value)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::random_settings;
+    use crate::error::KetError;
+    use crate::{Configuration, Pauli, PauliTerm, Process, ResultData};
+
+    #[test]
+    fn settings_cover_snapshots() {
+        let settings = random_settings(3, 1000, 42);
+        assert_eq!(settings.iter().map(|(_, count)| count).sum::<u64>(), 1000);
+        assert!(settings.len() <= 27);
+        assert!(settings.iter().all(|(basis, _)| basis.len() == 3));
+    }
+
+    #[test]
+    fn reconstruct_from_records() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(1));
+        process.config.optimize = false;
+        let qubit = process.allocate_qubit()?;
+
+        let shadow = process.classical_shadow(&[qubit], 300, 7)?;
+        assert_eq!(shadow.settings.len(), 3);
+
+        process.prepare_for_execution()?;
+
+        // The |0> state: Z always yields 0, X and Y are evenly split.
+        let samples = shadow
+            .settings
+            .iter()
+            .map(|basis| match basis[0] {
+                Pauli::PauliZ => (vec![0], vec![100]),
+                _ => (vec![0, 1], vec![50, 50]),
+            })
+            .collect();
+        process.set_result(ResultData {
+            samples,
+            ..Default::default()
+        })?;
+
+        let z = vec![PauliTerm {
+            pauli: Pauli::PauliZ,
+            qubit,
+        }];
+        let x = vec![PauliTerm {
+            pauli: Pauli::PauliX,
+            qubit,
+        }];
+
+        assert!((shadow.expectation(&process, &z).unwrap() - 1.0).abs() < 1e-12);
+        assert!(shadow.expectation(&process, &x).unwrap().abs() < 1e-12);
+        assert_eq!(shadow.expectation(&process, &Vec::new()), Some(1.0));
+
+        Ok(())
+    }
+}
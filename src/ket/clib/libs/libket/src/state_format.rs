@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Text form of quantum states, for debugging.
+//!
+//! [`format_state`] writes a dump one basis state per line, in ket notation, with the
+//! first qubit on the left:
+//!
+//! ```text
+//! |00⟩ 0.707107+0.000000i p=0.500000
+//! |11⟩ 0.000000-0.707107i p=0.500000
+//! ```
+//!
+//! A [`StateFormat`] selects the notation of the amplitudes, the order of the lines, the
+//! number of lines, and a subset of the qubits. With a subset, the other qubits are
+//! traced out and each line shows the marginal probability of a basis state of the
+//! subset, as its amplitude is not defined when the qubits are entangled with the rest.
+//!
+//! ```
+//! use ket::state_format::{format_state, Notation, StateFormat};
+//! use ket::DumpData;
+//!
+//! let dump = DumpData {
+//!     basis_states: vec![vec![0b00], vec![0b11]],
+//!     amplitudes_real: vec![0.6, 0.0],
+//!     amplitudes_imag: vec![0.0, 0.8],
+//! };
+//! let format = StateFormat {
+//!     notation: Notation::Polar,
+//!     sort_by_probability: true,
+//!     precision: 2,
+//!     ..Default::default()
+//! };
+//! assert_eq!(
+//!     format_state(&dump, &[0, 1], &format),
+//!     "|11⟩ 0.80∠+0.50π p=0.64\n|00⟩ 0.60∠+0.00π p=0.36\n"
+//! );
+//!
+//! let format = StateFormat {
+//!     qubits: Some(vec![1]),
+//!     precision: 2,
+//!     ..Default::default()
+//! };
+//! assert_eq!(format_state(&dump, &[0, 1], &format), "|0⟩ p=0.36\n|1⟩ p=0.64\n");
+//! ```
+
+use std::{collections::BTreeMap, f64::consts::PI, fmt::Write};
+
+use num::complex::Complex64;
+
+use crate::{basis, ir::DumpData};
+
+/// Notation of the amplitudes in [`format_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Notation {
+    /// Real and imaginary parts, as `0.707107+0.000000i`.
+    #[default]
+    Cartesian,
+    /// Magnitude and phase in multiples of π, as `0.707107∠+0.250000π`.
+    Polar,
+}
+
+/// Options of [`format_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateFormat {
+    /// Qubits to show, in this order, tracing out the others. All qubits are shown if
+    /// `None`.
+    pub qubits: Option<Vec<usize>>,
+
+    /// Notation of the amplitudes.
+    pub notation: Notation,
+
+    /// Sorts the basis states by decreasing probability, instead of by basis state.
+    pub sort_by_probability: bool,
+
+    /// Largest number of basis states shown, followed by a line with the number of
+    /// basis states left out.
+    pub max_lines: Option<usize>,
+
+    /// Number of decimal places.
+    pub precision: usize,
+}
+
+impl Default for StateFormat {
+    fn default() -> Self {
+        Self {
+            qubits: None,
+            notation: Notation::default(),
+            sort_by_probability: false,
+            max_lines: None,
+            precision: 6,
+        }
+    }
+}
+
+/// Returns the dump as text, one basis state per line, see
+/// [`state_format`](crate::state_format).
+///
+/// `dumped_qubits` are the qubits of the dump, the first one being the most significant
+/// bit of the basis states. Qubits of [`StateFormat::qubits`] that are not dumped are
+/// ignored.
+pub fn format_state(dump: &DumpData, dumped_qubits: &[usize], format: &StateFormat) -> String {
+    let num_qubits = dumped_qubits.len();
+    let bits = |state: &[u64], positions: &[usize]| -> String {
+        positions
+            .iter()
+            .map(|position| {
+                if basis::bit(state, num_qubits - position - 1) {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect()
+    };
+    let amplitudes = dump
+        .basis_states
+        .iter()
+        .zip(&dump.amplitudes_real)
+        .zip(&dump.amplitudes_imag)
+        .map(|((state, real), imag)| (state, Complex64::new(*real, *imag)));
+
+    // Basis states with their amplitudes, or only their probabilities if marginalized.
+    let mut lines: Vec<(String, Option<Complex64>, f64)> = match &format.qubits {
+        None => amplitudes
+            .map(|(state, amplitude)| {
+                let all: Vec<usize> = (0..num_qubits).collect();
+                (bits(state, &all), Some(amplitude), amplitude.norm_sqr())
+            })
+            .collect(),
+        Some(qubits) => {
+            let positions: Vec<usize> = qubits
+                .iter()
+                .filter_map(|qubit| dumped_qubits.iter().position(|dumped| dumped == qubit))
+                .collect();
+            let mut marginal: BTreeMap<String, f64> = BTreeMap::new();
+            for (state, amplitude) in amplitudes {
+                *marginal.entry(bits(state, &positions)).or_default() += amplitude.norm_sqr();
+            }
+            marginal
+                .into_iter()
+                .map(|(bits, probability)| (bits, None, probability))
+                .collect()
+        }
+    };
+    if format.sort_by_probability {
+        lines.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    } else {
+        lines.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+
+    let precision = format.precision;
+    let shown = format.max_lines.unwrap_or(lines.len()).min(lines.len());
+    let mut text = String::new();
+    for (bits, amplitude, probability) in &lines[..shown] {
+        write!(text, "|{bits}⟩ ").unwrap();
+        match (amplitude, format.notation) {
+            (Some(amplitude), Notation::Cartesian) => write!(
+                text,
+                "{:.precision$}{:+.precision$}i ",
+                amplitude.re, amplitude.im
+            )
+            .unwrap(),
+            (Some(amplitude), Notation::Polar) => write!(
+                text,
+                "{:.precision$}∠{:+.precision$}π ",
+                amplitude.norm(),
+                amplitude.arg() / PI
+            )
+            .unwrap(),
+            (None, _) => {}
+        }
+        writeln!(text, "p={probability:.precision$}").unwrap();
+    }
+    if shown < lines.len() {
+        writeln!(text, "... {} more basis states", lines.len() - shown).unwrap();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_state, Notation, StateFormat};
+    use crate::DumpData;
+
+    #[test]
+    fn filters_and_caps() {
+        // |q0 q1 q2> = (|000> + |011> + |110>) / sqrt(3), with a phase on |110>.
+        let amplitude = 1.0 / 3f64.sqrt();
+        let dump = DumpData {
+            basis_states: vec![vec![0b000], vec![0b011], vec![0b110]],
+            amplitudes_real: vec![amplitude, amplitude, -amplitude],
+            amplitudes_imag: vec![0.0; 3],
+        };
+        let qubits = [4, 7, 9];
+
+        let format = StateFormat {
+            precision: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_state(&dump, &qubits, &format),
+            "|000⟩ 0.577+0.000i p=0.333\n|011⟩ 0.577+0.000i p=0.333\n|110⟩ -0.577+0.000i p=0.333\n"
+        );
+
+        let format = StateFormat {
+            qubits: Some(vec![4]),
+            sort_by_probability: true,
+            max_lines: Some(1),
+            precision: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_state(&dump, &qubits, &format),
+            "|0⟩ p=0.667\n... 1 more basis states\n"
+        );
+
+        let format = StateFormat {
+            qubits: Some(vec![7]),
+            notation: Notation::Polar,
+            precision: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_state(&dump, &qubits, &format),
+            "|0⟩ p=0.3\n|1⟩ p=0.7\n"
+        );
+        let format = StateFormat {
+            notation: Notation::Polar,
+            sort_by_probability: true,
+            max_lines: Some(2),
+            precision: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_state(&dump, &qubits, &format),
+            "|000⟩ 0.6∠+0.0π p=0.3\n|011⟩ 0.6∠+0.0π p=0.3\n... 1 more basis states\n"
+        );
+    }
+}
@@ -0,0 +1,624 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! State preparation from a probability distribution.
+//!
+//! The Grover-Rudolph method prepares `Σ sqrt(p(i)) |i>` one qubit at a time. For each
+//! value of the qubits already prepared, a Y rotation on the next qubit splits the
+//! probability of the prefix between its two halves. The rotation is controlled on the
+//! value of the prefix with the control stack of the process, so it uses the same
+//! multi-controlled gate synthesis as any other controlled gate.
+//!
+//! [`GroverRudolph::from_amplitudes`] prepares complex amplitudes, adding the phases
+//! with Z rotations controlled in the same way, from the last qubit to the first.
+//!
+//! [`ApproximatePreparation`] trades fidelity for gates on long amplitude vectors. It
+//! splits the state into a product of smaller states, keeping only the largest Schmidt
+//! component of each cut while the fidelity with the original state stays above a
+//! target, and prepares each factor on its own qubits, so the rotations are controlled
+//! by the qubits of their factor only.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::{state_preparation::GroverRudolph, Configuration, Process};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let preparation = GroverRudolph::new(&[0.1, 0.2, 0.3, 0.4])?.threshold(1e-6);
+//!
+//! let mut process = Process::new(Configuration::new(2));
+//! let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+//! preparation.apply(&mut process, &qubits)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use num::complex::Complex64;
+
+use crate::{
+    error::{KetError, Result},
+    ir::QuantumGate,
+    Angle, Process,
+};
+
+/// Circuit that loads a discrete probability distribution in the amplitudes of a state.
+#[derive(Debug, Clone)]
+pub struct GroverRudolph {
+    /// Rotation angle of each qubit, for each value of the previous qubits.
+    angles: Vec<Vec<f64>>,
+
+    /// Z rotation angle of each qubit, for each value of the previous qubits, empty if
+    /// the amplitudes are real and non-negative.
+    phases: Vec<Vec<f64>>,
+
+    /// Phase left after the Z rotations.
+    global_phase: f64,
+
+    /// Rotations with a smaller angle are not applied.
+    threshold: f64,
+}
+
+impl GroverRudolph {
+    /// Computes the rotations that prepare the distribution.
+    ///
+    /// The distribution is normalized, and the probability of index `i` is loaded in the
+    /// basis state `|i>`, the first qubit being the most significant bit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidDistribution`] if the length of the distribution is not
+    /// a power of two greater than one, if a probability is negative or not finite, or
+    /// if the probabilities sum to zero.
+    pub fn new(probabilities: &[f64]) -> Result<Self> {
+        let len = probabilities.len();
+        if len < 2
+            || !len.is_power_of_two()
+            || probabilities.iter().any(|p| !p.is_finite() || *p < 0.0)
+        {
+            return Err(KetError::InvalidDistribution);
+        }
+        let total: f64 = probabilities.iter().sum();
+        if total <= 0.0 {
+            return Err(KetError::InvalidDistribution);
+        }
+
+        // Probability of each value of the prefix, from the last qubit to the first.
+        let mut levels = vec![probabilities.iter().map(|p| p / total).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let merged = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| pair[0] + pair[1])
+                .collect();
+            levels.push(merged);
+        }
+        levels.reverse();
+
+        let angles = levels
+            .windows(2)
+            .map(|window| {
+                let (prefixes, halves) = (&window[0], &window[1]);
+                prefixes
+                    .iter()
+                    .enumerate()
+                    .map(|(prefix, probability)| {
+                        if *probability <= 0.0 {
+                            0.0
+                        } else {
+                            let zero = (halves[2 * prefix] / probability).clamp(0.0, 1.0);
+                            2.0 * zero.sqrt().acos()
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            angles,
+            phases: Vec::new(),
+            global_phase: 0.0,
+            threshold: 0.0,
+        })
+    }
+
+    /// Computes the rotations that prepare the state with the given amplitudes.
+    ///
+    /// The amplitudes are normalized, and the amplitude of index `i` is loaded in the
+    /// basis state `|i>`, the first qubit being the most significant bit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidDistribution`] if the length of the amplitudes is not
+    /// a power of two greater than one, if an amplitude is not finite, or if all
+    /// amplitudes are zero.
+    pub fn from_amplitudes(amplitudes: &[Complex64]) -> Result<Self> {
+        let probabilities: Vec<f64> = amplitudes.iter().map(Complex64::norm_sqr).collect();
+        let mut preparation = Self::new(&probabilities)?;
+        if amplitudes
+            .iter()
+            .all(|amplitude| amplitude.im == 0.0 && amplitude.re >= 0.0)
+        {
+            return Ok(preparation);
+        }
+
+        // Each pair of phases of the last qubit is a Z rotation by their difference,
+        // controlled by the prefix, and their mean is a phase of the prefix.
+        let mut phases: Vec<f64> = amplitudes.iter().map(|amplitude| amplitude.arg()).collect();
+        let mut levels = Vec::new();
+        while phases.len() > 1 {
+            levels.push(phases.chunks(2).map(|pair| pair[1] - pair[0]).collect());
+            phases = phases
+                .chunks(2)
+                .map(|pair| (pair[0] + pair[1]) / 2.0)
+                .collect();
+        }
+        levels.reverse();
+        preparation.phases = levels;
+        preparation.global_phase = phases[0];
+        Ok(preparation)
+    }
+
+    /// Skips the rotations with an angle smaller than `threshold`, trading precision for
+    /// fewer controlled gates.
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Returns the number of qubits of the state.
+    pub fn num_qubits(&self) -> usize {
+        self.angles.len()
+    }
+
+    /// Returns the rotation angles of each qubit, for each value of the previous qubits.
+    pub fn angles(&self) -> &[Vec<f64>] {
+        &self.angles
+    }
+
+    /// Prepares the state on `qubits`, which must be in the `|0...0>` state.
+    ///
+    /// The index of an amplitude follows the [bit order](crate::Configuration::bit_order)
+    /// of the process. The rotations of a qubit are not controlled if they are the same
+    /// for all values of the previous qubits.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidDistribution`] if the number of qubits does not match,
+    /// and the errors of applying the gates.
+    pub fn apply(&self, process: &mut Process, qubits: &[usize]) -> Result<()> {
+        if qubits.len() != self.num_qubits() {
+            return Err(KetError::InvalidDistribution);
+        }
+        let qubits = process.config.bit_order.arrange(qubits);
+        self.apply_arranged(process, &qubits)
+    }
+
+    /// Prepares the state on `qubits`, from the most to the least significant bit.
+    fn apply_arranged(&self, process: &mut Process, qubits: &[usize]) -> Result<()> {
+        for (level, angles) in self.angles.iter().enumerate() {
+            self.multiplexed(process, qubits, level, angles, QuantumGate::RotationY)?;
+        }
+        for (level, angles) in self.phases.iter().enumerate() {
+            self.multiplexed(process, qubits, level, angles, QuantumGate::RotationZ)?;
+        }
+        if self.global_phase != 0.0 {
+            process.apply_global_phase(Angle::Scalar(self.global_phase))?;
+        }
+        Ok(())
+    }
+
+    /// Applies the rotation of each value of the qubits before `level` on the qubit at
+    /// `level`.
+    fn multiplexed(
+        &self,
+        process: &mut Process,
+        qubits: &[usize],
+        level: usize,
+        angles: &[f64],
+        gate: fn(Angle) -> QuantumGate,
+    ) -> Result<()> {
+        let target = qubits[level];
+        if angles
+            .iter()
+            .all(|angle| (angle - angles[0]).abs() <= self.threshold)
+        {
+            return self.rotate(process, gate, angles[0], target);
+        }
+
+        let controls = &qubits[..level];
+        for (prefix, angle) in angles.iter().enumerate() {
+            if angle.abs() <= self.threshold {
+                continue;
+            }
+            let zeros: Vec<usize> = controls
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| (prefix >> (level - 1 - index)) & 1 == 0)
+                .map(|(_, qubit)| *qubit)
+                .collect();
+
+            for qubit in &zeros {
+                process.apply_gate(QuantumGate::PauliX, *qubit)?;
+            }
+            process.ctrl_push(controls)?;
+            self.rotate(process, gate, *angle, target)?;
+            process.ctrl_pop()?;
+            for qubit in &zeros {
+                process.apply_gate(QuantumGate::PauliX, *qubit)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn rotate(
+        &self,
+        process: &mut Process,
+        gate: fn(Angle) -> QuantumGate,
+        angle: f64,
+        target: usize,
+    ) -> Result<()> {
+        if angle.abs() <= self.threshold {
+            return Ok(());
+        }
+        process.apply_gate(gate(Angle::Scalar(angle)), target)
+    }
+}
+
+/// Tolerance of the fidelity target, for the rounding errors of exact factorizations.
+const FIDELITY_TOLERANCE: f64 = 1e-12;
+
+/// Circuit that prepares an approximation of a state as a product of smaller states.
+///
+/// Starting from the whole state, each step replaces a factor with the product of the
+/// largest Schmidt component of a cut of its qubits, choosing the factor and the cut
+/// that keep the largest fidelity with the original state. The steps stop when no cut
+/// keeps the fidelity above the target. Each factor is then prepared with
+/// [`GroverRudolph::from_amplitudes`].
+///
+/// ```
+/// # use ket::error::KetError;
+/// use ket::{state_preparation::ApproximatePreparation, Configuration, Process};
+/// use num::complex::Complex64;
+///
+/// # fn main() -> Result<(), KetError> {
+/// // Almost |+>|0>, with a small entangled component.
+/// let amplitudes = [1.0, 0.02, 1.0, -0.02].map(|x| Complex64::new(x, 0.0));
+/// let preparation = ApproximatePreparation::new(&amplitudes, 0.999)?;
+/// assert_eq!(preparation.factors(), [1, 1]);
+/// assert!(preparation.fidelity() >= 0.999);
+///
+/// let mut process = Process::new(Configuration::new(2));
+/// let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+/// preparation.apply(&mut process, &qubits)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ApproximatePreparation {
+    /// Number of qubits and preparation of each factor, from the first qubit.
+    factors: Vec<(usize, GroverRudolph)>,
+
+    /// Fidelity of the prepared state with the original state.
+    fidelity: f64,
+}
+
+impl ApproximatePreparation {
+    /// Computes the factors of an approximation of the state with the given amplitudes,
+    /// with a fidelity of at least `fidelity` with it.
+    ///
+    /// The amplitudes are normalized, and the amplitude of index `i` is loaded in the
+    /// basis state `|i>`, the first qubit being the most significant bit. With
+    /// `fidelity` 1, the state is only factored where it is a product state.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidDistribution`] if the length of the amplitudes is not
+    /// a power of two greater than one, if an amplitude is not finite, if all
+    /// amplitudes are zero, or if `fidelity` is not in `(0, 1]`.
+    pub fn new(amplitudes: &[Complex64], fidelity: f64) -> Result<Self> {
+        let len = amplitudes.len();
+        let norm = amplitudes
+            .iter()
+            .map(Complex64::norm_sqr)
+            .sum::<f64>()
+            .sqrt();
+        if len < 2
+            || !len.is_power_of_two()
+            || !norm.is_finite()
+            || norm <= 0.0
+            || !(fidelity > 0.0 && fidelity <= 1.0)
+        {
+            return Err(KetError::InvalidDistribution);
+        }
+        let state: Vec<Complex64> = amplitudes.iter().map(|x| x / norm).collect();
+
+        let mut factors = vec![state.clone()];
+        let mut achieved = 1.0;
+        loop {
+            let mut best: Option<(f64, Vec<Vec<Complex64>>)> = None;
+            for (index, factor) in factors.iter().enumerate() {
+                let num_qubits = factor.len().trailing_zeros() as usize;
+                for cut in 1..num_qubits {
+                    let (first, second) = schmidt(factor, 1 << cut);
+                    let mut candidate = factors.clone();
+                    candidate.splice(index..=index, [first, second]);
+                    let candidate_fidelity = overlap(&state, &product(&candidate)).norm_sqr();
+                    if best
+                        .as_ref()
+                        .is_none_or(|(best, _)| candidate_fidelity > *best)
+                    {
+                        best = Some((candidate_fidelity, candidate));
+                    }
+                }
+            }
+            match best {
+                Some((candidate_fidelity, candidate))
+                    if candidate_fidelity >= fidelity - FIDELITY_TOLERANCE =>
+                {
+                    factors = candidate;
+                    achieved = candidate_fidelity.min(1.0);
+                }
+                _ => break,
+            }
+        }
+
+        // The global phase of the product matches the original state.
+        let phase = overlap(&product(&factors), &state);
+        if phase.norm() > 0.0 {
+            let phase = phase / phase.norm();
+            factors[0].iter_mut().for_each(|x| *x *= phase);
+        }
+
+        let factors = factors
+            .iter()
+            .map(|factor| {
+                Ok((
+                    factor.len().trailing_zeros() as usize,
+                    GroverRudolph::from_amplitudes(factor)?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            factors,
+            fidelity: achieved,
+        })
+    }
+
+    /// Returns the fidelity of the prepared state with the original state.
+    pub fn fidelity(&self) -> f64 {
+        self.fidelity
+    }
+
+    /// Returns the number of qubits of the state.
+    pub fn num_qubits(&self) -> usize {
+        self.factors.iter().map(|(num_qubits, _)| num_qubits).sum()
+    }
+
+    /// Returns the number of qubits of each factor, from the first qubit.
+    pub fn factors(&self) -> Vec<usize> {
+        self.factors
+            .iter()
+            .map(|(num_qubits, _)| *num_qubits)
+            .collect()
+    }
+
+    /// Prepares the approximate state on `qubits`, which must be in the `|0...0>`
+    /// state.
+    ///
+    /// The index of an amplitude follows the [bit order](crate::Configuration::bit_order)
+    /// of the process, as in [`GroverRudolph::apply`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KetError::InvalidDistribution`] if the number of qubits does not match,
+    /// and the errors of applying the gates.
+    pub fn apply(&self, process: &mut Process, qubits: &[usize]) -> Result<()> {
+        if qubits.len() != self.num_qubits() {
+            return Err(KetError::InvalidDistribution);
+        }
+        let qubits = process.config.bit_order.arrange(qubits);
+        let mut start = 0;
+        for (num_qubits, preparation) in &self.factors {
+            preparation.apply_arranged(process, &qubits[start..start + num_qubits])?;
+            start += num_qubits;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `<a|b>`.
+fn overlap(a: &[Complex64], b: &[Complex64]) -> Complex64 {
+    a.iter().zip(b).map(|(a, b)| a.conj() * b).sum()
+}
+
+/// Returns the tensor product of the factors, the first one being the most significant.
+fn product(factors: &[Vec<Complex64>]) -> Vec<Complex64> {
+    factors
+        .iter()
+        .fold(vec![Complex64::new(1.0, 0.0)], |state, factor| {
+            state
+                .iter()
+                .flat_map(|x| factor.iter().map(move |y| x * y))
+                .collect()
+        })
+}
+
+/// Returns the normalized states of the largest Schmidt component of the cut of a state
+/// after its first `rows` basis states, by power iteration.
+fn schmidt(state: &[Complex64], rows: usize) -> (Vec<Complex64>, Vec<Complex64>) {
+    let columns = state.len() / rows;
+    let entry = |row: usize, column: usize| state[row * columns + column];
+    let normalize = |vector: Vec<Complex64>| {
+        let norm = vector.iter().map(Complex64::norm_sqr).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vector.into_iter().map(|x| x / norm).collect()
+        } else {
+            vector
+        }
+    };
+
+    // Starts from the row with the largest norm, which is not orthogonal to the
+    // largest right singular vector.
+    let start = (0..rows)
+        .max_by(|a, b| {
+            let norm = |row: usize| (0..columns).map(|c| entry(row, c).norm_sqr()).sum::<f64>();
+            norm(*a).total_cmp(&norm(*b))
+        })
+        .unwrap();
+    let mut right: Vec<Complex64> =
+        normalize((0..columns).map(|c| entry(start, c).conj()).collect());
+    let mut left = vec![Complex64::new(0.0, 0.0); rows];
+    for _ in 0..256 {
+        left = normalize(
+            (0..rows)
+                .map(|r| (0..columns).map(|c| entry(r, c) * right[c]).sum())
+                .collect(),
+        );
+        let next: Vec<Complex64> = normalize(
+            (0..columns)
+                .map(|c| (0..rows).map(|r| entry(r, c).conj() * left[r]).sum())
+                .collect(),
+        );
+        let change: f64 = next
+            .iter()
+            .zip(&right)
+            .map(|(a, b)| (a - b).norm_sqr())
+            .sum();
+        right = next;
+        if change < 1e-28 {
+            break;
+        }
+    }
+    (left, right.iter().map(Complex64::conj).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApproximatePreparation, GroverRudolph};
+    use crate::{error::KetError, BitOrder, Configuration, Instruction, Process, QuantumGate};
+    use num::complex::Complex64;
+
+    /// Returns the targets of the Y rotations with a nonzero angle.
+    fn rotated(process: &Process) -> Vec<usize> {
+        process
+            .instructions()
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Gate {
+                    gate: QuantumGate::RotationY(angle),
+                    target,
+                    ..
+                } if angle.radians().abs() > 1e-12 => Some(*target),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bit_order() -> Result<(), KetError> {
+        // The amplitude of index 1 is |01> from the most significant bit, and |10> from
+        // the least significant one.
+        let amplitudes = [0.0, 1.0, 0.0, 0.0].map(|x| Complex64::new(x, 0.0));
+        for (order, flipped) in [(BitOrder::MsbFirst, 1), (BitOrder::LsbFirst, 0)] {
+            let mut configuration = Configuration::new(4);
+            configuration.bit_order = order;
+            let mut process = Process::new(configuration);
+            let qubits: Vec<usize> = (0..4)
+                .map(|_| process.allocate_qubit())
+                .collect::<Result<_, _>>()?;
+
+            GroverRudolph::from_amplitudes(&amplitudes)?.apply(&mut process, &qubits[..2])?;
+            ApproximatePreparation::new(&amplitudes, 1.0)?.apply(&mut process, &qubits[2..])?;
+            assert_eq!(rotated(&process), [qubits[flipped], qubits[2 + flipped]]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rotations() -> Result<(), KetError> {
+        let preparation = GroverRudolph::new(&[1.0, 1.0, 0.0, 2.0])?;
+        let half = std::f64::consts::FRAC_PI_2;
+        assert_eq!(preparation.num_qubits(), 2);
+        assert!((preparation.angles()[0][0] - half).abs() < 1e-12);
+        assert!((preparation.angles()[1][0] - half).abs() < 1e-12);
+        assert!((preparation.angles()[1][1] - std::f64::consts::PI).abs() < 1e-12);
+
+        // The uniform distribution needs no controlled rotation.
+        let mut process = Process::new(Configuration::new(3));
+        let qubits: Vec<usize> = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        GroverRudolph::new(&[0.5; 8])?.apply(&mut process, &qubits)?;
+        assert!(process
+            .instructions()
+            .iter()
+            .all(|instruction| match instruction {
+                Instruction::Alloc { .. } => true,
+                Instruction::Gate { control, .. } => control.is_empty(),
+                _ => false,
+            }));
+
+        for distribution in [&[1.0][..], &[1.0, 2.0, 3.0], &[0.0, 0.0], &[-1.0, 2.0]] {
+            assert!(matches!(
+                GroverRudolph::new(distribution),
+                Err(KetError::InvalidDistribution)
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn approximate() -> Result<(), KetError> {
+        let real = |amplitudes: &[f64]| -> Vec<Complex64> {
+            amplitudes.iter().map(|x| Complex64::new(*x, 0.0)).collect()
+        };
+
+        // A GHZ state has no product approximation better than 1/2.
+        let ghz = real(&[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        let preparation = ApproximatePreparation::new(&ghz, 0.9)?;
+        assert_eq!(preparation.factors(), [3]);
+        assert!((preparation.fidelity() - 1.0).abs() < 1e-12);
+        assert_eq!(ApproximatePreparation::new(&ghz, 0.5)?.factors(), [1, 1, 1]);
+
+        // |+> (|0> + 2i|1>) |1> with a small perturbation.
+        let mut state: Vec<Complex64> = (0..8)
+            .map(|index| {
+                let second = if index & 2 == 0 {
+                    Complex64::new(1.0, 0.0)
+                } else {
+                    Complex64::new(0.0, 2.0)
+                };
+                let third = if index & 1 == 0 { 0.0 } else { 1.0 };
+                second * third
+            })
+            .collect();
+        state[0] += 0.05;
+        let preparation = ApproximatePreparation::new(&state, 0.99)?;
+        assert_eq!(preparation.factors(), [1, 1, 1]);
+        assert!(preparation.fidelity() >= 0.99 && preparation.fidelity() < 1.0);
+        assert_eq!(ApproximatePreparation::new(&state, 1.0)?.factors(), [3]);
+
+        let mut process = Process::new(Configuration::new(3));
+        let qubits: Vec<usize> = (0..3)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<_, _>>()?;
+        preparation.apply(&mut process, &qubits)?;
+        assert!(process
+            .instructions()
+            .iter()
+            .all(|instruction| match instruction {
+                Instruction::Gate { control, .. } => control.is_empty(),
+                _ => true,
+            }));
+
+        for fidelity in [0.0, 1.5, f64::NAN] {
+            assert!(matches!(
+                ApproximatePreparation::new(&ghz, fidelity),
+                Err(KetError::InvalidDistribution)
+            ));
+        }
+        Ok(())
+    }
+}
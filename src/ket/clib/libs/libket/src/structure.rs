@@ -0,0 +1,271 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hierarchical view of the control and inverse scopes of a process.
+//!
+//! The process flattens every scope into its instructions: a gate in a control scope
+//! receives the control qubits of the scope, and the gates of an inverse scope are
+//! inverted and reversed. [`Process::structure`](crate::Process::structure) returns the
+//! same circuit as a tree of [`Node`]s instead, so a controlled QFT is a single node
+//! whose body is the QFT, and [`flatten`] recovers the instructions.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ir::{Angle, Instruction, QuantumGate};
+
+/// Node of the hierarchical view of a process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Node {
+    /// Instruction as written in its scope, without the control qubits of the
+    /// enclosing control scopes and not inverted by the enclosing inverse scopes.
+    Instruction(Instruction),
+
+    /// Global phase, which becomes a phase gate on the control qubits of the enclosing
+    /// control scopes.
+    GlobalPhase(Angle),
+
+    /// Control scope.
+    Controlled {
+        /// Control qubits added by the scope.
+        control: Vec<usize>,
+        /// Nodes in the scope.
+        body: Vec<Node>,
+    },
+
+    /// Inverse scope.
+    Adjoint {
+        /// Nodes in the scope, in the order they were written.
+        body: Vec<Node>,
+    },
+}
+
+impl Node {
+    /// Returns the number of instructions of the node once flattened.
+    pub fn instruction_count(&self) -> usize {
+        match self {
+            Node::Instruction(_) | Node::GlobalPhase(_) => 1,
+            Node::Controlled { body, .. } | Node::Adjoint { body } => {
+                body.iter().map(Node::instruction_count).sum()
+            }
+        }
+    }
+
+    fn flatten_into(&self, control: &[usize], adjoint: bool, result: &mut Vec<Instruction>) {
+        let gate = |gate: &QuantumGate, target: usize, control: Vec<usize>| Instruction::Gate {
+            gate: if adjoint {
+                gate.inverse()
+            } else {
+                gate.clone()
+            },
+            target,
+            control,
+        };
+        match self {
+            Node::Instruction(Instruction::Gate {
+                gate: quantum_gate,
+                target,
+                control: own,
+            }) => result.push(gate(quantum_gate, *target, [control, own].concat())),
+            Node::Instruction(instruction) => result.push(instruction.clone()),
+            Node::GlobalPhase(angle) => {
+                if let Some((target, control)) = control.split_first() {
+                    result.push(gate(
+                        &QuantumGate::Phase(angle.clone()),
+                        *target,
+                        control.to_vec(),
+                    ));
+                }
+            }
+            Node::Controlled { control: own, body } => {
+                let control = [control, own].concat();
+                for node in body {
+                    node.flatten_into(&control, adjoint, result);
+                }
+            }
+            Node::Adjoint { body } => {
+                for node in body.iter().rev() {
+                    node.flatten_into(control, !adjoint, result);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the instructions of the nodes, applying the control and inverse scopes.
+pub fn flatten(nodes: &[Node]) -> Vec<Instruction> {
+    let mut result = Vec::new();
+    for node in nodes {
+        node.flatten_into(&[], false, &mut result);
+    }
+    result
+}
+
+/// Scope opened in the process.
+#[derive(Debug, Clone)]
+enum Scope {
+    Controlled(Vec<usize>),
+    Adjoint,
+}
+
+/// Records the hierarchical view while the process is built.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StructureRecorder {
+    nodes: Vec<Node>,
+    scopes: Vec<(Scope, Vec<Node>)>,
+    /// Number of instructions of the recorded nodes.
+    recorded: usize,
+    /// Set to `false` if the scopes are not closed in the order they were opened.
+    nested: bool,
+}
+
+impl StructureRecorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            nested: true,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn push(&mut self, node: Node) {
+        self.recorded += node.instruction_count();
+        match self.scopes.last_mut() {
+            Some((_, body)) => body.push(node),
+            None => self.nodes.push(node),
+        }
+    }
+
+    pub(crate) fn begin_controlled(&mut self, control: &[usize]) {
+        self.scopes
+            .push((Scope::Controlled(control.to_vec()), Vec::new()));
+    }
+
+    pub(crate) fn begin_adjoint(&mut self) {
+        self.scopes.push((Scope::Adjoint, Vec::new()));
+    }
+
+    pub(crate) fn end_controlled(&mut self) {
+        self.end(|scope| matches!(scope, Scope::Controlled(_)));
+    }
+
+    pub(crate) fn end_adjoint(&mut self) {
+        self.end(|scope| matches!(scope, Scope::Adjoint));
+    }
+
+    fn end(&mut self, expected: impl Fn(&Scope) -> bool) {
+        let Some((scope, body)) = self.scopes.pop() else {
+            return;
+        };
+        self.nested &= expected(&scope);
+        let node = match scope {
+            Scope::Controlled(control) => Node::Controlled { control, body },
+            Scope::Adjoint => Node::Adjoint { body },
+        };
+        match self.scopes.last_mut() {
+            Some((_, parent)) => parent.push(node),
+            None => self.nodes.push(node),
+        }
+    }
+
+    /// Restarts the view from the flat instructions, after they were rewritten by a
+    /// pass.
+    pub(crate) fn reset(&mut self, instructions: &[Instruction]) {
+        *self = Self::new();
+        for instruction in instructions {
+            self.push(Node::Instruction(instruction.clone()));
+        }
+    }
+
+    /// Returns the recorded view if it still describes the instructions, or the flat
+    /// view otherwise.
+    pub(crate) fn nodes(&self, instructions: &[Instruction]) -> Vec<Node> {
+        if self.nested && self.scopes.is_empty() && self.recorded == instructions.len() {
+            self.nodes.clone()
+        } else {
+            instructions
+                .iter()
+                .cloned()
+                .map(Node::Instruction)
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flatten, Node};
+    use crate::{
+        ir::{Angle, QuantumGate},
+        Configuration, Process,
+    };
+
+    fn qft(process: &mut Process, qubits: &[usize]) {
+        for (i, qubit) in qubits.iter().enumerate() {
+            process.apply_gate(QuantumGate::Hadamard, *qubit).unwrap();
+            for (k, control) in qubits[i + 1..].iter().enumerate() {
+                process.ctrl_push(&[*control]).unwrap();
+                process
+                    .apply_gate(
+                        QuantumGate::Phase(Angle::pi_fraction(1, 1 << (k + 1))),
+                        *qubit,
+                    )
+                    .unwrap();
+                process.ctrl_pop().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn controlled_adjoint_qft() {
+        let mut process = Process::new(Configuration::new(4));
+        let qubits: Vec<usize> = (0..4).map(|_| process.allocate_qubit().unwrap()).collect();
+
+        process.ctrl_push(&[qubits[0]]).unwrap();
+        process.adj_begin().unwrap();
+        qft(&mut process, &qubits[1..]);
+        process
+            .apply_global_phase(Angle::pi_fraction(1, 4))
+            .unwrap();
+        process.adj_end().unwrap();
+        process.ctrl_pop().unwrap();
+        process.measure(&qubits).unwrap();
+
+        let nodes = process.structure();
+        assert_eq!(nodes.len(), 6);
+        let Node::Controlled { control, body } = &nodes[4] else {
+            panic!()
+        };
+        assert_eq!(control, &[qubits[0]]);
+        assert!(matches!(&body[..], [Node::Adjoint { .. }]));
+        assert_eq!(nodes[4].instruction_count(), 7);
+
+        assert_eq!(
+            serde_json::to_string(&flatten(&nodes)).unwrap(),
+            serde_json::to_string(process.instructions()).unwrap()
+        );
+    }
+
+    #[test]
+    fn flat_view_with_open_scope() {
+        let mut process = Process::new(Configuration::new(2));
+        let qubits = [
+            process.allocate_qubit().unwrap(),
+            process.allocate_qubit().unwrap(),
+        ];
+        process.ctrl_push(&[qubits[0]]).unwrap();
+        process.apply_gate(QuantumGate::PauliX, qubits[1]).unwrap();
+
+        let nodes = process.structure();
+        assert_eq!(nodes.len(), 3);
+        assert!(nodes
+            .iter()
+            .all(|node| matches!(node, Node::Instruction(_))));
+
+        process.ctrl_pop().unwrap();
+        assert!(matches!(
+            &process.structure()[2],
+            Node::Controlled { body, .. } if body.len() == 1
+        ));
+    }
+}
@@ -0,0 +1,306 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Circuit templates over placeholder qubits.
+//!
+//! A [`Template`] is recorded once on `n` placeholder qubits and applied any number of
+//! times on concrete qubits of a process with [`Process::apply_template`]. The template
+//! keeps the control and inverse scopes and the global phases of the recorded circuit,
+//! so an application inside a control or inverse scope is the same as writing the
+//! circuit there, without computing the qubit indices by hand.
+//!
+//! ```
+//! # use ket::{template::Template, Configuration, Process, QuantumGate};
+//! # fn main() -> Result<(), ket::error::KetError> {
+//! let bell = Template::record(2, |process, qubits| {
+//!     process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+//!     process.ctrl_push(&[qubits[0]])?;
+//!     process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+//!     process.ctrl_pop()
+//! })?;
+//!
+//! let mut process = Process::new(Configuration::new(3));
+//! let qubits: Vec<usize> = (0..3).map(|_| process.allocate_qubit()).collect::<Result<_, _>>()?;
+//! process.apply_template(&bell, &[qubits[2], qubits[0]])?;
+//! process.ctrl_push(&[qubits[1]])?;
+//! process.apply_template(&bell, &[qubits[0], qubits[2]])?;
+//! process.ctrl_pop()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{KetError, Result},
+    ir::Instruction,
+    structure::{flatten, Node},
+    Configuration, Process,
+};
+
+/// Circuit recorded on placeholder qubits `0..num_qubits`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Template {
+    num_qubits: usize,
+    body: Vec<Node>,
+}
+
+impl Template {
+    /// Records the circuit built by `build` on `num_qubits` placeholder qubits.
+    ///
+    /// The closure receives a process and the placeholder qubits, and may only apply
+    /// gates and global phases, and open and close control and inverse scopes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `build`, and [`KetError::InvalidTemplate`] if the circuit
+    /// allocates, frees, or measures qubits, or leaves a scope open.
+    pub fn record(
+        num_qubits: usize,
+        build: impl FnOnce(&mut Process, &[usize]) -> Result<()>,
+    ) -> Result<Self> {
+        let mut process = Process::new(Configuration::new(num_qubits + 1));
+        let placeholders = (0..num_qubits)
+            .map(|_| process.allocate_qubit())
+            .collect::<Result<Vec<_>>>()?;
+
+        // Recording in a control scope keeps the global phases, which the process
+        // ignores outside of one.
+        let anchor = process.allocate_qubit()?;
+        process.ctrl_push(&[anchor])?;
+        build(&mut process, &placeholders)?;
+        if process.ctrl_stack.len() != 1 || !process.adj_stack.is_empty() {
+            return Err(KetError::InvalidTemplate);
+        }
+        process.ctrl_pop()?;
+
+        let body = process
+            .structure()
+            .into_iter()
+            .find_map(|node| match node {
+                Node::Controlled { control, body } if control == [anchor] => Some(body),
+                _ => None,
+            })
+            .ok_or(KetError::InvalidTemplate)?;
+
+        if !body.iter().all(is_unitary) {
+            return Err(KetError::InvalidTemplate);
+        }
+
+        Ok(Self { num_qubits, body })
+    }
+
+    /// Number of placeholder qubits.
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    /// Recorded circuit as a tree of scopes, on the placeholder qubits.
+    pub fn nodes(&self) -> &[Node] {
+        &self.body
+    }
+
+    /// Recorded circuit as gates, on the placeholder qubits.
+    ///
+    /// The global phases of the template only appear once it is applied in a control
+    /// scope.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        flatten(&self.body)
+    }
+}
+
+fn is_unitary(node: &Node) -> bool {
+    match node {
+        Node::Instruction(instruction) => matches!(instruction, Instruction::Gate { .. }),
+        Node::GlobalPhase(_) => true,
+        Node::Controlled { body, .. } | Node::Adjoint { body } => body.iter().all(is_unitary),
+    }
+}
+
+fn apply_nodes(process: &mut Process, nodes: &[Node], qubits: &[usize]) -> Result<()> {
+    let map = |placeholders: &[usize]| -> Vec<usize> {
+        placeholders.iter().map(|qubit| qubits[*qubit]).collect()
+    };
+
+    for node in nodes {
+        match node {
+            Node::Instruction(Instruction::Gate {
+                gate,
+                target,
+                control,
+            }) => {
+                if control.is_empty() {
+                    process.apply_gate(gate.clone(), qubits[*target])?;
+                } else {
+                    process.ctrl_push(&map(control))?;
+                    process.apply_gate(gate.clone(), qubits[*target])?;
+                    process.ctrl_pop()?;
+                }
+            }
+            Node::Instruction(_) => return Err(KetError::InvalidTemplate),
+            Node::GlobalPhase(angle) => process.apply_global_phase(angle.clone())?,
+            Node::Controlled { control, body } => {
+                process.ctrl_push(&map(control))?;
+                apply_nodes(process, body, qubits)?;
+                process.ctrl_pop()?;
+            }
+            Node::Adjoint { body } => {
+                process.adj_begin()?;
+                apply_nodes(process, body, qubits)?;
+                process.adj_end()?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the template with placeholder `i` bound to `qubits[i]`, see
+/// [`Process::apply_template`].
+pub(crate) fn apply_template(
+    process: &mut Process,
+    template: &Template,
+    qubits: &[usize],
+) -> Result<()> {
+    let distinct = qubits
+        .iter()
+        .enumerate()
+        .all(|(i, qubit)| !qubits[..i].contains(qubit));
+    if qubits.len() != template.num_qubits || !distinct {
+        return Err(KetError::InvalidTemplate);
+    }
+    apply_nodes(process, &template.body, qubits)
+}
+
+/// Applies `template` `2^k` times inside a control scope on `control`.
+pub(crate) fn apply_power(
+    process: &mut Process,
+    template: &Template,
+    qubits: &[usize],
+    k: u32,
+    control: &[usize],
+) -> Result<()> {
+    let power = 1u64.checked_shl(k).ok_or(KetError::InvalidTemplate)?;
+    if control.iter().any(|qubit| qubits.contains(qubit)) {
+        return Err(KetError::InvalidTemplate);
+    }
+    if !control.is_empty() {
+        process.ctrl_push(control)?;
+    }
+    let result = (0..power).try_for_each(|_| apply_template(process, template, qubits));
+    if !control.is_empty() {
+        process.ctrl_pop()?;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+    use crate::{
+        error::KetError,
+        ir::{Angle, QuantumGate},
+        Configuration, Process,
+    };
+
+    fn controlled_phase(process: &mut Process, qubits: &[usize]) -> Result<(), KetError> {
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::Phase(Angle::pi_fraction(1, 4)), qubits[1])?;
+        process.ctrl_pop()?;
+        process.adj_begin()?;
+        process.apply_gate(QuantumGate::RotationY(Angle::Scalar(0.3)), qubits[1])?;
+        process.adj_end()?;
+        process.apply_global_phase(Angle::pi_fraction(1, 2))
+    }
+
+    fn allocate(process: &mut Process, n: usize) -> Vec<usize> {
+        (0..n).map(|_| process.allocate_qubit().unwrap()).collect()
+    }
+
+    #[test]
+    fn apply_in_scopes() -> Result<(), KetError> {
+        let template = Template::record(2, controlled_phase)?;
+        assert_eq!(template.num_qubits(), 2);
+        assert_eq!(template.instructions().len(), 3);
+
+        let mut expected = Process::new(Configuration::new(3));
+        let qubits = allocate(&mut expected, 3);
+        controlled_phase(&mut expected, &[qubits[2], qubits[1]])?;
+        expected.ctrl_push(&[qubits[0]])?;
+        expected.adj_begin()?;
+        controlled_phase(&mut expected, &[qubits[1], qubits[2]])?;
+        expected.adj_end()?;
+        expected.ctrl_pop()?;
+
+        let mut process = Process::new(Configuration::new(3));
+        let qubits = allocate(&mut process, 3);
+        process.apply_template(&template, &[qubits[2], qubits[1]])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.adj_begin()?;
+        process.apply_template(&template, &[qubits[1], qubits[2]])?;
+        process.adj_end()?;
+        process.ctrl_pop()?;
+
+        assert_eq!(
+            serde_json::to_string(process.instructions()).unwrap(),
+            serde_json::to_string(expected.instructions()).unwrap()
+        );
+        // The global phase is a phase gate on the control qubit in the second
+        // application.
+        assert_eq!(process.instructions().len(), 3 + 3 + 4);
+
+        assert!(process.apply_template(&template, &[qubits[0]]).is_err());
+        assert!(process
+            .apply_template(&template, &[qubits[0], qubits[0]])
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn controlled_power() -> Result<(), KetError> {
+        let template = Template::record(2, controlled_phase)?;
+
+        let mut expected = Process::new(Configuration::new(3));
+        let qubits = allocate(&mut expected, 3);
+        expected.ctrl_push(&[qubits[0]])?;
+        for _ in 0..4 {
+            controlled_phase(&mut expected, &qubits[1..])?;
+        }
+        expected.ctrl_pop()?;
+
+        let mut process = Process::new(Configuration::new(3));
+        let qubits = allocate(&mut process, 3);
+        process.apply_power(&template, &qubits[1..], 2, &qubits[..1])?;
+        assert_eq!(
+            serde_json::to_string(process.instructions()).unwrap(),
+            serde_json::to_string(expected.instructions()).unwrap()
+        );
+
+        assert!(process
+            .apply_power(&template, &qubits[1..], 1, &qubits[1..2])
+            .is_err());
+        assert!(process
+            .apply_power(&template, &qubits[1..], 64, &[])
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_non_unitary() {
+        assert!(Template::record(1, |process, qubits| {
+            process.measure(qubits)?;
+            Ok(())
+        })
+        .is_err());
+        assert!(Template::record(1, |process, _| process.ctrl_push(&[0])).is_err());
+        assert!(Template::record(1, |process, _| {
+            process.allocate_qubit()?;
+            Ok(())
+        })
+        .is_err());
+    }
+}
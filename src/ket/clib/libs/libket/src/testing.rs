@@ -0,0 +1,468 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Assertions on quantum states, for tests.
+//!
+//! [`assert_state!`](crate::assert_state) compares the amplitudes of a state and
+//! [`assert_prob!`](crate::assert_prob) compares the probabilities of its basis states,
+//! within a tolerance. The state is either a [`Process`] in live mode, whose allocated
+//! qubits are dumped, or a [`DumpData`] of a finished execution. Basis states are written
+//! as bitstrings, with the most significant bit on the left, so their bits follow
+//! [`Configuration::bit_order`](crate::Configuration::bit_order). Basis states left out
+//! of the expected list must have a zero amplitude.
+//!
+//! The global phase is removed before amplitudes are compared, and a failed assertion
+//! prints every basis state with its expected and actual values.
+//!
+//! [`verify_arithmetic`] checks an arithmetic circuit against classical arithmetic for
+//! every basis state input, and reports the inputs with a wrong output.
+//!
+//! ```
+//! use std::f64::consts::FRAC_1_SQRT_2;
+//! use ket::DumpData;
+//!
+//! let mut state = DumpData {
+//!     basis_states: vec![vec![0b00], vec![0b11]],
+//!     amplitudes_real: vec![FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+//!     amplitudes_imag: vec![0.0, 0.0],
+//! };
+//!
+//! ket::assert_state!(state, &[("00", FRAC_1_SQRT_2), ("11", FRAC_1_SQRT_2)]);
+//! ket::assert_prob!(state, &[("00", 0.5), ("11", 0.5)], 1e-3);
+//! ```
+
+use std::collections::BTreeMap;
+
+use num::complex::Complex64;
+
+use crate::{
+    basis,
+    error::{self, KetError},
+    ir::{DumpData, QuantumGate},
+    Configuration, Process,
+};
+
+/// Tolerance of the assertions when none is given.
+pub const DEFAULT_TOLERANCE: f64 = 1e-6;
+
+/// State compared by the assertions.
+pub trait StateSource {
+    /// Returns the state and, if known, its number of qubits.
+    fn state(&mut self) -> Result<(DumpData, Option<usize>), String>;
+}
+
+impl StateSource for Process {
+    /// Dumps every allocated qubit.
+    fn state(&mut self) -> Result<(DumpData, Option<usize>), String> {
+        let qubits = self.allocated_qubits();
+        let dump = self.dump(&qubits).map_err(|error| error.to_string())?;
+        match self.get_dump_data(dump) {
+            Some(data) => Ok((data, Some(qubits.len()))),
+            None => Err("the state of a process is only known in live mode".to_string()),
+        }
+    }
+}
+
+impl StateSource for DumpData {
+    fn state(&mut self) -> Result<(DumpData, Option<usize>), String> {
+        Ok((self.clone(), None))
+    }
+}
+
+impl<T: StateSource + ?Sized> StateSource for &mut T {
+    fn state(&mut self) -> Result<(DumpData, Option<usize>), String> {
+        (**self).state()
+    }
+}
+
+/// Expected and actual values of each basis state, keyed by the basis state without
+/// leading zero words.
+type Comparison<T> = BTreeMap<Vec<u64>, (Option<T>, Complex64)>;
+
+/// Returns the comparison of the state with the expected values, and the bitstring
+/// width.
+fn collect<T: Copy>(
+    source: &mut impl StateSource,
+    expected: &[(&str, T)],
+) -> Result<(Comparison<T>, usize), String> {
+    let (data, num_qubits) = source.state()?;
+    let width = expected
+        .iter()
+        .map(|(bitstring, _)| bitstring.len())
+        .chain(num_qubits)
+        .max()
+        .unwrap_or(1);
+
+    let mut states = BTreeMap::new();
+    for (bitstring, value) in expected {
+        if num_qubits.is_some_and(|num_qubits| bitstring.len() != num_qubits) {
+            return Err(format!(
+                "bitstring {:?} does not have {} bits",
+                bitstring,
+                num_qubits.unwrap()
+            ));
+        }
+        let state = basis::from_bitstring(bitstring)
+            .ok_or_else(|| format!("invalid bitstring {:?}", bitstring))?;
+        let state = basis::trim(&state).to_vec();
+        if states
+            .insert(state, (Some(*value), Complex64::default()))
+            .is_some()
+        {
+            return Err(format!("repeated bitstring {:?}", bitstring));
+        }
+    }
+    for ((state, real), imag) in data
+        .basis_states
+        .into_iter()
+        .zip(data.amplitudes_real)
+        .zip(data.amplitudes_imag)
+    {
+        states
+            .entry(basis::trim(&state).to_vec())
+            .or_insert((None, Complex64::default()))
+            .1 += Complex64::new(real, imag);
+    }
+    Ok((states, width))
+}
+
+/// Formats the comparison of each basis state, marking the mismatches with `>`.
+fn report<'a>(
+    title: &str,
+    width: usize,
+    rows: impl Iterator<Item = (&'a Vec<u64>, String, String, bool)>,
+) -> String {
+    let column = width.max("basis".len());
+    let mut message = format!(
+        "{}\n  {:<column$}  {:<24}  actual\n",
+        title, "basis", "expected"
+    );
+    for (state, expected, actual, matches) in rows {
+        message += &format!(
+            "{} {:<column$}  {:<24}  {}\n",
+            if matches { ' ' } else { '>' },
+            basis::to_bitstring(state, width),
+            expected,
+            actual,
+        );
+    }
+    message
+}
+
+fn format_complex(value: Complex64) -> String {
+    format!("{:+.6}{:+.6}i", value.re, value.im)
+}
+
+/// Compares the amplitudes of a state, up to a global phase.
+///
+/// # Errors
+///
+/// Returns the comparison of every basis state if an amplitude differs by more than
+/// `tolerance`, or a description of the problem if the state cannot be read.
+pub fn check_state<T: Into<Complex64> + Copy>(
+    mut source: impl StateSource,
+    expected: &[(&str, T)],
+    tolerance: f64,
+) -> Result<(), String> {
+    let (states, width) = collect(&mut source, expected)?;
+    let expected_of = |value: &Option<T>| value.map_or(Complex64::default(), Into::into);
+
+    // The reference phase is taken from the basis state where both amplitudes are the
+    // largest.
+    let weight =
+        |(expected, actual): &(Option<T>, Complex64)| expected_of(expected).norm() * actual.norm();
+    let phase = states
+        .values()
+        .max_by(|a, b| weight(a).total_cmp(&weight(b)))
+        .map(|(expected, actual)| {
+            let (expected, actual) = (expected_of(expected), *actual);
+            if expected.norm() > tolerance && actual.norm() > tolerance {
+                (actual / actual.norm()) / (expected / expected.norm())
+            } else {
+                Complex64::new(1.0, 0.0)
+            }
+        })
+        .unwrap_or(Complex64::new(1.0, 0.0));
+
+    let mut mismatch = false;
+    let rows: Vec<_> = states
+        .iter()
+        .map(|(state, (expected, actual))| {
+            let (expected, actual) = (expected_of(expected), actual / phase);
+            let matches = (expected - actual).norm() <= tolerance;
+            mismatch |= !matches;
+            (
+                state,
+                format_complex(expected),
+                format_complex(actual),
+                matches,
+            )
+        })
+        .collect();
+
+    if mismatch {
+        Err(report(
+            &format!(
+                "state mismatch (tolerance {:e}, global phase removed):",
+                tolerance
+            ),
+            width,
+            rows.into_iter(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Compares the probabilities of the basis states of a state.
+///
+/// # Errors
+///
+/// Returns the comparison of every basis state if a probability differs by more than
+/// `tolerance`, or a description of the problem if the state cannot be read.
+pub fn check_prob(
+    mut source: impl StateSource,
+    expected: &[(&str, f64)],
+    tolerance: f64,
+) -> Result<(), String> {
+    let (states, width) = collect(&mut source, expected)?;
+
+    let mut mismatch = false;
+    let rows: Vec<_> = states
+        .iter()
+        .map(|(state, (expected, actual))| {
+            let (expected, actual) = (expected.unwrap_or_default(), actual.norm_sqr());
+            let matches = (expected - actual).abs() <= tolerance;
+            mismatch |= !matches;
+            (
+                state,
+                format!("{:.6}", expected),
+                format!("{:.6}", actual),
+                matches,
+            )
+        })
+        .collect();
+
+    if mismatch {
+        Err(report(
+            &format!("probability mismatch (tolerance {:e}):", tolerance),
+            width,
+            rows.into_iter(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Wrong output of an arithmetic circuit, found by [`verify_arithmetic`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArithmeticFailure {
+    /// Value of each register before the circuit.
+    pub inputs: Vec<u64>,
+
+    /// Value of each register computed by the classical reference.
+    pub expected: Vec<u64>,
+
+    /// Measured value of each register after the circuit.
+    pub actual: Vec<u64>,
+}
+
+/// Result of [`verify_arithmetic`].
+#[derive(Debug, Clone, Default)]
+pub struct ArithmeticReport {
+    /// Width of each register.
+    pub widths: Vec<usize>,
+
+    /// Number of inputs checked.
+    pub cases: usize,
+
+    /// Inputs with a wrong output.
+    pub failures: Vec<ArithmeticFailure>,
+}
+
+impl ArithmeticReport {
+    /// Returns whether every output matched the classical reference.
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl std::fmt::Display for ArithmeticReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "arithmetic check: {} of {} inputs failed",
+            self.failures.len(),
+            self.cases
+        )?;
+        let registers = |values: &[u64]| {
+            values
+                .iter()
+                .zip(&self.widths)
+                .map(|(value, width)| basis::to_bitstring(&[*value], *width))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        for failure in &self.failures {
+            writeln!(
+                f,
+                "  {} -> expected {}, measured {}",
+                registers(&failure.inputs),
+                registers(&failure.expected),
+                registers(&failure.actual)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks an arithmetic circuit against classical arithmetic for every basis state
+/// input.
+///
+/// For each value of the registers, whose widths are given in `widths`, a new process
+/// is created with `configuration`, the registers are prepared in the basis state,
+/// `circuit` is applied on them, and they are measured. The measured values must match
+/// `reference`, which maps the values of the registers before the circuit to their
+/// values after it, including any wrap-around on overflow. Register values have the
+/// first qubit as the most significant bit, whatever the
+/// [bit order](Configuration::bit_order), so a circuit that mixes up the order
+/// of its qubits fails the check.
+///
+/// The number of inputs grows exponentially with the total width, so the check is
+/// meant for small registers.
+///
+/// # Errors
+///
+/// Returns the errors of the circuit or of the execution, and
+/// [`KetError::DataNotAvailable`] if the executor does not return the measurements.
+pub fn verify_arithmetic<C, F, R>(
+    mut configuration: C,
+    widths: &[usize],
+    mut circuit: F,
+    reference: R,
+) -> error::Result<ArithmeticReport>
+where
+    C: FnMut() -> Configuration,
+    F: FnMut(&mut Process, &[Vec<usize>]) -> error::Result<()>,
+    R: Fn(&[u64]) -> Vec<u64>,
+{
+    let mut report = ArithmeticReport {
+        widths: widths.to_vec(),
+        ..Default::default()
+    };
+    let total: usize = widths.iter().sum();
+
+    for input in 0..1u64 << total {
+        let mut process = Process::new(configuration());
+        let mut offset = total;
+        let mut inputs = Vec::with_capacity(widths.len());
+        let mut registers = Vec::with_capacity(widths.len());
+        for width in widths {
+            offset -= width;
+            let value = (input >> offset) & ((1 << width) - 1);
+            let mut register = Vec::with_capacity(*width);
+            for index in 0..*width {
+                let qubit = process.allocate_qubit()?;
+                if (value >> (width - 1 - index)) & 1 == 1 {
+                    process.apply_gate(QuantumGate::PauliX, qubit)?;
+                }
+                register.push(qubit);
+            }
+            inputs.push(value);
+            registers.push(register);
+        }
+
+        circuit(&mut process, &registers)?;
+        let measurements = registers
+            .iter()
+            .map(|register| process.measure_arranged(register))
+            .collect::<error::Result<Vec<_>>>()?;
+        process.prepare_for_execution()?;
+        let actual = measurements
+            .iter()
+            .map(|index| process.get_measurement(*index).result)
+            .collect::<Option<Vec<_>>>()
+            .ok_or(KetError::DataNotAvailable)?;
+
+        let expected: Vec<u64> = reference(&inputs)
+            .iter()
+            .zip(widths)
+            .map(|(value, width)| value & ((1 << width) - 1))
+            .collect();
+        report.cases += 1;
+        if actual != expected {
+            report.failures.push(ArithmeticFailure {
+                inputs,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Asserts that a state has the expected amplitudes, up to a global phase.
+///
+/// The first argument is a live [`Process`](crate::Process) or a
+/// [`DumpData`](crate::DumpData), the second a slice of `(bitstring, amplitude)`, and the
+/// optional third the tolerance, [`DEFAULT_TOLERANCE`] by default. See the
+/// [`testing`](crate::testing) module.
+#[macro_export]
+macro_rules! assert_state {
+    ($source:expr, $expected:expr $(,)?) => {
+        $crate::assert_state!($source, $expected, $crate::testing::DEFAULT_TOLERANCE)
+    };
+    ($source:expr, $expected:expr, $tolerance:expr $(,)?) => {
+        if let Err(message) = $crate::testing::check_state(&mut $source, $expected, $tolerance) {
+            panic!("{}", message);
+        }
+    };
+}
+
+/// Asserts that the basis states of a state have the expected probabilities.
+///
+/// The arguments are the same as in [`assert_state!`], with probabilities instead of
+/// amplitudes.
+#[macro_export]
+macro_rules! assert_prob {
+    ($source:expr, $expected:expr $(,)?) => {
+        $crate::assert_prob!($source, $expected, $crate::testing::DEFAULT_TOLERANCE)
+    };
+    ($source:expr, $expected:expr, $tolerance:expr $(,)?) => {
+        if let Err(message) = $crate::testing::check_prob(&mut $source, $expected, $tolerance) {
+            panic!("{}", message);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_prob, check_state};
+    use crate::ir::DumpData;
+
+    #[test]
+    fn compare_dump() {
+        let mut data = DumpData {
+            basis_states: vec![vec![0b00], vec![0b11]],
+            amplitudes_real: vec![0.0, 0.0],
+            amplitudes_imag: vec![0.5f64.sqrt(), 0.5f64.sqrt()],
+        };
+        let half = 0.5f64.sqrt();
+
+        // Equal up to the global phase i.
+        crate::assert_state!(data, &[("00", half), ("11", half)]);
+        crate::assert_prob!(data, &[("00", 0.5), ("11", 0.5)]);
+
+        let message = check_state(&mut data, &[("00", half), ("10", half)], 1e-6).unwrap_err();
+        assert!(message.contains("> 10 "), "{}", message);
+        assert!(message.contains("> 11 "), "{}", message);
+        assert!(message.contains("  00 "), "{}", message);
+
+        assert!(check_prob(&mut data, &[("00", 0.5), ("11", 0.49)], 1e-3).is_err());
+        assert!(check_prob(&mut data, &[("00", 0.5), ("11", 0.49)], 1e-1).is_ok());
+        assert!(check_prob(&mut data, &[("0x", 1.0)], 1e-1).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+// SPDX-FileCopyrightText: 2020 Evandro Chagas Ribeiro da Rosa <evandro@quantuloop.com>
+// SPDX-FileCopyrightText: 2020 Rafael de Santiago <r.santiago@ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Recording and replay of the calls made on a process.
+//!
+//! After [`Process::start_trace`], every call that builds the circuit, from qubit
+//! allocation to [`Process::prepare_for_execution`], is appended to a [`Trace`], which
+//! can be saved and replayed on a new process to reproduce a problem exactly, even if
+//! the original program was generated from Python through the C API. The calls made by
+//! [`Process::optimize`] on the process are not recorded, as replaying the optimization
+//! makes them again, and the results of live measurements are recorded and forced on
+//! replay.
+//!
+//! A trace file has one JSON value per line: a header with the format version and the
+//! number of qubits of the configuration, followed by one line per call.
+//!
+//! ```
+//! # use ket::error::KetError;
+//! use ket::{trace::Trace, Configuration, Process, QuantumGate};
+//!
+//! # fn main() -> Result<(), KetError> {
+//! let mut process = Process::new(Configuration::new(2));
+//! process.start_trace();
+//! let qubit = process.allocate_qubit()?;
+//! process.apply_gate(QuantumGate::Hadamard, qubit)?;
+//! process.measure(&[qubit])?;
+//!
+//! let mut file = Vec::new();
+//! process.trace().unwrap().write(&mut file).unwrap();
+//!
+//! let trace = Trace::read(file.as_slice()).unwrap();
+//! let mut replayed = Process::new(Configuration::new(trace.num_qubits()));
+//! trace.replay(&mut replayed)?;
+//! assert!(replayed == process);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{KetError, Result},
+    ir::{KrausOperator, Pauli, PauliHamiltonian, QuantumGate},
+    provenance::SourceLocation,
+    Angle, Process,
+};
+
+/// Version of the trace file format.
+pub const TRACE_VERSION: u32 = 1;
+
+/// First line of a trace file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    ket_trace: u32,
+    num_qubits: usize,
+}
+
+/// Call made on a process.
+///
+/// The qubits of measurements, samples, and dumps are stored from the most to the least
+/// significant bit, so a trace does not depend on the
+/// [bit order](crate::Configuration::bit_order) of the process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TraceCall {
+    AllocateQubit,
+    FreeQubit {
+        qubit: usize,
+    },
+    ApplyGate {
+        gate: QuantumGate,
+        target: usize,
+    },
+    ApplyGlobalPhase {
+        phase: Angle,
+    },
+    ApplyKrausChannel {
+        operators: Vec<KrausOperator>,
+        target: usize,
+    },
+    ApplyDiagonal {
+        phases: Vec<f64>,
+        qubits: Vec<usize>,
+    },
+    ApplyMultiplexor {
+        axis: Pauli,
+        angles: Vec<f64>,
+        control: Vec<usize>,
+        target: usize,
+    },
+    CtrlPush {
+        qubits: Vec<usize>,
+    },
+    CtrlPop,
+    AdjBegin,
+    AdjEnd,
+    CondPush {
+        expected: Vec<(usize, u64)>,
+    },
+    CondPop,
+    /// Measurement, with its result if it was measured by a live execution.
+    Measure {
+        qubits: Vec<usize>,
+        result: Option<u64>,
+    },
+    ForceMeasurement {
+        index: usize,
+        outcome: u64,
+    },
+    ExpValue {
+        hamiltonian: PauliHamiltonian,
+    },
+    Sample {
+        qubits: Vec<usize>,
+        shots: u64,
+    },
+    Dump {
+        qubits: Vec<usize>,
+    },
+    DumpTopK {
+        qubits: Vec<usize>,
+        k: usize,
+    },
+    Optimize,
+    Canonicalize,
+    Freeze,
+    OpaqueBegin,
+    OpaqueEnd,
+    BudgetBegin {
+        name: String,
+        fidelity: f64,
+    },
+    BudgetEnd,
+    SetSourceLocation {
+        location: Option<SourceLocation>,
+    },
+    Checkpoint {
+        index: usize,
+    },
+    Rollback {
+        checkpoint: usize,
+    },
+    PrepareForExecution,
+}
+
+/// Calls recorded on a process, see [`Process::start_trace`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trace {
+    num_qubits: usize,
+    calls: Vec<TraceCall>,
+}
+
+impl Trace {
+    pub(crate) fn new(num_qubits: usize) -> Self {
+        Self {
+            num_qubits,
+            calls: Vec::new(),
+        }
+    }
+
+    /// Returns the number of qubits of the configuration of the recorded process.
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    /// Returns the recorded calls, in order.
+    pub fn calls(&self) -> &[TraceCall] {
+        &self.calls
+    }
+
+    pub(crate) fn push(&mut self, call: TraceCall) {
+        self.calls.push(call);
+    }
+
+    /// Sets the result of the last recorded call, if it is a measurement.
+    pub(crate) fn set_measurement_result(&mut self, value: u64) {
+        if let Some(TraceCall::Measure { result, .. }) = self.calls.last_mut() {
+            *result = Some(value);
+        }
+    }
+
+    /// Writes the trace in the trace file format.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of the writer.
+    pub fn write(&self, writer: impl Write) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        let header = Header {
+            ket_trace: TRACE_VERSION,
+            num_qubits: self.num_qubits,
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writeln!(writer)?;
+        for call in &self.calls {
+            serde_json::to_writer(&mut writer, call)?;
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Reads a trace in the trace file format.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of the reader, and [`io::ErrorKind::InvalidData`] if a line is
+    /// not valid or the format version is not supported.
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "empty trace")),
+        };
+        if header.ket_trace != TRACE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported trace version {}", header.ket_trace),
+            ));
+        }
+
+        let mut trace = Self::new(header.num_qubits);
+        for line in lines {
+            let line = line?;
+            if !line.trim().is_empty() {
+                trace.calls.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(trace)
+    }
+
+    /// Saves the trace to a file.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of creating or writing the file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write(File::create(path)?)
+    }
+
+    /// Loads a trace from a file, see [`Trace::read`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of opening or reading the file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Makes the recorded calls on the process.
+    ///
+    /// The recorded results of live measurements are forced with
+    /// [`Process::force_measurement`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of a call, which reproduces the error of the recorded
+    /// process if it stopped at the last call.
+    pub fn replay(&self, process: &mut Process) -> Result<()> {
+        let mut checkpoints = HashMap::new();
+        for call in &self.calls {
+            match call {
+                TraceCall::AllocateQubit => process.allocate_qubit().map(drop)?,
+                TraceCall::FreeQubit { qubit } => process.free_qubit(*qubit)?,
+                TraceCall::ApplyGate { gate, target } => {
+                    process.apply_gate(gate.clone(), *target)?
+                }
+                TraceCall::ApplyGlobalPhase { phase } => {
+                    process.apply_global_phase(phase.clone())?
+                }
+                TraceCall::ApplyKrausChannel { operators, target } => {
+                    process.apply_kraus_channel(operators, *target)?
+                }
+                TraceCall::ApplyDiagonal { phases, qubits } => {
+                    process.apply_diagonal(phases, qubits)?
+                }
+                TraceCall::ApplyMultiplexor {
+                    axis,
+                    angles,
+                    control,
+                    target,
+                } => process.apply_multiplexor(*axis, angles, control, *target)?,
+                TraceCall::CtrlPush { qubits } => process.ctrl_push(qubits)?,
+                TraceCall::CtrlPop => process.ctrl_pop()?,
+                TraceCall::AdjBegin => process.adj_begin()?,
+                TraceCall::AdjEnd => process.adj_end()?,
+                TraceCall::CondPush { expected } => process.cond_push(expected)?,
+                TraceCall::CondPop => process.cond_pop()?,
+                TraceCall::Measure { qubits, result } => {
+                    if let Some(result) = result {
+                        process.force_measurement(process.measurements.len(), *result)?;
+                    }
+                    process.measure_arranged(qubits).map(drop)?
+                }
+                TraceCall::ForceMeasurement { index, outcome } => {
+                    process.force_measurement(*index, *outcome)?
+                }
+                TraceCall::ExpValue { hamiltonian } => {
+                    process.exp_values(hamiltonian.clone()).map(drop)?
+                }
+                TraceCall::Sample { qubits, shots } => {
+                    process.sample_arranged(qubits, *shots).map(drop)?
+                }
+                TraceCall::Dump { qubits } => process.dump_arranged(qubits).map(drop)?,
+                TraceCall::DumpTopK { qubits, k } => {
+                    process.dump_truncated(qubits, Some(*k)).map(drop)?
+                }
+                TraceCall::Optimize => process.optimize()?,
+                TraceCall::Canonicalize => process.canonicalize()?,
+                TraceCall::Freeze => process.freeze()?,
+                TraceCall::OpaqueBegin => process.opaque_begin()?,
+                TraceCall::OpaqueEnd => process.opaque_end()?,
+                TraceCall::BudgetBegin { name, fidelity } => {
+                    process.budget_begin(name, *fidelity)?
+                }
+                TraceCall::BudgetEnd => process.budget_end()?,
+                TraceCall::SetSourceLocation { location } => {
+                    process.set_source_location(location.clone())
+                }
+                TraceCall::Checkpoint { index } => {
+                    checkpoints.insert(*index, process.checkpoint()?);
+                }
+                TraceCall::Rollback { checkpoint } => process.rollback(
+                    checkpoints
+                        .get(checkpoint)
+                        .ok_or(KetError::InvalidCheckpoint)?,
+                )?,
+                TraceCall::PrepareForExecution => process.prepare_for_execution()?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Trace, TraceCall};
+    use crate::{error::KetError, Angle, Configuration, Process, QuantumGate};
+
+    #[test]
+    fn replay_until_error() -> Result<(), KetError> {
+        let path = std::env::temp_dir().join(format!("ket-trace-{}.jsonl", std::process::id()));
+
+        let mut process = Process::new(Configuration::new(2));
+        process.start_trace();
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.apply_global_phase(Angle::pi_fraction(1, 2))?;
+        process.ctrl_pop()?;
+        process.adj_begin()?;
+        process.apply_gate(QuantumGate::RotationY(Angle::Scalar(0.3)), qubits[1])?;
+        process.adj_end()?;
+        process.measure(&qubits)?;
+        // The program stopped at this error.
+        assert!(process.ctrl_pop().is_err());
+
+        process.trace().unwrap().save(&path).unwrap();
+        let trace = Trace::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(trace.num_qubits(), 2);
+        assert!(matches!(trace.calls().last(), Some(TraceCall::CtrlPop)));
+
+        let mut replayed = Process::new(Configuration::new(2));
+        assert!(matches!(trace.replay(&mut replayed), Err(KetError::NoCtrl)));
+        assert!(replayed == process);
+        assert_eq!(replayed.get_measurement(0).qubits, qubits);
+
+        process.freeze()?;
+        let calls = process.stop_trace().unwrap().calls().len();
+        assert_eq!(calls, trace.calls().len() + 1);
+        assert!(process.trace().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+// SPDX-FileCopyrightText: 2024 Gabriel da Silva Cardoso <cardoso.gabriel@grad.ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! On-disk cache of optimized circuits.
+//!
+//! Each entry is a JSON file named after the [`CircuitHash`] of the circuit and the
+//! [`ZXStrategy`], holding the circuit and its optimized instructions. The circuit is
+//! compared with the one being optimized before an entry is used, so a hash collision
+//! is only a cache miss. Entries are written to a temporary file and renamed, so
+//! processes sharing the directory never read a partial entry.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    hashing::{structurally_equal, CircuitHash},
+    Instruction,
+};
+
+use super::ZXStrategy;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    circuit: Vec<Instruction>,
+    optimized: Vec<Instruction>,
+}
+
+/// Directory of optimized circuits, set in
+/// [`Configuration::optimization_cache`](crate::Configuration::optimization_cache).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizationCache {
+    directory: PathBuf,
+}
+
+impl OptimizationCache {
+    /// Uses `directory` for the entries. It is created on the first insertion.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Returns the cache directory.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn path(&self, circuit: &[Instruction], strategy: ZXStrategy) -> PathBuf {
+        let strategy = format!("{:?}", strategy).to_lowercase();
+        self.directory
+            .join(format!("{}-{}.json", CircuitHash::new(circuit), strategy))
+    }
+
+    /// Returns the optimized instructions of the circuit, if they are in the cache.
+    ///
+    /// Entries that cannot be read are misses.
+    pub fn get(&self, circuit: &[Instruction], strategy: ZXStrategy) -> Option<Vec<Instruction>> {
+        let path = self.path(circuit, strategy);
+        let entry: Entry = match fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|error| error.to_string()))
+        {
+            Ok(entry) => entry,
+            Err(error) => {
+                debug!(path = %path.display(), %error, "optimization cache miss");
+                return None;
+            }
+        };
+
+        if structurally_equal(&entry.circuit, circuit) {
+            debug!(path = %path.display(), "optimization cache hit");
+            Some(entry.optimized)
+        } else {
+            debug!(path = %path.display(), "optimization cache collision");
+            None
+        }
+    }
+
+    /// Stores the optimized instructions of the circuit.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of creating the directory or writing the entry.
+    pub fn insert(
+        &self,
+        circuit: &[Instruction],
+        strategy: ZXStrategy,
+        optimized: &[Instruction],
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let path = self.path(circuit, strategy);
+        let entry = Entry {
+            circuit: circuit.to_vec(),
+            optimized: optimized.to_vec(),
+        };
+        let json = serde_json::to_string(&entry).map_err(io::Error::other)?;
+
+        let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary, json)?;
+        fs::rename(temporary, path)
+    }
+}
+
+#[cfg(all(test, feature = "optimizer"))]
+mod tests {
+    use super::OptimizationCache;
+    use crate::{
+        error::KetError, zx::ZXStrategy, Configuration, Instruction, Process, QuantumGate,
+    };
+
+    #[test]
+    fn cached_optimization() -> Result<(), KetError> {
+        let directory =
+            std::env::temp_dir().join(format!("ket-optimization-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let cache = OptimizationCache::new(&directory);
+
+        let circuit = |x_first: bool| -> Result<Process, KetError> {
+            let mut process = Process::new(Configuration {
+                optimization_cache: Some(cache.clone()),
+                ..Configuration::new(2)
+            });
+            let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+            let mut gates = [
+                (QuantumGate::PauliX, qubits[1]),
+                (QuantumGate::Hadamard, qubits[0]),
+            ];
+            if !x_first {
+                gates.reverse();
+            }
+            for (gate, qubit) in gates {
+                process.apply_gate(gate, qubit)?;
+            }
+            process.measure(&qubits)?;
+            Ok(process)
+        };
+
+        // A known result is stored by hand, so a hit is distinguishable from quizx.
+        let process = circuit(true)?;
+        let optimized = [
+            Instruction::Alloc { target: 0 },
+            Instruction::Alloc { target: 1 },
+            Instruction::Gate {
+                gate: QuantumGate::PauliZ,
+                target: 0,
+                control: vec![],
+            },
+            Instruction::Measure {
+                qubits: vec![0, 1],
+                output: 0,
+            },
+        ];
+        cache
+            .insert(process.instructions(), ZXStrategy::Clifford, &optimized)
+            .unwrap();
+
+        // The gates on disjoint qubits are reordered, so the hash is the same.
+        let mut process = circuit(false)?;
+        process.optimize()?;
+        assert_eq!(process.instructions().len(), 4);
+        assert!(matches!(
+            process.instructions()[2],
+            Instruction::Gate {
+                gate: QuantumGate::PauliZ,
+                ..
+            }
+        ));
+        assert_eq!(process.get_measurement(0).qubits, [0, 1]);
+
+        let mut process = circuit(true)?;
+        process.apply_gate(QuantumGate::PauliY, 0)?;
+        assert!(cache
+            .get(process.instructions(), ZXStrategy::Clifford)
+            .is_none());
+        assert!(cache
+            .get(circuit(true)?.instructions(), ZXStrategy::Full)
+            .is_none());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+        Ok(())
+    }
+}
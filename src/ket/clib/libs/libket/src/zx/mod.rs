@@ -2,14 +2,120 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+//! ZX-calculus optimization.
+//!
+//! The optimizer exports the circuit to OpenQASM and simplifies it with `quizx`, so
+//! [`optimize`] and [`warm_start`] need the `optimizer` feature. Without it,
+//! [`Process::optimize`] keeps the instructions as they are. The strategies, the
+//! reports, and the [`cache`] are always available, so configurations and metadata
+//! are the same in every build.
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "optimizer"))]
+use crate::{error::Result, Process};
+
+pub mod cache;
+#[cfg(feature = "optimizer")]
 pub mod optimize;
+#[cfg(feature = "optimizer")]
+pub mod warm_start;
 // mod utils;
 
-#[cfg(test)]
+/// ZX-calculus simplification strategy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZXStrategy {
+    /// Clifford simplification, `quizx::simplify::clifford_simp`.
+    #[default]
+    Clifford,
+    /// Full simplification, `quizx::simplify::full_simp`.
+    Full,
+}
+
+/// Outcome of the ZX optimization of the sections of a circuit, recorded in the
+/// [`Metadata`](crate::Metadata) of the process.
+///
+/// The gates of a section are kept as they were if the circuit extraction fails or if
+/// the extracted circuit is worse, so the optimization never makes a circuit worse.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OptimizationReport {
+    /// Number of sections with gates.
+    pub sections: usize,
+
+    /// Number of sections replaced by their simplified circuit.
+    pub optimized: usize,
+
+    /// Reason the original gates of each of the other sections were kept.
+    pub warnings: Vec<String>,
+}
+
+#[cfg(feature = "optimizer")]
+pub(crate) use optimize::optimize;
+
+/// Keeps the instructions as they are, as the optimizer needs the `optimizer` feature.
+#[cfg(not(feature = "optimizer"))]
+pub(crate) fn optimize(_process: &mut Process) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, feature = "optimizer"))]
 mod tests {
+    use num::complex::Complex64;
+
+    use super::optimize::{cost, gates, rebuild, split_sections, suffix_start, Barrier};
     use crate::error::KetError;
+    use crate::passes::canonicalize::action;
+    use crate::qasmv2::creg::CregMap;
     use crate::qasmv2::instruction_set::InstructionSet::QELIB;
-    use crate::{Configuration, Process, QuantumGate};
+    use crate::{
+        BatchExecution, Configuration, ExecutionStatus, Instruction, Process, QuantumGate,
+        ResultData,
+    };
+
+    /// Batch executor that fails the test if the circuit is executed.
+    struct Unreachable;
+
+    impl BatchExecution for Unreachable {
+        fn submit_execution(&mut self, _: &[Instruction]) {
+            panic!("the circuit was executed");
+        }
+
+        fn get_result(&mut self) -> ResultData {
+            unreachable!()
+        }
+
+        fn get_status(&self) -> ExecutionStatus {
+            ExecutionStatus::New
+        }
+    }
+
+    /// Returns the action of the gates before the first result on the qubits `qubits`.
+    fn gate_action(process: &Process, qubits: &[usize]) -> Vec<Complex64> {
+        let gates: Vec<_> = process
+            .instructions
+            .iter()
+            .take_while(|instruction| {
+                !matches!(
+                    instruction,
+                    Instruction::Measure { .. } | Instruction::Dump { .. }
+                )
+            })
+            .cloned()
+            .collect();
+        action(&gates, qubits).unwrap()
+    }
+
+    /// Returns the measured qubits of each measurement instruction.
+    fn measured_qubits(process: &Process) -> Vec<Vec<usize>> {
+        process
+            .instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Measure { qubits, .. } => Some(qubits.clone()),
+                _ => None,
+            })
+            .collect()
+    }
 
     #[test]
     fn test_tof_10() -> Result<(), KetError> {
@@ -105,8 +211,112 @@ mod tests {
         let qubits = 19;
         let mut process = Process::new(Configuration::new(qubits));
         process.from_qasmv2(qasm, QELIB, false)?;
-        process.optimize().unwrap();
-        println!("{}", process.to_qasmv2(false, QELIB).unwrap());
+        let before = cost(&gates(&process.to_qasmv2(false, QELIB)?));
+        process.optimize()?;
+
+        // The optimization never makes the circuit worse.
+        let after = cost(&gates(&process.to_qasmv2(false, QELIB)?));
+        assert!(after <= before, "{after:?} > {before:?}");
+        assert_eq!(process.qubits.len(), qubits);
+        let report = process.metadata.optimization.as_ref().unwrap();
+        assert_eq!(report.sections, 1);
+        assert_eq!(report.optimized + report.warnings.len(), report.sections);
+        Ok(())
+    }
+
+    #[test]
+    fn failed_rebuild_restores_process() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(2));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.ctrl_pop()?;
+        let measurement = process.measure(&qubits)?;
+        let before = process.to_qasmv2(false, QELIB)?;
+
+        let result = rebuild(&mut process, |process| {
+            let qubit = process.allocate_qubit()?;
+            process.apply_gate(QuantumGate::PauliX, qubit)?;
+            process.allocate_qubit()?;
+            process.allocate_qubit()?;
+            Ok(())
+        });
+
+        assert!(matches!(result, Err(KetError::NumberOfQubitsExceeded)));
+        assert_eq!(process.to_qasmv2(false, QELIB)?, before);
+        assert_eq!(process.qubits.len(), 2);
+        assert_eq!(process.get_measurement(measurement).qubits, qubits);
+        process.optimize()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_optimization_is_returned() -> Result<(), KetError> {
+        let mut configuration = Configuration::new(2);
+        configuration.batch_execution = Some(Box::new(Unreachable));
+        let mut process = Process::new(configuration);
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+
+        // The QASM export has no controlled Hadamard, so the circuit is not optimized.
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::Hadamard, qubits[1])?;
+        process.ctrl_pop()?;
+        process.measure(&qubits)?;
+
+        assert!(matches!(
+            process.prepare_for_execution(),
+            Err(KetError::UnsuportedGateExport)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn measured_suffix() -> Result<(), KetError> {
+        let mut process = Process::new(Configuration::new(2));
+        let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+        process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+        process.ctrl_push(&[qubits[0]])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.ctrl_pop()?;
+        process.measure(&qubits)?;
+        process.optimize()?;
+
+        // The measurement of the suffix follows the ones already optimized.
+        process.apply_gate(QuantumGate::PauliX, qubits[0])?;
+        process.apply_gate(QuantumGate::PauliX, qubits[0])?;
+        let measurement = process.measure(&[qubits[0]])?;
+        process.optimize()?;
+        assert_eq!(measurement, 1);
+        assert_eq!(process.measurements.len(), 2);
+        assert_eq!(process.get_measurement(measurement).qubits, [qubits[0]]);
+        assert!(matches!(
+            process.instructions.last(),
+            Some(Instruction::Measure { output: 1, .. })
+        ));
+
+        // A sample stays in place, after the gates before it.
+        process.apply_gate(QuantumGate::Hadamard, qubits[1])?;
+        process.apply_gate(QuantumGate::Hadamard, qubits[1])?;
+        let sample = process.sample(&[qubits[1]], 10)?;
+        process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+        process.optimize()?;
+        assert_eq!(process.samples.len(), 1);
+        assert_eq!(
+            measured_qubits(&process),
+            [vec![qubits[0], qubits[1]], vec![qubits[0]]]
+        );
+        let [.., before, last] = process.instructions.as_slice() else {
+            panic!("the circuit lost its instructions")
+        };
+        assert!(matches!(before, Instruction::Sample { output, .. } if *output == sample));
+        assert!(matches!(
+            last,
+            Instruction::Gate { gate: QuantumGate::PauliX, target, control }
+                if *target == qubits[1] && control.is_empty()
+        ));
+
         Ok(())
     }
 
@@ -125,10 +335,24 @@ mod tests {
         process.apply_gate(QuantumGate::PauliX, qubit_b)?;
         process.ctrl_pop()?;
 
-        let _m_a = process.dump(&[qubit_a, qubit_b])?;
+        let dump = process.dump(&[qubit_a, qubit_b])?;
+        let before = gate_action(&process, &[qubit_a, qubit_b]);
 
-        process.optimize().unwrap();
-        println!("{}", process.to_qasmv2(false, QELIB).unwrap());
+        process.optimize()?;
+
+        // The gates before the dump act the same, up to a global phase.
+        let after = gate_action(&process, &[qubit_a, qubit_b]);
+        let phase = after[0] / before[0];
+        assert!(before
+            .iter()
+            .zip(&after)
+            .all(|(before, after)| (before * phase - after).norm() < 1e-9));
+        assert_eq!(process.dumps.len(), 1);
+        assert_eq!(process.dumps[dump].qubits, [qubit_a, qubit_b]);
+        assert!(matches!(
+            process.instructions.last(),
+            Some(Instruction::Dump { output: 0, .. })
+        ));
 
         Ok(())
     }
@@ -150,10 +374,105 @@ mod tests {
         let qubits = 2;
         let mut process = Process::new(Configuration::new(qubits));
         process.from_qasmv2(qasm, QELIB, false)?;
+        let measurements = process.measurements.len();
+        let measured = measured_qubits(&process);
 
-        process.optimize().unwrap();
-        // println!("{}", process.to_qasmv2(false, QELIB).unwrap());
+        process.optimize()?;
 
+        // The measurements stay in place, with their qubits.
+        assert_eq!(process.measurements.len(), measurements);
+        assert_eq!(measured_qubits(&process), measured);
         Ok(())
     }
+
+    #[test]
+    fn test_split_sections() {
+        let qasm = "// Generated from libket\n\
+                    OPENQASM 2.0;\n\
+                    include \"qelib1.inc\";\n\
+                    qreg q[2];\n\
+                    creg c[3];\n\
+                    opaque dump(classic) a;\n\
+                    h q[0];\n\
+                    cx q[0], q[1];\n\
+                    measure q[0] -> c[0];\n\
+                    measure q[1] -> c[1];\n\
+                    dump(0) q[1];\n\
+                    x q[1];\n\
+                    measure q[1] -> c[2];\n";
+
+        let creg = CregMap::from_instructions(&[
+            Instruction::Measure {
+                qubits: vec![0, 1],
+                output: 0,
+            },
+            Instruction::Dump {
+                qubits: vec![1],
+                output: 0,
+            },
+            Instruction::Measure {
+                qubits: vec![1],
+                output: 1,
+            },
+        ]);
+        let (header, sections) = split_sections(qasm);
+        assert_eq!(
+            header,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\ncreg c[3];\n"
+        );
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].gates, ["h q[0]", "cx q[0], q[1]"]);
+        assert_eq!(
+            sections[0].barrier_groups(&creg),
+            [
+                (Barrier::Measure { qubit: 0, bit: 0 }, vec![0, 1]),
+                (Barrier::Dump { qubit: 1, bit: 0 }, vec![1]),
+            ]
+        );
+        assert_eq!(sections[1].gates, ["x q[1]"]);
+        assert_eq!(
+            sections[1].barriers,
+            [Barrier::Measure { qubit: 1, bit: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_suffix_start() {
+        let gate = Instruction::Gate {
+            gate: QuantumGate::Hadamard,
+            target: 0,
+            control: Vec::new(),
+        };
+        let mut instructions = vec![Instruction::Alloc { target: 0 }];
+        instructions.extend(vec![gate.clone(); 40]);
+
+        assert_eq!(suffix_start(&instructions, 0), 0);
+        assert_eq!(suffix_start(&instructions, 10), 1);
+        assert_eq!(suffix_start(&instructions, 41), 9);
+
+        instructions.insert(
+            30,
+            Instruction::Measure {
+                qubits: vec![0],
+                output: 0,
+            },
+        );
+        assert_eq!(suffix_start(&instructions, 42), 31);
+    }
+
+    #[test]
+    fn test_cost() {
+        let gates = |gates: &[&str]| {
+            gates
+                .iter()
+                .map(|gate| gate.to_string())
+                .collect::<Vec<_>>()
+        };
+        let original = cost(&gates(&["h q[0]", "cx q[0],q[1]", "h q[0]"]));
+        let simplified = cost(&gates(&["u3(0.1,0.2,0.3) q[0]", "cz q[0], q[1]"]));
+        assert_eq!(original, (1, 3));
+        assert_eq!(simplified, (1, 2));
+        assert!(simplified <= original);
+        assert!(cost(&gates(&["cx q[0],q[1]", "cx q[1],q[0]"])) > original);
+    }
 }
@@ -2,151 +2,537 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::fs::metadata;
-use std::mem;
 use quizx::circuit::Circuit;
 use quizx::extract::ToCircuit;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 use crate::error::Result;
+use crate::passes::rewrite_editable;
+use crate::qasmv2::creg::CregMap;
 use crate::qasmv2::instruction_set::InstructionSet;
-use crate::{Configuration, Metadata, Process};
+use crate::{Configuration, Instruction, Process};
+
+use super::cache::OptimizationCache;
+pub use super::{OptimizationReport, ZXStrategy};
 use quizx::hash_graph::Graph;
-use regex::Regex;
+use tracing::{debug, debug_span, info_span, warn};
+
+/// Number of optimized instructions before the new ones that are optimized again with
+/// them, so gates that cancel across the boundary are still simplified.
+const BOUNDARY: usize = 32;
 
 pub fn optimize(process: &mut Process) -> Result<()> {
-    println!("optimizing");
-    let qasm = process.to_qasmv2(false, InstructionSet::QELIB).unwrap();
+    optimize_incremental(process, ZXStrategy::Clifford)
+}
 
-    prepare_process(process);
+/// Optimizes only the instructions added since the last optimization.
+///
+/// The new instructions and the last [`BOUNDARY`] optimized instructions are exported
+/// as a separate circuit on all the qubits of the process, simplified, and spliced back
+/// after the untouched prefix. The boundary never crosses a measurement, dump, or any
+/// other non-gate instruction, nor the frozen prefix, see [`Process::freeze`]. Falls
+/// back to [`optimize_with`] on the first call or if the new instructions free qubits
+/// or have no OpenQASM statement, see [`optimize_with`]. With a frozen prefix, only the instructions after the last freed qubit are optimized
+/// instead. With opaque blocks, see [`Process::opaque_begin`], only the runs of
+/// consecutive gates outside the blocks are optimized.
+pub fn optimize_incremental(process: &mut Process, strategy: ZXStrategy) -> Result<()> {
+    if has_opaque_blocks(process) {
+        return optimize_around_opaque(process, strategy);
+    }
+    let len = process.instructions.len();
+    let _span = info_span!(
+        "optimize",
+        ?strategy,
+        instructions = len,
+        qubits = process.qubits.len()
+    )
+    .entered();
+    let frozen = process.frozen_len.min(len);
+    let optimized = process.optimized_len.max(frozen).min(len);
+    if optimized == len && optimized > 0 {
+        return Ok(());
+    }
 
-    let qasm_sections = separate_code_sections(&qasm);
+    let cache = process.config.optimization_cache.clone();
+    let mut start = suffix_start(&process.instructions, optimized).max(frozen);
+    if frozen > 0 {
+        if let Some(free) = process.instructions[start..]
+            .iter()
+            .rposition(|instruction| matches!(instruction, Instruction::Free { .. }))
+        {
+            start += free + 1;
+        }
+        if start == len {
+            process.optimized_len = len;
+            return Ok(());
+        }
+    }
+    let suffix = &process.instructions[start..];
+    if start == 0
+        || suffix.iter().any(|instruction| {
+            matches!(instruction, Instruction::Free { .. }) || is_unexported(instruction)
+        })
+    {
+        return optimize_cached(process, strategy, cache.as_ref());
+    }
+    debug!(optimized = suffix.len(), "optimizing the new instructions");
 
-    let mut is_first_iteration = true;
+    let num_qubits = process.qubits.len();
+    let mut suffix_process = Process::new(Configuration::new(num_qubits));
+    for _ in 0..num_qubits {
+        suffix_process.allocate_qubit()?;
+    }
+    let mut allocations = Vec::new();
+    for instruction in suffix {
+        match instruction {
+            Instruction::Alloc { .. } => allocations.push(instruction.clone()),
+            Instruction::Measure { qubits, .. } => {
+                suffix_process.measure(qubits)?;
+            }
+            Instruction::Dump { qubits, .. } => {
+                suffix_process.dump(qubits)?;
+            }
+            _ => suffix_process.instructions.push(instruction.clone()),
+        }
+    }
+    optimize_cached(&mut suffix_process, strategy, cache.as_ref())?;
+
+    let prefix = &process.instructions[..start];
+    let measurements = count(prefix, |i| matches!(i, Instruction::Measure { .. }));
+    let dumps = count(prefix, |i| matches!(i, Instruction::Dump { .. }));
+
+    process.instructions.truncate(start);
+    process.instructions.extend(allocations);
+    process
+        .instructions
+        .extend(suffix_process.instructions.into_iter().filter_map(
+            |instruction| match instruction {
+                Instruction::Alloc { .. } => None,
+                Instruction::Measure { qubits, output } => Some(Instruction::Measure {
+                    qubits,
+                    output: output + measurements,
+                }),
+                Instruction::Dump { qubits, output } => Some(Instruction::Dump {
+                    qubits,
+                    output: output + dumps,
+                }),
+                instruction => Some(instruction),
+            },
+        ));
+    process.measurements.truncate(measurements);
+    process.measurements.extend(suffix_process.measurements);
+    let top_k: Vec<_> = process.dumps[dumps..]
+        .iter()
+        .map(|dump| dump.top_k)
+        .collect();
+    process.dumps.truncate(dumps);
+    process.dumps.extend(suffix_process.dumps);
+    for (dump, top_k) in process.dumps[dumps..].iter_mut().zip(top_k) {
+        dump.top_k = top_k;
+    }
+    if let Some(report) = suffix_process.metadata.optimization {
+        process.metadata.optimization = Some(report);
+    }
+    process.optimized_len = process.instructions.len();
+    process.rewritten();
 
-    for section in qasm_sections {
-        println!("--{}", &section);
-        let measure_regex = Regex::new(r"measure q\[(\d+)\] -> c\[(\d+)\];").unwrap();
+    Ok(())
+}
 
-        let mut measure_vec: Vec<Vec<usize>> = Vec::new();
-        let mut measure_current_bit = 0;
+fn count(instructions: &[Instruction], predicate: impl Fn(&Instruction) -> bool) -> usize {
+    instructions.iter().filter(|i| predicate(i)).count()
+}
 
-        for cap in measure_regex.captures_iter(&*section) {
-            let to_measure = cap[1].parse::<usize>().unwrap();
-            let bit = cap[2].parse::<usize>().unwrap();
+/// Index of the first instruction optimized again after `optimized` instructions were
+/// already optimized.
+///
+/// Goes back at most [`BOUNDARY`] instructions, stopping after the last non-gate
+/// instruction, whose order relative to the gates must be kept.
+pub(crate) fn suffix_start(instructions: &[Instruction], optimized: usize) -> usize {
+    let window = optimized.saturating_sub(BOUNDARY);
+    instructions[window..optimized]
+        .iter()
+        .rposition(|instruction| !matches!(instruction, Instruction::Gate { .. }))
+        .map_or(window, |index| window + index + 1)
+}
 
-            if measure_vec.len() > 0 && &bit == &measure_current_bit {
-                measure_vec.last_mut().unwrap().push(to_measure);
-            } else {
-                measure_vec.push(vec![to_measure]);
-                measure_current_bit = bit;
-            }
-        }
+/// Optimizes the whole circuit, or reads the optimized instructions from the cache.
+///
+/// On a miss, the result of [`optimize_with`] is stored in the cache. A cache that
+/// cannot be written only logs a warning.
+fn optimize_cached(
+    process: &mut Process,
+    strategy: ZXStrategy,
+    cache: Option<&OptimizationCache>,
+) -> Result<()> {
+    let Some(cache) = cache else {
+        return optimize_with(process, strategy);
+    };
+
+    if let Some(optimized) = cache.get(&process.instructions, strategy) {
+        process.instructions = optimized;
+        process.optimized_len = process.instructions.len();
+        process.rewritten();
+        return Ok(());
+    }
 
-        let dump_regex = Regex::new(r"dump\((\d+)\) q\[(\d+)\];").unwrap();
-        let mut dump_vec: Vec<Vec<usize>> = Vec::new();
-        let mut dump_current_bit = 0;
+    let circuit = process.instructions.clone();
+    optimize_with(process, strategy)?;
+    if let Err(error) = cache.insert(&circuit, strategy, &process.instructions) {
+        warn!(%error, "could not write the optimization cache");
+    }
+    Ok(())
+}
 
-        for cap in dump_regex.captures_iter(&*section) {
-            let to_dump = cap[2].parse::<usize>().unwrap();
-            let bit = cap[1].parse::<usize>().unwrap();
+fn has_opaque_blocks(process: &Process) -> bool {
+    !process.opaque.is_empty()
+        || process.opaque_scope.is_some()
+        || !process.budgets.is_empty()
+        || process.budget_scope.is_some()
+}
 
-            if dump_vec.len() > 0 && &bit == &dump_current_bit {
-                dump_vec.last_mut().unwrap().push(to_dump);
+/// Optimizes the runs of consecutive gates between the opaque blocks, the bounds of the
+/// error budget sections, and the other instructions, see [`Process::opaque_begin`].
+fn optimize_around_opaque(process: &mut Process, strategy: ZXStrategy) -> Result<()> {
+    let _span = info_span!(
+        "optimize_around_opaque",
+        ?strategy,
+        instructions = process.instructions.len(),
+        opaque_blocks = process.opaque.len(),
+        budgets = process.budgets.len()
+    )
+    .entered();
+    let num_qubits = process.qubits.len();
+    let cache = process.config.optimization_cache.clone();
+    rewrite_editable(process, |segment| {
+        let mut instructions = Vec::with_capacity(segment.len());
+        for run in segment.chunk_by(|a, b| is_gate(a) == is_gate(b)) {
+            if is_gate(&run[0]) {
+                instructions.extend(optimize_run(num_qubits, run, strategy, cache.as_ref())?);
             } else {
-                dump_vec.push(vec![to_dump]);
-                dump_current_bit = bit;
+                instructions.extend_from_slice(run);
             }
         }
+        Ok(instructions)
+    })?;
+    process.optimized_len = process.instructions.len();
+    process.rewritten();
+    Ok(())
+}
 
+/// Returns `true` for the instructions that the QASM export rejects.
+fn is_unexported(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Sample { .. }
+            | Instruction::ExpValue { .. }
+            | Instruction::KrausChannel { .. }
+            | Instruction::ConditionalGate { .. }
+    )
+}
 
+fn is_gate(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::Gate { .. })
+}
 
-        let clean_qasm = measure_regex.replace_all(&*section, "").to_string();
-
-        let qasm_optimized = zx_optimize(&clean_qasm);
-
-        process.from_qasmv2(&*qasm_optimized, InstructionSet::QELIB, !is_first_iteration);
-
-        if is_first_iteration {
-            is_first_iteration = false;
-        }
+/// Optimizes a run of gates on the qubits of a process with `num_qubits` qubits.
+///
+/// Runs of a single gate are returned as they are.
+pub(crate) fn optimize_run(
+    num_qubits: usize,
+    run: &[Instruction],
+    strategy: ZXStrategy,
+    cache: Option<&OptimizationCache>,
+) -> Result<Vec<Instruction>> {
+    if run.len() < 2 {
+        return Ok(run.to_vec());
+    }
+    let mut run_process = Process::new(Configuration::new(num_qubits));
+    for _ in 0..num_qubits {
+        run_process.allocate_qubit()?;
+    }
+    run_process.instructions.extend(run.iter().cloned());
+    optimize_cached(&mut run_process, strategy, cache)?;
+    Ok(run_process
+        .instructions
+        .into_iter()
+        .filter(|instruction| !matches!(instruction, Instruction::Alloc { .. }))
+        .collect())
+}
 
-        for measure_val in measure_vec {
-            process.measure(&measure_val).unwrap();
-        }
-        for dump_val in dump_vec {
-            process.dump(&dump_val).unwrap();
+/// Optimizes the whole circuit.
+///
+/// Samples, expected values, Kraus channels, and conditional gates have no OpenQASM
+/// statement, so a circuit with them is optimized as one with opaque blocks: they are
+/// kept in place, and only the runs of gates between them are simplified.
+pub fn optimize_with(process: &mut Process, strategy: ZXStrategy) -> Result<()> {
+    if has_opaque_blocks(process) || process.instructions.iter().any(is_unexported) {
+        return optimize_around_opaque(process, strategy);
+    }
+    let span = info_span!(
+        "optimize_with",
+        ?strategy,
+        instructions = process.instructions.len(),
+        qubits = process.qubits.len(),
+        sections = tracing::field::Empty,
+        optimized_instructions = tracing::field::Empty
+    );
+    let _entered = span.enter();
+    let qasm = process.to_qasmv2(false, InstructionSet::QELIB)?;
+    let creg = process.creg_map();
+
+    let (header, sections) = split_sections(&qasm);
+    span.record("sections", sections.len());
+
+    // The sections are independent circuits, so they are simplified in parallel, with
+    // the `parallel` feature, and only imported in order.
+    #[cfg(feature = "parallel")]
+    let sections_iter = sections.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let sections_iter = sections.iter();
+    let optimized: Vec<(String, Option<String>)> = sections_iter
+        .enumerate()
+        .map(|(index, section)| {
+            // Rayon threads do not inherit the current span.
+            let _span =
+                debug_span!(parent: &span, "section", index, gates = section.gates.len()).entered();
+            if section.gates.is_empty() {
+                return (header.clone(), None);
+            }
+            let qasm = section.qasm(&header);
+            match zx_optimize(&qasm, strategy) {
+                Ok(optimized) => {
+                    let (before, after) = (cost(&section.gates), cost(&gates(&optimized)));
+                    debug!(
+                        multi_qubit = after.0,
+                        total = after.1,
+                        "simplified the section"
+                    );
+                    if after <= before {
+                        (optimized, None)
+                    } else {
+                        let warning = format!(
+                            "section {index}: kept the original gates, the simplified \
+                             circuit has {} multi-qubit and {} total gates instead of {} and {}",
+                            after.0, after.1, before.0, before.1
+                        );
+                        (qasm, Some(warning))
+                    }
+                }
+                Err(error) => (
+                    qasm,
+                    Some(format!("section {index}: extraction failed: {error}")),
+                ),
+            }
+        })
+        .collect();
+
+    let mut report = OptimizationReport {
+        sections: sections.iter().filter(|s| !s.gates.is_empty()).count(),
+        ..Default::default()
+    };
+    for (_, warning) in &optimized {
+        if let Some(warning) = warning {
+            warn!("{warning}");
+            report.warnings.push(warning.clone());
         }
     }
+    report.optimized = report.sections - report.warnings.len();
+    let optimized = optimized.into_iter().map(|(qasm, _)| qasm);
+
+    rebuild(process, |scratch| {
+        for (index, (section, qasm_optimized)) in sections.iter().zip(optimized).enumerate() {
+            scratch.from_qasmv2(&qasm_optimized, InstructionSet::QELIB, index > 0)?;
+
+            for (barrier, qubits) in section.barrier_groups(&creg) {
+                match barrier {
+                    Barrier::Measure { .. } => scratch.measure_arranged(&qubits)?,
+                    Barrier::Dump { .. } => scratch.dump_arranged(&qubits)?,
+                };
+            }
+        }
+        Ok(())
+    })?;
 
-
-    // process.from_qasmv2(&qasm_optimized, InstructionSet::QELIB);
-
-    // let dump_regex = Regex::new(r"dump q\[(\d+)\];").unwrap();;
-    // let mut dump_vec = Vec::new();
-    // for cap in dump_regex.captures_iter(&*qasm) {
-    //     dump_vec.push(cap[1].parse::<usize>().unwrap());
-    // }
-    // process.dump(&dump_vec).unwrap();
-
+    span.record("optimized_instructions", process.instructions.len());
+    process.metadata.optimization = Some(report);
+    process.optimized_len = process.instructions.len();
     Ok(())
 }
 
-fn prepare_process(process: &mut Process) {
-    process.instructions.clear();
-    process.ctrl_stack.clear();
-    process.ctrl_list_is_up_to_date = false;
-    process.adj_stack.clear();
-    process.qubits.clear();
-    process.qubit_allocated = 0;
-    process.dumps.clear();
-    process.measurements.clear();
+/// Rebuilds the circuit of the process with `build`, which runs on a scratch process
+/// without instructions or qubits, see [`Process::scratch`].
+///
+/// The initial state of the process is not prepared again, as its preparation is
+/// already in the circuit. The circuit of the scratch process replaces the one of the
+/// process only if `build` succeeds, so an import that fails midway leaves the process
+/// untouched, and the error is returned. The results and the metadata of the process
+/// are not rebuilt.
+pub(crate) fn rebuild(
+    process: &mut Process,
+    build: impl FnOnce(&mut Process) -> Result<()>,
+) -> Result<()> {
+    let mut scratch = process.scratch();
+    build(&mut scratch)?;
+
+    // The dumps keep their truncation. The rebuilt instructions take the locations of
+    // the old ones in `rewritten`.
+    for (dump, old) in scratch.dumps.iter_mut().zip(&process.dumps) {
+        dump.top_k = old.top_k;
+    }
+    process.instructions = scratch.instructions;
+    process.ctrl_stack = scratch.ctrl_stack;
+    process.ctrl_list = scratch.ctrl_list;
+    process.ctrl_list_is_up_to_date = scratch.ctrl_list_is_up_to_date;
+    process.adj_stack = scratch.adj_stack;
+    process.qubits = scratch.qubits;
+    process.qubit_allocated = scratch.qubit_allocated;
+    process.dumps = scratch.dumps;
+    process.measurements = scratch.measurements;
+    process.rewritten();
+    Ok(())
 }
 
-fn zx_optimize(qasm: &str) -> String {
-    let c = Circuit::from_qasm(qasm).unwrap();
-    let mut g: Graph = c.clone().to_graph();
-    quizx::simplify::clifford_simp(&mut g);
-    let c_optimized = g.to_circuit().unwrap();
-    c_optimized.to_qasm()
+fn zx_optimize(qasm: &str, strategy: ZXStrategy) -> std::result::Result<String, String> {
+    let c = Circuit::from_qasm(qasm)?;
+    let mut g: Graph = c.to_graph();
+    match strategy {
+        ZXStrategy::Clifford => quizx::simplify::clifford_simp(&mut g),
+        ZXStrategy::Full => quizx::simplify::full_simp(&mut g),
+    };
+    let c_optimized = g.to_circuit().map_err(|error| error.to_string())?;
+    Ok(c_optimized.to_qasm())
 }
 
-fn separate_code_sections(input: &str) -> Vec<String> {
-    let mut sections: Vec<String> = Vec::new();
-    let mut current_section = Vec::new();
-    let mut measure_section = Vec::new();
+/// Gate statements of a program.
+pub(crate) fn gates(qasm: &str) -> Vec<String> {
+    split_sections(qasm)
+        .1
+        .into_iter()
+        .flat_map(|section| section.gates)
+        .collect()
+}
 
-    let re = Regex::new(r"(creg c\[\d+\];)").unwrap();
+/// Number of multi-qubit gates and of gates of the statements, compared in this order.
+pub(crate) fn cost(gates: &[String]) -> (usize, usize) {
+    let multi_qubit = gates
+        .iter()
+        .filter(|gate| gate.rsplit(')').next().unwrap_or_default().contains(','))
+        .count();
+    (multi_qubit, gates.len())
+}
 
+/// Statement that ends a section, on the qubit `qubit` and classical bit `bit`, or the
+/// index of the dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Barrier {
+    Measure { qubit: usize, bit: usize },
+    Dump { qubit: usize, bit: usize },
+}
 
-    if let Some(mat) = re.find(input) {
-        let split_index = mat.start() + mat.as_str().len();
-        let mut qasm_regs = &input[..split_index];
-        let gates = &input[split_index..];
+/// Gates of the program followed by the measurements and dumps that end them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Section {
+    pub(crate) gates: Vec<String>,
+    pub(crate) barriers: Vec<Barrier>,
+}
 
+impl Section {
+    /// Program with the gates of the section.
+    fn qasm(&self, header: &str) -> String {
+        let mut qasm = header.to_string();
+        for gate in &self.gates {
+            qasm += gate;
+            qasm += ";\n";
+        }
+        qasm
+    }
 
-        for line in gates.lines() {
-            if line.trim().starts_with("measure|dump") {
-                measure_section.push(line.to_string());
-            } else {
-                if !measure_section.is_empty() {
-                    current_section.append(&mut measure_section);
-                    sections.push(format!("{}\n{}", &qasm_regs, current_section.join("\n")));
-                    current_section = Vec::new();
-                    measure_section = Vec::new();
-                }
-                current_section.push(line.to_string());
+    /// Groups consecutive barriers that come from the same instruction: measurements
+    /// with bits of the same measurement in `creg`, or dumps with the same index.
+    pub(crate) fn barrier_groups(&self, creg: &CregMap) -> Vec<(Barrier, Vec<usize>)> {
+        let mut groups: Vec<(Barrier, Vec<usize>)> = Vec::new();
+        for barrier in &self.barriers {
+            let qubit = match barrier {
+                Barrier::Measure { qubit, .. } | Barrier::Dump { qubit, .. } => *qubit,
+            };
+            match groups.last_mut() {
+                Some((last, qubits)) if same_instruction(creg, last, barrier) => qubits.push(qubit),
+                _ => groups.push((*barrier, vec![qubit])),
             }
         }
+        groups
+    }
+}
 
-        if !measure_section.is_empty() {
-            current_section.append(&mut measure_section);
-        }
-        if !current_section.join("\n").replace(|c: char| c.is_whitespace(), "").is_empty() {
-            sections.push(format!("{}\n{}", &qasm_regs, current_section.join("\n")));
+fn same_instruction(creg: &CregMap, a: &Barrier, b: &Barrier) -> bool {
+    match (a, b) {
+        (Barrier::Measure { bit: a, .. }, Barrier::Measure { bit: b, .. }) => {
+            creg.measurement(*a) == creg.measurement(*b)
         }
+        (Barrier::Dump { bit: a, .. }, Barrier::Dump { bit: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+/// Returns the number between the first `open` and the following `close`.
+fn index_between(text: &str, open: char, close: char) -> Option<usize> {
+    let start = text.find(open)? + 1;
+    let end = start + text[start..].find(close)?;
+    text[start..end].trim().parse().ok()
+}
 
-        sections
-    } else {
-        return vec![input.to_string()]
+/// Splits the exported program into its declarations and the sections delimited by
+/// measurements and dumps.
+///
+/// The program is split statement by statement, classifying each one by its keyword,
+/// so the declarations are repeated at the beginning of every section.
+pub(crate) fn split_sections(qasm: &str) -> (String, Vec<Section>) {
+    let code: String = qasm
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut header = String::new();
+    let mut sections = vec![Section::default()];
+    for statement in code.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+        let keyword = statement
+            .split(|c: char| c.is_whitespace() || c == '(')
+            .next()
+            .unwrap_or_default();
+        let barrier = match keyword {
+            "OPENQASM" | "include" | "qreg" | "creg" => {
+                header += statement;
+                header += ";\n";
+                continue;
+            }
+            "opaque" => continue,
+            "measure" => index_between(statement, '[', ']')
+                .zip(
+                    statement
+                        .split("->")
+                        .nth(1)
+                        .and_then(|bit| index_between(bit, '[', ']')),
+                )
+                .map(|(qubit, bit)| Barrier::Measure { qubit, bit }),
+            "dump" => index_between(statement, '(', ')')
+                .zip(index_between(statement, '[', ']'))
+                .map(|(bit, qubit)| Barrier::Dump { qubit, bit }),
+            _ => None,
+        };
+
+        let current = sections.last_mut().unwrap();
+        match barrier {
+            Some(barrier) => current.barriers.push(barrier),
+            None if current.barriers.is_empty() => current.gates.push(statement.to_string()),
+            None => sections.push(Section {
+                gates: vec![statement.to_string()],
+                barriers: Vec::new(),
+            }),
+        }
     }
-}
\ No newline at end of file
+
+    (header, sections)
+}
@@ -0,0 +1,265 @@
+// SPDX-FileCopyrightText: 2024 Gabriel da Silva Cardoso <cardoso.gabriel@grad.ufsc.br>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Warm-started ZX optimization of parametric circuits.
+//!
+//! Variational algorithms run the same circuit for many parameter points, changing
+//! only the angles of some rotations. [`WarmStart`] splits the circuit at the gates with
+//! a scalar angle, the parameters, and simplifies the runs of fixed gates between them
+//! with the ZX-calculus. The optimized runs are kept, so a circuit that only differs
+//! in the scalar angles is optimized by putting the new rotations between the same
+//! optimized runs, without simplifying them again.
+//!
+//! Angles given as fractions of π, see [`Angle::pi_fraction`], are part of the fixed
+//! gates, and the instructions that are not gates, such as measurements, are kept in
+//! place.
+
+use tracing::{debug, info_span};
+
+use crate::{
+    error::Result, ir::QuantumGate, passes::rewrite_editable, Angle, Instruction, Process,
+};
+
+use super::{optimize::optimize_run, ZXStrategy};
+
+/// Optimized runs of fixed gates of a parametric circuit.
+#[derive(Debug, Clone, Default)]
+pub struct WarmStart {
+    strategy: ZXStrategy,
+
+    /// Circuit of the optimized runs, see [`skeleton`].
+    skeleton: Option<String>,
+
+    /// Optimized gates of each run, in the order of the circuit.
+    runs: Vec<Vec<Instruction>>,
+
+    hits: usize,
+}
+
+/// Returns `true` for a gate whose angle is a parameter.
+fn is_parametric(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Gate {
+            gate: QuantumGate::RotationX(Angle::Scalar(_))
+                | QuantumGate::RotationY(Angle::Scalar(_))
+                | QuantumGate::RotationZ(Angle::Scalar(_))
+                | QuantumGate::Phase(Angle::Scalar(_)),
+            ..
+        }
+    )
+}
+
+/// Returns `true` for a gate that is optimized with the run it is in.
+fn is_fixed(instruction: &Instruction) -> bool {
+    matches!(instruction, Instruction::Gate { .. }) && !is_parametric(instruction)
+}
+
+/// Serializes the circuit with the scalar angles set to zero, and the instructions that
+/// are not optimized.
+fn skeleton(process: &Process) -> String {
+    let instructions: Vec<Instruction> = process
+        .instructions
+        .iter()
+        .map(|instruction| match instruction {
+            Instruction::Gate {
+                gate,
+                target,
+                control,
+            } if is_parametric(instruction) => Instruction::Gate {
+                gate: match gate {
+                    QuantumGate::RotationX(_) => QuantumGate::RotationX(Angle::Scalar(0.0)),
+                    QuantumGate::RotationY(_) => QuantumGate::RotationY(Angle::Scalar(0.0)),
+                    QuantumGate::RotationZ(_) => QuantumGate::RotationZ(Angle::Scalar(0.0)),
+                    _ => QuantumGate::Phase(Angle::Scalar(0.0)),
+                },
+                target: *target,
+                control: control.clone(),
+            },
+            instruction => instruction.clone(),
+        })
+        .collect();
+    serde_json::to_string(&(instructions, process.frozen_len, &process.opaque))
+        .expect("instructions are serializable")
+}
+
+/// Splits the instructions in runs of consecutive fixed gates and the instructions
+/// between them, which are `Err`.
+fn split_runs(
+    instructions: &[Instruction],
+) -> Vec<std::result::Result<&[Instruction], &Instruction>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (index, instruction) in instructions.iter().enumerate() {
+        if !is_fixed(instruction) {
+            if start < index {
+                parts.push(Ok(&instructions[start..index]));
+            }
+            parts.push(Err(instruction));
+            start = index + 1;
+        }
+    }
+    if start < instructions.len() {
+        parts.push(Ok(&instructions[start..]));
+    }
+    parts
+}
+
+impl WarmStart {
+    /// Creates an empty warm start for the strategy.
+    pub fn new(strategy: ZXStrategy) -> Self {
+        Self {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `true` if the process only differs from the last optimized circuit in
+    /// its scalar angles, so it is optimized without simplifying the fixed gates.
+    pub fn matches(&self, process: &Process) -> bool {
+        self.skeleton
+            .as_ref()
+            .is_some_and(|skeleton| *skeleton == self::skeleton(process))
+    }
+
+    /// Returns the number of optimizations that reused the optimized runs.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Optimizes the process, reusing the optimized runs if it only differs from the
+    /// last optimized circuit in its scalar angles.
+    ///
+    /// The frozen instructions and the opaque blocks, see [`Process::freeze`] and
+    /// [`Process::opaque_begin`], are kept as they are, and a process that already
+    /// executed chunks is not optimized.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of the optimization of the runs.
+    pub fn optimize(&mut self, process: &mut Process) -> Result<()> {
+        if process.streamed_len > 0 {
+            return Ok(());
+        }
+        let _span = info_span!(
+            "warm_start",
+            instructions = process.instructions.len(),
+            qubits = process.qubits.len()
+        )
+        .entered();
+        let skeleton = skeleton(process);
+        let mut reused = if self.skeleton.as_ref() == Some(&skeleton) {
+            debug!(runs = self.runs.len(), "reusing the optimized runs");
+            self.hits += 1;
+            std::mem::take(&mut self.runs)
+        } else {
+            Vec::new()
+        }
+        .into_iter();
+
+        let num_qubits = process.qubits.len();
+        let cache = process.config.optimization_cache.clone();
+        let mut runs = Vec::new();
+        rewrite_editable(process, |segment| {
+            let mut instructions = Vec::with_capacity(segment.len());
+            for part in split_runs(segment) {
+                match part {
+                    Ok(run) => {
+                        let optimized = match reused.next() {
+                            Some(optimized) => optimized,
+                            None => optimize_run(num_qubits, run, self.strategy, cache.as_ref())?,
+                        };
+                        instructions.extend(optimized.iter().cloned());
+                        runs.push(optimized);
+                    }
+                    Err(instruction) => instructions.push(instruction.clone()),
+                }
+            }
+            Ok(instructions)
+        })?;
+
+        self.runs = runs;
+        self.skeleton = Some(skeleton);
+        process.optimized_len = process.instructions.len();
+        process.rewritten();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WarmStart;
+    use crate::{
+        error::KetError,
+        zx::{cache::OptimizationCache, optimize::ZXStrategy},
+        Angle, Configuration, Instruction, Process, QuantumGate,
+    };
+
+    #[test]
+    fn reused_runs() -> Result<(), KetError> {
+        let directory = std::env::temp_dir().join(format!("ket-warm-start-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let cache = OptimizationCache::new(&directory);
+
+        let circuit = |theta: f64| -> Result<Process, KetError> {
+            let mut process = Process::new(Configuration {
+                optimization_cache: Some(cache.clone()),
+                ..Configuration::new(2)
+            });
+            let qubits = [process.allocate_qubit()?, process.allocate_qubit()?];
+            process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+            process.apply_gate(QuantumGate::Hadamard, qubits[0])?;
+            process.apply_gate(QuantumGate::RotationZ(Angle::Scalar(theta)), qubits[0])?;
+            process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+            process.apply_gate(QuantumGate::PauliX, qubits[1])?;
+            process.measure(&qubits)?;
+            Ok(process)
+        };
+
+        // The runs are stored in the cache by hand, so no simplification runs.
+        let allocations = [
+            Instruction::Alloc { target: 0 },
+            Instruction::Alloc { target: 1 },
+        ];
+        let instruction = |gate, target| Instruction::Gate {
+            gate,
+            target,
+            control: vec![],
+        };
+        for (gate, target) in [(QuantumGate::Hadamard, 0), (QuantumGate::PauliX, 1)] {
+            let mut run = allocations.to_vec();
+            run.extend([instruction(gate.clone(), target), instruction(gate, target)]);
+            cache
+                .insert(&run, ZXStrategy::Clifford, &allocations)
+                .unwrap();
+        }
+
+        let mut warm = WarmStart::new(ZXStrategy::Clifford);
+        let mut process = circuit(0.5)?;
+        warm.optimize(&mut process)?;
+        assert_eq!(process.instructions().len(), 4);
+        assert_eq!(warm.hits(), 0);
+
+        // Without the cache, only a warm start avoids the simplification.
+        std::fs::remove_dir_all(&directory).unwrap();
+        let mut process = circuit(1.5)?;
+        assert!(warm.matches(&process));
+        warm.optimize(&mut process)?;
+        assert_eq!(warm.hits(), 1);
+        assert!(matches!(
+            process.instructions()[2],
+            Instruction::Gate {
+                gate: QuantumGate::RotationZ(Angle::Scalar(angle)),
+                ..
+            } if angle == 1.5
+        ));
+        assert_eq!(process.get_measurement(0).qubits, [0, 1]);
+
+        let mut process = circuit(1.5)?;
+        process.apply_gate(QuantumGate::PauliY, 1)?;
+        assert!(!warm.matches(&process));
+
+        Ok(())
+    }
+}